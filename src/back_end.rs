@@ -0,0 +1,126 @@
+//! Lowering of the type checked abstract syntax tree towards executable code
+pub mod asm;
+pub mod bytecode;
+pub mod c;
+pub mod closure_conversion;
+pub mod ir;
+pub mod llvm;
+pub mod wasm;
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Most bytes of memory that the heap of a program built by the x86-64 or the WebAssembly backend
+/// holds after collecting: past it they stop with an out of memory panic
+pub const HEAP_LIMIT: u32 = 1 << 30;
+
+/// Runs `build` in a new temporary directory, which is removed afterwards. The directory has a
+/// random name and must not exist yet, so that nothing else can have prepared it.
+pub fn in_temporary_directory(
+    output: &Path,
+    build: impl FnOnce(&Path) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let directory = std::env::temp_dir().join(format!(
+        "crawfish-{}-{:016x}-{}",
+        std::process::id(),
+        RandomState::new().build_hasher().finish(),
+        output
+            .file_name()
+            .map_or("out".into(), |name| name.to_string_lossy())
+    ));
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&directory).map_err(|e| {
+        format!(
+            "Cannot create the build directory `{}` ({})",
+            directory.display(),
+            e
+        )
+    })?;
+    let result = build(&directory);
+    let removed = fs::remove_dir_all(&directory);
+    // A failed build explains more than a directory left behind
+    result?;
+    Ok(removed?)
+}
+
+/// Compiles the C runtime into `directory`, and builds an executable at `output` from it and
+/// `inputs` (C sources or object files) with `compiler`
+pub fn link(
+    directory: &Path,
+    inputs: &[PathBuf],
+    output: &Path,
+    compiler: &str,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(directory.join("crawfish.h"), c::RUNTIME_HEADER)?;
+    fs::write(directory.join("crawfish.c"), c::RUNTIME_SOURCE)?;
+    let mut words = compiler.split_whitespace();
+    let program = words.next().ok_or("The C compiler command is empty")?;
+    let mut command = Command::new(program);
+    command
+        .args(words)
+        .args(["-std=c99", "-O2", "-o"])
+        .arg(output)
+        .args(inputs)
+        .arg(directory.join("crawfish.c"))
+        .arg("-lm");
+    run_tool(&mut command, program, "CC")
+}
+
+/// Runs an external tool of the build, such as the C compiler, which the environment variable
+/// `variable` selects
+pub fn run_tool(
+    command: &mut Command,
+    program: &str,
+    variable: &str,
+) -> Result<(), Box<dyn Error>> {
+    let status = command.status().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => format!(
+            "`{}` not found (set {} to choose another one)",
+            program, variable
+        ),
+        _ => format!("Cannot run `{}` ({})", program, e),
+    })?;
+    if !status.success() {
+        return Err(format!("`{}` failed ({})", program, status).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temporary_directories_are_fresh_and_removed() {
+        let output = Path::new("program");
+        let mut seen = Vec::new();
+        for _ in 0..2 {
+            in_temporary_directory(output, |directory| {
+                assert!(fs::read_dir(directory)?.next().is_none());
+                seen.push(directory.to_path_buf());
+                Ok(())
+            })
+            .unwrap();
+        }
+        assert_ne!(seen[0], seen[1]);
+        assert!(seen.iter().all(|directory| !directory.exists()));
+
+        // The error of the build wins over removing what it left behind
+        let mut directory = PathBuf::new();
+        let error = in_temporary_directory(output, |path| {
+            directory = path.to_path_buf();
+            fs::write(path.join("partial.o"), "")?;
+            Err("`cc` failed".into())
+        })
+        .unwrap_err();
+        assert_eq!(error.to_string(), "`cc` failed");
+        assert!(!directory.exists());
+    }
+}
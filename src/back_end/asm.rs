@@ -0,0 +1,555 @@
+//! x86-64 backend, which compiles the IR to GNU assembler source for Linux and the System V ABI,
+//! and builds it into an executable with `as` and `ld`, dynamically linked against the C library
+//! the system already has (glibc or musl).
+//! Each function is lowered to machine instructions over virtual registers (`lower`), which are
+//! mapped to hardware registers or stack slots by linear scan (`regalloc`) and printed in AT&T
+//! syntax (`emit`). Every value fits in a 64-bit word by NaN-boxing, as laid out in `asm/runtime.s`,
+//! the hand-written runtime that the executables are linked with.
+pub mod emit;
+pub mod lower;
+pub mod regalloc;
+
+use crate::back_end;
+use crate::back_end::ir::Program;
+use crate::front_end::modules::SourceFile;
+use crate::front_end::token::Span;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Source of the runtime, assembled next to every program
+pub const RUNTIME: &str = include_str!("asm/runtime.s");
+
+/// Tags of NaN-boxed values, as numbered in `asm/runtime.s`
+pub const INT: u64 = 0xFFF9 << 48;
+pub const BOOL: u64 = 0xFFFA << 48;
+pub const CHAR: u64 = 0xFFFB << 48;
+pub const UNIT: u64 = 0xFFFC << 48;
+pub const NULL: u64 = UNIT | 1;
+pub const OBJECT: u64 = 0xFFFD << 48;
+
+/// Kinds of heap objects, stored in their first word
+pub const KIND_STRING: u32 = 1;
+pub const KIND_FUNCTION: u32 = 7;
+
+/// The second word of the header of an object of the program itself, which is always marked
+pub const STATIC_HEADER: u64 = 1 << 32;
+
+/// Fields of heap objects, counted in words from their start
+pub const FIELD_VALUE: u32 = 1;
+pub const FIELD_CAPTURES: u32 = 3;
+
+/// The libraries that executables loaded by glibc's and by musl's dynamic linker are linked
+/// against. musl keeps the math functions in its C library.
+const GLIBC: &[&str] = &["libc.so.6", "libm.so.6"];
+const MUSL: &[&str] = &["libc.so"];
+
+/// Where x86-64 Linux systems keep the program interpreter of dynamically linked executables
+const DYNAMIC_LINKERS: [(&str, &[&str]); 3] = [
+    ("/lib64/ld-linux-x86-64.so.2", GLIBC),
+    ("/lib/x86_64-linux-gnu/ld-linux-x86-64.so.2", GLIBC),
+    ("/lib/ld-musl-x86_64.so.1", MUSL),
+];
+
+/// Where the libraries linked against are looked for
+const LIBRARY_DIRECTORIES: [&str; 6] = [
+    "/lib/x86_64-linux-gnu",
+    "/usr/lib/x86_64-linux-gnu",
+    "/lib64",
+    "/usr/lib64",
+    "/lib",
+    "/usr/lib",
+];
+
+/// A virtual register, holding one value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VReg(pub u32);
+
+/// A position in the instructions of a function that jumps can target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(pub u32);
+
+/// A lowered function
+/// - `index` is the position of the function in the program, which names its symbol
+/// - `vregs` is the number of virtual registers it uses
+/// - `roots` are the virtual registers that may point to heap objects, which the function's frame
+///   on the shadow stack holds a copy of for the garbage collector
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineFunction {
+    pub name: String,
+    pub index: usize,
+    pub file: usize,
+    pub vregs: u32,
+    pub roots: Vec<VReg>,
+    pub instructions: Vec<Inst>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    NegateInt,
+    NegateFloat,
+    BitNot,
+    Not,
+}
+
+/// What a branch tests before jumping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    False,
+}
+
+/// An argument of a call into the runtime
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Value(VReg),
+    /// The address of the source location of the call, for panics
+    Site(Span),
+    Word(u64),
+    Symbol(String),
+}
+
+/// A machine instruction. Instructions read all their operands before writing their results.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Label(Label),
+    /// Receives the environment of the function, then its arguments
+    Parameters(Vec<VReg>),
+    Const {
+        dst: VReg,
+        bits: u64,
+    },
+    /// Loads a value pointing to a static object
+    Object {
+        dst: VReg,
+        symbol: String,
+    },
+    Move {
+        dst: VReg,
+        src: VReg,
+    },
+    LoadField {
+        dst: VReg,
+        object: VReg,
+        field: u32,
+    },
+    StoreField {
+        object: VReg,
+        field: u32,
+        value: VReg,
+    },
+    /// Integer arithmetic, panicking at `span` on overflow
+    Int {
+        op: IntOp,
+        dst: VReg,
+        a: VReg,
+        b: VReg,
+        span: Span,
+    },
+    Float {
+        op: FloatOp,
+        dst: VReg,
+        a: VReg,
+        b: VReg,
+    },
+    Unary {
+        op: UnaryOp,
+        dst: VReg,
+        src: VReg,
+        span: Span,
+    },
+    /// Calls a function of the runtime, with the C calling convention
+    CallRuntime {
+        dst: Option<VReg>,
+        function: &'static str,
+        args: Vec<Arg>,
+    },
+    /// Calls a compiled function
+    Call {
+        dst: VReg,
+        function: usize,
+        args: Vec<VReg>,
+        span: Span,
+    },
+    /// Calls a function value, which is either a function or a closure
+    CallValue {
+        dst: VReg,
+        callee: VReg,
+        args: Vec<VReg>,
+        span: Span,
+    },
+    Jump(Label),
+    Branch {
+        condition: Condition,
+        value: VReg,
+        target: Label,
+    },
+    /// Loads the first item of a range
+    RangeStart {
+        dst: VReg,
+        range: VReg,
+    },
+    /// Tests whether `item` is not past the end of a range
+    RangeContains {
+        dst: VReg,
+        range: VReg,
+        item: VReg,
+    },
+    IsNull {
+        dst: VReg,
+        src: VReg,
+    },
+    Return(VReg),
+}
+
+impl Inst {
+    /// The virtual registers the instruction reads
+    pub fn uses(&self) -> Vec<VReg> {
+        match self {
+            Inst::Label(_)
+            | Inst::Parameters(_)
+            | Inst::Const { .. }
+            | Inst::Object { .. }
+            | Inst::Jump(_) => vec![],
+            Inst::Move { src, .. } | Inst::Unary { src, .. } | Inst::IsNull { src, .. } => {
+                vec![*src]
+            }
+            Inst::LoadField { object, .. } | Inst::RangeStart { range: object, .. } => {
+                vec![*object]
+            }
+            Inst::RangeContains { range, item, .. } => vec![*range, *item],
+            Inst::StoreField { object, value, .. } => vec![*object, *value],
+            Inst::Int { a, b, .. } | Inst::Float { a, b, .. } => vec![*a, *b],
+            Inst::CallRuntime { args, .. } => args
+                .iter()
+                .filter_map(|arg| match arg {
+                    Arg::Value(value) => Some(*value),
+                    _ => None,
+                })
+                .collect(),
+            Inst::Call { args, .. } => args.clone(),
+            Inst::CallValue { callee, args, .. } => {
+                let mut uses = vec![*callee];
+                uses.extend(args);
+                uses
+            }
+            Inst::Branch { value, .. } | Inst::Return(value) => vec![*value],
+        }
+    }
+
+    /// The virtual registers the instruction writes
+    pub fn defs(&self) -> Vec<VReg> {
+        match self {
+            Inst::Parameters(params) => params.clone(),
+            Inst::Const { dst, .. }
+            | Inst::Object { dst, .. }
+            | Inst::Move { dst, .. }
+            | Inst::LoadField { dst, .. }
+            | Inst::Int { dst, .. }
+            | Inst::Float { dst, .. }
+            | Inst::Unary { dst, .. }
+            | Inst::Call { dst, .. }
+            | Inst::CallValue { dst, .. }
+            | Inst::RangeStart { dst, .. }
+            | Inst::RangeContains { dst, .. }
+            | Inst::IsNull { dst, .. } => vec![*dst],
+            Inst::CallRuntime { dst, .. } => dst.iter().copied().collect(),
+            Inst::Label(_)
+            | Inst::StoreField { .. }
+            | Inst::Jump(_)
+            | Inst::Branch { .. }
+            | Inst::Return(_) => vec![],
+        }
+    }
+
+    /// Every virtual register the instruction mentions, for renaming them
+    pub fn vregs_mut(&mut self) -> Vec<&mut VReg> {
+        match self {
+            Inst::Label(_) | Inst::Jump(_) => vec![],
+            Inst::Parameters(params) => params.iter_mut().collect(),
+            Inst::Const { dst, .. } | Inst::Object { dst, .. } => vec![dst],
+            Inst::Move { dst, src } | Inst::Unary { dst, src, .. } | Inst::IsNull { dst, src } => {
+                vec![dst, src]
+            }
+            Inst::LoadField { dst, object, .. } | Inst::RangeStart { dst, range: object } => {
+                vec![dst, object]
+            }
+            Inst::RangeContains { dst, range, item } => vec![dst, range, item],
+            Inst::StoreField { object, value, .. } => vec![object, value],
+            Inst::Int { dst, a, b, .. } | Inst::Float { dst, a, b, .. } => vec![dst, a, b],
+            Inst::CallRuntime { dst, args, .. } => dst
+                .iter_mut()
+                .chain(args.iter_mut().filter_map(|arg| match arg {
+                    Arg::Value(value) => Some(value),
+                    _ => None,
+                }))
+                .collect(),
+            Inst::Call { dst, args, .. } => std::iter::once(dst).chain(args).collect(),
+            Inst::CallValue {
+                dst, callee, args, ..
+            } => [dst, callee].into_iter().chain(args).collect(),
+            Inst::Branch { value, .. } | Inst::Return(value) => vec![value],
+        }
+    }
+
+    /// The label the instruction may jump to
+    pub fn target(&self) -> Option<Label> {
+        match self {
+            Inst::Jump(target) | Inst::Branch { target, .. } => Some(*target),
+            _ => None,
+        }
+    }
+
+    /// Whether execution may continue with the next instruction
+    pub fn falls_through(&self) -> bool {
+        !matches!(self, Inst::Jump(_) | Inst::Return(_))
+    }
+
+    /// Whether the instruction calls a function, which may overwrite caller-saved registers
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self,
+            Inst::CallRuntime { .. }
+                | Inst::Call { .. }
+                | Inst::CallValue { .. }
+                | Inst::Float {
+                    op: FloatOp::Remainder,
+                    ..
+                }
+        )
+    }
+}
+
+/// Generates the assembly source of a program
+pub fn generate(program: &Program, files: &[SourceFile]) -> String {
+    let mut module = emit::Module::new(program, files);
+    for index in 0..program.functions.len() {
+        let function = lower::lower(program, index, &mut module.strings);
+        let allocation = regalloc::allocate(&function);
+        module.function(&function, &allocation);
+    }
+    module.finish()
+}
+
+/// Assembles `source` and the runtime, and links them against the C library into an executable at
+/// `output`
+pub fn assemble(source: &str, output: &Path) -> Result<(), Box<dyn Error>> {
+    back_end::in_temporary_directory(output, |directory| {
+        let assembler = std::env::var("AS").unwrap_or_else(|_| "as".to_string());
+        let mut objects = Vec::new();
+        for (name, text) in [("main", source), ("runtime", RUNTIME)] {
+            let source = directory.join(format!("{}.s", name));
+            let object = directory.join(format!("{}.o", name));
+            fs::write(&source, text)?;
+            let mut command = Command::new(&assembler);
+            command.arg("-o").arg(&object).arg(&source);
+            back_end::run_tool(&mut command, &assembler, "AS")?;
+            objects.push(object);
+        }
+
+        let (dynamic_linker, libraries) = find_c_library()?;
+        let linker = std::env::var("LD").unwrap_or_else(|_| "ld".to_string());
+        let mut command = Command::new(&linker);
+        command
+            .arg("-dynamic-linker")
+            .arg(dynamic_linker)
+            .arg("-o")
+            .arg(output)
+            .args(&objects)
+            .args(libraries);
+        back_end::run_tool(&mut command, &linker, "LD")
+    })
+}
+
+/// Finds the program interpreter that executables are built for, which `CRAWFISH_DYNAMIC_LINKER`
+/// overrides, and returns it with the linker arguments naming the C library it goes with
+fn find_c_library() -> Result<(String, Vec<String>), String> {
+    let (dynamic_linker, libraries) = match std::env::var("CRAWFISH_DYNAMIC_LINKER") {
+        Ok(path) if path.contains("musl") => (path, MUSL),
+        Ok(path) => (path, GLIBC),
+        Err(_) => DYNAMIC_LINKERS
+            .iter()
+            .find(|(path, _)| Path::new(path).exists())
+            .map(|(path, libraries)| (path.to_string(), *libraries))
+            .ok_or_else(|| {
+                let paths: Vec<&str> = DYNAMIC_LINKERS.iter().map(|(path, _)| *path).collect();
+                format!(
+                    "Cannot find the dynamic linker of x86-64 Linux at {} (set \
+                     CRAWFISH_DYNAMIC_LINKER to its path, or use the C backend)",
+                    paths.join(", ")
+                )
+            })?,
+    };
+    let mut args = Vec::new();
+    for library in libraries {
+        let directory = LIBRARY_DIRECTORIES
+            .iter()
+            .find(|directory| Path::new(directory).join(library).exists())
+            .ok_or_else(|| {
+                format!(
+                    "Cannot find the C library `{}` for `{}` in {} (use the C backend instead)",
+                    library,
+                    dynamic_linker,
+                    LIBRARY_DIRECTORIES.join(", ")
+                )
+            })?;
+        args.push(format!("-L{}", directory));
+        args.push(format!("-l:{}", library));
+    }
+    Ok((dynamic_linker, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::{ir, HEAP_LIMIT};
+    use crate::front_end;
+    use crate::testing::{
+        assert_outputs_match_interpreter, executable_path, run_with_input, source_files, FILES,
+        PROGRAMS,
+    };
+
+    fn generate_source(source: &str) -> String {
+        let analysis = front_end::analyze(source).unwrap();
+        generate(&ir::build(&analysis.program), &source_files(source))
+    }
+
+    /// Checks that the executable built from `source` prints and panics like the interpreter
+    fn assert_matches_interpreter(source: &str, name: &str) {
+        let executable = executable_path(&format!("asm-{}", name));
+        assemble(&generate_source(source), &executable).unwrap();
+        // Collecting on every allocation catches the roots that generated code fails to keep
+        let outputs: Vec<_> = ["0", "1"]
+            .iter()
+            .map(|stress| {
+                run_with_input(Command::new(&executable).env("CRAWFISH_GC_STRESS", stress))
+            })
+            .collect();
+        fs::remove_file(&executable).unwrap();
+        assert_outputs_match_interpreter(source, &outputs);
+    }
+
+    #[test]
+    fn test_generated_assembly_structure() {
+        let source = generate_source(
+            r#"func add(a: Int, b: Int) -> Int { return a + b; } func main() { println(add(1, 2)); println("hé"); }"#,
+        );
+        assert!(source.contains("cw_main:\n    xorl %edi, %edi\n    jmp f1\n"));
+        assert!(source.contains("f0: # add\n    pushq %rbp\n    movq %rsp, %rbp\n"));
+        assert!(source.contains("    addl %"));
+        assert!(source.contains("    jo .L0_panic0\n"));
+        assert!(source.contains("    call f0\n"));
+        assert!(source.contains("cw_site0:\n    .quad cw_path0\n    .long 1, 42\n"));
+        assert!(source.contains(
+            "    .quad 0, 0x100000000\ncw_string0:\n    .quad 1, 3\n    .ascii \"h\\303\\251\"\n"
+        ));
+        // `main` holds a string, so it pushes a frame of roots, which `add` has no need for
+        assert!(source.contains("    movq $1, -16(%rbp)\n    movq $0, -8(%rbp)\n"));
+        assert_eq!(
+            source.matches("    movq %rax, cw_frames(%rip)\n").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_running_out_of_memory_panics() {
+        assert!(RUNTIME.contains(&format!(".set HEAP_LIMIT, {:#x}\n", HEAP_LIMIT)));
+        if ["as", "ld"]
+            .iter()
+            .any(|tool| Command::new(tool).arg("--version").output().is_err())
+        {
+            eprintln!("skipping: no assembler or linker");
+            return;
+        }
+        let source = r#"func main() { var s = "x"; while true { s = "{s}{s}"; } }"#;
+        let executable = executable_path("asm-memory");
+        assemble(&generate_source(source), &executable).unwrap();
+        let output = run_with_input(&mut Command::new(&executable));
+        fs::remove_file(&executable).unwrap();
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "panicked: out of memory\n"
+        );
+        assert_eq!(output.status.code(), Some(101));
+    }
+
+    #[test]
+    fn test_unreachable_memory_is_freed() {
+        if ["as", "ld"]
+            .iter()
+            .any(|tool| Command::new(tool).arg("--version").output().is_err())
+        {
+            eprintln!("skipping: no assembler or linker");
+            return;
+        }
+        // Allocates more than the heap can hold, keeping only the last string
+        let source = r#"
+        func main() {
+            var s = "x";
+            for i in 0..20 { s = "{s}{s}"; }
+            var t = "";
+            for i in 0..1100 { t = "{s}{i}"; }
+            println(t == s);
+        }
+        "#;
+        assert_matches_interpreter(source, "freed");
+    }
+
+    #[test]
+    fn test_programs_match_interpreter() {
+        if ["as", "ld"]
+            .iter()
+            .any(|tool| Command::new(tool).arg("--version").output().is_err())
+        {
+            eprintln!("skipping: no assembler or linker");
+            return;
+        }
+        let path = std::env::temp_dir().join(format!("crawfish-asm-{}", std::process::id()));
+        let files = FILES.replace("PATH", path.to_str().unwrap());
+        // More variables live across the loop than there are registers to keep them in
+        let spilled = r#"
+        func main() {
+            var a = 1; var b = 2; var c = 3; var d = 4; var e = 5; var f = 6;
+            var g = 7; var h = 8; var i = 9; var j = 10; var k = 11; var l = 12;
+            for x in 0..3 {
+                a += x; b += a; c += b; d += c; e += d; f += e;
+                g += f; h += g; i += h; j += i; k += j; l += k;
+                println(x);
+            }
+            println(a + b + c + d + e + f + g + h + i + j + k + l);
+        }
+        "#;
+        for (n, program) in PROGRAMS
+            .iter()
+            .copied()
+            .chain([files.as_str(), spilled])
+            .enumerate()
+        {
+            assert_matches_interpreter(program, &n.to_string());
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,685 @@
+//! Printing of allocated machine code as GNU assembler source, in AT&T syntax.
+//! A frame saves `rbp` and the callee-saved registers the function uses, followed by its stack slots,
+//! so that `rsp` stays 16-byte aligned between instructions. A function with roots keeps its frame
+//! on the shadow stack after them, and copies every root there as soon as it is written.
+//! Compiled functions take their environment in `rdi` and their arguments in `rsi`, `rdx`, `rcx`,
+//! `r8` and `r9`, then on the stack, and return in `rax`.
+use crate::back_end::asm::lower::function_symbol;
+use crate::back_end::asm::regalloc::{Allocation, Location};
+use crate::back_end::asm::{
+    Arg, Condition, FloatOp, Inst, IntOp, Label, MachineFunction, UnaryOp, VReg, BOOL, FIELD_VALUE,
+    INT, KIND_FUNCTION, KIND_STRING, NULL, OBJECT, STATIC_HEADER,
+};
+use crate::back_end::ir::Program;
+use crate::front_end::modules::SourceFile;
+use crate::front_end::token::Span;
+use crate::runtime::vm::MAX_CALL_DEPTH;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Registers holding the environment and the first arguments of a compiled function
+const ARGUMENT_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+/// Registers holding the arguments of a runtime function
+const RUNTIME_REGISTERS: [&str; 4] = ["%rdi", "%rsi", "%rdx", "%rcx"];
+
+/// The assembly source of a program, built one function at a time
+/// - `strings` holds the string constants of the functions lowered so far, which name their symbols
+pub struct Module<'b> {
+    program: &'b Program,
+    files: &'b [SourceFile],
+    pub strings: Vec<String>,
+    text: String,
+    /// The file, line and column of every source location a panic can be raised at
+    sites: Vec<(usize, usize, usize)>,
+    site_indices: HashMap<(usize, Span), usize>,
+}
+
+impl<'b> Module<'b> {
+    pub fn new(program: &'b Program, files: &'b [SourceFile]) -> Module<'b> {
+        let mut text = String::from("# Generated by the Crawfish compiler\n    .text\n");
+        text.push_str("    .globl cw_main\ncw_main:\n    xorl %edi, %edi\n");
+        writeln!(text, "    jmp {}", function_symbol(program.entry)).unwrap();
+        Module {
+            program,
+            files,
+            strings: Vec::new(),
+            text,
+            sites: Vec::new(),
+            site_indices: HashMap::new(),
+        }
+    }
+
+    /// The symbol of the source location `span` of `file`
+    fn site(&mut self, file: usize, span: Span) -> String {
+        let next = self.sites.len();
+        let index = *self.site_indices.entry((file, span)).or_insert(next);
+        if index == next {
+            let (line, column) = span.line_col(&self.files[file].source);
+            self.sites.push((file, line, column));
+        }
+        format!("cw_site{}", index)
+    }
+
+    pub fn function(&mut self, function: &MachineFunction, allocation: &Allocation) {
+        let roots = function
+            .roots
+            .iter()
+            .copied()
+            .filter(|root| allocation.locations[root.0 as usize].is_some())
+            .zip(0..)
+            .collect();
+        let mut emitter = FunctionEmitter {
+            module: self,
+            function,
+            allocation,
+            roots,
+            panics: Vec::new(),
+            labels: 0,
+        };
+        emitter.function();
+    }
+
+    /// Finishes the source with the program's static data
+    pub fn finish(mut self) -> String {
+        let text = &mut self.text;
+        text.push_str("\n    .section .rodata\n    .p2align 3\n");
+        for index in 0..self.program.functions.len() {
+            writeln!(
+                text,
+                "    .quad 0, {:#x}\ncw_function{}:\n    .quad {}, {}, 0",
+                STATIC_HEADER,
+                index,
+                KIND_FUNCTION,
+                function_symbol(index)
+            )
+            .unwrap();
+        }
+        for (index, string) in self.strings.iter().enumerate() {
+            writeln!(
+                text,
+                "    .quad 0, {:#x}\ncw_string{}:\n    .quad {}, {}\n    .ascii {}\n    .p2align 3",
+                STATIC_HEADER,
+                index,
+                KIND_STRING,
+                string.len(),
+                gas_string(string.as_bytes())
+            )
+            .unwrap();
+        }
+        for (index, (file, line, column)) in self.sites.iter().enumerate() {
+            writeln!(
+                text,
+                "cw_site{}:\n    .quad cw_path{}\n    .long {}, {}",
+                index, file, line, column
+            )
+            .unwrap();
+        }
+        for (index, file) in self.files.iter().enumerate() {
+            let path = file.path.display().to_string();
+            writeln!(
+                text,
+                "cw_path{}:\n    .asciz {}",
+                index,
+                gas_string(path.as_bytes())
+            )
+            .unwrap();
+        }
+        text.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+        self.text
+    }
+}
+
+/// A string literal of the assembler, escaping everything but printable ASCII
+fn gas_string(bytes: &[u8]) -> String {
+    let mut literal = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+struct FunctionEmitter<'m, 'b> {
+    module: &'m mut Module<'b>,
+    function: &'m MachineFunction,
+    allocation: &'m Allocation,
+    /// The index of each root in the function's frame on the shadow stack
+    roots: HashMap<VReg, u32>,
+    /// Out-of-line code raising panics, as their label, site and message
+    panics: Vec<(String, String, &'static str)>,
+    labels: u32,
+}
+
+impl FunctionEmitter<'_, '_> {
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.module.text.push_str("    ");
+        self.module.text.push_str(line.as_ref());
+        self.module.text.push('\n');
+    }
+
+    fn function(&mut self) {
+        let function = self.function;
+        let symbol = function_symbol(function.index);
+        writeln!(
+            self.module.text,
+            "\n    .p2align 4\n{}: # {}",
+            symbol, function.name
+        )
+        .unwrap();
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        for register in &self.allocation.callee_saved {
+            self.line(format!("pushq {}", register.name()));
+        }
+        // Keeps `rsp` aligned, after the return address and `rbp` took 16 bytes
+        let saved = self.allocation.callee_saved.len() as u32;
+        let words = self.allocation.stack_slots + self.frame_words();
+        let slots = words + (saved + words) % 2;
+        if slots > 0 {
+            self.line(format!("subq ${}, %rsp", 8 * slots));
+        }
+        if !self.roots.is_empty() {
+            // Roots start out holding 0, which is not an object
+            let (parent, count) = (self.frame_slot(0), self.frame_slot(1));
+            self.line("movq cw_frames(%rip), %rax");
+            self.line(format!("movq %rax, {}", parent));
+            self.line(format!("movq ${}, {}", self.roots.len(), count));
+            for index in 0..self.roots.len() as u32 {
+                let root = self.frame_slot(2 + index);
+                self.line(format!("movq $0, {}", root));
+            }
+            self.line(format!("leaq {}, %rax", parent));
+            self.line("movq %rax, cw_frames(%rip)");
+        }
+
+        for inst in &function.instructions {
+            self.instruction(inst);
+            for def in inst.defs() {
+                self.root(def);
+            }
+        }
+
+        writeln!(self.module.text, "{}:", self.return_label()).unwrap();
+        if !self.roots.is_empty() {
+            let parent = self.frame_slot(0);
+            self.line(format!("movq {}, %rcx", parent));
+            self.line("movq %rcx, cw_frames(%rip)");
+        }
+        match saved {
+            0 => self.line("movq %rbp, %rsp"),
+            _ => self.line(format!("leaq -{}(%rbp), %rsp", 8 * saved)),
+        }
+        for register in self.allocation.callee_saved.iter().rev() {
+            self.line(format!("popq {}", register.name()));
+        }
+        self.line("popq %rbp");
+        self.line("ret");
+
+        for (label, site, message) in std::mem::take(&mut self.panics) {
+            writeln!(self.module.text, "{}:", label).unwrap();
+            self.line(format!("leaq {}(%rip), %rdi", site));
+            self.line(format!("leaq cw_message_{}(%rip), %rsi", message));
+            self.line("call cw_panic");
+        }
+    }
+
+    fn return_label(&self) -> String {
+        format!(".L{}_return", self.function.index)
+    }
+
+    fn label(&self, label: Label) -> String {
+        format!(".L{}_{}", self.function.index, label.0)
+    }
+
+    /// A label for a jump within the code of one instruction
+    fn local_label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}_local{}", self.function.index, self.labels)
+    }
+
+    /// A label jumping to which panics at `span` with the runtime's message `cw_message_{message}`
+    fn panic_label(&mut self, span: Span, message: &'static str) -> String {
+        let site = self.module.site(self.function.file, span);
+        let label = format!(".L{}_panic{}", self.function.index, self.panics.len());
+        self.panics.push((label.clone(), site, message));
+        label
+    }
+
+    fn location(&self, vreg: VReg) -> Location {
+        self.allocation.locations[vreg.0 as usize].expect("mentioned registers are allocated")
+    }
+
+    fn slot(&self, slot: u32) -> String {
+        let saved = self.allocation.callee_saved.len() as u32;
+        format!("-{}(%rbp)", 8 * (saved + slot + 1))
+    }
+
+    /// The words of the function's frame on the shadow stack: its parent frame, the number of
+    /// roots and the roots, or none when it has no roots
+    fn frame_words(&self) -> u32 {
+        match self.roots.len() as u32 {
+            0 => 0,
+            roots => roots + 2,
+        }
+    }
+
+    /// The word `word` of the function's frame on the shadow stack, which follows the stack slots
+    fn frame_slot(&self, word: u32) -> String {
+        self.slot(self.allocation.stack_slots + self.frame_words() - 1 - word)
+    }
+
+    /// Copies `vreg` to the function's frame on the shadow stack, if it is a root
+    fn root(&mut self, vreg: VReg) {
+        let Some(&index) = self.roots.get(&vreg) else {
+            return;
+        };
+        let root = self.frame_slot(2 + index);
+        match self.location(vreg) {
+            Location::Register(register) => {
+                self.line(format!("movq {}, {}", register.name(), root))
+            }
+            Location::Stack(_) => {
+                self.load(vreg, "%rax");
+                self.line(format!("movq %rax, {}", root));
+            }
+        }
+    }
+
+    /// The register or stack slot holding `vreg`
+    fn operand(&self, vreg: VReg) -> String {
+        match self.location(vreg) {
+            Location::Register(register) => register.name().to_string(),
+            Location::Stack(slot) => self.slot(slot),
+        }
+    }
+
+    /// The low 32 bits of `vreg`, which hold the payload of an `Int`
+    fn operand32(&self, vreg: VReg) -> String {
+        match self.location(vreg) {
+            Location::Register(register) => register.name32().to_string(),
+            Location::Stack(slot) => self.slot(slot),
+        }
+    }
+
+    fn load(&mut self, vreg: VReg, register: &str) {
+        let operand = self.operand(vreg);
+        self.line(format!("movq {}, {}", operand, register));
+    }
+
+    fn store(&mut self, register: &str, vreg: VReg) {
+        let operand = self.operand(vreg);
+        self.line(format!("movq {}, {}", register, operand));
+    }
+
+    /// Tags the 32-bit integer in `eax` and stores it in `dst`
+    fn store_int(&mut self, dst: VReg) {
+        self.line(format!("movabsq ${:#x}, %rcx", INT));
+        self.line("orq %rcx, %rax");
+        self.store("%rax", dst);
+    }
+
+    /// Tags the machine boolean in `eax` and stores it in `dst`
+    fn store_bool(&mut self, dst: VReg) {
+        self.line(format!("movabsq ${:#x}, %rcx", BOOL));
+        self.line("orq %rcx, %rax");
+        self.store("%rax", dst);
+    }
+
+    /// Replaces the value pointing to an object in `register` by the object's address
+    fn untag(&mut self, register: &str) {
+        self.line(format!("shlq $16, {}", register));
+        self.line(format!("shrq $16, {}", register));
+    }
+
+    /// Loads the word `field` of the object in `rax` into `dst`
+    fn load_field(&mut self, field: u32, dst: VReg) {
+        match self.location(dst) {
+            Location::Register(register) => {
+                self.line(format!("movq {}(%rax), {}", 8 * field, register.name()))
+            }
+            Location::Stack(_) => {
+                self.line(format!("movq {}(%rax), %rcx", 8 * field));
+                self.store("%rcx", dst);
+            }
+        }
+    }
+
+    /// Pushes the values and addresses of `args`, and pops them into `registers`
+    fn arguments(&mut self, args: &[Arg], registers: &[&str]) {
+        for arg in args {
+            match arg {
+                Arg::Value(vreg) => {
+                    let operand = self.operand(*vreg);
+                    self.line(format!("pushq {}", operand));
+                }
+                Arg::Site(span) => {
+                    let site = self.module.site(self.function.file, *span);
+                    self.line(format!("leaq {}(%rip), %rax", site));
+                    self.line("pushq %rax");
+                }
+                Arg::Word(word) if *word <= i32::MAX as u64 => {
+                    self.line(format!("pushq ${}", word))
+                }
+                Arg::Word(word) => {
+                    self.line(format!("movabsq ${:#x}, %rax", word));
+                    self.line("pushq %rax");
+                }
+                Arg::Symbol(symbol) => {
+                    self.line(format!("leaq {}(%rip), %rax", symbol));
+                    self.line("pushq %rax");
+                }
+            }
+        }
+        for register in registers[..args.len()].iter().rev() {
+            self.line(format!("popq {}", register));
+        }
+    }
+
+    /// Calls a compiled function, counting the call towards the maximum call depth. `target` is
+    /// the function's symbol, or `None` to call the code of the function value `env`.
+    fn call(&mut self, target: Option<String>, env: Option<VReg>, args: &[VReg], span: Span) {
+        let overflow = self.panic_label(span, "stack_overflow");
+        self.line(format!("cmpl ${}, cw_depth(%rip)", MAX_CALL_DEPTH));
+        self.line(format!("je {}", overflow));
+        self.line("incl cw_depth(%rip)");
+
+        let registers = ARGUMENT_REGISTERS.len() - 1;
+        let stacked = args.len().saturating_sub(registers);
+        let padding = stacked % 2;
+        if padding == 1 {
+            self.line("subq $8, %rsp");
+        }
+        for arg in args[args.len() - stacked..].iter().rev() {
+            let operand = self.operand(*arg);
+            self.line(format!("pushq {}", operand));
+        }
+        let mut values = vec![match env {
+            Some(env) => Arg::Value(env),
+            None => Arg::Word(0),
+        }];
+        values.extend(args[..args.len() - stacked].iter().copied().map(Arg::Value));
+        self.arguments(&values, &ARGUMENT_REGISTERS);
+
+        match target {
+            Some(symbol) => self.line(format!("call {}", symbol)),
+            None => {
+                // Functions and closures both keep their code in the word after their kind
+                self.line("movq %rdi, %r11");
+                self.untag("%r11");
+                self.line("call *8(%r11)");
+            }
+        }
+        if stacked + padding > 0 {
+            self.line(format!("addq ${}, %rsp", 8 * (stacked + padding)));
+        }
+        self.line("decl cw_depth(%rip)");
+    }
+
+    fn instruction(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Label(label) => {
+                let label = self.label(*label);
+                writeln!(self.module.text, "{}:", label).unwrap();
+            }
+            Inst::Parameters(params) => {
+                let in_registers = params.len().min(ARGUMENT_REGISTERS.len());
+                for register in &ARGUMENT_REGISTERS[..in_registers] {
+                    self.line(format!("pushq {}", register));
+                }
+                for param in params[..in_registers].iter().rev() {
+                    match self.allocation.locations[param.0 as usize] {
+                        Some(_) => {
+                            let operand = self.operand(*param);
+                            self.line(format!("popq {}", operand));
+                        }
+                        None => self.line("addq $8, %rsp"),
+                    }
+                }
+                for (index, param) in params[in_registers..].iter().enumerate() {
+                    if self.allocation.locations[param.0 as usize].is_some() {
+                        self.line(format!("movq {}(%rbp), %rax", 16 + 8 * index));
+                        self.store("%rax", *param);
+                    }
+                }
+            }
+            Inst::Const { dst, bits } => match self.location(*dst) {
+                Location::Register(register) => {
+                    self.line(format!("movabsq ${:#x}, {}", bits, register.name()))
+                }
+                Location::Stack(_) => {
+                    self.line(format!("movabsq ${:#x}, %rax", bits));
+                    self.store("%rax", *dst);
+                }
+            },
+            Inst::Object { dst, symbol } => {
+                self.line(format!("leaq {}(%rip), %rax", symbol));
+                self.line(format!("movabsq ${:#x}, %rcx", OBJECT));
+                self.line("orq %rcx, %rax");
+                self.store("%rax", *dst);
+            }
+            Inst::Move { dst, src } => {
+                let (to, from) = (self.location(*dst), self.location(*src));
+                match (to, from) {
+                    _ if to == from => {}
+                    (Location::Stack(_), Location::Stack(_)) => {
+                        self.load(*src, "%rax");
+                        self.store("%rax", *dst);
+                    }
+                    _ => {
+                        let (to, from) = (self.operand(*dst), self.operand(*src));
+                        self.line(format!("movq {}, {}", from, to));
+                    }
+                }
+            }
+            Inst::LoadField { dst, object, field } => {
+                self.load(*object, "%rax");
+                self.untag("%rax");
+                self.load_field(*field, *dst);
+            }
+            Inst::StoreField {
+                object,
+                field,
+                value,
+            } => {
+                self.load(*object, "%rax");
+                self.untag("%rax");
+                self.load(*value, "%rcx");
+                self.line(format!("movq %rcx, {}(%rax)", 8 * field));
+            }
+            Inst::Int {
+                op,
+                dst,
+                a,
+                b,
+                span,
+            } => {
+                let a = self.operand32(*a);
+                let b = self.operand32(*b);
+                self.line(format!("movl {}, %eax", a));
+                let (operation, overflow) = match op {
+                    IntOp::Add => ("addl", Some("add")),
+                    IntOp::Subtract => ("subl", Some("subtract")),
+                    IntOp::Multiply => ("imull", Some("multiply")),
+                    IntOp::And => ("andl", None),
+                    IntOp::Or => ("orl", None),
+                    IntOp::Xor => ("xorl", None),
+                    IntOp::Divide | IntOp::Remainder => {
+                        let message = match op {
+                            IntOp::Divide => "divide",
+                            _ => "remainder",
+                        };
+                        let zero = self.panic_label(*span, "divide_by_zero");
+                        let overflow = self.panic_label(*span, message);
+                        let divide = self.local_label();
+                        self.line(format!("movl {}, %ecx", b));
+                        self.line("testl %ecx, %ecx");
+                        self.line(format!("jz {}", zero));
+                        self.line("cmpl $-1, %ecx");
+                        self.line(format!("jne {}", divide));
+                        self.line("cmpl $-2147483648, %eax");
+                        self.line(format!("je {}", overflow));
+                        writeln!(self.module.text, "{}:", divide).unwrap();
+                        self.line("cltd");
+                        self.line("idivl %ecx");
+                        if *op == IntOp::Remainder {
+                            self.line("movl %edx, %eax");
+                        }
+                        self.store_int(*dst);
+                        return;
+                    }
+                    IntOp::ShiftLeft | IntOp::ShiftRight => {
+                        let (operation, message) = match op {
+                            IntOp::ShiftLeft => ("shll", "shift_left"),
+                            _ => ("sarl", "shift_right"),
+                        };
+                        let overflow = self.panic_label(*span, message);
+                        self.line(format!("movl {}, %ecx", b));
+                        // Negative amounts compare above 31 as unsigned numbers
+                        self.line("cmpl $31, %ecx");
+                        self.line(format!("ja {}", overflow));
+                        self.line(format!("{} %cl, %eax", operation));
+                        self.store_int(*dst);
+                        return;
+                    }
+                };
+                self.line(format!("{} {}, %eax", operation, b));
+                if let Some(message) = overflow {
+                    let overflow = self.panic_label(*span, message);
+                    self.line(format!("jo {}", overflow));
+                }
+                self.store_int(*dst);
+            }
+            Inst::Float { op, dst, a, b } => {
+                for (vreg, register) in [(*a, "%xmm0"), (*b, "%xmm1")] {
+                    match self.location(vreg) {
+                        Location::Register(source) => {
+                            self.line(format!("movq {}, {}", source.name(), register))
+                        }
+                        Location::Stack(slot) => {
+                            let slot = self.slot(slot);
+                            self.line(format!("movsd {}, {}", slot, register));
+                        }
+                    }
+                }
+                match op {
+                    FloatOp::Add => self.line("addsd %xmm1, %xmm0"),
+                    FloatOp::Subtract => self.line("subsd %xmm1, %xmm0"),
+                    FloatOp::Multiply => self.line("mulsd %xmm1, %xmm0"),
+                    FloatOp::Divide => self.line("divsd %xmm1, %xmm0"),
+                    FloatOp::Remainder => self.line("call fmod@PLT"),
+                }
+                self.line("movq %xmm0, %rax");
+                self.store("%rax", *dst);
+            }
+            Inst::Unary { op, dst, src, span } => match op {
+                UnaryOp::NegateInt | UnaryOp::BitNot => {
+                    let src = self.operand32(*src);
+                    self.line(format!("movl {}, %eax", src));
+                    if *op == UnaryOp::NegateInt {
+                        let overflow = self.panic_label(*span, "negate");
+                        self.line("negl %eax");
+                        self.line(format!("jo {}", overflow));
+                    } else {
+                        self.line("notl %eax");
+                    }
+                    self.store_int(*dst);
+                }
+                UnaryOp::Not | UnaryOp::NegateFloat => {
+                    self.load(*src, "%rax");
+                    match op {
+                        UnaryOp::Not => self.line("xorq $1, %rax"),
+                        _ => self.line("btcq $63, %rax"),
+                    }
+                    self.store("%rax", *dst);
+                }
+            },
+            Inst::CallRuntime {
+                dst,
+                function,
+                args,
+            } => {
+                self.arguments(args, &RUNTIME_REGISTERS);
+                self.line(format!("call {}", function));
+                if let Some(dst) = dst {
+                    self.store("%rax", *dst);
+                }
+            }
+            Inst::Call {
+                dst,
+                function,
+                args,
+                span,
+            } => {
+                self.call(Some(function_symbol(*function)), None, args, *span);
+                self.store("%rax", *dst);
+            }
+            Inst::CallValue {
+                dst,
+                callee,
+                args,
+                span,
+            } => {
+                self.call(None, Some(*callee), args, *span);
+                self.store("%rax", *dst);
+            }
+            Inst::Jump(target) => {
+                let target = self.label(*target);
+                self.line(format!("jmp {}", target));
+            }
+            Inst::Branch {
+                condition,
+                value,
+                target,
+            } => {
+                let target = self.label(*target);
+                match condition {
+                    Condition::False => {
+                        let operand = self.operand(*value);
+                        self.line(format!("btq $0, {}", operand));
+                        self.line(format!("jnc {}", target));
+                    }
+                }
+            }
+            Inst::RangeStart { dst, range } => {
+                self.load(*range, "%rax");
+                self.untag("%rax");
+                self.line(format!("movl {}(%rax), %eax", 8 * FIELD_VALUE));
+                self.store_int(*dst);
+            }
+            Inst::RangeContains { dst, range, item } => {
+                // Ranges hold their bounds sign-extended to 64 bits, then a machine boolean
+                let item = self.operand32(*item);
+                self.line(format!("movslq {}, %rcx", item));
+                self.load(*range, "%rax");
+                self.untag("%rax");
+                self.line(format!("cmpq {}(%rax), %rcx", 8 * (FIELD_VALUE + 1)));
+                self.line("setl %dl");
+                self.line("sete %cl");
+                self.line(format!("andb {}(%rax), %cl", 8 * (FIELD_VALUE + 2)));
+                self.line("orb %cl, %dl");
+                self.line("movzbl %dl, %eax");
+                self.store_bool(*dst);
+            }
+            Inst::IsNull { dst, src } => {
+                let operand = self.operand(*src);
+                self.line(format!("movabsq ${:#x}, %rcx", NULL));
+                self.line(format!("cmpq %rcx, {}", operand));
+                self.line("sete %al");
+                self.line("movzbl %al, %eax");
+                self.store_bool(*dst);
+            }
+            Inst::Return(value) => {
+                self.load(*value, "%rax");
+                let target = self.return_label();
+                self.line(format!("jmp {}", target));
+            }
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Instruction selection, which turns the IR of a function into machine instructions over virtual
+//! registers.
+//! IR value `n` becomes virtual register `n`, which is a root of the garbage collector if the value
+//! may point to a heap object. A phi is implemented by moves into its register at the end of each
+//! predecessor of its block, which follow from splitting critical edges first.
+use crate::back_end::asm::{
+    Arg, Condition, FloatOp, Inst, IntOp, Label, MachineFunction, UnaryOp, VReg, BOOL, CHAR,
+    FIELD_CAPTURES, FIELD_VALUE, INT, NULL, UNIT,
+};
+use crate::back_end::ir::{
+    cfg, Block, Builtin, Constant, Function, InstructionKind, Program, Terminator, Value,
+};
+use crate::front_end::ast::{self, BinaryOp};
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+
+/// The symbol of a compiled function
+pub fn function_symbol(index: usize) -> String {
+    format!("f{}", index)
+}
+
+/// Lowers the function at `index` in `program`, adding the string constants it uses to `strings`
+pub fn lower(program: &Program, index: usize, strings: &mut Vec<String>) -> MachineFunction {
+    let mut function = program.functions[index].clone();
+    cfg::split_critical_edges(&mut function);
+    let mut lowering = Lowering {
+        function: &function,
+        strings,
+        instructions: Vec::new(),
+        vregs: function.values.len() as u32,
+    };
+
+    let env = lowering.vreg();
+    let params = std::iter::once(env)
+        .chain(function.params.iter().map(|&param| vreg(param)))
+        .collect();
+    lowering.emit(Inst::Parameters(params));
+    for block in function.block_ids() {
+        if block != Block(0) {
+            lowering.emit(Inst::Label(Label(block.0)));
+        }
+        for instruction in &function.block(block).instructions {
+            lowering.instruction(&instruction.kind, instruction.result, instruction.span, env);
+        }
+        lowering.terminator(block);
+    }
+
+    let roots = (0..function.values.len() as u32)
+        .filter(|&n| function.values[n as usize].is_traced())
+        .map(VReg)
+        .collect();
+    MachineFunction {
+        name: function.name.clone(),
+        index,
+        file: function.file,
+        vregs: lowering.vregs,
+        roots,
+        instructions: lowering.instructions,
+    }
+}
+
+fn vreg(value: Value) -> VReg {
+    VReg(value.0)
+}
+
+struct Lowering<'f, 's> {
+    function: &'f Function,
+    strings: &'s mut Vec<String>,
+    instructions: Vec<Inst>,
+    vregs: u32,
+}
+
+impl Lowering<'_, '_> {
+    fn vreg(&mut self) -> VReg {
+        self.vregs += 1;
+        VReg(self.vregs - 1)
+    }
+
+    fn emit(&mut self, instruction: Inst) {
+        self.instructions.push(instruction);
+    }
+
+    /// The symbol of a string constant, which is emitted once however many times it is used
+    fn string(&mut self, value: &str) -> String {
+        let index = match self.strings.iter().position(|string| string == value) {
+            Some(index) => index,
+            None => {
+                self.strings.push(value.to_string());
+                self.strings.len() - 1
+            }
+        };
+        format!("cw_string{}", index)
+    }
+
+    fn runtime(&mut self, dst: VReg, function: &'static str, args: Vec<Arg>) {
+        self.emit(Inst::CallRuntime {
+            dst: Some(dst),
+            function,
+            args,
+        });
+    }
+
+    /// Lowers one instruction, whose result is `result` if it has one
+    fn instruction(
+        &mut self,
+        kind: &InstructionKind,
+        result: Option<Value>,
+        span: Span,
+        env: VReg,
+    ) {
+        let dst = result.map_or(VReg(u32::MAX), vreg);
+        match kind {
+            InstructionKind::Const(constant) => {
+                let bits = match constant {
+                    Constant::Int(value) => INT | *value as u32 as u64,
+                    Constant::Float(value) => value.to_bits(),
+                    Constant::Bool(value) => BOOL | *value as u64,
+                    Constant::Char(value) => CHAR | *value as u64,
+                    Constant::Unit => UNIT,
+                    Constant::Null => NULL,
+                    Constant::String(value) => {
+                        let symbol = self.string(value);
+                        return self.emit(Inst::Object { dst, symbol });
+                    }
+                };
+                self.emit(Inst::Const { dst, bits });
+            }
+            InstructionKind::Function(function) => {
+                let symbol = format!("cw_function{}", function);
+                self.emit(Inst::Object { dst, symbol });
+            }
+            InstructionKind::Closure { function, captures } => {
+                self.emit(Inst::CallRuntime {
+                    dst: Some(dst),
+                    function: "cw_closure",
+                    args: vec![
+                        Arg::Symbol(function_symbol(*function)),
+                        Arg::Word(captures.len() as u64),
+                    ],
+                });
+                for (field, value) in (FIELD_CAPTURES..).zip(captures) {
+                    self.emit(Inst::StoreField {
+                        object: dst,
+                        field,
+                        value: vreg(*value),
+                    });
+                }
+            }
+            InstructionKind::Capture(slot) => self.emit(Inst::LoadField {
+                dst,
+                object: env,
+                field: FIELD_CAPTURES + slot,
+            }),
+            InstructionKind::NewCell(value) => {
+                self.runtime(dst, "cw_cell", vec![Arg::Value(vreg(*value))])
+            }
+            InstructionKind::LoadCell(cell) => self.emit(Inst::LoadField {
+                dst,
+                object: vreg(*cell),
+                field: FIELD_VALUE,
+            }),
+            InstructionKind::StoreCell { cell, value } => self.emit(Inst::StoreField {
+                object: vreg(*cell),
+                field: FIELD_VALUE,
+                value: vreg(*value),
+            }),
+            InstructionKind::Unary { op, operand } => {
+                let float = *self.function.type_of(*operand) == Type::Float;
+                let op = match op {
+                    ast::UnaryOp::Negate if float => UnaryOp::NegateFloat,
+                    ast::UnaryOp::Negate => UnaryOp::NegateInt,
+                    ast::UnaryOp::Not => UnaryOp::Not,
+                    ast::UnaryOp::BitNot => UnaryOp::BitNot,
+                };
+                let src = vreg(*operand);
+                self.emit(Inst::Unary { op, dst, src, span });
+            }
+            InstructionKind::Binary { op, left, right } => {
+                let (a, b) = (vreg(*left), vreg(*right));
+                let float = *self.function.type_of(*left) == Type::Float;
+                let compare = |function| (function, vec![Arg::Value(a), Arg::Value(b)]);
+                let (function, args) = match op {
+                    BinaryOp::Equal => compare("cw_equal"),
+                    BinaryOp::NotEqual => compare("cw_not_equal"),
+                    BinaryOp::Less => compare("cw_less"),
+                    BinaryOp::LessEqual => compare("cw_less_equal"),
+                    BinaryOp::Greater => compare("cw_greater"),
+                    BinaryOp::GreaterEqual => compare("cw_greater_equal"),
+                    _ if float => {
+                        let op = match op {
+                            BinaryOp::Add => FloatOp::Add,
+                            BinaryOp::Subtract => FloatOp::Subtract,
+                            BinaryOp::Multiply => FloatOp::Multiply,
+                            BinaryOp::Divide => FloatOp::Divide,
+                            _ => FloatOp::Remainder,
+                        };
+                        return self.emit(Inst::Float { op, dst, a, b });
+                    }
+                    _ => {
+                        let op = match op {
+                            BinaryOp::Add => IntOp::Add,
+                            BinaryOp::Subtract => IntOp::Subtract,
+                            BinaryOp::Multiply => IntOp::Multiply,
+                            BinaryOp::Divide => IntOp::Divide,
+                            BinaryOp::Remainder => IntOp::Remainder,
+                            BinaryOp::BitAnd => IntOp::And,
+                            BinaryOp::BitOr => IntOp::Or,
+                            BinaryOp::BitXor => IntOp::Xor,
+                            BinaryOp::ShiftLeft => IntOp::ShiftLeft,
+                            BinaryOp::ShiftRight => IntOp::ShiftRight,
+                            _ => unreachable!("short-circuiting operators are lowered to branches"),
+                        };
+                        return self.emit(Inst::Int {
+                            op,
+                            dst,
+                            a,
+                            b,
+                            span,
+                        });
+                    }
+                };
+                self.runtime(dst, function, args);
+            }
+            InstructionKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let args = vec![
+                    Arg::Value(vreg(*start)),
+                    Arg::Value(vreg(*end)),
+                    Arg::Word(*inclusive as u64),
+                ];
+                self.runtime(dst, "cw_range", args);
+            }
+            InstructionKind::RangeStart(range) => self.emit(Inst::RangeStart {
+                dst,
+                range: vreg(*range),
+            }),
+            InstructionKind::RangeContains { range, item } => self.emit(Inst::RangeContains {
+                dst,
+                range: vreg(*range),
+                item: vreg(*item),
+            }),
+            InstructionKind::IsNull(value) => self.emit(Inst::IsNull {
+                dst,
+                src: vreg(*value),
+            }),
+            InstructionKind::Payload(result) => self.emit(Inst::LoadField {
+                dst,
+                object: vreg(*result),
+                field: FIELD_VALUE,
+            }),
+            InstructionKind::Call { function, args } => self.emit(Inst::Call {
+                dst,
+                function: *function,
+                args: args.iter().copied().map(vreg).collect(),
+                span,
+            }),
+            InstructionKind::CallValue { callee, args } => self.emit(Inst::CallValue {
+                dst,
+                callee: vreg(*callee),
+                args: args.iter().copied().map(vreg).collect(),
+                span,
+            }),
+            InstructionKind::CallBuiltin { builtin, args } => {
+                let args: Vec<Arg> = args.iter().map(|&arg| Arg::Value(vreg(arg))).collect();
+                let (function, args) = match builtin {
+                    Builtin::Println if args.is_empty() => ("cw_println_empty", args),
+                    Builtin::Println => ("cw_println", args),
+                    Builtin::Print => ("cw_print", args),
+                    Builtin::PrintPadded => ("cw_print_padded", args),
+                    Builtin::Input => ("cw_input", args),
+                    Builtin::ToString => ("cw_to_string", args),
+                    Builtin::Concat => ("cw_concat", args),
+                    Builtin::Int => ("cw_to_int", [vec![Arg::Site(span)], args].concat()),
+                    Builtin::Float => ("cw_to_float", args),
+                    Builtin::Char => ("cw_to_char", [vec![Arg::Site(span)], args].concat()),
+                    Builtin::Panic => ("cw_panic_value", [vec![Arg::Site(span)], args].concat()),
+                    Builtin::Ok if args.is_empty() => ("cw_ok", vec![Arg::Word(UNIT)]),
+                    Builtin::Ok => ("cw_ok", args),
+                    Builtin::Err => ("cw_err", args),
+                    Builtin::Unwrap => ("cw_unwrap", [vec![Arg::Site(span)], args].concat()),
+                    Builtin::UnwrapErr => ("cw_unwrap_err", [vec![Arg::Site(span)], args].concat()),
+                    Builtin::IsOk => ("cw_is_ok", args),
+                    Builtin::IsErr => ("cw_is_err", args),
+                    Builtin::Min => ("cw_min", args),
+                    Builtin::Max => ("cw_max", args),
+                    Builtin::Abs => ("cw_abs", [vec![Arg::Site(span)], args].concat()),
+                    Builtin::Pow => ("cw_pow", [vec![Arg::Site(span)], args].concat()),
+                    Builtin::Sqrt => ("cw_sqrt", args),
+                    Builtin::Ceil => ("cw_ceil", args),
+                    Builtin::Floor => ("cw_floor", args),
+                    Builtin::Sin => ("cw_sin", args),
+                    Builtin::Cos => ("cw_cos", args),
+                    Builtin::Log => ("cw_log", args),
+                    Builtin::Ln => ("cw_ln", args),
+                    Builtin::PowE => ("cw_pow_e", args),
+                    Builtin::Open => ("cw_open", args),
+                    Builtin::Close => ("cw_close", args),
+                    Builtin::Read => ("cw_read", args),
+                    Builtin::ReadLine => ("cw_read_line", args),
+                    Builtin::Write => ("cw_write_file", args),
+                    Builtin::NextLine => ("cw_next_line", args),
+                    Builtin::CharAt => ("cw_char_at", args),
+                    Builtin::CharWidth => ("cw_char_width", args),
+                };
+                self.runtime(dst, function, args);
+            }
+        }
+    }
+
+    /// Moves the values that the phis of `to` take when coming from `from` into their registers,
+    /// through temporaries when there are several, since a phi may be the value of another
+    fn phi_moves(&mut self, from: Block, to: Block) {
+        let moves: Vec<(VReg, VReg)> = self
+            .function
+            .block(to)
+            .phis
+            .iter()
+            .filter_map(|phi| {
+                let (_, value) = phi.incoming.iter().find(|(block, _)| *block == from)?;
+                (*value != phi.result).then_some((vreg(phi.result), vreg(*value)))
+            })
+            .collect();
+        if let [(dst, src)] = moves[..] {
+            return self.emit(Inst::Move { dst, src });
+        }
+        let temporaries: Vec<VReg> = moves.iter().map(|_| self.vreg()).collect();
+        for (&dst, &(_, src)) in temporaries.iter().zip(&moves) {
+            self.emit(Inst::Move { dst, src });
+        }
+        for (&src, &(dst, _)) in temporaries.iter().zip(&moves) {
+            self.emit(Inst::Move { dst, src });
+        }
+    }
+
+    /// Ends a block, falling through to the next one rather than jumping to it
+    fn terminator(&mut self, block: Block) {
+        let next = Block(block.0 + 1);
+        match self.function.block(block).terminator {
+            Terminator::Jump(target) => {
+                self.phi_moves(block, target);
+                if target != next {
+                    self.emit(Inst::Jump(Label(target.0)));
+                }
+            }
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => {
+                // Critical edges are split, so a successor with phis has no other predecessor
+                self.phi_moves(block, then);
+                self.phi_moves(block, otherwise);
+                self.emit(Inst::Branch {
+                    condition: Condition::False,
+                    value: vreg(condition),
+                    target: Label(otherwise.0),
+                });
+                if then != next {
+                    self.emit(Inst::Jump(Label(then.0)));
+                }
+            }
+            Terminator::Return(value) => self.emit(Inst::Return(vreg(value))),
+            // The block ends in a call that never returns, but the code must not run past it
+            Terminator::Unreachable => {
+                let unit = self.vreg();
+                self.emit(Inst::Const {
+                    dst: unit,
+                    bits: UNIT,
+                });
+                self.emit(Inst::Return(unit));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::ir;
+    use crate::front_end;
+
+    fn lower_main(source: &str) -> MachineFunction {
+        let analysis = front_end::analyze(source).unwrap();
+        let program = ir::build(&analysis.program);
+        lower(&program, program.entry, &mut Vec::new())
+    }
+
+    #[test]
+    fn test_phis_become_moves() {
+        let function = lower_main("func main() { var b = true; println(if b { 1 } else { 2 }); }");
+        let constants: Vec<VReg> = function
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                Inst::Const { dst, bits } if *bits & !0xFFFF_FFFF == INT => Some(*dst),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(constants.len(), 2);
+        let moved: Vec<VReg> = function
+            .instructions
+            .iter()
+            .filter_map(|inst| match inst {
+                Inst::Move { dst, src } if constants.contains(src) => Some(*dst),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(moved.len(), 2);
+        assert_eq!(moved[0], moved[1]);
+    }
+
+    #[test]
+    fn test_for_loops_test_their_range() {
+        let function = lower_main("func main() { const r = 0..3; for i in r { println(i); } }");
+        assert!(matches!(function.instructions[0], Inst::Parameters(_)));
+        let count = |function: &MachineFunction, test: fn(&Inst) -> bool| {
+            function.instructions.iter().filter(|i| test(i)).count()
+        };
+        assert_eq!(
+            count(&function, |inst| matches!(inst, Inst::RangeStart { .. })),
+            1
+        );
+        assert_eq!(
+            count(&function, |inst| matches!(inst, Inst::RangeContains { .. })),
+            1
+        );
+
+        // A range written in the loop is only a counter
+        let function = lower_main("func main() { for i in 0..3 { println(i); } }");
+        assert_eq!(
+            count(&function, |inst| matches!(inst, Inst::RangeStart { .. })),
+            0
+        );
+        assert_eq!(
+            count(&function, |inst| matches!(inst, Inst::RangeContains { .. })),
+            0
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Linear-scan register allocation.
+//! Each virtual register gets one live interval, from the first to the last instruction it is live
+//! at, as found by a backward dataflow analysis. Intervals are visited by start, and take a free
+//! hardware register, or the register of the active interval that ends last, which is then spilled
+//! to a stack slot. Values live across a call only take callee-saved registers.
+use crate::back_end::asm::{Inst, Label, MachineFunction, VReg};
+use std::collections::HashMap;
+
+/// A hardware register available to the allocator. `rax`, `rcx`, `rdx` and `r11` are left as
+/// scratch registers for the instructions' own use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Register {
+    Rbx,
+    R12,
+    R13,
+    R14,
+    R15,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+}
+
+impl Register {
+    /// Registers preserved across calls by the System V ABI
+    pub const CALLEE_SAVED: [Register; 5] = [
+        Register::Rbx,
+        Register::R12,
+        Register::R13,
+        Register::R14,
+        Register::R15,
+    ];
+    pub const CALLER_SAVED: [Register; 5] = [
+        Register::Rsi,
+        Register::Rdi,
+        Register::R8,
+        Register::R9,
+        Register::R10,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Register::Rbx => "%rbx",
+            Register::R12 => "%r12",
+            Register::R13 => "%r13",
+            Register::R14 => "%r14",
+            Register::R15 => "%r15",
+            Register::Rsi => "%rsi",
+            Register::Rdi => "%rdi",
+            Register::R8 => "%r8",
+            Register::R9 => "%r9",
+            Register::R10 => "%r10",
+        }
+    }
+
+    /// The name of the register's low 32 bits
+    pub fn name32(&self) -> &'static str {
+        match self {
+            Register::Rbx => "%ebx",
+            Register::R12 => "%r12d",
+            Register::R13 => "%r13d",
+            Register::R14 => "%r14d",
+            Register::R15 => "%r15d",
+            Register::Rsi => "%esi",
+            Register::Rdi => "%edi",
+            Register::R8 => "%r8d",
+            Register::R9 => "%r9d",
+            Register::R10 => "%r10d",
+        }
+    }
+
+    pub fn is_callee_saved(&self) -> bool {
+        Register::CALLEE_SAVED.contains(self)
+    }
+}
+
+/// Where a virtual register is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Register(Register),
+    /// A slot of the stack frame, counted from 0
+    Stack(u32),
+}
+
+/// The result of register allocation
+/// - `locations` maps every virtual register the function mentions to its location
+/// - `callee_saved` lists the callee-saved registers the function uses, which it must preserve
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub locations: Vec<Option<Location>>,
+    pub stack_slots: u32,
+    pub callee_saved: Vec<Register>,
+}
+
+/// The instructions from `start` to `end` (inclusive) over which a virtual register is live
+/// - `crosses_call` is true if the register must survive a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub vreg: VReg,
+    pub start: usize,
+    pub end: usize,
+    pub crosses_call: bool,
+}
+
+/// A set of virtual registers
+#[derive(Debug, Clone, PartialEq)]
+struct Set(Vec<u64>);
+
+impl Set {
+    fn new(size: u32) -> Set {
+        Set(vec![0; (size as usize).div_ceil(64)])
+    }
+
+    fn insert(&mut self, vreg: VReg) {
+        self.0[vreg.0 as usize / 64] |= 1 << (vreg.0 % 64);
+    }
+
+    fn remove(&mut self, vreg: VReg) {
+        self.0[vreg.0 as usize / 64] &= !(1 << (vreg.0 % 64));
+    }
+
+    fn union(&mut self, other: &Set) {
+        for (word, other) in self.0.iter_mut().zip(&other.0) {
+            *word |= other;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = VReg> + '_ {
+        self.0.iter().enumerate().flat_map(|(index, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| VReg(index as u32 * 64 + bit))
+        })
+    }
+}
+
+/// The live intervals of every virtual register the function mentions, ordered by start
+pub fn intervals(function: &MachineFunction) -> Vec<Interval> {
+    let instructions = &function.instructions;
+    let labels: HashMap<Label, usize> = instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(position, inst)| match inst {
+            Inst::Label(label) => Some((*label, position)),
+            _ => None,
+        })
+        .collect();
+    let successors: Vec<Vec<usize>> = instructions
+        .iter()
+        .enumerate()
+        .map(|(position, inst)| {
+            let mut successors: Vec<usize> = inst
+                .target()
+                .map(|label| labels[&label])
+                .into_iter()
+                .collect();
+            if inst.falls_through() && position + 1 < instructions.len() {
+                successors.push(position + 1);
+            }
+            successors
+        })
+        .collect();
+
+    // live_in = uses ∪ (live_out − defs), iterated backwards until nothing changes
+    let mut live_in = vec![Set::new(function.vregs); instructions.len()];
+    let mut live_out = live_in.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for position in (0..instructions.len()).rev() {
+            let mut out = Set::new(function.vregs);
+            for &successor in &successors[position] {
+                out.union(&live_in[successor]);
+            }
+            let mut live = out.clone();
+            for vreg in instructions[position].defs() {
+                live.remove(vreg);
+            }
+            for vreg in instructions[position].uses() {
+                live.insert(vreg);
+            }
+            live_out[position] = out;
+            if live != live_in[position] {
+                live_in[position] = live;
+                changed = true;
+            }
+        }
+    }
+
+    let mut ranges: Vec<Option<Interval>> = vec![None; function.vregs as usize];
+    for (position, inst) in instructions.iter().enumerate() {
+        let defs = inst.defs();
+        let mentioned = live_in[position]
+            .iter()
+            .chain(defs.iter().copied())
+            .chain(inst.uses());
+        for vreg in mentioned {
+            let interval = ranges[vreg.0 as usize].get_or_insert(Interval {
+                vreg,
+                start: position,
+                end: position,
+                crosses_call: false,
+            });
+            interval.start = interval.start.min(position);
+            interval.end = interval.end.max(position);
+        }
+        if inst.is_call() {
+            // Results are written after the call returns
+            for vreg in live_out[position].iter() {
+                if !defs.contains(&vreg) {
+                    if let Some(interval) = &mut ranges[vreg.0 as usize] {
+                        interval.crosses_call = true;
+                    }
+                }
+            }
+        }
+    }
+    let mut intervals: Vec<Interval> = ranges.into_iter().flatten().collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.vreg));
+    intervals
+}
+
+/// Assigns a location to every virtual register of the function
+pub fn allocate(function: &MachineFunction) -> Allocation {
+    let mut locations = vec![None; function.vregs as usize];
+    let mut stack_slots = 0;
+    let mut spill = |locations: &mut Vec<Option<Location>>, vreg: VReg| {
+        locations[vreg.0 as usize] = Some(Location::Stack(stack_slots));
+        stack_slots += 1;
+    };
+    let mut active: Vec<(Interval, Register)> = Vec::new();
+    let mut free: Vec<Register> = Register::CALLER_SAVED
+        .into_iter()
+        .chain(Register::CALLEE_SAVED)
+        .collect();
+    let mut callee_saved = Vec::new();
+
+    for interval in intervals(function) {
+        // A register is only reused after the instruction its previous value was last read by
+        active.retain(|(other, register)| {
+            let expired = other.end < interval.start;
+            if expired {
+                free.push(*register);
+            }
+            !expired
+        });
+        let allowed = |register: &Register| !interval.crosses_call || register.is_callee_saved();
+        let choice = Register::CALLER_SAVED
+            .into_iter()
+            .chain(Register::CALLEE_SAVED)
+            .filter(allowed)
+            .find(|register| free.contains(register));
+        let register = match choice {
+            Some(register) => {
+                free.retain(|other| *other != register);
+                register
+            }
+            None => {
+                let victim = active
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, register))| allowed(register))
+                    .max_by_key(|(_, (other, _))| other.end)
+                    .filter(|(_, (other, _))| other.end > interval.end)
+                    .map(|(index, _)| index);
+                match victim {
+                    Some(index) => {
+                        let (other, register) = active.remove(index);
+                        spill(&mut locations, other.vreg);
+                        register
+                    }
+                    None => {
+                        spill(&mut locations, interval.vreg);
+                        continue;
+                    }
+                }
+            }
+        };
+        if register.is_callee_saved() && !callee_saved.contains(&register) {
+            callee_saved.push(register);
+        }
+        locations[interval.vreg.0 as usize] = Some(Location::Register(register));
+        active.push((interval, register));
+    }
+    callee_saved.sort();
+    Allocation {
+        locations,
+        stack_slots,
+        callee_saved,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::asm::{Arg, IntOp};
+    use crate::front_end::token::Span;
+
+    fn function(vregs: u32, instructions: Vec<Inst>) -> MachineFunction {
+        MachineFunction {
+            name: "test".to_string(),
+            index: 0,
+            file: 0,
+            vregs,
+            roots: Vec::new(),
+            instructions,
+        }
+    }
+
+    fn add(dst: u32, a: u32, b: u32) -> Inst {
+        Inst::Int {
+            op: IntOp::Add,
+            dst: VReg(dst),
+            a: VReg(a),
+            b: VReg(b),
+            span: Span::default(),
+        }
+    }
+
+    fn constant(dst: u32) -> Inst {
+        Inst::Const {
+            dst: VReg(dst),
+            bits: 0,
+        }
+    }
+
+    #[test]
+    fn test_intervals_follow_loops() {
+        let function = function(
+            3,
+            vec![
+                constant(0),
+                constant(1),
+                Inst::Label(Label(0)),
+                add(1, 1, 0),
+                constant(2),
+                Inst::Branch {
+                    condition: crate::back_end::asm::Condition::False,
+                    value: VReg(2),
+                    target: Label(0),
+                },
+                Inst::Return(VReg(1)),
+            ],
+        );
+        let intervals = intervals(&function);
+        // The first constant stays live around the loop, until its last iteration
+        assert_eq!((intervals[0].start, intervals[0].end), (0, 5));
+        assert_eq!((intervals[1].start, intervals[1].end), (1, 6));
+        assert_eq!((intervals[2].start, intervals[2].end), (4, 5));
+    }
+
+    #[test]
+    fn test_values_live_across_calls_are_callee_saved() {
+        let function = function(
+            3,
+            vec![
+                constant(0),
+                constant(1),
+                Inst::CallRuntime {
+                    dst: Some(VReg(2)),
+                    function: "cw_println",
+                    args: vec![Arg::Value(VReg(1))],
+                },
+                add(2, 0, 2),
+                Inst::Return(VReg(2)),
+            ],
+        );
+        let allocation = allocate(&function);
+        let Some(Location::Register(kept)) = allocation.locations[0] else {
+            panic!("no register for {:?}", allocation);
+        };
+        assert!(kept.is_callee_saved());
+        assert_eq!(allocation.callee_saved, vec![kept]);
+        let Some(Location::Register(argument)) = allocation.locations[1] else {
+            panic!("no register for {:?}", allocation);
+        };
+        assert!(!argument.is_callee_saved());
+    }
+
+    #[test]
+    fn test_overlapping_intervals_never_share_registers() {
+        // Twelve values live at once, more than there are registers
+        let mut instructions: Vec<Inst> = (0..12).map(constant).collect();
+        instructions.extend((1..12).map(|vreg| add(0, 0, vreg)));
+        instructions.push(Inst::Return(VReg(0)));
+        let function = function(12, instructions);
+        let allocation = allocate(&function);
+        assert_eq!(allocation.stack_slots, 2);
+        let intervals = intervals(&function);
+        for a in &intervals {
+            for b in &intervals {
+                let overlap = a.start <= b.end && b.start <= a.end;
+                if a.vreg != b.vreg && overlap {
+                    let (a, b) = (
+                        allocation.locations[a.vreg.0 as usize],
+                        allocation.locations[b.vreg.0 as usize],
+                    );
+                    assert_ne!(a, b);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,2349 @@
+# Runtime of the programs compiled by the Crawfish x86-64 backend, for Linux and the System V ABI.
+# It only needs the C library the system already has, so that binutils suffice to build programs.
+#
+# Every value is one 64-bit word: a double, or a NaN whose top 16 bits are a tag.
+#   0xFFF9  Int, in the low 32 bits
+#   0xFFFA  Bool, 0 or 1
+#   0xFFFB  Char, a Unicode code point
+#   0xFFFC  () when the payload is 0, null when it is 1
+#   0xFFFD  a pointer to a heap object, whose first word is its kind:
+#           1 String   [length] [bytes...]
+#           2 Range    [start] [end] [inclusive]
+#           3 Ok       [value]
+#           4 Err      [value]
+#           5 closure  [code] [capture count] [captures...]
+#           6 cell     [value]
+#           7 function [code] [0]
+#           8 file     [C stream, or 0 once closed] [1 if open for writing, else 0]
+# Arithmetic only ever produces the NaNs 0x7FF8... and 0xFFF8..., which stay below the tags.
+#
+# The 16 bytes before every object are its header: the header of the object allocated before it,
+# then its size in 32 bits, header included, and a byte that is 1 while it is marked. Objects of the
+# program itself, such as string constants, are always marked, and never collected.
+# The heap is garbage collected: compiled functions push a frame on the shadow stack that
+# `cw_frames` points to the top of, [parent frame] [root count] [roots...], holding every variable
+# that may point to an object, and the collector marks what those roots reach before freeing the
+# rest. In stress mode, set by `CRAWFISH_GC_STRESS`, every allocation collects first.
+
+    .set TAG_INT, 0xFFF9
+    .set TAG_BOOL, 0xFFFA
+    .set TAG_CHAR, 0xFFFB
+    .set TAG_UNIT, 0xFFFC
+    .set TAG_OBJECT, 0xFFFD
+    .set INT, 0xFFF9000000000000
+    .set UNIT, 0xFFFC000000000000
+    .set BOOL, 0xFFFA000000000000
+    .set CHAR, 0xFFFB000000000000
+    .set OBJECT, 0xFFFD000000000000
+    .set KIND_STRING, 1
+    .set KIND_RANGE, 2
+    .set KIND_OK, 3
+    .set KIND_ERR, 4
+    .set KIND_CLOSURE, 5
+    .set KIND_CELL, 6
+    .set KIND_FUNCTION, 7
+    .set KIND_FILE, 8
+    .set EPERM, 1
+    .set ENOENT, 2
+    .set EACCES, 13
+    # back_end::HEAP_LIMIT, the most bytes the heap holds after collecting
+    .set HEAP_LIMIT, 0x40000000
+    # Bytes the heap may grow to before it is first collected
+    .set MIN_HEAP, 0x100000
+    .set EISDIR, 21
+
+    .data
+# Number of calls in progress, counting `main()`
+    .globl cw_depth
+    .p2align 2
+cw_depth:
+    .long 1
+# The innermost frame of roots
+    .globl cw_frames
+    .p2align 3
+cw_frames:
+    .quad 0
+# The header of the last object allocated, the bytes objects take, and how large they may grow
+# before the next collection
+cw_objects:
+    .quad 0
+cw_allocated:
+    .quad 0
+cw_threshold:
+    .quad MIN_HEAP
+# The value that cw_wrap puts in an object, which only the runtime may hold while it allocates
+cw_wrapped:
+    .quad 0
+# Marked objects whose contents are still to be marked, and the room there is for them
+cw_gray:
+    .quad 0
+cw_gray_count:
+    .quad 0
+cw_gray_capacity:
+    .quad 0
+# 1 in stress mode
+cw_stress:
+    .byte 0
+
+    .section .rodata
+.Lnewline_format:
+    .asciz "\n"
+.Lint_format:
+    .asciz "%d"
+.Lwhole_format:
+    .asciz "%.1f"
+.Lscientific_format:
+    .asciz "%.*e"
+.Lfixed_format:
+    .asciz "%.*f"
+.Lpadding_format:
+    .asciz "%*s"
+.Lempty:
+    .asciz ""
+.Lrange_format:
+    .asciz "%d%s%d"
+.Lstress_variable:
+    .asciz "CRAWFISH_GC_STRESS"
+.Lpanic_format:
+    .asciz "panicked at %s:%u:%u: "
+.Ltrue:
+    .asciz "true"
+.Lfalse:
+    .asciz "false"
+.Lunit:
+    .asciz "()"
+.Lnull:
+    .asciz "null"
+.Lnan:
+    .asciz "NaN"
+.Linf:
+    .asciz "inf"
+.Lnegative_inf:
+    .asciz "-inf"
+.Lzero_point:
+    .asciz "0."
+.Lexclusive:
+    .asciz ".."
+.Linclusive:
+    .asciz "..="
+.Lok:
+    .asciz "Ok("
+.Lerr:
+    .asciz "Err("
+.Lclosure:
+    .asciz "<closure>"
+.Lfunction:
+    .asciz "<func>"
+.Lfile:
+    .asciz "<file>"
+.Lunwrap_err:
+    .asciz "called `unwrap()` on an `Err` value: "
+.Lunwrap_err_ok:
+    .asciz "called `unwrap_err()` on an `Ok` value: "
+.Lout_of_memory:
+    .asciz "panicked: out of memory\n"
+.Lprint_failed:
+    .asciz "panicked: failed printing to stdout\n"
+.Lcannot_convert:
+    .asciz "cannot convert "
+.Lconvert_to_format:
+    .asciz " to %s"
+.Lcannot_parse:
+    .asciz "cannot parse \""
+.Lparse_as_format:
+    .asciz "\" as %s"
+.Lint_name:
+    .asciz "Int"
+.Lfloat_name:
+    .asciz "Float"
+.Lchar_name:
+    .asciz "Char"
+.Lnegative_exponent:
+    .asciz "attempt to raise an integer to a negative power"
+.Lcannot_open:
+    .asciz "cannot open \""
+.Lopen_reason_format:
+    .asciz "\": %s"
+.Linvalid_mode:
+    .asciz "invalid mode"
+.Lno_such_file:
+    .asciz "no such file or directory"
+.Lpermission_denied:
+    .asciz "permission denied"
+.Lis_a_directory:
+    .asciz "is a directory"
+.Lio_error:
+    .asciz "input/output error"
+.Lfile_closed:
+    .asciz "the file is closed"
+.Lnot_readable:
+    .asciz "the file is not open for reading"
+.Lnot_writable:
+    .asciz "the file is not open for writing"
+# The floats just outside the range of Int, which both convert exactly
+    .p2align 3
+.Lint_below:
+    .double -2147483649.0
+.Lint_above:
+    .double 2147483648.0
+
+# Messages of the panics raised by compiled code
+    .globl cw_message_add, cw_message_subtract, cw_message_multiply, cw_message_divide
+    .globl cw_message_divide_by_zero, cw_message_remainder, cw_message_negate
+    .globl cw_message_shift_left, cw_message_shift_right, cw_message_stack_overflow
+cw_message_add:
+    .asciz "attempt to add with overflow"
+cw_message_subtract:
+    .asciz "attempt to subtract with overflow"
+cw_message_multiply:
+    .asciz "attempt to multiply with overflow"
+cw_message_divide:
+    .asciz "attempt to divide with overflow"
+cw_message_divide_by_zero:
+    .asciz "attempt to divide by zero"
+cw_message_remainder:
+    .asciz "attempt to calculate the remainder with overflow"
+cw_message_negate:
+    .asciz "attempt to negate with overflow"
+cw_message_shift_left:
+    .asciz "attempt to shift left with overflow"
+cw_message_shift_right:
+    .asciz "attempt to shift right with overflow"
+cw_message_stack_overflow:
+    .asciz "stack overflow"
+
+    .text
+
+# Entry point of the executable: runs `main()` through `cw_main`, which the compiled program defines
+    .globl _start
+_start:
+    xorl %ebp, %ebp
+    andq $-16, %rsp
+    leaq .Lstress_variable(%rip), %rdi
+    call getenv@PLT
+    testq %rax, %rax
+    jz 2f
+    movzbl (%rax), %ecx
+    testl %ecx, %ecx
+    jz 2f
+    cmpl $'0', %ecx
+    jne 1f
+    cmpb $0, 1(%rax)
+    je 2f
+1:
+    movb $1, cw_stress(%rip)
+2:
+    call cw_main
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call fflush@PLT
+    testl %eax, %eax
+    jnz 3f
+    xorl %edi, %edi
+    call exit@PLT
+3:
+    leaq .Lprint_failed(%rip), %rdi
+    jmp cw_fatal
+
+# cw_fatal(message): prints a C string to stderr and exits with code 101
+cw_fatal:
+    andq $-16, %rsp
+    movq %rdi, %rbx
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call fflush@PLT
+    movq %rbx, %rdi
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rsi
+    call fputs@PLT
+    movl $101, %edi
+    call exit@PLT
+
+# cw_alloc(size) -> an object of `size` bytes, after its header, collecting first if the heap is
+# full
+cw_alloc:
+    pushq %rbx
+    leaq 16(%rdi), %rbx
+    cmpb $0, cw_stress(%rip)
+    jne 1f
+    movq cw_allocated(%rip), %rax
+    addq %rbx, %rax
+    cmpq cw_threshold(%rip), %rax
+    jbe 2f
+1:
+    call cw_collect
+2:
+    movq cw_allocated(%rip), %rax
+    addq %rbx, %rax
+    cmpq $HEAP_LIMIT, %rax
+    ja .Lout_of_memory_panic
+    movq %rbx, %rdi
+    call malloc@PLT
+    testq %rax, %rax
+    jz .Lout_of_memory_panic
+    movq cw_objects(%rip), %rcx
+    movq %rcx, (%rax)
+    movl %ebx, 8(%rax)
+    movl $0, 12(%rax)
+    movq %rax, cw_objects(%rip)
+    addq %rbx, cw_allocated(%rip)
+    addq $16, %rax
+    popq %rbx
+    ret
+.Lout_of_memory_panic:
+    leaq .Lout_of_memory(%rip), %rdi
+    jmp cw_fatal
+
+# cw_buffer(size) -> memory of the C library's heap, for text that the runtime frees itself
+cw_buffer:
+    subq $8, %rsp
+    call malloc@PLT
+    addq $8, %rsp
+    testq %rax, %rax
+    jz .Lout_of_memory_panic
+    ret
+
+# cw_mark_value(value): marks the object that a value points to, if it is not marked yet, and
+# leaves it on the gray stack for its contents to be marked
+cw_mark_value:
+    movq %rdi, %rax
+    shrq $48, %rax
+    cmpl $TAG_OBJECT, %eax
+    jne 2f
+    shlq $16, %rdi
+    shrq $16, %rdi
+    cmpb $0, -4(%rdi)
+    jne 2f
+    movb $1, -4(%rdi)
+    movq cw_gray_count(%rip), %rax
+    cmpq cw_gray_capacity(%rip), %rax
+    jb 1f
+    pushq %rdi
+    movq cw_gray_capacity(%rip), %rsi
+    addq %rsi, %rsi
+    movl $256, %eax
+    testq %rsi, %rsi
+    cmovz %rax, %rsi
+    movq %rsi, cw_gray_capacity(%rip)
+    shlq $3, %rsi
+    movq cw_gray(%rip), %rdi
+    call realloc@PLT
+    testq %rax, %rax
+    jz .Lout_of_memory_panic
+    movq %rax, cw_gray(%rip)
+    popq %rdi
+    movq cw_gray_count(%rip), %rax
+1:
+    movq cw_gray(%rip), %rcx
+    movq %rdi, (%rcx,%rax,8)
+    incq cw_gray_count(%rip)
+2:
+    ret
+
+# cw_trace(object): marks the objects that a marked object points to
+cw_trace:
+    movq (%rdi), %rax
+    cmpq $KIND_CLOSURE, %rax
+    je 2f
+    cmpq $KIND_OK, %rax
+    je 1f
+    cmpq $KIND_ERR, %rax
+    je 1f
+    cmpq $KIND_CELL, %rax
+    je 1f
+    ret
+1:
+    movq 8(%rdi), %rdi
+    jmp cw_mark_value
+2:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rdi, %rbx
+    xorl %r12d, %r12d
+3:
+    cmpq 16(%rbx), %r12
+    jae 4f
+    movq 24(%rbx,%r12,8), %rdi
+    call cw_mark_value
+    incq %r12
+    jmp 3b
+4:
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_collect(): frees every object that the roots of the calls in progress cannot reach
+cw_collect:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq cw_wrapped(%rip), %rdi
+    call cw_mark_value
+    movq cw_frames(%rip), %rbx
+1:
+    testq %rbx, %rbx
+    jz 3f
+    xorl %r12d, %r12d
+2:
+    cmpq 8(%rbx), %r12
+    jae 9f
+    movq 16(%rbx,%r12,8), %rdi
+    call cw_mark_value
+    incq %r12
+    jmp 2b
+9:
+    movq (%rbx), %rbx
+    jmp 1b
+3:
+    movq cw_gray_count(%rip), %rax
+    testq %rax, %rax
+    jz 4f
+    decq %rax
+    movq %rax, cw_gray_count(%rip)
+    movq cw_gray(%rip), %rcx
+    movq (%rcx,%rax,8), %rdi
+    call cw_trace
+    jmp 3b
+4:
+    # %rbx: the link to the header in %r12, which is unmarked if it is kept
+    leaq cw_objects(%rip), %rbx
+5:
+    movq (%rbx), %r12
+    testq %r12, %r12
+    jz 8f
+    cmpb $0, 12(%r12)
+    je 6f
+    movb $0, 12(%r12)
+    movq %r12, %rbx
+    jmp 5b
+6:
+    movq (%r12), %rax
+    movq %rax, (%rbx)
+    movl 8(%r12), %eax
+    subq %rax, cw_allocated(%rip)
+    # A file that can no longer be used is closed, as if the program had closed it
+    cmpq $KIND_FILE, 16(%r12)
+    jne 7f
+    movq 24(%r12), %rdi
+    testq %rdi, %rdi
+    jz 7f
+    call fclose@PLT
+7:
+    # Objects freed in stress mode are overwritten, for missing roots to fail loudly
+    cmpb $0, cw_stress(%rip)
+    je 1f
+    movq %r12, %rdi
+    movl $0xAB, %esi
+    movl 8(%r12), %edx
+    call memset@PLT
+1:
+    movq %r12, %rdi
+    call free@PLT
+    jmp 5b
+8:
+    # The next collection is when the heap has doubled, within its limit
+    movq cw_allocated(%rip), %rax
+    addq %rax, %rax
+    movl $MIN_HEAP, %ecx
+    cmpq %rcx, %rax
+    cmovb %rcx, %rax
+    movl $HEAP_LIMIT, %ecx
+    cmpq %rcx, %rax
+    cmova %rcx, %rax
+    movq %rax, cw_threshold(%rip)
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# Tags the object pointer in %rax
+.macro BOX_OBJECT
+    movabsq $OBJECT, %rcx
+    orq %rcx, %rax
+.endm
+
+# Replaces a tagged object pointer in \register with the bare pointer
+.macro UNBOX register
+    shlq $16, \register
+    shrq $16, \register
+.endm
+
+# Returns %al (0 or 1) as a Bool
+cw_bool:
+    movzbl %al, %eax
+    movabsq $BOOL, %rcx
+    orq %rcx, %rax
+    ret
+
+# cw_wrap(value, kind) -> an object of `kind` holding `value`
+cw_wrap:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    # The value may be an object that the runtime just made, which nothing else holds
+    movq %rdi, cw_wrapped(%rip)
+    movl $16, %edi
+    call cw_alloc
+    movq $0, cw_wrapped(%rip)
+    movq %r12, (%rax)
+    movq %rbx, 8(%rax)
+    BOX_OBJECT
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_ok(value) -> Ok(value)
+    .globl cw_ok
+cw_ok:
+    movl $KIND_OK, %esi
+    jmp cw_wrap
+
+# cw_err(value) -> Err(value)
+    .globl cw_err
+cw_err:
+    movl $KIND_ERR, %esi
+    jmp cw_wrap
+
+# cw_cell(value) -> a heap cell holding value
+    .globl cw_cell
+cw_cell:
+    movl $KIND_CELL, %esi
+    jmp cw_wrap
+
+# cw_range(start, end, inclusive) -> Range, from two Ints and a machine boolean
+    .globl cw_range
+cw_range:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    movslq %edi, %rbx
+    movslq %esi, %r12
+    movq %rdx, %r13
+    movl $32, %edi
+    call cw_alloc
+    movq $KIND_RANGE, (%rax)
+    movq %rbx, 8(%rax)
+    movq %r12, 16(%rax)
+    movq %r13, 24(%rax)
+    BOX_OBJECT
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_closure(code, count) -> a closure whose `count` captures the caller fills in
+    .globl cw_closure
+cw_closure:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    leaq 24(,%rsi,8), %rdi
+    call cw_alloc
+    movq $KIND_CLOSURE, (%rax)
+    movq %rbx, 8(%rax)
+    movq %r12, 16(%rax)
+    BOX_OBJECT
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_is_ok(result) -> Bool
+    .globl cw_is_ok
+cw_is_ok:
+    UNBOX %rdi
+    cmpq $KIND_OK, (%rdi)
+    sete %al
+    jmp cw_bool
+
+# cw_is_err(result) -> Bool
+    .globl cw_is_err
+cw_is_err:
+    UNBOX %rdi
+    cmpq $KIND_ERR, (%rdi)
+    sete %al
+    jmp cw_bool
+
+# cw_write_utf8(file, code point)
+cw_write_utf8:
+    subq $24, %rsp
+    movq %rdi, %rcx
+    movl %esi, %eax
+    cmpl $0x80, %eax
+    jae 1f
+    movb %al, (%rsp)
+    movl $1, %edx
+    jmp 4f
+1:
+    cmpl $0x800, %eax
+    jae 2f
+    movl %eax, %edx
+    shrl $6, %edx
+    orl $0xC0, %edx
+    movb %dl, (%rsp)
+    andl $0x3F, %eax
+    orl $0x80, %eax
+    movb %al, 1(%rsp)
+    movl $2, %edx
+    jmp 4f
+2:
+    cmpl $0x10000, %eax
+    jae 3f
+    movl %eax, %edx
+    shrl $12, %edx
+    orl $0xE0, %edx
+    movb %dl, (%rsp)
+    movl %eax, %edx
+    shrl $6, %edx
+    andl $0x3F, %edx
+    orl $0x80, %edx
+    movb %dl, 1(%rsp)
+    andl $0x3F, %eax
+    orl $0x80, %eax
+    movb %al, 2(%rsp)
+    movl $3, %edx
+    jmp 4f
+3:
+    movl %eax, %edx
+    shrl $18, %edx
+    orl $0xF0, %edx
+    movb %dl, (%rsp)
+    movl %eax, %edx
+    shrl $12, %edx
+    andl $0x3F, %edx
+    orl $0x80, %edx
+    movb %dl, 1(%rsp)
+    movl %eax, %edx
+    shrl $6, %edx
+    andl $0x3F, %edx
+    orl $0x80, %edx
+    movb %dl, 2(%rsp)
+    andl $0x3F, %eax
+    orl $0x80, %eax
+    movb %al, 3(%rsp)
+    movl $4, %edx
+4:
+    movq %rsp, %rdi
+    movl $1, %esi
+    call fwrite@PLT
+    addq $24, %rsp
+    ret
+
+# cw_write_float(file, double in %xmm0): writes the shortest decimal that reads back as the same
+# number, without an exponent. Whole numbers keep a trailing `.0`.
+cw_write_float:
+    pushq %rbp
+    movq %rsp, %rbp
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    pushq %r15
+    # 0(%rsp): scientific notation, 32(%rsp): its digits, 64(%rsp): the number
+    subq $88, %rsp
+    movq %rdi, %rbx
+    movsd %xmm0, 64(%rsp)
+    ucomisd %xmm0, %xmm0
+    jp .Lwrite_nan
+    movq 64(%rsp), %rax
+    btrq $63, %rax
+    movabsq $0x7FF0000000000000, %rcx
+    cmpq %rcx, %rax
+    je .Lwrite_inf
+    call floor@PLT
+    ucomisd 64(%rsp), %xmm0
+    jne .Lwrite_fraction
+    movq %rbx, %rdi
+    leaq .Lwhole_format(%rip), %rsi
+    movsd 64(%rsp), %xmm0
+    movl $1, %eax
+    call fprintf@PLT
+    jmp .Lwrite_float_done
+.Lwrite_nan:
+    leaq .Lnan(%rip), %rdi
+    jmp .Lwrite_float_string
+.Lwrite_inf:
+    leaq .Linf(%rip), %rdi
+    leaq .Lnegative_inf(%rip), %rax
+    cmpq $0, 64(%rsp)
+    cmovlq %rax, %rdi
+.Lwrite_float_string:
+    movq %rbx, %rsi
+    call fputs@PLT
+    jmp .Lwrite_float_done
+
+.Lwrite_fraction:
+    # Finds the fewest digits that read back as the same number
+    xorl %r12d, %r12d
+.Lwrite_precision:
+    movq %rsp, %rdi
+    movl $32, %esi
+    leaq .Lscientific_format(%rip), %rdx
+    movl %r12d, %ecx
+    movsd 64(%rsp), %xmm0
+    movl $1, %eax
+    call snprintf@PLT
+    movq %rsp, %rdi
+    xorl %esi, %esi
+    call strtod@PLT
+    ucomisd 64(%rsp), %xmm0
+    jp 1f
+    je .Lwrite_digits
+1:
+    incl %r12d
+    cmpl $17, %r12d
+    jb .Lwrite_precision
+
+.Lwrite_digits:
+    # Splits `-d.ddde-x` into its sign, its digits and its exponent
+    movq %rsp, %r13
+    cmpb $'-', (%r13)
+    jne 1f
+    movl $'-', %edi
+    movq %rbx, %rsi
+    call fputc@PLT
+    incq %r13
+1:
+    xorl %r14d, %r14d
+2:
+    movzbl (%r13), %eax
+    cmpb $'e', %al
+    je 3f
+    cmpb $'.', %al
+    je 1f
+    movb %al, 32(%rsp,%r14)
+    incq %r14
+1:
+    incq %r13
+    jmp 2b
+3:
+    leaq 1(%r13), %rdi
+    call atoi@PLT
+    movl %eax, %r15d
+    testl %r15d, %r15d
+    js .Lwrite_small
+
+    # Digits before the point, padded with zeros, then the rest after it
+    xorl %r12d, %r12d
+1:
+    cmpl %r15d, %r12d
+    jg 3f
+    movl $'0', %edi
+    cmpq %r14, %r12
+    jae 2f
+    movzbl 32(%rsp,%r12), %edi
+2:
+    movq %rbx, %rsi
+    call fputc@PLT
+    incl %r12d
+    jmp 1b
+3:
+    movl $'.', %edi
+    movq %rbx, %rsi
+    call fputc@PLT
+    movslq %r15d, %rax
+    leaq 33(%rsp,%rax), %rdi
+    movl $1, %esi
+    movq %r14, %rdx
+    subq %rax, %rdx
+    decq %rdx
+    movq %rbx, %rcx
+    call fwrite@PLT
+    jmp .Lwrite_float_done
+
+.Lwrite_small:
+    # `0.`, the zeros after the point, then the digits
+    leaq .Lzero_point(%rip), %rdi
+    movq %rbx, %rsi
+    call fputs@PLT
+    movl %r15d, %r12d
+    negl %r12d
+    decl %r12d
+1:
+    testl %r12d, %r12d
+    jle 2f
+    movl $'0', %edi
+    movq %rbx, %rsi
+    call fputc@PLT
+    decl %r12d
+    jmp 1b
+2:
+    leaq 32(%rsp), %rdi
+    movl $1, %esi
+    movq %r14, %rdx
+    movq %rbx, %rcx
+    call fwrite@PLT
+
+.Lwrite_float_done:
+    addq $88, %rsp
+    popq %r15
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbx
+    popq %rbp
+    ret
+
+# cw_write(file, value)
+cw_write:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    movq %rsi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    jb .Lwrite_float_value
+    je .Lwrite_int
+    cmpl $TAG_BOOL, %eax
+    je .Lwrite_bool
+    cmpl $TAG_CHAR, %eax
+    je .Lwrite_char
+    cmpl $TAG_UNIT, %eax
+    je .Lwrite_unit
+    UNBOX %r12
+    movq (%r12), %rax
+    cmpq $KIND_STRING, %rax
+    je .Lwrite_string
+    cmpq $KIND_RANGE, %rax
+    je .Lwrite_range
+    cmpq $KIND_OK, %rax
+    je .Lwrite_ok
+    cmpq $KIND_ERR, %rax
+    je .Lwrite_err
+    cmpq $KIND_CELL, %rax
+    je .Lwrite_cell
+    leaq .Lfile(%rip), %rdi
+    cmpq $KIND_FILE, %rax
+    je .Lwrite_text
+    leaq .Lclosure(%rip), %rdi
+    leaq .Lfunction(%rip), %rcx
+    cmpq $KIND_FUNCTION, %rax
+    cmoveq %rcx, %rdi
+    jmp .Lwrite_text
+
+.Lwrite_float_value:
+    movq %rsi, %xmm0
+    call cw_write_float
+    jmp .Lwrite_done
+.Lwrite_int:
+    leaq .Lint_format(%rip), %rsi
+    movl %r12d, %edx
+    xorl %eax, %eax
+    call fprintf@PLT
+    jmp .Lwrite_done
+.Lwrite_bool:
+    leaq .Lfalse(%rip), %rdi
+    leaq .Ltrue(%rip), %rax
+    testb $1, %r12b
+    cmovnzq %rax, %rdi
+    jmp .Lwrite_text
+.Lwrite_char:
+    movl %r12d, %esi
+    call cw_write_utf8
+    jmp .Lwrite_done
+.Lwrite_unit:
+    leaq .Lunit(%rip), %rdi
+    leaq .Lnull(%rip), %rax
+    testb $1, %r12b
+    cmovnzq %rax, %rdi
+    jmp .Lwrite_text
+.Lwrite_string:
+    leaq 16(%r12), %rdi
+    movl $1, %esi
+    movq 8(%r12), %rdx
+    movq %rbx, %rcx
+    call fwrite@PLT
+    jmp .Lwrite_done
+.Lwrite_range:
+    leaq .Lrange_format(%rip), %rsi
+    movl 8(%r12), %edx
+    leaq .Lexclusive(%rip), %rcx
+    leaq .Linclusive(%rip), %rax
+    cmpq $0, 24(%r12)
+    cmovneq %rax, %rcx
+    movl 16(%r12), %r8d
+    xorl %eax, %eax
+    call fprintf@PLT
+    jmp .Lwrite_done
+.Lwrite_ok:
+    leaq .Lok(%rip), %rdi
+    jmp .Lwrite_wrapped
+.Lwrite_err:
+    leaq .Lerr(%rip), %rdi
+.Lwrite_wrapped:
+    movq %rbx, %rsi
+    call fputs@PLT
+    movq %rbx, %rdi
+    movq 8(%r12), %rsi
+    call cw_write
+    movl $')', %edi
+    movq %rbx, %rsi
+    call fputc@PLT
+    jmp .Lwrite_done
+.Lwrite_cell:
+    movq 8(%r12), %rsi
+    call cw_write
+    jmp .Lwrite_done
+.Lwrite_text:
+    movq %rbx, %rsi
+    call fputs@PLT
+.Lwrite_done:
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_println(value) -> ()
+    .globl cw_println
+cw_println:
+    pushq %rbx
+    movq %rdi, %rsi
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rbx
+    movq %rbx, %rdi
+    call cw_write
+    movl $'\n', %edi
+    movq %rbx, %rsi
+    call fputc@PLT
+    movabsq $UNIT, %rax
+    popq %rbx
+    ret
+
+# cw_println_empty() -> ()
+    .globl cw_println_empty
+cw_println_empty:
+    subq $8, %rsp
+    movl $'\n', %edi
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rsi
+    call fputc@PLT
+    movabsq $UNIT, %rax
+    addq $8, %rsp
+    ret
+
+# cw_print(value) -> ()
+    .globl cw_print
+cw_print:
+    subq $8, %rsp
+    movq %rdi, %rsi
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call cw_write
+    movabsq $UNIT, %rax
+    addq $8, %rsp
+    ret
+
+# cw_print_padded(value, width, precision, alignment) -> (): formats the value in memory, a float
+# with `precision` digits after the point unless it is negative, then prints it with the spaces
+# that pad it to `width` characters, before it (alignment 1), after it (0) or around it (2)
+    .globl cw_print_padded
+cw_print_padded:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    pushq %r15
+    # 0(%rsp): the formatted text, 8(%rsp): its size
+    subq $16, %rsp
+    movq %rdi, %rbx
+    movslq %esi, %r12
+    movslq %edx, %r13
+    movl %ecx, %r15d
+    movq %rsp, %rdi
+    leaq 8(%rsp), %rsi
+    call open_memstream@PLT
+    testq %rax, %rax
+    jz .Lstream_out_of_memory
+    movq %rax, %r14
+    # Only finite floats have a precision; NaN and infinities print as they always do
+    testq %r13, %r13
+    js 1f
+    movq %rbx, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    jae 1f
+    movq %rbx, %rax
+    btrq $63, %rax
+    movabsq $0x7FF0000000000000, %rcx
+    cmpq %rcx, %rax
+    jae 1f
+    movq %r14, %rdi
+    leaq .Lfixed_format(%rip), %rsi
+    movl %r13d, %edx
+    movq %rbx, %xmm0
+    movl $1, %eax
+    call fprintf@PLT
+    jmp 2f
+1:
+    movq %r14, %rdi
+    movq %rbx, %rsi
+    call cw_write
+2:
+    movq %r14, %rdi
+    call fclose@PLT
+    # Counts the characters, which are the bytes that do not continue a UTF-8 sequence
+    movq (%rsp), %rdi
+    movq 8(%rsp), %rcx
+    xorl %eax, %eax
+3:
+    testq %rcx, %rcx
+    jz 4f
+    movzbl (%rdi), %edx
+    andl $0xC0, %edx
+    cmpl $0x80, %edx
+    setne %dl
+    movzbl %dl, %edx
+    addq %rdx, %rax
+    incq %rdi
+    decq %rcx
+    jmp 3b
+4:
+    # %r13: the spaces before the text, %r12: the spaces after it
+    subq %rax, %r12
+    movl $0, %eax
+    cmovlq %rax, %r12
+    xorl %r13d, %r13d
+    cmpl $1, %r15d
+    cmoveq %r12, %r13
+    movq %r12, %rax
+    shrq $1, %rax
+    cmpl $2, %r15d
+    cmoveq %rax, %r13
+    subq %r13, %r12
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rbx
+    movq %rbx, %rdi
+    leaq .Lpadding_format(%rip), %rsi
+    movl %r13d, %edx
+    leaq .Lempty(%rip), %rcx
+    xorl %eax, %eax
+    call fprintf@PLT
+    movq (%rsp), %rdi
+    movl $1, %esi
+    movq 8(%rsp), %rdx
+    movq %rbx, %rcx
+    call fwrite@PLT
+    movq %rbx, %rdi
+    leaq .Lpadding_format(%rip), %rsi
+    movl %r12d, %edx
+    leaq .Lempty(%rip), %rcx
+    xorl %eax, %eax
+    call fprintf@PLT
+    movq (%rsp), %rdi
+    call free@PLT
+    movabsq $UNIT, %rax
+    addq $16, %rsp
+    popq %r15
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+.Lstream_out_of_memory:
+    leaq .Lout_of_memory(%rip), %rdi
+    jmp cw_fatal
+
+# cw_input() -> the next line of stdin as a String without its line ending, or null at the end of
+# stdin. Whatever was printed before is flushed first, so that prompts show.
+    .globl cw_input
+cw_input:
+    subq $8, %rsp
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call fflush@PLT
+    movq stdin@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    addq $8, %rsp
+    jmp cw_line
+
+# cw_line(stream) -> the next line of a C stream as a String without its line ending, or null at
+# the end of the stream or when reading fails, which ferror then tells apart
+cw_line:
+    pushq %rbx
+    pushq %r12
+    # 0(%rsp): the line that getline allocates, 8(%rsp): its capacity
+    subq $24, %rsp
+    movq %rdi, %rdx
+    movq $0, (%rsp)
+    movq $0, 8(%rsp)
+    movq %rsp, %rdi
+    leaq 8(%rsp), %rsi
+    call getline@PLT
+    testq %rax, %rax
+    js 2f
+    movq %rax, %rbx
+    movq (%rsp), %r12
+    # Drops a trailing "\n", then the "\r" before it
+    testq %rbx, %rbx
+    jz 1f
+    cmpb $'\n', -1(%r12,%rbx)
+    jne 1f
+    decq %rbx
+    jz 1f
+    cmpb $'\r', -1(%r12,%rbx)
+    jne 1f
+    decq %rbx
+1:
+    movq %r12, %rdi
+    movq %rbx, %rsi
+    call cw_string
+    movq %rax, %rbx
+    movq %r12, %rdi
+    call free@PLT
+    movq %rbx, %rax
+    jmp 3f
+2:
+    movq (%rsp), %rdi
+    call free@PLT
+    movabsq $UNIT+1, %rax
+3:
+    addq $24, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_string(bytes, length) -> a new String holding a copy of the bytes
+cw_string:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    leaq 16(%rsi), %rdi
+    call cw_alloc
+    movq $KIND_STRING, (%rax)
+    movq %r12, 8(%rax)
+    movq %rbx, %rsi
+    movq %rax, %rbx
+    leaq 16(%rax), %rdi
+    movq %r12, %rdx
+    call memcpy@PLT
+    movq %rbx, %rax
+    BOX_OBJECT
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_to_string(value) -> the text that printing `value` writes, as a String
+    .globl cw_to_string
+cw_to_string:
+    # A String is its own text
+    movq %rdi, %rax
+    shrq $48, %rax
+    cmpl $TAG_OBJECT, %eax
+    jne 1f
+    movq %rdi, %rax
+    UNBOX %rax
+    cmpq $KIND_STRING, (%rax)
+    jne 1f
+    movq %rdi, %rax
+    ret
+1:
+    pushq %rbx
+    pushq %r12
+    # 0(%rsp): the text, 8(%rsp): its size
+    subq $24, %rsp
+    movq %rdi, %rbx
+    movq %rsp, %rdi
+    leaq 8(%rsp), %rsi
+    call open_memstream@PLT
+    testq %rax, %rax
+    jz .Lstream_out_of_memory
+    movq %rax, %r12
+    movq %rax, %rdi
+    movq %rbx, %rsi
+    call cw_write
+    movq %r12, %rdi
+    call fclose@PLT
+    movq (%rsp), %rdi
+    movq 8(%rsp), %rsi
+    call cw_string
+    movq %rax, %rbx
+    movq (%rsp), %rdi
+    call free@PLT
+    movq %rbx, %rax
+    addq $24, %rsp
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_concat(a, b) -> the String `a` followed by the String `b`
+    .globl cw_concat
+cw_concat:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    movq %rdi, %rbx
+    UNBOX %rbx
+    movq %rsi, %r12
+    UNBOX %r12
+    movq 8(%rbx), %rdi
+    addq 8(%r12), %rdi
+    addq $16, %rdi
+    call cw_alloc
+    movq %rax, %r13
+    movq $KIND_STRING, (%rax)
+    movq 8(%rbx), %rcx
+    addq 8(%r12), %rcx
+    movq %rcx, 8(%rax)
+    leaq 16(%r13), %rdi
+    leaq 16(%rbx), %rsi
+    movq 8(%rbx), %rdx
+    call memcpy@PLT
+    leaq 16(%r13), %rdi
+    addq 8(%rbx), %rdi
+    leaq 16(%r12), %rsi
+    movq 8(%r12), %rdx
+    call memcpy@PLT
+    movq %r13, %rax
+    BOX_OBJECT
+    popq %r13
+    popq %r12
+    popq %rbx
+    ret
+
+# cw_to_int(site, value) -> int(value): the Int that a Float truncates to, panicking when there is
+# none, the scalar value of a Char, or the Result of parsing a String
+    .globl cw_to_int
+cw_to_int:
+    movq %rsi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    jb .Lfloat_to_int
+    cmpl $TAG_CHAR, %eax
+    je .Lchar_to_int
+    cmpl $TAG_OBJECT, %eax
+    je .Lstring_to_int
+    movq %rsi, %rax
+    ret
+.Lfloat_to_int:
+    # NaN is unordered, which fails both comparisons
+    movq %rsi, %xmm0
+    ucomisd .Lint_below(%rip), %xmm0
+    jbe 1f
+    movsd .Lint_above(%rip), %xmm1
+    ucomisd %xmm0, %xmm1
+    jbe 1f
+    cvttsd2si %xmm0, %eax
+    movabsq $INT, %rcx
+    orq %rcx, %rax
+    ret
+1:
+    leaq .Lint_name(%rip), %rdx
+    jmp cw_conversion_failed
+.Lchar_to_int:
+    movl %esi, %eax
+    movabsq $INT, %rcx
+    orq %rcx, %rax
+    ret
+.Lstring_to_int:
+    pushq %rsi
+    movq %rsi, %rdi
+    UNBOX %rdi
+    xorl %esi, %esi
+    call cw_is_number
+    popq %rsi
+    testl %eax, %eax
+    jz .Lint_parse_error
+    movq %rsi, %rdi
+    UNBOX %rdi
+    leaq 16(%rdi), %rcx
+    movq 8(%rdi), %rdx
+    addq %rcx, %rdx
+    # %r8b: the first byte, which is below '0' when it is a sign
+    movzbl (%rcx), %r8d
+    cmpb $'0', %r8b
+    jae 1f
+    incq %rcx
+1:
+    # Accumulates the magnitude in %rax, which is at most 2^31 for an Int
+    xorl %eax, %eax
+    movl $0x80000000, %r10d
+2:
+    movzbl (%rcx), %r9d
+    subl $'0', %r9d
+    imulq $10, %rax
+    addq %r9, %rax
+    cmpq %r10, %rax
+    ja .Lint_parse_error
+    incq %rcx
+    cmpq %rdx, %rcx
+    jne 2b
+    cmpb $'-', %r8b
+    jne 3f
+    negq %rax
+    jmp 4f
+3:
+    cmpq %r10, %rax
+    je .Lint_parse_error
+4:
+    movl %eax, %eax
+    movabsq $INT, %rcx
+    orq %rcx, %rax
+    movq %rax, %rdi
+    jmp cw_ok
+.Lint_parse_error:
+    movq %rsi, %rdi
+    leaq .Lint_name(%rip), %rsi
+    jmp cw_parse_error
+
+# cw_to_float(value) -> float(value): an Int exactly, or the Result of parsing a String
+    .globl cw_to_float
+cw_to_float:
+    movq %rdi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    je 1f
+    cmpl $TAG_OBJECT, %eax
+    je 2f
+    movq %rdi, %rax
+    ret
+1:
+    cvtsi2sdl %edi, %xmm0
+    movq %xmm0, %rax
+    ret
+2:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rdi, %r12
+    movq %rdi, %rbx
+    UNBOX %rbx
+    movq %rbx, %rdi
+    movl $1, %esi
+    call cw_is_number
+    testl %eax, %eax
+    jz 3f
+    # strtod reads up to a NUL, so it parses a copy of the text that ends with one
+    movq 8(%rbx), %rdi
+    incq %rdi
+    call cw_buffer
+    movq %rax, %r12
+    movq %rax, %rdi
+    leaq 16(%rbx), %rsi
+    movq 8(%rbx), %rdx
+    call memcpy@PLT
+    movq 8(%rbx), %rax
+    movb $0, (%r12,%rax)
+    movq %r12, %rdi
+    xorl %esi, %esi
+    call strtod@PLT
+    movq %xmm0, %rbx
+    movq %r12, %rdi
+    call free@PLT
+    movq %rbx, %rdi
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    jmp cw_ok
+3:
+    movq %r12, %rdi
+    leaq .Lfloat_name(%rip), %rsi
+    addq $8, %rsp
+    popq %r12
+    popq %rbx
+    jmp cw_parse_error
+
+# cw_to_char(site, value) -> char(value): the Char whose scalar value an Int is, panicking when
+# there is none
+    .globl cw_to_char
+cw_to_char:
+    movq %rsi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    je 1f
+    movq %rsi, %rax
+    ret
+1:
+    # Negative values are above 0x10FFFF unsigned, and surrogates are 0xD800 to 0xDFFF
+    movl %esi, %eax
+    cmpl $0x10FFFF, %eax
+    ja 2f
+    movl %eax, %ecx
+    andl $0xFFFFF800, %ecx
+    cmpl $0xD800, %ecx
+    je 2f
+    movabsq $CHAR, %rcx
+    orq %rcx, %rax
+    ret
+2:
+    leaq .Lchar_name(%rip), %rdx
+    jmp cw_conversion_failed
+
+# cw_is_number(string, fraction) -> 1 in %eax if the String object is an optional sign followed by
+# decimal digits and, when `fraction` is not 0, an optional fraction and exponent, else 0
+cw_is_number:
+    leaq 16(%rdi), %rcx
+    movq 8(%rdi), %rdx
+    addq %rcx, %rdx
+    call .Lskip_sign
+    call .Lskip_digits
+    jc 2f
+    testl %esi, %esi
+    jz 1f
+    cmpq %rdx, %rcx
+    je 1f
+    cmpb $'.', (%rcx)
+    jne 3f
+    incq %rcx
+    call .Lskip_digits
+    jc 2f
+    cmpq %rdx, %rcx
+    je 1f
+3:
+    # 'E' and 'e' are the only bytes that are 'e' with bit 5 set
+    movzbl (%rcx), %eax
+    orb $0x20, %al
+    cmpb $'e', %al
+    jne 1f
+    incq %rcx
+    call .Lskip_sign
+    call .Lskip_digits
+    jc 2f
+1:
+    cmpq %rdx, %rcx
+    sete %al
+    movzbl %al, %eax
+    ret
+2:
+    xorl %eax, %eax
+    ret
+
+# Advances %rcx past a sign, if one is before %rdx
+.Lskip_sign:
+    cmpq %rdx, %rcx
+    je 1f
+    cmpb $'+', (%rcx)
+    je 2f
+    cmpb $'-', (%rcx)
+    jne 1f
+2:
+    incq %rcx
+1:
+    ret
+
+# Advances %rcx past the decimal digits before %rdx, setting the carry flag when there is none
+.Lskip_digits:
+    movq %rcx, %r8
+1:
+    cmpq %rdx, %rcx
+    je 2f
+    movzbl (%rcx), %eax
+    subl $'0', %eax
+    cmpl $9, %eax
+    ja 2f
+    incq %rcx
+    jmp 1b
+2:
+    cmpq %rcx, %r8
+    je 3f
+    clc
+    ret
+3:
+    stc
+    ret
+
+# cw_parse_error(string, type) -> the Err of parsing a String that is not a number of the type
+# named by the C string `type`
+cw_parse_error:
+    leaq .Lcannot_parse(%rip), %rdx
+    leaq .Lparse_as_format(%rip), %rcx
+    jmp cw_quoted_error
+
+# cw_quoted_error(string, argument, prefix, format) -> an Err whose message is the C string
+# `prefix`, the String `string`, then the C string `argument` as printed by the C format `format`
+cw_quoted_error:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    pushq %r14
+    # 0(%rsp): the message, 8(%rsp): its size, 16(%rsp): the format
+    subq $24, %rsp
+    movq %rdi, %rbx
+    UNBOX %rbx
+    movq %rsi, %r12
+    movq %rdx, %r14
+    movq %rcx, 16(%rsp)
+    movq %rsp, %rdi
+    leaq 8(%rsp), %rsi
+    call open_memstream@PLT
+    testq %rax, %rax
+    jz .Lstream_out_of_memory
+    movq %rax, %r13
+    movq %r14, %rdi
+    movq %r13, %rsi
+    call fputs@PLT
+    leaq 16(%rbx), %rdi
+    movl $1, %esi
+    movq 8(%rbx), %rdx
+    movq %r13, %rcx
+    call fwrite@PLT
+    movq %r13, %rdi
+    movq 16(%rsp), %rsi
+    movq %r12, %rdx
+    xorl %eax, %eax
+    call fprintf@PLT
+    movq %r13, %rdi
+    call fclose@PLT
+    movq (%rsp), %rdi
+    movq 8(%rsp), %rsi
+    call cw_string
+    movq %rax, %rbx
+    movq (%rsp), %rdi
+    call free@PLT
+    movq %rbx, %rdi
+    addq $24, %rsp
+    popq %r14
+    popq %r13
+    popq %r12
+    popq %rbx
+    jmp cw_err
+
+# cw_conversion_failed(site, value, type): panics on converting `value` to the type named by the C
+# string `type`
+cw_conversion_failed:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    movq %rsi, %rbx
+    movq %rdx, %r12
+    call cw_panic_begin
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %r13
+    leaq .Lcannot_convert(%rip), %rdi
+    movq %r13, %rsi
+    call fputs@PLT
+    movq %r13, %rdi
+    movq %rbx, %rsi
+    call cw_write
+    movq %r13, %rdi
+    leaq .Lconvert_to_format(%rip), %rsi
+    movq %r12, %rdx
+    xorl %eax, %eax
+    call fprintf@PLT
+    jmp cw_panic_end
+
+# cw_min(a, b) -> the second argument when it is less than the first, or the first is NaN, and
+# otherwise the first. Both are Ints or both Floats.
+    .globl cw_min
+cw_min:
+    movq %rdi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    je 1f
+    movq %rdi, %xmm0
+    movq %rsi, %xmm1
+    # Only NaN is unordered with itself, and `ja` fails when either side is NaN
+    ucomisd %xmm0, %xmm0
+    jp 2f
+    ucomisd %xmm1, %xmm0
+    ja 2f
+    movq %rdi, %rax
+    ret
+1:
+    cmpl %edi, %esi
+    jl 2f
+    movq %rdi, %rax
+    ret
+2:
+    movq %rsi, %rax
+    ret
+
+# cw_max(a, b) -> the second argument when it is greater than the first, or the first is NaN, and
+# otherwise the first. Both are Ints or both Floats.
+    .globl cw_max
+cw_max:
+    movq %rdi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    je 1f
+    movq %rdi, %xmm0
+    movq %rsi, %xmm1
+    ucomisd %xmm0, %xmm0
+    jp 2f
+    ucomisd %xmm0, %xmm1
+    ja 2f
+    movq %rdi, %rax
+    ret
+1:
+    cmpl %edi, %esi
+    jg 2f
+    movq %rdi, %rax
+    ret
+2:
+    movq %rsi, %rax
+    ret
+
+# cw_abs(site, value) -> abs(value) of an Int, panicking on overflow, or of a Float
+    .globl cw_abs
+cw_abs:
+    movq %rsi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    je 1f
+    movq %rsi, %rax
+    btrq $63, %rax
+    ret
+1:
+    movl %esi, %eax
+    testl %eax, %eax
+    jns 2f
+    negl %eax
+    jo 3f
+2:
+    movabsq $INT, %rcx
+    orq %rcx, %rax
+    ret
+3:
+    leaq cw_message_negate(%rip), %rsi
+    jmp cw_panic
+
+# cw_pow(site, base, exponent) -> pow(base, exponent) of Ints, by squaring and panicking on
+# overflow, or of Floats
+    .globl cw_pow
+cw_pow:
+    movq %rsi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    je 1f
+    subq $8, %rsp
+    movq %rsi, %xmm0
+    movq %rdx, %xmm1
+    call pow@PLT
+    movq %xmm0, %rax
+    addq $8, %rsp
+    ret
+1:
+    # The result in %rax and the squares of the base in %rsi, which a square that overflows
+    # would be a factor of, are both checked to fit in 32 bits
+    movl %edx, %ecx
+    testl %ecx, %ecx
+    js 5f
+    movslq %esi, %rsi
+    movl $1, %eax
+2:
+    testl %ecx, %ecx
+    jz 4f
+    testl $1, %ecx
+    jz 3f
+    imulq %rsi, %rax
+    movslq %eax, %r8
+    cmpq %rax, %r8
+    jne 6f
+3:
+    shrl $1, %ecx
+    jz 4f
+    imulq %rsi, %rsi
+    movslq %esi, %r8
+    cmpq %rsi, %r8
+    jne 6f
+    jmp 2b
+4:
+    movl %eax, %eax
+    movabsq $INT, %rcx
+    orq %rcx, %rax
+    ret
+5:
+    leaq .Lnegative_exponent(%rip), %rsi
+    jmp cw_panic
+6:
+    leaq cw_message_multiply(%rip), %rsi
+    jmp cw_panic
+
+# cw_sqrt(value) and the other math built-ins of a Float -> the result of `function` of the C
+# library
+.macro FLOAT_FUNCTION name, function
+    .globl \name
+\name:
+    subq $8, %rsp
+    movq %rdi, %xmm0
+    call \function\()@PLT
+    movq %xmm0, %rax
+    addq $8, %rsp
+    ret
+.endm
+
+    FLOAT_FUNCTION cw_sqrt, sqrt
+    FLOAT_FUNCTION cw_ceil, ceil
+    FLOAT_FUNCTION cw_floor, floor
+    FLOAT_FUNCTION cw_sin, sin
+    FLOAT_FUNCTION cw_cos, cos
+    FLOAT_FUNCTION cw_log, log10
+    FLOAT_FUNCTION cw_ln, log
+    FLOAT_FUNCTION cw_pow_e, exp
+
+# cw_file_reason(errno) -> the reason, as a C string, that an operation on a file failed with
+# `errno`, which every backend words the same
+cw_file_reason:
+    leaq .Lno_such_file(%rip), %rax
+    cmpl $ENOENT, %edi
+    je 1f
+    leaq .Lpermission_denied(%rip), %rax
+    cmpl $EACCES, %edi
+    je 1f
+    cmpl $EPERM, %edi
+    je 1f
+    leaq .Lis_a_directory(%rip), %rax
+    cmpl $EISDIR, %edi
+    je 1f
+    leaq .Lio_error(%rip), %rax
+1:
+    ret
+
+# cw_errno() -> the value of errno in %eax
+cw_errno:
+    subq $8, %rsp
+    call __errno_location@PLT
+    movl (%rax), %eax
+    addq $8, %rsp
+    ret
+
+# cw_clear_errno(): sets errno to 0
+cw_clear_errno:
+    subq $8, %rsp
+    call __errno_location@PLT
+    movl $0, (%rax)
+    addq $8, %rsp
+    ret
+
+# cw_file_error(reason) -> an Err holding the C string `reason` as a String
+cw_file_error:
+    pushq %rbx
+    movq %rdi, %rbx
+    call strlen@PLT
+    movq %rbx, %rdi
+    movq %rax, %rsi
+    call cw_string
+    movq %rax, %rdi
+    popq %rbx
+    jmp cw_err
+
+# cw_errno_error() -> an Err holding the reason that errno gives
+cw_errno_error:
+    subq $8, %rsp
+    call cw_errno
+    movl %eax, %edi
+    call cw_file_reason
+    movq %rax, %rdi
+    addq $8, %rsp
+    jmp cw_file_error
+
+# cw_open(path, mode) -> Ok(file) for reading (mode "r"), writing over the file ("w") or appending
+# to it ("a"), or an Err with the reason it cannot be opened. Files written to are not buffered,
+# so that they hold what was written even if the program panics.
+    .globl cw_open
+cw_open:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    # 0(%rsp): the mode as a C string, 8(%rsp): errno after opening
+    subq $16, %rsp
+    movq %rdi, %rbx
+    movq %rsi, %r12
+    UNBOX %r12
+    cmpq $1, 8(%r12)
+    jne .Lopen_invalid_mode
+    movzbl 16(%r12), %eax
+    cmpb $'r', %al
+    je 1f
+    cmpb $'w', %al
+    je 1f
+    cmpb $'a', %al
+    jne .Lopen_invalid_mode
+1:
+    movb %al, (%rsp)
+    movb $0, 1(%rsp)
+    # The path as a C string, which fails to open when the path holds a NUL byte
+    movq %rbx, %r12
+    UNBOX %r12
+    movq 8(%r12), %rdi
+    incq %rdi
+    call cw_buffer
+    movq %rax, %r13
+    movq %rax, %rdi
+    leaq 16(%r12), %rsi
+    movq 8(%r12), %rdx
+    call memcpy@PLT
+    movq 8(%r12), %rax
+    movb $0, (%r13,%rax)
+    call cw_clear_errno
+    movq %r13, %rdi
+    call strlen@PLT
+    cmpq 8(%r12), %rax
+    movl $0, %r12d
+    jne 2f
+    movq %r13, %rdi
+    movq %rsp, %rsi
+    call fopen@PLT
+    movq %rax, %r12
+2:
+    call cw_errno
+    movl %eax, 8(%rsp)
+    movq %r13, %rdi
+    call free@PLT
+    testq %r12, %r12
+    jz .Lopen_failed
+    xorl %r13d, %r13d
+    cmpb $'r', (%rsp)
+    je 3f
+    movl $1, %r13d
+    # setvbuf(stream, NULL, _IONBF, 0)
+    movq %r12, %rdi
+    xorl %esi, %esi
+    movl $2, %edx
+    xorl %ecx, %ecx
+    call setvbuf@PLT
+3:
+    movl $24, %edi
+    call cw_alloc
+    movq $KIND_FILE, (%rax)
+    movq %r12, 8(%rax)
+    movq %r13, 16(%rax)
+    BOX_OBJECT
+    movq %rax, %rdi
+    addq $16, %rsp
+    popq %r13
+    popq %r12
+    popq %rbx
+    jmp cw_ok
+.Lopen_failed:
+    movl 8(%rsp), %edi
+    call cw_file_reason
+    movq %rax, %rsi
+    jmp 4f
+.Lopen_invalid_mode:
+    leaq .Linvalid_mode(%rip), %rsi
+4:
+    movq %rbx, %rdi
+    leaq .Lcannot_open(%rip), %rdx
+    leaq .Lopen_reason_format(%rip), %rcx
+    addq $16, %rsp
+    popq %r13
+    popq %r12
+    popq %rbx
+    jmp cw_quoted_error
+
+# cw_close(file) -> ()
+    .globl cw_close
+cw_close:
+    pushq %rbx
+    movq %rdi, %rbx
+    UNBOX %rbx
+    movq 8(%rbx), %rdi
+    testq %rdi, %rdi
+    jz 1f
+    call fclose@PLT
+    movq $0, 8(%rbx)
+1:
+    movabsq $UNIT, %rax
+    popq %rbx
+    ret
+
+# cw_readable(file) -> the stream of a file open for reading in %rax, or 0 with the reason it
+# cannot be read in %rdx
+cw_readable:
+    UNBOX %rdi
+    movq 8(%rdi), %rax
+    leaq .Lfile_closed(%rip), %rdx
+    testq %rax, %rax
+    jz 1f
+    cmpq $0, 16(%rdi)
+    je 1f
+    xorl %eax, %eax
+    leaq .Lnot_readable(%rip), %rdx
+1:
+    ret
+
+# cw_read(file) -> Ok(the rest of the file as a String), or an Err with the reason reading failed
+    .globl cw_read
+cw_read:
+    pushq %rbx
+    pushq %r12
+    pushq %r13
+    # 0(%rsp): the text, 8(%rsp): its size, 16(%rsp): a buffer of 4096 bytes
+    subq $4112, %rsp
+    call cw_readable
+    testq %rax, %rax
+    jz .Lread_unreadable
+    movq %rax, %rbx
+    movq %rsp, %rdi
+    leaq 8(%rsp), %rsi
+    call open_memstream@PLT
+    testq %rax, %rax
+    jz .Lstream_out_of_memory
+    movq %rax, %r12
+    movq %rbx, %rdi
+    call clearerr@PLT
+    call cw_clear_errno
+1:
+    leaq 16(%rsp), %rdi
+    movl $1, %esi
+    movl $4096, %edx
+    movq %rbx, %rcx
+    call fread@PLT
+    testq %rax, %rax
+    jz 2f
+    leaq 16(%rsp), %rdi
+    movl $1, %esi
+    movq %rax, %rdx
+    movq %r12, %rcx
+    call fwrite@PLT
+    jmp 1b
+2:
+    call cw_errno
+    movl %eax, %r13d
+    movq %r12, %rdi
+    call fclose@PLT
+    movq %rbx, %rdi
+    call ferror@PLT
+    testl %eax, %eax
+    jnz 3f
+    movq (%rsp), %rdi
+    movq 8(%rsp), %rsi
+    call cw_string
+    movq %rax, %rbx
+    movq (%rsp), %rdi
+    call free@PLT
+    movq %rbx, %rdi
+    addq $4112, %rsp
+    popq %r13
+    popq %r12
+    popq %rbx
+    jmp cw_ok
+3:
+    movq (%rsp), %rdi
+    call free@PLT
+    movl %r13d, %edi
+    call cw_file_reason
+    movq %rax, %rdx
+.Lread_unreadable:
+    movq %rdx, %rdi
+    addq $4112, %rsp
+    popq %r13
+    popq %r12
+    popq %rbx
+    jmp cw_file_error
+
+# cw_read_line(file) -> Ok(the next line of the file without its line ending, or null at its end),
+# or an Err with the reason reading failed
+    .globl cw_read_line
+cw_read_line:
+    pushq %rbx
+    call cw_readable
+    testq %rax, %rax
+    jz 3f
+    movq %rax, %rbx
+    # A failure to read before is forgotten, so that ferror only tells of this one
+    movq %rax, %rdi
+    call clearerr@PLT
+    call cw_clear_errno
+    movq %rbx, %rdi
+    call cw_line
+    movabsq $UNIT+1, %rcx
+    cmpq %rcx, %rax
+    jne 2f
+    movq %rbx, %rdi
+    call ferror@PLT
+    testl %eax, %eax
+    jz 1f
+    popq %rbx
+    jmp cw_errno_error
+1:
+    movabsq $UNIT+1, %rax
+2:
+    movq %rax, %rdi
+    popq %rbx
+    jmp cw_ok
+3:
+    movq %rdx, %rdi
+    popq %rbx
+    jmp cw_file_error
+
+# cw_write_file(file, text) -> Ok(()), or an Err with the reason writing failed
+    .globl cw_write_file
+cw_write_file:
+    pushq %rbx
+    movq %rdi, %rax
+    UNBOX %rax
+    movq 8(%rax), %rcx
+    leaq .Lfile_closed(%rip), %rdi
+    testq %rcx, %rcx
+    jz 2f
+    leaq .Lnot_writable(%rip), %rdi
+    cmpq $0, 16(%rax)
+    je 2f
+    movq %rsi, %rbx
+    UNBOX %rbx
+    leaq 16(%rbx), %rdi
+    movl $1, %esi
+    movq 8(%rbx), %rdx
+    call fwrite@PLT
+    cmpq 8(%rbx), %rax
+    jne 1f
+    movabsq $UNIT, %rdi
+    popq %rbx
+    jmp cw_ok
+1:
+    popq %rbx
+    jmp cw_errno_error
+2:
+    popq %rbx
+    jmp cw_file_error
+
+# cw_next_line(file) -> the next line of the file for a `for` loop over it, or null once there is
+# none to read
+    .globl cw_next_line
+cw_next_line:
+    call cw_readable
+    testq %rax, %rax
+    jz 1f
+    movq %rax, %rdi
+    jmp cw_line
+1:
+    movabsq $UNIT+1, %rax
+    ret
+
+# Leaves in %ecx the number of bytes of the UTF-8 sequence starting at byte %rsi of the unboxed
+# string in %rdi, or 0 once the offset is at its end. A byte that does not start a sequence counts
+# as one, and a sequence cut short by the end of the string as the bytes left. Leaves the first
+# byte in %eax.
+.Lutf8_width:
+    xorl %ecx, %ecx
+    movq 8(%rdi), %rdx
+    testq %rsi, %rsi
+    js 2f
+    cmpq %rdx, %rsi
+    jae 2f
+    subq %rsi, %rdx
+    movzbl 16(%rdi,%rsi), %eax
+    movl $1, %ecx
+    cmpl $0xC0, %eax
+    jb 1f
+    cmpl $0xF8, %eax
+    jae 1f
+    incl %ecx
+    cmpl $0xE0, %eax
+    jb 1f
+    incl %ecx
+    cmpl $0xF0, %eax
+    jb 1f
+    incl %ecx
+1:
+    cmpq %rdx, %rcx
+    cmova %edx, %ecx
+2:
+    ret
+
+# cw_char_width(string, offset) -> the number of bytes of the character starting at byte `offset`
+# of the string, or 0 once the offset is at its end
+    .globl cw_char_width
+cw_char_width:
+    UNBOX %rdi
+    movslq %esi, %rsi
+    call .Lutf8_width
+    movl %ecx, %eax
+    movabsq $INT, %rcx
+    orq %rcx, %rax
+    ret
+
+# cw_char_at(string, offset) -> the character starting at byte `offset` of the string for a `for`
+# loop over it
+    .globl cw_char_at
+cw_char_at:
+    UNBOX %rdi
+    movslq %esi, %rsi
+    call .Lutf8_width
+    leaq 16(%rdi,%rsi), %rdi
+    cmpl $1, %ecx
+    jbe 2f
+    # Keeps the bits of the first byte that are not its length
+    movl $0x7F, %r8d
+    shrl %cl, %r8d
+    andl %r8d, %eax
+    movl $1, %r9d
+1:
+    movzbl (%rdi,%r9), %r8d
+    andl $0x3F, %r8d
+    shll $6, %eax
+    orl %r8d, %eax
+    incq %r9
+    cmpq %rcx, %r9
+    jb 1b
+2:
+    movabsq $CHAR, %rcx
+    orq %rcx, %rax
+    ret
+
+# Starts reporting a panic at `site` (a path, then a 32-bit line and column), leaving the message
+# to the caller
+cw_panic_begin:
+    pushq %rbx
+    movq %rdi, %rbx
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call fflush@PLT
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    leaq .Lpanic_format(%rip), %rsi
+    movq (%rbx), %rdx
+    movl 8(%rbx), %ecx
+    movl 12(%rbx), %r8d
+    xorl %eax, %eax
+    call fprintf@PLT
+    popq %rbx
+    ret
+
+# Ends reporting a panic, exiting with code 101
+cw_panic_end:
+    andq $-16, %rsp
+    movl $'\n', %edi
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rsi
+    call fputc@PLT
+    movl $101, %edi
+    call exit@PLT
+
+# cw_panic(site, message): panics with a C string
+    .globl cw_panic
+cw_panic:
+    pushq %rbx
+    movq %rsi, %rbx
+    call cw_panic_begin
+    movq %rbx, %rdi
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rsi
+    call fputs@PLT
+    jmp cw_panic_end
+
+# cw_panic_value(site, message): panics with a value
+    .globl cw_panic_value
+cw_panic_value:
+    pushq %rbx
+    movq %rsi, %rbx
+    call cw_panic_begin
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    movq %rbx, %rsi
+    call cw_write
+    jmp cw_panic_end
+
+# cw_unwrap(site, result) -> the value of an Ok, panicking on an Err
+    .globl cw_unwrap
+cw_unwrap:
+    movq %rsi, %rax
+    UNBOX %rax
+    cmpq $KIND_OK, (%rax)
+    jne 1f
+    movq 8(%rax), %rax
+    ret
+1:
+    leaq .Lunwrap_err(%rip), %rdx
+    jmp cw_unwrap_failed
+
+# cw_unwrap_err(site, result) -> the error of an Err, panicking on an Ok
+    .globl cw_unwrap_err
+cw_unwrap_err:
+    movq %rsi, %rax
+    UNBOX %rax
+    cmpq $KIND_ERR, (%rax)
+    jne 1f
+    movq 8(%rax), %rax
+    ret
+1:
+    leaq .Lunwrap_err_ok(%rip), %rdx
+    jmp cw_unwrap_failed
+
+# cw_unwrap_failed(site, result, message): panics with `message` followed by the result's value
+cw_unwrap_failed:
+    pushq %rbx
+    pushq %r12
+    subq $8, %rsp
+    movq %rsi, %rbx
+    UNBOX %rbx
+    movq %rdx, %r12
+    call cw_panic_begin
+    movq %r12, %rdi
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rsi
+    call fputs@PLT
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    movq 8(%rbx), %rsi
+    call cw_write
+    jmp cw_panic_end
+
+# cw_same(a, b) -> 1 in %eax if the values are equal, else 0
+cw_same:
+    movq %rdi, %rax
+    shrq $48, %rax
+    movq %rsi, %rcx
+    shrq $48, %rcx
+    cmpl $TAG_INT, %eax
+    jae 1f
+    # A float only equals a float
+    cmpl $TAG_INT, %ecx
+    jae .Lsame_false
+    movq %rdi, %xmm0
+    movq %rsi, %xmm1
+    ucomisd %xmm1, %xmm0
+    jp .Lsame_false
+    jne .Lsame_false
+    movl $1, %eax
+    ret
+1:
+    cmpq %rdi, %rsi
+    je .Lsame_true
+    cmpl $TAG_OBJECT, %eax
+    jne .Lsame_false
+    cmpl $TAG_OBJECT, %ecx
+    jne .Lsame_false
+    UNBOX %rdi
+    UNBOX %rsi
+    movq (%rdi), %rax
+    cmpq (%rsi), %rax
+    jne .Lsame_false
+    cmpq $KIND_STRING, %rax
+    je .Lsame_string
+    cmpq $KIND_RANGE, %rax
+    je .Lsame_range
+    cmpq $KIND_OK, %rax
+    je .Lsame_wrapped
+    cmpq $KIND_ERR, %rax
+    je .Lsame_wrapped
+    # The type checker rejects comparisons between functions and between files
+    jmp .Lsame_false
+.Lsame_wrapped:
+    movq 8(%rdi), %rdi
+    movq 8(%rsi), %rsi
+    jmp cw_same
+.Lsame_range:
+    movq 8(%rdi), %rax
+    cmpq 8(%rsi), %rax
+    jne .Lsame_false
+    movq 16(%rdi), %rax
+    cmpq 16(%rsi), %rax
+    jne .Lsame_false
+    movq 24(%rdi), %rax
+    cmpq 24(%rsi), %rax
+    jne .Lsame_false
+    jmp .Lsame_true
+.Lsame_string:
+    movq 8(%rdi), %rdx
+    cmpq 8(%rsi), %rdx
+    jne .Lsame_false
+    subq $8, %rsp
+    addq $16, %rdi
+    addq $16, %rsi
+    call memcmp@PLT
+    addq $8, %rsp
+    testl %eax, %eax
+    sete %al
+    movzbl %al, %eax
+    ret
+.Lsame_true:
+    movl $1, %eax
+    ret
+.Lsame_false:
+    xorl %eax, %eax
+    ret
+
+# cw_equal(a, b) -> Bool
+    .globl cw_equal
+cw_equal:
+    subq $8, %rsp
+    call cw_same
+    addq $8, %rsp
+    jmp cw_bool
+
+# cw_not_equal(a, b) -> Bool
+    .globl cw_not_equal
+cw_not_equal:
+    subq $8, %rsp
+    call cw_same
+    addq $8, %rsp
+    xorl $1, %eax
+    jmp cw_bool
+
+# cw_compare(a, b) -> -1, 0 or 1 in %eax as a is less than, equal to or greater than b, or 2 when
+# either is NaN. Ints and Chars compare as signed 32-bit numbers.
+cw_compare:
+    movq %rdi, %rax
+    shrq $48, %rax
+    cmpl $TAG_INT, %eax
+    jb 2f
+    cmpl %esi, %edi
+    jl .Lcompare_less
+    jg .Lcompare_greater
+    xorl %eax, %eax
+    ret
+2:
+    movq %rdi, %xmm0
+    movq %rsi, %xmm1
+    ucomisd %xmm1, %xmm0
+    jp 3f
+    jb .Lcompare_less
+    ja .Lcompare_greater
+    xorl %eax, %eax
+    ret
+3:
+    movl $2, %eax
+    ret
+.Lcompare_less:
+    movl $-1, %eax
+    ret
+.Lcompare_greater:
+    movl $1, %eax
+    ret
+
+# cw_less(a, b) -> Bool
+    .globl cw_less
+cw_less:
+    subq $8, %rsp
+    call cw_compare
+    addq $8, %rsp
+    cmpl $-1, %eax
+    sete %al
+    jmp cw_bool
+
+# cw_less_equal(a, b) -> Bool
+    .globl cw_less_equal
+cw_less_equal:
+    subq $8, %rsp
+    call cw_compare
+    addq $8, %rsp
+    incl %eax
+    cmpl $1, %eax
+    setbe %al
+    jmp cw_bool
+
+# cw_greater(a, b) -> Bool
+    .globl cw_greater
+cw_greater:
+    subq $8, %rsp
+    call cw_compare
+    addq $8, %rsp
+    cmpl $1, %eax
+    sete %al
+    jmp cw_bool
+
+# cw_greater_equal(a, b) -> Bool
+    .globl cw_greater_equal
+cw_greater_equal:
+    subq $8, %rsp
+    call cw_compare
+    addq $8, %rsp
+    cmpl $1, %eax
+    setbe %al
+    jmp cw_bool
+
+    .section .note.GNU-stack,"",@progbits
@@ -0,0 +1,286 @@
+//! Bytecode for the stack-based virtual machine of `crawfish run`, compiled from the IR.
+//! Every function becomes a code object whose instructions push and pop operands on a shared stack.
+//! A call frame's locals (captured variables, then parameters, then the IR values that are not
+//! used right where they are computed) sit at the bottom of the frame, below its operands.
+pub mod compiler;
+pub mod file;
+pub mod verifier;
+
+use crate::front_end::token::Span;
+use std::error::Error;
+use std::fmt;
+
+pub use crate::back_end::ir::Builtin;
+pub use compiler::{compile, compile_ir};
+
+/// Why a `.crwb` file cannot be loaded
+#[derive(Debug, PartialEq)]
+pub enum BytecodeError {
+    NotBytecode,
+    UnsupportedVersion(u16),
+    Truncated,
+    ChecksumMismatch,
+    TrailingBytes,
+    InvalidTag(u8),
+    InvalidOpcode(u8),
+    InvalidChar(u32),
+    InvalidString,
+    /// A structurally valid file whose code could not run safely, as found by the verifier
+    Unverifiable {
+        function: String,
+        offset: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BytecodeError::NotBytecode => write!(f, "Not a Crawfish bytecode file"),
+            BytecodeError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported bytecode format version {} (expected {})",
+                version,
+                file::FORMAT_VERSION
+            ),
+            BytecodeError::Truncated => write!(f, "Bytecode file is truncated"),
+            BytecodeError::ChecksumMismatch => {
+                write!(f, "Bytecode file is corrupted (checksum mismatch)")
+            }
+            BytecodeError::TrailingBytes => write!(f, "Bytecode file has trailing bytes"),
+            BytecodeError::InvalidTag(tag) => write!(f, "Invalid constant tag {}", tag),
+            BytecodeError::InvalidOpcode(opcode) => write!(f, "Invalid opcode {}", opcode),
+            BytecodeError::InvalidChar(value) => {
+                write!(f, "Invalid character code point {:#x}", value)
+            }
+            BytecodeError::InvalidString => write!(f, "Invalid UTF-8 string"),
+            BytecodeError::Unverifiable {
+                function,
+                offset,
+                reason,
+            } => write!(
+                f,
+                "Bytecode verification failed in `{}` at offset {}: {}",
+                function, offset, reason
+            ),
+        }
+    }
+}
+
+impl Error for BytecodeError {}
+
+/// A compiled program
+/// - `constants` is the constant pool shared by every function
+/// - `entry` is the index of `main()` in `functions`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bytecode {
+    pub constants: Vec<Constant>,
+    pub functions: Vec<Code>,
+    pub entry: u32,
+}
+
+/// A value too large to be stored inline in an instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Float(f64),
+    String(String),
+}
+
+/// A compiled function
+/// - `captures` is the number of variables of its environment, which occupy the first local slots
+/// - `params` is the number of parameters, which occupy the local slots following the captures
+/// - `locals` is the total number of local slots, including captures and parameters
+/// - `max_stack` is the greatest number of operands it keeps on the stack at once
+/// - `lines` maps instruction offsets back to the source they were compiled from
+/// - `file` is the index of the source file the function is written in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Code {
+    pub name: String,
+    pub captures: u32,
+    pub params: u32,
+    pub locals: u32,
+    pub max_stack: u32,
+    pub instructions: Vec<Instruction>,
+    pub lines: Vec<Line>,
+    pub file: usize,
+}
+
+/// An entry of a line table, stating that the instructions from `offset` up to the next entry
+/// were compiled from `span`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Line {
+    pub offset: u32,
+    pub span: Span,
+}
+
+impl Code {
+    /// The source span of the instruction at `offset`
+    pub fn span_at(&self, offset: usize) -> Span {
+        let entry = self
+            .lines
+            .partition_point(|line| line.offset as usize <= offset);
+        self.lines
+            .get(entry.wrapping_sub(1))
+            .map_or(Span::default(), |line| line.span)
+    }
+}
+
+/// A virtual machine instruction. Jump targets are instruction offsets within the same function.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Int(i32),
+    Char(char),
+    Bool(bool),
+    Unit,
+    Null,
+    /// Pushes an entry of the constant pool
+    Constant(u32),
+    /// Pushes a top level function as a value
+    Function(u32),
+    /// Pops the `captures` values of an environment and pushes a closure of `function` over them
+    Closure {
+        function: u32,
+        captures: u32,
+    },
+
+    /// Pushes the value of a local slot, which is a heap cell for boxed variables
+    Load(u32),
+    /// Pops a value into a local slot
+    Store(u32),
+    /// Pushes the contents of the heap cell in a local slot
+    LoadCell(u32),
+    /// Pops a value into the heap cell in a local slot
+    StoreCell(u32),
+    /// Moves the value on top of the stack into a new heap cell
+    MakeCell,
+    Pop,
+
+    AddInt,
+    SubtractInt,
+    MultiplyInt,
+    DivideInt,
+    RemainderInt,
+    NegateInt,
+    AddFloat,
+    SubtractFloat,
+    MultiplyFloat,
+    DivideFloat,
+    RemainderFloat,
+    NegateFloat,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// Pops the end then the start of a range, and pushes the range
+    Range {
+        inclusive: bool,
+    },
+    /// Replaces a range on top of the stack with its first item
+    RangeStart,
+    /// Pops an item then a range, and pushes whether the item is not past the end of the range
+    RangeContains,
+    /// Replaces an `Ok` or an `Err` on top of the stack with its value
+    Payload,
+
+    Jump(u32),
+    /// Pops a condition, jumping when it is `false`
+    JumpIfFalse(u32),
+    /// Calls a top level function with the `argc` arguments on top of the stack
+    Call {
+        function: u32,
+        argc: u32,
+    },
+    /// Calls the function value found below the `argc` arguments on top of the stack
+    CallValue {
+        argc: u32,
+    },
+    CallBuiltin {
+        builtin: Builtin,
+        argc: u32,
+    },
+    /// Pops the return value and leaves the function
+    Return,
+}
+
+impl Instruction {
+    /// How many values the instruction adds to the stack (negative when it removes them), when
+    /// execution continues with the next instruction
+    pub fn stack_effect(&self) -> i32 {
+        match self {
+            Instruction::Int(_)
+            | Instruction::Char(_)
+            | Instruction::Bool(_)
+            | Instruction::Unit
+            | Instruction::Null
+            | Instruction::Constant(_)
+            | Instruction::Function(_)
+            | Instruction::Load(_)
+            | Instruction::LoadCell(_) => 1,
+            Instruction::Closure { captures, .. } => 1 - *captures as i32,
+            Instruction::Store(_)
+            | Instruction::StoreCell(_)
+            | Instruction::Pop
+            | Instruction::JumpIfFalse(_)
+            | Instruction::Return => -1,
+            Instruction::MakeCell
+            | Instruction::NegateInt
+            | Instruction::NegateFloat
+            | Instruction::BitNot
+            | Instruction::Not
+            | Instruction::RangeStart
+            | Instruction::Payload
+            | Instruction::Jump(_) => 0,
+            Instruction::AddInt
+            | Instruction::SubtractInt
+            | Instruction::MultiplyInt
+            | Instruction::DivideInt
+            | Instruction::RemainderInt
+            | Instruction::AddFloat
+            | Instruction::SubtractFloat
+            | Instruction::MultiplyFloat
+            | Instruction::DivideFloat
+            | Instruction::RemainderFloat
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor
+            | Instruction::ShiftLeft
+            | Instruction::ShiftRight
+            | Instruction::Equal
+            | Instruction::NotEqual
+            | Instruction::Less
+            | Instruction::LessEqual
+            | Instruction::Greater
+            | Instruction::GreaterEqual
+            | Instruction::Range { .. }
+            | Instruction::RangeContains => -1,
+            Instruction::Call { argc, .. } | Instruction::CallBuiltin { argc, .. } => {
+                1 - *argc as i32
+            }
+            Instruction::CallValue { argc } => -(*argc as i32),
+        }
+    }
+}
+
+/// Disassembles the function, one instruction per line
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (captures: {}, params: {}, locals: {}, max stack: {})",
+            self.name, self.captures, self.params, self.locals, self.max_stack
+        )?;
+        for (offset, instruction) in self.instructions.iter().enumerate() {
+            writeln!(f, "{:04} {:?}", offset, instruction)?;
+        }
+        Ok(())
+    }
+}
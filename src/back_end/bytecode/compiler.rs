@@ -0,0 +1,581 @@
+//! Compilation of the IR of a program to bytecode.
+//! Each block becomes a run of instructions ending in jumps to its successors. An IR value used
+//! once, by an instruction of the same block that finds it on top of the stack, stays on the stack;
+//! every other value, phi or not, gets a local slot. Before jumping, a block stores the incoming
+//! values of the phis of its successor into their slots.
+use crate::back_end::bytecode::{Bytecode, Code, Constant, Instruction, Line};
+use crate::back_end::ir::{self, cfg, Block, Function, InstructionKind, Terminator, Value};
+use crate::front_end::ast::{BinaryOp, Program, UnaryOp};
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use std::collections::HashSet;
+
+/// Compiles a type checked program
+pub fn compile(program: &Program) -> Bytecode {
+    compile_ir(&ir::build(program))
+}
+
+/// Compiles the IR of a program
+pub fn compile_ir(program: &ir::Program) -> Bytecode {
+    let mut constants = ConstantPool::default();
+    let functions = program
+        .functions
+        .iter()
+        .map(|function| FunctionCompiler::new(&mut constants, function).compile())
+        .collect();
+    Bytecode {
+        constants: constants.constants,
+        functions,
+        entry: program.entry as u32,
+    }
+}
+
+/// Constant pool, which stores each string once
+#[derive(Default)]
+struct ConstantPool {
+    constants: Vec<Constant>,
+    strings: std::collections::HashMap<String, u32>,
+}
+
+impl ConstantPool {
+    fn add(&mut self, constant: Constant) -> u32 {
+        if let Constant::String(string) = &constant {
+            if let Some(&index) = self.strings.get(string) {
+                return index;
+            }
+            self.strings
+                .insert(string.clone(), self.constants.len() as u32);
+        }
+        self.constants.push(constant);
+        self.constants.len() as u32 - 1
+    }
+}
+
+/// The operands an instruction pops from the stack, in the order they are pushed. The heap cells of
+/// `cell.load` and `cell.store` are read from their slot instead.
+fn stack_operands(kind: &InstructionKind) -> Vec<Value> {
+    match kind {
+        InstructionKind::LoadCell(_) => vec![],
+        InstructionKind::StoreCell { value, .. } => vec![*value],
+        kind => kind.operands(),
+    }
+}
+
+/// Function compiler
+/// - `function` has its critical edges split, so that the moves into the phis of a block can be
+///   placed at the end of its predecessors
+/// - `uses` counts the uses of every value, and `stacked` holds the values kept on the stack
+/// - `slots` holds the local slot of every value that has one
+/// - `depth` is the number of operands on the stack after the last emitted instruction
+/// - `jumps` lists the jumps to patch with the offset of the block they target once it is known
+struct FunctionCompiler<'c> {
+    constants: &'c mut ConstantPool,
+    function: Function,
+    uses: Vec<u32>,
+    stacked: HashSet<Value>,
+    slots: Vec<Option<u32>>,
+    code: Code,
+    depth: u32,
+    span: Span,
+    offsets: Vec<u32>,
+    jumps: Vec<(usize, Block)>,
+}
+
+impl<'c> FunctionCompiler<'c> {
+    fn new(constants: &'c mut ConstantPool, function: &Function) -> Self {
+        let mut function = function.clone();
+        cfg::split_critical_edges(&mut function);
+        let code = Code {
+            name: function.name.clone(),
+            captures: function.captures.len() as u32,
+            params: function.params.len() as u32,
+            locals: (function.captures.len() + function.params.len()) as u32,
+            max_stack: 0,
+            instructions: Vec::new(),
+            lines: Vec::new(),
+            file: function.file,
+        };
+        let mut slots = vec![None; function.values.len()];
+        for (index, param) in function.params.iter().enumerate() {
+            slots[param.0 as usize] = Some(function.captures.len() as u32 + index as u32);
+        }
+        Self {
+            constants,
+            uses: vec![0; function.values.len()],
+            stacked: HashSet::new(),
+            slots,
+            code,
+            depth: 0,
+            span: Span::default(),
+            offsets: vec![0; function.blocks.len()],
+            jumps: Vec::new(),
+            function,
+        }
+    }
+
+    fn compile(mut self) -> Code {
+        let function = std::mem::replace(&mut self.function, empty_function());
+        for block in &function.blocks {
+            for phi in &block.phis {
+                for (_, value) in &phi.incoming {
+                    self.uses[value.0 as usize] += 1;
+                }
+            }
+            for instruction in &block.instructions {
+                for operand in instruction.kind.operands() {
+                    self.uses[operand.0 as usize] += 1;
+                }
+                // The environment of a closure occupies its first slots
+                if let (InstructionKind::Capture(slot), Some(result)) =
+                    (&instruction.kind, instruction.result)
+                {
+                    self.slots[result.0 as usize] = Some(*slot);
+                }
+            }
+            if let Some(value) = block.terminator.operand() {
+                self.uses[value.0 as usize] += 1;
+            }
+        }
+        for block in &function.blocks {
+            self.schedule(block);
+        }
+
+        for block in function.block_ids() {
+            self.offsets[block.0 as usize] = self.offset();
+            let basic_block = function.block(block);
+            for instruction in &basic_block.instructions {
+                self.span = instruction.span;
+                self.instruction(&function, &instruction.kind, instruction.result);
+            }
+            let next = Block(block.0 + 1);
+            self.terminator(&function, block, next);
+        }
+        for (offset, block) in std::mem::take(&mut self.jumps) {
+            let target = self.offsets[block.0 as usize];
+            match &mut self.code.instructions[offset] {
+                Instruction::Jump(to) | Instruction::JumpIfFalse(to) => *to = target,
+                instruction => unreachable!("{:?} is not a jump", instruction),
+            }
+        }
+        self.code
+    }
+
+    /// Decides which values of a block stay on the stack between the instruction computing them
+    /// and their only use, simulating the stack of values that could. An instruction takes its
+    /// first operands from the top of the stack when they are there in order, and the values
+    /// below them can still be used later, but an operand found deeper is given a slot instead.
+    fn schedule(&mut self, block: &ir::BasicBlock) {
+        let mut pending: Vec<Value> = Vec::new();
+        let used_here: Vec<Value> = block
+            .instructions
+            .iter()
+            .flat_map(|instruction| stack_operands(&instruction.kind))
+            .chain(match &block.terminator {
+                Terminator::Branch { condition, .. } => Some(*condition),
+                Terminator::Return(value) => Some(*value),
+                _ => None,
+            })
+            .collect();
+        for instruction in &block.instructions {
+            self.consume(&mut pending, &stack_operands(&instruction.kind));
+            if let Some(result) = instruction.result {
+                let once = self.uses[result.0 as usize] == 1 && used_here.contains(&result);
+                if once && !matches!(instruction.kind, InstructionKind::Capture(_)) {
+                    pending.push(result);
+                }
+            }
+        }
+        if let Some(operand) = block.terminator.operand() {
+            self.consume(&mut pending, &[operand]);
+        }
+    }
+
+    /// Takes the operands of an instruction from the simulated stack of a block
+    fn consume(&mut self, pending: &mut Vec<Value>, operands: &[Value]) {
+        let taken = (0..=operands.len().min(pending.len()))
+            .rev()
+            .find(|&n| pending.ends_with(&operands[..n]))
+            .unwrap_or(0);
+        pending.truncate(pending.len() - taken);
+        self.stacked.extend(&operands[..taken]);
+        pending.retain(|value| !operands.contains(value));
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        let offset = self.code.instructions.len();
+        if self.code.lines.last().map(|line| line.span) != Some(self.span) {
+            self.code.lines.push(Line {
+                offset: offset as u32,
+                span: self.span,
+            });
+        }
+        self.code.instructions.push(instruction);
+        self.depth = (self.depth as i32 + instruction.stack_effect()) as u32;
+        self.code.max_stack = self.code.max_stack.max(self.depth);
+        offset
+    }
+
+    fn emit_jump(&mut self, instruction: Instruction, target: Block) {
+        let offset = self.emit(instruction);
+        self.jumps.push((offset, target));
+    }
+
+    fn offset(&self) -> u32 {
+        self.code.instructions.len() as u32
+    }
+
+    fn slot(&mut self, value: Value) -> u32 {
+        match self.slots[value.0 as usize] {
+            Some(slot) => slot,
+            None => {
+                let slot = self.code.locals;
+                self.code.locals += 1;
+                self.slots[value.0 as usize] = Some(slot);
+                slot
+            }
+        }
+    }
+
+    /// Pushes a value that is not already on the stack
+    fn load(&mut self, value: Value) {
+        if !self.stacked.contains(&value) {
+            let slot = self.slot(value);
+            self.emit(Instruction::Load(slot));
+        }
+    }
+
+    fn instruction(&mut self, function: &Function, kind: &InstructionKind, result: Option<Value>) {
+        let unused = result.is_some_and(|result| self.uses[result.0 as usize] == 0);
+        if unused
+            && matches!(
+                kind,
+                InstructionKind::Const(_) | InstructionKind::Function(_)
+            )
+        {
+            return;
+        }
+        for operand in stack_operands(kind) {
+            self.load(operand);
+        }
+        let instruction = match kind {
+            // Captures are read from their slot where they are used
+            InstructionKind::Capture(_) => return,
+            InstructionKind::Const(constant) => match constant {
+                ir::Constant::Int(value) => Instruction::Int(*value),
+                ir::Constant::Float(value) => {
+                    Instruction::Constant(self.constants.add(Constant::Float(*value)))
+                }
+                ir::Constant::Bool(value) => Instruction::Bool(*value),
+                ir::Constant::Char(value) => Instruction::Char(*value),
+                ir::Constant::String(value) => {
+                    Instruction::Constant(self.constants.add(Constant::String(value.clone())))
+                }
+                ir::Constant::Unit => Instruction::Unit,
+                ir::Constant::Null => Instruction::Null,
+            },
+            InstructionKind::Function(index) => Instruction::Function(*index as u32),
+            InstructionKind::Closure {
+                function: index,
+                captures,
+            } => Instruction::Closure {
+                function: *index as u32,
+                captures: captures.len() as u32,
+            },
+            InstructionKind::NewCell(_) => Instruction::MakeCell,
+            InstructionKind::LoadCell(cell) => Instruction::LoadCell(self.slot(*cell)),
+            InstructionKind::StoreCell { cell, .. } => Instruction::StoreCell(self.slot(*cell)),
+            InstructionKind::Unary { op, operand } => match (op, function.type_of(*operand)) {
+                (UnaryOp::Negate, Type::Float) => Instruction::NegateFloat,
+                (UnaryOp::Negate, _) => Instruction::NegateInt,
+                (UnaryOp::Not, _) => Instruction::Not,
+                (UnaryOp::BitNot, _) => Instruction::BitNot,
+            },
+            InstructionKind::Binary { op, left, .. } => binary(*op, function.type_of(*left)),
+            InstructionKind::Range { inclusive, .. } => Instruction::Range {
+                inclusive: *inclusive,
+            },
+            InstructionKind::RangeStart(_) => Instruction::RangeStart,
+            InstructionKind::RangeContains { .. } => Instruction::RangeContains,
+            InstructionKind::IsNull(_) => {
+                self.emit(Instruction::Null);
+                Instruction::Equal
+            }
+            InstructionKind::Payload(_) => Instruction::Payload,
+            InstructionKind::Call {
+                function: index,
+                args,
+            } => Instruction::Call {
+                function: *index as u32,
+                argc: args.len() as u32,
+            },
+            InstructionKind::CallValue { args, .. } => Instruction::CallValue {
+                argc: args.len() as u32,
+            },
+            InstructionKind::CallBuiltin { builtin, args } => Instruction::CallBuiltin {
+                builtin: *builtin,
+                argc: args.len() as u32,
+            },
+        };
+        self.emit(instruction);
+        if let Some(result) = result {
+            if unused {
+                self.emit(Instruction::Pop);
+            } else if !self.stacked.contains(&result) {
+                let slot = self.slot(result);
+                self.emit(Instruction::Store(slot));
+            }
+        }
+    }
+
+    /// Stores the values that the phis of `to` take when coming from `from`, all at once
+    fn phi_moves(&mut self, function: &Function, from: Block, to: Block) {
+        let moves: Vec<(Value, Value)> = function
+            .block(to)
+            .phis
+            .iter()
+            .filter_map(|phi| {
+                let (_, value) = phi.incoming.iter().find(|(block, _)| *block == from)?;
+                (*value != phi.result).then_some((phi.result, *value))
+            })
+            .collect();
+        for (_, value) in &moves {
+            let slot = self.slot(*value);
+            self.emit(Instruction::Load(slot));
+        }
+        for (phi, _) in moves.iter().rev() {
+            let slot = self.slot(*phi);
+            self.emit(Instruction::Store(slot));
+        }
+    }
+
+    /// Ends a block, falling through to `next` rather than jumping to it
+    fn terminator(&mut self, function: &Function, block: Block, next: Block) {
+        match function.block(block).terminator {
+            Terminator::Jump(target) => {
+                self.phi_moves(function, block, target);
+                if target != next {
+                    self.emit_jump(Instruction::Jump(0), target);
+                }
+            }
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => {
+                // Critical edges are split, so a successor with phis has no other predecessor
+                self.phi_moves(function, block, then);
+                self.phi_moves(function, block, otherwise);
+                self.load(condition);
+                self.emit_jump(Instruction::JumpIfFalse(0), otherwise);
+                if then != next {
+                    self.emit_jump(Instruction::Jump(0), then);
+                }
+            }
+            Terminator::Return(value) => {
+                self.load(value);
+                self.emit(Instruction::Return);
+            }
+            // The block ends in a call that never returns, but the code must not run past it
+            Terminator::Unreachable => {
+                self.emit(Instruction::Unit);
+                self.emit(Instruction::Return);
+            }
+        }
+    }
+}
+
+fn empty_function() -> Function {
+    Function {
+        name: String::new(),
+        captures: Vec::new(),
+        params: Vec::new(),
+        return_type: Type::Unit,
+        values: Vec::new(),
+        blocks: Vec::new(),
+        file: 0,
+    }
+}
+
+/// The instruction applying `op` to two operands of type `ty`
+fn binary(op: BinaryOp, ty: &Type) -> Instruction {
+    let float = *ty == Type::Float;
+    match op {
+        BinaryOp::Add if float => Instruction::AddFloat,
+        BinaryOp::Subtract if float => Instruction::SubtractFloat,
+        BinaryOp::Multiply if float => Instruction::MultiplyFloat,
+        BinaryOp::Divide if float => Instruction::DivideFloat,
+        BinaryOp::Remainder if float => Instruction::RemainderFloat,
+        BinaryOp::Add => Instruction::AddInt,
+        BinaryOp::Subtract => Instruction::SubtractInt,
+        BinaryOp::Multiply => Instruction::MultiplyInt,
+        BinaryOp::Divide => Instruction::DivideInt,
+        BinaryOp::Remainder => Instruction::RemainderInt,
+        BinaryOp::Equal => Instruction::Equal,
+        BinaryOp::NotEqual => Instruction::NotEqual,
+        BinaryOp::Less => Instruction::Less,
+        BinaryOp::LessEqual => Instruction::LessEqual,
+        BinaryOp::Greater => Instruction::Greater,
+        BinaryOp::GreaterEqual => Instruction::GreaterEqual,
+        BinaryOp::BitAnd => Instruction::BitAnd,
+        BinaryOp::BitOr => Instruction::BitOr,
+        BinaryOp::BitXor => Instruction::BitXor,
+        BinaryOp::ShiftLeft => Instruction::ShiftLeft,
+        BinaryOp::ShiftRight => Instruction::ShiftRight,
+        BinaryOp::And | BinaryOp::Or | BinaryOp::Coalesce => {
+            unreachable!("short-circuiting operators are lowered to branches")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::bytecode::{verifier::stack_depths, Builtin};
+    use crate::front_end;
+
+    fn compile_source(source: &str) -> Bytecode {
+        let analysis = front_end::analyze(source).unwrap();
+        compile(&analysis.program)
+    }
+
+    fn function<'b>(bytecode: &'b Bytecode, name: &str) -> &'b Code {
+        bytecode
+            .functions
+            .iter()
+            .find(|function| function.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_is_typed() {
+        let bytecode = compile_source(
+            "func f(a: Int, b: Float) -> Float { if a > 0 { return b * 2.0; } return -b; } func main() {}",
+        );
+        let f = function(&bytecode, "f");
+        assert_eq!(f.params, 2);
+        assert!(f.instructions.contains(&Instruction::MultiplyFloat));
+        assert!(f.instructions.contains(&Instruction::NegateFloat));
+        assert!(f.instructions.contains(&Instruction::Greater));
+        assert_eq!(bytecode.constants, [Constant::Float(2.0)]);
+        assert_eq!(bytecode.entry, 1);
+    }
+
+    #[test]
+    fn test_strings_are_pooled_once() {
+        let bytecode =
+            compile_source(r#"func main() { println("a"); println("b"); println("a"); }"#);
+        assert_eq!(
+            bytecode.constants,
+            [
+                Constant::String("a".to_string()),
+                Constant::String("b".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calls() {
+        let source = r#"
+            func id(x: Int) -> Int { return x; }
+            func main() {
+                id(1);
+                const f = id;
+                f(2);
+                println(3);
+            }
+        "#;
+        let main = &compile_source(source).functions[1];
+        assert_eq!(
+            main.instructions,
+            [
+                Instruction::Int(1),
+                Instruction::Call {
+                    function: 0,
+                    argc: 1
+                },
+                Instruction::Pop,
+                Instruction::Function(0),
+                Instruction::Int(2),
+                Instruction::CallValue { argc: 1 },
+                Instruction::Pop,
+                Instruction::Int(3),
+                Instruction::CallBuiltin {
+                    builtin: Builtin::Println,
+                    argc: 1
+                },
+                Instruction::Pop,
+                Instruction::Unit,
+                Instruction::Return,
+            ]
+        );
+        assert_eq!(main.locals, 0);
+        assert_eq!(main.max_stack, 2);
+    }
+
+    #[test]
+    fn test_captured_variables_are_boxed() {
+        let source = r#"
+            func main() {
+                var count = 0;
+                const increment = func() { count += 1; };
+                increment();
+            }
+        "#;
+        let bytecode = compile_source(source);
+        let main = function(&bytecode, "main");
+        assert_eq!(
+            main.instructions[..3],
+            [
+                Instruction::Int(0),
+                Instruction::MakeCell,
+                Instruction::Closure {
+                    function: 1,
+                    captures: 1
+                },
+            ]
+        );
+        let lambda = function(&bytecode, "main$lambda0");
+        assert_eq!(lambda.captures, 1);
+        assert_eq!(
+            lambda.instructions[..4],
+            [
+                Instruction::LoadCell(0),
+                Instruction::Int(1),
+                Instruction::AddInt,
+                Instruction::StoreCell(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_break_leaves_the_stack_balanced() {
+        let source = r#"
+            func main() {
+                while true {
+                    const x = 1 + { break; 2 };
+                }
+            }
+        "#;
+        let main = &compile_source(source).functions[0];
+        let depths = stack_depths(main).unwrap();
+        for (depth, instruction) in depths.iter().zip(&main.instructions) {
+            if let (Some(depth), Instruction::Jump(_) | Instruction::Return) = (depth, instruction)
+            {
+                assert!(*depth <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_table() {
+        let source = "func main() {\n    println(1 / 0);\n}";
+        let main = &compile_source(source).functions[0];
+        let divide = main
+            .instructions
+            .iter()
+            .position(|instruction| *instruction == Instruction::DivideInt)
+            .unwrap();
+        let start = source.find("1 / 0").unwrap();
+        assert_eq!(main.span_at(divide), Span::new(start, start + 5));
+    }
+}
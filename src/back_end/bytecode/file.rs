@@ -0,0 +1,537 @@
+//! The `.crwb` file format, which stores a compiled program so it can be run many times without
+//! being compiled again.
+//!
+//! A file starts with a header: the magic bytes `CRWB`, the format version, the payload length and a
+//! CRC-32 checksum of the payload. The payload holds the entry function, the constant pool, the
+//! functions with their line tables, and the path and text of every source file, so that panics
+//! point into the source even after it changed or moved. Integers are little-endian, and strings and
+//! lists are prefixed with their length.
+//!
+//! The checksum only catches accidental corruption, as anyone can compute it for a file they
+//! edited: the verifier, and the checks the virtual machine makes as it runs, are what keep a
+//! crafted file from doing more than stopping with an error.
+use crate::back_end::bytecode::{
+    verifier, Builtin, Bytecode, BytecodeError, Code, Constant, Instruction, Line,
+};
+use crate::front_end::modules::SourceFile;
+use crate::front_end::token::Span;
+use std::path::PathBuf;
+
+pub const MAGIC: [u8; 4] = *b"CRWB";
+
+/// Version of the format, increased whenever the layout or the instruction set changes
+pub const FORMAT_VERSION: u16 = 2;
+
+const HEADER_LEN: usize = 14;
+
+/// Serializes a program compiled from `files`
+pub fn encode(bytecode: &Bytecode, files: &[SourceFile]) -> Vec<u8> {
+    let mut payload = Writer::default();
+    payload.u32(bytecode.entry);
+
+    payload.u32(bytecode.constants.len() as u32);
+    for constant in &bytecode.constants {
+        match constant {
+            Constant::Float(value) => {
+                payload.u8(0);
+                payload.u64(value.to_bits());
+            }
+            Constant::String(value) => {
+                payload.u8(1);
+                payload.string(value);
+            }
+        }
+    }
+
+    payload.u32(bytecode.functions.len() as u32);
+    for code in &bytecode.functions {
+        payload.string(&code.name);
+        payload.u32(code.captures);
+        payload.u32(code.params);
+        payload.u32(code.locals);
+        payload.u32(code.max_stack);
+        payload.u32(code.file as u32);
+        payload.u32(code.instructions.len() as u32);
+        for instruction in &code.instructions {
+            payload.instruction(instruction);
+        }
+        payload.u32(code.lines.len() as u32);
+        for line in &code.lines {
+            payload.u32(line.offset);
+            payload.u32(line.span.start as u32);
+            payload.u32(line.span.end as u32);
+        }
+    }
+
+    payload.u32(files.len() as u32);
+    for file in files {
+        payload.string(&file.path.to_string_lossy());
+        match &file.module {
+            Some(module) => {
+                payload.u8(1);
+                payload.string(module);
+            }
+            None => payload.u8(0),
+        }
+        payload.string(&file.source);
+    }
+
+    let payload = payload.bytes;
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/// Deserializes and verifies a program, returning it with the source files it was compiled from
+pub fn decode(bytes: &[u8]) -> Result<(Bytecode, Vec<SourceFile>), BytecodeError> {
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Err(BytecodeError::NotBytecode);
+    }
+    let mut header = Reader::new(&bytes[MAGIC.len()..]);
+    let version = header.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+    let len = header.u32()? as usize;
+    let checksum = header.u32()?;
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() < len {
+        return Err(BytecodeError::Truncated);
+    }
+    if payload.len() > len {
+        return Err(BytecodeError::TrailingBytes);
+    }
+    if crc32(payload) != checksum {
+        return Err(BytecodeError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader::new(payload);
+    let entry = reader.u32()?;
+
+    let mut constants = Vec::new();
+    for _ in 0..reader.u32()? {
+        constants.push(match reader.u8()? {
+            0 => Constant::Float(f64::from_bits(reader.u64()?)),
+            1 => Constant::String(reader.string()?),
+            tag => return Err(BytecodeError::InvalidTag(tag)),
+        });
+    }
+
+    let mut functions = Vec::new();
+    for _ in 0..reader.u32()? {
+        let name = reader.string()?;
+        let captures = reader.u32()?;
+        let params = reader.u32()?;
+        let locals = reader.u32()?;
+        let max_stack = reader.u32()?;
+        let file = reader.u32()? as usize;
+        let mut instructions = Vec::new();
+        for _ in 0..reader.u32()? {
+            instructions.push(reader.instruction()?);
+        }
+        let mut lines = Vec::new();
+        for _ in 0..reader.u32()? {
+            let offset = reader.u32()?;
+            let start = reader.u32()? as usize;
+            let end = reader.u32()? as usize;
+            lines.push(Line {
+                offset,
+                span: Span::new(start, end),
+            });
+        }
+        functions.push(Code {
+            name,
+            captures,
+            params,
+            locals,
+            max_stack,
+            instructions,
+            lines,
+            file,
+        });
+    }
+
+    let mut files = Vec::new();
+    for _ in 0..reader.u32()? {
+        let path = PathBuf::from(reader.string()?);
+        let module = match reader.u8()? {
+            0 => None,
+            1 => Some(reader.string()?),
+            tag => return Err(BytecodeError::InvalidTag(tag)),
+        };
+        let source = reader.string()?;
+        files.push(SourceFile {
+            path,
+            module,
+            source,
+        });
+    }
+    if !reader.is_empty() {
+        return Err(BytecodeError::TrailingBytes);
+    }
+
+    let bytecode = Bytecode {
+        constants,
+        functions,
+        entry,
+    };
+    verifier::verify(&bytecode, &files)?;
+    Ok((bytecode, files))
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// Writes an opcode followed by the operands of the instruction
+    fn instruction(&mut self, instruction: &Instruction) {
+        let opcode = opcode(instruction);
+        self.u8(opcode);
+        match *instruction {
+            Instruction::Int(value) => self.u32(value as u32),
+            Instruction::Char(value) => self.u32(value as u32),
+            Instruction::Bool(value) => self.u8(value as u8),
+            Instruction::Range { inclusive } => self.u8(inclusive as u8),
+            Instruction::Constant(operand)
+            | Instruction::Function(operand)
+            | Instruction::Load(operand)
+            | Instruction::Store(operand)
+            | Instruction::LoadCell(operand)
+            | Instruction::StoreCell(operand)
+            | Instruction::Jump(operand)
+            | Instruction::JumpIfFalse(operand)
+            | Instruction::CallValue { argc: operand } => self.u32(operand),
+            Instruction::Closure {
+                function: first,
+                captures: second,
+            }
+            | Instruction::Call {
+                function: first,
+                argc: second,
+            } => {
+                self.u32(first);
+                self.u32(second);
+            }
+            Instruction::CallBuiltin { builtin, argc } => {
+                self.u8(builtin as u8);
+                self.u32(argc);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Opcodes without operands, in the order of their opcodes
+const SIMPLE: [Instruction; 33] = [
+    Instruction::Unit,
+    Instruction::Null,
+    Instruction::MakeCell,
+    Instruction::Pop,
+    Instruction::AddInt,
+    Instruction::SubtractInt,
+    Instruction::MultiplyInt,
+    Instruction::DivideInt,
+    Instruction::RemainderInt,
+    Instruction::NegateInt,
+    Instruction::AddFloat,
+    Instruction::SubtractFloat,
+    Instruction::MultiplyFloat,
+    Instruction::DivideFloat,
+    Instruction::RemainderFloat,
+    Instruction::NegateFloat,
+    Instruction::BitAnd,
+    Instruction::BitOr,
+    Instruction::BitXor,
+    Instruction::BitNot,
+    Instruction::ShiftLeft,
+    Instruction::ShiftRight,
+    Instruction::Not,
+    Instruction::Equal,
+    Instruction::NotEqual,
+    Instruction::Less,
+    Instruction::LessEqual,
+    Instruction::Greater,
+    Instruction::GreaterEqual,
+    Instruction::RangeStart,
+    Instruction::RangeContains,
+    Instruction::Payload,
+    Instruction::Return,
+];
+
+/// The first opcode of the instructions with operands
+const SIMPLE_COUNT: u8 = SIMPLE.len() as u8;
+
+fn opcode(instruction: &Instruction) -> u8 {
+    let with_operands = match instruction {
+        Instruction::Int(_) => 0,
+        Instruction::Char(_) => 1,
+        Instruction::Bool(_) => 2,
+        Instruction::Constant(_) => 3,
+        Instruction::Function(_) => 4,
+        Instruction::Closure { .. } => 5,
+        Instruction::Load(_) => 6,
+        Instruction::Store(_) => 7,
+        Instruction::LoadCell(_) => 8,
+        Instruction::StoreCell(_) => 9,
+        Instruction::Range { .. } => 10,
+        Instruction::Jump(_) => 11,
+        Instruction::JumpIfFalse(_) => 12,
+        Instruction::Call { .. } => 13,
+        Instruction::CallValue { .. } => 14,
+        Instruction::CallBuiltin { .. } => 15,
+        _ => {
+            let position = SIMPLE
+                .iter()
+                .position(|simple| simple == instruction)
+                .expect("every instruction has an opcode");
+            return position as u8;
+        }
+    };
+    SIMPLE_COUNT + with_operands
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn new(bytes: &'b [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], BytecodeError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + N)
+            .ok_or(BytecodeError::Truncated)?;
+        self.position += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn bool(&mut self) -> Result<bool, BytecodeError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(BytecodeError::InvalidTag(tag)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        let len = self.u32()? as usize;
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.saturating_add(len))
+            .ok_or(BytecodeError::Truncated)?;
+        self.position += len;
+        String::from_utf8(bytes.to_vec()).map_err(|_| BytecodeError::InvalidString)
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, BytecodeError> {
+        let opcode = self.u8()?;
+        if opcode < SIMPLE_COUNT {
+            return Ok(SIMPLE[opcode as usize]);
+        }
+        Ok(match opcode - SIMPLE_COUNT {
+            0 => Instruction::Int(self.u32()? as i32),
+            1 => {
+                let value = self.u32()?;
+                Instruction::Char(char::from_u32(value).ok_or(BytecodeError::InvalidChar(value))?)
+            }
+            2 => Instruction::Bool(self.bool()?),
+            3 => Instruction::Constant(self.u32()?),
+            4 => Instruction::Function(self.u32()?),
+            5 => Instruction::Closure {
+                function: self.u32()?,
+                captures: self.u32()?,
+            },
+            6 => Instruction::Load(self.u32()?),
+            7 => Instruction::Store(self.u32()?),
+            8 => Instruction::LoadCell(self.u32()?),
+            9 => Instruction::StoreCell(self.u32()?),
+            10 => Instruction::Range {
+                inclusive: self.bool()?,
+            },
+            11 => Instruction::Jump(self.u32()?),
+            12 => Instruction::JumpIfFalse(self.u32()?),
+            13 => Instruction::Call {
+                function: self.u32()?,
+                argc: self.u32()?,
+            },
+            14 => Instruction::CallValue { argc: self.u32()? },
+            15 => {
+                let tag = self.u8()?;
+                let builtin = *Builtin::ALL
+                    .get(tag as usize)
+                    .ok_or(BytecodeError::InvalidTag(tag))?;
+                Instruction::CallBuiltin {
+                    builtin,
+                    argc: self.u32()?,
+                }
+            }
+            _ => return Err(BytecodeError::InvalidOpcode(opcode)),
+        })
+    }
+}
+
+/// CRC-32 (IEEE 802.3) checksum
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::bytecode;
+    use crate::front_end;
+
+    fn compile_source(source: &str) -> (Bytecode, Vec<SourceFile>) {
+        let analysis = front_end::analyze(source).unwrap();
+        let files = vec![SourceFile {
+            path: PathBuf::from("main.crw"),
+            module: None,
+            source: source.to_string(),
+        }];
+        (bytecode::compile(&analysis.program), files)
+    }
+
+    const PROGRAM: &str = r#"
+        func main() {
+            var total = 0.5;
+            const add = func(x: Float) { total += x; };
+            for i in 0..=3 { add(1.5); }
+            println(total);
+            println('🦀');
+            println("done");
+        }
+    "#;
+
+    #[test]
+    fn test_round_trip() {
+        let (bytecode, files) = compile_source(PROGRAM);
+        let bytes = encode(&bytecode, &files);
+        assert_eq!(bytes[..4], *b"CRWB");
+        assert_eq!(decode(&bytes), Ok((bytecode, files)));
+    }
+
+    #[test]
+    fn test_every_opcode_round_trips() {
+        let instructions: Vec<Instruction> = SIMPLE
+            .into_iter()
+            .chain([
+                Instruction::Int(-5),
+                Instruction::Char('é'),
+                Instruction::Bool(true),
+                Instruction::Constant(1),
+                Instruction::Function(2),
+                Instruction::Closure {
+                    function: 3,
+                    captures: 4,
+                },
+                Instruction::Load(5),
+                Instruction::Store(6),
+                Instruction::LoadCell(7),
+                Instruction::StoreCell(8),
+                Instruction::Range { inclusive: true },
+                Instruction::Jump(10),
+                Instruction::JumpIfFalse(11),
+                Instruction::Call {
+                    function: 16,
+                    argc: 17,
+                },
+                Instruction::CallValue { argc: 18 },
+                Instruction::CallBuiltin {
+                    builtin: Builtin::IsErr,
+                    argc: 1,
+                },
+            ])
+            .collect();
+        let mut writer = Writer::default();
+        for instruction in &instructions {
+            writer.instruction(instruction);
+        }
+        let mut reader = Reader::new(&writer.bytes);
+        for instruction in &instructions {
+            assert_eq!(reader.instruction().as_ref(), Ok(instruction));
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_header_is_checked() {
+        let (bytecode, files) = compile_source(PROGRAM);
+        let bytes = encode(&bytecode, &files);
+
+        assert_eq!(decode(b"\x7fELF"), Err(BytecodeError::NotBytecode));
+        let mut future = bytes.clone();
+        future[4] = 99;
+        assert_eq!(decode(&future), Err(BytecodeError::UnsupportedVersion(99)));
+        assert_eq!(decode(&bytes[..10]), Err(BytecodeError::Truncated));
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::Truncated)
+        );
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(decode(&longer), Err(BytecodeError::TrailingBytes));
+        let mut corrupted = bytes.clone();
+        corrupted[HEADER_LEN + 20] ^= 1;
+        assert_eq!(decode(&corrupted), Err(BytecodeError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+}
@@ -0,0 +1,844 @@
+//! Verification of bytecode loaded from a file, before it runs.
+//! The verifier checks that every index points into the table it refers to, that calls pass as many
+//! arguments as their callee expects, and that every instruction finds the operands it pops at the
+//! same stack depth however it is reached, within the function's declared `max_stack`.
+//! It then follows the kind of every operand and local slot (an `Int`, a string, a heap cell...)
+//! and rejects instructions that are given operands of the wrong kind. Parameters, call results and
+//! the payloads of results can hold any kind of value: the virtual machine checks those as it runs,
+//! and stops a program that misuses them with an error instead of trusting the file.
+use crate::back_end::bytecode::{Builtin, Bytecode, BytecodeError, Code, Constant, Instruction};
+use crate::front_end::modules::SourceFile;
+use std::fmt;
+
+/// Most local slots, and most operands on the stack, that a function can declare
+pub const MAX_SLOTS: u32 = u16::MAX as u32;
+
+/// Verifies a program compiled from `files`
+pub fn verify(bytecode: &Bytecode, files: &[SourceFile]) -> Result<(), BytecodeError> {
+    let entry = bytecode
+        .functions
+        .get(bytecode.entry as usize)
+        .ok_or_else(|| BytecodeError::Unverifiable {
+            function: "<entry>".to_string(),
+            offset: 0,
+            reason: format!("entry function {} does not exist", bytecode.entry),
+        })?;
+    if entry.captures != 0 || entry.params != 0 {
+        return Err(BytecodeError::Unverifiable {
+            function: entry.name.clone(),
+            offset: 0,
+            reason: "the entry function must not take arguments".to_string(),
+        });
+    }
+    for code in &bytecode.functions {
+        FunctionVerifier {
+            bytecode,
+            files,
+            code,
+        }
+        .verify()?;
+    }
+    Ok(())
+}
+
+struct FunctionVerifier<'b> {
+    bytecode: &'b Bytecode,
+    files: &'b [SourceFile],
+    code: &'b Code,
+}
+
+impl FunctionVerifier<'_> {
+    fn verify(&self) -> Result<(), BytecodeError> {
+        let code = self.code;
+        if code.locals > MAX_SLOTS {
+            return Err(self.error(0, "the function declares too many local slots"));
+        }
+        if code.max_stack > MAX_SLOTS {
+            return Err(self.error(0, "the function declares too large a stack"));
+        }
+        if code.captures as u64 + code.params as u64 > code.locals as u64 {
+            return Err(self.error(0, "the arguments do not fit in the local slots"));
+        }
+        let source = self
+            .files
+            .get(code.file)
+            .ok_or_else(|| self.error(0, format!("source file {} does not exist", code.file)))?;
+        let mut previous = None;
+        for line in &code.lines {
+            let offset = line.offset as usize;
+            if previous.is_some_and(|previous| previous >= offset)
+                || offset >= code.instructions.len()
+            {
+                return Err(self.error(offset, "the line table is out of order"));
+            }
+            let span = line.span;
+            if span.start > span.end
+                || !source.source.is_char_boundary(span.start)
+                || !source.source.is_char_boundary(span.end)
+            {
+                return Err(self.error(offset, "the line table points outside the source"));
+            }
+            previous = Some(offset);
+        }
+        for (offset, instruction) in code.instructions.iter().enumerate() {
+            self.operands(offset, instruction)?;
+        }
+        stack_depths(code)?;
+        self.kinds()
+    }
+
+    /// Follows the kinds of the operands and local slots along every path through the function.
+    /// The state at the target of a jump joins the states of every path reaching it, and the
+    /// paths from it are followed again whenever that changes it. Only once no state changes
+    /// anymore are the instructions checked against the states they start with.
+    fn kinds(&self) -> Result<(), BytecodeError> {
+        let instructions = &self.code.instructions;
+        let mut targets = vec![false; instructions.len()];
+        targets[0] = true;
+        for instruction in instructions {
+            if let Instruction::Jump(to) | Instruction::JumpIfFalse(to) = *instruction {
+                targets[to as usize] = true;
+            }
+        }
+        let mut locals = vec![Kind::Unit; self.code.locals as usize];
+        let arguments = (self.code.captures + self.code.params) as usize;
+        locals[..arguments].fill(Kind::Any);
+        let mut states: Vec<Option<State>> = vec![None; instructions.len()];
+        states[0] = Some(State {
+            stack: Vec::new(),
+            locals,
+        });
+        let mut pending = vec![0];
+        while let Some(start) = pending.pop() {
+            let state = states[start].clone().expect("pending paths have a state");
+            let mut exits = Vec::new();
+            self.follow(start, state, &targets, &mut exits);
+            for (target, state) in exits {
+                join(&mut states, &mut pending, target, state);
+            }
+        }
+        for (start, state) in states.into_iter().enumerate() {
+            if let Some(state) = state {
+                if let Some((offset, reason)) = self.follow(start, state, &targets, &mut Vec::new())
+                {
+                    return Err(self.error(offset, reason));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the instructions from `start` on `state` up to the next jump target, adding the states
+    /// the jumps from them reach to `exits` and returning the first instruction given operands of
+    /// the wrong kind
+    fn follow(
+        &self,
+        start: usize,
+        mut state: State,
+        targets: &[bool],
+        exits: &mut Vec<(usize, State)>,
+    ) -> Option<(usize, String)> {
+        let mut mismatch = None;
+        let mut offset = start;
+        loop {
+            let instruction = self.code.instructions[offset];
+            if let Some(reason) = self.step(instruction, &mut state) {
+                mismatch = mismatch.or(Some((offset, reason)));
+            }
+            let next = match instruction {
+                Instruction::Return => break,
+                Instruction::Jump(to) => to as usize,
+                Instruction::JumpIfFalse(to) => {
+                    exits.push((to as usize, state.clone()));
+                    offset + 1
+                }
+                _ => offset + 1,
+            };
+            if targets[next] {
+                exits.push((next, state));
+                break;
+            }
+            offset = next;
+        }
+        mismatch
+    }
+
+    /// Applies an instruction to `state`, returning why its operands are of the wrong kind if they
+    /// are
+    fn step(&self, instruction: Instruction, state: &mut State) -> Option<String> {
+        let mut operands = Operands {
+            stack: &mut state.stack,
+            mismatch: None,
+        };
+        let pushed = match instruction {
+            Instruction::Int(_) => Kind::Int,
+            Instruction::Char(_) => Kind::Char,
+            Instruction::Bool(_) => Kind::Bool,
+            Instruction::Unit => Kind::Unit,
+            Instruction::Null => Kind::Null,
+            Instruction::Constant(index) => match self.bytecode.constants[index as usize] {
+                Constant::Float(_) => Kind::Float,
+                Constant::String(_) => Kind::String,
+            },
+            Instruction::Function(_) => Kind::Function,
+            Instruction::Closure { captures, .. } => {
+                operands.pop_many(captures);
+                Kind::Function
+            }
+            Instruction::Load(slot) => state.locals[slot as usize],
+            Instruction::Store(slot) => {
+                state.locals[slot as usize] = operands.pop(Kind::Any);
+                return operands.mismatch;
+            }
+            Instruction::LoadCell(slot) | Instruction::StoreCell(slot) => {
+                let found = state.locals[slot as usize];
+                if !found.fits(Kind::Cell) {
+                    operands.mismatch = Some(format!(
+                        "expected a heap cell in local slot {}, found {}",
+                        slot, found
+                    ));
+                }
+                if let Instruction::StoreCell(_) = instruction {
+                    operands.pop(Kind::Any);
+                    return operands.mismatch;
+                }
+                Kind::Any
+            }
+            Instruction::MakeCell => {
+                operands.pop(Kind::Any);
+                Kind::Cell
+            }
+            Instruction::Pop | Instruction::Return => {
+                operands.pop(Kind::Any);
+                return operands.mismatch;
+            }
+            Instruction::AddInt
+            | Instruction::SubtractInt
+            | Instruction::MultiplyInt
+            | Instruction::DivideInt
+            | Instruction::RemainderInt
+            | Instruction::BitAnd
+            | Instruction::BitOr
+            | Instruction::BitXor
+            | Instruction::ShiftLeft
+            | Instruction::ShiftRight => {
+                operands.pop(Kind::Int);
+                operands.pop(Kind::Int);
+                Kind::Int
+            }
+            Instruction::NegateInt | Instruction::BitNot => {
+                operands.pop(Kind::Int);
+                Kind::Int
+            }
+            Instruction::AddFloat
+            | Instruction::SubtractFloat
+            | Instruction::MultiplyFloat
+            | Instruction::DivideFloat
+            | Instruction::RemainderFloat => {
+                operands.pop(Kind::Float);
+                operands.pop(Kind::Float);
+                Kind::Float
+            }
+            Instruction::NegateFloat => {
+                operands.pop(Kind::Float);
+                Kind::Float
+            }
+            Instruction::Not => {
+                operands.pop(Kind::Bool);
+                Kind::Bool
+            }
+            Instruction::Equal | Instruction::NotEqual => {
+                operands.pop_many(2);
+                Kind::Bool
+            }
+            Instruction::Less
+            | Instruction::LessEqual
+            | Instruction::Greater
+            | Instruction::GreaterEqual => {
+                let b = operands.pop(Kind::Any);
+                let a = operands.pop(b);
+                let kind = a.join(b);
+                if !matches!(kind, Kind::Int | Kind::Float | Kind::Char | Kind::Any) {
+                    operands.mismatch = Some(format!("cannot order {}", kind));
+                }
+                Kind::Bool
+            }
+            Instruction::Range { .. } => {
+                operands.pop(Kind::Int);
+                operands.pop(Kind::Int);
+                Kind::Range
+            }
+            Instruction::RangeStart => {
+                operands.pop(Kind::Range);
+                Kind::Int
+            }
+            Instruction::RangeContains => {
+                operands.pop(Kind::Int);
+                operands.pop(Kind::Range);
+                Kind::Bool
+            }
+            Instruction::Payload => {
+                operands.pop(Kind::Result);
+                Kind::Any
+            }
+            Instruction::Jump(_) => return None,
+            Instruction::JumpIfFalse(_) => {
+                operands.pop(Kind::Bool);
+                return operands.mismatch;
+            }
+            Instruction::Call { argc, .. } => {
+                operands.pop_many(argc);
+                Kind::Any
+            }
+            Instruction::CallValue { argc } => {
+                operands.pop_many(argc);
+                operands.pop(Kind::Function);
+                Kind::Any
+            }
+            Instruction::CallBuiltin { builtin, argc } => {
+                let (params, result) = builtin_kinds(builtin);
+                let args = operands.pop_many(argc);
+                for (&expected, &found) in params.iter().zip(&args) {
+                    if !found.fits(expected) {
+                        operands.mismatch = Some(format!(
+                            "expected {} argument to `{}`, found {}",
+                            expected,
+                            builtin.name(),
+                            found
+                        ));
+                    }
+                }
+                result
+            }
+        };
+        operands.stack.push(pushed);
+        operands.mismatch
+    }
+
+    /// Checks the indices and counts an instruction refers to
+    fn operands(&self, offset: usize, instruction: &Instruction) -> Result<(), BytecodeError> {
+        let code = self.code;
+        let len = code.instructions.len() as u32;
+        let functions = &self.bytecode.functions;
+        let function = |index: u32| {
+            functions
+                .get(index as usize)
+                .ok_or_else(|| self.error(offset, format!("function {} does not exist", index)))
+        };
+        let local = |slot: u32| {
+            if slot < code.locals {
+                Ok(())
+            } else {
+                Err(self.error(offset, format!("local slot {} does not exist", slot)))
+            }
+        };
+        let target = |target: u32| {
+            if target < len {
+                Ok(())
+            } else {
+                Err(self.error(offset, format!("jump target {} is out of bounds", target)))
+            }
+        };
+        let arguments = |valid: bool| {
+            if valid {
+                Ok(())
+            } else {
+                Err(self.error(offset, "wrong number of arguments"))
+            }
+        };
+        match *instruction {
+            Instruction::Constant(index) if index as usize >= self.bytecode.constants.len() => {
+                Err(self.error(offset, format!("constant {} does not exist", index)))
+            }
+            Instruction::Function(index) if function(index)?.captures != 0 => {
+                Err(self.error(offset, "closures cannot be used without an environment"))
+            }
+            Instruction::Closure {
+                function: index,
+                captures,
+            } if function(index)?.captures != captures => {
+                Err(self.error(offset, "the closure's environment has the wrong size"))
+            }
+            Instruction::Load(slot)
+            | Instruction::Store(slot)
+            | Instruction::LoadCell(slot)
+            | Instruction::StoreCell(slot) => local(slot),
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) => target(to),
+            Instruction::Call {
+                function: index,
+                argc,
+            } => {
+                let callee = function(index)?;
+                arguments(callee.captures == 0 && callee.params == argc)
+            }
+            Instruction::CallBuiltin { builtin, argc } => {
+                let (min, max) = builtin.arity();
+                arguments((min..=max).contains(&argc))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn error(&self, offset: usize, reason: impl Into<String>) -> BytecodeError {
+        BytecodeError::Unverifiable {
+            function: self.code.name.clone(),
+            offset,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// The operands on the stack and the contents of the local slots before an instruction
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    stack: Vec<Kind>,
+    locals: Vec<Kind>,
+}
+
+/// The operands an instruction pops, with the first mismatch between the kind of one of them and
+/// the kind the instruction expects
+struct Operands<'s> {
+    stack: &'s mut Vec<Kind>,
+    mismatch: Option<String>,
+}
+
+impl Operands<'_> {
+    fn pop(&mut self, expected: Kind) -> Kind {
+        // The stack depths were verified before
+        let found = self.stack.pop().unwrap_or(Kind::Any);
+        if !found.fits(expected) && self.mismatch.is_none() {
+            self.mismatch = Some(format!("expected {} operand, found {}", expected, found));
+        }
+        found
+    }
+
+    /// Pops `count` operands of any kind, returning them in the order they were pushed
+    fn pop_many(&mut self, count: u32) -> Vec<Kind> {
+        let at = self.stack.len().saturating_sub(count as usize);
+        self.stack.split_off(at)
+    }
+}
+
+/// Joins `state` into the state at `offset`, following the paths from there (again) if it changed
+fn join(states: &mut [Option<State>], pending: &mut Vec<usize>, offset: usize, state: State) {
+    let joined = match &states[offset] {
+        None => state,
+        Some(known) => State {
+            stack: zip_join(&known.stack, &state.stack),
+            locals: zip_join(&known.locals, &state.locals),
+        },
+    };
+    if states[offset].as_ref() != Some(&joined) {
+        states[offset] = Some(joined);
+        pending.push(offset);
+    }
+}
+
+fn zip_join(a: &[Kind], b: &[Kind]) -> Vec<Kind> {
+    a.iter().zip(b).map(|(a, b)| a.join(*b)).collect()
+}
+
+/// What the verifier knows about a value: which variant of the virtual machine's values it is, or
+/// `Any` when that depends on how the function was called
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Int,
+    Float,
+    Bool,
+    Char,
+    Unit,
+    Null,
+    Range,
+    String,
+    Result,
+    /// A function or a closure
+    Function,
+    Cell,
+    File,
+    Any,
+}
+
+impl Kind {
+    /// Whether a value of kind `self` may be what an instruction expecting `expected` is given
+    fn fits(self, expected: Kind) -> bool {
+        self == expected || self == Kind::Any || expected == Kind::Any
+    }
+
+    /// The kind of a value that is of kind `self` on one path and `other` on another
+    fn join(self, other: Kind) -> Kind {
+        if self == other {
+            self
+        } else {
+            Kind::Any
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Kind::Int => "an `Int`",
+            Kind::Float => "a `Float`",
+            Kind::Bool => "a `Bool`",
+            Kind::Char => "a `Char`",
+            Kind::Unit => "`()`",
+            Kind::Null => "`null`",
+            Kind::Range => "a range",
+            Kind::String => "a string",
+            Kind::Result => "a result",
+            Kind::Function => "a function",
+            Kind::Cell => "a heap cell",
+            Kind::File => "a file",
+            Kind::Any => "any value",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The kinds of the leading arguments of a built-in function that only accepts one kind there, and
+/// the kind of its result
+fn builtin_kinds(builtin: Builtin) -> (&'static [Kind], Kind) {
+    match builtin {
+        Builtin::Println | Builtin::Print => (&[], Kind::Unit),
+        Builtin::PrintPadded => (&[Kind::Any, Kind::Int, Kind::Int, Kind::Int], Kind::Unit),
+        Builtin::ToString => (&[], Kind::String),
+        Builtin::Concat => (&[Kind::String, Kind::String], Kind::String),
+        Builtin::Ok | Builtin::Err => (&[], Kind::Result),
+        Builtin::Unwrap | Builtin::UnwrapErr => (&[Kind::Result], Kind::Any),
+        Builtin::IsOk | Builtin::IsErr => (&[Kind::Result], Kind::Bool),
+        Builtin::Open => (&[Kind::String, Kind::String], Kind::Result),
+        Builtin::Close => (&[Kind::File], Kind::Unit),
+        Builtin::Read | Builtin::ReadLine => (&[Kind::File], Kind::Result),
+        Builtin::Write => (&[Kind::File, Kind::String], Kind::Result),
+        Builtin::NextLine => (&[Kind::File], Kind::Any),
+        Builtin::CharAt => (&[Kind::String, Kind::Int], Kind::Char),
+        Builtin::CharWidth => (&[Kind::String, Kind::Int], Kind::Int),
+        // The conversions and math built-ins take and return more than one kind
+        _ => (&[], Kind::Any),
+    }
+}
+
+/// Follows every path through a function, returning how many operands are on the stack before each
+/// instruction, or `None` for unreachable instructions
+pub fn stack_depths(code: &Code) -> Result<Vec<Option<u32>>, BytecodeError> {
+    let error = |offset: usize, reason: String| BytecodeError::Unverifiable {
+        function: code.name.clone(),
+        offset,
+        reason,
+    };
+    let instructions = &code.instructions;
+    let mut depths: Vec<Option<u32>> = vec![None; instructions.len()];
+    let mut pending = vec![(0, 0)];
+    while let Some((offset, depth)) = pending.pop() {
+        let Some(instruction) = instructions.get(offset) else {
+            return Err(error(
+                offset,
+                "execution runs past the end of the function".to_string(),
+            ));
+        };
+        match depths[offset] {
+            Some(known) if known == depth => continue,
+            Some(known) => {
+                return Err(error(
+                    offset,
+                    format!("reached with {} and {} operands", known, depth),
+                ))
+            }
+            None => depths[offset] = Some(depth),
+        }
+        if depth < pops(instruction) {
+            return Err(error(offset, "too few operands on the stack".to_string()));
+        }
+        let next = (depth as i64 + instruction.stack_effect() as i64) as u32;
+        if next > code.max_stack {
+            return Err(error(
+                offset,
+                "the stack grows beyond its declared size".to_string(),
+            ));
+        }
+        match *instruction {
+            Instruction::Return => (),
+            Instruction::Jump(to) => pending.push((to as usize, next)),
+            Instruction::JumpIfFalse(to) => {
+                pending.push((to as usize, next));
+                pending.push((offset + 1, next));
+            }
+            _ => pending.push((offset + 1, next)),
+        }
+    }
+    Ok(depths)
+}
+
+/// How many operands an instruction pops
+fn pops(instruction: &Instruction) -> u32 {
+    match *instruction {
+        Instruction::Int(_)
+        | Instruction::Char(_)
+        | Instruction::Bool(_)
+        | Instruction::Unit
+        | Instruction::Null
+        | Instruction::Constant(_)
+        | Instruction::Function(_)
+        | Instruction::Load(_)
+        | Instruction::LoadCell(_)
+        | Instruction::Jump(_) => 0,
+        Instruction::Store(_)
+        | Instruction::StoreCell(_)
+        | Instruction::MakeCell
+        | Instruction::Pop
+        | Instruction::NegateInt
+        | Instruction::NegateFloat
+        | Instruction::BitNot
+        | Instruction::Not
+        | Instruction::RangeStart
+        | Instruction::Payload
+        | Instruction::JumpIfFalse(_)
+        | Instruction::Return => 1,
+        Instruction::AddInt
+        | Instruction::SubtractInt
+        | Instruction::MultiplyInt
+        | Instruction::DivideInt
+        | Instruction::RemainderInt
+        | Instruction::AddFloat
+        | Instruction::SubtractFloat
+        | Instruction::MultiplyFloat
+        | Instruction::DivideFloat
+        | Instruction::RemainderFloat
+        | Instruction::BitAnd
+        | Instruction::BitOr
+        | Instruction::BitXor
+        | Instruction::ShiftLeft
+        | Instruction::ShiftRight
+        | Instruction::Equal
+        | Instruction::NotEqual
+        | Instruction::Less
+        | Instruction::LessEqual
+        | Instruction::Greater
+        | Instruction::GreaterEqual
+        | Instruction::Range { .. }
+        | Instruction::RangeContains => 2,
+        Instruction::Closure { captures, .. } => captures,
+        Instruction::Call { argc, .. } | Instruction::CallBuiltin { argc, .. } => argc,
+        Instruction::CallValue { argc } => argc + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::bytecode::{self, Builtin};
+    use crate::front_end;
+    use std::path::PathBuf;
+
+    fn compile_source(source: &str) -> (Bytecode, Vec<SourceFile>) {
+        let analysis = front_end::analyze(source).unwrap();
+        let files = vec![SourceFile {
+            path: PathBuf::from("main.crw"),
+            module: None,
+            source: source.to_string(),
+        }];
+        (bytecode::compile(&analysis.program), files)
+    }
+
+    fn reason(result: Result<(), BytecodeError>) -> String {
+        match result {
+            Err(BytecodeError::Unverifiable { reason, .. }) => reason,
+            result => panic!("expected a verification error, found {:?}", result),
+        }
+    }
+
+    const PROGRAM: &str = r#"
+        func half(x: Int) -> Result[Int, String] {
+            if x % 2 != 0 { return Err("odd"); }
+            return Ok(x / 2);
+        }
+        func main() {
+            var n = 0;
+            const bump = func() { n += 1; };
+            while n < 10 {
+                const x = 1 + { if n == 7 { break; } n };
+                bump();
+                println(half(x) ?? -1);
+            }
+            for i in 0..3 { println(i); }
+        }
+    "#;
+
+    #[test]
+    fn test_compiled_programs_verify() {
+        let (bytecode, files) = compile_source(PROGRAM);
+        assert_eq!(verify(&bytecode, &files), Ok(()));
+    }
+
+    #[test]
+    fn test_bad_indices_are_rejected() {
+        let (bytecode, files) = compile_source(PROGRAM);
+        let main = bytecode.entry as usize;
+
+        let mut bad = bytecode.clone();
+        bad.functions[main].instructions[0] = Instruction::Constant(99);
+        assert_eq!(reason(verify(&bad, &files)), "constant 99 does not exist");
+
+        let mut bad = bytecode.clone();
+        bad.functions[main]
+            .instructions
+            .insert(0, Instruction::Load(99));
+        assert_eq!(reason(verify(&bad, &files)), "local slot 99 does not exist");
+
+        let mut bad = bytecode.clone();
+        bad.functions[main]
+            .instructions
+            .insert(0, Instruction::Jump(999));
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "jump target 999 is out of bounds"
+        );
+
+        let mut bad = bytecode.clone();
+        bad.functions[main].instructions.insert(
+            0,
+            Instruction::CallBuiltin {
+                builtin: Builtin::Panic,
+                argc: 2,
+            },
+        );
+        assert_eq!(reason(verify(&bad, &files)), "wrong number of arguments");
+
+        let mut bad = bytecode.clone();
+        bad.entry = 0;
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "the entry function must not take arguments"
+        );
+
+        assert_eq!(
+            reason(verify(&bytecode, &[])),
+            "source file 0 does not exist"
+        );
+    }
+
+    #[test]
+    fn test_stack_misuse_is_rejected() {
+        let (bytecode, files) = compile_source(PROGRAM);
+        let main = bytecode.entry as usize;
+
+        let mut bad = bytecode.clone();
+        bad.functions[main].instructions.insert(0, Instruction::Pop);
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "too few operands on the stack"
+        );
+
+        let mut bad = bytecode.clone();
+        let last = bad.functions[main].instructions.len() - 1;
+        bad.functions[main].instructions[last] = Instruction::Unit;
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "execution runs past the end of the function"
+        );
+
+        let mut bad = bytecode.clone();
+        bad.functions[main].locals = u32::MAX;
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "the function declares too many local slots"
+        );
+
+        let mut bad = bytecode.clone();
+        bad.functions[main].max_stack = MAX_SLOTS + 1;
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "the function declares too large a stack"
+        );
+
+        let mut bad = bytecode.clone();
+        bad.functions[main].max_stack = 1;
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "the stack grows beyond its declared size"
+        );
+
+        // A loop that pushes a value on every iteration
+        let mut bad = bytecode.clone();
+        bad.functions[main].instructions = vec![Instruction::Int(1), Instruction::Jump(0)];
+        bad.functions[main].lines.clear();
+        assert_eq!(
+            reason(verify(&bad, &files)),
+            "reached with 0 and 1 operands"
+        );
+    }
+
+    #[test]
+    fn test_operands_of_the_wrong_kind_are_rejected() {
+        let (bytecode, files) = compile_source(PROGRAM);
+        let main = bytecode.entry as usize;
+        let with_instructions = |instructions: Vec<Instruction>| {
+            let mut bad = bytecode.clone();
+            let code = &mut bad.functions[main];
+            code.instructions = instructions;
+            code.instructions
+                .extend([Instruction::Unit, Instruction::Return]);
+            code.lines.clear();
+            verify(&bad, &files)
+        };
+
+        assert_eq!(
+            reason(with_instructions(vec![
+                Instruction::Bool(true),
+                Instruction::Int(1),
+                Instruction::AddInt,
+                Instruction::Pop,
+            ])),
+            "expected an `Int` operand, found a `Bool`"
+        );
+        assert_eq!(
+            reason(with_instructions(vec![
+                Instruction::LoadCell(0),
+                Instruction::Pop
+            ])),
+            "expected a heap cell in local slot 0, found `()`"
+        );
+        assert_eq!(
+            reason(with_instructions(vec![
+                Instruction::Int(1),
+                Instruction::Int(2),
+                Instruction::CallBuiltin {
+                    builtin: Builtin::Concat,
+                    argc: 2,
+                },
+                Instruction::Pop,
+            ])),
+            "expected a string argument to `concat`, found an `Int`"
+        );
+        assert_eq!(
+            reason(with_instructions(vec![
+                Instruction::Null,
+                Instruction::Null,
+                Instruction::Less,
+                Instruction::Pop,
+            ])),
+            "cannot order `null`"
+        );
+
+        // A slot holding an `Int` on one path into a jump target and a `Bool` on the other can hold
+        // either there, which the virtual machine checks when it runs
+        let branches = |other: Instruction| {
+            with_instructions(vec![
+                Instruction::Int(1),
+                Instruction::Store(0),
+                Instruction::Bool(true),
+                Instruction::JumpIfFalse(6),
+                other,
+                Instruction::Store(0),
+                Instruction::Load(0),
+                Instruction::Not,
+                Instruction::Pop,
+            ])
+        };
+        assert_eq!(branches(Instruction::Bool(false)), Ok(()));
+        assert_eq!(
+            reason(branches(Instruction::Int(2))),
+            "expected a `Bool` operand, found an `Int`"
+        );
+    }
+}
@@ -0,0 +1,689 @@
+//! C backend, which lowers the IR of a program to portable C99 and builds it with the system C
+//! compiler, together with a small runtime (`c/crawfish.c`) for printing, panics, strings and the
+//! garbage collected heap. Every value is a tagged `cw_value`, so that optionals, results and
+//! closures share one representation. Heap cells are `cw_value *`, and blocks are labels that
+//! jumps `goto`. Every function pushes a frame with the addresses of its variables that may
+//! point into the heap, which are the roots the collector starts from.
+use crate::back_end;
+use crate::back_end::ir::{
+    self, Block, Builtin, Constant, Function, InstructionKind, Program, Terminator, Value,
+    ValueType,
+};
+use crate::front_end::ast::{BinaryOp, UnaryOp};
+use crate::front_end::modules::SourceFile;
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+pub const RUNTIME_HEADER: &str = include_str!("c/crawfish.h");
+pub const RUNTIME_SOURCE: &str = include_str!("c/crawfish.c");
+
+/// Generates the C source of a program compiled from `files`
+pub fn generate(program: &Program, files: &[SourceFile]) -> String {
+    let mut generator = Generator {
+        files,
+        sites: Vec::new(),
+        site_indices: HashMap::new(),
+        strings: Vec::new(),
+        string_indices: HashMap::new(),
+    };
+    let bodies: Vec<String> = program
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(index, function)| FunctionGenerator::new(&mut generator, function).generate(index))
+        .collect();
+
+    let mut out = String::from("/* Generated by the Crawfish C backend */\n");
+    out.push_str("#include \"crawfish.h\"\n#include <math.h>\n\n");
+    if !generator.sites.is_empty() {
+        out.push_str("static const cw_site cw_sites[] = {\n");
+        for (path, line, column) in &generator.sites {
+            writeln!(
+                out,
+                "    {{{}, {}, {}}},",
+                c_string(path.as_bytes()),
+                line,
+                column
+            )
+            .unwrap();
+        }
+        out.push_str("};\n\n");
+    }
+    if !generator.strings.is_empty() {
+        out.push_str("static const cw_string cw_strings[] = {\n");
+        for string in &generator.strings {
+            let bytes = string.as_bytes();
+            writeln!(
+                out,
+                "    {{CW_STATIC_OBJECT(CW_OBJECT_STRING), {}, {}}},",
+                bytes.len(),
+                c_string(bytes)
+            )
+            .unwrap();
+        }
+        out.push_str("};\n\n");
+    }
+    for (index, function) in program.functions.iter().enumerate() {
+        writeln!(out, "{};", signature(index, function)).unwrap();
+        if function.captures.is_empty() && !function.name.contains('$') {
+            writeln!(
+                out,
+                "static cw_closure cw_function{} = {{CW_STATIC_OBJECT(CW_OBJECT_CLOSURE), (cw_fn)f{}, 0}};",
+                index, index
+            )
+            .unwrap();
+        }
+    }
+    for body in bodies {
+        out.push('\n');
+        out.push_str(&body);
+    }
+    writeln!(
+        out,
+        "\nint main(void) {{\n    cw_init();\n    f{}(NULL);\n    return cw_finish();\n}}",
+        program.entry
+    )
+    .unwrap();
+    out
+}
+
+/// Builds an executable at `output` from generated C source, using `compiler` (e.g. `cc`, or a
+/// command with arguments such as `gcc -m64`)
+pub fn compile(source: &str, output: &Path, compiler: &str) -> Result<(), Box<dyn Error>> {
+    back_end::in_temporary_directory(output, |directory| {
+        let main = directory.join("main.c");
+        fs::write(&main, source)?;
+        back_end::link(directory, &[main], output, compiler)
+    })
+}
+
+/// A C string literal of `bytes`, escaping everything but printable ASCII
+fn c_string(bytes: &[u8]) -> String {
+    let mut literal = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' | b'?' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => write!(literal, "\\{:03o}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+fn signature(index: usize, function: &Function) -> String {
+    let mut signature = format!("static cw_value f{}(cw_closure *env", index);
+    for n in 0..function.params.len() {
+        write!(signature, ", cw_value a{}", n).unwrap();
+    }
+    signature.push(')');
+    signature
+}
+
+/// A C expression for a float constant, which may not be finite
+fn c_float(value: f64) -> String {
+    match value {
+        _ if value.is_nan() => "cw_float(NAN)".to_string(),
+        f64::INFINITY => "cw_float(INFINITY)".to_string(),
+        f64::NEG_INFINITY => "cw_float(-INFINITY)".to_string(),
+        _ => format!("cw_float({:?})", value),
+    }
+}
+
+/// The function of the C math library that computes a math built-in on floats, other than `min()`
+/// and `max()`
+pub fn libm_function(builtin: Builtin) -> &'static str {
+    match builtin {
+        Builtin::Abs => "fabs",
+        Builtin::Pow => "pow",
+        Builtin::Sqrt => "sqrt",
+        Builtin::Ceil => "ceil",
+        Builtin::Floor => "floor",
+        Builtin::Sin => "sin",
+        Builtin::Cos => "cos",
+        Builtin::Log => "log10",
+        Builtin::Ln => "log",
+        Builtin::PowE => "exp",
+        _ => unreachable!("`{}()` is not in the C math library", builtin.name()),
+    }
+}
+
+/// The C variable holding an IR value
+fn var(value: Value) -> String {
+    format!("v{}", value.0)
+}
+
+/// Program-wide state of the generator
+/// - `sites` lists the path, line and column of every operation that can panic
+/// - `strings` lists every string constant
+struct Generator<'p> {
+    files: &'p [SourceFile],
+    sites: Vec<(String, usize, usize)>,
+    site_indices: HashMap<(usize, Span), usize>,
+    strings: Vec<String>,
+    string_indices: HashMap<String, usize>,
+}
+
+/// Generates one function, with a C variable for every IR value and a label for every block.
+/// Heap cells are held as `cw_value *`, and phis are assigned before jumping to their block.
+/// The variables that may point into the heap are roots, in a frame pushed on entry when
+/// `rooted` and popped on return.
+struct FunctionGenerator<'g, 'p> {
+    generator: &'g mut Generator<'p>,
+    function: &'p Function,
+    out: String,
+    rooted: bool,
+}
+
+impl<'g, 'p> FunctionGenerator<'g, 'p> {
+    fn new(generator: &'g mut Generator<'p>, function: &'p Function) -> Self {
+        Self {
+            generator,
+            function,
+            out: String::new(),
+            rooted: false,
+        }
+    }
+
+    fn generate(mut self, index: usize) -> String {
+        let function = self.function;
+        let (mut values, mut cells) = (Vec::new(), Vec::new());
+        for (n, ty) in function.values.iter().enumerate() {
+            let value = Value(n as u32);
+            let param = function.params.iter().position(|&param| param == value);
+            // Roots start out holding nothing, since the collector reads them all
+            let declaration = match (ty, param) {
+                (_, Some(n)) => format!("cw_value {} = a{};", var(value), n),
+                (ValueType::Cell(_), None) => format!("cw_value *{} = NULL;", var(value)),
+                (_, None) if ty.is_traced() => format!("cw_value {} = cw_unit();", var(value)),
+                (ValueType::Value(_), None) => format!("cw_value {};", var(value)),
+            };
+            self.line(declaration);
+            match ty {
+                ValueType::Cell(_) => cells.push(format!("&{}", var(value))),
+                _ if ty.is_traced() => values.push(format!("&{}", var(value))),
+                _ => {}
+            }
+        }
+        self.rooted = !values.is_empty() || !cells.is_empty();
+        if self.rooted {
+            let mut roots = |name: &str, ty: &str, addresses: &[String]| {
+                if addresses.is_empty() {
+                    return "NULL".to_string();
+                }
+                self.line(format!(
+                    "{}const {}[] = {{{}}};",
+                    ty,
+                    name,
+                    addresses.join(", ")
+                ));
+                name.to_string()
+            };
+            let value_roots = roots("roots", "cw_value *", &values);
+            let cell_roots = roots("cells", "cw_value **", &cells);
+            self.line(format!(
+                "cw_frame frame = {{cw_frames, {}, {}, {}, {}}};",
+                values.len(),
+                cells.len(),
+                value_roots,
+                cell_roots
+            ));
+            self.line("cw_frames = &frame;");
+        }
+        for block in function.block_ids() {
+            if block != Block(0) {
+                writeln!(self.out, "{}:;", block).unwrap();
+            }
+            for instruction in &function.block(block).instructions {
+                self.instruction(instruction);
+            }
+            self.terminator(block);
+        }
+
+        format!(
+            "/* {} */\n{} {{\n{}}}\n",
+            function.name,
+            signature(index, function),
+            self.out
+        )
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    /// The address of the site entry for an operation at `span`
+    fn site(&mut self, span: Span) -> String {
+        let file = self.function.file;
+        let generator = &mut *self.generator;
+        let next = generator.sites.len();
+        let index = *generator.site_indices.entry((file, span)).or_insert(next);
+        if index == next {
+            let source = &generator.files[file];
+            let (line, column) = span.line_col(&source.source);
+            let path = source.path.display().to_string();
+            generator.sites.push((path, line, column));
+        }
+        format!("&cw_sites[{}]", index)
+    }
+
+    fn string(&mut self, value: &str) -> String {
+        let generator = &mut *self.generator;
+        let next = generator.strings.len();
+        let index = *generator
+            .string_indices
+            .entry(value.to_string())
+            .or_insert(next);
+        if index == next {
+            generator.strings.push(value.to_string());
+        }
+        format!("cw_str(&cw_strings[{}])", index)
+    }
+
+    fn is_cell(&self, value: Value) -> bool {
+        matches!(self.function.value_type(value), ValueType::Cell(_))
+    }
+
+    fn instruction(&mut self, instruction: &'p ir::Instruction) {
+        let span = instruction.span;
+        let value = match &instruction.kind {
+            InstructionKind::Const(constant) => match constant {
+                Constant::Int(value) => format!("cw_int({})", value),
+                Constant::Float(value) => c_float(*value),
+                Constant::Bool(value) => format!("cw_bool({})", value),
+                Constant::Char(value) => format!("cw_char(0x{:X}u)", *value as u32),
+                Constant::String(value) => self.string(value),
+                Constant::Unit => "cw_unit()".to_string(),
+                Constant::Null => "cw_null()".to_string(),
+            },
+            InstructionKind::Function(index) => {
+                format!("cw_closure_value(&cw_function{})", index)
+            }
+            InstructionKind::Closure { function, captures } => {
+                self.line("{");
+                self.line(format!(
+                    "    cw_closure *c = cw_new_closure((cw_fn)f{}, {});",
+                    function,
+                    captures.len()
+                ));
+                for (n, &capture) in captures.iter().enumerate() {
+                    // Variables captured by reference are passed as their heap cell
+                    let value = match self.is_cell(capture) {
+                        true => format!("cw_cell_value({})", var(capture)),
+                        false => var(capture),
+                    };
+                    self.line(format!("    c->captures[{}] = {};", n, value));
+                }
+                self.line(format!(
+                    "    {} = cw_closure_value(c);",
+                    var(instruction.result.unwrap())
+                ));
+                self.line("}");
+                return;
+            }
+            InstructionKind::Capture(slot) => match self.function.captures[*slot as usize] {
+                ValueType::Cell(_) => format!("env->captures[{}].as.boxed", slot),
+                ValueType::Value(_) => format!("env->captures[{}]", slot),
+            },
+            InstructionKind::NewCell(value) => format!("cw_new_cell({})", var(*value)),
+            InstructionKind::LoadCell(cell) => format!("*{}", var(*cell)),
+            InstructionKind::StoreCell { cell, value } => {
+                self.line(format!("*{} = {};", var(*cell), var(*value)));
+                return;
+            }
+            InstructionKind::Unary { op, operand } => {
+                let value = var(*operand);
+                match (op, self.function.type_of(*operand)) {
+                    (UnaryOp::Negate, Type::Float) => format!("cw_float(-{}.as.f)", value),
+                    (UnaryOp::Negate, _) => {
+                        format!("cw_int(cw_neg({}, {}.as.i))", self.site(span), value)
+                    }
+                    (UnaryOp::Not, _) => format!("cw_bool(!{}.as.b)", value),
+                    (UnaryOp::BitNot, _) => format!("cw_int(~{}.as.i)", value),
+                }
+            }
+            InstructionKind::Binary { op, left, right } => {
+                let ty = self.function.type_of(*left).clone();
+                self.binary(*op, &ty, &var(*left), &var(*right), span)
+            }
+            InstructionKind::Range {
+                start,
+                end,
+                inclusive,
+            } => format!(
+                "cw_range({}.as.i, {}.as.i, {})",
+                var(*start),
+                var(*end),
+                inclusive
+            ),
+            InstructionKind::RangeStart(range) => format!("cw_int({}.as.range.start)", var(*range)),
+            InstructionKind::RangeContains { range, item } => format!(
+                "cw_bool({i}.as.i < {r}.as.range.end || ({r}.as.range.inclusive && {i}.as.i == {r}.as.range.end))",
+                i = var(*item),
+                r = var(*range)
+            ),
+            InstructionKind::IsNull(value) => format!("cw_bool({}.tag == CW_NULL)", var(*value)),
+            InstructionKind::Payload(value) => format!("*{}.as.boxed", var(*value)),
+            InstructionKind::Call { function, args } => {
+                let mut call = format!("f{}(NULL", function);
+                for &arg in args {
+                    write!(call, ", {}", var(arg)).unwrap();
+                }
+                call.push(')');
+                return self.counted_call(instruction.result, &call, span);
+            }
+            InstructionKind::CallValue { callee, args } => {
+                let callee = var(*callee);
+                let mut cast = String::from("cw_value (*)(cw_closure *");
+                let mut call = format!("{}.as.closure", callee);
+                for &arg in args {
+                    cast.push_str(", cw_value");
+                    write!(call, ", {}", var(arg)).unwrap();
+                }
+                cast.push(')');
+                let call = format!("(({}){}.as.closure->fn)({})", cast, callee, call);
+                return self.counted_call(instruction.result, &call, span);
+            }
+            InstructionKind::CallBuiltin { builtin, args } if builtin.is_math() => {
+                let float = *self.function.type_of(args[0]) == Type::Float;
+                let args: Vec<String> = args.iter().map(|&arg| var(arg)).collect();
+                self.math(*builtin, float, &args, span)
+            }
+            InstructionKind::CallBuiltin { builtin, args } => {
+                let args: Vec<String> = args.iter().map(|&arg| var(arg)).collect();
+                self.builtin(*builtin, &args, span)
+            }
+        };
+        let result = instruction.result.expect("only stores have no result");
+        self.line(format!("{} = {};", var(result), value));
+    }
+
+    /// Calls a compiled function, counting the call towards the maximum call depth
+    fn counted_call(&mut self, result: Option<Value>, call: &str, span: Span) {
+        let site = self.site(span);
+        self.line(format!("cw_enter({});", site));
+        let result = result.expect("calls have a result");
+        self.line(format!("{} = {};", var(result), call));
+        self.line("cw_depth--;");
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[String], span: Span) -> String {
+        match (builtin, args) {
+            (Builtin::Println, []) => "cw_println_empty()".to_string(),
+            (Builtin::Println, [value]) => format!("cw_println({})", value),
+            (Builtin::Print, [value]) => format!("cw_print({})", value),
+            (Builtin::PrintPadded, [value, width, precision, alignment]) => format!(
+                "cw_print_padded({}, {}, {}, {})",
+                value, width, precision, alignment
+            ),
+            (Builtin::Input, []) => "cw_input()".to_string(),
+            (Builtin::ToString, [value]) => format!("cw_to_string({})", value),
+            (Builtin::Concat, [a, b]) => format!("cw_concat({}, {})", a, b),
+            (Builtin::Int, [value]) => format!("cw_to_int({}, {})", self.site(span), value),
+            (Builtin::Float, [value]) => format!("cw_to_float({})", value),
+            (Builtin::Char, [value]) => format!("cw_to_char({}, {})", self.site(span), value),
+            (Builtin::Panic, [message]) => {
+                format!("cw_panic_with({}, {})", self.site(span), message)
+            }
+            (Builtin::Ok, []) => "cw_ok(cw_unit())".to_string(),
+            (Builtin::Ok, [value]) => format!("cw_ok({})", value),
+            (Builtin::Err, [error]) => format!("cw_err({})", error),
+            (Builtin::Unwrap, [result]) => format!("cw_unwrap({}, {})", self.site(span), result),
+            (Builtin::UnwrapErr, [result]) => {
+                format!("cw_unwrap_err({}, {})", self.site(span), result)
+            }
+            (Builtin::IsOk, [result]) => format!("cw_bool({}.tag == CW_OK)", result),
+            (Builtin::IsErr, [result]) => format!("cw_bool({}.tag == CW_ERR)", result),
+            (Builtin::Open, [path, mode]) => format!("cw_open({}, {})", path, mode),
+            (Builtin::Write, [file, text]) => format!("cw_write_file({}, {})", file, text),
+            (Builtin::CharAt | Builtin::CharWidth, [string, offset]) => {
+                format!("cw_{}({}, {})", builtin.name(), string, offset)
+            }
+            (Builtin::Close | Builtin::Read | Builtin::ReadLine | Builtin::NextLine, [file]) => {
+                format!("cw_{}({})", builtin.name(), file)
+            }
+            _ => unreachable!("the verifier checks the arguments of built-in functions"),
+        }
+    }
+
+    /// Calls a math built-in on floats if `float` is true, and otherwise on integers
+    fn math(&mut self, builtin: Builtin, float: bool, args: &[String], span: Span) -> String {
+        match (builtin, float, args) {
+            (Builtin::Min | Builtin::Max, _, [a, b]) => {
+                let (ty, field) = if float { ("float", "f") } else { ("int", "i") };
+                format!(
+                    "cw_{ty}(cw_{}_{ty}({a}.as.{field}, {b}.as.{field}))",
+                    builtin.name()
+                )
+            }
+            (Builtin::Abs, false, [a]) => {
+                format!("cw_int(cw_abs_int({}, {}.as.i))", self.site(span), a)
+            }
+            (Builtin::Pow, false, [a, b]) => {
+                format!(
+                    "cw_int(cw_pow_int({}, {}.as.i, {}.as.i))",
+                    self.site(span),
+                    a,
+                    b
+                )
+            }
+            (_, true, [a]) => format!("cw_float({}({}.as.f))", libm_function(builtin), a),
+            (_, true, [a, b]) => {
+                format!(
+                    "cw_float({}({}.as.f, {}.as.f))",
+                    libm_function(builtin),
+                    a,
+                    b
+                )
+            }
+            _ => unreachable!("the type checker validates calls to math built-ins"),
+        }
+    }
+
+    /// Assigns the phis of `to` the values they take when coming from `from`, all at once
+    fn phi_moves(&mut self, from: Block, to: Block, indent: &str) {
+        let moves: Vec<(Value, Value)> = self
+            .function
+            .block(to)
+            .phis
+            .iter()
+            .filter_map(|phi| {
+                let (_, value) = phi.incoming.iter().find(|(block, _)| *block == from)?;
+                (*value != phi.result).then_some((phi.result, *value))
+            })
+            .collect();
+        if let [(phi, value)] = moves[..] {
+            return self.line(format!("{}{} = {};", indent, var(phi), var(value)));
+        }
+        if moves.is_empty() {
+            return;
+        }
+        // Temporaries keep the moves from overwriting each other's sources
+        self.line(format!("{}{{", indent));
+        for (n, (_, value)) in moves.iter().enumerate() {
+            self.line(format!("{}    cw_value p{} = {};", indent, n, var(*value)));
+        }
+        for (n, (phi, _)) in moves.iter().enumerate() {
+            self.line(format!("{}    {} = p{};", indent, var(*phi), n));
+        }
+        self.line(format!("{}}}", indent));
+    }
+
+    /// Ends a block, falling through to the next one rather than jumping to it
+    fn terminator(&mut self, block: Block) {
+        let next = Block(block.0 + 1);
+        match self.function.block(block).terminator {
+            Terminator::Jump(target) => {
+                self.phi_moves(block, target, "");
+                if target != next {
+                    self.line(format!("goto {};", target));
+                }
+            }
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.line(format!("if ({}.as.b) {{", var(condition)));
+                self.phi_moves(block, then, "    ");
+                self.line(format!("    goto {};", then));
+                self.line("}");
+                self.phi_moves(block, otherwise, "");
+                if otherwise != next {
+                    self.line(format!("goto {};", otherwise));
+                }
+            }
+            Terminator::Return(value) => {
+                if self.rooted {
+                    self.line("cw_frames = frame.parent;");
+                }
+                self.line(format!("return {};", var(value)));
+            }
+            // The block ends in a call that never returns
+            Terminator::Unreachable => self.line("return cw_unit();"),
+        }
+    }
+
+    /// A C expression applying `op` to two operands of type `ty`
+    fn binary(&mut self, op: BinaryOp, ty: &Type, a: &str, b: &str, span: Span) -> String {
+        let field = match ty {
+            Type::Float => "f",
+            Type::Char => "c",
+            _ => "i",
+        };
+        let checked = |this: &mut Self, function: &str| {
+            format!(
+                "cw_int({}({}, {}.as.i, {}.as.i))",
+                function,
+                this.site(span),
+                a,
+                b
+            )
+        };
+        match (op, field) {
+            (BinaryOp::Add, "f") => format!("cw_float({}.as.f + {}.as.f)", a, b),
+            (BinaryOp::Subtract, "f") => format!("cw_float({}.as.f - {}.as.f)", a, b),
+            (BinaryOp::Multiply, "f") => format!("cw_float({}.as.f * {}.as.f)", a, b),
+            (BinaryOp::Divide, "f") => format!("cw_float({}.as.f / {}.as.f)", a, b),
+            (BinaryOp::Remainder, "f") => format!("cw_float(fmod({}.as.f, {}.as.f))", a, b),
+            (BinaryOp::Add, _) => checked(self, "cw_add"),
+            (BinaryOp::Subtract, _) => checked(self, "cw_sub"),
+            (BinaryOp::Multiply, _) => checked(self, "cw_mul"),
+            (BinaryOp::Divide, _) => checked(self, "cw_div"),
+            (BinaryOp::Remainder, _) => checked(self, "cw_rem"),
+            (BinaryOp::ShiftLeft, _) => checked(self, "cw_shl"),
+            (BinaryOp::ShiftRight, _) => checked(self, "cw_shr"),
+            (BinaryOp::BitAnd, _) => format!("cw_int({}.as.i & {}.as.i)", a, b),
+            (BinaryOp::BitOr, _) => format!("cw_int({}.as.i | {}.as.i)", a, b),
+            (BinaryOp::BitXor, _) => format!("cw_int({}.as.i ^ {}.as.i)", a, b),
+            // Integers and characters compare by payload, without the generic runtime call
+            (BinaryOp::Equal, _) if matches!(ty, Type::Int | Type::Char) => {
+                format!("cw_bool({a}.as.{f} == {b}.as.{f})", f = field)
+            }
+            (BinaryOp::NotEqual, _) if matches!(ty, Type::Int | Type::Char) => {
+                format!("cw_bool({a}.as.{f} != {b}.as.{f})", f = field)
+            }
+            (BinaryOp::Equal, _) => format!("cw_bool(cw_equal({}, {}))", a, b),
+            (BinaryOp::NotEqual, _) => format!("cw_bool(!cw_equal({}, {}))", a, b),
+            (BinaryOp::Less, field) => format!("cw_bool({a}.as.{f} < {b}.as.{f})", f = field),
+            (BinaryOp::LessEqual, field) => {
+                format!("cw_bool({a}.as.{f} <= {b}.as.{f})", f = field)
+            }
+            (BinaryOp::Greater, field) => format!("cw_bool({a}.as.{f} > {b}.as.{f})", f = field),
+            (BinaryOp::GreaterEqual, field) => {
+                format!("cw_bool({a}.as.{f} >= {b}.as.{f})", f = field)
+            }
+            (BinaryOp::And | BinaryOp::Or | BinaryOp::Coalesce, _) => {
+                unreachable!("short-circuiting operators are lowered to branches")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end;
+    use crate::testing::{
+        assert_outputs_match_interpreter, executable_path, run_with_input, source_files, FILES,
+        PROGRAMS,
+    };
+    use std::process::Command;
+
+    fn generate_source(source: &str) -> String {
+        let analysis = front_end::analyze(source).unwrap();
+        generate(&ir::build(&analysis.program), &source_files(source))
+    }
+
+    /// Checks that the executable built from `source` prints and panics like the interpreter
+    fn assert_matches_interpreter(source: &str, name: &str) {
+        let executable = executable_path(name);
+        compile(&generate_source(source), &executable, "cc").unwrap();
+        // Collecting on every allocation catches the roots that generated code fails to declare
+        let outputs: Vec<_> = ["0", "1"]
+            .iter()
+            .map(|stress| {
+                run_with_input(Command::new(&executable).env("CRAWFISH_GC_STRESS", stress))
+            })
+            .collect();
+        fs::remove_file(&executable).unwrap();
+        assert_outputs_match_interpreter(source, &outputs);
+    }
+
+    #[test]
+    fn test_c_strings_are_escaped() {
+        assert_eq!(c_string(b"a\"b\\c?"), r#""a\"b\\c\?""#);
+        assert_eq!(c_string("é\n".as_bytes()), r#""\303\251\012""#);
+    }
+
+    #[test]
+    fn test_generated_program_structure() {
+        let c = generate_source(
+            r#"func add(a: Int, b: Int) -> Int { return a + b; } func main() { println(add(1, 2)); println("hi"); println("hi"); }"#,
+        );
+        assert!(c.contains("static cw_value f0(cw_closure *env, cw_value a0, cw_value a1);"));
+        assert!(c.contains("cw_add(&cw_sites[0], "));
+        assert!(c.contains("static const cw_site cw_sites[] = {\n    {\"main.crw\", 1, 42},"));
+        assert!(c.contains(
+            "static const cw_string cw_strings[] = {\n    {CW_STATIC_OBJECT(CW_OBJECT_STRING), 2, \"hi\"},\n};"
+        ));
+        assert!(c.contains("int main(void) {\n    cw_init();\n    f1(NULL);"));
+    }
+
+    #[test]
+    fn test_heap_values_are_roots() {
+        let c = generate_source(
+            r#"func main() { var n = 0; const next = func() -> Int { n += 1; return n; }; println(next()); }"#,
+        );
+        assert!(c.contains("    cw_value *v1 = NULL;\n"));
+        assert!(c.contains("    cw_value v2 = cw_unit();\n"));
+        assert!(c.contains(
+            "    cw_value *const roots[] = {&v2};\n    cw_value **const cells[] = {&v1};\n    cw_frame frame = {cw_frames, 1, 1, roots, cells};\n    cw_frames = &frame;\n"
+        ));
+        assert!(c.contains("    cw_frames = frame.parent;\n    return "));
+    }
+
+    #[test]
+    fn test_programs_match_interpreter() {
+        if Command::new("cc").arg("--version").output().is_err() {
+            eprintln!("skipping: no C compiler");
+            return;
+        }
+        let path = std::env::temp_dir().join(format!("crawfish-c-{}", std::process::id()));
+        let files = FILES.replace("PATH", path.to_str().unwrap());
+        for (n, program) in PROGRAMS.iter().copied().chain([files.as_str()]).enumerate() {
+            assert_matches_interpreter(program, &n.to_string());
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
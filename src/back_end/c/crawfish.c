@@ -0,0 +1,908 @@
+/* Runtime of the programs compiled by the Crawfish C backend */
+/* `getline` and `open_memstream` are POSIX */
+#define _POSIX_C_SOURCE 200809L
+
+#include "crawfish.h"
+
+#include <errno.h>
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Number of calls in progress, counting `main()` */
+uint32_t cw_depth = 1;
+
+/* The innermost call in progress that has roots */
+cw_frame *cw_frames = NULL;
+
+/* Bytes the heap may grow to before it is first collected */
+#define CW_MIN_HEAP (1u << 20)
+
+/*
+ * The heap: every object allocated, the bytes they take, and how large they may grow before the
+ * next collection. In stress mode, set by `CRAWFISH_GC_STRESS`, every allocation collects first, so
+ * that a missing root shows up as soon as possible.
+ */
+static cw_object *cw_objects = NULL;
+static size_t cw_allocated = 0;
+static size_t cw_threshold = CW_MIN_HEAP;
+static bool cw_stress = false;
+
+/* Marked objects whose contents are still to be marked */
+static cw_object **cw_gray = NULL;
+static size_t cw_gray_count = 0;
+static size_t cw_gray_capacity = 0;
+
+/* A heap cell, which `cw_value *` pointers point to the value of */
+typedef struct cw_cell {
+    cw_object object;
+    cw_value value;
+} cw_cell;
+
+/*
+ * A file of `open()`, whose stream is NULL once it is closed. Streams that are written to are not
+ * buffered, so that the file holds what was written even if the program panics.
+ */
+struct cw_file {
+    cw_object object;
+    FILE *stream;
+    bool writing;
+};
+
+static char cw_stdout_buffer[1 << 16];
+
+void cw_init(void) {
+    setvbuf(stdout, cw_stdout_buffer, _IOFBF, sizeof cw_stdout_buffer);
+    const char *stress = getenv("CRAWFISH_GC_STRESS");
+    cw_stress = stress != NULL && *stress != '\0' && strcmp(stress, "0") != 0;
+}
+
+int cw_finish(void) {
+    if (fflush(stdout) != 0) {
+        fprintf(stderr, "panicked: failed printing to stdout\n");
+        return 101;
+    }
+    return 0;
+}
+
+CW_NORETURN static void cw_out_of_memory(void) {
+    fflush(stdout);
+    fprintf(stderr, "panicked: out of memory\n");
+    exit(101);
+}
+
+static cw_object *cw_cell_object(cw_value *value) {
+    return &((cw_cell *)((char *)value - offsetof(cw_cell, value)))->object;
+}
+
+static void cw_mark_object(cw_object *object) {
+    if (object->marked) return;
+    object->marked = 1;
+    if (cw_gray_count == cw_gray_capacity) {
+        size_t capacity = cw_gray_capacity == 0 ? 256 : cw_gray_capacity * 2;
+        cw_object **gray = realloc(cw_gray, capacity * sizeof *gray);
+        if (gray == NULL) cw_out_of_memory();
+        cw_gray = gray;
+        cw_gray_capacity = capacity;
+    }
+    cw_gray[cw_gray_count++] = object;
+}
+
+static void cw_mark_value(cw_value v) {
+    switch (v.tag) {
+    case CW_STRING:
+        cw_mark_object((cw_object *)&v.as.s->object);
+        break;
+    case CW_OK:
+    case CW_ERR:
+    case CW_CELL:
+        cw_mark_object(cw_cell_object(v.as.boxed));
+        break;
+    case CW_CLOSURE:
+        cw_mark_object(&v.as.closure->object);
+        break;
+    case CW_FILE:
+        cw_mark_object(&v.as.file->object);
+        break;
+    }
+}
+
+/* Marks the objects that a marked object points to */
+static void cw_trace(cw_object *object) {
+    switch (object->kind) {
+    case CW_OBJECT_CELL:
+        cw_mark_value(((cw_cell *)object)->value);
+        break;
+    case CW_OBJECT_CLOSURE: {
+        cw_closure *closure = (cw_closure *)object;
+        for (uint32_t i = 0; i < closure->count; i++) cw_mark_value(closure->captures[i]);
+        break;
+    }
+    case CW_OBJECT_STRING:
+    case CW_OBJECT_FILE:
+        break;
+    }
+}
+
+/* Frees every object that the roots of the calls in progress cannot reach */
+static void cw_collect(void) {
+    for (cw_frame *frame = cw_frames; frame != NULL; frame = frame->parent) {
+        for (uint32_t i = 0; i < frame->value_count; i++) cw_mark_value(*frame->values[i]);
+        for (uint32_t i = 0; i < frame->cell_count; i++) {
+            if (*frame->cells[i] != NULL) cw_mark_object(cw_cell_object(*frame->cells[i]));
+        }
+    }
+    while (cw_gray_count > 0) cw_trace(cw_gray[--cw_gray_count]);
+
+    cw_object **link = &cw_objects;
+    while (*link != NULL) {
+        cw_object *object = *link;
+        if (object->marked) {
+            object->marked = 0;
+            link = &object->next;
+        } else {
+            *link = object->next;
+            cw_allocated -= object->size;
+            /* A file that can no longer be used is closed, as if the program had closed it */
+            if (object->kind == CW_OBJECT_FILE && ((cw_file *)object)->stream != NULL) {
+                fclose(((cw_file *)object)->stream);
+            }
+            /* Objects freed in stress mode are overwritten, for missing roots to fail loudly */
+            if (cw_stress) memset(object, 0xAB, object->size);
+            free(object);
+        }
+    }
+    cw_threshold = cw_allocated * 2 > CW_MIN_HEAP ? cw_allocated * 2 : CW_MIN_HEAP;
+}
+
+/* Allocates an object of `size` bytes, header included, collecting first if the heap is full */
+static cw_object *cw_alloc(uint8_t kind, size_t size) {
+    if (cw_stress || cw_allocated + size > cw_threshold) cw_collect();
+    cw_object *object = malloc(size);
+    if (object == NULL) cw_out_of_memory();
+    object->next = cw_objects;
+    object->size = (uint32_t)size;
+    object->kind = kind;
+    object->marked = 0;
+    cw_objects = object;
+    cw_allocated += size;
+    return object;
+}
+
+static void cw_write_utf8(FILE *out, uint32_t c) {
+    if (c < 0x80) {
+        fputc((int)c, out);
+    } else if (c < 0x800) {
+        fputc((int)(0xC0 | (c >> 6)), out);
+        fputc((int)(0x80 | (c & 0x3F)), out);
+    } else if (c < 0x10000) {
+        fputc((int)(0xE0 | (c >> 12)), out);
+        fputc((int)(0x80 | ((c >> 6) & 0x3F)), out);
+        fputc((int)(0x80 | (c & 0x3F)), out);
+    } else {
+        fputc((int)(0xF0 | (c >> 18)), out);
+        fputc((int)(0x80 | ((c >> 12) & 0x3F)), out);
+        fputc((int)(0x80 | ((c >> 6) & 0x3F)), out);
+        fputc((int)(0x80 | (c & 0x3F)), out);
+    }
+}
+
+/*
+ * Writes a float as the shortest decimal that reads back as the same number, without an exponent.
+ * Whole numbers keep a trailing `.0`, so that floats never print like integers.
+ */
+static void cw_write_float(FILE *out, double f) {
+    if (isnan(f)) {
+        fputs("NaN", out);
+        return;
+    }
+    if (isinf(f)) {
+        fputs(f < 0 ? "-inf" : "inf", out);
+        return;
+    }
+    if (f == floor(f)) {
+        fprintf(out, "%.1f", f);
+        return;
+    }
+
+    char scientific[32];
+    int precision;
+    for (precision = 0; precision < 17; precision++) {
+        snprintf(scientific, sizeof scientific, "%.*e", precision, f);
+        if (strtod(scientific, NULL) == f) break;
+    }
+
+    /* Splits `-d.ddde-x` into its sign, digits and exponent */
+    const char *p = scientific;
+    if (*p == '-') {
+        fputc('-', out);
+        p++;
+    }
+    char digits[32];
+    int count = 0;
+    for (; *p != 'e'; p++) {
+        if (*p != '.') digits[count++] = *p;
+    }
+    int exponent = atoi(p + 1);
+
+    if (exponent < 0) {
+        fputs("0.", out);
+        for (int i = 0; i < -exponent - 1; i++) fputc('0', out);
+        fwrite(digits, 1, (size_t)count, out);
+    } else {
+        for (int i = 0; i <= exponent; i++) fputc(i < count ? digits[i] : '0', out);
+        fputc('.', out);
+        fwrite(digits + exponent + 1, 1, (size_t)(count - exponent - 1), out);
+    }
+}
+
+static void cw_write(FILE *out, cw_value v) {
+    switch (v.tag) {
+    case CW_INT:
+        fprintf(out, "%d", (int)v.as.i);
+        break;
+    case CW_FLOAT:
+        cw_write_float(out, v.as.f);
+        break;
+    case CW_BOOL:
+        fputs(v.as.b ? "true" : "false", out);
+        break;
+    case CW_CHAR:
+        cw_write_utf8(out, v.as.c);
+        break;
+    case CW_UNIT:
+        fputs("()", out);
+        break;
+    case CW_NULL:
+        fputs("null", out);
+        break;
+    case CW_RANGE:
+        fprintf(out, "%d%s%d", (int)v.as.range.start, v.as.range.inclusive ? "..=" : "..",
+                (int)v.as.range.end);
+        break;
+    case CW_STRING:
+        fwrite(v.as.s->bytes, 1, v.as.s->len, out);
+        break;
+    case CW_OK:
+        fputs("Ok(", out);
+        cw_write(out, *v.as.boxed);
+        fputc(')', out);
+        break;
+    case CW_ERR:
+        fputs("Err(", out);
+        cw_write(out, *v.as.boxed);
+        fputc(')', out);
+        break;
+    case CW_CLOSURE:
+        fputs("<closure>", out);
+        break;
+    case CW_CELL:
+        cw_write(out, *v.as.boxed);
+        break;
+    case CW_FILE:
+        fputs("<file>", out);
+        break;
+    }
+}
+
+/* Starts reporting a panic, leaving the message to the caller */
+static void cw_panic_begin(const cw_site *site) {
+    fflush(stdout);
+    fprintf(stderr, "panicked at %s:%u:%u: ", site->path, (unsigned)site->line,
+            (unsigned)site->column);
+}
+
+CW_NORETURN static void cw_panic_end(void) {
+    fputc('\n', stderr);
+    exit(101);
+}
+
+void cw_panic(const cw_site *site, const char *message) {
+    cw_panic_begin(site);
+    fputs(message, stderr);
+    cw_panic_end();
+}
+
+void cw_panic_value(const cw_site *site, const cw_value *message) {
+    cw_panic_begin(site);
+    cw_write(stderr, *message);
+    cw_panic_end();
+}
+
+cw_value cw_panic_with(const cw_site *site, cw_value message) {
+    cw_panic_value(site, &message);
+}
+
+void cw_enter(const cw_site *site) {
+    if (cw_depth == CW_MAX_CALL_DEPTH) cw_panic(site, "stack overflow");
+    cw_depth++;
+}
+
+cw_value *cw_new_cell(cw_value value) {
+    cw_cell *cell = (cw_cell *)cw_alloc(CW_OBJECT_CELL, sizeof *cell);
+    cell->value = value;
+    return &cell->value;
+}
+
+cw_value *cw_box(const cw_value *value) {
+    return cw_new_cell(*value);
+}
+
+cw_closure *cw_new_closure(cw_fn fn, uint32_t count) {
+    size_t size = sizeof(cw_closure) + count * sizeof(cw_value);
+    cw_closure *closure = (cw_closure *)cw_alloc(CW_OBJECT_CLOSURE, size);
+    closure->fn = fn;
+    closure->count = count;
+    /* Captures are filled in after the closure is allocated, by code that allocates nothing */
+    for (uint32_t i = 0; i < count; i++) closure->captures[i] = cw_unit();
+    return closure;
+}
+
+static cw_value cw_wrap(uint8_t tag, cw_value value) {
+    cw_value v;
+    v.tag = tag;
+    v.as.boxed = cw_new_cell(value);
+    return v;
+}
+
+cw_value cw_ok(cw_value value) {
+    return cw_wrap(CW_OK, value);
+}
+
+cw_value cw_err(cw_value value) {
+    return cw_wrap(CW_ERR, value);
+}
+
+/* Reports a failed `unwrap()` of an `Err`, or a failed `unwrap_err()` of an `Ok` */
+void cw_unwrap_failed(const cw_site *site, const cw_value *result) {
+    cw_panic_begin(site);
+    if (result->tag == CW_ERR) {
+        fputs("called `unwrap()` on an `Err` value: ", stderr);
+    } else {
+        fputs("called `unwrap_err()` on an `Ok` value: ", stderr);
+    }
+    cw_write(stderr, *result->as.boxed);
+    cw_panic_end();
+}
+
+cw_value cw_unwrap(const cw_site *site, cw_value result) {
+    if (result.tag == CW_OK) return *result.as.boxed;
+    cw_unwrap_failed(site, &result);
+}
+
+cw_value cw_unwrap_err(const cw_site *site, cw_value result) {
+    if (result.tag == CW_ERR) return *result.as.boxed;
+    cw_unwrap_failed(site, &result);
+}
+
+bool cw_equal(cw_value a, cw_value b) {
+    if (a.tag != b.tag) return false;
+    switch (a.tag) {
+    case CW_INT:
+        return a.as.i == b.as.i;
+    case CW_FLOAT:
+        return a.as.f == b.as.f;
+    case CW_BOOL:
+        return a.as.b == b.as.b;
+    case CW_CHAR:
+        return a.as.c == b.as.c;
+    case CW_UNIT:
+    case CW_NULL:
+        return true;
+    case CW_RANGE:
+        return a.as.range.start == b.as.range.start && a.as.range.end == b.as.range.end &&
+               a.as.range.inclusive == b.as.range.inclusive;
+    case CW_STRING:
+        return a.as.s->len == b.as.s->len &&
+               memcmp(a.as.s->bytes, b.as.s->bytes, a.as.s->len) == 0;
+    case CW_OK:
+    case CW_ERR:
+        return cw_equal(*a.as.boxed, *b.as.boxed);
+    default:
+        /* The type checker rejects comparisons between functions and between files */
+        return false;
+    }
+}
+
+bool cw_equal_at(const cw_value *a, const cw_value *b) {
+    return cw_equal(*a, *b);
+}
+
+/* Prints `value` followed by a newline, or only a newline when `value` is NULL */
+void cw_print_line(const cw_value *value) {
+    if (value != NULL) cw_write(stdout, *value);
+    fputc('\n', stdout);
+}
+
+cw_value cw_println(cw_value value) {
+    cw_write(stdout, value);
+    fputc('\n', stdout);
+    return cw_unit();
+}
+
+cw_value cw_println_empty(void) {
+    fputc('\n', stdout);
+    return cw_unit();
+}
+
+cw_value cw_print(cw_value value) {
+    cw_write(stdout, value);
+    return cw_unit();
+}
+
+/*
+ * Returns the text of a value, which the caller frees, and its length in `size`. Finite floats have
+ * `precision` digits after the point unless it is negative.
+ */
+static char *cw_text(cw_value value, int32_t precision, size_t *size) {
+    char *text = NULL;
+    FILE *out = open_memstream(&text, size);
+    if (out == NULL) cw_out_of_memory();
+    if (value.tag == CW_FLOAT && precision >= 0 && isfinite(value.as.f)) {
+        fprintf(out, "%.*f", (int)precision, value.as.f);
+    } else {
+        cw_write(out, value);
+    }
+    if (fclose(out) != 0) cw_out_of_memory();
+    return text;
+}
+
+/* Allocates a string of `len` uninitialized bytes */
+static cw_string *cw_new_string(size_t len) {
+    cw_string *s = (cw_string *)cw_alloc(CW_OBJECT_STRING, sizeof(cw_string) + len);
+    s->len = len;
+    s->bytes = (char *)(s + 1);
+    return s;
+}
+
+/*
+ * Prints a value with the spaces that pad it to `width` characters, before it (alignment 1), after
+ * it (0) or around it (2). Finite floats have `precision` digits after the point unless it is
+ * negative.
+ */
+cw_value cw_print_padded(cw_value value, cw_value width, cw_value precision, cw_value alignment) {
+    size_t size = 0;
+    char *text = cw_text(value, precision.as.i, &size);
+
+    /* Characters are counted as the bytes that do not continue a UTF-8 sequence */
+    int32_t count = 0;
+    for (size_t i = 0; i < size; i++) {
+        if (((unsigned char)text[i] & 0xC0) != 0x80) count++;
+    }
+    int32_t padding = width.as.i > count ? width.as.i - count : 0;
+    int32_t before = alignment.as.i == 1 ? padding : alignment.as.i == 2 ? padding / 2 : 0;
+    fprintf(stdout, "%*s", (int)before, "");
+    fwrite(text, 1, size, stdout);
+    fprintf(stdout, "%*s", (int)(padding - before), "");
+    free(text);
+    return cw_unit();
+}
+
+/*
+ * Reads the next line of a stream without its line ending, or returns null at the end of the
+ * stream or when reading fails, which `ferror()` then tells apart
+ */
+static cw_value cw_line(FILE *in) {
+    char *line = NULL;
+    size_t capacity = 0;
+    ssize_t len = getline(&line, &capacity, in);
+    if (len < 0) {
+        free(line);
+        return cw_null();
+    }
+    if (len > 0 && line[len - 1] == '\n') {
+        len--;
+        if (len > 0 && line[len - 1] == '\r') len--;
+    }
+    cw_string *s = cw_new_string((size_t)len);
+    memcpy((char *)s->bytes, line, (size_t)len);
+    free(line);
+    return cw_str(s);
+}
+
+/*
+ * Reads the next line of stdin without its line ending, or returns null at the end of stdin.
+ * Whatever was printed before is flushed first, so that prompts show.
+ */
+cw_value cw_input(void) {
+    fflush(stdout);
+    return cw_line(stdin);
+}
+
+/* Returns the text that printing a value writes, as a string */
+cw_value cw_to_string(cw_value value) {
+    if (value.tag == CW_STRING) return value;
+    size_t size = 0;
+    char *text = cw_text(value, -1, &size);
+    cw_string *s = cw_new_string(size);
+    memcpy((char *)s->bytes, text, size);
+    free(text);
+    return cw_str(s);
+}
+
+/* Returns the string `a` followed by the string `b`, which the caller keeps rooted */
+cw_value cw_concat(cw_value a, cw_value b) {
+    cw_string *s = cw_new_string(a.as.s->len + b.as.s->len);
+    memcpy((char *)s->bytes, a.as.s->bytes, a.as.s->len);
+    memcpy((char *)s->bytes + a.as.s->len, b.as.s->bytes, b.as.s->len);
+    return cw_str(s);
+}
+
+/* Panics on converting a value, shown as `println()` shows it, to the type named `type` */
+CW_NORETURN static void cw_conversion_failed(const cw_site *site, const cw_value *value,
+                                             const char *type) {
+    cw_panic_begin(site);
+    fputs("cannot convert ", stderr);
+    cw_write(stderr, *value);
+    fprintf(stderr, " to %s", type);
+    cw_panic_end();
+}
+
+static const char *cw_skip_sign(const char *p, const char *end) {
+    return p < end && (*p == '+' || *p == '-') ? p + 1 : p;
+}
+
+/* Skips at least one decimal digit, or returns NULL when there is none */
+static const char *cw_skip_digits(const char *p, const char *end) {
+    if (p == end || *p < '0' || *p > '9') return NULL;
+    while (p < end && *p >= '0' && *p <= '9') p++;
+    return p;
+}
+
+/*
+ * Whether a string is an optional sign followed by decimal digits and, when `fraction` is set,
+ * an optional fraction and exponent
+ */
+static bool cw_is_number(const cw_string *text, bool fraction) {
+    const char *end = text->bytes + text->len;
+    const char *p = cw_skip_digits(cw_skip_sign(text->bytes, end), end);
+    if (fraction && p != NULL && p < end && *p == '.') p = cw_skip_digits(p + 1, end);
+    if (fraction && p != NULL && p < end && (*p == 'e' || *p == 'E')) {
+        p = cw_skip_digits(cw_skip_sign(p + 1, end), end);
+    }
+    return p == end;
+}
+
+/* Wraps a value in an `Ok` or an `Err`, keeping it rooted while the result is allocated */
+static cw_value cw_wrap_rooted(uint8_t tag, cw_value value) {
+    cw_value *const roots[] = {&value};
+    cw_frame frame = {cw_frames, 1, 0, roots, NULL};
+    cw_frames = &frame;
+    cw_value result = cw_wrap(tag, value);
+    cw_frames = frame.parent;
+    return result;
+}
+
+/* Returns an `Err` of the text written to `out`, a stream of `open_memstream()` into `bytes` */
+static cw_value cw_error_text(FILE *out, char **bytes, size_t *size) {
+    if (fclose(out) != 0) cw_out_of_memory();
+    cw_string *s = cw_new_string(*size);
+    memcpy((char *)s->bytes, *bytes, *size);
+    free(*bytes);
+    return cw_wrap_rooted(CW_ERR, cw_str(s));
+}
+
+/* Returns the `Err` of parsing a string that is not a number of the type named `type` */
+static cw_value cw_parse_error(const cw_string *text, const char *type) {
+    char *bytes = NULL;
+    size_t size = 0;
+    FILE *out = open_memstream(&bytes, &size);
+    if (out == NULL) cw_out_of_memory();
+    fputs("cannot parse \"", out);
+    fwrite(text->bytes, 1, text->len, out);
+    fprintf(out, "\" as %s", type);
+    return cw_error_text(out, &bytes, &size);
+}
+
+/*
+ * Converts a value with `int()`: a float to the integer it truncates to, panicking when there is
+ * none, a character to its scalar value, and a string, which the caller keeps rooted, to a result
+ */
+cw_value cw_to_int(const cw_site *site, cw_value value) {
+    switch (value.tag) {
+    case CW_FLOAT:
+        /* Both bounds are exact, and NaN is within neither */
+        if (value.as.f > -2147483649.0 && value.as.f < 2147483648.0) {
+            return cw_int((int32_t)value.as.f);
+        }
+        cw_conversion_failed(site, &value, "Int");
+    case CW_CHAR:
+        return cw_int((int32_t)value.as.c);
+    case CW_STRING:
+        if (cw_is_number(value.as.s, false)) {
+            const char *p = value.as.s->bytes, *end = p + value.as.s->len;
+            bool negative = *p == '-';
+            int64_t n = 0;
+            for (p = cw_skip_sign(p, end); p < end && n <= 2147483648LL; p++) {
+                n = n * 10 + (*p - '0');
+            }
+            if (negative) n = -n;
+            if (n >= INT32_MIN && n <= INT32_MAX) return cw_ok(cw_int((int32_t)n));
+        }
+        return cw_parse_error(value.as.s, "Int");
+    default:
+        return value;
+    }
+}
+
+/* Converts a value with `float()`: an integer exactly, and a string, kept rooted, to a result */
+cw_value cw_to_float(cw_value value) {
+    switch (value.tag) {
+    case CW_INT:
+        return cw_float((double)value.as.i);
+    case CW_STRING: {
+        if (!cw_is_number(value.as.s, true)) return cw_parse_error(value.as.s, "Float");
+        char *text = malloc(value.as.s->len + 1);
+        if (text == NULL) cw_out_of_memory();
+        memcpy(text, value.as.s->bytes, value.as.s->len);
+        text[value.as.s->len] = '\0';
+        double f = strtod(text, NULL);
+        free(text);
+        return cw_ok(cw_float(f));
+    }
+    default:
+        return value;
+    }
+}
+
+/* Converts a value with `char()`: an integer to the character it is the scalar value of */
+cw_value cw_to_char(const cw_site *site, cw_value value) {
+    if (value.tag != CW_INT) return value;
+    int32_t i = value.as.i;
+    if (i < 0 || i > 0x10FFFF || (i >= 0xD800 && i <= 0xDFFF)) {
+        cw_conversion_failed(site, &value, "Char");
+    }
+    return cw_char((uint32_t)i);
+}
+
+/*
+ * `pow()` of integers, by squaring. A square that overflows would be a factor of the result, so
+ * both the result and the squares are checked.
+ */
+int32_t cw_pow_int(const cw_site *site, int32_t base, int32_t exponent) {
+    if (exponent < 0) cw_panic(site, "attempt to raise an integer to a negative power");
+    int64_t result = 1;
+    int64_t square = base;
+    while (exponent != 0) {
+        if (exponent & 1) {
+            result *= square;
+            if (result > INT32_MAX || result < INT32_MIN) {
+                cw_panic(site, "attempt to multiply with overflow");
+            }
+        }
+        exponent >>= 1;
+        if (exponent != 0) {
+            square *= square;
+            if (square > INT32_MAX) cw_panic(site, "attempt to multiply with overflow");
+        }
+    }
+    return (int32_t)result;
+}
+
+/* The reason an operation on a file failed with `errno`, which every backend words the same */
+static const char *cw_file_reason(int error) {
+    switch (error) {
+    case ENOENT:
+        return "no such file or directory";
+    case EACCES:
+    case EPERM:
+        return "permission denied";
+    case EISDIR:
+        return "is a directory";
+    default:
+        return "input/output error";
+    }
+}
+
+static cw_value cw_file_error(const char *reason) {
+    char *bytes = NULL;
+    size_t size = 0;
+    FILE *out = open_memstream(&bytes, &size);
+    if (out == NULL) cw_out_of_memory();
+    fputs(reason, out);
+    return cw_error_text(out, &bytes, &size);
+}
+
+static cw_value cw_open_error(const cw_string *path, const char *reason) {
+    char *bytes = NULL;
+    size_t size = 0;
+    FILE *out = open_memstream(&bytes, &size);
+    if (out == NULL) cw_out_of_memory();
+    fputs("cannot open \"", out);
+    fwrite(path->bytes, 1, path->len, out);
+    fprintf(out, "\": %s", reason);
+    return cw_error_text(out, &bytes, &size);
+}
+
+/*
+ * Opens a file for reading (mode "r"), writing over it ("w") or appending to it ("a"), returning
+ * it in an `Ok`, or the reason it cannot be opened in an `Err`
+ */
+cw_value cw_open(cw_value path, cw_value mode) {
+    const cw_string *m = mode.as.s;
+    if (m->len != 1 || (m->bytes[0] != 'r' && m->bytes[0] != 'w' && m->bytes[0] != 'a')) {
+        return cw_open_error(path.as.s, "invalid mode");
+    }
+    char *name = malloc(path.as.s->len + 1);
+    if (name == NULL) cw_out_of_memory();
+    memcpy(name, path.as.s->bytes, path.as.s->len);
+    name[path.as.s->len] = '\0';
+    char flags[] = {m->bytes[0], '\0'};
+    errno = 0;
+    FILE *stream = memchr(name, '\0', path.as.s->len) == NULL ? fopen(name, flags) : NULL;
+    int error = errno;
+    free(name);
+    if (stream == NULL) return cw_open_error(path.as.s, cw_file_reason(error));
+
+    bool writing = m->bytes[0] != 'r';
+    if (writing) setvbuf(stream, NULL, _IONBF, 0);
+    cw_file *file = (cw_file *)cw_alloc(CW_OBJECT_FILE, sizeof *file);
+    file->stream = stream;
+    file->writing = writing;
+    cw_value v;
+    v.tag = CW_FILE;
+    v.as.file = file;
+    return cw_wrap_rooted(CW_OK, v);
+}
+
+cw_value cw_close(cw_value file) {
+    if (file.as.file->stream != NULL) fclose(file.as.file->stream);
+    file.as.file->stream = NULL;
+    return cw_unit();
+}
+
+/* The `Err` of reading a file that is closed or open for writing, or NULL if it can be read */
+static const char *cw_unreadable(const cw_file *file) {
+    if (file->stream == NULL) return "the file is closed";
+    if (file->writing) return "the file is not open for reading";
+    return NULL;
+}
+
+/* Reads the rest of a file */
+cw_value cw_read(cw_value file) {
+    const char *unreadable = cw_unreadable(file.as.file);
+    if (unreadable != NULL) return cw_file_error(unreadable);
+    FILE *in = file.as.file->stream;
+    char *bytes = NULL;
+    size_t size = 0;
+    FILE *out = open_memstream(&bytes, &size);
+    if (out == NULL) cw_out_of_memory();
+    char buffer[4096];
+    size_t count;
+    clearerr(in);
+    errno = 0;
+    while ((count = fread(buffer, 1, sizeof buffer, in)) > 0) fwrite(buffer, 1, count, out);
+    int error = errno;
+    if (fclose(out) != 0) cw_out_of_memory();
+    if (ferror(in)) {
+        free(bytes);
+        return cw_file_error(cw_file_reason(error));
+    }
+    cw_string *s = cw_new_string(size);
+    memcpy((char *)s->bytes, bytes, size);
+    free(bytes);
+    return cw_wrap_rooted(CW_OK, cw_str(s));
+}
+
+/* Reads the next line of a file without its line ending, or null at the end of the file */
+cw_value cw_read_line(cw_value file) {
+    const char *unreadable = cw_unreadable(file.as.file);
+    if (unreadable != NULL) return cw_file_error(unreadable);
+    /* A failure to read before is forgotten, so that `ferror()` only tells of this one */
+    clearerr(file.as.file->stream);
+    errno = 0;
+    cw_value line = cw_line(file.as.file->stream);
+    if (line.tag == CW_NULL && ferror(file.as.file->stream)) {
+        return cw_file_error(cw_file_reason(errno));
+    }
+    return cw_wrap_rooted(CW_OK, line);
+}
+
+cw_value cw_write_file(cw_value file, cw_value text) {
+    if (file.as.file->stream == NULL) return cw_file_error("the file is closed");
+    if (!file.as.file->writing) return cw_file_error("the file is not open for writing");
+    size_t len = text.as.s->len;
+    if (fwrite(text.as.s->bytes, 1, len, file.as.file->stream) != len) {
+        return cw_file_error(cw_file_reason(errno));
+    }
+    return cw_ok(cw_unit());
+}
+
+/* The next line of a file for a `for` loop over it, or null once there is none to read */
+cw_value cw_next_line(cw_value file) {
+    if (cw_unreadable(file.as.file) != NULL) return cw_null();
+    return cw_line(file.as.file->stream);
+}
+
+/*
+ * The number of bytes of the UTF-8 sequence starting at byte `offset` of a string, or 0 once the
+ * offset is at its end. A byte that does not start a sequence counts as one, and a sequence cut
+ * short by the end of the string as the bytes left.
+ */
+static int32_t cw_utf8_width(const cw_string *s, int32_t offset) {
+    if (offset < 0 || (size_t)offset >= s->len) return 0;
+    unsigned char lead = (unsigned char)s->bytes[offset];
+    size_t width = lead >= 0xF8 ? 1 : lead >= 0xF0 ? 4 : lead >= 0xE0 ? 3 : lead >= 0xC0 ? 2 : 1;
+    size_t left = s->len - (size_t)offset;
+    return (int32_t)(width < left ? width : left);
+}
+
+/* The character starting at byte `offset` of a string for a `for` loop over it */
+cw_value cw_char_at(cw_value string, cw_value offset) {
+    const unsigned char *bytes = (const unsigned char *)string.as.s->bytes + offset.as.i;
+    int32_t width = cw_utf8_width(string.as.s, offset.as.i);
+    uint32_t c = width > 1 ? bytes[0] & (0x7F >> width) : bytes[0];
+    for (int32_t i = 1; i < width; i++) {
+        c = (c << 6) | (bytes[i] & 0x3F);
+    }
+    return cw_char(c);
+}
+
+cw_value cw_char_width(cw_value string, cw_value offset) {
+    return cw_int(cw_utf8_width(string.as.s, offset.as.i));
+}
+
+void cw_print_at(const cw_value *value) {
+    cw_write(stdout, *value);
+}
+
+void cw_print_padded_at(const cw_value *value, int32_t width, int32_t precision, int32_t alignment) {
+    cw_print_padded(*value, cw_int(width), cw_int(precision), cw_int(alignment));
+}
+
+void cw_input_at(cw_value *result) {
+    *result = cw_input();
+}
+
+void cw_to_string_at(cw_value *result, const cw_value *value) {
+    *result = cw_to_string(*value);
+}
+
+void cw_concat_at(cw_value *result, const cw_value *a, const cw_value *b) {
+    *result = cw_concat(*a, *b);
+}
+
+void cw_to_int_at(cw_value *result, const cw_site *site, const cw_value *value) {
+    *result = cw_to_int(site, *value);
+}
+
+void cw_to_float_at(cw_value *result, const cw_value *value) {
+    *result = cw_to_float(*value);
+}
+
+void cw_to_char_at(cw_value *result, const cw_site *site, const cw_value *value) {
+    *result = cw_to_char(site, *value);
+}
+
+void cw_open_at(cw_value *result, const cw_value *path, const cw_value *mode) {
+    *result = cw_open(*path, *mode);
+}
+
+void cw_close_at(const cw_value *file) {
+    cw_close(*file);
+}
+
+void cw_read_at(cw_value *result, const cw_value *file) {
+    *result = cw_read(*file);
+}
+
+void cw_read_line_at(cw_value *result, const cw_value *file) {
+    *result = cw_read_line(*file);
+}
+
+void cw_write_file_at(cw_value *result, const cw_value *file, const cw_value *text) {
+    *result = cw_write_file(*file, *text);
+}
+
+void cw_next_line_at(cw_value *result, const cw_value *file) {
+    *result = cw_next_line(*file);
+}
+
+void cw_char_at_at(cw_value *result, const cw_value *string, int32_t offset) {
+    *result = cw_char_at(*string, cw_int(offset));
+}
+
+int32_t cw_char_width_at(const cw_value *string, int32_t offset) {
+    return cw_utf8_width(string->as.s, offset);
+}
@@ -0,0 +1,326 @@
+/*
+ * Runtime of the programs compiled by the Crawfish C backend.
+ * Every Crawfish value is a `cw_value`: a tag followed by the value itself, or a pointer for values
+ * that live on the heap. The heap is garbage collected: compiled functions push a `cw_frame` with
+ * the addresses of their variables that may point into it, and the collector marks everything
+ * reachable from those roots before freeing the rest.
+ */
+#ifndef CRAWFISH_H
+#define CRAWFISH_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#if defined(__GNUC__)
+#define CW_NORETURN __attribute__((noreturn))
+#else
+#define CW_NORETURN
+#endif
+
+/* Deepest chain of nested calls before the program is stopped with a stack overflow */
+#define CW_MAX_CALL_DEPTH 10000
+
+enum {
+    CW_INT,
+    CW_FLOAT,
+    CW_BOOL,
+    CW_CHAR,
+    CW_UNIT,
+    CW_NULL,
+    CW_RANGE,
+    CW_STRING,
+    CW_OK,
+    CW_ERR,
+    CW_CLOSURE,
+    CW_CELL,
+    CW_FILE
+};
+
+/* Kinds of objects on the heap */
+enum { CW_OBJECT_CELL, CW_OBJECT_CLOSURE, CW_OBJECT_STRING, CW_OBJECT_FILE };
+
+/*
+ * The header every object on the heap starts with, linking it to the object allocated before it.
+ * Objects of the program itself, such as string constants, are always marked, and never collected.
+ */
+typedef struct cw_object {
+    struct cw_object *next;
+    uint32_t size;
+    uint8_t kind;
+    uint8_t marked;
+} cw_object;
+
+/* The header of an object that is part of the program rather than allocated */
+#define CW_STATIC_OBJECT(kind) {NULL, 0, kind, 1}
+
+typedef struct cw_string {
+    cw_object object;
+    size_t len;
+    const char *bytes;
+} cw_string;
+
+typedef struct cw_closure cw_closure;
+typedef struct cw_file cw_file;
+
+typedef struct cw_value {
+    uint8_t tag;
+    union {
+        int32_t i;
+        double f;
+        bool b;
+        uint32_t c;
+        struct {
+            int32_t start;
+            int32_t end;
+            bool inclusive;
+        } range;
+        const cw_string *s;
+        /* the value inside an `Ok` or an `Err`, or the contents of a heap cell */
+        struct cw_value *boxed;
+        cw_closure *closure;
+        cw_file *file;
+    } as;
+} cw_value;
+
+/* Compiled functions take their closure, then their arguments, and are cast to this type */
+typedef void (*cw_fn)(void);
+
+/* A function with the variables it captured; top level functions have no captures */
+struct cw_closure {
+    cw_object object;
+    cw_fn fn;
+    uint32_t count;
+    cw_value captures[];
+};
+
+/* The source location of an operation that can panic */
+typedef struct cw_site {
+    const char *path;
+    uint32_t line;
+    uint32_t column;
+} cw_site;
+
+/*
+ * The roots of a call in progress, on the shadow stack that `cw_frames` points to the top of:
+ * the addresses of the variables holding values that may point into the heap, and of the ones
+ * holding heap cells
+ */
+typedef struct cw_frame {
+    struct cw_frame *parent;
+    uint32_t value_count;
+    uint32_t cell_count;
+    cw_value *const *values;
+    cw_value **const *cells;
+} cw_frame;
+
+extern uint32_t cw_depth;
+extern cw_frame *cw_frames;
+
+void cw_init(void);
+int cw_finish(void);
+
+CW_NORETURN void cw_panic(const cw_site *site, const char *message);
+cw_value cw_panic_with(const cw_site *site, cw_value message);
+void cw_enter(const cw_site *site);
+
+cw_value *cw_new_cell(cw_value value);
+cw_closure *cw_new_closure(cw_fn fn, uint32_t count);
+cw_value cw_ok(cw_value value);
+cw_value cw_err(cw_value value);
+cw_value cw_unwrap(const cw_site *site, cw_value result);
+cw_value cw_unwrap_err(const cw_site *site, cw_value result);
+
+bool cw_equal(cw_value a, cw_value b);
+cw_value cw_println(cw_value value);
+cw_value cw_println_empty(void);
+cw_value cw_print(cw_value value);
+cw_value cw_print_padded(cw_value value, cw_value width, cw_value precision, cw_value alignment);
+cw_value cw_input(void);
+cw_value cw_to_string(cw_value value);
+cw_value cw_concat(cw_value a, cw_value b);
+cw_value cw_to_int(const cw_site *site, cw_value value);
+cw_value cw_to_float(cw_value value);
+cw_value cw_to_char(const cw_site *site, cw_value value);
+int32_t cw_pow_int(const cw_site *site, int32_t base, int32_t exponent);
+cw_value cw_open(cw_value path, cw_value mode);
+cw_value cw_close(cw_value file);
+cw_value cw_read(cw_value file);
+cw_value cw_read_line(cw_value file);
+cw_value cw_write_file(cw_value file, cw_value text);
+cw_value cw_next_line(cw_value file);
+cw_value cw_char_at(cw_value string, cw_value offset);
+cw_value cw_char_width(cw_value string, cw_value offset);
+
+/* Entry points for backends that pass values by address, such as the LLVM backend */
+cw_value *cw_box(const cw_value *value);
+bool cw_equal_at(const cw_value *a, const cw_value *b);
+void cw_print_line(const cw_value *value);
+void cw_print_at(const cw_value *value);
+void cw_print_padded_at(const cw_value *value, int32_t width, int32_t precision, int32_t alignment);
+void cw_input_at(cw_value *result);
+void cw_to_string_at(cw_value *result, const cw_value *value);
+void cw_concat_at(cw_value *result, const cw_value *a, const cw_value *b);
+void cw_to_int_at(cw_value *result, const cw_site *site, const cw_value *value);
+void cw_to_float_at(cw_value *result, const cw_value *value);
+void cw_to_char_at(cw_value *result, const cw_site *site, const cw_value *value);
+void cw_open_at(cw_value *result, const cw_value *path, const cw_value *mode);
+void cw_close_at(const cw_value *file);
+void cw_read_at(cw_value *result, const cw_value *file);
+void cw_read_line_at(cw_value *result, const cw_value *file);
+void cw_write_file_at(cw_value *result, const cw_value *file, const cw_value *text);
+void cw_next_line_at(cw_value *result, const cw_value *file);
+void cw_char_at_at(cw_value *result, const cw_value *string, int32_t offset);
+int32_t cw_char_width_at(const cw_value *string, int32_t offset);
+CW_NORETURN void cw_panic_value(const cw_site *site, const cw_value *message);
+CW_NORETURN void cw_unwrap_failed(const cw_site *site, const cw_value *result);
+
+static inline cw_value cw_int(int32_t i) {
+    cw_value v;
+    v.tag = CW_INT;
+    v.as.i = i;
+    return v;
+}
+
+static inline cw_value cw_float(double f) {
+    cw_value v;
+    v.tag = CW_FLOAT;
+    v.as.f = f;
+    return v;
+}
+
+static inline cw_value cw_bool(bool b) {
+    cw_value v;
+    v.tag = CW_BOOL;
+    v.as.b = b;
+    return v;
+}
+
+static inline cw_value cw_char(uint32_t c) {
+    cw_value v;
+    v.tag = CW_CHAR;
+    v.as.c = c;
+    return v;
+}
+
+static inline cw_value cw_unit(void) {
+    cw_value v;
+    v.tag = CW_UNIT;
+    v.as.i = 0;
+    return v;
+}
+
+static inline cw_value cw_null(void) {
+    cw_value v;
+    v.tag = CW_NULL;
+    v.as.i = 0;
+    return v;
+}
+
+static inline cw_value cw_range(int32_t start, int32_t end, bool inclusive) {
+    cw_value v;
+    v.tag = CW_RANGE;
+    v.as.range.start = start;
+    v.as.range.end = end;
+    v.as.range.inclusive = inclusive;
+    return v;
+}
+
+static inline cw_value cw_str(const cw_string *s) {
+    cw_value v;
+    v.tag = CW_STRING;
+    v.as.s = s;
+    return v;
+}
+
+static inline cw_value cw_closure_value(cw_closure *closure) {
+    cw_value v;
+    v.tag = CW_CLOSURE;
+    v.as.closure = closure;
+    return v;
+}
+
+/* A heap cell as a value, which is how closures capture variables by reference */
+static inline cw_value cw_cell_value(cw_value *cell) {
+    cw_value v;
+    v.tag = CW_CELL;
+    v.as.boxed = cell;
+    return v;
+}
+
+/* Checked integer arithmetic, which panics where Crawfish semantics require it */
+
+static inline int32_t cw_add(const cw_site *site, int32_t a, int32_t b) {
+    int64_t r = (int64_t)a + b;
+    if (r > INT32_MAX || r < INT32_MIN) cw_panic(site, "attempt to add with overflow");
+    return (int32_t)r;
+}
+
+static inline int32_t cw_sub(const cw_site *site, int32_t a, int32_t b) {
+    int64_t r = (int64_t)a - b;
+    if (r > INT32_MAX || r < INT32_MIN) cw_panic(site, "attempt to subtract with overflow");
+    return (int32_t)r;
+}
+
+static inline int32_t cw_mul(const cw_site *site, int32_t a, int32_t b) {
+    int64_t r = (int64_t)a * b;
+    if (r > INT32_MAX || r < INT32_MIN) cw_panic(site, "attempt to multiply with overflow");
+    return (int32_t)r;
+}
+
+static inline int32_t cw_div(const cw_site *site, int32_t a, int32_t b) {
+    if (b == 0) cw_panic(site, "attempt to divide by zero");
+    if (a == INT32_MIN && b == -1) cw_panic(site, "attempt to divide with overflow");
+    return a / b;
+}
+
+static inline int32_t cw_rem(const cw_site *site, int32_t a, int32_t b) {
+    if (b == 0) cw_panic(site, "attempt to divide by zero");
+    if (a == INT32_MIN && b == -1) {
+        cw_panic(site, "attempt to calculate the remainder with overflow");
+    }
+    return a % b;
+}
+
+static inline int32_t cw_neg(const cw_site *site, int32_t a) {
+    if (a == INT32_MIN) cw_panic(site, "attempt to negate with overflow");
+    return -a;
+}
+
+static inline int32_t cw_shl(const cw_site *site, int32_t a, int32_t b) {
+    if (b < 0 || b >= 32) cw_panic(site, "attempt to shift left with overflow");
+    return (int32_t)((uint32_t)a << b);
+}
+
+static inline int32_t cw_shr(const cw_site *site, int32_t a, int32_t b) {
+    if (b < 0 || b >= 32) cw_panic(site, "attempt to shift right with overflow");
+    /* An arithmetic shift, whatever the C implementation does with negative numbers */
+    return a < 0 ? ~(~a >> b) : a >> b;
+}
+
+static inline int32_t cw_abs_int(const cw_site *site, int32_t a) {
+    return a < 0 ? cw_neg(site, a) : a;
+}
+
+/*
+ * `min()` and `max()`, which return their first argument when both are equal, and of floats
+ * ignore a NaN
+ */
+
+static inline int32_t cw_min_int(int32_t a, int32_t b) {
+    return b < a ? b : a;
+}
+
+static inline int32_t cw_max_int(int32_t a, int32_t b) {
+    return b > a ? b : a;
+}
+
+static inline double cw_min_float(double a, double b) {
+    return b < a || a != a ? b : a;
+}
+
+static inline double cw_max_float(double a, double b) {
+    return b > a || a != a ? b : a;
+}
+
+#endif
@@ -0,0 +1,405 @@
+//! Closure conversion, which turns every anonymous function into a top level function that receives
+//! the variables it captures through an explicit environment record.
+//! A captured variable that is never assigned after its declaration is copied into the record (by value).
+//! Any other captured variable is moved into a heap cell that the declaring function and every closure
+//! capturing it share (by reference), so that assignments on either side are seen by the other.
+use crate::front_end::ast::{
+    Block, Capture, Expr, ExprKind, Ident, Item, Program, StmtKind, StringPart, VariableId,
+};
+use crate::front_end::token::Span;
+use crate::front_end::type_checker;
+use crate::front_end::types::Type;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    Value,
+    Reference,
+}
+
+/// One slot of an environment record
+/// - `variable` is the captured variable, which `name` refers to throughout the closure's body
+/// - `mode` is `Reference` when the slot holds a pointer to the variable's heap cell rather than its value
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentSlot {
+    pub name: String,
+    pub variable: VariableId,
+    pub ty: Type,
+    pub mode: CaptureMode,
+}
+
+/// A function after closure conversion
+/// - `name` is the declared name of a top level function, or `<enclosing name>$lambda<n>` for the
+///   `n`th anonymous function written directly inside another function
+/// - `environment` is the layout of the record passed to a closure as a hidden first argument,
+///   which is empty for top level functions
+/// - `boxed` holds the locals and parameters declared by this function that live in heap cells,
+///   because some closure captures them by reference
+/// - `body` is the original body, in which every anonymous function is looked up in `ConvertedProgram::closures`
+/// - `file` is the index of the source file the function is written in
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertedFunction {
+    pub name: String,
+    pub params: Vec<(Ident, Type)>,
+    pub return_type: Type,
+    pub environment: Vec<EnvironmentSlot>,
+    pub boxed: HashSet<VariableId>,
+    pub body: Block,
+    pub file: usize,
+}
+
+/// A program in which every function is top level
+/// - `closures` maps the file and span of each anonymous function expression to the index of the
+///   function it became
+#[derive(Debug, Default, PartialEq)]
+pub struct ConvertedProgram {
+    pub functions: Vec<ConvertedFunction>,
+    pub closures: HashMap<(usize, Span), usize>,
+}
+
+/// Closure converts a type checked program
+pub fn convert(program: &Program) -> ConvertedProgram {
+    let mut converter = ClosureConverter {
+        program: ConvertedProgram::default(),
+        enclosing: Vec::new(),
+    };
+    for item in &program.items {
+        let Item::Function(function) = item;
+        let params = function
+            .params
+            .iter()
+            .map(|param| {
+                let ty = type_checker::resolve_annotation(&param.ty);
+                (param.name.clone(), ty)
+            })
+            .collect();
+        let return_type = function
+            .return_type
+            .as_ref()
+            .map_or(Type::Unit, type_checker::resolve_annotation);
+        converter.function(ConvertedFunction {
+            name: function.name.name.clone(),
+            params,
+            return_type,
+            environment: Vec::new(),
+            boxed: HashSet::new(),
+            body: function.body.clone(),
+            file: function.file,
+        });
+    }
+    converter.program
+}
+
+/// A function whose body is being converted
+/// - `assigned` holds every variable assigned anywhere in the body, including inside closures
+struct Enclosing {
+    index: usize,
+    assigned: HashSet<VariableId>,
+}
+
+struct ClosureConverter {
+    program: ConvertedProgram,
+    enclosing: Vec<Enclosing>,
+}
+
+impl ClosureConverter {
+    /// Adds `function` to the program, then lifts out the anonymous functions written in its body
+    fn function(&mut self, function: ConvertedFunction) {
+        let mut visitor = BodyVisitor::default();
+        visitor.block(&function.body);
+        let BodyVisitor {
+            lambdas, assigned, ..
+        } = visitor;
+        let lambdas: Vec<Expr> = lambdas.into_iter().cloned().collect();
+
+        let index = self.program.functions.len();
+        let name = function.name.clone();
+        let file = function.file;
+        self.program.functions.push(function);
+        self.enclosing.push(Enclosing { index, assigned });
+        for (n, expr) in lambdas.iter().enumerate() {
+            let ExprKind::Lambda(lambda) = &expr.kind else {
+                unreachable!("the visitor only collects anonymous functions");
+            };
+            let Type::Function(param_types, return_type) = &expr.ty else {
+                unreachable!("anonymous functions are type checked as functions");
+            };
+            let environment = lambda
+                .captures
+                .iter()
+                .map(|capture| EnvironmentSlot {
+                    name: capture.name.clone(),
+                    variable: capture.variable,
+                    ty: capture.ty.clone(),
+                    mode: self.capture_mode(capture),
+                })
+                .collect();
+            let params = lambda
+                .params
+                .iter()
+                .zip(param_types)
+                .map(|(param, ty)| (param.name.clone(), ty.clone()))
+                .collect();
+
+            self.program
+                .closures
+                .insert((file, expr.span), self.program.functions.len());
+            self.function(ConvertedFunction {
+                name: format!("{}$lambda{}", name, n),
+                params,
+                return_type: *return_type.clone(),
+                environment,
+                boxed: HashSet::new(),
+                body: lambda.body.clone(),
+                file,
+            });
+        }
+        self.enclosing.pop();
+    }
+
+    /// Decides how a closure written in the innermost enclosing function captures a variable, boxing
+    /// it in the function that declares it when it is captured by reference
+    fn capture_mode(&mut self, capture: &Capture) -> CaptureMode {
+        // Enclosing closures that capture the variable themselves pass it along, so the declaring
+        // function is the innermost one whose environment does not contain it
+        let functions = &mut self.program.functions;
+        let declaring = self
+            .enclosing
+            .iter()
+            .rev()
+            .find(|enclosing| {
+                !functions[enclosing.index]
+                    .environment
+                    .iter()
+                    .any(|slot| slot.variable == capture.variable)
+            })
+            .expect("captured variables are declared by an enclosing function");
+        if declaring.assigned.contains(&capture.variable) {
+            functions[declaring.index].boxed.insert(capture.variable);
+            CaptureMode::Reference
+        } else {
+            CaptureMode::Value
+        }
+    }
+}
+
+/// Collects the anonymous functions written directly in a body, and every variable assigned in it
+#[derive(Default)]
+struct BodyVisitor<'a> {
+    lambdas: Vec<&'a Expr>,
+    assigned: HashSet<VariableId>,
+    depth: usize,
+}
+
+impl<'a> BodyVisitor<'a> {
+    fn block(&mut self, block: &'a Block) {
+        for stmt in &block.stmts {
+            match &stmt.kind {
+                StmtKind::Var { value, .. } => self.expr(value),
+                StmtKind::Assign { target, value, .. } => {
+                    self.assigned.extend(target.variable);
+                    self.expr(value);
+                }
+                StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) | StmtKind::Defer(expr) => {
+                    self.expr(expr)
+                }
+                StmtKind::While { cond: expr, body }
+                | StmtKind::For {
+                    iterable: expr,
+                    body,
+                    ..
+                } => {
+                    self.expr(expr);
+                    self.block(body);
+                }
+                StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue => (),
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::Identifier(_) | ExprKind::Qualified { .. } => (),
+            ExprKind::Unary { operand, .. } | ExprKind::Try(operand) => self.expr(operand),
+            ExprKind::Binary { left, right, .. }
+            | ExprKind::Range {
+                start: left,
+                end: right,
+                ..
+            } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+            ExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::Lambda(lambda) => {
+                if self.depth == 0 {
+                    self.lambdas.push(expr);
+                }
+                self.depth += 1;
+                self.block(&lambda.body);
+                self.depth -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end::{parser, type_checker};
+
+    fn convert_source(source: &str) -> ConvertedProgram {
+        let mut program = parser::parse(source).unwrap();
+        type_checker::check(&mut program).unwrap();
+        convert(&program)
+    }
+
+    fn params(function: &ConvertedFunction) -> Vec<(&str, Type)> {
+        let params = function.params.iter();
+        params
+            .map(|(param, ty)| (param.name.as_str(), ty.clone()))
+            .collect()
+    }
+
+    fn slot(name: &str, variable: VariableId, ty: Type, mode: CaptureMode) -> EnvironmentSlot {
+        EnvironmentSlot {
+            name: name.to_string(),
+            variable,
+            ty,
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_top_level_functions_are_unchanged() {
+        let program =
+            convert_source("func add(a: Int, b: Int) -> Int { return a + b; } func main() {}");
+        let names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["add", "main"]);
+        assert_eq!(
+            params(&program.functions[0]),
+            [("a", Type::Int), ("b", Type::Int)]
+        );
+        assert_eq!(program.functions[0].return_type, Type::Int);
+        assert!(program.functions[0].environment.is_empty());
+        assert!(program.closures.is_empty());
+    }
+
+    #[test]
+    fn test_lambdas_are_lifted() {
+        let source =
+            "func main() { const n = 2; const f = func(x: Int) -> Int { return x * n; }; }";
+        let program = convert_source(source);
+        let lambda = &program.functions[1];
+        assert_eq!(lambda.name, "main$lambda0");
+        assert_eq!(params(lambda), [("x", Type::Int)]);
+        assert_eq!(
+            lambda.environment,
+            [slot("n", 0, Type::Int, CaptureMode::Value)]
+        );
+        assert!(program.functions[0].boxed.is_empty());
+
+        let start = source.find("func(").unwrap();
+        let span = Span::new(start, source.len() - 3);
+        assert_eq!(program.closures[&(0, span)], 1);
+    }
+
+    #[test]
+    fn test_assigned_captures_are_by_reference() {
+        let source = r#"
+            func main() {
+                var count = 0;
+                var total = 10;
+                const increment = func() { count += 1; };
+                const read = func() -> Int { return count + total; };
+                total = 20;
+            }
+        "#;
+        let program = convert_source(source);
+        let main = &program.functions[0];
+        assert_eq!(main.boxed, HashSet::from([0, 1]));
+        assert_eq!(
+            program.functions[1].environment,
+            [slot("count", 0, Type::Int, CaptureMode::Reference)]
+        );
+        assert_eq!(
+            program.functions[2].environment,
+            [
+                slot("count", 0, Type::Int, CaptureMode::Reference),
+                slot("total", 1, Type::Int, CaptureMode::Reference)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_lambdas_pass_captures_along() {
+        let source = r#"
+            func main() {
+                var x = 1;
+                const outer = func() -> func() -> Int {
+                    return func() -> Int { return x; };
+                };
+                x = 2;
+            }
+        "#;
+        let program = convert_source(source);
+        let names: Vec<&str> = program.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["main", "main$lambda0", "main$lambda0$lambda0"]);
+        let by_reference = [slot("x", 0, Type::Int, CaptureMode::Reference)];
+        assert_eq!(program.functions[1].environment, by_reference);
+        assert_eq!(program.functions[2].environment, by_reference);
+        assert_eq!(program.functions[0].boxed, HashSet::from([0]));
+        assert!(program.functions[1].boxed.is_empty());
+    }
+
+    #[test]
+    fn test_shadowed_captures_are_told_apart() {
+        let source = r#"
+            func main() {
+                const y = 3;
+                const k = func() -> func() -> Int {
+                    var y = y + 1;
+                    return func() -> Int { y += 1; return y; };
+                };
+            }
+        "#;
+        let program = convert_source(source);
+        assert!(program.functions[0].boxed.is_empty());
+        assert_eq!(
+            program.functions[1].environment,
+            [slot("y", 0, Type::Int, CaptureMode::Value)]
+        );
+        assert_eq!(program.functions[1].boxed, HashSet::from([1]));
+        assert_eq!(
+            program.functions[2].environment,
+            [slot("y", 1, Type::Int, CaptureMode::Reference)]
+        );
+    }
+}
@@ -0,0 +1,966 @@
+//! The crawfish IR, a mid-level intermediate representation between the type checked syntax tree and
+//! every backend.
+//!
+//! A function is a control-flow graph of basic blocks in SSA form: every value is defined exactly
+//! once, by a parameter, a phi node at the start of a block or an instruction, and every block ends
+//! with an explicit terminator. Values are typed with the front end's types, except the heap cells
+//! of variables captured by reference, which are `Cell[T]`. `and`, `or`, `??`, `?`, `if` and loops
+//! are lowered to branches, so instructions only compute values.
+//!
+//! `builder` constructs the IR of a type checked program, the `Display` implementations print its
+//! textual form and `parser` reads it back, so that passes can be tested on hand-written functions.
+//! `verifier` checks that a function is well formed, using the analyses of `cfg`, and `passes`
+//! optimizes it.
+pub mod builder;
+pub mod cfg;
+pub mod parser;
+pub mod passes;
+pub mod verifier;
+
+use crate::front_end::ast::{BinaryOp, UnaryOp};
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use crate::runtime::math;
+use std::error::Error;
+use std::fmt;
+
+pub use builder::build;
+pub use parser::parse;
+pub use verifier::verify;
+
+/// Why IR text cannot be read, or a function is not well formed
+#[derive(Debug, PartialEq)]
+pub enum IrError {
+    Syntax { line: usize, message: String },
+    Invalid { function: String, reason: String },
+}
+
+impl fmt::Display for IrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrError::Syntax { line, message } => {
+                write!(f, "IR syntax error on line {}: {}", line, message)
+            }
+            IrError::Invalid { function, reason } => {
+                write!(f, "Invalid IR in `{}`: {}", function, reason)
+            }
+        }
+    }
+}
+
+impl Error for IrError {}
+
+/// An SSA value, printed `%n`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+/// A basic block of a function, printed `bbn`. The first block is the entry of the function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Block(pub u32);
+
+/// The type of a value: a crawfish value, or a heap cell holding one
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    Value(Type),
+    Cell(Type),
+}
+
+impl ValueType {
+    /// Whether the value may point to an object that native runtimes allocate on their heap, which
+    /// makes the variable holding it a root that the garbage collector must know about
+    pub fn is_traced(&self) -> bool {
+        fn traced(ty: &Type) -> bool {
+            match ty {
+                Type::String
+                | Type::Range
+                | Type::File
+                | Type::Result(_, _)
+                | Type::Function(_, _) => true,
+                Type::Optional(inner) => traced(inner),
+                _ => false,
+            }
+        }
+        match self {
+            ValueType::Cell(_) => true,
+            ValueType::Value(ty) => traced(ty),
+        }
+    }
+}
+
+/// A whole program, whose functions are all top level after closure conversion
+/// - `entry` is the index of `main()` in `functions`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub entry: usize,
+}
+
+/// A function
+/// - `captures` is the layout of the environment of a closure, read with `capture n`
+/// - `params` are the values of the arguments, defined on entry
+/// - `values` holds the type of every value, indexed by its number
+/// - `file` is the index of the source file the function is written in, which spans point into
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub captures: Vec<ValueType>,
+    pub params: Vec<Value>,
+    pub return_type: Type,
+    pub values: Vec<ValueType>,
+    pub blocks: Vec<BasicBlock>,
+    pub file: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub phis: Vec<Phi>,
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// A value that depends on the predecessor control came from, with one incoming value per
+/// predecessor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Phi {
+    pub result: Value,
+    pub incoming: Vec<(Block, Value)>,
+}
+
+/// An instruction, which defines `result` unless it is only run for its effect
+/// - `span` is the source it was lowered from, which panics it raises point to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub result: Option<Value>,
+    pub kind: InstructionKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstructionKind {
+    Const(Constant),
+    /// A top level function as a value
+    Function(usize),
+    /// A closure of `function` over the values of its environment
+    Closure {
+        function: usize,
+        captures: Vec<Value>,
+    },
+    /// Slot `n` of the environment of the running closure
+    Capture(u32),
+    NewCell(Value),
+    LoadCell(Value),
+    StoreCell {
+        cell: Value,
+        value: Value,
+    },
+    /// Integer, float and boolean operators, which operate on the type of their operands.
+    /// Integer arithmetic panics on overflow.
+    Unary {
+        op: UnaryOp,
+        operand: Value,
+    },
+    /// Never one of the short-circuiting operators `and`, `or` and `??`
+    Binary {
+        op: BinaryOp,
+        left: Value,
+        right: Value,
+    },
+    Range {
+        start: Value,
+        end: Value,
+        inclusive: bool,
+    },
+    /// The first item of a range
+    RangeStart(Value),
+    /// Whether `item`, which is at least the start of `range`, is not past its end
+    RangeContains {
+        range: Value,
+        item: Value,
+    },
+    IsNull(Value),
+    /// The value inside an `Ok` or an `Err`
+    Payload(Value),
+    /// Calls a top level function, counting the call towards the maximum call depth
+    Call {
+        function: usize,
+        args: Vec<Value>,
+    },
+    /// Calls a function value, counting the call towards the maximum call depth
+    CallValue {
+        callee: Value,
+        args: Vec<Value>,
+    },
+    CallBuiltin {
+        builtin: Builtin,
+        args: Vec<Value>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Unit,
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(Block),
+    Branch {
+        condition: Value,
+        then: Block,
+        otherwise: Block,
+    },
+    Return(Value),
+    /// Ends a block that control never leaves, such as one that panics
+    Unreachable,
+}
+
+/// A function provided by the language rather than written in the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Println,
+    Panic,
+    Ok,
+    Err,
+    Unwrap,
+    UnwrapErr,
+    IsOk,
+    IsErr,
+    Print,
+    /// `print_padded(value, width, precision, alignment)` prints a value padded with spaces to at
+    /// least `width` characters, a float with `precision` digits after the decimal point unless it
+    /// is negative, on the side given by `format::Align`'s number for `alignment`
+    PrintPadded,
+    Input,
+    /// `string(value)` is the text that printing the value writes
+    ToString,
+    /// `concat(a, b)` is the string `a` followed by the string `b`
+    Concat,
+    /// `int(value)` converts a float, a character or a string, as `runtime::convert` describes
+    Int,
+    /// `float(value)` converts an integer or a string
+    Float,
+    /// `char(value)` converts an integer
+    Char,
+    /// `min(a, b)` and the other math built-ins compute as `runtime::math` describes
+    Min,
+    Max,
+    Abs,
+    Pow,
+    Sqrt,
+    Ceil,
+    Floor,
+    Sin,
+    Cos,
+    Log,
+    Ln,
+    PowE,
+    /// `open(path, mode)` and the other file built-ins behave as `runtime::file` describes
+    Open,
+    Close,
+    Read,
+    ReadLine,
+    Write,
+    /// `next_line(file)` is the next line of a file a `for` loop goes through, or `null` at its end
+    /// or once it cannot be read
+    NextLine,
+    /// `char_at(string, offset)` is the character starting at byte `offset` of a string a `for`
+    /// loop goes through, which is before its end
+    CharAt,
+    /// `char_width(string, offset)` is the number of bytes of the UTF-8 sequence starting at byte
+    /// `offset` of a string, or 0 once the offset is at its end. A byte that does not start a
+    /// sequence counts as one, and a sequence cut short by the end of the string as the bytes left.
+    CharWidth,
+}
+
+impl Builtin {
+    /// Every built-in function, numbered in bytecode files by its position
+    pub const ALL: [Builtin; 36] = [
+        Builtin::Println,
+        Builtin::Panic,
+        Builtin::Ok,
+        Builtin::Err,
+        Builtin::Unwrap,
+        Builtin::UnwrapErr,
+        Builtin::IsOk,
+        Builtin::IsErr,
+        Builtin::Print,
+        Builtin::PrintPadded,
+        Builtin::Input,
+        Builtin::ToString,
+        Builtin::Concat,
+        Builtin::Int,
+        Builtin::Float,
+        Builtin::Char,
+        Builtin::Min,
+        Builtin::Max,
+        Builtin::Abs,
+        Builtin::Pow,
+        Builtin::Sqrt,
+        Builtin::Ceil,
+        Builtin::Floor,
+        Builtin::Sin,
+        Builtin::Cos,
+        Builtin::Log,
+        Builtin::Ln,
+        Builtin::PowE,
+        Builtin::Open,
+        Builtin::Close,
+        Builtin::Read,
+        Builtin::ReadLine,
+        Builtin::Write,
+        Builtin::NextLine,
+        Builtin::CharAt,
+        Builtin::CharWidth,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::ALL
+            .into_iter()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    /// The smallest and largest number of arguments the built-in function accepts
+    pub fn arity(&self) -> (u32, u32) {
+        match self {
+            Builtin::Println | Builtin::Ok => (0, 1),
+            Builtin::PrintPadded => (4, 4),
+            Builtin::Input => (0, 0),
+            Builtin::Concat
+            | Builtin::Min
+            | Builtin::Max
+            | Builtin::Pow
+            | Builtin::Open
+            | Builtin::Write
+            | Builtin::CharAt
+            | Builtin::CharWidth => (2, 2),
+            _ => (1, 1),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Println => "println",
+            Builtin::Panic => "panic",
+            Builtin::Ok => "Ok",
+            Builtin::Err => "Err",
+            Builtin::Unwrap => "unwrap",
+            Builtin::UnwrapErr => "unwrap_err",
+            Builtin::IsOk => "is_ok",
+            Builtin::IsErr => "is_err",
+            Builtin::Print => "print",
+            Builtin::PrintPadded => "print_padded",
+            Builtin::Input => "input",
+            Builtin::ToString => "string",
+            Builtin::Concat => "concat",
+            Builtin::Int => "int",
+            Builtin::Float => "float",
+            Builtin::Char => "char",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::Abs => "abs",
+            Builtin::Pow => "pow",
+            Builtin::Sqrt => "sqrt",
+            Builtin::Ceil => "ceil",
+            Builtin::Floor => "floor",
+            Builtin::Sin => "sin",
+            Builtin::Cos => "cos",
+            Builtin::Log => "log",
+            Builtin::Ln => "ln",
+            Builtin::PowE => "pow_e",
+            Builtin::Open => "open",
+            Builtin::Close => "close",
+            Builtin::Read => "read",
+            Builtin::ReadLine => "read_line",
+            Builtin::Write => "write",
+            Builtin::NextLine => "next_line",
+            Builtin::CharAt => "char_at",
+            Builtin::CharWidth => "char_width",
+        }
+    }
+
+    /// Whether the built-in function is one of `runtime::math`'s
+    pub fn is_math(&self) -> bool {
+        math::FUNCTIONS.contains(&self.name())
+    }
+
+    /// Whether the built-in function reads, writes, opens or closes a file
+    pub fn is_file(&self) -> bool {
+        matches!(
+            self,
+            Builtin::Open
+                | Builtin::Close
+                | Builtin::Read
+                | Builtin::ReadLine
+                | Builtin::Write
+                | Builtin::NextLine
+        )
+    }
+}
+
+impl Program {
+    /// The index of the function named `name`
+    pub fn function_index(&self, name: &str) -> Option<usize> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+    }
+}
+
+impl Function {
+    pub fn block(&self, block: Block) -> &BasicBlock {
+        &self.blocks[block.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, block: Block) -> &mut BasicBlock {
+        &mut self.blocks[block.0 as usize]
+    }
+
+    /// Every block, in order
+    pub fn block_ids(&self) -> impl Iterator<Item = Block> {
+        (0..self.blocks.len() as u32).map(Block)
+    }
+
+    pub fn value_type(&self, value: Value) -> &ValueType {
+        &self.values[value.0 as usize]
+    }
+
+    /// The crawfish type of a value that is not a heap cell
+    pub fn type_of(&self, value: Value) -> &Type {
+        match self.value_type(value) {
+            ValueType::Value(ty) | ValueType::Cell(ty) => ty,
+        }
+    }
+
+    /// Adds a value of type `ty`, which something must then define
+    pub fn new_value(&mut self, ty: ValueType) -> Value {
+        self.values.push(ty);
+        Value(self.values.len() as u32 - 1)
+    }
+
+    /// Adds an empty block ending in `unreachable`
+    pub fn new_block(&mut self) -> Block {
+        self.blocks.push(BasicBlock {
+            phis: Vec::new(),
+            instructions: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        Block(self.blocks.len() as u32 - 1)
+    }
+}
+
+impl InstructionKind {
+    /// The values the instruction reads
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            InstructionKind::Const(_)
+            | InstructionKind::Function(_)
+            | InstructionKind::Capture(_) => {
+                vec![]
+            }
+            InstructionKind::NewCell(value)
+            | InstructionKind::LoadCell(value)
+            | InstructionKind::RangeStart(value)
+            | InstructionKind::IsNull(value)
+            | InstructionKind::Payload(value)
+            | InstructionKind::Unary { operand: value, .. } => vec![*value],
+            InstructionKind::StoreCell { cell: a, value: b }
+            | InstructionKind::Binary {
+                left: a, right: b, ..
+            }
+            | InstructionKind::Range {
+                start: a, end: b, ..
+            }
+            | InstructionKind::RangeContains { range: a, item: b } => vec![*a, *b],
+            InstructionKind::Closure { captures: args, .. }
+            | InstructionKind::Call { args, .. }
+            | InstructionKind::CallBuiltin { args, .. } => args.clone(),
+            InstructionKind::CallValue { callee, args } => {
+                let mut operands = vec![*callee];
+                operands.extend(args);
+                operands
+            }
+        }
+    }
+
+    /// Every value the instruction reads, for renaming them
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            InstructionKind::Const(_)
+            | InstructionKind::Function(_)
+            | InstructionKind::Capture(_) => {
+                vec![]
+            }
+            InstructionKind::NewCell(value)
+            | InstructionKind::LoadCell(value)
+            | InstructionKind::RangeStart(value)
+            | InstructionKind::IsNull(value)
+            | InstructionKind::Payload(value)
+            | InstructionKind::Unary { operand: value, .. } => vec![value],
+            InstructionKind::StoreCell { cell: a, value: b }
+            | InstructionKind::Binary {
+                left: a, right: b, ..
+            }
+            | InstructionKind::Range {
+                start: a, end: b, ..
+            }
+            | InstructionKind::RangeContains { range: a, item: b } => vec![a, b],
+            InstructionKind::Closure { captures: args, .. }
+            | InstructionKind::Call { args, .. }
+            | InstructionKind::CallBuiltin { args, .. } => args.iter_mut().collect(),
+            InstructionKind::CallValue { callee, args } => {
+                std::iter::once(callee).chain(args).collect()
+            }
+        }
+    }
+}
+
+impl Terminator {
+    /// The blocks control may continue with
+    pub fn successors(&self) -> Vec<Block> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut Block> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    /// The value the terminator reads, if any
+    pub fn operand(&self) -> Option<Value> {
+        match self {
+            Terminator::Branch { condition, .. } => Some(*condition),
+            Terminator::Return(value) => Some(*value),
+            Terminator::Jump(_) | Terminator::Unreachable => None,
+        }
+    }
+
+    pub fn operand_mut(&mut self) -> Option<&mut Value> {
+        match self {
+            Terminator::Branch { condition, .. } => Some(condition),
+            Terminator::Return(value) => Some(value),
+            Terminator::Jump(_) | Terminator::Unreachable => None,
+        }
+    }
+}
+
+/// The mnemonic of a unary operator
+pub fn unary_mnemonic(op: UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::Negate => "neg",
+        UnaryOp::Not => "not",
+        UnaryOp::BitNot => "bitnot",
+    }
+}
+
+/// The mnemonic of a binary operator that is not short-circuiting
+pub fn binary_mnemonic(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "add",
+        BinaryOp::Subtract => "sub",
+        BinaryOp::Multiply => "mul",
+        BinaryOp::Divide => "div",
+        BinaryOp::Remainder => "rem",
+        BinaryOp::Equal => "eq",
+        BinaryOp::NotEqual => "ne",
+        BinaryOp::Less => "lt",
+        BinaryOp::LessEqual => "le",
+        BinaryOp::Greater => "gt",
+        BinaryOp::GreaterEqual => "ge",
+        BinaryOp::BitAnd => "and",
+        BinaryOp::BitOr => "or",
+        BinaryOp::BitXor => "xor",
+        BinaryOp::ShiftLeft => "shl",
+        BinaryOp::ShiftRight => "shr",
+        BinaryOp::And | BinaryOp::Or | BinaryOp::Coalesce => {
+            unreachable!("short-circuiting operators are lowered to branches")
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Value(ty) => write!(f, "{}", ty),
+            ValueType::Cell(ty) => write!(f, "Cell[{}]", ty),
+        }
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(value) => write!(f, "{}", value),
+            Constant::Float(value) => write!(f, "{:?}", value),
+            Constant::Bool(value) => write!(f, "{}", value),
+            Constant::Char(value) => write!(f, "{:?}", value),
+            Constant::String(value) => write!(f, "{:?}", value),
+            Constant::Unit => write!(f, "()"),
+            Constant::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// Writes `(%a, %b, ...)`
+fn write_args(f: &mut fmt::Formatter<'_>, args: &[Value]) -> fmt::Result {
+    write!(f, "(")?;
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", arg)?;
+    }
+    write!(f, ")")
+}
+
+/// Prints the program in the textual form read by `parser`, one function after another
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            FunctionPrinter {
+                function,
+                program: Some(self),
+            }
+            .fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the function alone, naming the functions it refers to by their index
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        FunctionPrinter {
+            function: self,
+            program: None,
+        }
+        .fmt(f)
+    }
+}
+
+/// Prints a function, naming the functions it refers to after their declaration in `program`
+struct FunctionPrinter<'p> {
+    function: &'p Function,
+    program: Option<&'p Program>,
+}
+
+impl FunctionPrinter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let function = self.function;
+        write!(f, "func @{}(", function.name)?;
+        for (i, param) in function.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", param, function.value_type(*param))?;
+        }
+        write!(f, ") -> {}", function.return_type)?;
+        if !function.captures.is_empty() {
+            write!(f, " captures [")?;
+            for (i, capture) in function.captures.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", capture)?;
+            }
+            write!(f, "]")?;
+        }
+        writeln!(f, " {{")?;
+        for block in function.block_ids() {
+            writeln!(f, "{}:", block)?;
+            let block = function.block(block);
+            for phi in &block.phis {
+                write!(
+                    f,
+                    "    {}: {} = phi",
+                    phi.result,
+                    function.value_type(phi.result)
+                )?;
+                for (i, (from, value)) in phi.incoming.iter().enumerate() {
+                    let separator = if i > 0 { "," } else { "" };
+                    write!(f, "{} [{}: {}]", separator, from, value)?;
+                }
+                writeln!(f)?;
+            }
+            for instruction in &block.instructions {
+                write!(f, "    ")?;
+                if let Some(result) = instruction.result {
+                    write!(f, "{}: {} = ", result, function.value_type(result))?;
+                }
+                self.instruction(f, &instruction.kind)?;
+                writeln!(f)?;
+            }
+            match &block.terminator {
+                Terminator::Jump(target) => writeln!(f, "    jump {}", target)?,
+                Terminator::Branch {
+                    condition,
+                    then,
+                    otherwise,
+                } => writeln!(f, "    branch {}, {}, {}", condition, then, otherwise)?,
+                Terminator::Return(value) => writeln!(f, "    return {}", value)?,
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+
+    fn function_name(&self, f: &mut fmt::Formatter<'_>, index: usize) -> fmt::Result {
+        match self.program {
+            Some(program) => write!(f, "@{}", program.functions[index].name),
+            None => write!(f, "@{}", index),
+        }
+    }
+
+    fn instruction(&self, f: &mut fmt::Formatter<'_>, kind: &InstructionKind) -> fmt::Result {
+        match kind {
+            InstructionKind::Const(constant) => write!(f, "const {}", constant),
+            InstructionKind::Function(function) => {
+                write!(f, "function ")?;
+                self.function_name(f, *function)
+            }
+            InstructionKind::Closure { function, captures } => {
+                write!(f, "closure ")?;
+                self.function_name(f, *function)?;
+                write_args(f, captures)
+            }
+            InstructionKind::Capture(slot) => write!(f, "capture {}", slot),
+            InstructionKind::NewCell(value) => write!(f, "cell.new {}", value),
+            InstructionKind::LoadCell(cell) => write!(f, "cell.load {}", cell),
+            InstructionKind::StoreCell { cell, value } => {
+                write!(f, "cell.store {}, {}", cell, value)
+            }
+            InstructionKind::Unary { op, operand } => {
+                write!(f, "{} {}", unary_mnemonic(*op), operand)
+            }
+            InstructionKind::Binary { op, left, right } => {
+                write!(f, "{} {}, {}", binary_mnemonic(*op), left, right)
+            }
+            InstructionKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let mnemonic = if *inclusive {
+                    "range.inclusive"
+                } else {
+                    "range"
+                };
+                write!(f, "{} {}, {}", mnemonic, start, end)
+            }
+            InstructionKind::RangeStart(range) => write!(f, "range.start {}", range),
+            InstructionKind::RangeContains { range, item } => {
+                write!(f, "range.contains {}, {}", range, item)
+            }
+            InstructionKind::IsNull(value) => write!(f, "is_null {}", value),
+            InstructionKind::Payload(value) => write!(f, "payload {}", value),
+            InstructionKind::Call { function, args } => {
+                write!(f, "call ")?;
+                self.function_name(f, *function)?;
+                write_args(f, args)
+            }
+            InstructionKind::CallValue { callee, args } => {
+                write!(f, "call {}", callee)?;
+                write_args(f, args)
+            }
+            InstructionKind::CallBuiltin { builtin, args } => {
+                write!(f, "builtin {}", builtin.name())?;
+                write_args(f, args)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end::{parser, type_checker};
+    use std::fs;
+    use std::path::Path;
+
+    fn build_source(source: &str) -> Program {
+        let mut program = parser::parse(source).unwrap();
+        type_checker::check(&mut program).unwrap();
+        build(&program)
+    }
+
+    /// Builds every program in `ir/golden` and compares its IR with the `.ir` file next to it.
+    /// Setting `CRAWFISH_UPDATE_GOLDEN` rewrites the `.ir` files instead.
+    #[test]
+    fn test_golden_files() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/back_end/ir/golden");
+        let mut sources: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "crw"))
+            .collect();
+        sources.sort();
+        assert!(!sources.is_empty());
+        for source in sources {
+            let program = build_source(&fs::read_to_string(&source).unwrap());
+            verify(&program).unwrap();
+            let text = program.to_string();
+            let expected = source.with_extension("ir");
+            if std::env::var_os("CRAWFISH_UPDATE_GOLDEN").is_some() {
+                fs::write(&expected, &text).unwrap();
+            }
+            assert_eq!(
+                text,
+                fs::read_to_string(&expected).unwrap(),
+                "{}",
+                source.display()
+            );
+
+            let parsed = parse(&text).unwrap();
+            verify(&parsed).unwrap();
+            assert_eq!(parsed.to_string(), text, "{}", source.display());
+        }
+    }
+
+    #[test]
+    fn test_loop_variables_become_phis() {
+        let program =
+            build_source("func main() { var i = 0; while i < 3 { i += 1; } println(i); }");
+        let main = &program.functions[program.entry];
+        let header = main.block(Block(1));
+        assert_eq!(header.phis.len(), 1);
+        let phi = &header.phis[0];
+        assert_eq!(phi.incoming.len(), 2);
+        assert_eq!(main.value_type(phi.result), &ValueType::Value(Type::Int));
+    }
+
+    #[test]
+    fn test_unreachable_code_is_removed() {
+        let program =
+            build_source("func f() -> Int { return 1; println(2); } func main() { println(f()); }");
+        let f = &program.functions[0];
+        assert_eq!(f.blocks.len(), 1);
+        assert_eq!(
+            f.to_string(),
+            "func @f() -> Int {\nbb0:\n    %0: Int = const 1\n    return %0\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_hand_written_function() {
+        let text = r#"
+            ; Counts down from n, referring to values before they are defined
+            func @count(%n: Int) -> Int {
+            entry:
+                jump loop
+            loop:
+                %i: Int = phi [entry: %n], [body: %next]
+                %zero: Int = const 0
+                %done: Bool = le %i, %zero
+                branch %done, exit, body
+            body:
+                %one: Int = const 1
+                %next: Int = sub %i, %one
+                jump loop
+            exit:
+                return %i
+            }
+        "#;
+        let program = parse(text);
+        assert!(program.is_err(), "value names are numbers");
+
+        let text = text
+            .replace("%next", "%5")
+            .replace("%n", "%0")
+            .replace("%i", "%1")
+            .replace("%zero", "%2")
+            .replace("%done", "%3")
+            .replace("%one", "%4");
+        let program = parse(&text).unwrap();
+        verify(&program).unwrap();
+        let function = &program.functions[0];
+        assert_eq!(function.blocks.len(), 4);
+        assert_eq!(
+            function.block(Block(1)).phis[0].incoming,
+            [(Block(0), Value(0)), (Block(2), Value(5))]
+        );
+        assert!(program
+            .to_string()
+            .contains("    %1: Int = phi [bb0: %0], [bb2: %5]\n"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse("func @f() -> Int {\nbb0:\n    %0: Int = frobnicate\n}"),
+            Err(IrError::Syntax {
+                line: 3,
+                message: "unknown instruction `frobnicate`".to_string()
+            })
+        );
+        assert_eq!(
+            parse("func @f() -> Int {\nbb0:\n    jump bb1\n}"),
+            Err(IrError::Syntax {
+                line: 3,
+                message: "unknown block `bb1`".to_string()
+            })
+        );
+        assert_eq!(
+            parse("func @f() -> Int {\nbb0:\n    return %4\n}"),
+            Err(IrError::Invalid {
+                function: "f".to_string(),
+                reason: "value `%4` is never defined".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_verifier_rejects_malformed_functions() {
+        let check = |text: &str| verify(&parse(text).unwrap()).unwrap_err().to_string();
+        assert_eq!(
+            check("func @f() -> Int {\nbb0:\n    branch %0, bb1, bb1\nbb1:\n    %0: Bool = const true\n    unreachable\n}"),
+            "Invalid IR in `f`: bb0 branches to bb1 either way"
+        );
+        assert_eq!(
+            check("func @f() -> Int {\nbb0:\n    %0: Bool = const true\n    branch %0, bb1, bb2\nbb1:\n    %1: Int = const 1\n    jump bb3\nbb2:\n    jump bb3\nbb3:\n    return %1\n}"),
+            "Invalid IR in `f`: %1 is used in bb3 where its definition does not dominate the use"
+        );
+        assert_eq!(
+            check("func @f() -> Int {\nbb0:\n    %0: Int = const 1\n    jump bb1\nbb1:\n    %1: Int = phi [bb0: %0], [bb1: %0]\n    return %1\n}"),
+            "Invalid IR in `f`: the incoming blocks of %1 are not the predecessors of bb1"
+        );
+        assert_eq!(
+            check("func @f() -> Int {\nbb0:\n    %0: Int = const 1\n    %1: Int = call @f(%0)\n    return %1\n}"),
+            "Invalid IR in `f`: `f` takes 0 arguments, not 1"
+        );
+        assert_eq!(
+            check("func @f() -> () {\nbb0:\n    %0: Int = const 1\n    %1: func() -> Int = closure @g(%0)\n    %2: () = const ()\n    return %2\n}\n\nfunc @g() -> Int captures [Cell[Int]] {\nbb0:\n    %0: Cell[Int] = capture 0\n    %1: Int = cell.load %0\n    return %1\n}"),
+            "Invalid IR in `f`: slot 0 of `g` holds Cell[Int], but %0 is Int"
+        );
+    }
+}
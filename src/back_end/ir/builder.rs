@@ -0,0 +1,1053 @@
+//! Construction of the IR of a type checked program, after closure conversion.
+//! Local variables become SSA values as the body is lowered, with the algorithm of Braun et al.,
+//! "Simple and Efficient Construction of Static Single Assignment Form": a variable read in a block
+//! that does not assign it is looked up in its predecessors, through a phi when there are several.
+//! Blocks whose predecessors are not all known yet (loop headers) are sealed once they are, and the
+//! phis that turn out to merge a single value are replaced by it.
+use crate::back_end::closure_conversion::{self, CaptureMode, ConvertedFunction, ConvertedProgram};
+use crate::back_end::ir::passes::{self, resolve, trivial_value};
+use crate::back_end::ir::{
+    Block, Builtin, Constant, Function, Instruction, InstructionKind, Phi, Program, Terminator,
+    Value, ValueType,
+};
+use crate::front_end::ast::{self, BinaryOp, Expr, ExprKind, Literal, Stmt, StmtKind};
+use crate::front_end::format::{self, Piece};
+use crate::front_end::token::Span;
+use crate::front_end::type_checker;
+use crate::front_end::types::Type;
+use std::collections::HashMap;
+
+/// Builds the IR of a type checked program
+pub fn build(program: &ast::Program) -> Program {
+    let converted = closure_conversion::convert(program);
+    let indices: HashMap<&str, usize> = converted
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(index, function)| (function.name.as_str(), index))
+        .collect();
+    let functions = converted
+        .functions
+        .iter()
+        .map(|function| FunctionBuilder::new(&converted, &indices, function).build())
+        .collect();
+    Program {
+        functions,
+        entry: indices["main"],
+    }
+}
+
+/// A variable in scope, which is either renamed to SSA values or lives in a heap cell because a
+/// closure captures it by reference
+#[derive(Clone, Copy)]
+enum Binding {
+    Variable(usize),
+    Cell(Value),
+}
+
+/// The targets of `continue` and `break` in a loop being lowered, and the number of blocks
+/// around its body, whose deferred expressions these leave in place
+struct Loop {
+    continue_target: Block,
+    exit: Block,
+    deferred: usize,
+}
+
+/// A deferred expression, and the scopes its variables are looked up in
+type Deferred<'p> = (&'p Expr, Vec<HashMap<&'p str, Binding>>);
+
+/// Function builder
+/// - `deferred` holds, for each block being lowered, innermost last, the expressions deferred in it
+///   so far. They are lowered again wherever control leaves the block.
+/// - `current` is the block instructions are added to, or `None` after a terminator, until code
+///   that control can reach again starts. Code in between goes to blocks without predecessors,
+///   which are removed once the function is complete.
+/// - `variables` holds the type of every SSA variable, including the temporaries holding the
+///   result of conditional expressions
+/// - `definitions` maps a variable and a block to the value the variable has at the end of it
+/// - `incomplete` holds the phis of unsealed blocks, whose operands are added when they are sealed
+/// - `forward` maps the phis found to be trivial to the value they are replaced by
+struct FunctionBuilder<'p, 'c> {
+    program: &'p ConvertedProgram,
+    indices: &'c HashMap<&'p str, usize>,
+    source: &'p ConvertedFunction,
+    function: Function,
+    scopes: Vec<HashMap<&'p str, Binding>>,
+    loops: Vec<Loop>,
+    deferred: Vec<Vec<Deferred<'p>>>,
+    current: Option<Block>,
+    variables: Vec<Type>,
+    definitions: HashMap<(usize, Block), Value>,
+    predecessors: Vec<Vec<Block>>,
+    sealed: Vec<bool>,
+    incomplete: Vec<Vec<(usize, Value)>>,
+    phi_blocks: HashMap<Value, Block>,
+    forward: HashMap<Value, Value>,
+}
+
+impl<'p, 'c> FunctionBuilder<'p, 'c> {
+    fn new(
+        program: &'p ConvertedProgram,
+        indices: &'c HashMap<&'p str, usize>,
+        source: &'p ConvertedFunction,
+    ) -> Self {
+        let captures = source
+            .environment
+            .iter()
+            .map(|slot| match slot.mode {
+                CaptureMode::Value => ValueType::Value(slot.ty.clone()),
+                CaptureMode::Reference => ValueType::Cell(slot.ty.clone()),
+            })
+            .collect();
+        let function = Function {
+            name: source.name.clone(),
+            captures,
+            params: Vec::new(),
+            return_type: source.return_type.clone(),
+            values: Vec::new(),
+            blocks: Vec::new(),
+            file: source.file,
+        };
+        Self {
+            program,
+            indices,
+            source,
+            function,
+            scopes: vec![HashMap::new()],
+            loops: Vec::new(),
+            deferred: Vec::new(),
+            current: None,
+            variables: Vec::new(),
+            definitions: HashMap::new(),
+            predecessors: Vec::new(),
+            sealed: Vec::new(),
+            incomplete: Vec::new(),
+            phi_blocks: HashMap::new(),
+            forward: HashMap::new(),
+        }
+    }
+
+    fn build(mut self) -> Function {
+        let source = self.source;
+        let span = source.body.span;
+        let entry = self.new_block();
+        self.seal(entry);
+        self.current = Some(entry);
+        for (slot, environment) in source.environment.iter().enumerate() {
+            let kind = InstructionKind::Capture(slot as u32);
+            let ty = self.function.captures[slot].clone();
+            let value = self.emit(kind, Some(ty), span).unwrap();
+            match environment.mode {
+                CaptureMode::Value => self.declare(&environment.name, &environment.ty, value),
+                CaptureMode::Reference => {
+                    self.scope().insert(&environment.name, Binding::Cell(value));
+                }
+            }
+        }
+        for (name, ty) in &source.params {
+            let value = self.function.new_value(ValueType::Value(ty.clone()));
+            self.function.params.push(value);
+            self.bind(name, ty, value, span);
+        }
+        let value = self.block(&source.body);
+        self.terminate(Terminator::Return(value));
+        self.finish()
+    }
+
+    fn scope(&mut self) -> &mut HashMap<&'p str, Binding> {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+    }
+
+    /// Declares a variable holding `value`, in a heap cell if a closure captures it by reference
+    fn bind(&mut self, name: &'p ast::Ident, ty: &Type, value: Value, span: Span) {
+        let boxed = name
+            .variable
+            .is_some_and(|id| self.source.boxed.contains(&id));
+        if boxed {
+            let kind = InstructionKind::NewCell(value);
+            let cell = self.emit(kind, Some(ValueType::Cell(ty.clone())), span);
+            self.scope()
+                .insert(&name.name, Binding::Cell(cell.unwrap()));
+        } else {
+            self.declare(&name.name, ty, value);
+        }
+    }
+
+    /// Declares an SSA variable holding `value`
+    fn declare(&mut self, name: &'p str, ty: &Type, value: Value) {
+        let variable = self.temporary(ty.clone());
+        self.assign(variable, value);
+        self.scope().insert(name, Binding::Variable(variable));
+    }
+
+    /// Adds an SSA variable that is not in scope, such as the result of a conditional expression
+    fn temporary(&mut self, ty: Type) -> usize {
+        self.variables.push(ty);
+        self.variables.len() - 1
+    }
+
+    fn load(&mut self, binding: Binding, span: Span) -> Value {
+        match binding {
+            Binding::Variable(variable) => self.read(variable),
+            Binding::Cell(cell) => {
+                let ty = self.function.type_of(cell).clone();
+                self.value(InstructionKind::LoadCell(cell), ty, span)
+            }
+        }
+    }
+
+    fn store(&mut self, binding: Binding, value: Value, span: Span) {
+        match binding {
+            Binding::Variable(variable) => self.assign(variable, value),
+            Binding::Cell(cell) => {
+                self.emit(InstructionKind::StoreCell { cell, value }, None, span);
+            }
+        }
+    }
+
+    fn binding_type(&self, binding: Binding) -> Type {
+        match binding {
+            Binding::Variable(variable) => self.variables[variable].clone(),
+            Binding::Cell(cell) => self.function.type_of(cell).clone(),
+        }
+    }
+
+    fn new_block(&mut self) -> Block {
+        self.predecessors.push(Vec::new());
+        self.sealed.push(false);
+        self.incomplete.push(Vec::new());
+        self.function.new_block()
+    }
+
+    /// The block to add instructions to, which is a new block without predecessors after a terminator
+    fn block_for_code(&mut self) -> Block {
+        match self.current {
+            Some(block) => block,
+            None => {
+                let block = self.new_block();
+                self.seal(block);
+                self.current = Some(block);
+                block
+            }
+        }
+    }
+
+    /// Adds an instruction defining a value of type `ty`, or no value
+    fn emit(&mut self, kind: InstructionKind, ty: Option<ValueType>, span: Span) -> Option<Value> {
+        let block = self.block_for_code();
+        let result = ty.map(|ty| self.function.new_value(ty));
+        self.function
+            .block_mut(block)
+            .instructions
+            .push(Instruction { result, kind, span });
+        result
+    }
+
+    fn value(&mut self, kind: InstructionKind, ty: Type, span: Span) -> Value {
+        self.emit(kind, Some(ValueType::Value(ty)), span).unwrap()
+    }
+
+    fn constant(&mut self, constant: Constant, ty: Type, span: Span) -> Value {
+        self.value(InstructionKind::Const(constant), ty, span)
+    }
+
+    /// Ends the current block. Blocks that control cannot reach do not count as predecessors.
+    fn terminate(&mut self, terminator: Terminator) {
+        let Some(block) = self.current.take() else {
+            return;
+        };
+        if block.0 == 0 || !self.predecessors[block.0 as usize].is_empty() {
+            for successor in terminator.successors() {
+                self.predecessors[successor.0 as usize].push(block);
+            }
+        }
+        self.function.block_mut(block).terminator = terminator;
+    }
+
+    fn jump(&mut self, target: Block) {
+        self.terminate(Terminator::Jump(target));
+    }
+
+    fn branch(&mut self, condition: Value, then: Block, otherwise: Block) {
+        self.terminate(Terminator::Branch {
+            condition,
+            then,
+            otherwise,
+        });
+    }
+
+    /// Continues in `block`, whose predecessors are all known
+    fn switch_to_sealed(&mut self, block: Block) {
+        self.seal(block);
+        self.current = Some(block);
+    }
+
+    /// Assigns `value` to a variable at the end of the current block
+    fn assign(&mut self, variable: usize, value: Value) {
+        if let Some(block) = self.current {
+            self.definitions.insert((variable, block), value);
+        }
+    }
+
+    /// The value of a variable in the current block
+    fn read(&mut self, variable: usize) -> Value {
+        let block = self.block_for_code();
+        self.read_in(variable, block)
+    }
+
+    fn read_in(&mut self, variable: usize, block: Block) -> Value {
+        match self.definitions.get(&(variable, block)) {
+            Some(&value) => self.resolve(value),
+            None => self.read_recursive(variable, block),
+        }
+    }
+
+    fn read_recursive(&mut self, variable: usize, block: Block) -> Value {
+        let predecessors = &self.predecessors[block.0 as usize];
+        let value = if !self.sealed[block.0 as usize] {
+            let phi = self.new_phi(variable, block);
+            self.incomplete[block.0 as usize].push((variable, phi));
+            phi
+        } else if predecessors.len() == 1 {
+            self.read_in(variable, predecessors[0])
+        } else {
+            let phi = self.new_phi(variable, block);
+            self.definitions.insert((variable, block), phi);
+            self.add_phi_operands(variable, phi)
+        };
+        self.definitions.insert((variable, block), value);
+        value
+    }
+
+    fn new_phi(&mut self, variable: usize, block: Block) -> Value {
+        let ty = ValueType::Value(self.variables[variable].clone());
+        let result = self.function.new_value(ty);
+        self.function.block_mut(block).phis.push(Phi {
+            result,
+            incoming: Vec::new(),
+        });
+        self.phi_blocks.insert(result, block);
+        result
+    }
+
+    fn phi_mut(&mut self, phi: Value) -> &mut Phi {
+        let block = self.phi_blocks[&phi];
+        self.function
+            .block_mut(block)
+            .phis
+            .iter_mut()
+            .find(|candidate| candidate.result == phi)
+            .unwrap()
+    }
+
+    fn add_phi_operands(&mut self, variable: usize, phi: Value) -> Value {
+        let block = self.phi_blocks[&phi];
+        for predecessor in self.predecessors[block.0 as usize].clone() {
+            let value = self.read_in(variable, predecessor);
+            self.phi_mut(phi).incoming.push((predecessor, value));
+        }
+        self.try_remove_trivial_phi(phi)
+    }
+
+    /// Replaces a phi whose operands are all the same value, or the phi itself, by that value
+    fn try_remove_trivial_phi(&mut self, phi: Value) -> Value {
+        let incoming: Vec<Value> = self.phi_mut(phi).incoming.iter().map(|(_, v)| *v).collect();
+        match trivial_value(phi, incoming, &self.forward) {
+            Some(same) => {
+                self.forward.insert(phi, same);
+                same
+            }
+            None => phi,
+        }
+    }
+
+    fn resolve(&self, value: Value) -> Value {
+        resolve(&self.forward, value)
+    }
+
+    /// Marks a block whose predecessors are all known, completing the phis read in it so far
+    fn seal(&mut self, block: Block) {
+        for (variable, phi) in std::mem::take(&mut self.incomplete[block.0 as usize]) {
+            self.add_phi_operands(variable, phi);
+        }
+        self.sealed[block.0 as usize] = true;
+    }
+
+    /// Lowers a block, returning its value
+    fn block(&mut self, block: &'p ast::Block) -> Value {
+        self.scopes.push(HashMap::new());
+        self.deferred.push(Vec::new());
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        let value = match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => self.constant(Constant::Unit, Type::Unit, block.span),
+        };
+        self.leave_blocks(self.deferred.len() - 1);
+        self.deferred.pop();
+        self.scopes.pop();
+        value
+    }
+
+    /// Lowers a block whose value is not used
+    fn block_effect(&mut self, block: &'p ast::Block) {
+        self.scopes.push(HashMap::new());
+        self.deferred.push(Vec::new());
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            self.effect(tail);
+        }
+        self.leave_blocks(self.deferred.len() - 1);
+        self.deferred.pop();
+        self.scopes.pop();
+    }
+
+    /// Lowers the expressions deferred in the blocks being lowered that control leaves to go to
+    /// the `outer` innermost of them, the latest first
+    fn leave_blocks(&mut self, outer: usize) {
+        if self.current.is_none() {
+            return;
+        }
+        let deferred: Vec<Deferred<'p>> = self.deferred[outer..]
+            .iter()
+            .rev()
+            .flat_map(|block| block.iter().rev().cloned())
+            .collect();
+        for (expr, scopes) in deferred {
+            let scopes = std::mem::replace(&mut self.scopes, scopes);
+            self.effect(expr);
+            self.scopes = scopes;
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'p Stmt) {
+        let span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Var {
+                name, ty, value, ..
+            } => {
+                let ty = ty
+                    .as_ref()
+                    .map_or_else(|| value.ty.clone(), type_checker::resolve_annotation);
+                let value = self.expr(value);
+                self.bind(name, &ty, value, span);
+            }
+            StmtKind::Assign { target, op, value } => {
+                let ExprKind::Identifier(name) = &target.kind else {
+                    unreachable!("the type checker only accepts variables as assignment targets");
+                };
+                let binding = self.lookup(name).expect("assigned variables are declared");
+                let value = match op {
+                    Some(op) => {
+                        let current = self.load(binding, span);
+                        let right = self.expr(value);
+                        let kind = InstructionKind::Binary {
+                            op: *op,
+                            left: current,
+                            right,
+                        };
+                        let ty = self.binding_type(binding);
+                        self.value(kind, ty, span)
+                    }
+                    None => self.expr(value),
+                };
+                self.store(binding, value, span);
+            }
+            StmtKind::Expr(expr) => self.effect(expr),
+            StmtKind::While { cond, body } => {
+                let header = self.new_block();
+                self.jump(header);
+                self.current = Some(header);
+                let condition = self.expr(cond);
+                let (entered, exit) = (self.new_block(), self.new_block());
+                self.branch(condition, entered, exit);
+                self.switch_to_sealed(entered);
+                self.loop_body(body, header, exit);
+                self.jump(header);
+                self.seal(header);
+                self.switch_to_sealed(exit);
+            }
+            StmtKind::For {
+                item,
+                iterable,
+                body,
+            } => self.for_loop(item, iterable, body, span),
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.expr(value),
+                    None => self.constant(Constant::Unit, Type::Unit, span),
+                };
+                self.leave_blocks(0);
+                self.terminate(Terminator::Return(value));
+            }
+            StmtKind::Defer(expr) => {
+                let scopes = self.scopes.clone();
+                self.deferred.last_mut().unwrap().push((expr, scopes));
+            }
+            StmtKind::Break => {
+                let target = self.loops.last().expect("loops enclose break");
+                let (exit, deferred) = (target.exit, target.deferred);
+                self.leave_blocks(deferred);
+                self.jump(exit);
+            }
+            StmtKind::Continue => {
+                let target = self.loops.last().expect("loops enclose continue");
+                let (continue_target, deferred) = (target.continue_target, target.deferred);
+                self.leave_blocks(deferred);
+                self.jump(continue_target);
+            }
+        }
+    }
+
+    fn loop_body(&mut self, body: &'p ast::Block, continue_target: Block, exit: Block) {
+        self.loops.push(Loop {
+            continue_target,
+            exit,
+            deferred: self.deferred.len(),
+        });
+        self.block_effect(body);
+        self.loops.pop();
+    }
+
+    /// Lowers a `for` loop over a range, counting from its start until an item is past its end.
+    /// The counter is not incremented past the largest integer, which may be the last item.
+    fn for_loop(
+        &mut self,
+        item: &'p ast::Ident,
+        iterable: &'p Expr,
+        body: &'p ast::Block,
+        span: Span,
+    ) {
+        match iterable.ty {
+            Type::File => return self.for_lines(item, iterable, body, span),
+            Type::String => return self.for_chars(item, iterable, body, span),
+            _ => (),
+        }
+        if let ExprKind::Range {
+            start,
+            end,
+            inclusive,
+        } = &iterable.kind
+        {
+            return self.for_bounds(item, start, end, *inclusive, body, span);
+        }
+        let range = self.expr(iterable);
+        let start = self.value(InstructionKind::RangeStart(range), Type::Int, span);
+        let counter = self.temporary(Type::Int);
+        self.assign(counter, start);
+        let header = self.new_block();
+        self.jump(header);
+        self.current = Some(header);
+
+        let value = self.read(counter);
+        let kind = InstructionKind::RangeContains { range, item: value };
+        let contains = self.value(kind, Type::Bool, span);
+        let (entered, exit) = (self.new_block(), self.new_block());
+        self.branch(contains, entered, exit);
+        self.switch_to_sealed(entered);
+        self.scopes.push(HashMap::new());
+        self.bind(item, &Type::Int, value, item.span);
+        let latch = self.new_block();
+        self.loop_body(body, latch, exit);
+        self.scopes.pop();
+        self.jump(latch);
+
+        self.switch_to_sealed(latch);
+        let max = self.constant(Constant::Int(i32::MAX), Type::Int, span);
+        let kind = InstructionKind::Binary {
+            op: BinaryOp::Equal,
+            left: value,
+            right: max,
+        };
+        let last = self.value(kind, Type::Bool, span);
+        let step = self.new_block();
+        self.branch(last, exit, step);
+        self.switch_to_sealed(step);
+        let one = self.constant(Constant::Int(1), Type::Int, span);
+        let kind = InstructionKind::Binary {
+            op: BinaryOp::Add,
+            left: value,
+            right: one,
+        };
+        let next = self.value(kind, Type::Int, span);
+        self.assign(counter, next);
+        self.jump(header);
+        self.seal(header);
+        self.switch_to_sealed(exit);
+    }
+
+    /// Lowers a `for` loop over a range written in place, which is never built: the counter is
+    /// compared with the end directly. An inclusive loop stops after its end rather than stepping
+    /// past it, so that `..=2147483647` cannot overflow.
+    fn for_bounds(
+        &mut self,
+        item: &'p ast::Ident,
+        start: &'p Expr,
+        end: &'p Expr,
+        inclusive: bool,
+        body: &'p ast::Block,
+        span: Span,
+    ) {
+        let start = self.expr(start);
+        let end = self.expr(end);
+        let counter = self.temporary(Type::Int);
+        self.assign(counter, start);
+        let header = self.new_block();
+        self.jump(header);
+        self.current = Some(header);
+
+        let value = self.read(counter);
+        let kind = InstructionKind::Binary {
+            op: if inclusive {
+                BinaryOp::LessEqual
+            } else {
+                BinaryOp::Less
+            },
+            left: value,
+            right: end,
+        };
+        let within = self.value(kind, Type::Bool, span);
+        let (entered, exit) = (self.new_block(), self.new_block());
+        self.branch(within, entered, exit);
+        self.switch_to_sealed(entered);
+        self.scopes.push(HashMap::new());
+        self.bind(item, &Type::Int, value, item.span);
+        let latch = self.new_block();
+        self.loop_body(body, latch, exit);
+        self.scopes.pop();
+        self.jump(latch);
+
+        self.switch_to_sealed(latch);
+        if inclusive {
+            let kind = InstructionKind::Binary {
+                op: BinaryOp::Equal,
+                left: value,
+                right: end,
+            };
+            let last = self.value(kind, Type::Bool, span);
+            let step = self.new_block();
+            self.branch(last, exit, step);
+            self.switch_to_sealed(step);
+        }
+        let one = self.constant(Constant::Int(1), Type::Int, span);
+        let kind = InstructionKind::Binary {
+            op: BinaryOp::Add,
+            left: value,
+            right: one,
+        };
+        let next = self.value(kind, Type::Int, span);
+        self.assign(counter, next);
+        self.jump(header);
+        self.seal(header);
+        self.switch_to_sealed(exit);
+    }
+
+    /// Lowers a `for` loop over the lines of a file, reading the next one until there is none
+    fn for_lines(
+        &mut self,
+        item: &'p ast::Ident,
+        iterable: &'p Expr,
+        body: &'p ast::Block,
+        span: Span,
+    ) {
+        let file = self.expr(iterable);
+        let header = self.new_block();
+        self.jump(header);
+        self.current = Some(header);
+
+        let ty = Type::Optional(Box::new(Type::String));
+        let line = self.builtin(Builtin::NextLine, vec![file], ty, span);
+        let ended = self.value(InstructionKind::IsNull(line), Type::Bool, span);
+        let (entered, exit) = (self.new_block(), self.new_block());
+        self.branch(ended, exit, entered);
+        self.switch_to_sealed(entered);
+        self.scopes.push(HashMap::new());
+        self.bind(item, &Type::String, line, item.span);
+        self.loop_body(body, header, exit);
+        self.scopes.pop();
+        self.jump(header);
+        self.seal(header);
+        self.switch_to_sealed(exit);
+    }
+
+    /// Lowers a `for` loop over the characters of a string, counting the byte offset of the next one
+    /// until the UTF-8 sequence there is empty
+    fn for_chars(
+        &mut self,
+        item: &'p ast::Ident,
+        iterable: &'p Expr,
+        body: &'p ast::Block,
+        span: Span,
+    ) {
+        let string = self.expr(iterable);
+        let offset = self.temporary(Type::Int);
+        let zero = self.constant(Constant::Int(0), Type::Int, span);
+        self.assign(offset, zero);
+        let header = self.new_block();
+        self.jump(header);
+        self.current = Some(header);
+
+        let start = self.read(offset);
+        let args = vec![string, start];
+        let width = self.builtin(Builtin::CharWidth, args.clone(), Type::Int, span);
+        let zero = self.constant(Constant::Int(0), Type::Int, span);
+        let kind = InstructionKind::Binary {
+            op: BinaryOp::Equal,
+            left: width,
+            right: zero,
+        };
+        let ended = self.value(kind, Type::Bool, span);
+        let (entered, exit) = (self.new_block(), self.new_block());
+        self.branch(ended, exit, entered);
+        self.switch_to_sealed(entered);
+        let c = self.builtin(Builtin::CharAt, args, Type::Char, span);
+        let kind = InstructionKind::Binary {
+            op: BinaryOp::Add,
+            left: start,
+            right: width,
+        };
+        let next = self.value(kind, Type::Int, span);
+        self.assign(offset, next);
+        self.scopes.push(HashMap::new());
+        self.bind(item, &Type::Char, c, item.span);
+        self.loop_body(body, header, exit);
+        self.scopes.pop();
+        self.jump(header);
+        self.seal(header);
+        self.switch_to_sealed(exit);
+    }
+
+    /// Lowers an expression whose value is not used
+    fn effect(&mut self, expr: &'p Expr) {
+        match &expr.kind {
+            ExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.conditional(cond, then_branch, else_branch.as_deref(), None);
+            }
+            ExprKind::Block(block) => self.block_effect(block),
+            _ => {
+                self.expr(expr);
+            }
+        }
+    }
+
+    /// Lowers an expression, returning its value
+    fn expr(&mut self, expr: &'p Expr) -> Value {
+        let span = expr.span;
+        let ty = expr.ty.clone();
+        match &expr.kind {
+            ExprKind::Literal(literal) => {
+                let constant = match literal {
+                    // The type checker only accepts integer literals that fit in 32 bits
+                    Literal::Int(value) => Constant::Int(*value as i32),
+                    Literal::Float(value) => Constant::Float(*value),
+                    Literal::Bool(value) => Constant::Bool(*value),
+                    Literal::Char(value) => Constant::Char(*value),
+                    Literal::String(value) => Constant::String(value.clone()),
+                    Literal::Null => Constant::Null,
+                };
+                self.constant(constant, ty, span)
+            }
+            ExprKind::Identifier(name) => match self.lookup(name) {
+                Some(binding) => self.load(binding, span),
+                None => {
+                    let function = self.indices[name.as_str()];
+                    self.value(InstructionKind::Function(function), ty, span)
+                }
+            },
+            ExprKind::Qualified { .. } => {
+                unreachable!("the type checker resolves qualified names")
+            }
+            ExprKind::Unary { op, operand } => {
+                let operand = self.expr(operand);
+                self.value(InstructionKind::Unary { op: *op, operand }, ty, span)
+            }
+            ExprKind::Binary { left, op, right } => match op {
+                BinaryOp::And | BinaryOp::Or => self.short_circuit(left, *op, right, ty),
+                BinaryOp::Coalesce => self.coalesce(left, right, ty, span),
+                _ => {
+                    let left = self.expr(left);
+                    let right = self.expr(right);
+                    let kind = InstructionKind::Binary {
+                        op: *op,
+                        left,
+                        right,
+                    };
+                    self.value(kind, ty, span)
+                }
+            },
+            ExprKind::Call { callee, args } => self.call(callee, args, ty, span),
+            ExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let value = self.conditional(cond, then_branch, else_branch.as_deref(), Some(ty));
+                value.unwrap_or_else(|| self.constant(Constant::Unit, Type::Unit, span))
+            }
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let start = self.expr(start);
+                let end = self.expr(end);
+                let kind = InstructionKind::Range {
+                    start,
+                    end,
+                    inclusive: *inclusive,
+                };
+                self.value(kind, ty, span)
+            }
+            ExprKind::Try(operand) => {
+                let result = self.expr(operand);
+                let is_ok = self.builtin(Builtin::IsOk, vec![result], Type::Bool, span);
+                let (ok, err) = (self.new_block(), self.new_block());
+                self.branch(is_ok, ok, err);
+                // An `Err` is returned as it is, since it fits the result type of the function
+                self.switch_to_sealed(err);
+                self.leave_blocks(0);
+                self.terminate(Terminator::Return(result));
+                self.switch_to_sealed(ok);
+                self.value(InstructionKind::Payload(result), ty, span)
+            }
+            ExprKind::Interpolation(parts) => self.interpolation(parts, span),
+            ExprKind::Lambda(_) => {
+                let function = self.program.closures[&(self.source.file, span)];
+                let environment = &self.program.functions[function].environment;
+                let captures = environment
+                    .iter()
+                    .map(|slot| {
+                        let binding = self
+                            .lookup(&slot.name)
+                            .expect("captured variables are in scope");
+                        match (binding, slot.mode) {
+                            // Variables captured by reference are passed as their heap cell
+                            (Binding::Cell(cell), CaptureMode::Reference) => cell,
+                            (binding, _) => self.load(binding, span),
+                        }
+                    })
+                    .collect();
+                self.value(InstructionKind::Closure { function, captures }, ty, span)
+            }
+        }
+    }
+
+    /// Lowers an `if`, returning its value when `ty` is given and it has an `else` branch
+    fn conditional(
+        &mut self,
+        cond: &'p Expr,
+        then_branch: &'p ast::Block,
+        else_branch: Option<&'p Expr>,
+        ty: Option<Type>,
+    ) -> Option<Value> {
+        let result = match (&ty, else_branch) {
+            (Some(ty), Some(_)) => Some(self.temporary(ty.clone())),
+            _ => None,
+        };
+        let condition = self.expr(cond);
+        let then = self.new_block();
+        let otherwise = else_branch.map(|_| self.new_block());
+        let end = self.new_block();
+        self.branch(condition, then, otherwise.unwrap_or(end));
+
+        self.switch_to_sealed(then);
+        match result {
+            Some(result) => {
+                let value = self.block(then_branch);
+                self.assign(result, value);
+            }
+            None => self.block_effect(then_branch),
+        }
+        self.jump(end);
+        if let (Some(otherwise), Some(else_branch)) = (otherwise, else_branch) {
+            self.switch_to_sealed(otherwise);
+            match result {
+                Some(result) => {
+                    let value = self.expr(else_branch);
+                    self.assign(result, value);
+                }
+                None => self.effect(else_branch),
+            }
+            self.jump(end);
+        }
+        self.switch_to_sealed(end);
+        result.map(|result| self.read(result))
+    }
+
+    /// Lowers `and` and `or`, whose result is the left operand when it decides it
+    fn short_circuit(&mut self, left: &'p Expr, op: BinaryOp, right: &'p Expr, ty: Type) -> Value {
+        let result = self.temporary(ty);
+        let value = self.expr(left);
+        self.assign(result, value);
+        let (evaluate, end) = (self.new_block(), self.new_block());
+        match op {
+            BinaryOp::And => self.branch(value, evaluate, end),
+            _ => self.branch(value, end, evaluate),
+        }
+        self.switch_to_sealed(evaluate);
+        let value = self.expr(right);
+        self.assign(result, value);
+        self.jump(end);
+        self.switch_to_sealed(end);
+        self.read(result)
+    }
+
+    /// Lowers `??`, which only evaluates its right operand when the left one is `null` or an `Err`
+    fn coalesce(&mut self, left: &'p Expr, right: &'p Expr, ty: Type, span: Span) -> Value {
+        let result = self.temporary(ty.clone());
+        let value = self.expr(left);
+        let (fallback, end) = (self.new_block(), self.new_block());
+        if matches!(left.ty, Type::Result(..)) {
+            let is_ok = self.builtin(Builtin::IsOk, vec![value], Type::Bool, span);
+            let ok = self.new_block();
+            self.branch(is_ok, ok, fallback);
+            self.switch_to_sealed(ok);
+            let payload = self.value(InstructionKind::Payload(value), ty, span);
+            self.assign(result, payload);
+            self.jump(end);
+        } else {
+            self.assign(result, value);
+            let is_null = self.value(InstructionKind::IsNull(value), Type::Bool, span);
+            self.branch(is_null, fallback, end);
+        }
+        self.switch_to_sealed(fallback);
+        let value = self.expr(right);
+        self.assign(result, value);
+        self.jump(end);
+        self.switch_to_sealed(end);
+        self.read(result)
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: Vec<Value>, ty: Type, span: Span) -> Value {
+        self.value(InstructionKind::CallBuiltin { builtin, args }, ty, span)
+    }
+
+    fn call(&mut self, callee: &'p Expr, args: &'p [Expr], ty: Type, span: Span) -> Value {
+        let never = ty == Type::Never;
+        let kind = match &callee.kind {
+            ExprKind::Identifier(name) if self.lookup(name).is_none() => {
+                if let Some(format) = format::of_call(name, args) {
+                    return self.print_format(name == "println", format, &args[1..], span);
+                }
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                match self.indices.get(name.as_str()) {
+                    Some(&function) => InstructionKind::Call { function, args },
+                    None => InstructionKind::CallBuiltin {
+                        builtin: Builtin::from_name(name).expect("the type checker resolves calls"),
+                        args,
+                    },
+                }
+            }
+            _ => {
+                let callee = self.expr(callee);
+                let args = args.iter().map(|arg| self.expr(arg)).collect();
+                InstructionKind::CallValue { callee, args }
+            }
+        };
+        let value = self.value(kind, ty, span);
+        // Calls that never return, like `panic`, end the block
+        if never {
+            self.terminate(Terminator::Unreachable);
+        }
+        value
+    }
+
+    /// Lowers an interpolated string into the concatenation of its parts, left to right, where
+    /// embedded expressions that are not already strings go through `to_string`
+    fn interpolation(&mut self, parts: &'p [ast::StringPart], span: Span) -> Value {
+        let mut text = None;
+        for part in parts {
+            let value = match part {
+                ast::StringPart::Text(part) => {
+                    self.constant(Constant::String(part.clone()), Type::String, span)
+                }
+                ast::StringPart::Expr(expr) if expr.ty == Type::String => self.expr(expr),
+                ast::StringPart::Expr(expr) => {
+                    let value = self.expr(expr);
+                    self.builtin(Builtin::ToString, vec![value], Type::String, expr.span)
+                }
+            };
+            text = Some(match text {
+                Some(text) => self.builtin(Builtin::Concat, vec![text, value], Type::String, span),
+                None => value,
+            });
+        }
+        text.unwrap_or_else(|| self.constant(Constant::String(String::new()), Type::String, span))
+    }
+
+    /// Lowers `print()` or `println()` with a format string into one print per piece, once every
+    /// argument is evaluated. `println` prints its last piece along with the newline when it can.
+    fn print_format(&mut self, newline: bool, format: &str, args: &'p [Expr], span: Span) -> Value {
+        let pieces = format::parse(format).expect("the type checker validates format strings");
+        let values: Vec<Value> = args.iter().map(|arg| self.expr(arg)).collect();
+        let mut values = values.into_iter().zip(args);
+        let mut printed = None;
+        for (index, piece) in pieces.iter().enumerate() {
+            let last = newline && index + 1 == pieces.len();
+            let print = if last {
+                Builtin::Println
+            } else {
+                Builtin::Print
+            };
+            printed = Some(match piece {
+                Piece::Text(text) => {
+                    let text = self.constant(Constant::String(text.clone()), Type::String, span);
+                    self.builtin(print, vec![text], Type::Unit, span)
+                }
+                Piece::Placeholder(spec) => {
+                    let (value, arg) = values.next().expect("the type checker counts arguments");
+                    if spec.is_plain() {
+                        self.builtin(print, vec![value], Type::Unit, span)
+                    } else {
+                        let width = spec.width as i32;
+                        let precision = spec.precision.map_or(-1, |precision| precision as i32);
+                        let align = spec.alignment(arg.ty.is_numeric()) as i32;
+                        let mut args = vec![value];
+                        for number in [width, precision, align] {
+                            args.push(self.constant(Constant::Int(number), Type::Int, span));
+                        }
+                        let padded = self.builtin(Builtin::PrintPadded, args, Type::Unit, span);
+                        if last {
+                            self.builtin(Builtin::Println, Vec::new(), Type::Unit, span)
+                        } else {
+                            padded
+                        }
+                    }
+                }
+            });
+        }
+        match printed {
+            Some(printed) => printed,
+            None if newline => self.builtin(Builtin::Println, Vec::new(), Type::Unit, span),
+            None => self.constant(Constant::Unit, Type::Unit, span),
+        }
+    }
+
+    /// Removes the blocks control cannot reach and the phis that merge a single value, then
+    /// numbers the blocks in reverse postorder and the values in order of definition
+    fn finish(self) -> Function {
+        let mut function = self.function;
+        let forward = self.forward;
+        for block in &mut function.blocks {
+            block.phis.retain(|phi| !forward.contains_key(&phi.result));
+        }
+        passes::compact(&mut function, forward);
+        function
+    }
+}
@@ -0,0 +1,181 @@
+//! Control-flow graph analyses of IR functions: predecessors, orderings and dominators
+use crate::back_end::ir::{BasicBlock, Block, Function, Terminator};
+
+/// The predecessors of every block, in the order of the blocks that jump to them
+pub fn predecessors(function: &Function) -> Vec<Vec<Block>> {
+    let mut predecessors = vec![Vec::new(); function.blocks.len()];
+    for block in function.block_ids() {
+        for successor in function.block(block).terminator.successors() {
+            let list: &mut Vec<Block> = &mut predecessors[successor.0 as usize];
+            if !list.contains(&block) {
+                list.push(block);
+            }
+        }
+    }
+    predecessors
+}
+
+/// The blocks reachable from the entry, each before its successors except along back edges.
+/// The `then` side of a branch comes before the `otherwise` side.
+pub fn reverse_postorder(function: &Function) -> Vec<Block> {
+    let mut visited = vec![false; function.blocks.len()];
+    let mut postorder = Vec::new();
+    // Each entry is a block and the number of its successors already visited, last to first
+    let mut stack = vec![(Block(0), 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        let mut successors = function.block(block).terminator.successors();
+        successors.reverse();
+        match successors.get(next) {
+            Some(&successor) => {
+                stack.push((block, next + 1));
+                if !visited[successor.0 as usize] {
+                    visited[successor.0 as usize] = true;
+                    stack.push((successor, 0));
+                }
+            }
+            None => postorder.push(block),
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+/// The dominator tree of a function, computed with the algorithm of Cooper, Harvey and Kennedy,
+/// "A Simple, Fast Dominance Algorithm"
+/// - `order` is the position of every block in reverse postorder, or `None` if it is unreachable
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dominators {
+    idom: Vec<Option<Block>>,
+    order: Vec<Option<usize>>,
+    children: Vec<Vec<Block>>,
+}
+
+impl Dominators {
+    pub fn compute(function: &Function) -> Self {
+        let rpo = reverse_postorder(function);
+        let predecessors = predecessors(function);
+        let mut order = vec![None; function.blocks.len()];
+        for (position, block) in rpo.iter().enumerate() {
+            order[block.0 as usize] = Some(position);
+        }
+        let mut idom: Vec<Option<Block>> = vec![None; function.blocks.len()];
+        idom[0] = Some(Block(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &predecessor in &predecessors[block.0 as usize] {
+                    if idom[predecessor.0 as usize].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(other) => intersect(&idom, &order, predecessor, other),
+                    });
+                }
+                if new_idom.is_some() && idom[block.0 as usize] != new_idom {
+                    idom[block.0 as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        let mut children = vec![Vec::new(); function.blocks.len()];
+        for &block in rpo.iter().skip(1) {
+            let parent = idom[block.0 as usize].expect("reachable blocks have dominators");
+            children[parent.0 as usize].push(block);
+        }
+        Self {
+            idom,
+            order,
+            children,
+        }
+    }
+
+    /// The immediate dominator of a block, or `None` for the entry and unreachable blocks
+    pub fn idom(&self, block: Block) -> Option<Block> {
+        match block.0 {
+            0 => None,
+            _ => self.idom[block.0 as usize],
+        }
+    }
+
+    /// The blocks `block` immediately dominates, in reverse postorder
+    pub fn children(&self, block: Block) -> &[Block] {
+        &self.children[block.0 as usize]
+    }
+
+    pub fn is_reachable(&self, block: Block) -> bool {
+        self.order[block.0 as usize].is_some()
+    }
+
+    /// The position of a reachable block in reverse postorder
+    pub fn rpo_number(&self, block: Block) -> usize {
+        self.order[block.0 as usize].expect("only reachable blocks are ordered")
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`, which holds when `a` is `b`
+    pub fn dominates(&self, a: Block, b: Block) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        let mut block = b;
+        loop {
+            if block == a {
+                return true;
+            }
+            match self.idom(block) {
+                Some(parent) => block = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// The closest common dominator of two blocks whose dominators are known so far
+fn intersect(idom: &[Option<Block>], order: &[Option<usize>], a: Block, b: Block) -> Block {
+    let position = |block: Block| order[block.0 as usize].unwrap();
+    let (mut a, mut b) = (a, b);
+    while a != b {
+        while position(a) > position(b) {
+            a = idom[a.0 as usize].unwrap();
+        }
+        while position(b) > position(a) {
+            b = idom[b.0 as usize].unwrap();
+        }
+    }
+    a
+}
+
+/// Splits every edge from a block with several successors to a block with several predecessors,
+/// by inserting an empty block that jumps to the successor. Afterwards, the moves that implement
+/// the phis of a block can be placed at the end of each of its predecessors.
+pub fn split_critical_edges(function: &mut Function) {
+    let predecessors = predecessors(function);
+    for block in function.block_ids().collect::<Vec<_>>() {
+        let successors = function.block(block).terminator.successors();
+        if successors.len() < 2 {
+            continue;
+        }
+        for (n, successor) in successors.into_iter().enumerate() {
+            if predecessors[successor.0 as usize].len() < 2 {
+                continue;
+            }
+            let split = Block(function.blocks.len() as u32);
+            function.blocks.push(BasicBlock {
+                phis: Vec::new(),
+                instructions: Vec::new(),
+                terminator: Terminator::Jump(successor),
+            });
+            *function.block_mut(block).terminator.successors_mut()[n] = split;
+            for phi in &mut function.block_mut(successor).phis {
+                for (from, _) in &mut phi.incoming {
+                    if *from == block {
+                        *from = split;
+                    }
+                }
+            }
+        }
+    }
+}
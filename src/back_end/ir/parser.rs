@@ -0,0 +1,776 @@
+//! Parser of the textual form of the IR, as printed by its `Display` implementations.
+//! Values and blocks may be referenced before they are defined, and are numbered in order of
+//! definition, so printing a parsed program gives back the same text. Spans and files are not part
+//! of the text, so parsed functions have default ones. `;` starts a comment running to the end of
+//! the line.
+use crate::back_end::ir::{
+    BasicBlock, Block, Builtin, Constant, Function, Instruction, InstructionKind, IrError, Phi,
+    Program, Terminator, Value, ValueType,
+};
+use crate::front_end::ast::{BinaryOp, UnaryOp};
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use std::collections::HashMap;
+
+/// Parses a whole program. Its entry is the function named `main`, or else the first one.
+pub fn parse(text: &str) -> Result<Program, IrError> {
+    let tokens = lex(text)?;
+    // Functions may call the ones defined after them
+    let names: Vec<String> = tokens
+        .windows(2)
+        .filter_map(|pair| match (&pair[0].token, &pair[1].token) {
+            (Token::Word(word), Token::Global(name)) if word == "func" => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        names,
+    };
+    let mut functions = Vec::new();
+    while parser.peek().is_some() {
+        functions.push(parser.function()?);
+    }
+    let entry = functions
+        .iter()
+        .position(|function: &Function| function.name == "main")
+        .unwrap_or(0);
+    Ok(Program { functions, entry })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Value(u32),
+    Global(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Char(char),
+    Punct(char),
+    Arrow,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("`{}`", word),
+            Token::Value(value) => format!("`%{}`", value),
+            Token::Global(name) => format!("`@{}`", name),
+            Token::Int(value) => format!("`{}`", value),
+            Token::Float(value) => format!("`{:?}`", value),
+            Token::String(value) => format!("{:?}", value),
+            Token::Char(value) => format!("{:?}", value),
+            Token::Punct(c) => format!("`{}`", c),
+            Token::Arrow => "`->`".to_string(),
+        }
+    }
+}
+
+struct Located {
+    token: Token,
+    line: usize,
+}
+
+fn syntax(line: usize, message: impl Into<String>) -> IrError {
+    IrError::Syntax {
+        line,
+        message: message.into(),
+    }
+}
+
+fn lex(text: &str) -> Result<Vec<Located>, IrError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+                continue;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            ';' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            '%' => {
+                chars.next();
+                let digits = take_while(&mut chars, |c| c.is_ascii_digit());
+                let number = digits
+                    .parse()
+                    .map_err(|_| syntax(line, "expected a value number after `%`"))?;
+                Token::Value(number)
+            }
+            '@' => {
+                chars.next();
+                let name = take_while(&mut chars, |c| {
+                    !c.is_whitespace() && !matches!(c, '(' | ')' | ',' | '[' | ']' | '{' | '}')
+                });
+                if name.is_empty() {
+                    return Err(syntax(line, "expected a function name after `@`"));
+                }
+                Token::Global(name)
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.push(escape(&mut chars, line)?),
+                        Some(c) => string.push(c),
+                        None => return Err(syntax(line, "unterminated string")),
+                    }
+                }
+                Token::String(string)
+            }
+            '\'' => {
+                chars.next();
+                let value = match chars.next() {
+                    Some('\\') => escape(&mut chars, line)?,
+                    Some(c) => c,
+                    None => return Err(syntax(line, "unterminated character")),
+                };
+                if chars.next() != Some('\'') {
+                    return Err(syntax(line, "unterminated character"));
+                }
+                Token::Char(value)
+            }
+            '-' => {
+                chars.next();
+                if chars.next_if_eq(&'>').is_some() {
+                    Token::Arrow
+                } else {
+                    match number(&mut chars, line)? {
+                        Token::Int(value) => Token::Int(-value),
+                        Token::Float(value) => Token::Float(-value),
+                        Token::Word(word) if word == "inf" => Token::Float(f64::NEG_INFINITY),
+                        _ => return Err(syntax(line, "expected a number after `-`")),
+                    }
+                }
+            }
+            c if c.is_ascii_digit() => number(&mut chars, line)?,
+            c if c.is_alphanumeric() || c == '_' => Token::Word(take_while(&mut chars, |c| {
+                c.is_alphanumeric() || c == '_' || c == '.'
+            })),
+            '(' | ')' | '[' | ']' | '{' | '}' | ',' | ':' | '=' | '?' => {
+                chars.next();
+                Token::Punct(c)
+            }
+            c => return Err(syntax(line, format!("unexpected character {:?}", c))),
+        };
+        tokens.push(Located { token, line });
+    }
+    Ok(tokens)
+}
+
+type Chars<'t> = std::iter::Peekable<std::str::Chars<'t>>;
+
+fn take_while(chars: &mut Chars, predicate: impl Fn(char) -> bool) -> String {
+    let mut taken = String::new();
+    while let Some(c) = chars.next_if(|&c| predicate(c)) {
+        taken.push(c);
+    }
+    taken
+}
+
+/// Lexes an integer, or a float as printed with `{:?}`
+fn number(chars: &mut Chars, line: usize) -> Result<Token, IrError> {
+    if chars.peek().is_some_and(|c| c.is_alphabetic()) {
+        return Ok(Token::Word(take_while(chars, |c| c.is_alphanumeric())));
+    }
+    let mut text = take_while(chars, |c| c.is_ascii_digit());
+    let mut float = false;
+    if chars.next_if_eq(&'.').is_some() {
+        float = true;
+        text.push('.');
+        text.push_str(&take_while(chars, |c| c.is_ascii_digit()));
+    }
+    if chars.next_if_eq(&'e').is_some() {
+        float = true;
+        text.push('e');
+        if chars.next_if_eq(&'-').is_some() {
+            text.push('-');
+        }
+        text.push_str(&take_while(chars, |c| c.is_ascii_digit()));
+    }
+    let invalid = || syntax(line, format!("invalid number `{}`", text));
+    match float {
+        true => text.parse().map(Token::Float).map_err(|_| invalid()),
+        false => text.parse().map(Token::Int).map_err(|_| invalid()),
+    }
+}
+
+/// Reads the escape sequence after a `\` in a string or character
+fn escape(chars: &mut Chars, line: usize) -> Result<char, IrError> {
+    let escaped = match chars.next() {
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('0') => '\0',
+        Some(c @ ('\\' | '"' | '\'')) => c,
+        Some('u') if chars.next_if_eq(&'{').is_some() => {
+            let digits = take_while(chars, |c| c.is_ascii_hexdigit());
+            chars.next_if_eq(&'}');
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| syntax(line, "invalid unicode escape"))?
+        }
+        _ => return Err(syntax(line, "invalid escape sequence")),
+    };
+    Ok(escaped)
+}
+
+/// The values and blocks of the function being parsed, by the number they have in the text
+/// - `definitions` lists the values in order of definition, with their type
+#[derive(Default)]
+struct Names {
+    definitions: Vec<(u32, ValueType, usize)>,
+    blocks: HashMap<String, Block>,
+    block_uses: Vec<(String, usize)>,
+}
+
+struct Parser {
+    tokens: Vec<Located>,
+    position: usize,
+    names: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|located| &located.token)
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.position) {
+            Some(located) => located.line,
+            None => self.tokens.last().map_or(1, |located| located.line),
+        }
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, IrError> {
+        let found = match self.peek() {
+            Some(token) => token.describe(),
+            None => "the end of the text".to_string(),
+        };
+        Err(syntax(
+            self.line(),
+            format!("expected {}, found {}", expected, found),
+        ))
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.eat(&Token::Word(word.to_string()))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), IrError> {
+        match self.eat(&Token::Punct(c)) {
+            true => Ok(()),
+            false => self.error(&format!("`{}`", c)),
+        }
+    }
+
+    fn word(&mut self) -> Result<String, IrError> {
+        match self.peek() {
+            Some(Token::Word(word)) => {
+                let word = word.clone();
+                self.position += 1;
+                Ok(word)
+            }
+            _ => self.error("a name"),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, IrError> {
+        match self.peek() {
+            Some(&Token::Value(number)) => {
+                self.position += 1;
+                Ok(Value(number))
+            }
+            _ => self.error("a value"),
+        }
+    }
+
+    fn function_index(&mut self) -> Result<usize, IrError> {
+        let line = self.line();
+        let Some(Token::Global(name)) = self.peek().cloned() else {
+            return self.error("a function name");
+        };
+        self.position += 1;
+        if let Some(index) = self.names.iter().position(|other| *other == name) {
+            return Ok(index);
+        }
+        // Functions printed on their own refer to the others by index
+        name.parse()
+            .map_err(|_| syntax(line, format!("unknown function `@{}`", name)))
+    }
+
+    fn block_label(&mut self, names: &mut Names) -> Result<Block, IrError> {
+        let line = self.line();
+        let label = self.word()?;
+        names.block_uses.push((label.clone(), line));
+        Ok(Block(names.block_uses.len() as u32 - 1))
+    }
+
+    fn comma_list<T>(
+        &mut self,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T, IrError>,
+    ) -> Result<Vec<T>, IrError> {
+        let mut items = Vec::new();
+        if self.eat(&Token::Punct(close)) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.eat(&Token::Punct(close)) {
+                return Ok(items);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn ty(&mut self) -> Result<Type, IrError> {
+        let mut ty = match self.next() {
+            Some(Token::Word(word)) => match word.as_str() {
+                "Range" => Type::Range,
+                "Never" => Type::Never,
+                "Result" => {
+                    self.expect('[')?;
+                    let ok = self.ty()?;
+                    self.expect(',')?;
+                    let err = self.ty()?;
+                    self.expect(']')?;
+                    Type::Result(Box::new(ok), Box::new(err))
+                }
+                "func" => {
+                    self.expect('(')?;
+                    let params = self.comma_list(')', Self::ty)?;
+                    let ret = match self.eat(&Token::Arrow) {
+                        true => self.ty()?,
+                        false => Type::Unit,
+                    };
+                    Type::Function(params, Box::new(ret))
+                }
+                "null" => Type::Null,
+                name => match Type::from_name(name) {
+                    Some(ty) => ty,
+                    None => {
+                        self.position -= 1;
+                        return self.error("a type");
+                    }
+                },
+            },
+            Some(Token::Punct('(')) => {
+                self.expect(')')?;
+                Type::Unit
+            }
+            _ => {
+                self.position -= 1;
+                return self.error("a type");
+            }
+        };
+        while self.eat(&Token::Punct('?')) {
+            ty = Type::Optional(Box::new(ty));
+        }
+        Ok(ty)
+    }
+
+    fn value_type(&mut self) -> Result<ValueType, IrError> {
+        if self.eat_word("Cell") {
+            self.expect('[')?;
+            let ty = self.ty()?;
+            self.expect(']')?;
+            return Ok(ValueType::Cell(ty));
+        }
+        self.ty().map(ValueType::Value)
+    }
+
+    fn function(&mut self) -> Result<Function, IrError> {
+        if !self.eat_word("func") {
+            return self.error("`func`");
+        }
+        let Some(Token::Global(name)) = self.next() else {
+            self.position -= 1;
+            return self.error("a function name");
+        };
+        let mut names = Names::default();
+        self.expect('(')?;
+        let params = self.comma_list(')', |parser| {
+            let line = parser.line();
+            let value = parser.value()?;
+            parser.expect(':')?;
+            let ty = parser.value_type()?;
+            names.definitions.push((value.0, ty, line));
+            Ok(value)
+        })?;
+        if !self.eat(&Token::Arrow) {
+            return self.error("`->`");
+        }
+        let return_type = self.ty()?;
+        let mut captures = Vec::new();
+        if self.eat_word("captures") {
+            self.expect('[')?;
+            captures = self.comma_list(']', Self::value_type)?;
+        }
+        self.expect('{')?;
+
+        let mut blocks = Vec::new();
+        let mut labels = Vec::new();
+        while !self.eat(&Token::Punct('}')) {
+            let line = self.line();
+            let label = self.word()?;
+            self.expect(':')?;
+            if names
+                .blocks
+                .insert(label.clone(), Block(blocks.len() as u32))
+                .is_some()
+            {
+                return Err(syntax(line, format!("block `{}` is defined twice", label)));
+            }
+            labels.push(label);
+            blocks.push(self.block(&mut names)?);
+        }
+        if blocks.is_empty() {
+            return self.error("a block");
+        }
+
+        let mut function = Function {
+            name,
+            captures,
+            params,
+            return_type,
+            values: Vec::new(),
+            blocks,
+            file: 0,
+        };
+        resolve(&mut function, names)?;
+        Ok(function)
+    }
+
+    fn block(&mut self, names: &mut Names) -> Result<BasicBlock, IrError> {
+        let mut phis = Vec::new();
+        let mut instructions = Vec::new();
+        loop {
+            let line = self.line();
+            match self.peek() {
+                Some(Token::Value(_)) => {
+                    let result = self.value()?;
+                    self.expect(':')?;
+                    let ty = self.value_type()?;
+                    self.expect('=')?;
+                    names.definitions.push((result.0, ty, line));
+                    if self.eat_word("phi") {
+                        if !instructions.is_empty() {
+                            return Err(syntax(line, "phis must come before the instructions"));
+                        }
+                        let mut incoming = Vec::new();
+                        loop {
+                            self.expect('[')?;
+                            let from = self.block_label(names)?;
+                            self.expect(':')?;
+                            incoming.push((from, self.value()?));
+                            self.expect(']')?;
+                            if !self.eat(&Token::Punct(',')) {
+                                break;
+                            }
+                        }
+                        phis.push(Phi { result, incoming });
+                    } else {
+                        let kind = self.instruction()?;
+                        instructions.push(Instruction {
+                            result: Some(result),
+                            kind,
+                            span: Span::default(),
+                        });
+                    }
+                }
+                Some(Token::Word(word)) if word == "jump" => {
+                    self.position += 1;
+                    let target = self.block_label(names)?;
+                    let terminator = Terminator::Jump(target);
+                    return Ok(BasicBlock {
+                        phis,
+                        instructions,
+                        terminator,
+                    });
+                }
+                Some(Token::Word(word)) if word == "branch" => {
+                    self.position += 1;
+                    let condition = self.value()?;
+                    self.expect(',')?;
+                    let then = self.block_label(names)?;
+                    self.expect(',')?;
+                    let otherwise = self.block_label(names)?;
+                    let terminator = Terminator::Branch {
+                        condition,
+                        then,
+                        otherwise,
+                    };
+                    return Ok(BasicBlock {
+                        phis,
+                        instructions,
+                        terminator,
+                    });
+                }
+                Some(Token::Word(word)) if word == "return" => {
+                    self.position += 1;
+                    let terminator = Terminator::Return(self.value()?);
+                    return Ok(BasicBlock {
+                        phis,
+                        instructions,
+                        terminator,
+                    });
+                }
+                Some(Token::Word(word)) if word == "unreachable" => {
+                    self.position += 1;
+                    return Ok(BasicBlock {
+                        phis,
+                        instructions,
+                        terminator: Terminator::Unreachable,
+                    });
+                }
+                Some(Token::Word(word)) if word == "cell.store" => {
+                    let kind = self.instruction()?;
+                    instructions.push(Instruction {
+                        result: None,
+                        kind,
+                        span: Span::default(),
+                    });
+                }
+                _ => return self.error("an instruction or a terminator"),
+            }
+        }
+    }
+
+    fn args(&mut self) -> Result<Vec<Value>, IrError> {
+        self.expect('(')?;
+        self.comma_list(')', Self::value)
+    }
+
+    fn pair(&mut self) -> Result<(Value, Value), IrError> {
+        let a = self.value()?;
+        self.expect(',')?;
+        Ok((a, self.value()?))
+    }
+
+    fn instruction(&mut self) -> Result<InstructionKind, IrError> {
+        let line = self.line();
+        let mnemonic = self.word()?;
+        let kind = match mnemonic.as_str() {
+            "const" => InstructionKind::Const(self.constant()?),
+            "function" => InstructionKind::Function(self.function_index()?),
+            "closure" => {
+                let function = self.function_index()?;
+                let captures = self.args()?;
+                InstructionKind::Closure { function, captures }
+            }
+            "capture" => match self.next() {
+                Some(Token::Int(slot)) if (0..=u32::MAX as i64).contains(&slot) => {
+                    InstructionKind::Capture(slot as u32)
+                }
+                _ => {
+                    self.position -= 1;
+                    return self.error("a capture slot");
+                }
+            },
+            "cell.new" => InstructionKind::NewCell(self.value()?),
+            "cell.load" => InstructionKind::LoadCell(self.value()?),
+            "cell.store" => {
+                let (cell, value) = self.pair()?;
+                InstructionKind::StoreCell { cell, value }
+            }
+            "range" | "range.inclusive" => {
+                let (start, end) = self.pair()?;
+                InstructionKind::Range {
+                    start,
+                    end,
+                    inclusive: mnemonic == "range.inclusive",
+                }
+            }
+            "range.start" => InstructionKind::RangeStart(self.value()?),
+            "range.contains" => {
+                let (range, item) = self.pair()?;
+                InstructionKind::RangeContains { range, item }
+            }
+            "is_null" => InstructionKind::IsNull(self.value()?),
+            "payload" => InstructionKind::Payload(self.value()?),
+            "call" => match self.peek() {
+                Some(Token::Value(_)) => {
+                    let callee = self.value()?;
+                    let args = self.args()?;
+                    InstructionKind::CallValue { callee, args }
+                }
+                _ => {
+                    let function = self.function_index()?;
+                    let args = self.args()?;
+                    InstructionKind::Call { function, args }
+                }
+            },
+            "builtin" => {
+                let name = self.word()?;
+                let Some(builtin) = Builtin::from_name(&name) else {
+                    return Err(syntax(line, format!("unknown built-in `{}`", name)));
+                };
+                let args = self.args()?;
+                InstructionKind::CallBuiltin { builtin, args }
+            }
+            mnemonic => {
+                if let Some(op) = unary_op(mnemonic) {
+                    let operand = self.value()?;
+                    InstructionKind::Unary { op, operand }
+                } else if let Some(op) = binary_op(mnemonic) {
+                    let (left, right) = self.pair()?;
+                    InstructionKind::Binary { op, left, right }
+                } else {
+                    return Err(syntax(line, format!("unknown instruction `{}`", mnemonic)));
+                }
+            }
+        };
+        Ok(kind)
+    }
+
+    fn constant(&mut self) -> Result<Constant, IrError> {
+        let constant = match self.next() {
+            Some(Token::Int(value)) => match i32::try_from(value) {
+                Ok(value) => Constant::Int(value),
+                Err(_) => {
+                    self.position -= 1;
+                    return self.error("an integer that fits in 32 bits");
+                }
+            },
+            Some(Token::Float(value)) => Constant::Float(value),
+            Some(Token::String(value)) => Constant::String(value),
+            Some(Token::Char(value)) => Constant::Char(value),
+            Some(Token::Punct('(')) => {
+                self.expect(')')?;
+                Constant::Unit
+            }
+            Some(Token::Word(word)) => match word.as_str() {
+                "true" => Constant::Bool(true),
+                "false" => Constant::Bool(false),
+                "null" => Constant::Null,
+                "inf" => Constant::Float(f64::INFINITY),
+                "NaN" => Constant::Float(f64::NAN),
+                _ => {
+                    self.position -= 1;
+                    return self.error("a constant");
+                }
+            },
+            _ => {
+                self.position -= 1;
+                return self.error("a constant");
+            }
+        };
+        Ok(constant)
+    }
+}
+
+fn unary_op(mnemonic: &str) -> Option<UnaryOp> {
+    [UnaryOp::Negate, UnaryOp::Not, UnaryOp::BitNot]
+        .into_iter()
+        .find(|op| super::unary_mnemonic(*op) == mnemonic)
+}
+
+fn binary_op(mnemonic: &str) -> Option<BinaryOp> {
+    [
+        BinaryOp::Add,
+        BinaryOp::Subtract,
+        BinaryOp::Multiply,
+        BinaryOp::Divide,
+        BinaryOp::Remainder,
+        BinaryOp::Equal,
+        BinaryOp::NotEqual,
+        BinaryOp::Less,
+        BinaryOp::LessEqual,
+        BinaryOp::Greater,
+        BinaryOp::GreaterEqual,
+        BinaryOp::BitAnd,
+        BinaryOp::BitOr,
+        BinaryOp::BitXor,
+        BinaryOp::ShiftLeft,
+        BinaryOp::ShiftRight,
+    ]
+    .into_iter()
+    .find(|op| super::binary_mnemonic(*op) == mnemonic)
+}
+
+/// Numbers the values of a parsed function in order of definition, and points the block labels to
+/// the blocks they name
+fn resolve(function: &mut Function, names: Names) -> Result<(), IrError> {
+    let mut numbers = HashMap::new();
+    for (text, ty, line) in names.definitions {
+        let value = Value(function.values.len() as u32);
+        if numbers.insert(text, value).is_some() {
+            return Err(syntax(line, format!("value `%{}` is defined twice", text)));
+        }
+        function.values.push(ty);
+    }
+    let mut blocks = Vec::new();
+    for (label, line) in &names.block_uses {
+        match names.blocks.get(label) {
+            Some(&block) => blocks.push(block),
+            None => return Err(syntax(*line, format!("unknown block `{}`", label))),
+        }
+    }
+    let mut undefined = None;
+    let mut renumber = |value: &mut Value| match numbers.get(&value.0) {
+        Some(&number) => *value = number,
+        None => undefined = Some(value.0),
+    };
+    function.params.iter_mut().for_each(&mut renumber);
+    for block in &mut function.blocks {
+        for phi in &mut block.phis {
+            renumber(&mut phi.result);
+            for (from, value) in &mut phi.incoming {
+                *from = blocks[from.0 as usize];
+                renumber(value);
+            }
+        }
+        for instruction in &mut block.instructions {
+            if let Some(result) = &mut instruction.result {
+                renumber(result);
+            }
+            instruction
+                .kind
+                .operands_mut()
+                .into_iter()
+                .for_each(&mut renumber);
+        }
+        if let Some(value) = block.terminator.operand_mut() {
+            renumber(value);
+        }
+        for successor in block.terminator.successors_mut() {
+            *successor = blocks[successor.0 as usize];
+        }
+    }
+    match undefined {
+        Some(value) => Err(IrError::Invalid {
+            function: function.name.clone(),
+            reason: format!("value `%{}` is never defined", value),
+        }),
+        None => Ok(()),
+    }
+}
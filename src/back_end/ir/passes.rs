@@ -0,0 +1,440 @@
+//! Optimizations of the IR, which the pass manager runs in the order of a pipeline chosen by the
+//! optimization level. Every pass keeps the IR well formed, which debug builds verify after each
+//! one, and preserves what a program prints and where it panics:
+//! - `sccp` propagates constants along the branches that can be taken, and removes the others
+//! - `dce` removes instructions whose result is unused and that have no effect
+//! - `gvn` replaces an instruction by an identical one that dominates it
+//! - `inline` copies small functions into the blocks that call them
+//! - `licm` moves the instructions of a loop that compute the same value on every iteration
+//!   before it
+//! - `simplify` merges the blocks that follow each other unconditionally
+pub mod dce;
+pub mod gvn;
+pub mod inline;
+pub mod licm;
+pub mod sccp;
+pub mod simplify;
+
+use crate::back_end::ir::{
+    cfg, verifier, Block, Builtin, Function, InstructionKind, Program, Value,
+};
+use crate::front_end::ast::{BinaryOp, UnaryOp};
+use crate::front_end::types::Type;
+use std::collections::HashMap;
+
+/// How much `crawfish build` optimizes, chosen with `-O0`, `-O1` or `-O2`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimization, so that the code follows the source closely
+    #[default]
+    O0,
+    /// Optimizations within each function
+    O1,
+    /// Also inlining and loop-invariant code motion
+    O2,
+}
+
+/// An optimization pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Sccp,
+    Dce,
+    Gvn,
+    Inline,
+    Licm,
+    Simplify,
+}
+
+impl Pass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Sccp => "sccp",
+            Pass::Dce => "dce",
+            Pass::Gvn => "gvn",
+            Pass::Inline => "inline",
+            Pass::Licm => "licm",
+            Pass::Simplify => "simplify",
+        }
+    }
+
+    pub fn run(&self, program: &mut Program) {
+        if *self == Pass::Inline {
+            return inline::run(program);
+        }
+        for function in &mut program.functions {
+            match self {
+                Pass::Sccp => sccp::run(function),
+                Pass::Dce => dce::run(function),
+                Pass::Gvn => gvn::run(function),
+                Pass::Licm => licm::run(function),
+                Pass::Simplify => simplify::run(function),
+                Pass::Inline => unreachable!("inlining runs on the whole program"),
+            }
+        }
+    }
+}
+
+/// Runs a pipeline of passes over a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassManager {
+    pub passes: Vec<Pass>,
+}
+
+impl PassManager {
+    /// The pipeline of an optimization level
+    pub fn new(level: OptLevel) -> Self {
+        let passes = match level {
+            OptLevel::O0 => vec![],
+            OptLevel::O1 => vec![Pass::Sccp, Pass::Gvn, Pass::Dce, Pass::Simplify],
+            // Inlining exposes constant arguments, and hoisting works best on deduplicated loops
+            OptLevel::O2 => vec![
+                Pass::Inline,
+                Pass::Sccp,
+                Pass::Gvn,
+                Pass::Licm,
+                Pass::Dce,
+                Pass::Simplify,
+            ],
+        };
+        Self { passes }
+    }
+
+    pub fn run(&self, program: &mut Program) {
+        for pass in &self.passes {
+            pass.run(program);
+            if cfg!(debug_assertions) {
+                if let Err(error) = verifier::verify(program) {
+                    panic!("`{}` produced invalid IR: {}", pass.name(), error);
+                }
+            }
+        }
+    }
+}
+
+/// Optimizes a program at `level`
+pub fn optimize(program: &mut Program, level: OptLevel) {
+    PassManager::new(level).run(program);
+}
+
+/// Whether an instruction can be removed, or run when it was not going to be: it has no effect and
+/// cannot panic
+pub fn is_removable(function: &Function, kind: &InstructionKind) -> bool {
+    match kind {
+        InstructionKind::Const(_)
+        | InstructionKind::Function(_)
+        | InstructionKind::Closure { .. }
+        | InstructionKind::Capture(_)
+        | InstructionKind::NewCell(_)
+        | InstructionKind::LoadCell(_)
+        | InstructionKind::Range { .. }
+        | InstructionKind::RangeStart(_)
+        | InstructionKind::RangeContains { .. }
+        | InstructionKind::IsNull(_)
+        | InstructionKind::Payload(_) => true,
+        InstructionKind::StoreCell { .. }
+        | InstructionKind::Call { .. }
+        | InstructionKind::CallValue { .. } => false,
+        // A float may have no integer to truncate to, and an integer no character
+        InstructionKind::CallBuiltin {
+            builtin: builtin @ (Builtin::Int | Builtin::Char),
+            args,
+        } => {
+            let panics_on = if *builtin == Builtin::Int {
+                Type::Float
+            } else {
+                Type::Int
+            };
+            *function.type_of(args[0]) != panics_on
+        }
+        // Integer `abs()` and `pow()` panic on overflow
+        InstructionKind::CallBuiltin {
+            builtin: Builtin::Abs | Builtin::Pow,
+            args,
+        } => *function.type_of(args[0]) != Type::Int,
+        InstructionKind::CallBuiltin { builtin, .. } => {
+            matches!(
+                builtin,
+                Builtin::Ok
+                    | Builtin::Err
+                    | Builtin::IsOk
+                    | Builtin::IsErr
+                    | Builtin::ToString
+                    | Builtin::Concat
+                    | Builtin::Float
+                    | Builtin::CharWidth
+            ) || builtin.is_math()
+        }
+        // Integer arithmetic panics on overflow and division by zero
+        InstructionKind::Unary { op, operand } => {
+            !(*op == UnaryOp::Negate && function.type_of(*operand) == &Type::Int)
+        }
+        InstructionKind::Binary { op, left, .. } => {
+            let checked = matches!(
+                op,
+                BinaryOp::Add
+                    | BinaryOp::Subtract
+                    | BinaryOp::Multiply
+                    | BinaryOp::Divide
+                    | BinaryOp::Remainder
+                    | BinaryOp::ShiftLeft
+                    | BinaryOp::ShiftRight
+            );
+            !(checked && function.type_of(*left) == &Type::Int)
+        }
+    }
+}
+
+/// Whether an instruction computes the same result whenever its operands are the same, without
+/// effects besides panicking. A second one with the same operands can reuse the result of the
+/// first, which did not panic.
+pub fn is_pure(kind: &InstructionKind) -> bool {
+    match kind {
+        // Cells and closures are distinct objects, and cells change
+        InstructionKind::Closure { .. }
+        | InstructionKind::NewCell(_)
+        | InstructionKind::LoadCell(_)
+        | InstructionKind::StoreCell { .. }
+        | InstructionKind::Call { .. }
+        | InstructionKind::CallValue { .. } => false,
+        InstructionKind::CallBuiltin { builtin, .. } => {
+            !(matches!(
+                builtin,
+                Builtin::Println
+                    | Builtin::Print
+                    | Builtin::PrintPadded
+                    | Builtin::Input
+                    | Builtin::Panic
+            ) || builtin.is_file())
+        }
+        _ => true,
+    }
+}
+
+/// Follows the chain of replacements of a value
+pub fn resolve(forward: &HashMap<Value, Value>, mut value: Value) -> Value {
+    while let Some(&next) = forward.get(&value) {
+        value = next;
+    }
+    value
+}
+
+/// The only value a phi merges besides itself, if there is one
+pub fn trivial_value(
+    phi: Value,
+    incoming: Vec<Value>,
+    forward: &HashMap<Value, Value>,
+) -> Option<Value> {
+    let mut same = None;
+    for value in incoming {
+        let value = resolve(forward, value);
+        if Some(value) == same || value == phi {
+            continue;
+        }
+        if same.is_some() {
+            return None;
+        }
+        same = Some(value);
+    }
+    same
+}
+
+/// Replaces the values in `forward` by the ones they map to, removes the blocks control cannot
+/// reach and the phis that merge a single value, then numbers the blocks in reverse postorder and
+/// the values in order of definition. The definitions of replaced values must already be gone.
+pub fn compact(function: &mut Function, mut forward: HashMap<Value, Value>) {
+    let order = cfg::reverse_postorder(function);
+    let mut blocks = vec![None; function.blocks.len()];
+    for (position, block) in order.iter().enumerate() {
+        blocks[block.0 as usize] = Some(Block(position as u32));
+    }
+    for &block in &order {
+        for phi in &mut function.block_mut(block).phis {
+            phi.incoming
+                .retain(|(from, _)| blocks[from.0 as usize].is_some());
+        }
+    }
+    // Removing a phi can make the phis using it trivial
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order {
+            let phis = std::mem::take(&mut function.block_mut(block).phis);
+            for phi in phis {
+                let incoming = phi.incoming.iter().map(|(_, value)| *value).collect();
+                match trivial_value(phi.result, incoming, &forward) {
+                    Some(same) => {
+                        forward.insert(phi.result, same);
+                        changed = true;
+                    }
+                    None => function.block_mut(block).phis.push(phi),
+                }
+            }
+        }
+    }
+
+    let mut values = Vec::new();
+    let mut numbers = HashMap::new();
+    let mut define = |value: Value| {
+        numbers.insert(value, Value(values.len() as u32));
+        values.push(function.values[value.0 as usize].clone());
+    };
+    function.params.iter().for_each(|&param| define(param));
+    for &block in &order {
+        let block = function.block(block);
+        block.phis.iter().for_each(|phi| define(phi.result));
+        block
+            .instructions
+            .iter()
+            .filter_map(|instruction| instruction.result)
+            .for_each(&mut define);
+    }
+    let renumber = |value: &mut Value| *value = numbers[&resolve(&forward, *value)];
+    let mut old_blocks: Vec<_> = function.blocks.drain(..).map(Some).collect();
+    for &block in &order {
+        let mut block = old_blocks[block.0 as usize].take().unwrap();
+        for phi in &mut block.phis {
+            renumber(&mut phi.result);
+            for (from, value) in &mut phi.incoming {
+                *from = blocks[from.0 as usize].unwrap();
+                renumber(value);
+            }
+        }
+        for instruction in &mut block.instructions {
+            if let Some(result) = &mut instruction.result {
+                renumber(result);
+            }
+            instruction
+                .kind
+                .operands_mut()
+                .into_iter()
+                .for_each(renumber);
+        }
+        if let Some(value) = block.terminator.operand_mut() {
+            renumber(value);
+        }
+        for successor in block.terminator.successors_mut() {
+            *successor = blocks[successor.0 as usize].unwrap();
+        }
+        function.blocks.push(block);
+    }
+    function.params.iter_mut().for_each(renumber);
+    function.values = values;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::bytecode;
+    use crate::back_end::ir::{self, parse};
+    use crate::front_end;
+    use crate::runtime::panic::Panic;
+    use crate::runtime::vm;
+
+    /// Checks that `pass` turns the IR `before` into `after`. Both are read back from their text,
+    /// which numbers the values in order of definition, as passes may leave gaps in between.
+    pub fn assert_pass(pass: Pass, before: &str, after: &str) {
+        let mut program = parse(before).unwrap();
+        pass.run(&mut program);
+        verifier::verify(&program).unwrap();
+        let optimized = parse(&program.to_string()).unwrap().to_string();
+        assert_eq!(optimized, parse(after).unwrap().to_string());
+    }
+
+    /// Runs `source` on the virtual machine after optimizing it at `level`
+    fn run_source(source: &str, level: OptLevel) -> (String, Option<Panic>) {
+        let analysis = front_end::analyze(source).unwrap();
+        let mut program = ir::build(&analysis.program);
+        optimize(&mut program, level);
+        let mut out = Vec::new();
+        let result = vm::run(
+            &bytecode::compile_ir(&program),
+            &mut std::io::empty(),
+            &mut out,
+        );
+        (String::from_utf8(out).unwrap(), result.err())
+    }
+
+    #[test]
+    fn test_pipelines() {
+        assert!(PassManager::new(OptLevel::O0).passes.is_empty());
+        assert!(!PassManager::new(OptLevel::O1)
+            .passes
+            .contains(&Pass::Inline));
+        assert_eq!(PassManager::new(OptLevel::O2).passes[0], Pass::Inline);
+    }
+
+    #[test]
+    fn test_optimized_programs_behave_the_same() {
+        let programs = [
+            r#"
+            func square(x: Int) -> Int { return x * x; }
+            func main() {
+                const limit = 10;
+                var total = 0;
+                for i in 0..limit {
+                    const step = limit * 2 + 1;
+                    if i % 2 == 0 { total += square(i) + step; } else { total -= 1; }
+                }
+                println(total);
+                println(if limit > 5 { "big" } else { "small" });
+            }
+            "#,
+            r#"
+            func half(x: Int) -> Result[Int, String] {
+                if x % 2 != 0 { return Err("odd"); }
+                return Ok(x / 2);
+            }
+            func main() {
+                var n = 0;
+                const bump = func() { n += 1; };
+                while n < 6 {
+                    bump();
+                    println(half(n) ?? -1);
+                }
+                const unused = 1.5 * 2.0;
+                println(-0.0);
+                const nan = 0.0 / 0.0;
+                println(nan == nan);
+                println(nan);
+            }
+            "#,
+            // Arithmetic that panics is kept, even when its result is unused or constant
+            r#"
+            func main() {
+                const big = 2147483647;
+                println(1);
+                const overflow = big + 1;
+                println(2);
+            }
+            "#,
+            r#"
+            func main() {
+                var total = 0.0;
+                for i in 0..4 { total += sqrt(2.0) * float(i) + pow(2.0, 3.0); }
+                println(total);
+                println(max(-0.0, 0.0));
+                const unused = pow(2, 40);
+                println(3);
+            }
+            "#,
+            r#"
+            func divide(a: Int, b: Int) -> Int { return a / b; }
+            func main() {
+                for i in 0..3 { println(divide(6, 2 - i)); }
+            }
+            "#,
+        ];
+        for source in programs {
+            let expected = run_source(source, OptLevel::O0);
+            for level in [OptLevel::O1, OptLevel::O2] {
+                assert_eq!(
+                    run_source(source, level),
+                    expected,
+                    "{:?}: {}",
+                    level,
+                    source
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Dead code elimination: the values that terminators and effects need, directly or through other
+//! values, are marked live starting from them, and the phis and removable instructions defining
+//! the other values are deleted.
+use crate::back_end::ir::passes::is_removable;
+use crate::back_end::ir::{Function, Value};
+
+pub fn run(function: &mut Function) {
+    // The values each value is computed from
+    let mut inputs: Vec<Vec<Value>> = vec![Vec::new(); function.values.len()];
+    let mut worklist = Vec::new();
+    for block in &function.blocks {
+        for phi in &block.phis {
+            inputs[phi.result.0 as usize] = phi.incoming.iter().map(|(_, value)| *value).collect();
+        }
+        for instruction in &block.instructions {
+            let operands = instruction.kind.operands();
+            match instruction.result {
+                Some(result) if is_removable(function, &instruction.kind) => {
+                    inputs[result.0 as usize] = operands;
+                }
+                _ => worklist.extend(operands),
+            }
+        }
+        worklist.extend(block.terminator.operand());
+    }
+
+    let mut live = vec![false; function.values.len()];
+    while let Some(value) = worklist.pop() {
+        if !live[value.0 as usize] {
+            live[value.0 as usize] = true;
+            worklist.extend(&inputs[value.0 as usize]);
+        }
+    }
+
+    let removable: Vec<Vec<bool>> = function
+        .blocks
+        .iter()
+        .map(|block| {
+            block
+                .instructions
+                .iter()
+                .map(|instruction| is_removable(function, &instruction.kind))
+                .collect()
+        })
+        .collect();
+    for (block, removable) in function.blocks.iter_mut().zip(removable) {
+        block.phis.retain(|phi| live[phi.result.0 as usize]);
+        let mut removable = removable.into_iter();
+        block.instructions.retain(|instruction| {
+            let removable = removable.next().unwrap();
+            match instruction.result {
+                Some(result) => !removable || live[result.0 as usize],
+                None => true,
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::back_end::ir::passes::tests::assert_pass;
+    use crate::back_end::ir::passes::Pass;
+
+    #[test]
+    fn test_unused_values_are_removed() {
+        let before = r#"
+            func @f(%0: Float, %1: Int) -> Int {
+            bb0:
+                %2: Float = mul %0, %0
+                %3: Float = add %2, %0
+                %4: Int = const 1
+                %5: Int = add %1, %4
+                %6: () = builtin println(%4)
+                return %4
+            }
+        "#;
+        // Integer addition may overflow, so it stays
+        let after = r#"
+            func @f(%0: Float, %1: Int) -> Int {
+            bb0:
+                %2: Int = const 1
+                %3: Int = add %1, %2
+                %4: () = builtin println(%2)
+                return %2
+            }
+        "#;
+        assert_pass(Pass::Dce, before, after);
+    }
+
+    #[test]
+    fn test_dead_loop_phis_are_removed() {
+        let before = r#"
+            func @f(%0: Bool) -> () {
+            bb0:
+                %1: Float = const 0.0
+                jump bb1
+            bb1:
+                %2: Float = phi [bb0: %1], [bb2: %4]
+                branch %0, bb2, bb3
+            bb2:
+                %3: Float = const 1.0
+                %4: Float = add %2, %3
+                jump bb1
+            bb3:
+                %5: () = const ()
+                return %5
+            }
+        "#;
+        let after = r#"
+            func @f(%0: Bool) -> () {
+            bb0:
+                jump bb1
+            bb1:
+                branch %0, bb2, bb3
+            bb2:
+                jump bb1
+            bb3:
+                %1: () = const ()
+                return %1
+            }
+        "#;
+        assert_pass(Pass::Dce, before, after);
+    }
+}
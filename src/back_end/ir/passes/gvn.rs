@@ -0,0 +1,194 @@
+//! Global value numbering over the dominator tree: walking down from the entry, a pure instruction
+//! that computes the same operation on the same operands as one in a dominating block, or earlier
+//! in its own, is removed and its uses read the result of the first one instead. Commutative
+//! operations match whichever way round their operands are.
+use crate::back_end::ir::passes::{compact, is_pure, resolve};
+use crate::back_end::ir::{cfg::Dominators, Block, Constant, Function, InstructionKind, Value};
+use crate::front_end::ast::BinaryOp;
+use std::collections::HashMap;
+
+/// An instruction available to the blocks being visited, which are dominated by its block
+struct Available {
+    kind: InstructionKind,
+    result: Value,
+}
+
+pub fn run(function: &mut Function) {
+    let dominators = Dominators::compute(function);
+    let mut forward = HashMap::new();
+    // Available instructions by their operands, and the operands of the ones added in each
+    // enclosing block, to forget them when leaving it
+    let mut available: HashMap<Vec<Value>, Vec<Available>> = HashMap::new();
+    let mut added: Vec<Vec<Vec<Value>>> = Vec::new();
+    // Blocks to enter, or to leave when `None`
+    let mut stack = vec![Some(Block(0))];
+    while let Some(visit) = stack.pop() {
+        let Some(block) = visit else {
+            for operands in added.pop().unwrap() {
+                available.get_mut(&operands).unwrap().pop();
+            }
+            continue;
+        };
+        let mut here = Vec::new();
+        let mut instructions = std::mem::take(&mut function.block_mut(block).instructions);
+        instructions.retain_mut(|instruction| {
+            for operand in instruction.kind.operands_mut() {
+                *operand = resolve(&forward, *operand);
+            }
+            let Some(result) = instruction.result else {
+                return true;
+            };
+            if !is_pure(&instruction.kind) {
+                return true;
+            }
+            let kind = canonical(&instruction.kind);
+            let operands = kind.operands();
+            let bucket = available.entry(operands.clone()).or_default();
+            let ty = function.value_type(result);
+            let existing = bucket.iter().find(|available| {
+                equivalent(&available.kind, &kind) && function.value_type(available.result) == ty
+            });
+            match existing {
+                Some(existing) => {
+                    forward.insert(result, existing.result);
+                    false
+                }
+                None => {
+                    bucket.push(Available { kind, result });
+                    here.push(operands);
+                    true
+                }
+            }
+        });
+        function.block_mut(block).instructions = instructions;
+        added.push(here);
+        stack.push(None);
+        stack.extend(
+            dominators
+                .children(block)
+                .iter()
+                .rev()
+                .map(|&child| Some(child)),
+        );
+    }
+    compact(function, forward);
+}
+
+/// The instruction with the operands of a commutative operation in order
+fn canonical(kind: &InstructionKind) -> InstructionKind {
+    match *kind {
+        InstructionKind::Binary { op, left, right }
+            if right < left
+                && matches!(
+                    op,
+                    BinaryOp::Add
+                        | BinaryOp::Multiply
+                        | BinaryOp::Equal
+                        | BinaryOp::NotEqual
+                        | BinaryOp::BitAnd
+                        | BinaryOp::BitOr
+                        | BinaryOp::BitXor
+                ) =>
+        {
+            InstructionKind::Binary {
+                op,
+                left: right,
+                right: left,
+            }
+        }
+        _ => kind.clone(),
+    }
+}
+
+/// Whether two instructions compute the same value, telling apart float constants that compare
+/// equal like `0.0` and `-0.0`
+fn equivalent(a: &InstructionKind, b: &InstructionKind) -> bool {
+    match (a, b) {
+        (
+            InstructionKind::Const(Constant::Float(a)),
+            InstructionKind::Const(Constant::Float(b)),
+        ) => a.to_bits() == b.to_bits(),
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::back_end::ir::passes::tests::assert_pass;
+    use crate::back_end::ir::passes::Pass;
+
+    #[test]
+    fn test_redundant_instructions_are_removed() {
+        let before = r#"
+            func @f(%0: Int, %1: Int) -> Int {
+            bb0:
+                %2: Int = add %0, %1
+                %3: Int = add %1, %0
+                %4: Int = sub %3, %2
+                %5: Int = sub %2, %3
+                %6: Float = const 0.0
+                %7: Float = const -0.0
+                %8: Float = const 0.0
+                %9: Float = add %7, %8
+                %10: Int = add %4, %5
+                return %10
+            }
+        "#;
+        let after = r#"
+            func @f(%0: Int, %1: Int) -> Int {
+            bb0:
+                %2: Int = add %0, %1
+                %3: Int = sub %2, %2
+                %4: Float = const 0.0
+                %5: Float = const -0.0
+                %6: Float = add %5, %4
+                %7: Int = add %3, %3
+                return %7
+            }
+        "#;
+        assert_pass(Pass::Gvn, before, after);
+    }
+
+    #[test]
+    fn test_only_dominating_instructions_are_reused() {
+        let before = r#"
+            func @f(%0: Bool, %1: Int) -> Int {
+            bb0:
+                %2: Int = neg %1
+                branch %0, bb1, bb2
+            bb1:
+                %3: Int = neg %1
+                %4: Int = mul %3, %3
+                jump bb3
+            bb2:
+                %5: Int = mul %2, %2
+                jump bb3
+            bb3:
+                %6: Int = phi [bb1: %4], [bb2: %5]
+                %7: Int = mul %2, %2
+                %8: () = builtin println(%7)
+                return %6
+            }
+        "#;
+        // The multiplications in both branches are only merged by the phi, not replaced
+        let after = r#"
+            func @f(%0: Bool, %1: Int) -> Int {
+            bb0:
+                %2: Int = neg %1
+                branch %0, bb1, bb2
+            bb1:
+                %3: Int = mul %2, %2
+                jump bb3
+            bb2:
+                %4: Int = mul %2, %2
+                jump bb3
+            bb3:
+                %5: Int = phi [bb1: %3], [bb2: %4]
+                %6: Int = mul %2, %2
+                %7: () = builtin println(%6)
+                return %5
+            }
+        "#;
+        assert_pass(Pass::Gvn, before, after);
+    }
+}
@@ -0,0 +1,233 @@
+//! Inlining of calls to small functions: the block of the call is split in two, and a copy of the
+//! callee's blocks goes in between, with its parameters replaced by the arguments and its returns
+//! by jumps to a phi merging the result.
+//!
+//! Only functions that call nothing themselves are inlined, so that recursion is never unrolled
+//! and a call deep in the program still counts towards the maximum call depth. They must not
+//! capture anything, and must be in the same file as the caller, since their spans point into it.
+use crate::back_end::ir::passes::compact;
+use crate::back_end::ir::{Block, Function, InstructionKind, Phi, Program, Terminator, Value};
+use std::collections::HashMap;
+use std::mem;
+
+/// The most phis and instructions a function may have to be inlined
+const SIZE_LIMIT: usize = 24;
+
+pub fn run(program: &mut Program) {
+    // Inlining never changes the functions that can be inlined, which call nothing
+    let callees: Vec<Option<Function>> = program
+        .functions
+        .iter()
+        .map(|function| is_inlinable(function).then(|| function.clone()))
+        .collect();
+    for caller in &mut program.functions {
+        let mut inlined = false;
+        let mut block = 0;
+        while block < caller.blocks.len() {
+            let call = caller.blocks[block]
+                .instructions
+                .iter()
+                .position(|instruction| match instruction.kind {
+                    InstructionKind::Call { function, .. } => callees[function]
+                        .as_ref()
+                        .is_some_and(|callee| callee.file == caller.file),
+                    _ => false,
+                });
+            match call {
+                Some(position) => {
+                    let InstructionKind::Call { function, .. } =
+                        caller.blocks[block].instructions[position].kind
+                    else {
+                        unreachable!("a call was found");
+                    };
+                    let callee = callees[function].as_ref().unwrap();
+                    inline_call(caller, Block(block as u32), position, callee);
+                    inlined = true;
+                }
+                None => block += 1,
+            }
+        }
+        if inlined {
+            compact(caller, HashMap::new());
+        }
+    }
+}
+
+fn is_inlinable(function: &Function) -> bool {
+    let size: usize = function
+        .blocks
+        .iter()
+        .map(|block| block.phis.len() + block.instructions.len())
+        .sum();
+    let calls = function.blocks.iter().any(|block| {
+        block.instructions.iter().any(|instruction| {
+            matches!(
+                instruction.kind,
+                InstructionKind::Call { .. } | InstructionKind::CallValue { .. }
+            )
+        })
+    });
+    function.captures.is_empty() && size <= SIZE_LIMIT && !calls
+}
+
+/// Replaces the call at `position` in `block` with the body of `callee`
+fn inline_call(caller: &mut Function, block: Block, position: usize, callee: &Function) {
+    let mut rest = caller
+        .block_mut(block)
+        .instructions
+        .split_off(position)
+        .into_iter();
+    let call = rest.next().unwrap();
+    let InstructionKind::Call { args, .. } = call.kind else {
+        unreachable!("only calls are inlined");
+    };
+
+    // The instructions after the call continue in a new block
+    let continuation = caller.new_block();
+    let terminator = mem::replace(
+        &mut caller.block_mut(block).terminator,
+        Terminator::Unreachable,
+    );
+    for successor in terminator.successors() {
+        for phi in &mut caller.block_mut(successor).phis {
+            for (from, _) in &mut phi.incoming {
+                if *from == block {
+                    *from = continuation;
+                }
+            }
+        }
+    }
+    caller.block_mut(continuation).instructions = rest.collect();
+    caller.block_mut(continuation).terminator = terminator;
+
+    let mut values: Vec<Option<Value>> = vec![None; callee.values.len()];
+    for (param, arg) in callee.params.iter().zip(args) {
+        values[param.0 as usize] = Some(arg);
+    }
+    for (value, ty) in values.iter_mut().zip(&callee.values) {
+        if value.is_none() {
+            *value = Some(caller.new_value(ty.clone()));
+        }
+    }
+    let rename = |value: &mut Value| *value = values[value.0 as usize].unwrap();
+    let blocks: Vec<Block> = callee.blocks.iter().map(|_| caller.new_block()).collect();
+    let mut returns = Vec::new();
+    for (copy, original) in blocks.iter().zip(&callee.blocks) {
+        let mut body = original.clone();
+        for phi in &mut body.phis {
+            rename(&mut phi.result);
+            for (from, incoming) in &mut phi.incoming {
+                *from = blocks[from.0 as usize];
+                rename(incoming);
+            }
+        }
+        for instruction in &mut body.instructions {
+            if let Some(result) = &mut instruction.result {
+                rename(result);
+            }
+            instruction.kind.operands_mut().into_iter().for_each(rename);
+        }
+        if let Some(operand) = body.terminator.operand_mut() {
+            rename(operand);
+        }
+        for successor in body.terminator.successors_mut() {
+            *successor = blocks[successor.0 as usize];
+        }
+        if let Terminator::Return(result) = body.terminator {
+            returns.push((*copy, result));
+            body.terminator = Terminator::Jump(continuation);
+        }
+        *caller.block_mut(*copy) = body;
+    }
+    caller.block_mut(block).terminator = Terminator::Jump(blocks[0]);
+    let result = call.result.expect("calls have a result");
+    caller.block_mut(continuation).phis.push(Phi {
+        result,
+        incoming: returns,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::back_end::ir::passes::tests::assert_pass;
+    use crate::back_end::ir::passes::Pass;
+
+    #[test]
+    fn test_small_functions_are_inlined() {
+        let before = r#"
+            func @abs(%0: Int) -> Int {
+            bb0:
+                %1: Int = const 0
+                %2: Bool = lt %0, %1
+                branch %2, bb1, bb2
+            bb1:
+                %3: Int = neg %0
+                return %3
+            bb2:
+                return %0
+            }
+
+            func @main() -> () {
+            bb0:
+                %0: Int = const -3
+                %1: Int = call @abs(%0)
+                %2: () = builtin println(%1)
+                return %2
+            }
+        "#;
+        let after = r#"
+            func @abs(%0: Int) -> Int {
+            bb0:
+                %1: Int = const 0
+                %2: Bool = lt %0, %1
+                branch %2, bb1, bb2
+            bb1:
+                %3: Int = neg %0
+                return %3
+            bb2:
+                return %0
+            }
+
+            func @main() -> () {
+            bb0:
+                %0: Int = const -3
+                jump bb1
+            bb1:
+                %1: Int = const 0
+                %2: Bool = lt %0, %1
+                branch %2, bb2, bb3
+            bb2:
+                %3: Int = neg %0
+                jump bb4
+            bb3:
+                jump bb4
+            bb4:
+                %4: Int = phi [bb2: %3], [bb3: %0]
+                %5: () = builtin println(%4)
+                return %5
+            }
+        "#;
+        // Merging the blocks is left to `simplify`
+        assert_pass(Pass::Inline, before, after);
+    }
+
+    #[test]
+    fn test_recursive_functions_are_not_inlined() {
+        let text = r#"
+            func @countdown(%0: Int) -> Int {
+            bb0:
+                %1: Int = call @countdown(%0)
+                return %1
+            }
+
+            func @main() -> () {
+            bb0:
+                %0: Int = const 3
+                %1: Int = call @countdown(%0)
+                %2: () = const ()
+                return %2
+            }
+        "#;
+        assert_pass(Pass::Inline, text, text);
+    }
+}
@@ -0,0 +1,227 @@
+//! Loop-invariant code motion: the instructions of a natural loop whose operands are all defined
+//! outside of it compute the same value on every iteration, and are moved to its preheader, the
+//! block control always comes from when it enters the loop. Only removable instructions move,
+//! since the loop may not run them at all.
+//!
+//! Each loop gets a preheader first, unless control enters it from several blocks. Inner loops
+//! come before the loops around them, so that an instruction can move out of several loops.
+use crate::back_end::ir::passes::{is_pure, is_removable};
+use crate::back_end::ir::{cfg, cfg::Dominators, Block, Function, Terminator, Value};
+
+/// A natural loop, made of its header and the blocks that reach a back edge to it without going
+/// through it
+struct Loop {
+    header: Block,
+    body: Vec<bool>,
+    size: usize,
+}
+
+pub fn run(function: &mut Function) {
+    // Adding a preheader changes the loops around it, so they are found again
+    while let Some((outside, header)) = missing_preheader(function) {
+        let preheader = function.new_block();
+        function.block_mut(preheader).terminator = Terminator::Jump(header);
+        for successor in function.block_mut(outside).terminator.successors_mut() {
+            if *successor == header {
+                *successor = preheader;
+            }
+        }
+        for phi in &mut function.block_mut(header).phis {
+            for (from, _) in &mut phi.incoming {
+                if *from == outside {
+                    *from = preheader;
+                }
+            }
+        }
+    }
+
+    let predecessors = cfg::predecessors(function);
+    let order = cfg::reverse_postorder(function);
+    let mut definitions: Vec<Option<Block>> = vec![None; function.values.len()];
+    for &block in &order {
+        let basic_block = function.block(block);
+        for phi in &basic_block.phis {
+            definitions[phi.result.0 as usize] = Some(block);
+        }
+        for result in basic_block.instructions.iter().filter_map(|i| i.result) {
+            definitions[result.0 as usize] = Some(block);
+        }
+    }
+    for natural_loop in loops(function) {
+        let inside = |block: Block| natural_loop.body[block.0 as usize];
+        let outside: Vec<Block> = predecessors[natural_loop.header.0 as usize]
+            .iter()
+            .copied()
+            .filter(|&block| !inside(block))
+            .collect();
+        let preheader = match outside[..] {
+            [preheader] if function.block(preheader).terminator.successors().len() == 1 => {
+                preheader
+            }
+            _ => continue,
+        };
+        let invariant = |definitions: &[Option<Block>], value: Value| {
+            definitions[value.0 as usize].is_none_or(|block| !inside(block))
+        };
+        for &block in order.iter().filter(|&&block| inside(block)) {
+            let instructions = std::mem::take(&mut function.block_mut(block).instructions);
+            let mut kept = Vec::new();
+            for instruction in instructions {
+                let movable = instruction.result.is_some()
+                    && is_pure(&instruction.kind)
+                    && is_removable(function, &instruction.kind)
+                    && instruction
+                        .kind
+                        .operands()
+                        .into_iter()
+                        .all(|operand| invariant(&definitions, operand));
+                match (movable, instruction.result) {
+                    (true, Some(result)) => {
+                        definitions[result.0 as usize] = Some(preheader);
+                        function.block_mut(preheader).instructions.push(instruction);
+                    }
+                    _ => kept.push(instruction),
+                }
+            }
+            function.block_mut(block).instructions = kept;
+        }
+    }
+}
+
+/// A loop whose header is entered from a single block outside of it that has other successors,
+/// which the loop needs a preheader between
+fn missing_preheader(function: &Function) -> Option<(Block, Block)> {
+    let predecessors = cfg::predecessors(function);
+    loops(function).into_iter().find_map(|natural_loop| {
+        let outside: Vec<Block> = predecessors[natural_loop.header.0 as usize]
+            .iter()
+            .copied()
+            .filter(|block| !natural_loop.body[block.0 as usize])
+            .collect();
+        match outside[..] {
+            [block] if function.block(block).terminator.successors().len() > 1 => {
+                Some((block, natural_loop.header))
+            }
+            _ => None,
+        }
+    })
+}
+
+/// The natural loops of a function, smallest first, so that inner loops come before the loops
+/// around them. The back edges to the same header make a single loop.
+fn loops(function: &Function) -> Vec<Loop> {
+    let dominators = Dominators::compute(function);
+    let predecessors = cfg::predecessors(function);
+    let mut loops: Vec<Loop> = Vec::new();
+    for header in cfg::reverse_postorder(function) {
+        let latches: Vec<Block> = predecessors[header.0 as usize]
+            .iter()
+            .copied()
+            .filter(|&block| dominators.dominates(header, block))
+            .collect();
+        if latches.is_empty() {
+            continue;
+        }
+        let mut body = vec![false; function.blocks.len()];
+        body[header.0 as usize] = true;
+        let mut size = 1;
+        let mut worklist = latches;
+        while let Some(block) = worklist.pop() {
+            if !body[block.0 as usize] {
+                body[block.0 as usize] = true;
+                size += 1;
+                worklist.extend(&predecessors[block.0 as usize]);
+            }
+        }
+        loops.push(Loop { header, body, size });
+    }
+    loops.sort_by_key(|natural_loop| natural_loop.size);
+    loops
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::back_end::ir::passes::tests::assert_pass;
+    use crate::back_end::ir::passes::Pass;
+
+    #[test]
+    fn test_invariant_instructions_leave_the_loop() {
+        let before = r#"
+            func @f(%0: Float, %1: Int) -> Float {
+            bb0:
+                %2: Float = const 0.0
+                jump bb1
+            bb1:
+                %3: Float = phi [bb0: %2], [bb2: %8]
+                %4: Int = phi [bb0: %1], [bb2: %9]
+                %5: Int = const 0
+                %6: Bool = gt %4, %5
+                branch %6, bb2, bb3
+            bb2:
+                %7: Float = mul %0, %0
+                %8: Float = add %3, %7
+                %10: Int = const 1
+                %9: Int = sub %4, %10
+                jump bb1
+            bb3:
+                return %3
+            }
+        "#;
+        // The subtraction may overflow, so it only runs when the loop does
+        let after = r#"
+            func @f(%0: Float, %1: Int) -> Float {
+            bb0:
+                %2: Float = const 0.0
+                %5: Int = const 0
+                %7: Float = mul %0, %0
+                %10: Int = const 1
+                jump bb1
+            bb1:
+                %3: Float = phi [bb0: %2], [bb2: %8]
+                %4: Int = phi [bb0: %1], [bb2: %9]
+                %6: Bool = gt %4, %5
+                branch %6, bb2, bb3
+            bb2:
+                %8: Float = add %3, %7
+                %9: Int = sub %4, %10
+                jump bb1
+            bb3:
+                return %3
+            }
+        "#;
+        assert_pass(Pass::Licm, before, after);
+    }
+
+    #[test]
+    fn test_loops_get_a_preheader() {
+        let before = r#"
+            func @f(%0: Bool, %1: Int) -> Int {
+            bb0:
+                branch %0, bb1, bb3
+            bb1:
+                %2: Int = bitnot %1
+                branch %0, bb1, bb2
+            bb2:
+                return %2
+            bb3:
+                return %1
+            }
+        "#;
+        let after = r#"
+            func @f(%0: Bool, %1: Int) -> Int {
+            bb0:
+                branch %0, bb4, bb3
+            bb1:
+                branch %0, bb1, bb2
+            bb2:
+                return %2
+            bb3:
+                return %1
+            bb4:
+                %2: Int = bitnot %1
+                jump bb1
+            }
+        "#;
+        assert_pass(Pass::Licm, before, after);
+    }
+}
@@ -0,0 +1,445 @@
+//! Sparse conditional constant propagation, after Wegman and Zadeck, "Constant Propagation with
+//! Conditional Branches". Every value starts unknown and every block unreached, then the reached
+//! blocks are evaluated until nothing changes: a phi only merges the values coming from the
+//! edges taken so far, and a branch on a constant only takes one of its edges. Values found to be
+//! constant are then computed by `const` instructions, branches on constants become jumps, and the
+//! blocks never reached are removed.
+//!
+//! Operations are folded like they run, and the ones that would panic are left for the program to
+//! panic at run time.
+use crate::back_end::ir::passes::compact;
+use crate::back_end::ir::{
+    cfg, Block, Builtin, Constant, Function, Instruction, InstructionKind, Terminator, Value,
+};
+use crate::front_end::ast::{BinaryOp, UnaryOp};
+use crate::runtime::convert;
+use crate::runtime::math::{self, Number};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// What is known of a value: nothing yet, that it is always the same constant, or that it varies
+#[derive(Debug, Clone, PartialEq)]
+enum Lattice {
+    Unknown,
+    Constant(Constant),
+    Varying,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Unknown, known) | (known, Lattice::Unknown) => known.clone(),
+            (Lattice::Constant(a), Lattice::Constant(b)) if same(a, b) => self.clone(),
+            _ => Lattice::Varying,
+        }
+    }
+
+    /// Whether two facts are the same, which `==` is not for NaN
+    fn is(&self, other: &Lattice) -> bool {
+        match (self, other) {
+            (Lattice::Constant(a), Lattice::Constant(b)) => same(a, b),
+            _ => self == other,
+        }
+    }
+}
+
+/// Whether two constants are the same, telling apart floats that compare equal like `0.0` and
+/// `-0.0`
+fn same(a: &Constant, b: &Constant) -> bool {
+    match (a, b) {
+        (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+        _ => a == b,
+    }
+}
+
+pub fn run(function: &mut Function) {
+    let (values, edges) = analyze(function);
+    let reached = |block: Block| block.0 == 0 || edges.iter().any(|&(_, to)| to == block);
+
+    for block in function.block_ids().collect::<Vec<_>>() {
+        if !reached(block) {
+            continue;
+        }
+        let basic_block = function.block_mut(block);
+        let mut constants = Vec::new();
+        basic_block
+            .phis
+            .retain(|phi| match &values[phi.result.0 as usize] {
+                Lattice::Constant(constant) => {
+                    constants.push(Instruction {
+                        result: Some(phi.result),
+                        kind: InstructionKind::Const(constant.clone()),
+                        span: Default::default(),
+                    });
+                    false
+                }
+                _ => true,
+            });
+        for instruction in &mut basic_block.instructions {
+            if let Some(Lattice::Constant(constant)) =
+                instruction.result.map(|result| &values[result.0 as usize])
+            {
+                instruction.kind = InstructionKind::Const(constant.clone());
+            }
+        }
+        basic_block.instructions.splice(0..0, constants);
+
+        if let Terminator::Branch {
+            then, otherwise, ..
+        } = basic_block.terminator
+        {
+            let (taken, untaken) = match (
+                edges.contains(&(block, then)),
+                edges.contains(&(block, otherwise)),
+            ) {
+                (true, false) => (then, otherwise),
+                (false, true) => (otherwise, then),
+                _ => continue,
+            };
+            basic_block.terminator = Terminator::Jump(taken);
+            for phi in &mut function.block_mut(untaken).phis {
+                phi.incoming.retain(|(from, _)| *from != block);
+            }
+        }
+    }
+    compact(function, HashMap::new());
+}
+
+/// What is known of every value, and the edges control can take
+fn analyze(function: &Function) -> (Vec<Lattice>, HashSet<(Block, Block)>) {
+    let order = cfg::reverse_postorder(function);
+    let mut values = vec![Lattice::Unknown; function.values.len()];
+    for param in &function.params {
+        values[param.0 as usize] = Lattice::Varying;
+    }
+    let mut edges = HashSet::new();
+    let mut reached = vec![false; function.blocks.len()];
+    reached[0] = true;
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order {
+            if !reached[block.0 as usize] {
+                continue;
+            }
+            let basic_block = function.block(block);
+            for phi in &basic_block.phis {
+                let merged = phi
+                    .incoming
+                    .iter()
+                    .filter(|(from, _)| edges.contains(&(*from, block)))
+                    .fold(Lattice::Unknown, |merged, (_, value)| {
+                        merged.meet(&values[value.0 as usize])
+                    });
+                changed |= update(&mut values, phi.result, merged);
+            }
+            for instruction in &basic_block.instructions {
+                if let Some(result) = instruction.result {
+                    let evaluated = evaluate(&instruction.kind, &values);
+                    changed |= update(&mut values, result, evaluated);
+                }
+            }
+            let taken = match &basic_block.terminator {
+                Terminator::Branch {
+                    condition,
+                    then,
+                    otherwise,
+                } => match &values[condition.0 as usize] {
+                    Lattice::Unknown => vec![],
+                    Lattice::Constant(Constant::Bool(true)) => vec![*then],
+                    Lattice::Constant(Constant::Bool(false)) => vec![*otherwise],
+                    _ => vec![*then, *otherwise],
+                },
+                terminator => terminator.successors(),
+            };
+            for successor in taken {
+                if edges.insert((block, successor)) {
+                    reached[successor.0 as usize] = true;
+                    changed = true;
+                }
+            }
+        }
+    }
+    (values, edges)
+}
+
+/// Lowers what is known of `value` to `new`, returning whether that changed anything
+fn update(values: &mut [Lattice], value: Value, new: Lattice) -> bool {
+    let old = &values[value.0 as usize];
+    let new = old.meet(&new);
+    let changed = !old.is(&new);
+    values[value.0 as usize] = new;
+    changed
+}
+
+/// What is known of the result of an instruction, from what is known of its operands
+fn evaluate(kind: &InstructionKind, values: &[Lattice]) -> Lattice {
+    let foldable = match kind {
+        InstructionKind::Const(_)
+        | InstructionKind::Unary { .. }
+        | InstructionKind::Binary { .. }
+        | InstructionKind::IsNull(_) => true,
+        InstructionKind::CallBuiltin { builtin, .. } => {
+            matches!(builtin, Builtin::Int | Builtin::Float | Builtin::Char) || builtin.is_math()
+        }
+        _ => false,
+    };
+    if !foldable {
+        return Lattice::Varying;
+    }
+    let mut operands = Vec::new();
+    for operand in kind.operands() {
+        match &values[operand.0 as usize] {
+            Lattice::Constant(constant) => operands.push(constant),
+            Lattice::Unknown => return Lattice::Unknown,
+            Lattice::Varying => return Lattice::Varying,
+        }
+    }
+    let folded = match (kind, &operands[..]) {
+        (InstructionKind::Const(constant), []) => Some(constant.clone()),
+        (InstructionKind::Unary { op, .. }, [operand]) => fold_unary(*op, operand),
+        (InstructionKind::Binary { op, .. }, [left, right]) => fold_binary(*op, left, right),
+        (InstructionKind::IsNull(_), [operand]) => {
+            Some(Constant::Bool(**operand == Constant::Null))
+        }
+        (InstructionKind::CallBuiltin { builtin, .. }, operands) if builtin.is_math() => {
+            fold_math(*builtin, operands)
+        }
+        (InstructionKind::CallBuiltin { builtin, .. }, [operand]) => {
+            fold_conversion(*builtin, operand)
+        }
+        _ => None,
+    };
+    match folded {
+        Some(constant) => Lattice::Constant(constant),
+        None => Lattice::Varying,
+    }
+}
+
+/// The result of a unary operation, unless it panics
+pub fn fold_unary(op: UnaryOp, operand: &Constant) -> Option<Constant> {
+    match (op, operand) {
+        (UnaryOp::Negate, Constant::Int(value)) => value.checked_neg().map(Constant::Int),
+        (UnaryOp::Negate, Constant::Float(value)) => Some(Constant::Float(-value)),
+        (UnaryOp::Not, Constant::Bool(value)) => Some(Constant::Bool(!value)),
+        (UnaryOp::BitNot, Constant::Int(value)) => Some(Constant::Int(!value)),
+        _ => None,
+    }
+}
+
+/// The result of a binary operation, unless it panics
+pub fn fold_binary(op: BinaryOp, left: &Constant, right: &Constant) -> Option<Constant> {
+    match op {
+        BinaryOp::Equal => return Some(Constant::Bool(left == right)),
+        BinaryOp::NotEqual => return Some(Constant::Bool(left != right)),
+        BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+            let ordering = match (left, right) {
+                (Constant::Int(a), Constant::Int(b)) => a.partial_cmp(b),
+                (Constant::Float(a), Constant::Float(b)) => a.partial_cmp(b),
+                (Constant::Char(a), Constant::Char(b)) => a.partial_cmp(b),
+                _ => return None,
+            };
+            // Every comparison with NaN is false
+            let result = ordering.is_some_and(|ordering| match op {
+                BinaryOp::Less => ordering == Ordering::Less,
+                BinaryOp::LessEqual => ordering != Ordering::Greater,
+                BinaryOp::Greater => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            });
+            return Some(Constant::Bool(result));
+        }
+        _ => {}
+    }
+    match (left, right) {
+        (&Constant::Int(a), &Constant::Int(b)) => {
+            let shift = |shift: fn(i32, u32) -> Option<i32>| {
+                u32::try_from(b).ok().and_then(|b| shift(a, b))
+            };
+            let result = match op {
+                BinaryOp::Add => a.checked_add(b),
+                BinaryOp::Subtract => a.checked_sub(b),
+                BinaryOp::Multiply => a.checked_mul(b),
+                BinaryOp::Divide => a.checked_div(b),
+                BinaryOp::Remainder => a.checked_rem(b),
+                BinaryOp::BitAnd => Some(a & b),
+                BinaryOp::BitOr => Some(a | b),
+                BinaryOp::BitXor => Some(a ^ b),
+                BinaryOp::ShiftLeft => shift(i32::checked_shl),
+                BinaryOp::ShiftRight => shift(i32::checked_shr),
+                _ => None,
+            };
+            result.map(Constant::Int)
+        }
+        (&Constant::Float(a), &Constant::Float(b)) => {
+            let result = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Subtract => a - b,
+                BinaryOp::Multiply => a * b,
+                BinaryOp::Divide => a / b,
+                BinaryOp::Remainder => a % b,
+                _ => return None,
+            };
+            Some(Constant::Float(result))
+        }
+        _ => None,
+    }
+}
+
+/// The result of `int()`, `float()` or `char()`, unless it panics or parses a string
+pub fn fold_conversion(builtin: Builtin, operand: &Constant) -> Option<Constant> {
+    match (builtin, operand) {
+        (Builtin::Int, &Constant::Float(value)) => convert::float_to_int(value).map(Constant::Int),
+        (Builtin::Int, &Constant::Char(c)) => Some(Constant::Int(c as i32)),
+        (Builtin::Float, &Constant::Int(value)) => Some(Constant::Float(value as f64)),
+        (Builtin::Char, &Constant::Int(value)) => convert::int_to_char(value).map(Constant::Char),
+        (_, Constant::String(_)) => None,
+        (_, operand) => Some(operand.clone()),
+    }
+}
+
+/// The result of a math built-in, unless it panics
+pub fn fold_math(builtin: Builtin, operands: &[&Constant]) -> Option<Constant> {
+    let numbers = operands
+        .iter()
+        .map(|operand| match operand {
+            Constant::Int(value) => Some(Number::Int(*value)),
+            Constant::Float(value) => Some(Number::Float(*value)),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    match math::call(builtin.name(), &numbers).ok()? {
+        Number::Int(value) => Some(Constant::Int(value)),
+        Number::Float(value) => Some(Constant::Float(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::ir::passes::tests::assert_pass;
+    use crate::back_end::ir::passes::Pass;
+
+    #[test]
+    fn test_constants_are_folded() {
+        let before = r#"
+            func @f() -> Int {
+            bb0:
+                %0: Int = const 6
+                %1: Int = const 7
+                %2: Int = mul %0, %1
+                %3: Int = neg %2
+                %4: Bool = lt %3, %0
+                %5: () = builtin println(%4)
+                %6: Float = builtin float(%3)
+                %7: Char = builtin char(%1)
+                %8: Int = builtin int(%7)
+                %9: () = builtin println(%6)
+                %10: Int = builtin pow(%0, %1)
+                %11: Float = builtin abs(%6)
+                %12: Float = builtin max(%6, %11)
+                return %3
+            }
+        "#;
+        let after = r#"
+            func @f() -> Int {
+            bb0:
+                %0: Int = const 6
+                %1: Int = const 7
+                %2: Int = const 42
+                %3: Int = const -42
+                %4: Bool = const true
+                %5: () = builtin println(%4)
+                %6: Float = const -42.0
+                %7: Char = const '\u{7}'
+                %8: Int = const 7
+                %9: () = builtin println(%6)
+                %10: Int = const 279936
+                %11: Float = const 42.0
+                %12: Float = const 42.0
+                return %3
+            }
+        "#;
+        assert_pass(Pass::Sccp, before, after);
+    }
+
+    #[test]
+    fn test_operations_that_panic_are_not_folded() {
+        assert_eq!(
+            fold_binary(BinaryOp::Add, &Constant::Int(i32::MAX), &Constant::Int(1)),
+            None
+        );
+        assert_eq!(
+            fold_binary(BinaryOp::Remainder, &Constant::Int(1), &Constant::Int(0)),
+            None
+        );
+        assert_eq!(
+            fold_binary(BinaryOp::ShiftLeft, &Constant::Int(1), &Constant::Int(32)),
+            None
+        );
+        assert_eq!(fold_unary(UnaryOp::Negate, &Constant::Int(i32::MIN)), None);
+        assert_eq!(
+            fold_conversion(Builtin::Int, &Constant::Float(f64::NAN)),
+            None
+        );
+        assert_eq!(fold_conversion(Builtin::Char, &Constant::Int(-1)), None);
+        assert_eq!(
+            fold_math(Builtin::Pow, &[&Constant::Int(2), &Constant::Int(31)]),
+            None
+        );
+        assert_eq!(fold_math(Builtin::Abs, &[&Constant::Int(i32::MIN)]), None);
+        assert_eq!(
+            fold_binary(
+                BinaryOp::Equal,
+                &Constant::Float(f64::NAN),
+                &Constant::Float(f64::NAN)
+            ),
+            Some(Constant::Bool(false))
+        );
+    }
+
+    #[test]
+    fn test_branches_on_constants_are_resolved() {
+        // The loop runs once: `%i` is 0 on entry, and the back edge is never taken
+        let before = r#"
+            func @f(%0: Int) -> Int {
+            bb0:
+                %1: Int = const 0
+                jump bb1
+            bb1:
+                %2: Int = phi [bb0: %1], [bb3: %6]
+                %3: Int = const 1
+                %4: Bool = lt %2, %3
+                branch %4, bb2, bb4
+            bb2:
+                %5: Bool = gt %2, %3
+                branch %5, bb3, bb4
+            bb3:
+                %6: Int = add %2, %0
+                jump bb1
+            bb4:
+                %7: Int = phi [bb1: %2], [bb2: %3]
+                return %7
+            }
+        "#;
+        let after = r#"
+            func @f(%0: Int) -> Int {
+            bb0:
+                %1: Int = const 0
+                jump bb1
+            bb1:
+                %2: Int = const 0
+                %3: Int = const 1
+                %4: Bool = const true
+                jump bb2
+            bb2:
+                %5: Bool = const false
+                jump bb3
+            bb3:
+                %6: Int = const 1
+                return %6
+            }
+        "#;
+        assert_pass(Pass::Sccp, before, after);
+    }
+}
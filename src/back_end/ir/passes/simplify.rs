@@ -0,0 +1,127 @@
+//! Control-flow graph simplification: a block whose only predecessor jumps to it is appended to
+//! that predecessor, then the unreachable blocks and trivial phis left behind by other passes are
+//! removed, and the function is renumbered.
+use crate::back_end::ir::passes::{compact, resolve};
+use crate::back_end::ir::{cfg, BasicBlock, Block, Function, Terminator};
+use std::collections::HashMap;
+use std::mem;
+
+pub fn run(function: &mut Function) {
+    // Unreachable predecessors would keep blocks apart
+    compact(function, HashMap::new());
+    let mut predecessors = cfg::predecessors(function);
+    let mut forward = HashMap::new();
+    for block in function.block_ids() {
+        while let Terminator::Jump(target) = function.block(block).terminator {
+            if target == block || target.0 == 0 || predecessors[target.0 as usize] != [block] {
+                break;
+            }
+            let empty = BasicBlock {
+                phis: Vec::new(),
+                instructions: Vec::new(),
+                terminator: Terminator::Unreachable,
+            };
+            let merged = mem::replace(function.block_mut(target), empty);
+            // The phis of a block with a single predecessor merge a single value
+            for phi in merged.phis {
+                let (_, value) = phi.incoming[0];
+                forward.insert(phi.result, resolve(&forward, value));
+            }
+            for successor in merged.terminator.successors() {
+                rename_predecessor(function, &mut predecessors, successor, target, block);
+            }
+            let host = function.block_mut(block);
+            host.instructions.extend(merged.instructions);
+            host.terminator = merged.terminator;
+        }
+    }
+    compact(function, forward);
+}
+
+/// Records that control reaches `block` from `to` instead of `from`
+fn rename_predecessor(
+    function: &mut Function,
+    predecessors: &mut [Vec<Block>],
+    block: Block,
+    from: Block,
+    to: Block,
+) {
+    for predecessor in &mut predecessors[block.0 as usize] {
+        if *predecessor == from {
+            *predecessor = to;
+        }
+    }
+    for phi in &mut function.block_mut(block).phis {
+        for (incoming, _) in &mut phi.incoming {
+            if *incoming == from {
+                *incoming = to;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::back_end::ir::passes::tests::assert_pass;
+    use crate::back_end::ir::passes::Pass;
+
+    #[test]
+    fn test_straight_line_blocks_are_merged() {
+        let before = r#"
+            func @f(%0: Int) -> Int {
+            bb0:
+                jump bb1
+            bb1:
+                %1: Int = phi [bb0: %0]
+                %2: Bool = const true
+                branch %2, bb2, bb3
+            bb2:
+                jump bb4
+            bb3:
+                jump bb4
+            bb4:
+                %3: Int = phi [bb2: %1], [bb3: %0]
+                return %3
+            }
+        "#;
+        let after = r#"
+            func @f(%0: Int) -> Int {
+            bb0:
+                %1: Bool = const true
+                branch %1, bb1, bb2
+            bb1:
+                jump bb3
+            bb2:
+                jump bb3
+            bb3:
+                return %0
+            }
+        "#;
+        assert_pass(Pass::Simplify, before, after);
+    }
+
+    #[test]
+    fn test_unreachable_blocks_are_removed() {
+        let before = r#"
+            func @f() -> Int {
+            bb0:
+                %0: Int = const 1
+                jump bb2
+            bb1:
+                %1: Int = const 2
+                jump bb2
+            bb2:
+                %2: Int = phi [bb0: %0], [bb1: %1]
+                return %2
+            }
+        "#;
+        let after = r#"
+            func @f() -> Int {
+            bb0:
+                %0: Int = const 1
+                return %0
+            }
+        "#;
+        assert_pass(Pass::Simplify, before, after);
+    }
+}
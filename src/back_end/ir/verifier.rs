@@ -0,0 +1,271 @@
+//! Checks that the functions of an IR program are well formed, so that passes can rely on it:
+//! - the entry block has no predecessors and no phis, and every jump targets a block
+//! - every value is defined once, and every use of a value is dominated by its definition
+//! - phis have one incoming value for each predecessor of their block, and nothing else
+//! - instructions refer to existing functions and environment slots, with the right number of
+//!   arguments, closures capture values of the types their function's environment declares, and
+//!   heap cells are only used as such
+use crate::back_end::ir::{
+    cfg::{self, Dominators},
+    Block, Function, InstructionKind, IrError, Program, Value, ValueType,
+};
+
+/// Verifies every function of a program
+pub fn verify(program: &Program) -> Result<(), IrError> {
+    if program.entry >= program.functions.len() {
+        return Err(IrError::Invalid {
+            function: String::new(),
+            reason: format!("the entry function {} does not exist", program.entry),
+        });
+    }
+    for function in &program.functions {
+        verify_function(program, function)?;
+    }
+    Ok(())
+}
+
+/// Where a value is defined: a parameter, or the position of its definition in a block, where
+/// phis come first
+#[derive(Debug, Clone, Copy)]
+enum Definition {
+    Parameter,
+    Block(Block, usize),
+}
+
+/// Verifies one function of a program
+pub fn verify_function(program: &Program, function: &Function) -> Result<(), IrError> {
+    let invalid = |reason: String| IrError::Invalid {
+        function: function.name.clone(),
+        reason,
+    };
+    if function.blocks.is_empty() {
+        return Err(invalid("it has no blocks".to_string()));
+    }
+    for block in function.block_ids() {
+        let terminator = &function.block(block).terminator;
+        for successor in terminator.successors() {
+            if successor.0 as usize >= function.blocks.len() {
+                return Err(invalid(format!(
+                    "{} jumps to {}, which does not exist",
+                    block, successor
+                )));
+            }
+            if successor.0 == 0 {
+                return Err(invalid(format!("{} jumps to the entry block", block)));
+            }
+        }
+        if let [then, otherwise] = terminator.successors()[..] {
+            if then == otherwise {
+                return Err(invalid(format!(
+                    "{} branches to {} either way",
+                    block, then
+                )));
+            }
+        }
+    }
+    if !function.blocks[0].phis.is_empty() {
+        return Err(invalid("the entry block has phis".to_string()));
+    }
+
+    // Definitions
+    let mut definitions: Vec<Option<Definition>> = vec![None; function.values.len()];
+    let mut define =
+        |value: Value, definition: Definition| match definitions.get_mut(value.0 as usize) {
+            None => Err(invalid(format!("{} has no type", value))),
+            Some(Some(_)) => Err(invalid(format!("{} is defined more than once", value))),
+            Some(slot) => {
+                *slot = Some(definition);
+                Ok(())
+            }
+        };
+    for &param in &function.params {
+        define(param, Definition::Parameter)?;
+    }
+    for block in function.block_ids() {
+        let basic_block = function.block(block);
+        for (position, phi) in basic_block.phis.iter().enumerate() {
+            define(phi.result, Definition::Block(block, position))?;
+        }
+        let phis = basic_block.phis.len();
+        for (position, instruction) in basic_block.instructions.iter().enumerate() {
+            let has_result = !matches!(instruction.kind, InstructionKind::StoreCell { .. });
+            match (instruction.result, has_result) {
+                (Some(result), true) => define(result, Definition::Block(block, phis + position))?,
+                (None, false) => (),
+                (Some(result), false) => {
+                    return Err(invalid(format!(
+                        "{} is defined by an instruction without a result",
+                        result
+                    )))
+                }
+                (None, true) => {
+                    return Err(invalid(format!(
+                        "an instruction of {} has no result",
+                        block
+                    )))
+                }
+            }
+        }
+    }
+
+    // Phis
+    let predecessors = cfg::predecessors(function);
+    for block in function.block_ids() {
+        let mut expected = predecessors[block.0 as usize].clone();
+        expected.sort();
+        for phi in &function.block(block).phis {
+            let mut incoming: Vec<Block> = phi.incoming.iter().map(|(from, _)| *from).collect();
+            incoming.sort();
+            if incoming != expected {
+                return Err(invalid(format!(
+                    "the incoming blocks of {} are not the predecessors of {}",
+                    phi.result, block
+                )));
+            }
+        }
+    }
+
+    // Uses
+    let dominators = Dominators::compute(function);
+    let check_use = |value: Value, block: Block, position: usize| -> Result<(), IrError> {
+        match definitions.get(value.0 as usize).copied().flatten() {
+            None => Err(invalid(format!(
+                "{} is used in {} but never defined",
+                value, block
+            ))),
+            Some(Definition::Parameter) => Ok(()),
+            // Dominance only makes sense for blocks reachable from the entry
+            Some(_) if !dominators.is_reachable(block) => Ok(()),
+            Some(Definition::Block(defined, at)) => {
+                let dominated = match defined == block {
+                    true => at < position,
+                    false => dominators.dominates(defined, block),
+                };
+                match dominated {
+                    true => Ok(()),
+                    false => Err(invalid(format!(
+                        "{} is used in {} where its definition does not dominate the use",
+                        value, block
+                    ))),
+                }
+            }
+        }
+    };
+    for block in function.block_ids() {
+        let basic_block = function.block(block);
+        for phi in &basic_block.phis {
+            for &(from, value) in &phi.incoming {
+                // An incoming value is used at the end of its predecessor
+                check_use(value, from, usize::MAX)?;
+            }
+        }
+        let phis = basic_block.phis.len();
+        for (position, instruction) in basic_block.instructions.iter().enumerate() {
+            for operand in instruction.kind.operands() {
+                check_use(operand, block, phis + position)?;
+            }
+            check_instruction(program, function, &instruction.kind).map_err(invalid)?;
+        }
+        if let Some(value) = basic_block.terminator.operand() {
+            check_use(value, block, usize::MAX)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the functions, environment slots, arguments and cells an instruction refers to
+fn check_instruction(
+    program: &Program,
+    function: &Function,
+    kind: &InstructionKind,
+) -> Result<(), String> {
+    let callee = |index: usize| match program.functions.get(index) {
+        Some(callee) => Ok(callee),
+        None => Err(format!("function {} does not exist", index)),
+    };
+    let is_cell = |value: Value| {
+        matches!(
+            function.values.get(value.0 as usize),
+            Some(ValueType::Cell(_))
+        )
+    };
+    match kind {
+        InstructionKind::Function(index) => {
+            callee(*index)?;
+        }
+        InstructionKind::Closure {
+            function: index,
+            captures,
+        } => {
+            let closure = callee(*index)?;
+            if closure.captures.len() != captures.len() {
+                return Err(format!(
+                    "`{}` captures {} values, not {}",
+                    closure.name,
+                    closure.captures.len(),
+                    captures.len()
+                ));
+            }
+            for (slot, (expected, &capture)) in closure.captures.iter().zip(captures).enumerate() {
+                match function.values.get(capture.0 as usize) {
+                    Some(found) if found != expected => {
+                        return Err(format!(
+                            "slot {} of `{}` holds {}, but {} is {}",
+                            slot, closure.name, expected, capture, found
+                        ));
+                    }
+                    _ => (),
+                }
+            }
+        }
+        InstructionKind::Call {
+            function: index,
+            args,
+        } => {
+            let called = callee(*index)?;
+            if called.params.len() != args.len() {
+                return Err(format!(
+                    "`{}` takes {} arguments, not {}",
+                    called.name,
+                    called.params.len(),
+                    args.len()
+                ));
+            }
+        }
+        InstructionKind::Capture(slot) if *slot as usize >= function.captures.len() => {
+            return Err(format!("there is no environment slot {}", slot));
+        }
+        InstructionKind::LoadCell(cell) | InstructionKind::StoreCell { cell, .. }
+            if !is_cell(*cell) =>
+        {
+            return Err(format!("{} is not a heap cell", cell));
+        }
+        InstructionKind::CallBuiltin { builtin, args } => {
+            let (min, max) = builtin.arity();
+            if !(min as usize..=max as usize).contains(&args.len()) {
+                return Err(format!(
+                    "`{}` does not take {} arguments",
+                    builtin.name(),
+                    args.len()
+                ));
+            }
+        }
+        _ => (),
+    }
+    // Heap cells are only created, read and written, or passed to closures
+    let passes_cells = matches!(
+        kind,
+        InstructionKind::Closure { .. } | InstructionKind::StoreCell { .. }
+    );
+    if !passes_cells {
+        let operands = kind.operands();
+        let cells = match kind {
+            InstructionKind::LoadCell(_) => &operands[1..],
+            _ => &operands[..],
+        };
+        if let Some(cell) = cells.iter().find(|&&value| is_cell(value)) {
+            return Err(format!("{} is a heap cell", cell));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1316 @@
+//! LLVM backend, which lowers the IR of a program to textual LLVM IR, and builds it with `llc`
+//! (or `clang`) into an object file that is linked with the runtime of the C backend.
+//! Values share the runtime's tagged `cw_value` layout, whose payload words are read as on
+//! little-endian targets. The values and phis of the IR map directly to LLVM's own, and variables
+//! captured by reference live in heap cells. Values that may point into the heap are also stored
+//! in stack slots, which the frame a function pushes lists as roots for the garbage collector.
+use crate::back_end::ir::{
+    self, Block, Builtin, Constant, Function, InstructionKind, Program, Terminator, Value,
+    ValueType,
+};
+use crate::back_end::{self, c};
+use crate::front_end::ast::{BinaryOp, UnaryOp};
+use crate::front_end::modules::SourceFile;
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Tags of `cw_value`, as numbered in `c/crawfish.h`
+const INT: u8 = 0;
+const FLOAT: u8 = 1;
+const BOOL: u8 = 2;
+const CHAR: u8 = 3;
+const UNIT: u8 = 4;
+const NULL: u8 = 5;
+const RANGE: u8 = 6;
+const STRING: u8 = 7;
+const OK: u8 = 8;
+const ERR: u8 = 9;
+const CLOSURE: u8 = 10;
+const CELL: u8 = 11;
+
+/// Kinds of heap objects, as numbered in `c/crawfish.h`
+const OBJECT_CLOSURE: u8 = 1;
+const OBJECT_STRING: u8 = 2;
+
+const PRELUDE: &str = r#"%cw_value = type { i8, [2 x i64] }
+%cw_object = type { ptr, i32, i8, i8 }
+%cw_string = type { %cw_object, i64, ptr }
+%cw_closure = type { %cw_object, ptr, i32, [0 x %cw_value] }
+%cw_site = type { ptr, i32, i32 }
+%cw_frame = type { ptr, i32, i32, ptr, ptr }
+
+@cw_depth = external global i32
+@cw_frames = external global ptr
+
+declare void @cw_init()
+declare i32 @cw_finish()
+declare void @cw_enter(ptr)
+declare void @cw_panic(ptr, ptr) noreturn
+declare void @cw_panic_value(ptr, ptr) noreturn
+declare void @cw_unwrap_failed(ptr, ptr) noreturn
+declare ptr @cw_new_closure(ptr, i32)
+declare ptr @cw_box(ptr)
+declare zeroext i1 @cw_equal_at(ptr, ptr)
+declare void @cw_print_line(ptr)
+declare void @cw_print_at(ptr)
+declare void @cw_print_padded_at(ptr, i32, i32, i32)
+declare void @cw_input_at(ptr)
+declare void @cw_to_string_at(ptr, ptr)
+declare void @cw_concat_at(ptr, ptr, ptr)
+declare void @cw_to_int_at(ptr, ptr, ptr)
+declare void @cw_to_float_at(ptr, ptr)
+declare void @cw_to_char_at(ptr, ptr, ptr)
+declare i32 @cw_pow_int(ptr, i32, i32)
+declare void @cw_open_at(ptr, ptr, ptr)
+declare void @cw_close_at(ptr)
+declare void @cw_read_at(ptr, ptr)
+declare void @cw_read_line_at(ptr, ptr)
+declare void @cw_write_file_at(ptr, ptr, ptr)
+declare void @cw_next_line_at(ptr, ptr)
+declare void @cw_char_at_at(ptr, ptr, i32)
+declare i32 @cw_char_width_at(ptr, i32)
+declare double @fabs(double)
+declare double @pow(double, double)
+declare double @sqrt(double)
+declare double @ceil(double)
+declare double @floor(double)
+declare double @sin(double)
+declare double @cos(double)
+declare double @log10(double)
+declare double @log(double)
+declare double @exp(double)
+declare { i32, i1 } @llvm.sadd.with.overflow.i32(i32, i32)
+declare { i32, i1 } @llvm.ssub.with.overflow.i32(i32, i32)
+declare { i32, i1 } @llvm.smul.with.overflow.i32(i32, i32)
+"#;
+
+/// Generates the LLVM IR of a program compiled from `files`
+pub fn generate(program: &Program, files: &[SourceFile]) -> String {
+    let mut module = Module {
+        files,
+        globals: String::new(),
+        paths: HashMap::new(),
+        sites: HashMap::new(),
+        strings: HashMap::new(),
+        messages: HashMap::new(),
+    };
+    let mut functions = String::new();
+    for (index, function) in program.functions.iter().enumerate() {
+        let body = FunctionGenerator::new(&mut module, function).generate(index);
+        functions.push('\n');
+        functions.push_str(&body);
+        if function.captures.is_empty() && !function.name.contains('$') {
+            writeln!(
+                module.globals,
+                "@cw_function{} = internal global %cw_closure {{ {}, ptr @f{}, i32 0, [0 x %cw_value] zeroinitializer }}",
+                index,
+                static_object(OBJECT_CLOSURE),
+                index
+            )
+            .unwrap();
+        }
+    }
+
+    let mut out = String::from("; Generated by the Crawfish LLVM backend\n");
+    out.push_str(PRELUDE);
+    out.push('\n');
+    out.push_str(&module.globals);
+    out.push_str(&functions);
+    writeln!(
+        out,
+        "\ndefine i32 @main() {{\n  call void @cw_init()\n  %result = call %cw_value @f{}(ptr null)\n  %code = call i32 @cw_finish()\n  ret i32 %code\n}}",
+        program.entry
+    )
+    .unwrap();
+    out
+}
+
+/// Builds an executable at `output` from generated LLVM IR, with `llc` (or `clang` when `llc` is
+/// missing) and the C compiler `compiler`
+pub fn compile(ir: &str, output: &Path, compiler: &str) -> Result<(), Box<dyn Error>> {
+    back_end::in_temporary_directory(output, |directory| {
+        let source = directory.join("main.ll");
+        let object = directory.join("main.o");
+        fs::write(&source, ir)?;
+
+        let llc = std::env::var("LLC").unwrap_or_else(|_| "llc".to_string());
+        match Command::new(&llc).arg("--version").output() {
+            Ok(version) => {
+                let mut command = Command::new(&llc);
+                command.args(["-O2", "-filetype=obj", "-relocation-model=pic"]);
+                // Opaque pointers are the default from LLVM 15 on
+                if llvm_major_version(&String::from_utf8_lossy(&version.stdout)) < 15 {
+                    command.arg("-opaque-pointers");
+                }
+                command.arg(&source).arg("-o").arg(&object);
+                back_end::run_tool(&mut command, &llc, "LLC")?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut command = Command::new("clang");
+                command.args(["-O2", "-c", "-fPIC"]);
+                command.arg(&source).arg("-o").arg(&object);
+                back_end::run_tool(&mut command, "clang", "LLC")?;
+            }
+            Err(e) => return Err(format!("Cannot run `{}` ({})", llc, e).into()),
+        }
+        back_end::link(directory, &[object], output, compiler)
+    })
+}
+
+/// The major version in the output of `llc --version`, e.g. 14 for "LLVM version 14.0.6"
+fn llvm_major_version(version: &str) -> u32 {
+    version
+        .split("LLVM version ")
+        .nth(1)
+        .and_then(|rest| rest.split('.').next())
+        .and_then(|major| major.trim().parse().ok())
+        .unwrap_or(u32::MAX)
+}
+
+/// An LLVM string constant of `bytes`, escaping everything but printable ASCII
+fn llvm_string(bytes: &[u8]) -> String {
+    let mut literal = String::from("c\"");
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(literal, "\\{:02X}", byte).unwrap(),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => write!(literal, "\\{:02X}", byte).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// A constant `cw_value` with `tag`, whose first payload word is `word`
+fn constant(tag: u8, word: impl std::fmt::Display) -> String {
+    format!("{{ i8 {}, [2 x i64] [i64 {}, i64 0] }}", tag, word)
+}
+
+/// The header of a heap object that is part of the program, which is always marked
+fn static_object(kind: u8) -> String {
+    format!("%cw_object {{ ptr null, i32 0, i8 {}, i8 1 }}", kind)
+}
+
+/// Module-wide state of the generator
+/// - `globals` holds the constants emitted so far: paths, sites, strings and panic messages
+/// - `paths`, `sites`, `strings` and `messages` map what was emitted to its global's number
+struct Module<'p> {
+    files: &'p [SourceFile],
+    globals: String,
+    paths: HashMap<usize, usize>,
+    sites: HashMap<(usize, Span), usize>,
+    strings: HashMap<String, usize>,
+    messages: HashMap<&'static str, usize>,
+}
+
+impl Module<'_> {
+    /// The global describing the source location of an operation at `span` in `file`
+    fn site(&mut self, file: usize, span: Span) -> String {
+        if let Some(index) = self.sites.get(&(file, span)) {
+            return format!("@site.{}", index);
+        }
+        let path = match self.paths.get(&file) {
+            Some(&path) => path,
+            None => {
+                let path = self.paths.len();
+                let mut bytes = self.files[file].path.display().to_string().into_bytes();
+                bytes.push(0);
+                writeln!(
+                    self.globals,
+                    "@path.{} = private unnamed_addr constant [{} x i8] {}",
+                    path,
+                    bytes.len(),
+                    llvm_string(&bytes)
+                )
+                .unwrap();
+                self.paths.insert(file, path);
+                path
+            }
+        };
+        let index = self.sites.len();
+        let (line, column) = span.line_col(&self.files[file].source);
+        writeln!(
+            self.globals,
+            "@site.{} = private unnamed_addr constant %cw_site {{ ptr @path.{}, i32 {}, i32 {} }}",
+            index, path, line, column
+        )
+        .unwrap();
+        self.sites.insert((file, span), index);
+        format!("@site.{}", index)
+    }
+
+    /// The global `cw_string` of a string literal
+    fn string(&mut self, value: &str) -> String {
+        if let Some(index) = self.strings.get(value) {
+            return format!("@string.{}", index);
+        }
+        let index = self.strings.len();
+        let bytes = value.as_bytes();
+        writeln!(
+            self.globals,
+            "@bytes.{} = private unnamed_addr constant [{} x i8] {}",
+            index,
+            bytes.len(),
+            llvm_string(bytes)
+        )
+        .unwrap();
+        writeln!(
+            self.globals,
+            "@string.{} = private unnamed_addr constant %cw_string {{ {}, i64 {}, ptr @bytes.{} }}",
+            index,
+            static_object(OBJECT_STRING),
+            bytes.len(),
+            index
+        )
+        .unwrap();
+        self.strings.insert(value.to_string(), index);
+        format!("@string.{}", index)
+    }
+
+    /// The global holding a panic message as a C string
+    fn message(&mut self, message: &'static str) -> String {
+        if let Some(index) = self.messages.get(message) {
+            return format!("@message.{}", index);
+        }
+        let index = self.messages.len();
+        let mut bytes = message.as_bytes().to_vec();
+        bytes.push(0);
+        writeln!(
+            self.globals,
+            "@message.{} = private unnamed_addr constant [{} x i8] {}",
+            index,
+            bytes.len(),
+            llvm_string(&bytes)
+        )
+        .unwrap();
+        self.messages.insert(message, index);
+        format!("@message.{}", index)
+    }
+}
+
+/// The LLVM type of an IR value
+fn llvm_type(ty: &ValueType) -> &'static str {
+    match ty {
+        ValueType::Value(_) => "%cw_value",
+        ValueType::Cell(_) => "ptr",
+    }
+}
+
+/// Generates one function. IR values become LLVM values (`ptr`s for heap cells), and IR blocks
+/// become LLVM blocks, which the checks of operations that can panic split further.
+/// - `operands` holds the LLVM operand of every IR value generated so far
+/// - `blocks` holds the code of every IR block generated so far, and `ends` the label of the LLVM
+///   block it ends in, which the phis of its successors name
+/// - `current` is the label of the LLVM block being generated
+struct FunctionGenerator<'m, 'p> {
+    module: &'m mut Module<'p>,
+    function: &'p Function,
+    operands: Vec<String>,
+    blocks: Vec<String>,
+    ends: Vec<String>,
+    current: String,
+    allocas: String,
+    body: String,
+    names: usize,
+    terminated: bool,
+    rooted: bool,
+}
+
+impl<'m, 'p> FunctionGenerator<'m, 'p> {
+    fn new(module: &'m mut Module<'p>, function: &'p Function) -> Self {
+        let mut operands = vec![String::new(); function.values.len()];
+        for (n, param) in function.params.iter().enumerate() {
+            operands[param.0 as usize] = format!("%a{}", n);
+        }
+        Self {
+            module,
+            function,
+            operands,
+            blocks: Vec::new(),
+            ends: Vec::new(),
+            current: String::new(),
+            allocas: String::new(),
+            body: String::new(),
+            names: 0,
+            terminated: false,
+            rooted: false,
+        }
+    }
+
+    fn generate(mut self, index: usize) -> String {
+        let function = self.function;
+        // Scratch space to pass values to the runtime by address
+        self.allocas.push_str("  %scratch.a = alloca %cw_value\n");
+        self.allocas.push_str("  %scratch.b = alloca %cw_value\n");
+        self.rooted = self.push_frame();
+        for block in function.block_ids() {
+            // The entry block follows the allocas
+            self.current = match block {
+                Block(0) => "entry".to_string(),
+                _ => block.to_string(),
+            };
+            self.terminated = false;
+            for phi in &function.block(block).phis {
+                self.operands[phi.result.0 as usize] = format!("%v{}", phi.result.0);
+                self.root(phi.result);
+            }
+            for instruction in &function.block(block).instructions {
+                self.instruction(instruction);
+            }
+            self.terminator(block);
+            self.blocks.push(std::mem::take(&mut self.body));
+            self.ends.push(self.current.clone());
+        }
+
+        let mut body = String::new();
+        for block in function.block_ids() {
+            if block != Block(0) {
+                writeln!(body, "{}:", block).unwrap();
+            }
+            for phi in &function.block(block).phis {
+                let incoming: Vec<String> = phi
+                    .incoming
+                    .iter()
+                    .map(|(from, value)| {
+                        format!(
+                            "[ {}, %{} ]",
+                            self.operands[value.0 as usize], self.ends[from.0 as usize]
+                        )
+                    })
+                    .collect();
+                writeln!(
+                    body,
+                    "  %v{} = phi {} {}",
+                    phi.result.0,
+                    llvm_type(function.value_type(phi.result)),
+                    incoming.join(", ")
+                )
+                .unwrap();
+            }
+            body.push_str(&self.blocks[block.0 as usize]);
+        }
+
+        let mut params = String::from("ptr %env");
+        for n in 0..function.params.len() {
+            write!(params, ", %cw_value %a{}", n).unwrap();
+        }
+        format!(
+            "; {}\ndefine internal %cw_value @f{}({}) {{\nentry:\n{}{}}}\n",
+            function.name, index, params, self.allocas, body
+        )
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("%{}.{}", prefix, self.names)
+    }
+
+    fn fresh_label(&mut self, prefix: &str) -> String {
+        self.names += 1;
+        format!("{}.{}", prefix, self.names)
+    }
+
+    /// Emits an instruction, in a new unreachable block if the current one was terminated
+    fn emit(&mut self, instruction: impl AsRef<str>) {
+        if self.terminated {
+            let label = self.fresh_label("dead");
+            self.label(&label);
+        }
+        self.body.push_str("  ");
+        self.body.push_str(instruction.as_ref());
+        self.body.push('\n');
+    }
+
+    /// Emits an instruction that ends the current block
+    fn terminate(&mut self, instruction: impl AsRef<str>) {
+        self.emit(instruction);
+        self.terminated = true;
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.body, "{}:", label).unwrap();
+        self.current = label.to_string();
+        self.terminated = false;
+    }
+
+    fn branch(&mut self, condition: &str, then: &str, otherwise: &str) {
+        self.terminate(format!(
+            "br i1 {}, label %{}, label %{}",
+            condition, then, otherwise
+        ));
+    }
+
+    /// Emits an instruction producing a value, returning its name
+    fn assign(&mut self, prefix: &str, instruction: impl AsRef<str>) -> String {
+        let name = self.fresh(prefix);
+        self.emit(format!("{} = {}", name, instruction.as_ref()));
+        name
+    }
+
+    fn load(&mut self, pointer: &str) -> String {
+        self.assign("t", format!("load %cw_value, ptr {}", pointer))
+    }
+
+    fn store(&mut self, value: &str, pointer: &str) {
+        self.emit(format!("store %cw_value {}, ptr {}", value, pointer));
+    }
+
+    /// Stores `value` in scratch space, returning its address for a runtime call
+    fn spill(&mut self, value: &str, scratch: &str) -> String {
+        let pointer = format!("%scratch.{}", scratch);
+        self.store(value, &pointer);
+        pointer
+    }
+
+    fn tag(&mut self, value: &str) -> String {
+        self.assign("tag", format!("extractvalue %cw_value {}, 0", value))
+    }
+
+    fn word(&mut self, value: &str) -> String {
+        self.assign("word", format!("extractvalue %cw_value {}, 1, 0", value))
+    }
+
+    fn int(&mut self, value: &str) -> String {
+        let word = self.word(value);
+        self.assign("i", format!("trunc i64 {} to i32", word))
+    }
+
+    fn float(&mut self, value: &str) -> String {
+        let word = self.word(value);
+        self.assign("f", format!("bitcast i64 {} to double", word))
+    }
+
+    fn boolean(&mut self, value: &str) -> String {
+        let word = self.word(value);
+        self.assign("b", format!("trunc i64 {} to i1", word))
+    }
+
+    fn pointer(&mut self, value: &str) -> String {
+        let word = self.word(value);
+        self.assign("p", format!("inttoptr i64 {} to ptr", word))
+    }
+
+    /// Checks whether the tag of `value` is `tag`
+    fn has_tag(&mut self, value: &str, tag: u8) -> String {
+        let actual = self.tag(value);
+        self.assign("is", format!("icmp eq i8 {}, {}", actual, tag))
+    }
+
+    /// A value with `tag` and the 64-bit `word` as its payload
+    fn make(&mut self, tag: u8, word: &str) -> String {
+        self.assign(
+            "t",
+            format!(
+                "insertvalue %cw_value {{ i8 {}, [2 x i64] zeroinitializer }}, i64 {}, 1, 0",
+                tag, word
+            ),
+        )
+    }
+
+    fn make_int(&mut self, tag: u8, int: &str) -> String {
+        let word = self.assign("word", format!("zext i32 {} to i64", int));
+        self.make(tag, &word)
+    }
+
+    fn make_float(&mut self, float: &str) -> String {
+        let word = self.assign("word", format!("bitcast double {} to i64", float));
+        self.make(FLOAT, &word)
+    }
+
+    fn make_bool(&mut self, boolean: &str) -> String {
+        let word = self.assign("word", format!("zext i1 {} to i64", boolean));
+        self.make(BOOL, &word)
+    }
+
+    fn make_pointer(&mut self, tag: u8, pointer: &str) -> String {
+        let word = self.assign("word", format!("ptrtoint ptr {} to i64", pointer));
+        self.make(tag, &word)
+    }
+
+    fn site(&mut self, span: Span) -> String {
+        self.module.site(self.function.file, span)
+    }
+
+    /// Panics with `message` at `span` when `failed` is true
+    fn check(&mut self, failed: &str, span: Span, message: &'static str) {
+        let panic = self.fresh_label("panic");
+        let ok = self.fresh_label("ok");
+        self.branch(failed, &panic, &ok);
+        self.label(&panic);
+        let site = self.site(span);
+        let message = self.module.message(message);
+        self.emit(format!(
+            "call void @cw_panic(ptr {}, ptr {})",
+            site, message
+        ));
+        self.terminate("unreachable");
+        self.label(&ok);
+    }
+
+    /// The LLVM operand of an IR value
+    fn operand(&self, value: Value) -> String {
+        self.operands[value.0 as usize].clone()
+    }
+
+    fn instruction(&mut self, instruction: &'p ir::Instruction) {
+        let span = instruction.span;
+        let operand = |value: &Value| self.operand(*value);
+        let value = match &instruction.kind {
+            InstructionKind::Const(literal) => match literal {
+                Constant::Int(value) => constant(INT, *value as u32),
+                Constant::Float(value) => constant(FLOAT, value.to_bits() as i64),
+                Constant::Bool(value) => constant(BOOL, *value as u8),
+                Constant::Char(value) => constant(CHAR, *value as u32),
+                Constant::String(value) => {
+                    let string = self.module.string(value);
+                    constant(STRING, format!("ptrtoint (ptr {} to i64)", string))
+                }
+                Constant::Unit => constant(UNIT, 0),
+                Constant::Null => constant(NULL, 0),
+            },
+            InstructionKind::Function(index) => constant(
+                CLOSURE,
+                format!("ptrtoint (ptr @cw_function{} to i64)", index),
+            ),
+            InstructionKind::Closure { function, captures } => {
+                let closure = self.assign(
+                    "closure",
+                    format!(
+                        "call ptr @cw_new_closure(ptr @f{}, i32 {})",
+                        function,
+                        captures.len()
+                    ),
+                );
+                for (n, &capture) in captures.iter().enumerate() {
+                    // Variables captured by reference are passed as their heap cell
+                    let value = self.operand(capture);
+                    let value = match self.function.value_type(capture) {
+                        ValueType::Cell(_) => self.make_pointer(CELL, &value),
+                        ValueType::Value(_) => value,
+                    };
+                    let capture = self.assign(
+                        "capture",
+                        format!(
+                            "getelementptr %cw_closure, ptr {}, i64 0, i32 3, i64 {}",
+                            closure, n
+                        ),
+                    );
+                    self.store(&value, &capture);
+                }
+                self.make_pointer(CLOSURE, &closure)
+            }
+            InstructionKind::Capture(slot) => {
+                let capture = self.assign(
+                    "capture",
+                    format!(
+                        "getelementptr %cw_closure, ptr %env, i64 0, i32 3, i64 {}",
+                        slot
+                    ),
+                );
+                let value = self.load(&capture);
+                match self.function.captures[*slot as usize] {
+                    ValueType::Cell(_) => self.pointer(&value),
+                    ValueType::Value(_) => value,
+                }
+            }
+            InstructionKind::NewCell(value) => {
+                let value = self.spill(&operand(value), "a");
+                self.assign("cell", format!("call ptr @cw_box(ptr {})", value))
+            }
+            InstructionKind::LoadCell(cell) => self.load(&operand(cell)),
+            InstructionKind::StoreCell { cell, value } => {
+                let (cell, value) = (operand(cell), operand(value));
+                return self.store(&value, &cell);
+            }
+            InstructionKind::Unary { op, operand: value } => {
+                let ty = self.function.type_of(*value);
+                let value = operand(value);
+                match (op, ty) {
+                    (UnaryOp::Negate, Type::Float) => {
+                        let float = self.float(&value);
+                        let negated = self.assign("f", format!("fneg double {}", float));
+                        self.make_float(&negated)
+                    }
+                    (UnaryOp::Negate, _) => {
+                        let int = self.int(&value);
+                        let overflow =
+                            self.assign("overflow", format!("icmp eq i32 {}, -2147483648", int));
+                        self.check(&overflow, span, "attempt to negate with overflow");
+                        let negated = self.assign("i", format!("sub i32 0, {}", int));
+                        self.make_int(INT, &negated)
+                    }
+                    (UnaryOp::Not, _) => {
+                        let boolean = self.boolean(&value);
+                        let negated = self.assign("b", format!("xor i1 {}, true", boolean));
+                        self.make_bool(&negated)
+                    }
+                    (UnaryOp::BitNot, _) => {
+                        let int = self.int(&value);
+                        let inverted = self.assign("i", format!("xor i32 {}, -1", int));
+                        self.make_int(INT, &inverted)
+                    }
+                }
+            }
+            InstructionKind::Binary { op, left, right } => {
+                let (left_type, right_type) =
+                    (self.function.type_of(*left), self.function.type_of(*right));
+                let (a, b) = (operand(left), operand(right));
+                self.binary(*op, left_type, right_type, &a, &b, span)
+            }
+            InstructionKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                // The bounds share the first payload word, and inclusiveness takes the second
+                let (start, end) = (operand(start), operand(end));
+                let start = self.int(&start);
+                let end = self.int(&end);
+                let low = self.assign("word", format!("zext i32 {} to i64", start));
+                let high = self.assign("word", format!("zext i32 {} to i64", end));
+                let high = self.assign("word", format!("shl i64 {}, 32", high));
+                let word = self.assign("word", format!("or i64 {}, {}", low, high));
+                let range = self.make(RANGE, &word);
+                self.assign(
+                    "t",
+                    format!(
+                        "insertvalue %cw_value {}, i64 {}, 1, 1",
+                        range, *inclusive as u8
+                    ),
+                )
+            }
+            InstructionKind::RangeStart(range) => {
+                let start = self.int(&operand(range));
+                self.make_int(INT, &start)
+            }
+            InstructionKind::RangeContains { range, item } => {
+                let (range, item) = (operand(range), operand(item));
+                let word = self.word(&range);
+                let end = self.assign("end", format!("ashr i64 {}, 32", word));
+                let end = self.assign("end", format!("trunc i64 {} to i32", end));
+                let inclusive = self.assign(
+                    "inclusive",
+                    format!("extractvalue %cw_value {}, 1, 1", range),
+                );
+                let inclusive = self.assign("inclusive", format!("trunc i64 {} to i1", inclusive));
+                let item = self.int(&item);
+                let before = self.assign("before", format!("icmp slt i32 {}, {}", item, end));
+                let last = self.assign("last", format!("icmp eq i32 {}, {}", item, end));
+                let last = self.assign("last", format!("and i1 {}, {}", last, inclusive));
+                let contains = self.assign("contains", format!("or i1 {}, {}", before, last));
+                self.make_bool(&contains)
+            }
+            InstructionKind::IsNull(value) => {
+                let null = self.has_tag(&operand(value), NULL);
+                self.make_bool(&null)
+            }
+            InstructionKind::Payload(value) => {
+                let pointer = self.pointer(&operand(value));
+                self.load(&pointer)
+            }
+            InstructionKind::Call { function, args } => {
+                let mut call = format!("call %cw_value @f{}(ptr null", function);
+                for arg in args {
+                    write!(call, ", %cw_value {}", operand(arg)).unwrap();
+                }
+                call.push(')');
+                self.counted_call(&call, span)
+            }
+            InstructionKind::CallValue { callee, args } => {
+                let closure = self.pointer(&operand(callee));
+                let field = self.assign(
+                    "fn",
+                    format!("getelementptr %cw_closure, ptr {}, i64 0, i32 1", closure),
+                );
+                let function = self.assign("fn", format!("load ptr, ptr {}", field));
+                let mut call = format!("call %cw_value {}(ptr {}", function, closure);
+                for arg in args {
+                    write!(call, ", %cw_value {}", self.operand(*arg)).unwrap();
+                }
+                call.push(')');
+                self.counted_call(&call, span)
+            }
+            InstructionKind::CallBuiltin { builtin, args } if builtin.is_math() => {
+                let float = *self.function.type_of(args[0]) == Type::Float;
+                let args: Vec<String> = args.iter().map(operand).collect();
+                self.math(*builtin, float, &args, span)
+            }
+            InstructionKind::CallBuiltin { builtin, args } => {
+                let args: Vec<String> = args.iter().map(operand).collect();
+                self.builtin(*builtin, &args, span)
+            }
+        };
+        let result = instruction.result.expect("only stores have no result");
+        self.operands[result.0 as usize] = value;
+        self.root(result);
+    }
+
+    /// Stores a value that may point into the heap in its root slot, where the collector finds it
+    fn root(&mut self, value: Value) {
+        let ty = self.function.value_type(value);
+        if ty.is_traced() {
+            let operand = self.operand(value);
+            let store = format!("store {} {}, ptr %root.{}", llvm_type(ty), operand, value.0);
+            self.emit(store);
+        }
+    }
+
+    /// Allocates a root slot for every value that may point into the heap, and pushes a frame
+    /// listing them, returning whether there was any
+    fn push_frame(&mut self) -> bool {
+        let function = self.function;
+        let (mut values, mut cells) = (Vec::new(), Vec::new());
+        for (n, ty) in function.values.iter().enumerate() {
+            if !ty.is_traced() {
+                continue;
+            }
+            // Roots start out holding nothing, since the collector reads them all
+            let initial = match function
+                .params
+                .iter()
+                .position(|param| param.0 as usize == n)
+            {
+                Some(param) => format!("%a{}", param),
+                None if matches!(ty, ValueType::Cell(_)) => "null".to_string(),
+                None => constant(UNIT, 0),
+            };
+            let ty = llvm_type(ty);
+            writeln!(self.allocas, "  %root.{} = alloca {}", n, ty).unwrap();
+            writeln!(self.allocas, "  store {} {}, ptr %root.{}", ty, initial, n).unwrap();
+            match ty {
+                "ptr" => cells.push(n),
+                _ => values.push(n),
+            }
+        }
+        if values.is_empty() && cells.is_empty() {
+            return false;
+        }
+
+        let mut array = |name: &str, slots: &[usize]| {
+            if slots.is_empty() {
+                return "null".to_string();
+            }
+            let array = format!("[{} x ptr]", slots.len());
+            writeln!(self.allocas, "  %{} = alloca {}", name, array).unwrap();
+            for (i, n) in slots.iter().enumerate() {
+                writeln!(
+                    self.allocas,
+                    "  %{name}.{i} = getelementptr {array}, ptr %{name}, i64 0, i64 {i}\n  store ptr %root.{n}, ptr %{name}.{i}",
+                )
+                .unwrap();
+            }
+            format!("%{}", name)
+        };
+        let fields = [
+            "ptr %frame.parent".to_string(),
+            format!("i32 {}", values.len()),
+            format!("i32 {}", cells.len()),
+            format!("ptr {}", array("roots", &values)),
+            format!("ptr {}", array("cells", &cells)),
+        ];
+        self.allocas.push_str("  %frame = alloca %cw_frame\n");
+        self.allocas
+            .push_str("  %frame.parent = load ptr, ptr @cw_frames\n");
+        for (i, field) in fields.iter().enumerate() {
+            writeln!(
+                self.allocas,
+                "  %frame.{i} = getelementptr %cw_frame, ptr %frame, i32 0, i32 {i}\n  store {field}, ptr %frame.{i}",
+            )
+            .unwrap();
+        }
+        self.allocas
+            .push_str("  store ptr %frame, ptr @cw_frames\n");
+        true
+    }
+
+    fn terminator(&mut self, block: Block) {
+        if self.terminated {
+            return;
+        }
+        match self.function.block(block).terminator {
+            Terminator::Jump(target) => self.terminate(format!("br label %{}", target)),
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let condition = self.boolean(&self.operand(condition));
+                self.branch(&condition, &then.to_string(), &otherwise.to_string());
+            }
+            Terminator::Return(value) => {
+                if self.rooted {
+                    self.emit("store ptr %frame.parent, ptr @cw_frames");
+                }
+                let value = self.operand(value);
+                self.terminate(format!("ret %cw_value {}", value));
+            }
+            Terminator::Unreachable => self.terminate("unreachable"),
+        }
+    }
+
+    /// Calls a compiled function, counting the call towards the maximum call depth
+    fn counted_call(&mut self, call: &str, span: Span) -> String {
+        let site = self.site(span);
+        self.emit(format!("call void @cw_enter(ptr {})", site));
+        let result = self.assign("t", call);
+        let depth = self.assign("depth", "load i32, ptr @cw_depth");
+        let depth = self.assign("depth", format!("sub i32 {}, 1", depth));
+        self.emit(format!("store i32 {}, ptr @cw_depth", depth));
+        result
+    }
+
+    /// Calls a math built-in on floats if `float` is true, and otherwise on integers
+    fn math(&mut self, builtin: Builtin, float: bool, args: &[String], span: Span) -> String {
+        let min = builtin == Builtin::Min;
+        if float {
+            let args: Vec<String> = args.iter().map(|arg| self.float(arg)).collect();
+            let result = match (builtin, &args[..]) {
+                // The second argument when it is less, or greater, or when the first is NaN
+                (Builtin::Min | Builtin::Max, [a, b]) => {
+                    let predicate = if min { "olt" } else { "ogt" };
+                    let second =
+                        self.assign("cmp", format!("fcmp {} double {}, {}", predicate, b, a));
+                    let nan = self.assign("nan", format!("fcmp uno double {}, {}", a, a));
+                    let second = self.assign("cmp", format!("or i1 {}, {}", second, nan));
+                    self.assign(
+                        "f",
+                        format!("select i1 {}, double {}, double {}", second, b, a),
+                    )
+                }
+                _ => {
+                    let args: Vec<String> =
+                        args.iter().map(|arg| format!("double {}", arg)).collect();
+                    self.assign(
+                        "f",
+                        format!(
+                            "call double @{}({})",
+                            c::libm_function(builtin),
+                            args.join(", ")
+                        ),
+                    )
+                }
+            };
+            return self.make_float(&result);
+        }
+        let args: Vec<String> = args.iter().map(|arg| self.int(arg)).collect();
+        let result = match (builtin, &args[..]) {
+            (Builtin::Min | Builtin::Max, [a, b]) => {
+                let predicate = if min { "slt" } else { "sgt" };
+                let second = self.assign("cmp", format!("icmp {} i32 {}, {}", predicate, b, a));
+                self.assign("i", format!("select i1 {}, i32 {}, i32 {}", second, b, a))
+            }
+            (Builtin::Abs, [a]) => {
+                let overflow = self.assign("overflow", format!("icmp eq i32 {}, -2147483648", a));
+                self.check(&overflow, span, "attempt to negate with overflow");
+                let negative = self.assign("cmp", format!("icmp slt i32 {}, 0", a));
+                let negated = self.assign("i", format!("sub i32 0, {}", a));
+                self.assign(
+                    "i",
+                    format!("select i1 {}, i32 {}, i32 {}", negative, negated, a),
+                )
+            }
+            (Builtin::Pow, [a, b]) => {
+                let site = self.site(span);
+                self.assign(
+                    "i",
+                    format!("call i32 @cw_pow_int(ptr {}, i32 {}, i32 {})", site, a, b),
+                )
+            }
+            _ => unreachable!("the type checker validates calls to math built-ins"),
+        };
+        self.make_int(INT, &result)
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[String], span: Span) -> String {
+        match (builtin, args) {
+            (Builtin::Println, []) => {
+                self.emit("call void @cw_print_line(ptr null)");
+                constant(UNIT, 0)
+            }
+            (Builtin::Println, [value]) => {
+                let value = self.spill(value, "a");
+                self.emit(format!("call void @cw_print_line(ptr {})", value));
+                constant(UNIT, 0)
+            }
+            (Builtin::Print, [value]) => {
+                let value = self.spill(value, "a");
+                self.emit(format!("call void @cw_print_at(ptr {})", value));
+                constant(UNIT, 0)
+            }
+            (Builtin::PrintPadded, [value, width, precision, alignment]) => {
+                let numbers = [width, precision, alignment].map(|number| self.int(number));
+                let value = self.spill(value, "a");
+                self.emit(format!(
+                    "call void @cw_print_padded_at(ptr {}, i32 {}, i32 {}, i32 {})",
+                    value, numbers[0], numbers[1], numbers[2]
+                ));
+                constant(UNIT, 0)
+            }
+            (Builtin::Input, []) => {
+                self.emit("call void @cw_input_at(ptr %scratch.a)");
+                self.load("%scratch.a")
+            }
+            (Builtin::ToString, [value]) => {
+                let value = self.spill(value, "a");
+                self.emit(format!(
+                    "call void @cw_to_string_at(ptr %scratch.a, ptr {})",
+                    value
+                ));
+                self.load("%scratch.a")
+            }
+            (Builtin::Concat, [a, b]) => {
+                let a = self.spill(a, "a");
+                let b = self.spill(b, "b");
+                self.emit(format!(
+                    "call void @cw_concat_at(ptr %scratch.a, ptr {}, ptr {})",
+                    a, b
+                ));
+                self.load("%scratch.a")
+            }
+            (Builtin::Int | Builtin::Char, [value]) => {
+                let value = self.spill(value, "a");
+                let site = self.site(span);
+                self.emit(format!(
+                    "call void @cw_to_{}_at(ptr %scratch.a, ptr {}, ptr {})",
+                    builtin.name(),
+                    site,
+                    value
+                ));
+                self.load("%scratch.a")
+            }
+            (Builtin::Float, [value]) => {
+                let value = self.spill(value, "a");
+                self.emit(format!(
+                    "call void @cw_to_float_at(ptr %scratch.a, ptr {})",
+                    value
+                ));
+                self.load("%scratch.a")
+            }
+            (Builtin::Panic, [message]) => {
+                let message = self.spill(message, "a");
+                let site = self.site(span);
+                self.emit(format!(
+                    "call void @cw_panic_value(ptr {}, ptr {})",
+                    site, message
+                ));
+                self.terminate("unreachable");
+                constant(UNIT, 0)
+            }
+            (Builtin::Ok, []) => self.wrap(OK, &constant(UNIT, 0)),
+            (Builtin::Ok, [value]) => self.wrap(OK, value),
+            (Builtin::Err, [error]) => self.wrap(ERR, error),
+            (Builtin::Unwrap, [result]) => self.unwrap(OK, result, span),
+            (Builtin::UnwrapErr, [result]) => self.unwrap(ERR, result, span),
+            (Builtin::IsOk, [result]) => {
+                let ok = self.has_tag(result, OK);
+                self.make_bool(&ok)
+            }
+            (Builtin::IsErr, [result]) => {
+                let err = self.has_tag(result, ERR);
+                self.make_bool(&err)
+            }
+            (Builtin::Close, [file]) => {
+                let file = self.spill(file, "a");
+                self.emit(format!("call void @cw_close_at(ptr {})", file));
+                constant(UNIT, 0)
+            }
+            (Builtin::Read | Builtin::ReadLine | Builtin::NextLine, [file]) => {
+                let file = self.spill(file, "a");
+                self.emit(format!(
+                    "call void @cw_{}_at(ptr %scratch.a, ptr {})",
+                    builtin.name(),
+                    file
+                ));
+                self.load("%scratch.a")
+            }
+            (Builtin::CharAt, [string, offset]) => {
+                let offset = self.int(offset);
+                let string = self.spill(string, "a");
+                self.emit(format!(
+                    "call void @cw_char_at_at(ptr %scratch.a, ptr {}, i32 {})",
+                    string, offset
+                ));
+                self.load("%scratch.a")
+            }
+            (Builtin::CharWidth, [string, offset]) => {
+                let offset = self.int(offset);
+                let string = self.spill(string, "a");
+                let width = self.assign(
+                    "i",
+                    format!("call i32 @cw_char_width_at(ptr {}, i32 {})", string, offset),
+                );
+                self.make_int(INT, &width)
+            }
+            (Builtin::Open | Builtin::Write, [a, b]) => {
+                let a = self.spill(a, "a");
+                let b = self.spill(b, "b");
+                let function = match builtin {
+                    Builtin::Open => "cw_open_at",
+                    _ => "cw_write_file_at",
+                };
+                self.emit(format!(
+                    "call void @{}(ptr %scratch.a, ptr {}, ptr {})",
+                    function, a, b
+                ));
+                self.load("%scratch.a")
+            }
+            _ => unreachable!("the verifier checks the arguments of built-in functions"),
+        }
+    }
+
+    /// An `Ok` or an `Err` (`tag`) holding `value`
+    fn wrap(&mut self, tag: u8, value: &str) -> String {
+        let value = self.spill(value, "a");
+        let cell = self.assign("cell", format!("call ptr @cw_box(ptr {})", value));
+        self.make_pointer(tag, &cell)
+    }
+
+    /// The value inside `result`, which panics unless its tag is `expected`
+    fn unwrap(&mut self, expected: u8, result: &str, span: Span) -> String {
+        let matches = self.has_tag(result, expected);
+        let ok = self.fresh_label("ok");
+        let failed = self.fresh_label("failed");
+        self.branch(&matches, &ok, &failed);
+        self.label(&failed);
+        let scratch = self.spill(result, "a");
+        let site = self.site(span);
+        self.emit(format!(
+            "call void @cw_unwrap_failed(ptr {}, ptr {})",
+            site, scratch
+        ));
+        self.terminate("unreachable");
+        self.label(&ok);
+        let pointer = self.pointer(result);
+        self.load(&pointer)
+    }
+
+    /// Applies `op` to two operands, the left one of type `left`
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        left: &Type,
+        right: &Type,
+        a: &str,
+        b: &str,
+        span: Span,
+    ) -> String {
+        if let Type::Float = left {
+            let a = self.float(a);
+            let b = self.float(b);
+            let instruction = match op {
+                BinaryOp::Add => "fadd",
+                BinaryOp::Subtract => "fsub",
+                BinaryOp::Multiply => "fmul",
+                BinaryOp::Divide => "fdiv",
+                BinaryOp::Remainder => "frem",
+                _ => {
+                    let predicate = match op {
+                        BinaryOp::Equal => "oeq",
+                        BinaryOp::NotEqual => "une",
+                        BinaryOp::Less => "olt",
+                        BinaryOp::LessEqual => "ole",
+                        BinaryOp::Greater => "ogt",
+                        _ => "oge",
+                    };
+                    let result =
+                        self.assign("cmp", format!("fcmp {} double {}, {}", predicate, a, b));
+                    return self.make_bool(&result);
+                }
+            };
+            let result = self.assign("f", format!("{} double {}, {}", instruction, a, b));
+            return self.make_float(&result);
+        }
+
+        match op {
+            BinaryOp::Equal | BinaryOp::NotEqual => {
+                // Values of the same simple type are equal when their payloads are. The runtime
+                // only writes the part of the word a payload takes, so the rest is not compared.
+                let equal = match (left, right) {
+                    (Type::Int | Type::Char, _) if left == right => {
+                        let a = self.int(a);
+                        let b = self.int(b);
+                        self.assign("cmp", format!("icmp eq i32 {}, {}", a, b))
+                    }
+                    (Type::Bool, Type::Bool) => {
+                        let a = self.boolean(a);
+                        let b = self.boolean(b);
+                        self.assign("cmp", format!("icmp eq i1 {}, {}", a, b))
+                    }
+                    _ => {
+                        let a = self.spill(a, "a");
+                        let b = self.spill(b, "b");
+                        self.assign(
+                            "cmp",
+                            format!("call zeroext i1 @cw_equal_at(ptr {}, ptr {})", a, b),
+                        )
+                    }
+                };
+                let result = match op {
+                    BinaryOp::Equal => equal,
+                    _ => self.assign("cmp", format!("xor i1 {}, true", equal)),
+                };
+                return self.make_bool(&result);
+            }
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                let signed = if let Type::Char = left { "u" } else { "s" };
+                let predicate = match op {
+                    BinaryOp::Less => "lt",
+                    BinaryOp::LessEqual => "le",
+                    BinaryOp::Greater => "gt",
+                    _ => "ge",
+                };
+                let a = self.int(a);
+                let b = self.int(b);
+                let result = self.assign(
+                    "cmp",
+                    format!("icmp {}{} i32 {}, {}", signed, predicate, a, b),
+                );
+                return self.make_bool(&result);
+            }
+            _ => (),
+        }
+
+        let a = self.int(a);
+        let b = self.int(b);
+        let result = match op {
+            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply => {
+                let (intrinsic, message) = match op {
+                    BinaryOp::Add => ("sadd", "attempt to add with overflow"),
+                    BinaryOp::Subtract => ("ssub", "attempt to subtract with overflow"),
+                    _ => ("smul", "attempt to multiply with overflow"),
+                };
+                let pair = self.assign(
+                    "pair",
+                    format!(
+                        "call {{ i32, i1 }} @llvm.{}.with.overflow.i32(i32 {}, i32 {})",
+                        intrinsic, a, b
+                    ),
+                );
+                let overflow = self.assign(
+                    "overflow",
+                    format!("extractvalue {{ i32, i1 }} {}, 1", pair),
+                );
+                self.check(&overflow, span, message);
+                self.assign("i", format!("extractvalue {{ i32, i1 }} {}, 0", pair))
+            }
+            BinaryOp::Divide | BinaryOp::Remainder => {
+                let zero = self.assign("zero", format!("icmp eq i32 {}, 0", b));
+                self.check(&zero, span, "attempt to divide by zero");
+                let minimum = self.assign("min", format!("icmp eq i32 {}, -2147483648", a));
+                let minus_one = self.assign("minus", format!("icmp eq i32 {}, -1", b));
+                let overflow =
+                    self.assign("overflow", format!("and i1 {}, {}", minimum, minus_one));
+                let (instruction, message) = match op {
+                    BinaryOp::Divide => ("sdiv", "attempt to divide with overflow"),
+                    _ => ("srem", "attempt to calculate the remainder with overflow"),
+                };
+                self.check(&overflow, span, message);
+                self.assign("i", format!("{} i32 {}, {}", instruction, a, b))
+            }
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight => {
+                // Negative amounts are large unsigned numbers, so one comparison covers both
+                let overflow = self.assign("overflow", format!("icmp uge i32 {}, 32", b));
+                let (instruction, message) = match op {
+                    BinaryOp::ShiftLeft => ("shl", "attempt to shift left with overflow"),
+                    _ => ("ashr", "attempt to shift right with overflow"),
+                };
+                self.check(&overflow, span, message);
+                self.assign("i", format!("{} i32 {}, {}", instruction, a, b))
+            }
+            BinaryOp::BitAnd => self.assign("i", format!("and i32 {}, {}", a, b)),
+            BinaryOp::BitOr => self.assign("i", format!("or i32 {}, {}", a, b)),
+            BinaryOp::BitXor => self.assign("i", format!("xor i32 {}, {}", a, b)),
+            _ => unreachable!(
+                "comparisons are handled above, and short-circuiting operators are branches"
+            ),
+        };
+        self.make_int(INT, &result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end;
+    use crate::testing::{
+        assert_outputs_match_interpreter, executable_path, run_with_input, source_files, FILES,
+        PROGRAMS,
+    };
+
+    fn generate_source(source: &str) -> String {
+        let analysis = front_end::analyze(source).unwrap();
+        generate(&ir::build(&analysis.program), &source_files(source))
+    }
+
+    /// Checks that the executable built from `source` prints and panics like the interpreter
+    fn assert_matches_interpreter(source: &str, name: &str) {
+        let executable = executable_path(&format!("llvm-{}", name));
+        compile(&generate_source(source), &executable, "cc").unwrap();
+        // Collecting on every allocation catches the roots that generated code fails to declare
+        let outputs: Vec<_> = ["0", "1"]
+            .iter()
+            .map(|stress| {
+                run_with_input(Command::new(&executable).env("CRAWFISH_GC_STRESS", stress))
+            })
+            .collect();
+        fs::remove_file(&executable).unwrap();
+        assert_outputs_match_interpreter(source, &outputs);
+    }
+
+    #[test]
+    fn test_llvm_version() {
+        assert_eq!(
+            llvm_major_version("Debian LLVM version 14.0.6\n  Optimized build."),
+            14
+        );
+        assert_eq!(
+            llvm_major_version("LLVM (http://llvm.org/):\n  LLVM version 18.1.3"),
+            18
+        );
+        assert_eq!(llvm_major_version("unknown"), u32::MAX);
+    }
+
+    #[test]
+    fn test_generated_module_structure() {
+        let ir = generate_source(
+            r#"func add(a: Int, b: Int) -> Int { return a + b; } func main() { println(add(1, 2)); println("hé"); }"#,
+        );
+        assert!(
+            ir.contains("define internal %cw_value @f0(ptr %env, %cw_value %a0, %cw_value %a1) {")
+        );
+        assert!(ir.contains("@llvm.sadd.with.overflow.i32("));
+        assert!(ir.contains(
+            "@site.0 = private unnamed_addr constant %cw_site { ptr @path.0, i32 1, i32 42 }"
+        ));
+        assert!(ir.contains("@bytes.0 = private unnamed_addr constant [3 x i8] c\"h\\C3\\A9\""));
+        assert!(ir.contains("%result = call %cw_value @f1(ptr null)"));
+        // Scratch space is allocated in the entry block, which the body of `main` continues
+        let main = &ir[ir.find("; main\n").unwrap()..];
+        assert!(main.contains("entry:\n  %scratch.a = alloca %cw_value\n"));
+    }
+
+    #[test]
+    fn test_programs_match_interpreter() {
+        let tools = ["llc", "cc"];
+        if tools
+            .iter()
+            .any(|tool| Command::new(tool).arg("--version").output().is_err())
+        {
+            eprintln!("skipping: no LLVM or C toolchain");
+            return;
+        }
+        let path = std::env::temp_dir().join(format!("crawfish-llvm-{}", std::process::id()));
+        let files = FILES.replace("PATH", path.to_str().unwrap());
+        for (n, program) in PROGRAMS.iter().copied().chain([files.as_str()]).enumerate() {
+            assert_matches_interpreter(program, &n.to_string());
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
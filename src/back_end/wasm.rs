@@ -0,0 +1,1264 @@
+//! WebAssembly backend, which lowers the IR of a program to a `wasm32` module.
+//!
+//! Values are NaN-boxed into `i64`s like in the x86-64 backend: floats are stored as their bits,
+//! and other values in the payload of a NaN, whose top 16 bits are a tag. Strings, ranges, results,
+//! closures and heap cells are objects in linear memory, whose first 32-bit word is their kind.
+//! String literals and source locations live in a data segment, and objects made at run time in a
+//! collected heap after it. Each function keeps the values that can hold objects in a frame on a
+//! shadow stack in linear memory, which the collector takes its roots from. Every compiled function
+//! is in the table, and takes the closure it is called through, or 0, followed by its arguments, so
+//! that function values are called with `call_indirect`.
+//!
+//! Modules import their I/O from the host, and export their memory, a `main` function that runs the
+//! program, and a `stress` function that makes every allocation collect when the host calls it
+//! before `main`, as it should when `CRAWFISH_GC_STRESS` is set. The host provides, in a `crawfish`
+//! module:
+//! - `write(stream, pointer, length)`, which writes bytes to stdout (1) or stderr (2). Output to
+//!   stdout is buffered, and flushed when the program ends, panics or reads input.
+//! - `format_float(value, precision, pointer) -> length`, which writes the text of a float at
+//!   `pointer`, at most 1 KiB, as the other backends print it: with `precision` digits after the
+//!   decimal point, or when it is negative, whole numbers with `.0` and others in their shortest
+//!   round-trip form, without exponent. NaN and infinities are `NaN`, `inf` and `-inf`.
+//! - `read_line(pointer, capacity) -> length`, which returns the length of the next line of stdin
+//!   without its line ending, or -1 at the end of stdin. The line is copied to `pointer` if it has
+//!   at most `capacity` bytes, and otherwise kept for the next call.
+//! - `exit(code)`, which stops the program.
+//! - `parse_float(pointer, length) -> value`, which reads the float written in decimal at `pointer`,
+//!   as an optional sign, digits, and an optional fraction and exponent, rounding to the nearest.
+//! - `sin(x)`, `cos(x)`, `log10(x)`, `log(x)`, `exp(x)` and `pow(x, y)`, the functions of the C
+//!   math library, which JavaScript's `Math` has under the same names.
+//! - `open(path, length, mode) -> handle`, which opens the file at the path of `length` bytes at
+//!   `path` for reading (`mode` is `'r'`), writing over it (`'w'`) or appending to it (`'a'`),
+//!   creating it to write. Writes are not buffered.
+//! - `read_file(handle, line, pointer, capacity) -> length`, which reads the next line of a file
+//!   without its line ending if `line` is 1, and the rest of it if `line` is 0, like `read_line`
+//!   does stdin. At the end of the file, a line is -1, and the rest is empty.
+//! - `write_file(handle, pointer, length) -> status`, which writes bytes to a file and returns 0.
+//! - `close(handle)`, which closes a file.
+//!
+//! The file functions fail by returning a negative code: -2 when there is no such file or
+//! directory, -3 when permission is denied, -4 when the path is a directory, and -5 for any other
+//! input/output error.
+use crate::back_end::ir::cfg::{self, Dominators};
+use crate::back_end::ir::{
+    self, Block, Builtin, Constant, Function, InstructionKind, Program, Terminator, Value,
+};
+use crate::front_end::ast::{BinaryOp, UnaryOp};
+use crate::front_end::modules::SourceFile;
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use crate::runtime::vm::MAX_CALL_DEPTH;
+use module::{Access, BlockType, Data, Export, ExportKind, Global, Instr, Module, Op, ValType};
+use runtime::Runtime;
+use std::collections::HashMap;
+use std::fmt;
+
+pub mod module;
+pub mod runtime;
+pub mod validator;
+
+/// Tags of NaN-boxed values, in their top 16 bits; anything below `INT` is a float
+pub const INT: i64 = 0xFFF9 << 48;
+pub const BOOL: i64 = 0xFFFA << 48;
+pub const CHAR: i64 = 0xFFFB << 48;
+pub const UNIT: i64 = 0xFFFC << 48;
+pub const NULL: i64 = UNIT | 1;
+pub const OBJECT: i64 = 0xFFFD << 48;
+
+/// Kinds of objects
+/// - a string holds its length in its second word, then its bytes
+/// - a range holds whether it is inclusive, then its start and end
+/// - `Ok`, `Err` and cells hold a value at offset 8
+/// - a closure holds the table index of its function, then its captured values
+/// - a file holds the host's handle of it, then whether it is closed (0), or open for reading (1)
+///   or writing (2)
+pub const STRING: i32 = 1;
+pub const RANGE: i32 = 2;
+pub const OK: i32 = 3;
+pub const ERR: i32 = 4;
+pub const CLOSURE: i32 = 5;
+pub const CELL: i32 = 6;
+pub const FILE: i32 = 7;
+
+/// Layout of memory: the head of the list of free blocks of the heap, scratch space for formatting
+/// numbers, the text of the float the host formats, the buffer of stdout, then the data segment,
+/// the shadow stack of roots, and the heap
+pub const FREE_LIST: u32 = 8;
+pub const SCRATCH: u32 = 16;
+pub const SCRATCH_END: u32 = 48;
+pub const FLOAT_TEXT: u32 = 64;
+pub const OUTPUT: u32 = FLOAT_TEXT + 1024;
+pub const OUTPUT_SIZE: u32 = 4096;
+pub const DATA_START: u32 = OUTPUT + OUTPUT_SIZE;
+
+/// Globals: the end of the heap, the call depth, the length of the buffered output, the number of
+/// characters or bytes counted while `print_padded` or `to_string` measures a value, which is -1
+/// the rest of the time, and where `to_string` writes the text of a value. Then those of the
+/// collector: the top and the bottom of the shadow stack, the start of the heap, the bytes
+/// allocated in it, how many it may hold before collecting, whether every allocation collects,
+/// and the top of the stack of objects marked but not traced yet, which lives past the heap.
+pub const HEAP: u32 = 0;
+pub const DEPTH: u32 = 1;
+pub const OUTPUT_LENGTH: u32 = 2;
+pub const COUNT: u32 = 3;
+pub const CAPTURE: u32 = 4;
+pub const ROOTS: u32 = 5;
+pub const ROOTS_START: u32 = 6;
+pub const HEAP_START: u32 = 7;
+pub const ALLOCATED: u32 = 8;
+pub const THRESHOLD: u32 = 9;
+pub const STRESS: u32 = 10;
+pub const GRAY: u32 = 11;
+
+/// The heap collects when allocating past twice what was live after the last collection, but not
+/// before holding this many bytes
+pub const MIN_HEAP: u32 = 1 << 20;
+
+/// Slots of the shadow stack where the runtime roots the objects it allocates with
+const RUNTIME_ROOTS: u32 = 2;
+
+const PAGE_SIZE: u32 = 65536;
+
+/// Why a WebAssembly module cannot be loaded
+#[derive(Debug, PartialEq)]
+pub enum WasmError {
+    NotWasm,
+    UnsupportedVersion(u32),
+    Truncated,
+    TrailingBytes,
+    InvalidSection(u8),
+    InvalidOpcode(u8),
+    InvalidType(u8),
+    InvalidInteger,
+    InvalidString,
+    /// A well-formed module that breaks a validation rule
+    Invalid {
+        context: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WasmError::NotWasm => write!(f, "Not a WebAssembly module"),
+            WasmError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported WebAssembly version {}", version)
+            }
+            WasmError::Truncated => write!(f, "WebAssembly module is truncated"),
+            WasmError::TrailingBytes => write!(f, "WebAssembly module has trailing bytes"),
+            WasmError::InvalidSection(id) => write!(f, "Invalid or misplaced section {}", id),
+            WasmError::InvalidOpcode(opcode) => write!(f, "Invalid opcode {:#04x}", opcode),
+            WasmError::InvalidType(code) => write!(f, "Invalid value type {:#04x}", code),
+            WasmError::InvalidInteger => write!(f, "Invalid LEB128 integer"),
+            WasmError::InvalidString => write!(f, "Invalid UTF-8 name"),
+            WasmError::Invalid { context, reason } => {
+                write!(f, "Invalid module: {}: {}", context, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WasmError {}
+
+/// Generates the module of a program compiled from `files`
+pub fn generate(program: &Program, files: &[SourceFile]) -> Module {
+    let mut wasm = Module::default();
+    let mut statics = Statics::default();
+    let runtime = Runtime::add(&mut wasm, &mut statics);
+    let base = (wasm.imports.len() + wasm.functions.len()) as u32;
+    let mut generator = Generator {
+        files,
+        wasm,
+        statics,
+        runtime,
+        base,
+        functions: HashMap::new(),
+        roots: 0,
+    };
+    for (index, function) in program.functions.iter().enumerate() {
+        let compiled = FunctionGenerator::new(&mut generator, function).generate();
+        generator.wasm.functions.push(compiled);
+        generator.wasm.table.push(base + index as u32);
+    }
+
+    let Generator {
+        mut wasm,
+        statics,
+        runtime,
+        roots,
+        ..
+    } = generator;
+    let start = wasm.type_index(&[], &[]);
+    wasm.functions.push(module::Function {
+        name: "start".to_string(),
+        ty: start,
+        locals: Vec::new(),
+        body: vec![
+            Instr::I32Const(0),
+            Instr::Call(base + program.entry as u32),
+            Instr::Drop,
+            Instr::Call(runtime.flush),
+        ],
+    });
+    // Calls nest at most `MAX_CALL_DEPTH` deep, so the shadow stack never outgrows this space
+    let roots_start = (DATA_START + statics.bytes.len() as u32).next_multiple_of(8);
+    let heap = roots_start + (MAX_CALL_DEPTH as u32 * roots + RUNTIME_ROOTS) * 8;
+    wasm.memory = heap / PAGE_SIZE + 1;
+    wasm.globals = vec![
+        Global {
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(heap as i32),
+        },
+        Global {
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(1),
+        },
+        Global {
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(0),
+        },
+        Global {
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(-1),
+        },
+        Global {
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(0),
+        },
+    ];
+    for init in [roots_start, roots_start, heap, 0, MIN_HEAP, 0, 0] {
+        wasm.globals.push(Global {
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(init as i32),
+        });
+    }
+    wasm.exports = vec![
+        Export {
+            name: "main".to_string(),
+            kind: ExportKind::Function,
+            index: base + program.functions.len() as u32,
+        },
+        Export {
+            name: "memory".to_string(),
+            kind: ExportKind::Memory,
+            index: 0,
+        },
+        Export {
+            name: "stress".to_string(),
+            kind: ExportKind::Function,
+            index: runtime.stress,
+        },
+    ];
+    wasm.data = vec![Data {
+        offset: DATA_START,
+        bytes: statics.bytes,
+    }];
+    wasm
+}
+
+/// The contents of the data segment, which starts at `DATA_START`
+/// - `texts` maps raw texts, such as paths and messages, to their address
+/// - `strings` maps string literals to the address of their object
+/// - `sites` maps source locations to the address of their `{path, length, line, column}` record
+#[derive(Default)]
+pub struct Statics {
+    bytes: Vec<u8>,
+    texts: HashMap<String, u32>,
+    strings: HashMap<String, u32>,
+    sites: HashMap<(usize, Span), u32>,
+}
+
+impl Statics {
+    fn address(&self) -> u32 {
+        DATA_START + self.bytes.len() as u32
+    }
+
+    fn align(&mut self) {
+        while !self.bytes.len().is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+    }
+
+    fn word(&mut self, word: u32) {
+        self.bytes.extend(word.to_le_bytes());
+    }
+
+    /// The address and length of raw bytes holding `text`
+    pub fn text(&mut self, text: &str) -> (u32, u32) {
+        let len = text.len() as u32;
+        if let Some(&address) = self.texts.get(text) {
+            return (address, len);
+        }
+        let address = self.address();
+        self.bytes.extend(text.as_bytes());
+        self.texts.insert(text.to_string(), address);
+        (address, len)
+    }
+
+    /// The address of the string object of a literal
+    fn string(&mut self, value: &str) -> u32 {
+        if let Some(&address) = self.strings.get(value) {
+            return address;
+        }
+        self.align();
+        let address = self.address();
+        self.word(STRING as u32);
+        self.word(value.len() as u32);
+        self.bytes.extend(value.as_bytes());
+        self.strings.insert(value.to_string(), address);
+        address
+    }
+
+    /// The address of the closure object of a function that captures nothing
+    fn function(&mut self, index: u32) -> u32 {
+        self.align();
+        let address = self.address();
+        self.word(CLOSURE as u32);
+        self.word(index);
+        address
+    }
+
+    /// The address of the record describing the source location of an operation
+    fn site(&mut self, file: &SourceFile, index: usize, span: Span) -> u32 {
+        if let Some(&address) = self.sites.get(&(index, span)) {
+            return address;
+        }
+        let (path, len) = self.text(&file.path.display().to_string());
+        let (line, column) = span.line_col(&file.source);
+        self.align();
+        let address = self.address();
+        for word in [path, len, line as u32, column as u32] {
+            self.word(word);
+        }
+        self.sites.insert((index, span), address);
+        address
+    }
+}
+
+/// Module-wide state of the generator
+/// - `base` is the index of the first compiled function, after the runtime's
+/// - `functions` maps compiled functions that capture nothing to their closure object
+/// - `roots` is the most slots that the frame of a function takes on the shadow stack
+struct Generator<'p> {
+    files: &'p [SourceFile],
+    wasm: Module,
+    statics: Statics,
+    runtime: Runtime,
+    base: u32,
+    functions: HashMap<usize, u32>,
+    roots: u32,
+}
+
+impl Generator<'_> {
+    /// The type of compiled functions taking `arity` arguments
+    fn function_type(&mut self, arity: usize) -> u32 {
+        let mut params = vec![ValType::I32];
+        params.extend(std::iter::repeat_n(ValType::I64, arity));
+        self.wasm.type_index(&params, &[ValType::I64])
+    }
+}
+
+/// An enclosing construct of the code being generated, which `br` can target
+/// - a block is followed by the code of a block of the IR, which branching to it jumps to
+/// - a loop is headed by the code of a block of the IR, which branching to it jumps back to
+/// - an `if` is only there to be counted
+#[derive(Clone, Copy, PartialEq)]
+enum Frame {
+    BlockFollowedBy(Block),
+    LoopHeadedBy(Block),
+    IfThenElse,
+}
+
+/// Generates one function, placing the blocks of its IR in structured control flow with the
+/// algorithm of Ramsey, "Beyond Relooper", which relies on the control-flow graph being reducible.
+/// Each block is generated where its immediate dominator places it:
+/// - a loop header is wrapped in a `loop`, which branches from its body jump back to
+/// - a block with several forward predecessors follows a `block` that they all branch out of,
+///   which encloses its immediate dominator's code
+/// - any other block is generated inline, where its only predecessor jumps to it
+///
+/// Every IR value lives in an `i64` local, and phis are assigned before jumping to their block.
+/// Values that can hold objects are also copied to the function's frame on the shadow stack
+/// whenever they are assigned, so that the collector finds them.
+struct FunctionGenerator<'g, 'p> {
+    generator: &'g mut Generator<'p>,
+    function: &'p Function,
+    dominators: Dominators,
+    predecessors: Vec<Vec<Block>>,
+    /// The local of each IR value, allocated when it is first mentioned
+    values: Vec<Option<u32>>,
+    /// Types of the locals after the parameters
+    locals: Vec<ValType>,
+    /// A local for an operand kept aside for an instant, by `operands`
+    scratch: Option<u32>,
+    /// A local for the closure of an indirect call
+    callee: Option<u32>,
+    /// The slot in the frame of each IR value that can hold an object
+    roots: Vec<Option<u32>>,
+    /// A local holding the address of the frame, when the function has roots
+    frame: Option<u32>,
+    body: Vec<Instr>,
+}
+
+impl<'g, 'p> FunctionGenerator<'g, 'p> {
+    fn new(generator: &'g mut Generator<'p>, function: &'p Function) -> Self {
+        let mut values = vec![None; function.values.len()];
+        for (n, param) in function.params.iter().enumerate() {
+            values[param.0 as usize] = Some(n as u32 + 1);
+        }
+        let mut slots = 0u32..;
+        let roots = function
+            .values
+            .iter()
+            .map(|ty| ty.is_traced().then(|| slots.next().unwrap()))
+            .collect();
+        Self {
+            generator,
+            function,
+            dominators: Dominators::compute(function),
+            predecessors: cfg::predecessors(function),
+            values,
+            locals: Vec::new(),
+            scratch: None,
+            callee: None,
+            roots,
+            frame: None,
+            body: Vec::new(),
+        }
+    }
+
+    fn generate(mut self) -> module::Function {
+        let function = self.function;
+        self.prologue();
+        self.do_tree(Block(0), &mut Vec::new());
+        // Every path returns before reaching the end of the body
+        self.emit([Instr::Unreachable]);
+        let ty = self.generator.function_type(function.params.len());
+        module::Function {
+            name: function.name.clone(),
+            ty,
+            locals: self.locals,
+            body: self.body,
+        }
+    }
+
+    fn emit(&mut self, instrs: impl IntoIterator<Item = Instr>) {
+        self.body.extend(instrs);
+    }
+
+    /// Pushes the frame of the function on the shadow stack, with its slots cleared of what earlier
+    /// frames left there, and roots the parameters
+    fn prologue(&mut self) {
+        let slots = self.roots.iter().flatten().count() as u32;
+        if slots == 0 {
+            return;
+        }
+        let frame = self.local(ValType::I32);
+        self.frame = Some(frame);
+        self.generator.roots = self.generator.roots.max(slots);
+        self.emit([
+            Instr::GlobalGet(ROOTS),
+            Instr::LocalTee(frame),
+            Instr::I32Const(8 * slots as i32),
+            Instr::Numeric(Op::I32Add),
+            Instr::GlobalSet(ROOTS),
+        ]);
+        for slot in 0..slots {
+            self.emit([
+                Instr::LocalGet(frame),
+                Instr::I64Const(0),
+                Instr::Memory(Access::I64Store, 8 * slot),
+            ]);
+        }
+        for &param in &self.function.params {
+            self.root(param);
+        }
+    }
+
+    /// Copies `value` to its slot in the frame, if it can hold an object
+    fn root(&mut self, value: Value) {
+        if let (Some(frame), Some(slot)) = (self.frame, self.roots[value.0 as usize]) {
+            let local = self.value(value);
+            self.emit([
+                Instr::LocalGet(frame),
+                Instr::LocalGet(local),
+                Instr::Memory(Access::I64Store, 8 * slot),
+            ]);
+        }
+    }
+
+    /// A new local of type `ty`
+    fn local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        (self.function.params.len() + self.locals.len()) as u32
+    }
+
+    /// The local holding an IR value
+    fn value(&mut self, value: Value) -> u32 {
+        match self.values[value.0 as usize] {
+            Some(local) => local,
+            None => {
+                let local = self.local(ValType::I64);
+                self.values[value.0 as usize] = Some(local);
+                local
+            }
+        }
+    }
+
+    fn get(&mut self, value: Value) {
+        let local = self.value(value);
+        self.emit([Instr::LocalGet(local)]);
+    }
+
+    /// Whether `block` has more than one forward predecessor, so that it cannot be placed inline
+    fn is_merge(&self, block: Block) -> bool {
+        let number = self.dominators.rpo_number(block);
+        let forward = self.predecessors[block.0 as usize]
+            .iter()
+            .filter(|&&from| self.dominators.rpo_number(from) < number)
+            .count();
+        forward > 1
+    }
+
+    fn is_loop_header(&self, block: Block) -> bool {
+        let number = self.dominators.rpo_number(block);
+        self.predecessors[block.0 as usize]
+            .iter()
+            .any(|&from| self.dominators.rpo_number(from) >= number)
+    }
+
+    /// Generates `block` and the blocks it immediately dominates, within the enclosing `context`
+    fn do_tree(&mut self, block: Block, context: &mut Vec<Frame>) {
+        let merges: Vec<Block> = self
+            .dominators
+            .children(block)
+            .iter()
+            .copied()
+            .filter(|&child| self.is_merge(child))
+            .collect();
+        if self.is_loop_header(block) {
+            self.emit([Instr::Loop(BlockType::Empty)]);
+            context.push(Frame::LoopHeadedBy(block));
+            self.node_within(block, &merges, context);
+            context.pop();
+            self.emit([Instr::End]);
+        } else {
+            self.node_within(block, &merges, context);
+        }
+    }
+
+    /// Generates `block`, followed by the merge blocks it dominates, the last of which is placed
+    /// outermost
+    fn node_within(&mut self, block: Block, merges: &[Block], context: &mut Vec<Frame>) {
+        if let Some((&merge, inner)) = merges.split_last() {
+            self.emit([Instr::Block(BlockType::Empty)]);
+            context.push(Frame::BlockFollowedBy(merge));
+            self.node_within(block, inner, context);
+            context.pop();
+            self.emit([Instr::End]);
+            return self.do_tree(merge, context);
+        }
+        for instruction in &self.function.block(block).instructions {
+            self.instruction(instruction);
+        }
+        match self.function.block(block).terminator {
+            Terminator::Jump(target) => self.do_branch(block, target, context),
+            Terminator::Branch {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.get(condition);
+                self.emit([Instr::Numeric(Op::I32WrapI64), Instr::If(BlockType::Empty)]);
+                context.push(Frame::IfThenElse);
+                self.do_branch(block, then, context);
+                self.emit([Instr::Else]);
+                self.do_branch(block, otherwise, context);
+                context.pop();
+                self.emit([Instr::End]);
+            }
+            Terminator::Return(value) => {
+                self.get(value);
+                if let Some(frame) = self.frame {
+                    self.emit([Instr::LocalGet(frame), Instr::GlobalSet(ROOTS)]);
+                }
+                self.emit([Instr::Return]);
+            }
+            Terminator::Unreachable => self.emit([Instr::Unreachable]),
+        }
+    }
+
+    /// Jumps from the end of `from` to `to`, assigning the phis of `to` first
+    fn do_branch(&mut self, from: Block, to: Block, context: &mut Vec<Frame>) {
+        let moves: Vec<(Value, Value)> = self
+            .function
+            .block(to)
+            .phis
+            .iter()
+            .filter_map(|phi| {
+                let (_, value) = phi.incoming.iter().find(|(block, _)| *block == from)?;
+                (*value != phi.result).then_some((phi.result, *value))
+            })
+            .collect();
+        for &(_, value) in &moves {
+            self.get(value);
+        }
+        for &(phi, _) in moves.iter().rev() {
+            let local = self.value(phi);
+            self.emit([Instr::LocalSet(local)]);
+            self.root(phi);
+        }
+
+        let backward = self.dominators.rpo_number(to) <= self.dominators.rpo_number(from);
+        if backward || self.is_merge(to) {
+            let frame = match backward {
+                true => Frame::LoopHeadedBy(to),
+                false => Frame::BlockFollowedBy(to),
+            };
+            let position = context
+                .iter()
+                .rposition(|&enclosing| enclosing == frame)
+                .expect("reducible control flow branches to enclosing constructs");
+            self.emit([Instr::Br((context.len() - 1 - position) as u32)]);
+        } else {
+            self.do_tree(to, context);
+        }
+    }
+
+    /// Pushes the address of the site of an operation at `span`
+    fn site(&mut self, span: Span) {
+        let file = self.function.file;
+        let address = self
+            .generator
+            .statics
+            .site(&self.generator.files[file], file, span);
+        self.emit([Instr::I32Const(address as i32)]);
+    }
+
+    /// Pushes a constant value
+    fn constant(&mut self, bits: i64) {
+        self.emit([Instr::I64Const(bits)]);
+    }
+
+    /// Tags the `i32` on top of the stack
+    fn make(&mut self, tag: i64) {
+        self.emit([
+            Instr::Numeric(Op::I64ExtendI32U),
+            Instr::I64Const(tag),
+            Instr::Numeric(Op::I64Or),
+        ]);
+    }
+
+    /// Converts the two values on top of the stack with `convert`
+    fn operands(&mut self, convert: Op) {
+        let scratch = match self.scratch {
+            Some(scratch) => scratch,
+            None => {
+                let scratch = self.local(ValType::I64);
+                self.scratch = Some(scratch);
+                scratch
+            }
+        };
+        self.emit([
+            Instr::LocalSet(scratch),
+            Instr::Numeric(convert),
+            Instr::LocalGet(scratch),
+            Instr::Numeric(convert),
+        ]);
+    }
+
+    /// Replaces the object on top of the stack with the value it holds at offset 8
+    fn inner(&mut self) {
+        self.emit([
+            Instr::Numeric(Op::I32WrapI64),
+            Instr::Memory(Access::I64Load, 8),
+        ]);
+    }
+
+    /// Pushes the address of the range `range` and loads the 32-bit word at `offset` in it
+    fn range_field(&mut self, range: Value, offset: u32) {
+        self.get(range);
+        self.emit([
+            Instr::Numeric(Op::I32WrapI64),
+            Instr::Memory(Access::I32Load, offset),
+        ]);
+    }
+
+    /// Generates an instruction, storing its result in the local of its value
+    fn instruction(&mut self, instruction: &'p ir::Instruction) {
+        let span = instruction.span;
+        match &instruction.kind {
+            InstructionKind::Const(constant) => match constant {
+                Constant::Int(value) => self.constant(INT | *value as u32 as i64),
+                Constant::Float(value) => self.constant(value.to_bits() as i64),
+                Constant::Bool(value) => self.constant(BOOL | *value as i64),
+                Constant::Char(value) => self.constant(CHAR | *value as i64),
+                Constant::String(value) => {
+                    let address = self.generator.statics.string(value);
+                    self.constant(OBJECT | address as i64);
+                }
+                Constant::Unit => self.constant(UNIT),
+                Constant::Null => self.constant(NULL),
+            },
+            InstructionKind::Function(index) => {
+                let generator = &mut *self.generator;
+                let address = *generator
+                    .functions
+                    .entry(*index)
+                    .or_insert_with(|| generator.statics.function(*index as u32));
+                self.constant(OBJECT | address as i64);
+            }
+            InstructionKind::Closure { function, captures } => {
+                let closure = self.local(ValType::I32);
+                self.emit([
+                    Instr::I32Const(8 + 8 * captures.len() as i32),
+                    Instr::Call(self.generator.runtime.alloc),
+                    Instr::LocalTee(closure),
+                    Instr::I32Const(CLOSURE),
+                    Instr::Memory(Access::I32Store, 0),
+                    Instr::LocalGet(closure),
+                    Instr::I32Const(*function as i32),
+                    Instr::Memory(Access::I32Store, 4),
+                ]);
+                // Variables captured by reference are passed as their heap cell
+                for (n, &capture) in captures.iter().enumerate() {
+                    self.emit([Instr::LocalGet(closure)]);
+                    self.get(capture);
+                    self.emit([Instr::Memory(Access::I64Store, 8 + 8 * n as u32)]);
+                }
+                self.emit([Instr::LocalGet(closure)]);
+                self.make(OBJECT);
+            }
+            InstructionKind::Capture(slot) => self.emit([
+                Instr::LocalGet(0),
+                Instr::Memory(Access::I64Load, 8 + 8 * slot),
+            ]),
+            InstructionKind::NewCell(value) => {
+                self.get(*value);
+                self.emit([
+                    Instr::I32Const(CELL),
+                    Instr::Call(self.generator.runtime.wrap),
+                ]);
+            }
+            InstructionKind::LoadCell(cell) => {
+                self.get(*cell);
+                self.inner();
+            }
+            InstructionKind::StoreCell { cell, value } => {
+                self.get(*cell);
+                self.emit([Instr::Numeric(Op::I32WrapI64)]);
+                self.get(*value);
+                return self.emit([Instr::Memory(Access::I64Store, 8)]);
+            }
+            InstructionKind::Unary { op, operand } => {
+                self.get(*operand);
+                match (op, self.function.type_of(*operand)) {
+                    (UnaryOp::Negate, Type::Float) => self.emit([
+                        Instr::Numeric(Op::F64ReinterpretI64),
+                        Instr::Numeric(Op::F64Neg),
+                        Instr::Numeric(Op::I64ReinterpretF64),
+                    ]),
+                    (UnaryOp::Negate, _) => {
+                        self.emit([Instr::Numeric(Op::I32WrapI64)]);
+                        self.site(span);
+                        self.emit([Instr::Call(self.generator.runtime.negate)]);
+                        self.make(INT);
+                    }
+                    (UnaryOp::Not, _) => {
+                        self.emit([Instr::I64Const(1), Instr::Numeric(Op::I64Xor)]);
+                    }
+                    (UnaryOp::BitNot, _) => {
+                        self.emit([Instr::I64Const(0xFFFF_FFFF), Instr::Numeric(Op::I64Xor)]);
+                    }
+                }
+            }
+            InstructionKind::Binary { op, left, right } => {
+                self.get(*left);
+                self.get(*right);
+                let function = self.function;
+                self.binary(*op, function.type_of(*left), function.type_of(*right), span);
+            }
+            InstructionKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                self.get(*start);
+                self.get(*end);
+                self.operands(Op::I32WrapI64);
+                self.emit([
+                    Instr::I32Const(*inclusive as i32),
+                    Instr::Call(self.generator.runtime.range),
+                ]);
+            }
+            InstructionKind::RangeStart(range) => {
+                self.range_field(*range, 8);
+                self.make(INT);
+            }
+            InstructionKind::RangeContains { range, item } => {
+                // Before the end, or at the end of an inclusive range
+                for comparison in [Op::I32LtS, Op::I32Eq] {
+                    self.get(*item);
+                    self.emit([Instr::Numeric(Op::I32WrapI64)]);
+                    self.range_field(*range, 12);
+                    self.emit([Instr::Numeric(comparison)]);
+                }
+                self.range_field(*range, 4);
+                self.emit([Instr::Numeric(Op::I32And), Instr::Numeric(Op::I32Or)]);
+                self.make(BOOL);
+            }
+            InstructionKind::IsNull(value) => {
+                self.get(*value);
+                self.emit([Instr::I64Const(NULL), Instr::Numeric(Op::I64Eq)]);
+                self.make(BOOL);
+            }
+            InstructionKind::Payload(value) => {
+                self.get(*value);
+                self.inner();
+            }
+            InstructionKind::Call { function, args } => {
+                self.emit([Instr::I32Const(0)]);
+                for &arg in args {
+                    self.get(arg);
+                }
+                let function = self.generator.base + *function as u32;
+                self.counted_call(Instr::Call(function), span);
+            }
+            InstructionKind::CallValue { callee, args } => {
+                let closure = match self.callee {
+                    Some(closure) => closure,
+                    None => {
+                        let closure = self.local(ValType::I32);
+                        self.callee = Some(closure);
+                        closure
+                    }
+                };
+                self.get(*callee);
+                self.emit([Instr::Numeric(Op::I32WrapI64), Instr::LocalTee(closure)]);
+                for &arg in args {
+                    self.get(arg);
+                }
+                let ty = self.generator.function_type(args.len());
+                self.site(span);
+                self.emit([
+                    Instr::Call(self.generator.runtime.enter),
+                    Instr::LocalGet(closure),
+                    Instr::Memory(Access::I32Load, 4),
+                    Instr::CallIndirect(ty),
+                    Instr::Call(self.generator.runtime.leave),
+                ]);
+            }
+            InstructionKind::CallBuiltin { builtin, args } if builtin.is_math() => {
+                self.math(*builtin, args, span);
+            }
+            InstructionKind::CallBuiltin { builtin, args } => {
+                for &arg in args {
+                    self.get(arg);
+                }
+                self.builtin(*builtin, args.len(), span);
+            }
+        }
+        let result = instruction.result.expect("only stores have no result");
+        let local = self.value(result);
+        self.emit([Instr::LocalSet(local)]);
+        self.root(result);
+    }
+
+    /// Calls a compiled function, whose arguments are on the stack, counting the call towards the
+    /// maximum call depth
+    fn counted_call(&mut self, call: Instr, span: Span) {
+        self.site(span);
+        self.emit([
+            Instr::Call(self.generator.runtime.enter),
+            call,
+            Instr::Call(self.generator.runtime.leave),
+        ]);
+    }
+
+    /// Calls a built-in function, whose `arity` arguments are on the stack
+    fn builtin(&mut self, builtin: Builtin, arity: usize, span: Span) {
+        let runtime = self.generator.runtime;
+        match (builtin, arity) {
+            (Builtin::Println, 0) => {
+                self.emit([Instr::Call(runtime.println_empty)]);
+                self.constant(UNIT);
+            }
+            (Builtin::Println, 1) => {
+                self.emit([Instr::Call(runtime.println)]);
+                self.constant(UNIT);
+            }
+            (Builtin::Print, 1) => {
+                self.emit([Instr::Call(runtime.print)]);
+                self.constant(UNIT);
+            }
+            (Builtin::PrintPadded, 4) => {
+                self.emit([Instr::Call(runtime.print_padded)]);
+                self.constant(UNIT);
+            }
+            (Builtin::Input, 0) => self.emit([Instr::Call(runtime.input)]),
+            (Builtin::ToString, 1) => self.emit([Instr::Call(runtime.to_string)]),
+            (Builtin::Concat, 2) => self.emit([Instr::Call(runtime.concat)]),
+            (Builtin::Int, 1) => {
+                self.site(span);
+                self.emit([Instr::Call(runtime.to_int)]);
+            }
+            (Builtin::Float, 1) => self.emit([Instr::Call(runtime.to_float)]),
+            (Builtin::Char, 1) => {
+                self.site(span);
+                self.emit([Instr::Call(runtime.to_char)]);
+            }
+            (Builtin::Panic, 1) => {
+                let panic = runtime.panic_value;
+                self.site(span);
+                self.emit([Instr::Call(panic), Instr::Unreachable]);
+            }
+            (Builtin::Ok, 0) => {
+                self.constant(UNIT);
+                self.wrap(OK);
+            }
+            (Builtin::Ok, 1) => self.wrap(OK),
+            (Builtin::Err, 1) => self.wrap(ERR),
+            (Builtin::Unwrap, 1) => self.unwrap(OK, span),
+            (Builtin::UnwrapErr, 1) => self.unwrap(ERR, span),
+            (Builtin::IsOk | Builtin::IsErr, 1) => {
+                let kind = if builtin == Builtin::IsOk { OK } else { ERR };
+                self.emit([Instr::I32Const(kind), Instr::Call(runtime.has_kind)]);
+                self.make(BOOL);
+            }
+            (Builtin::Open, 2) => self.emit([Instr::Call(runtime.open)]),
+            (Builtin::Close, 1) => self.emit([Instr::Call(runtime.close)]),
+            (Builtin::Read | Builtin::ReadLine, 1) => {
+                let line = (builtin == Builtin::ReadLine) as i32;
+                self.emit([Instr::I32Const(line), Instr::Call(runtime.read)]);
+            }
+            (Builtin::Write, 2) => self.emit([Instr::Call(runtime.write_file)]),
+            (Builtin::NextLine, 1) => self.emit([Instr::Call(runtime.next_line)]),
+            (Builtin::CharAt, 2) => self.emit([Instr::Call(runtime.char_at)]),
+            (Builtin::CharWidth, 2) => self.emit([Instr::Call(runtime.char_width)]),
+            _ => unreachable!("the verifier checks the arguments of built-in functions"),
+        }
+    }
+
+    /// Calls a math built-in on `args`, which are all integers or all floats
+    fn math(&mut self, builtin: Builtin, args: &[Value], span: Span) {
+        let runtime = self.generator.runtime;
+        let float = *self.function.type_of(args[0]) == Type::Float;
+        let convert = if float {
+            Op::F64ReinterpretI64
+        } else {
+            Op::I32WrapI64
+        };
+        if let (Builtin::Min | Builtin::Max, &[a, b]) = (builtin, args) {
+            // The second argument when it is less, or greater, or when the first is NaN
+            let comparison = match (builtin, float) {
+                (Builtin::Min, true) => Op::F64Lt,
+                (Builtin::Min, false) => Op::I32LtS,
+                (_, true) => Op::F64Gt,
+                (_, false) => Op::I32GtS,
+            };
+            self.get(b);
+            self.get(a);
+            for value in [b, a] {
+                self.get(value);
+                self.emit([Instr::Numeric(convert)]);
+            }
+            self.emit([Instr::Numeric(comparison)]);
+            if float {
+                for value in [a, a] {
+                    self.get(value);
+                    self.emit([Instr::Numeric(convert)]);
+                }
+                self.emit([Instr::Numeric(Op::F64Ne), Instr::Numeric(Op::I32Or)]);
+            }
+            return self.emit([Instr::Select]);
+        }
+        for &arg in args {
+            self.get(arg);
+            self.emit([Instr::Numeric(convert)]);
+        }
+        if !float {
+            self.site(span);
+            let function = match builtin {
+                Builtin::Abs => runtime.abs,
+                _ => runtime.pow_int,
+            };
+            self.emit([Instr::Call(function)]);
+            return self.make(INT);
+        }
+        let instr = match builtin {
+            Builtin::Abs => Instr::Numeric(Op::F64Abs),
+            Builtin::Sqrt => Instr::Numeric(Op::F64Sqrt),
+            Builtin::Ceil => Instr::Numeric(Op::F64Ceil),
+            Builtin::Floor => Instr::Numeric(Op::F64Floor),
+            Builtin::Pow => Instr::Call(runtime.pow),
+            Builtin::Sin => Instr::Call(runtime.sin),
+            Builtin::Cos => Instr::Call(runtime.cos),
+            Builtin::Log => Instr::Call(runtime.log10),
+            Builtin::Ln => Instr::Call(runtime.log),
+            _ => Instr::Call(runtime.exp),
+        };
+        self.emit([instr, Instr::Numeric(Op::I64ReinterpretF64)]);
+    }
+
+    /// Replaces the value on top of the stack with an `Ok` or an `Err` (`kind`) holding it
+    fn wrap(&mut self, kind: i32) {
+        self.emit([
+            Instr::I32Const(kind),
+            Instr::Call(self.generator.runtime.wrap),
+        ]);
+    }
+
+    /// Replaces the result on top of the stack with the value inside, panicking unless its kind is
+    /// `expected`
+    fn unwrap(&mut self, expected: i32, span: Span) {
+        self.emit([Instr::I32Const(expected)]);
+        self.site(span);
+        self.emit([Instr::Call(self.generator.runtime.unwrap)]);
+    }
+
+    /// Applies `op` to the two operands on top of the stack, the left one of type `left`
+    fn binary(&mut self, op: BinaryOp, left: &Type, right: &Type, span: Span) {
+        if let Type::Float = left {
+            self.operands(Op::F64ReinterpretI64);
+            let instr = match op {
+                BinaryOp::Add => Op::F64Add,
+                BinaryOp::Subtract => Op::F64Sub,
+                BinaryOp::Multiply => Op::F64Mul,
+                BinaryOp::Divide => Op::F64Div,
+                BinaryOp::Remainder => {
+                    self.emit([
+                        Instr::Call(self.generator.runtime.fmod),
+                        Instr::Numeric(Op::I64ReinterpretF64),
+                    ]);
+                    return;
+                }
+                _ => {
+                    let comparison = match op {
+                        BinaryOp::Equal => Op::F64Eq,
+                        BinaryOp::NotEqual => Op::F64Ne,
+                        BinaryOp::Less => Op::F64Lt,
+                        BinaryOp::LessEqual => Op::F64Le,
+                        BinaryOp::Greater => Op::F64Gt,
+                        _ => Op::F64Ge,
+                    };
+                    self.emit([Instr::Numeric(comparison)]);
+                    self.make(BOOL);
+                    return;
+                }
+            };
+            self.emit([Instr::Numeric(instr), Instr::Numeric(Op::I64ReinterpretF64)]);
+            return;
+        }
+
+        match op {
+            BinaryOp::Equal | BinaryOp::NotEqual => {
+                // Values of the same simple type are equal when their bits are
+                match (left, right) {
+                    (Type::Int | Type::Char | Type::Bool, _) if left == right => {
+                        self.emit([Instr::Numeric(Op::I64Eq)]);
+                    }
+                    _ => self.emit([Instr::Call(self.generator.runtime.equal)]),
+                }
+                if op == BinaryOp::NotEqual {
+                    self.emit([Instr::Numeric(Op::I32Eqz)]);
+                }
+                self.make(BOOL);
+                return;
+            }
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => {
+                let unsigned = matches!(left, Type::Char);
+                let comparison = match (op, unsigned) {
+                    (BinaryOp::Less, false) => Op::I32LtS,
+                    (BinaryOp::Less, true) => Op::I32LtU,
+                    (BinaryOp::LessEqual, false) => Op::I32LeS,
+                    (BinaryOp::LessEqual, true) => Op::I32LeU,
+                    (BinaryOp::Greater, false) => Op::I32GtS,
+                    (BinaryOp::Greater, true) => Op::I32GtU,
+                    (_, false) => Op::I32GeS,
+                    (_, true) => Op::I32GeU,
+                };
+                self.operands(Op::I32WrapI64);
+                self.emit([Instr::Numeric(comparison)]);
+                self.make(BOOL);
+                return;
+            }
+            // The tags of the operands are equal, so bitwise operations only need to restore them
+            BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => {
+                let instr = match op {
+                    BinaryOp::BitAnd => Op::I64And,
+                    BinaryOp::BitOr => Op::I64Or,
+                    _ => Op::I64Xor,
+                };
+                self.emit([
+                    Instr::Numeric(instr),
+                    Instr::I64Const(INT),
+                    Instr::Numeric(Op::I64Or),
+                ]);
+                return;
+            }
+            _ => (),
+        }
+
+        let runtime = self.generator.runtime;
+        let function = match op {
+            BinaryOp::Add => runtime.add,
+            BinaryOp::Subtract => runtime.subtract,
+            BinaryOp::Multiply => runtime.multiply,
+            BinaryOp::Divide => runtime.divide,
+            BinaryOp::Remainder => runtime.remainder,
+            BinaryOp::ShiftLeft => runtime.shift_left,
+            BinaryOp::ShiftRight => runtime.shift_right,
+            _ => unreachable!(
+                "comparisons are handled above, and short-circuiting operators are branches"
+            ),
+        };
+        self.operands(Op::I32WrapI64);
+        self.site(span);
+        self.emit([Instr::Call(function)]);
+        self.make(INT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::HEAP_LIMIT;
+    use crate::front_end;
+    use crate::testing::{
+        assert_outputs_match_interpreter, executable_path, run_with_input, source_files, FILES,
+        PROGRAMS,
+    };
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn generate_source(source: &str) -> Module {
+        let analysis = front_end::analyze(source).unwrap();
+        generate(&ir::build(&analysis.program), &source_files(source))
+    }
+
+    /// Checks that the module generated from `source`, run by Node.js, prints and panics like the
+    /// interpreter
+    fn assert_matches_interpreter(source: &str, name: &str) {
+        let module = executable_path(&format!("wasm-{}.wasm", name));
+        fs::write(&module, generate_source(source).encode()).unwrap();
+        let host = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/back_end/wasm/host.js");
+        // Collecting on every allocation catches the roots that generated code fails to keep, and
+        // Node.js needs a larger stack than its default to nest `MAX_CALL_DEPTH` calls
+        let outputs: Vec<_> = ["0", "1"]
+            .iter()
+            .map(|stress| {
+                run_with_input(
+                    Command::new("node")
+                        .arg("--stack-size=4000")
+                        .arg(&host)
+                        .arg(&module)
+                        .env("CRAWFISH_GC_STRESS", stress),
+                )
+            })
+            .collect();
+        fs::remove_file(&module).unwrap();
+        assert_outputs_match_interpreter(source, &outputs);
+    }
+
+    #[test]
+    fn test_generated_module_structure() {
+        let module = generate_source(
+            r#"func sum(a: Int, b: Int) -> Int { return a + b; } func main() { println(sum(1, 2)); println("hé"); }"#,
+        );
+        let wat = module.to_string();
+        assert!(wat.contains(r#"(import "crawfish" "write" (func (;0;) (type 0)))"#));
+        assert!(wat.contains(r#"(import "crawfish" "format_float""#));
+        assert!(wat.contains(r#"(export "memory" (memory 0))"#));
+        let sum = &wat[wat.find(";; sum\n").unwrap()..];
+        let sum = &sum[..sum.find("\n  )").unwrap()];
+        // The right operand is kept aside while the left one is converted
+        assert!(sum.contains(
+            "local.get 1\n    local.get 2\n    local.set 3\n    i32.wrap_i64\n    local.get 3\n"
+        ));
+        assert!(sum.contains("\n    return\n"));
+        // The string object: its kind, its length, then its bytes
+        assert!(wat.contains(r#"\01\00\00\00\03\00\00\00h\c3\a9"#));
+        // The allocator stops at the heap limit, and `main` roots its string in a frame of one slot
+        assert!(wat.contains(&format!("i32.const {}\n      i32.le_u\n", HEAP_LIMIT)));
+        let main = &wat[wat.find(";; main\n").unwrap()..];
+        assert!(main.contains("global.get 5\n    local.tee 1\n    i32.const 8\n    i32.add\n"));
+        assert!(!sum.contains("global.get 5\n"));
+        assert_eq!(module.table.len(), 2);
+        assert_eq!(
+            module.exports[0].index as usize,
+            module.imports.len() + module.functions.len() - 1
+        );
+    }
+
+    #[test]
+    fn test_generated_modules_are_valid() {
+        let files = FILES.replace("PATH", "data.txt");
+        // Files read through `?`, which the host of the WebAssembly module implements
+        let copy = r#"
+        func copy(from: String, to: String) -> Result[Int, String] {
+            var input = open(from, "r")?;
+            defer close(input);
+            var output = open(to, "w")?;
+            defer close(output);
+            var count = 0;
+            for line in input {
+                write(output, "{line}\n")?;
+                count += 1;
+            }
+            println(read_line(input));
+            println(read(input));
+            return Ok(count);
+        }
+        func main() {
+            println(copy("in.txt", "out.txt"));
+        }
+        "#;
+        for program in PROGRAMS.iter().copied().chain([files.as_str(), copy]) {
+            let module = generate_source(program);
+            let decoded = validator::decode(&module.encode()).unwrap();
+            assert_eq!(decoded.functions.len(), module.functions.len());
+            assert_eq!(validator::validate(&decoded), Ok(()), "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_unreachable_memory_is_freed() {
+        if Command::new("node").arg("--version").output().is_err() {
+            eprintln!("skipping: no Node.js");
+            return;
+        }
+        // Allocates more than the heap can hold, keeping only the last string
+        let source = r#"
+        func main() {
+            var s = "x";
+            for i in 0..20 { s = "{s}{s}"; }
+            var t = "";
+            for i in 0..1100 { t = "{s}{i}"; }
+            println(t == s);
+        }
+        "#;
+        assert_matches_interpreter(source, "freed");
+    }
+
+    #[test]
+    fn test_programs_match_interpreter() {
+        if Command::new("node").arg("--version").output().is_err() {
+            eprintln!("skipping: no Node.js");
+            return;
+        }
+        let path = std::env::temp_dir().join(format!("crawfish-wasm-{}", std::process::id()));
+        let files = FILES.replace("PATH", path.to_str().unwrap());
+        for (n, program) in PROGRAMS.iter().copied().chain([files.as_str()]).enumerate() {
+            assert_matches_interpreter(program, &n.to_string());
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+// A Node.js host for modules of the WebAssembly backend, which the tests run them with:
+// `node --stack-size=4000 host.js program.wasm`, the stack size letting calls nest as deep as the
+// other backends allow. Setting `CRAWFISH_GC_STRESS` to anything but 0 makes the module
+// collect its heap on every allocation.
+const fs = require('fs');
+const bytes = fs.readFileSync(process.argv[2]);
+let memory;
+const encoder = new TextEncoder();
+
+// The shortest round-trip form, without exponent, whole numbers with `.0`
+function shortest(x) {
+  if (Number.isInteger(x)) return fixed(x, 1);
+  let s = String(x);
+  if (/e/.test(s)) s = x.toLocaleString('fullwide', { useGrouping: false, maximumSignificantDigits: 17 });
+  return s;
+}
+
+// Exactly `precision` digits after the point, rounding ties to even like Rust and C
+function fixed(x, precision) {
+  const view = new DataView(new ArrayBuffer(8));
+  view.setFloat64(0, x);
+  const bits = view.getBigUint64(0);
+  const negative = bits >> 63n === 1n;
+  const exponent = Number((bits >> 52n) & 0x7FFn);
+  let mantissa = bits & ((1n << 52n) - 1n);
+  let shift = exponent - 1075;
+  if (exponent === 0) shift = -1074; else mantissa |= 1n << 52n;
+  // x = mantissa * 2^shift; scaled = x * 10^precision, rounded
+  let numerator = mantissa * 10n ** BigInt(precision);
+  let denominator = 1n;
+  if (shift >= 0) numerator <<= BigInt(shift); else denominator <<= BigInt(-shift);
+  let quotient = numerator / denominator;
+  const remainder = numerator % denominator;
+  if (2n * remainder > denominator || (2n * remainder === denominator && quotient % 2n === 1n)) quotient += 1n;
+  let digits = quotient.toString().padStart(precision + 1, '0');
+  if (precision > 0) digits = digits.slice(0, -precision) + '.' + digits.slice(-precision);
+  return (negative ? '-' : '') + digits;
+}
+
+function format(x, precision) {
+  if (isNaN(x)) return 'NaN';
+  if (!isFinite(x)) return x > 0 ? 'inf' : '-inf';
+  return precision < 0 ? shortest(x) : fixed(x, precision);
+}
+
+let input = Buffer.alloc(0), ended = false, pending = null;
+function nextLine() {
+  for (;;) {
+    const newline = input.indexOf(10);
+    if (newline >= 0) {
+      let line = input.subarray(0, newline);
+      input = input.subarray(newline + 1);
+      if (line.length && line[line.length - 1] === 13) line = line.subarray(0, -1);
+      return line;
+    }
+    if (ended) {
+      if (!input.length) return null;
+      const line = input; input = Buffer.alloc(0); return line;
+    }
+    const chunk = Buffer.alloc(65536);
+    let n = 0;
+    try { n = fs.readSync(0, chunk); } catch (e) { if (e.code !== 'EOF') throw e; }
+    if (n === 0) ended = true; else input = Buffer.concat([input, chunk.subarray(0, n)]);
+  }
+}
+
+const files = [];
+function code(e) {
+  return { ENOENT: -2, EACCES: -3, EPERM: -3, EISDIR: -4 }[e.code] ?? -5;
+}
+function fill(file) {
+  const chunk = Buffer.alloc(65536);
+  const n = fs.readSync(file.fd, chunk);
+  if (n === 0) file.ended = true; else file.input = Buffer.concat([file.input, chunk.subarray(0, n)]);
+}
+function fileLine(file) {
+  for (;;) {
+    const newline = file.input.indexOf(10);
+    if (newline >= 0) {
+      let line = file.input.subarray(0, newline);
+      file.input = file.input.subarray(newline + 1);
+      if (line.length && line[line.length - 1] === 13) line = line.subarray(0, -1);
+      return line;
+    }
+    if (file.ended) {
+      if (!file.input.length) return null;
+      const line = file.input; file.input = Buffer.alloc(0); return line;
+    }
+    fill(file);
+  }
+}
+function fileRest(file) {
+  while (!file.ended) fill(file);
+  const rest = file.input; file.input = Buffer.alloc(0); file.ended = false; return rest;
+}
+const imports = { crawfish: {
+  write: (stream, pointer, length) => fs.writeSync(stream, new Uint8Array(memory.buffer, pointer, length)),
+  format_float: (value, precision, pointer) => {
+    const text = encoder.encode(format(value, precision));
+    new Uint8Array(memory.buffer, pointer, text.length).set(text);
+    return text.length;
+  },
+  read_line: (pointer, capacity) => {
+    if (pending === null) pending = nextLine();
+    if (pending === null) return -1;
+    const length = pending.length;
+    if (length <= capacity) {
+      new Uint8Array(memory.buffer, pointer, length).set(pending);
+      pending = null;
+    }
+    return length;
+  },
+  exit: (code) => process.exit(code),
+  sin: Math.sin, cos: Math.cos, log10: Math.log10, log: Math.log, exp: Math.exp, pow: Math.pow,
+  parse_float: (pointer, length) => Number(new TextDecoder().decode(new Uint8Array(memory.buffer, pointer, length))),
+  open: (pointer, length, mode) => {
+    const path = Buffer.from(new Uint8Array(memory.buffer, pointer, length)).toString();
+    try {
+      const fd = fs.openSync(path, String.fromCharCode(mode));
+      files.push({ fd, input: Buffer.alloc(0), ended: false, pending: null });
+      return files.length - 1;
+    } catch (e) { return code(e); }
+  },
+  read_file: (handle, line, pointer, capacity) => {
+    const file = files[handle];
+    try {
+      if (file.pending === null) file.pending = line ? fileLine(file) : fileRest(file);
+    } catch (e) { return code(e); }
+    if (file.pending === null) return -1;
+    const length = file.pending.length;
+    if (length <= capacity) {
+      new Uint8Array(memory.buffer, pointer, length).set(file.pending);
+      file.pending = null;
+    }
+    return length;
+  },
+  write_file: (handle, pointer, length) => {
+    try { fs.writeSync(files[handle].fd, new Uint8Array(memory.buffer, pointer, length)); return 0; }
+    catch (e) { return code(e); }
+  },
+  close: (handle) => fs.closeSync(files[handle].fd),
+} };
+WebAssembly.instantiate(bytes, imports).then(({ instance }) => {
+  memory = instance.exports.memory;
+  const stress = process.env.CRAWFISH_GC_STRESS;
+  if (stress && stress !== '0') instance.exports.stress();
+  instance.exports.main();
+});
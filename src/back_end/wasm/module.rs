@@ -0,0 +1,908 @@
+//! An in-memory WebAssembly module, with its binary encoding and its text format (WAT).
+//! Only the parts of the MVP specification the backend uses are modeled: one memory, one table of
+//! functions, mutable globals, and numeric instructions over `i32`, `i64` and `f64`.
+use std::fmt;
+
+pub const MAGIC: &[u8; 4] = b"\0asm";
+pub const VERSION: u32 = 1;
+
+/// Section ids, in the order sections appear in a module
+pub const TYPE_SECTION: u8 = 1;
+pub const IMPORT_SECTION: u8 = 2;
+pub const FUNCTION_SECTION: u8 = 3;
+pub const TABLE_SECTION: u8 = 4;
+pub const MEMORY_SECTION: u8 = 5;
+pub const GLOBAL_SECTION: u8 = 6;
+pub const EXPORT_SECTION: u8 = 7;
+pub const ELEMENT_SECTION: u8 = 9;
+pub const CODE_SECTION: u8 = 10;
+pub const DATA_SECTION: u8 = 11;
+
+pub const FUNC_TYPE: u8 = 0x60;
+pub const FUNCREF: u8 = 0x70;
+pub const EMPTY_BLOCK: u8 = 0x40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F64,
+}
+
+impl ValType {
+    pub fn code(&self) -> u8 {
+        match self {
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+            ValType::F64 => 0x7C,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<ValType> {
+        match code {
+            0x7F => Some(ValType::I32),
+            0x7E => Some(ValType::I64),
+            0x7C => Some(ValType::F64),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F64 => "f64",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// The type of the values a block, a loop or an `if` leaves on the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(ValType),
+}
+
+/// A numeric instruction, which takes its operands from the stack and has no immediates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32LtU,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64LtU,
+    I64GtS,
+    I64GtU,
+    I64LeS,
+    I64LeU,
+    I64GeS,
+    I64GeU,
+    F64Eq,
+    F64Ne,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    I32Clz,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32DivU,
+    I32RemS,
+    I32RemU,
+    I32And,
+    I32Or,
+    I32Xor,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I64Clz,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemS,
+    I64RemU,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    I64ShrU,
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Nearest,
+    F64Sqrt,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    F64Max,
+    I32WrapI64,
+    I32TruncF64S,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    F64ConvertI32S,
+    I64ReinterpretF64,
+    F64ReinterpretI64,
+}
+
+const I32: ValType = ValType::I32;
+const I64: ValType = ValType::I64;
+const F64: ValType = ValType::F64;
+
+impl Op {
+    pub const ALL: [Op; 76] = [
+        Op::I32Eqz,
+        Op::I32Eq,
+        Op::I32Ne,
+        Op::I32LtS,
+        Op::I32LtU,
+        Op::I32GtS,
+        Op::I32GtU,
+        Op::I32LeS,
+        Op::I32LeU,
+        Op::I32GeS,
+        Op::I32GeU,
+        Op::I64Eqz,
+        Op::I64Eq,
+        Op::I64Ne,
+        Op::I64LtS,
+        Op::I64LtU,
+        Op::I64GtS,
+        Op::I64GtU,
+        Op::I64LeS,
+        Op::I64LeU,
+        Op::I64GeS,
+        Op::I64GeU,
+        Op::F64Eq,
+        Op::F64Ne,
+        Op::F64Lt,
+        Op::F64Gt,
+        Op::F64Le,
+        Op::F64Ge,
+        Op::I32Clz,
+        Op::I32Add,
+        Op::I32Sub,
+        Op::I32Mul,
+        Op::I32DivS,
+        Op::I32DivU,
+        Op::I32RemS,
+        Op::I32RemU,
+        Op::I32And,
+        Op::I32Or,
+        Op::I32Xor,
+        Op::I32Shl,
+        Op::I32ShrS,
+        Op::I32ShrU,
+        Op::I64Clz,
+        Op::I64Add,
+        Op::I64Sub,
+        Op::I64Mul,
+        Op::I64DivS,
+        Op::I64DivU,
+        Op::I64RemS,
+        Op::I64RemU,
+        Op::I64And,
+        Op::I64Or,
+        Op::I64Xor,
+        Op::I64Shl,
+        Op::I64ShrS,
+        Op::I64ShrU,
+        Op::F64Abs,
+        Op::F64Neg,
+        Op::F64Ceil,
+        Op::F64Floor,
+        Op::F64Trunc,
+        Op::F64Nearest,
+        Op::F64Sqrt,
+        Op::F64Add,
+        Op::F64Sub,
+        Op::F64Mul,
+        Op::F64Div,
+        Op::F64Min,
+        Op::F64Max,
+        Op::I32WrapI64,
+        Op::I32TruncF64S,
+        Op::I64ExtendI32S,
+        Op::I64ExtendI32U,
+        Op::F64ConvertI32S,
+        Op::I64ReinterpretF64,
+        Op::F64ReinterpretI64,
+    ];
+
+    /// The opcode, the text name, the operand types and the result type of the instruction
+    pub fn info(&self) -> (u8, &'static str, &'static [ValType], ValType) {
+        match self {
+            Op::I32Eqz => (0x45, "i32.eqz", &[I32], I32),
+            Op::I32Eq => (0x46, "i32.eq", &[I32, I32], I32),
+            Op::I32Ne => (0x47, "i32.ne", &[I32, I32], I32),
+            Op::I32LtS => (0x48, "i32.lt_s", &[I32, I32], I32),
+            Op::I32LtU => (0x49, "i32.lt_u", &[I32, I32], I32),
+            Op::I32GtS => (0x4A, "i32.gt_s", &[I32, I32], I32),
+            Op::I32GtU => (0x4B, "i32.gt_u", &[I32, I32], I32),
+            Op::I32LeS => (0x4C, "i32.le_s", &[I32, I32], I32),
+            Op::I32LeU => (0x4D, "i32.le_u", &[I32, I32], I32),
+            Op::I32GeS => (0x4E, "i32.ge_s", &[I32, I32], I32),
+            Op::I32GeU => (0x4F, "i32.ge_u", &[I32, I32], I32),
+            Op::I64Eqz => (0x50, "i64.eqz", &[I64], I32),
+            Op::I64Eq => (0x51, "i64.eq", &[I64, I64], I32),
+            Op::I64Ne => (0x52, "i64.ne", &[I64, I64], I32),
+            Op::I64LtS => (0x53, "i64.lt_s", &[I64, I64], I32),
+            Op::I64LtU => (0x54, "i64.lt_u", &[I64, I64], I32),
+            Op::I64GtS => (0x55, "i64.gt_s", &[I64, I64], I32),
+            Op::I64GtU => (0x56, "i64.gt_u", &[I64, I64], I32),
+            Op::I64LeS => (0x57, "i64.le_s", &[I64, I64], I32),
+            Op::I64LeU => (0x58, "i64.le_u", &[I64, I64], I32),
+            Op::I64GeS => (0x59, "i64.ge_s", &[I64, I64], I32),
+            Op::I64GeU => (0x5A, "i64.ge_u", &[I64, I64], I32),
+            Op::F64Eq => (0x61, "f64.eq", &[F64, F64], I32),
+            Op::F64Ne => (0x62, "f64.ne", &[F64, F64], I32),
+            Op::F64Lt => (0x63, "f64.lt", &[F64, F64], I32),
+            Op::F64Gt => (0x64, "f64.gt", &[F64, F64], I32),
+            Op::F64Le => (0x65, "f64.le", &[F64, F64], I32),
+            Op::F64Ge => (0x66, "f64.ge", &[F64, F64], I32),
+            Op::I32Clz => (0x67, "i32.clz", &[I32], I32),
+            Op::I32Add => (0x6A, "i32.add", &[I32, I32], I32),
+            Op::I32Sub => (0x6B, "i32.sub", &[I32, I32], I32),
+            Op::I32Mul => (0x6C, "i32.mul", &[I32, I32], I32),
+            Op::I32DivS => (0x6D, "i32.div_s", &[I32, I32], I32),
+            Op::I32DivU => (0x6E, "i32.div_u", &[I32, I32], I32),
+            Op::I32RemS => (0x6F, "i32.rem_s", &[I32, I32], I32),
+            Op::I32RemU => (0x70, "i32.rem_u", &[I32, I32], I32),
+            Op::I32And => (0x71, "i32.and", &[I32, I32], I32),
+            Op::I32Or => (0x72, "i32.or", &[I32, I32], I32),
+            Op::I32Xor => (0x73, "i32.xor", &[I32, I32], I32),
+            Op::I32Shl => (0x74, "i32.shl", &[I32, I32], I32),
+            Op::I32ShrS => (0x75, "i32.shr_s", &[I32, I32], I32),
+            Op::I32ShrU => (0x76, "i32.shr_u", &[I32, I32], I32),
+            Op::I64Clz => (0x79, "i64.clz", &[I64], I64),
+            Op::I64Add => (0x7C, "i64.add", &[I64, I64], I64),
+            Op::I64Sub => (0x7D, "i64.sub", &[I64, I64], I64),
+            Op::I64Mul => (0x7E, "i64.mul", &[I64, I64], I64),
+            Op::I64DivS => (0x7F, "i64.div_s", &[I64, I64], I64),
+            Op::I64DivU => (0x80, "i64.div_u", &[I64, I64], I64),
+            Op::I64RemS => (0x81, "i64.rem_s", &[I64, I64], I64),
+            Op::I64RemU => (0x82, "i64.rem_u", &[I64, I64], I64),
+            Op::I64And => (0x83, "i64.and", &[I64, I64], I64),
+            Op::I64Or => (0x84, "i64.or", &[I64, I64], I64),
+            Op::I64Xor => (0x85, "i64.xor", &[I64, I64], I64),
+            Op::I64Shl => (0x86, "i64.shl", &[I64, I64], I64),
+            Op::I64ShrS => (0x87, "i64.shr_s", &[I64, I64], I64),
+            Op::I64ShrU => (0x88, "i64.shr_u", &[I64, I64], I64),
+            Op::F64Abs => (0x99, "f64.abs", &[F64], F64),
+            Op::F64Neg => (0x9A, "f64.neg", &[F64], F64),
+            Op::F64Ceil => (0x9B, "f64.ceil", &[F64], F64),
+            Op::F64Floor => (0x9C, "f64.floor", &[F64], F64),
+            Op::F64Trunc => (0x9D, "f64.trunc", &[F64], F64),
+            Op::F64Nearest => (0x9E, "f64.nearest", &[F64], F64),
+            Op::F64Sqrt => (0x9F, "f64.sqrt", &[F64], F64),
+            Op::F64Add => (0xA0, "f64.add", &[F64, F64], F64),
+            Op::F64Sub => (0xA1, "f64.sub", &[F64, F64], F64),
+            Op::F64Mul => (0xA2, "f64.mul", &[F64, F64], F64),
+            Op::F64Div => (0xA3, "f64.div", &[F64, F64], F64),
+            Op::F64Min => (0xA4, "f64.min", &[F64, F64], F64),
+            Op::F64Max => (0xA5, "f64.max", &[F64, F64], F64),
+            Op::I32WrapI64 => (0xA7, "i32.wrap_i64", &[I64], I32),
+            Op::I32TruncF64S => (0xAA, "i32.trunc_f64_s", &[F64], I32),
+            Op::I64ExtendI32S => (0xAC, "i64.extend_i32_s", &[I32], I64),
+            Op::I64ExtendI32U => (0xAD, "i64.extend_i32_u", &[I32], I64),
+            Op::F64ConvertI32S => (0xB7, "f64.convert_i32_s", &[I32], F64),
+            Op::I64ReinterpretF64 => (0xBD, "i64.reinterpret_f64", &[F64], I64),
+            Op::F64ReinterpretI64 => (0xBF, "f64.reinterpret_i64", &[I64], F64),
+        }
+    }
+
+    pub fn from_opcode(opcode: u8) -> Option<Op> {
+        Op::ALL.into_iter().find(|op| op.info().0 == opcode)
+    }
+}
+
+/// An instruction accessing linear memory, at an address popped from the stack plus an offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    I32Load,
+    I64Load,
+    F64Load,
+    I32Load8U,
+    I32Store,
+    I64Store,
+    F64Store,
+    I32Store8,
+}
+
+impl Access {
+    pub const ALL: [Access; 8] = [
+        Access::I32Load,
+        Access::I64Load,
+        Access::F64Load,
+        Access::I32Load8U,
+        Access::I32Store,
+        Access::I64Store,
+        Access::F64Store,
+        Access::I32Store8,
+    ];
+
+    /// The opcode, the text name, the natural alignment (as a power of two), the type of the
+    /// value loaded or stored, and whether it is a store
+    pub fn info(&self) -> (u8, &'static str, u32, ValType, bool) {
+        match self {
+            Access::I32Load => (0x28, "i32.load", 2, I32, false),
+            Access::I64Load => (0x29, "i64.load", 3, I64, false),
+            Access::F64Load => (0x2B, "f64.load", 3, F64, false),
+            Access::I32Load8U => (0x2D, "i32.load8_u", 0, I32, false),
+            Access::I32Store => (0x36, "i32.store", 2, I32, true),
+            Access::I64Store => (0x37, "i64.store", 3, I64, true),
+            Access::F64Store => (0x39, "f64.store", 3, F64, true),
+            Access::I32Store8 => (0x3A, "i32.store8", 0, I32, true),
+        }
+    }
+
+    pub fn from_opcode(opcode: u8) -> Option<Access> {
+        Access::ALL
+            .into_iter()
+            .find(|access| access.info().0 == opcode)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    Unreachable,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    /// Branches to the label `n` blocks out
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    /// Calls a function of the table, whose index is on top of the stack, with the given type
+    CallIndirect(u32),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    /// A memory access at the given offset
+    Memory(Access, u32),
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    F64Const(f64),
+    Numeric(Op),
+}
+
+impl Instr {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            Instr::Unreachable => out.push(0x00),
+            Instr::Block(ty) => {
+                out.push(0x02);
+                encode_block_type(ty, out);
+            }
+            Instr::Loop(ty) => {
+                out.push(0x03);
+                encode_block_type(ty, out);
+            }
+            Instr::If(ty) => {
+                out.push(0x04);
+                encode_block_type(ty, out);
+            }
+            Instr::Else => out.push(0x05),
+            Instr::End => out.push(0x0B),
+            Instr::Br(depth) => {
+                out.push(0x0C);
+                unsigned(depth as u64, out);
+            }
+            Instr::BrIf(depth) => {
+                out.push(0x0D);
+                unsigned(depth as u64, out);
+            }
+            Instr::Return => out.push(0x0F),
+            Instr::Call(function) => {
+                out.push(0x10);
+                unsigned(function as u64, out);
+            }
+            Instr::CallIndirect(ty) => {
+                out.push(0x11);
+                unsigned(ty as u64, out);
+                out.push(0x00);
+            }
+            Instr::Drop => out.push(0x1A),
+            Instr::Select => out.push(0x1B),
+            Instr::LocalGet(index) => {
+                out.push(0x20);
+                unsigned(index as u64, out);
+            }
+            Instr::LocalSet(index) => {
+                out.push(0x21);
+                unsigned(index as u64, out);
+            }
+            Instr::LocalTee(index) => {
+                out.push(0x22);
+                unsigned(index as u64, out);
+            }
+            Instr::GlobalGet(index) => {
+                out.push(0x23);
+                unsigned(index as u64, out);
+            }
+            Instr::GlobalSet(index) => {
+                out.push(0x24);
+                unsigned(index as u64, out);
+            }
+            Instr::Memory(access, offset) => {
+                let (opcode, _, align, _, _) = access.info();
+                out.push(opcode);
+                unsigned(align as u64, out);
+                unsigned(offset as u64, out);
+            }
+            Instr::MemorySize => out.extend([0x3F, 0x00]),
+            Instr::MemoryGrow => out.extend([0x40, 0x00]),
+            Instr::I32Const(value) => {
+                out.push(0x41);
+                signed(value as i64, out);
+            }
+            Instr::I64Const(value) => {
+                out.push(0x42);
+                signed(value, out);
+            }
+            Instr::F64Const(value) => {
+                out.push(0x44);
+                out.extend(value.to_le_bytes());
+            }
+            Instr::Numeric(op) => out.push(op.info().0),
+        }
+    }
+}
+
+/// Writes an instruction in the text format
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let block = |name: &str, ty: &BlockType| match ty {
+            BlockType::Empty => name.to_string(),
+            BlockType::Value(ty) => format!("{} (result {})", name, ty.name()),
+        };
+        match self {
+            Instr::Unreachable => write!(f, "unreachable"),
+            Instr::Block(ty) => write!(f, "{}", block("block", ty)),
+            Instr::Loop(ty) => write!(f, "{}", block("loop", ty)),
+            Instr::If(ty) => write!(f, "{}", block("if", ty)),
+            Instr::Else => write!(f, "else"),
+            Instr::End => write!(f, "end"),
+            Instr::Br(depth) => write!(f, "br {}", depth),
+            Instr::BrIf(depth) => write!(f, "br_if {}", depth),
+            Instr::Return => write!(f, "return"),
+            Instr::Call(function) => write!(f, "call {}", function),
+            Instr::CallIndirect(ty) => write!(f, "call_indirect (type {})", ty),
+            Instr::Drop => write!(f, "drop"),
+            Instr::Select => write!(f, "select"),
+            Instr::LocalGet(index) => write!(f, "local.get {}", index),
+            Instr::LocalSet(index) => write!(f, "local.set {}", index),
+            Instr::LocalTee(index) => write!(f, "local.tee {}", index),
+            Instr::GlobalGet(index) => write!(f, "global.get {}", index),
+            Instr::GlobalSet(index) => write!(f, "global.set {}", index),
+            Instr::Memory(access, 0) => write!(f, "{}", access.info().1),
+            Instr::Memory(access, offset) => write!(f, "{} offset={}", access.info().1, offset),
+            Instr::MemorySize => write!(f, "memory.size"),
+            Instr::MemoryGrow => write!(f, "memory.grow"),
+            Instr::I32Const(value) => write!(f, "i32.const {}", value),
+            Instr::I64Const(value) => write!(f, "i64.const {}", value),
+            // Hexadecimal floats keep every bit of the value, including NaN payloads
+            Instr::F64Const(value) => write!(f, "f64.const {}", hex_float(*value)),
+            Instr::Numeric(op) => write!(f, "{}", op.info().1),
+        }
+    }
+}
+
+/// A function defined in the module
+/// - `locals` are the types of its local variables, after its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub ty: u32,
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub ty: ValType,
+    pub mutable: bool,
+    pub init: Instr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    Function,
+    Memory,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+    pub index: u32,
+}
+
+/// A data segment, copied into memory at `offset` when the module is instantiated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Data {
+    pub offset: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// A module. Imported functions come first in the function index space, then `functions`.
+/// - `table` lists the functions of the table, from index 0, for indirect calls
+/// - `memory` is the initial size of the memory, in 64 KiB pages
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub types: Vec<FuncType>,
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    pub table: Vec<u32>,
+    pub memory: u32,
+    pub globals: Vec<Global>,
+    pub exports: Vec<Export>,
+    pub data: Vec<Data>,
+}
+
+impl Module {
+    /// The index of a function type, adding it to the module if it is new
+    pub fn type_index(&mut self, params: &[ValType], results: &[ValType]) -> u32 {
+        let ty = FuncType {
+            params: params.to_vec(),
+            results: results.to_vec(),
+        };
+        match self.types.iter().position(|other| *other == ty) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    /// The binary encoding of the module
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend(VERSION.to_le_bytes());
+
+        section(&mut out, TYPE_SECTION, &self.types, |ty, out| {
+            out.push(FUNC_TYPE);
+            for list in [&ty.params, &ty.results] {
+                unsigned(list.len() as u64, out);
+                out.extend(list.iter().map(ValType::code));
+            }
+        });
+        section(&mut out, IMPORT_SECTION, &self.imports, |import, out| {
+            name(&import.module, out);
+            name(&import.name, out);
+            out.push(0x00);
+            unsigned(import.ty as u64, out);
+        });
+        section(
+            &mut out,
+            FUNCTION_SECTION,
+            &self.functions,
+            |function, out| unsigned(function.ty as u64, out),
+        );
+        section(&mut out, TABLE_SECTION, &[self.table.len()], |size, out| {
+            out.extend([FUNCREF, 0x00]);
+            unsigned(*size as u64, out);
+        });
+        section(&mut out, MEMORY_SECTION, &[self.memory], |pages, out| {
+            out.push(0x00);
+            unsigned(*pages as u64, out);
+        });
+        section(&mut out, GLOBAL_SECTION, &self.globals, |global, out| {
+            out.push(global.ty.code());
+            out.push(global.mutable as u8);
+            global.init.encode(out);
+            Instr::End.encode(out);
+        });
+        section(&mut out, EXPORT_SECTION, &self.exports, |export, out| {
+            name(&export.name, out);
+            out.push(match export.kind {
+                ExportKind::Function => 0x00,
+                ExportKind::Memory => 0x02,
+            });
+            unsigned(export.index as u64, out);
+        });
+        section(&mut out, ELEMENT_SECTION, &[&self.table], |table, out| {
+            out.push(0x00);
+            Instr::I32Const(0).encode(out);
+            Instr::End.encode(out);
+            unsigned(table.len() as u64, out);
+            for function in table.iter() {
+                unsigned(*function as u64, out);
+            }
+        });
+        section(&mut out, CODE_SECTION, &self.functions, |function, out| {
+            let mut code = Vec::new();
+            // Runs of locals of the same type are declared together
+            let mut runs: Vec<(u32, ValType)> = Vec::new();
+            for ty in &function.locals {
+                match runs.last_mut() {
+                    Some((count, last)) if last == ty => *count += 1,
+                    _ => runs.push((1, *ty)),
+                }
+            }
+            unsigned(runs.len() as u64, &mut code);
+            for (count, ty) in runs {
+                unsigned(count as u64, &mut code);
+                code.push(ty.code());
+            }
+            for instr in &function.body {
+                instr.encode(&mut code);
+            }
+            Instr::End.encode(&mut code);
+            unsigned(code.len() as u64, out);
+            out.extend(code);
+        });
+        section(&mut out, DATA_SECTION, &self.data, |data, out| {
+            out.push(0x00);
+            Instr::I32Const(data.offset as i32).encode(out);
+            Instr::End.encode(out);
+            unsigned(data.bytes.len() as u64, out);
+            out.extend(&data.bytes);
+        });
+        out
+    }
+}
+
+/// Writes the module in the text format, one instruction per line
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let signature = |ty: &FuncType| {
+            let mut text = String::new();
+            for (keyword, list) in [("param", &ty.params), ("result", &ty.results)] {
+                if !list.is_empty() {
+                    let names: Vec<&str> = list.iter().map(ValType::name).collect();
+                    text.push_str(&format!(" ({} {})", keyword, names.join(" ")));
+                }
+            }
+            text
+        };
+        writeln!(f, "(module")?;
+        for (index, ty) in self.types.iter().enumerate() {
+            writeln!(f, "  (type (;{};) (func{}))", index, signature(ty))?;
+        }
+        for (index, import) in self.imports.iter().enumerate() {
+            writeln!(
+                f,
+                "  (import {} {} (func (;{};) (type {})))",
+                wat_string(import.module.as_bytes()),
+                wat_string(import.name.as_bytes()),
+                index,
+                import.ty
+            )?;
+        }
+        let imported = self.imports.len();
+        for (index, function) in self.functions.iter().enumerate() {
+            let ty = &self.types[function.ty as usize];
+            writeln!(
+                f,
+                "  (func (;{};) (type {}){} ;; {}",
+                imported + index,
+                function.ty,
+                signature(ty),
+                function.name
+            )?;
+            if !function.locals.is_empty() {
+                let names: Vec<&str> = function.locals.iter().map(ValType::name).collect();
+                writeln!(f, "    (local {})", names.join(" "))?;
+            }
+            let mut depth = 2;
+            for instr in &function.body {
+                if matches!(instr, Instr::End | Instr::Else) {
+                    depth -= 1;
+                }
+                writeln!(f, "{:width$}{}", "", instr, width = 2 * depth)?;
+                if matches!(
+                    instr,
+                    Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        writeln!(f, "  (table (;0;) {} funcref)", self.table.len())?;
+        writeln!(f, "  (memory (;0;) {})", self.memory)?;
+        for (index, global) in self.globals.iter().enumerate() {
+            let ty = match global.mutable {
+                true => format!("(mut {})", global.ty.name()),
+                false => global.ty.name().to_string(),
+            };
+            writeln!(f, "  (global (;{};) {} ({}))", index, ty, global.init)?;
+        }
+        for export in &self.exports {
+            let kind = match export.kind {
+                ExportKind::Function => "func",
+                ExportKind::Memory => "memory",
+            };
+            writeln!(
+                f,
+                "  (export {} ({} {}))",
+                wat_string(export.name.as_bytes()),
+                kind,
+                export.index
+            )?;
+        }
+        let functions: Vec<String> = self.table.iter().map(u32::to_string).collect();
+        writeln!(
+            f,
+            "  (elem (;0;) (i32.const 0) func {})",
+            functions.join(" ")
+        )?;
+        for (index, data) in self.data.iter().enumerate() {
+            writeln!(
+                f,
+                "  (data (;{};) (i32.const {}) {})",
+                index,
+                data.offset,
+                wat_string(&data.bytes)
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Appends a section with one entry per item, unless there are none
+fn section<T>(out: &mut Vec<u8>, id: u8, items: &[T], mut entry: impl FnMut(&T, &mut Vec<u8>)) {
+    if items.is_empty() {
+        return;
+    }
+    let mut contents = Vec::new();
+    unsigned(items.len() as u64, &mut contents);
+    for item in items {
+        entry(item, &mut contents);
+    }
+    out.push(id);
+    unsigned(contents.len() as u64, out);
+    out.extend(contents);
+}
+
+fn encode_block_type(ty: BlockType, out: &mut Vec<u8>) {
+    match ty {
+        BlockType::Empty => out.push(EMPTY_BLOCK),
+        BlockType::Value(ty) => out.push(ty.code()),
+    }
+}
+
+fn name(name: &str, out: &mut Vec<u8>) {
+    unsigned(name.len() as u64, out);
+    out.extend(name.as_bytes());
+}
+
+/// Appends `value` in unsigned LEB128
+pub fn unsigned(mut value: u64, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Appends `value` in signed LEB128
+pub fn signed(mut value: i64, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// A string of the text format, escaping everything but printable ASCII
+fn wat_string(bytes: &[u8]) -> String {
+    let mut literal = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:02x}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// A float in the hexadecimal notation of the text format, e.g. `0x1.8p+1` for 3
+fn hex_float(value: f64) -> String {
+    let bits = value.to_bits();
+    let sign = if bits >> 63 == 1 { "-" } else { "" };
+    let exponent = ((bits >> 52) & 0x7FF) as i64;
+    let mantissa = bits & ((1 << 52) - 1);
+    match exponent {
+        0x7FF if mantissa == 0 => format!("{}inf", sign),
+        0x7FF => format!("{}nan:0x{:x}", sign, mantissa),
+        0 if mantissa == 0 => format!("{}0x0p+0", sign),
+        0 => format!("{}0x0.{:013x}p-1022", sign, mantissa),
+        _ => format!("{}0x1.{:013x}p{:+}", sign, mantissa, exponent - 1023),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leb128() {
+        let encode = |value: i64| {
+            let mut out = Vec::new();
+            signed(value, &mut out);
+            out
+        };
+        assert_eq!(encode(0), [0x00]);
+        assert_eq!(encode(63), [0x3F]);
+        assert_eq!(encode(64), [0xC0, 0x00]);
+        assert_eq!(encode(-1), [0x7F]);
+        assert_eq!(encode(-65), [0xBF, 0x7F]);
+        let mut out = Vec::new();
+        unsigned(624485, &mut out);
+        assert_eq!(out, [0xE5, 0x8E, 0x26]);
+    }
+
+    #[test]
+    fn test_text_format() {
+        assert_eq!(hex_float(3.0), "0x1.8000000000000p+1");
+        assert_eq!(hex_float(-0.0), "-0x0p+0");
+        assert_eq!(hex_float(f64::INFINITY), "inf");
+        assert_eq!(
+            Instr::Memory(Access::I64Load, 8).to_string(),
+            "i64.load offset=8"
+        );
+        assert_eq!(
+            Instr::If(BlockType::Value(ValType::I64)).to_string(),
+            "if (result i64)"
+        );
+        assert_eq!(wat_string("é\"".as_bytes()), "\"\\c3\\a9\\\"\"");
+    }
+}
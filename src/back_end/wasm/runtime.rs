@@ -0,0 +1,2941 @@
+//! The runtime of generated modules, written directly as WebAssembly functions: a first-fit
+//! allocator with a mark-and-sweep collector that stops at `HEAP_LIMIT`, buffered printing, reading
+//! lines, converting values to strings and concatenating them, the conversions of `int()`,
+//! `float()` and `char()`, panics, equality, checked integer arithmetic including `abs()` and
+//! `pow()`, the call depth counter, `fmod`, the file built-ins and the characters of strings for
+//! `for` loops.
+//! Functions whose failure panics take the panic's site as their last argument.
+use crate::back_end::wasm::module::Access::*;
+use crate::back_end::wasm::module::BlockType::Empty;
+use crate::back_end::wasm::module::Instr::*;
+use crate::back_end::wasm::module::Op::*;
+use crate::back_end::wasm::module::{BlockType, Function, Import, Instr, Module, ValType};
+use crate::back_end::wasm::{
+    Statics, ALLOCATED, BOOL, CAPTURE, CELL, CHAR, CLOSURE, COUNT, DEPTH, ERR, FILE, FLOAT_TEXT,
+    FREE_LIST, GRAY, HEAP, HEAP_START, INT, MIN_HEAP, NULL, OBJECT, OK, OUTPUT, OUTPUT_LENGTH,
+    OUTPUT_SIZE, RANGE, ROOTS, ROOTS_START, SCRATCH, SCRATCH_END, STRESS, STRING, THRESHOLD, UNIT,
+};
+use crate::back_end::HEAP_LIMIT;
+use crate::front_end::format::MAX_WIDTH;
+use crate::runtime::vm::MAX_CALL_DEPTH;
+
+const I32: ValType = ValType::I32;
+const I64: ValType = ValType::I64;
+const F64: ValType = ValType::F64;
+
+/// The top 16 bits of a tag, which `write_value` and `equal` compare values' tops with
+fn top(tag: i64) -> i32 {
+    (tag as u64 >> 48) as i32
+}
+
+const MEMORY: i32 = 0;
+const STDOUT: i32 = 1;
+const STDERR: i32 = 2;
+const PANIC_EXIT_CODE: i32 = 101;
+
+/// The reasons that operations on files fail with, as the negative codes that the host and the
+/// runtime return. The host reports the first four, and any other code it returns is an I/O error.
+const REASONS: [(i32, &str); 8] = [
+    (-2, "no such file or directory"),
+    (-3, "permission denied"),
+    (-4, "is a directory"),
+    (-5, "input/output error"),
+    (-6, "the file is closed"),
+    (-7, "the file is not open for reading"),
+    (-8, "the file is not open for writing"),
+    (-9, "invalid mode"),
+];
+const IO_ERROR: i32 = -5;
+const CLOSED: i32 = -6;
+const NOT_READABLE: i32 = -7;
+const NOT_WRITABLE: i32 = -8;
+const INVALID_MODE: i32 = -9;
+
+/// The state of a block of the heap, in the word after its size in its 8-byte header: allocated (0),
+/// marked while collecting, or free, when the word after the header is the next free block
+const MARKED: i32 = 1;
+const FREE: i32 = 2;
+
+/// The byte that stress mode fills freed blocks with
+const POISON: i64 = 0xABAB_ABAB_ABAB_ABABu64 as i64;
+
+/// The state of a file object: closed, or open for reading or writing
+const OPEN_FOR_READING: i32 = 1;
+const OPEN_FOR_WRITING: i32 = 2;
+
+/// The indices of the runtime's functions, starting with the imported ones
+#[derive(Clone, Copy)]
+pub struct Runtime {
+    /// `write(stream, pointer, length)` writes bytes to stdout (1) or stderr (2)
+    pub host_write: u32,
+    /// `format_float(value, precision, pointer) -> length` writes the text of a float at `pointer`
+    pub format_float: u32,
+    /// `read_line(pointer, capacity) -> length` reads a line of stdin
+    pub read_line: u32,
+    /// `exit(code)` stops the program
+    pub exit: u32,
+    /// `parse_float(pointer, length) -> value` reads a float that `is_number` accepted
+    pub parse_float: u32,
+    /// `sin(x)`, `cos(x)`, `log10(x)`, `log(x)`, `exp(x)` and `pow(x, y)` compute like the
+    /// functions of the C math library
+    pub sin: u32,
+    pub cos: u32,
+    pub log10: u32,
+    pub log: u32,
+    pub exp: u32,
+    pub pow: u32,
+    /// `open(path, length, mode) -> handle` opens a file in the mode whose letter is `mode`
+    pub host_open: u32,
+    /// `read_file(handle, line, pointer, capacity) -> length` reads the next line or the rest of a
+    /// file, like `read_line` reads stdin
+    pub host_read: u32,
+    /// `write_file(handle, pointer, length) -> status` writes bytes to a file
+    pub host_write_file: u32,
+    /// `close(handle)` closes a file
+    pub host_close: u32,
+    pub flush: u32,
+    /// `copy(destination, source, length)` copies bytes within memory
+    pub copy: u32,
+    /// `write(stream, pointer, length)` like the host's, through the buffer of stdout, or to the
+    /// memory at `CAPTURE` for stream 0
+    pub write: u32,
+    /// `alloc(size) -> pointer` allocates an object, collecting the heap first when it has grown
+    pub alloc: u32,
+    /// `mark(value)` marks the object a value holds, if it is on the heap, and pushes it to trace
+    pub mark: u32,
+    /// `collect()` frees the objects that the shadow stack does not reach
+    pub collect: u32,
+    /// `stress()`, which the host calls before `main`, makes every allocation collect
+    pub stress: u32,
+    pub write_int: u32,
+    pub write_char: u32,
+    pub write_float: u32,
+    pub write_value: u32,
+    pub println: u32,
+    pub println_empty: u32,
+    pub print: u32,
+    pub print_padded: u32,
+    pub input: u32,
+    pub to_string: u32,
+    pub concat: u32,
+    pub is_number: u32,
+    pub parse_error: u32,
+    pub to_int: u32,
+    pub to_float: u32,
+    pub to_char: u32,
+    pub panic_begin: u32,
+    pub panic: u32,
+    pub panic_value: u32,
+    pub unwrap: u32,
+    pub wrap: u32,
+    pub has_kind: u32,
+    pub range: u32,
+    pub equal: u32,
+    pub enter: u32,
+    pub leave: u32,
+    pub add: u32,
+    pub subtract: u32,
+    pub multiply: u32,
+    pub divide: u32,
+    pub remainder: u32,
+    pub shift_left: u32,
+    pub shift_right: u32,
+    pub negate: u32,
+    pub abs: u32,
+    /// `pow_int(base, exponent, site)` is `pow()` of integers
+    pub pow_int: u32,
+    pub fmod: u32,
+    /// `file_reason(code) -> string` is the reason an operation on a file failed with `code`
+    pub file_reason: u32,
+    pub open: u32,
+    pub close: u32,
+    /// `read(file, line) -> result` reads the next line of a file, or the rest of it if `line` is 0
+    pub read: u32,
+    pub write_file: u32,
+    /// `next_line(file)` is the next line of a file, or null at its end or if reading it fails
+    pub next_line: u32,
+    /// `utf8_width(string, offset) -> i32` is the number of bytes of the UTF-8 sequence starting
+    /// at byte `offset` of a string, or 0 once the offset is at its end
+    pub utf8_width: u32,
+    pub char_width: u32,
+    /// `char_at(string, offset)` is the character starting at byte `offset` of a string
+    pub char_at: u32,
+}
+
+impl Runtime {
+    /// Adds the runtime's imports and functions to an empty module
+    pub fn add(module: &mut Module, statics: &mut Statics) -> Runtime {
+        let mut next = 0;
+        let mut index = || {
+            next += 1;
+            next - 1
+        };
+        let runtime = Runtime {
+            host_write: index(),
+            format_float: index(),
+            read_line: index(),
+            exit: index(),
+            parse_float: index(),
+            sin: index(),
+            cos: index(),
+            log10: index(),
+            log: index(),
+            exp: index(),
+            pow: index(),
+            host_open: index(),
+            host_read: index(),
+            host_write_file: index(),
+            host_close: index(),
+            flush: index(),
+            copy: index(),
+            write: index(),
+            alloc: index(),
+            mark: index(),
+            collect: index(),
+            stress: index(),
+            write_int: index(),
+            write_char: index(),
+            write_float: index(),
+            write_value: index(),
+            println: index(),
+            println_empty: index(),
+            print: index(),
+            print_padded: index(),
+            input: index(),
+            to_string: index(),
+            concat: index(),
+            is_number: index(),
+            parse_error: index(),
+            to_int: index(),
+            to_float: index(),
+            to_char: index(),
+            panic_begin: index(),
+            panic: index(),
+            panic_value: index(),
+            unwrap: index(),
+            wrap: index(),
+            has_kind: index(),
+            range: index(),
+            equal: index(),
+            enter: index(),
+            leave: index(),
+            add: index(),
+            subtract: index(),
+            multiply: index(),
+            divide: index(),
+            remainder: index(),
+            shift_left: index(),
+            shift_right: index(),
+            negate: index(),
+            abs: index(),
+            pow_int: index(),
+            fmod: index(),
+            file_reason: index(),
+            open: index(),
+            close: index(),
+            read: index(),
+            write_file: index(),
+            next_line: index(),
+            utf8_width: index(),
+            char_width: index(),
+            char_at: index(),
+        };
+        let mut builder = Builder {
+            module,
+            statics,
+            runtime: &runtime,
+        };
+        builder.build();
+        runtime
+    }
+}
+
+struct Builder<'a> {
+    module: &'a mut Module,
+    statics: &'a mut Statics,
+    runtime: &'a Runtime,
+}
+
+impl Builder<'_> {
+    fn import(&mut self, index: u32, name: &str, signature: (&[ValType], &[ValType])) {
+        assert_eq!(self.module.imports.len() as u32, index);
+        let (params, results) = signature;
+        let ty = self.module.type_index(params, results);
+        self.module.imports.push(Import {
+            module: "crawfish".to_string(),
+            name: name.to_string(),
+            ty,
+        });
+    }
+
+    /// Defines the function at `index`, which must be the next one
+    fn define(
+        &mut self,
+        index: u32,
+        name: &str,
+        signature: (&[ValType], &[ValType]),
+        locals: &[ValType],
+        body: Vec<Instr>,
+    ) {
+        let (params, results) = signature;
+        assert_eq!(
+            (self.module.imports.len() + self.module.functions.len()) as u32,
+            index
+        );
+        let ty = self.module.type_index(params, results);
+        self.module.functions.push(Function {
+            name: name.to_string(),
+            ty,
+            locals: locals.to_vec(),
+            body,
+        });
+    }
+
+    /// Writes constant text to `stream`
+    fn text(&mut self, stream: Instr, text: &str) -> Vec<Instr> {
+        let (pointer, len) = self.statics.text(text);
+        vec![
+            stream,
+            I32Const(pointer as i32),
+            I32Const(len as i32),
+            Call(self.runtime.write),
+        ]
+    }
+
+    /// The pointer and length of one of two texts, chosen by `condition`
+    fn choose(&mut self, then: &str, otherwise: &str, condition: &[Instr]) -> Vec<Instr> {
+        let (a, a_len) = self.statics.text(then);
+        let (b, b_len) = self.statics.text(otherwise);
+        let mut code = vec![I32Const(a as i32), I32Const(b as i32)];
+        code.extend_from_slice(condition);
+        code.extend([Select, I32Const(a_len as i32), I32Const(b_len as i32)]);
+        code.extend_from_slice(condition);
+        code.push(Select);
+        code
+    }
+
+    /// Panics with `message` at the site held in local `site`
+    fn fail(&mut self, site: u32, message: &str) -> Vec<Instr> {
+        let (pointer, len) = self.statics.text(message);
+        vec![
+            I32Const(pointer as i32),
+            I32Const(len as i32),
+            LocalGet(site),
+            Call(self.runtime.panic),
+        ]
+    }
+
+    /// Panics on converting the value in local `value` to the type `to`, at the site in local `site`
+    fn conversion_failed(&mut self, value: u32, site: u32, to: &str) -> Vec<Instr> {
+        let mut code = vec![LocalGet(site), Call(self.runtime.panic_begin)];
+        code.extend(self.text(I32Const(STDERR), "cannot convert "));
+        code.extend([
+            I32Const(STDERR),
+            LocalGet(value),
+            Call(self.runtime.write_value),
+        ]);
+        code.extend(self.text(I32Const(STDERR), &format!(" to {}", to)));
+        code.extend(self.finish_panic());
+        code
+    }
+
+    /// Returns the `Err` of parsing the string in local `string` as the type `to`
+    fn parse_failed(&mut self, string: u32, to: &str) -> Vec<Instr> {
+        let (suffix, len) = self.statics.text(&format!("\" as {}", to));
+        vec![
+            LocalGet(string),
+            I32Const(suffix as i32),
+            I32Const(len as i32),
+            Call(self.runtime.parse_error),
+            Return,
+        ]
+    }
+
+    /// Calls `alloc` with the size on top of the stack, keeping the values in the `i64` locals
+    /// `values` on the shadow stack meanwhile, since they may be objects that nothing roots yet
+    fn rooted_alloc(&self, values: &[u32]) -> Vec<Instr> {
+        let mut code = Vec::new();
+        for (n, &value) in values.iter().enumerate() {
+            code.extend([
+                GlobalGet(ROOTS),
+                LocalGet(value),
+                Memory(I64Store, 8 * n as u32),
+            ]);
+        }
+        let size = 8 * values.len() as i32;
+        code.extend([
+            GlobalGet(ROOTS),
+            I32Const(size),
+            Numeric(I32Add),
+            GlobalSet(ROOTS),
+            Call(self.runtime.alloc),
+            GlobalGet(ROOTS),
+            I32Const(size),
+            Numeric(I32Sub),
+            GlobalSet(ROOTS),
+        ]);
+        code
+    }
+
+    /// Ends a panic: a newline after the message, and the exit
+    fn finish_panic(&mut self) -> Vec<Instr> {
+        let mut code = self.text(I32Const(STDERR), "\n");
+        code.extend([
+            I32Const(PANIC_EXIT_CODE),
+            Call(self.runtime.exit),
+            Unreachable,
+        ]);
+        code
+    }
+
+    fn build(&mut self) {
+        let r = self.runtime;
+        self.import(r.host_write, "write", (&[I32, I32, I32], &[]));
+        self.import(r.format_float, "format_float", (&[F64, I32, I32], &[I32]));
+        self.import(r.read_line, "read_line", (&[I32, I32], &[I32]));
+        self.import(r.exit, "exit", (&[I32], &[]));
+        self.import(r.parse_float, "parse_float", (&[I32, I32], &[F64]));
+        for (index, name) in [
+            (r.sin, "sin"),
+            (r.cos, "cos"),
+            (r.log10, "log10"),
+            (r.log, "log"),
+            (r.exp, "exp"),
+        ] {
+            self.import(index, name, (&[F64], &[F64]));
+        }
+        self.import(r.pow, "pow", (&[F64, F64], &[F64]));
+        self.import(r.host_open, "open", (&[I32, I32, I32], &[I32]));
+        self.import(r.host_read, "read_file", (&[I32, I32, I32, I32], &[I32]));
+        self.import(r.host_write_file, "write_file", (&[I32, I32, I32], &[I32]));
+        self.import(r.host_close, "close", (&[I32], &[]));
+
+        // flush() writes the buffered output to stdout
+        let body = vec![
+            GlobalGet(OUTPUT_LENGTH),
+            If(Empty),
+            I32Const(STDOUT),
+            I32Const(OUTPUT as i32),
+            GlobalGet(OUTPUT_LENGTH),
+            Call(r.host_write),
+            I32Const(0),
+            GlobalSet(OUTPUT_LENGTH),
+            End,
+        ];
+        self.define(r.flush, "flush", (&[], &[]), &[], body);
+
+        // copy(destination, source, length)
+        let body = vec![
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(3),
+            LocalGet(2),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(0),
+            LocalGet(3),
+            Numeric(I32Add),
+            LocalGet(1),
+            LocalGet(3),
+            Numeric(I32Add),
+            Memory(I32Load8U, 0),
+            Memory(I32Store8, 0),
+            LocalGet(3),
+            I32Const(1),
+            Numeric(I32Add),
+            LocalSet(3),
+            Br(0),
+            End,
+            End,
+        ];
+        self.define(r.copy, "copy", (&[I32, I32, I32], &[]), &[I32], body);
+
+        // write(stream, pointer, length) buffers the output to stdout, flushing it before writing to
+        // stderr so that both keep their order, and copies the output to memory to `CAPTURE`. While
+        // `COUNT` is not negative, the bytes are not written but counted: as bytes for memory, and
+        // otherwise as characters, which are the bytes that do not continue UTF-8.
+        let body = vec![
+            GlobalGet(COUNT),
+            I32Const(0),
+            Numeric(I32GeS),
+            If(Empty),
+            LocalGet(0),
+            I32Const(MEMORY),
+            Numeric(I32Eq),
+            If(Empty),
+            GlobalGet(COUNT),
+            LocalGet(2),
+            Numeric(I32Add),
+            GlobalSet(COUNT),
+            Return,
+            End,
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(2),
+            Numeric(I32Eqz),
+            BrIf(1),
+            LocalGet(2),
+            I32Const(1),
+            Numeric(I32Sub),
+            LocalSet(2),
+            GlobalGet(COUNT),
+            LocalGet(1),
+            LocalGet(2),
+            Numeric(I32Add),
+            Memory(I32Load8U, 0),
+            I32Const(0xC0),
+            Numeric(I32And),
+            I32Const(0x80),
+            Numeric(I32Ne),
+            Numeric(I32Add),
+            GlobalSet(COUNT),
+            Br(0),
+            End,
+            End,
+            Return,
+            End,
+            LocalGet(0),
+            I32Const(MEMORY),
+            Numeric(I32Eq),
+            If(Empty),
+            GlobalGet(CAPTURE),
+            LocalGet(1),
+            LocalGet(2),
+            Call(r.copy),
+            GlobalGet(CAPTURE),
+            LocalGet(2),
+            Numeric(I32Add),
+            GlobalSet(CAPTURE),
+            Return,
+            End,
+            LocalGet(0),
+            I32Const(STDOUT),
+            Numeric(I32Ne),
+            If(Empty),
+            Call(r.flush),
+            LocalGet(0),
+            LocalGet(1),
+            LocalGet(2),
+            Call(r.host_write),
+            Return,
+            End,
+            GlobalGet(OUTPUT_LENGTH),
+            LocalGet(2),
+            Numeric(I32Add),
+            I32Const(OUTPUT_SIZE as i32),
+            Numeric(I32GtU),
+            If(Empty),
+            Call(r.flush),
+            LocalGet(2),
+            I32Const(OUTPUT_SIZE as i32),
+            Numeric(I32GtU),
+            If(Empty),
+            I32Const(STDOUT),
+            LocalGet(1),
+            LocalGet(2),
+            Call(r.host_write),
+            Return,
+            End,
+            End,
+            GlobalGet(OUTPUT_LENGTH),
+            I32Const(OUTPUT as i32),
+            Numeric(I32Add),
+            LocalGet(1),
+            LocalGet(2),
+            Call(r.copy),
+            GlobalGet(OUTPUT_LENGTH),
+            LocalGet(2),
+            Numeric(I32Add),
+            GlobalSet(OUTPUT_LENGTH),
+        ];
+        self.define(r.write, "write", (&[I32, I32, I32], &[]), &[], body);
+
+        // alloc(size) -> pointer. Blocks of the heap hold their size and state before the object.
+        // The first free block that is large enough is taken, or its end if the rest of it can hold
+        // another block; otherwise the heap grows. Locals: 1 is the size of the block, 2 the
+        // address of the link to the free block in 3, and 4 the bytes the free block has left.
+        let mut out_of_memory = self.text(I32Const(STDERR), "panicked: out of memory");
+        out_of_memory.extend(self.finish_panic());
+        let mut body = vec![
+            LocalGet(0),
+            I32Const(15),
+            Numeric(I32Add),
+            I32Const(-8),
+            Numeric(I32And),
+            LocalSet(1),
+            GlobalGet(STRESS),
+            GlobalGet(ALLOCATED),
+            LocalGet(1),
+            Numeric(I32Add),
+            GlobalGet(THRESHOLD),
+            Numeric(I32GtU),
+            Numeric(I32Or),
+            If(Empty),
+            Call(r.collect),
+            End,
+            Block(Empty),
+            LocalGet(1),
+            I32Const(HEAP_LIMIT as i32),
+            Numeric(I32LeU),
+            GlobalGet(ALLOCATED),
+            LocalGet(1),
+            Numeric(I32Add),
+            I32Const(HEAP_LIMIT as i32),
+            Numeric(I32LeU),
+            Numeric(I32And),
+            BrIf(0),
+        ];
+        body.extend(out_of_memory.clone());
+        body.extend([
+            End,
+            I32Const(FREE_LIST as i32),
+            LocalSet(2),
+            Block(Empty),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(2),
+            Memory(I32Load, 0),
+            LocalTee(3),
+            Numeric(I32Eqz),
+            BrIf(1),
+            LocalGet(3),
+            Memory(I32Load, 0),
+            LocalGet(1),
+            Numeric(I32GeU),
+            If(Empty),
+            LocalGet(3),
+            Memory(I32Load, 0),
+            LocalGet(1),
+            Numeric(I32Sub),
+            LocalTee(4),
+            I32Const(16),
+            Numeric(I32GeU),
+            If(Empty),
+            LocalGet(3),
+            LocalGet(4),
+            Memory(I32Store, 0),
+            LocalGet(3),
+            LocalGet(4),
+            Numeric(I32Add),
+            LocalSet(3),
+            Else,
+            LocalGet(2),
+            LocalGet(3),
+            Memory(I32Load, 8),
+            Memory(I32Store, 0),
+            // Eight bytes left over make a free block too small to link, until sweeping merges it
+            LocalGet(4),
+            If(Empty),
+            LocalGet(3),
+            LocalGet(1),
+            Numeric(I32Add),
+            I32Const(8),
+            Memory(I32Store, 0),
+            LocalGet(3),
+            LocalGet(1),
+            Numeric(I32Add),
+            I32Const(FREE),
+            Memory(I32Store, 4),
+            End,
+            End,
+            Br(3),
+            End,
+            LocalGet(3),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalSet(2),
+            Br(0),
+            End,
+            End,
+            GlobalGet(HEAP),
+            LocalTee(3),
+            LocalGet(1),
+            Numeric(I32Add),
+            GlobalSet(HEAP),
+            GlobalGet(HEAP),
+            MemorySize,
+            I32Const(16),
+            Numeric(I32Shl),
+            Numeric(I32GtU),
+            If(Empty),
+            GlobalGet(HEAP),
+            MemorySize,
+            I32Const(16),
+            Numeric(I32Shl),
+            Numeric(I32Sub),
+            I32Const(65535),
+            Numeric(I32Add),
+            I32Const(16),
+            Numeric(I32ShrU),
+            MemoryGrow,
+            I32Const(-1),
+            Numeric(I32Eq),
+            If(Empty),
+        ]);
+        body.extend(out_of_memory.clone());
+        body.extend([
+            End,
+            End,
+            End,
+            LocalGet(3),
+            LocalGet(1),
+            Memory(I32Store, 0),
+            LocalGet(3),
+            I32Const(0),
+            Memory(I32Store, 4),
+            GlobalGet(ALLOCATED),
+            LocalGet(1),
+            Numeric(I32Add),
+            GlobalSet(ALLOCATED),
+            LocalGet(3),
+            I32Const(8),
+            Numeric(I32Add),
+        ]);
+        self.define(
+            r.alloc,
+            "alloc",
+            (&[I32], &[I32]),
+            &[I32, I32, I32, I32],
+            body,
+        );
+
+        // mark(value). Statics lie below the heap and are never marked. The block of a marked
+        // object is pushed past the end of the heap, growing the memory for it. Local 1 is the
+        // block.
+        let mut body = vec![
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            I32Const(top(OBJECT)),
+            Numeric(I32Eq),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            GlobalGet(HEAP_START),
+            Numeric(I32GeU),
+            Numeric(I32And),
+            If(Empty),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            I32Const(8),
+            Numeric(I32Sub),
+            LocalTee(1),
+            Memory(I32Load, 4),
+            Numeric(I32Eqz),
+            If(Empty),
+            LocalGet(1),
+            I32Const(MARKED),
+            Memory(I32Store, 4),
+            GlobalGet(GRAY),
+            I32Const(4),
+            Numeric(I32Add),
+            MemorySize,
+            I32Const(16),
+            Numeric(I32Shl),
+            Numeric(I32GtU),
+            If(Empty),
+            I32Const(1),
+            MemoryGrow,
+            I32Const(-1),
+            Numeric(I32Eq),
+            If(Empty),
+        ];
+        body.extend(out_of_memory);
+        body.extend([
+            End,
+            End,
+            GlobalGet(GRAY),
+            LocalGet(1),
+            Memory(I32Store, 0),
+            GlobalGet(GRAY),
+            I32Const(4),
+            Numeric(I32Add),
+            GlobalSet(GRAY),
+            End,
+            End,
+        ]);
+        self.define(r.mark, "mark", (&[I64], &[]), &[I32], body);
+
+        // collect() marks what the shadow stack holds and traces the marked objects, then sweeps
+        // the heap: each run of blocks that are free or unreachable becomes one free block, linked
+        // in address order, and unreachable files that are still open are closed. The heap may
+        // then grow to twice what is live before the next collection. Locals: 0 is the slot,
+        // block or run being visited, 1 the block being traced, 2 an offset in it or the word
+        // being poisoned, 3 the link to the next free block, and 4 the end of the run.
+        let body = vec![
+            GlobalGet(HEAP),
+            GlobalSet(GRAY),
+            GlobalGet(ROOTS_START),
+            LocalSet(0),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(0),
+            GlobalGet(ROOTS),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(0),
+            Memory(I64Load, 0),
+            Call(r.mark),
+            LocalGet(0),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalSet(0),
+            Br(0),
+            End,
+            End,
+            Block(Empty),
+            Loop(Empty),
+            GlobalGet(GRAY),
+            GlobalGet(HEAP),
+            Numeric(I32LeU),
+            BrIf(1),
+            GlobalGet(GRAY),
+            I32Const(4),
+            Numeric(I32Sub),
+            GlobalSet(GRAY),
+            GlobalGet(GRAY),
+            Memory(I32Load, 0),
+            LocalTee(1),
+            Memory(I32Load, 8),
+            LocalTee(2),
+            I32Const(CLOSURE),
+            Numeric(I32Eq),
+            If(Empty),
+            // The captures of a closure fill its block after the index of its function
+            I32Const(16),
+            LocalSet(2),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(2),
+            LocalGet(1),
+            Memory(I32Load, 0),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(1),
+            LocalGet(2),
+            Numeric(I32Add),
+            Memory(I64Load, 0),
+            Call(r.mark),
+            LocalGet(2),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalSet(2),
+            Br(0),
+            End,
+            End,
+            Else,
+            LocalGet(2),
+            I32Const(OK),
+            Numeric(I32Eq),
+            LocalGet(2),
+            I32Const(ERR),
+            Numeric(I32Eq),
+            Numeric(I32Or),
+            LocalGet(2),
+            I32Const(CELL),
+            Numeric(I32Eq),
+            Numeric(I32Or),
+            If(Empty),
+            LocalGet(1),
+            Memory(I64Load, 16),
+            Call(r.mark),
+            End,
+            End,
+            Br(0),
+            End,
+            End,
+            I32Const(FREE_LIST as i32),
+            LocalSet(3),
+            GlobalGet(HEAP_START),
+            LocalSet(0),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(0),
+            GlobalGet(HEAP),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(0),
+            Memory(I32Load, 4),
+            I32Const(MARKED),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(0),
+            I32Const(0),
+            Memory(I32Store, 4),
+            LocalGet(0),
+            LocalGet(0),
+            Memory(I32Load, 0),
+            Numeric(I32Add),
+            LocalSet(0),
+            Br(1),
+            End,
+            LocalGet(0),
+            LocalSet(4),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(4),
+            GlobalGet(HEAP),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(4),
+            Memory(I32Load, 4),
+            I32Const(MARKED),
+            Numeric(I32Eq),
+            BrIf(1),
+            LocalGet(4),
+            Memory(I32Load, 4),
+            Numeric(I32Eqz),
+            If(Empty),
+            GlobalGet(ALLOCATED),
+            LocalGet(4),
+            Memory(I32Load, 0),
+            Numeric(I32Sub),
+            GlobalSet(ALLOCATED),
+            LocalGet(4),
+            Memory(I32Load, 8),
+            I32Const(FILE),
+            Numeric(I32Eq),
+            LocalGet(4),
+            Memory(I32Load, 16),
+            I32Const(0),
+            Numeric(I32Ne),
+            Numeric(I32And),
+            If(Empty),
+            LocalGet(4),
+            Memory(I32Load, 12),
+            Call(r.host_close),
+            End,
+            End,
+            LocalGet(4),
+            LocalGet(4),
+            Memory(I32Load, 0),
+            Numeric(I32Add),
+            LocalSet(4),
+            Br(0),
+            End,
+            End,
+            LocalGet(0),
+            LocalGet(4),
+            LocalGet(0),
+            Numeric(I32Sub),
+            Memory(I32Store, 0),
+            LocalGet(0),
+            I32Const(FREE),
+            Memory(I32Store, 4),
+            GlobalGet(STRESS),
+            If(Empty),
+            LocalGet(0),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalSet(2),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(2),
+            LocalGet(4),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(2),
+            I64Const(POISON),
+            Memory(I64Store, 0),
+            LocalGet(2),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalSet(2),
+            Br(0),
+            End,
+            End,
+            End,
+            LocalGet(4),
+            LocalGet(0),
+            Numeric(I32Sub),
+            I32Const(16),
+            Numeric(I32GeU),
+            If(Empty),
+            LocalGet(3),
+            LocalGet(0),
+            Memory(I32Store, 0),
+            LocalGet(0),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalSet(3),
+            End,
+            LocalGet(4),
+            LocalSet(0),
+            Br(0),
+            End,
+            End,
+            LocalGet(3),
+            I32Const(0),
+            Memory(I32Store, 0),
+            GlobalGet(ALLOCATED),
+            I32Const(1),
+            Numeric(I32Shl),
+            LocalTee(0),
+            I32Const(MIN_HEAP as i32),
+            LocalGet(0),
+            I32Const(MIN_HEAP as i32),
+            Numeric(I32GtU),
+            Select,
+            LocalTee(0),
+            I32Const(HEAP_LIMIT as i32),
+            LocalGet(0),
+            I32Const(HEAP_LIMIT as i32),
+            Numeric(I32LtU),
+            Select,
+            GlobalSet(THRESHOLD),
+        ];
+        self.define(r.collect, "collect", (&[], &[]), &[I32; 5], body);
+        let body = vec![I32Const(1), GlobalSet(STRESS)];
+        self.define(r.stress, "stress", (&[], &[]), &[], body);
+
+        // write_int(stream, value): digits are written backwards, ending at `SCRATCH_END`
+        let body = vec![
+            I32Const(SCRATCH_END as i32),
+            LocalSet(2),
+            LocalGet(1),
+            Numeric(I64ExtendI32S),
+            LocalSet(3),
+            LocalGet(3),
+            I64Const(0),
+            Numeric(I64LtS),
+            If(Empty),
+            I64Const(0),
+            LocalGet(3),
+            Numeric(I64Sub),
+            LocalSet(3),
+            End,
+            Loop(Empty),
+            LocalGet(2),
+            I32Const(1),
+            Numeric(I32Sub),
+            LocalTee(2),
+            LocalGet(3),
+            I64Const(10),
+            Numeric(I64RemU),
+            Numeric(I32WrapI64),
+            I32Const(b'0' as i32),
+            Numeric(I32Add),
+            Memory(I32Store8, 0),
+            LocalGet(3),
+            I64Const(10),
+            Numeric(I64DivU),
+            LocalTee(3),
+            I64Const(0),
+            Numeric(I64Ne),
+            BrIf(0),
+            End,
+            LocalGet(1),
+            I32Const(0),
+            Numeric(I32LtS),
+            If(Empty),
+            LocalGet(2),
+            I32Const(1),
+            Numeric(I32Sub),
+            LocalTee(2),
+            I32Const(b'-' as i32),
+            Memory(I32Store8, 0),
+            End,
+            LocalGet(0),
+            LocalGet(2),
+            I32Const(SCRATCH_END as i32),
+            LocalGet(2),
+            Numeric(I32Sub),
+            Call(r.write),
+        ];
+        self.define(
+            r.write_int,
+            "write_int",
+            (&[I32, I32], &[]),
+            &[I32, I64],
+            body,
+        );
+
+        // write_char(stream, code point), encoded as UTF-8 at `SCRATCH`
+        let byte = |offset: u32, shift: i32, prefix: i32, mask: i32| {
+            vec![
+                I32Const((SCRATCH + offset) as i32),
+                LocalGet(1),
+                I32Const(shift),
+                Numeric(I32ShrU),
+                I32Const(mask),
+                Numeric(I32And),
+                I32Const(prefix),
+                Numeric(I32Or),
+                Memory(I32Store8, 0),
+            ]
+        };
+        let encoding = |len: u32| {
+            let mut code = match len {
+                1 => byte(0, 0, 0, 0x7F),
+                2 => byte(0, 6, 0xC0, 0x1F),
+                3 => byte(0, 12, 0xE0, 0x0F),
+                _ => byte(0, 18, 0xF0, 0x07),
+            };
+            for n in 1..len {
+                code.extend(byte(n, 6 * (len - 1 - n) as i32, 0x80, 0x3F));
+            }
+            code.extend([I32Const(len as i32), LocalSet(2)]);
+            code
+        };
+        let mut body = Vec::new();
+        for (len, limit) in [(1, 0x80), (2, 0x800), (3, 0x10000)] {
+            body.extend([LocalGet(1), I32Const(limit), Numeric(I32LtU), If(Empty)]);
+            body.extend(encoding(len));
+            body.push(Else);
+        }
+        body.extend(encoding(4));
+        body.extend([End, End, End]);
+        body.extend([
+            LocalGet(0),
+            I32Const(SCRATCH as i32),
+            LocalGet(2),
+            Call(r.write),
+        ]);
+        self.define(r.write_char, "write_char", (&[I32, I32], &[]), &[I32], body);
+
+        // write_float(stream, value, precision), formatted by the host at `FLOAT_TEXT`
+        let body = vec![
+            LocalGet(0),
+            I32Const(FLOAT_TEXT as i32),
+            LocalGet(1),
+            LocalGet(2),
+            I32Const(FLOAT_TEXT as i32),
+            Call(r.format_float),
+            Call(r.write),
+        ];
+        self.define(
+            r.write_float,
+            "write_float",
+            (&[I32, F64, I32], &[]),
+            &[],
+            body,
+        );
+
+        // write_value(stream, value), dispatching on the tag, then on the kind of objects
+        let tag = |tag: i64| vec![LocalGet(2), I32Const(top(tag)), Numeric(I32Eq), If(Empty)];
+        let kind = |kind: i32| vec![LocalGet(4), I32Const(kind), Numeric(I32Eq)];
+        let mut body = vec![
+            LocalGet(1),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            LocalTee(2),
+            I32Const(top(INT)),
+            Numeric(I32LtU),
+            If(Empty),
+            LocalGet(0),
+            LocalGet(1),
+            Numeric(F64ReinterpretI64),
+            I32Const(-1),
+            Call(r.write_float),
+            Return,
+            End,
+        ];
+        body.extend(tag(INT));
+        body.extend([
+            LocalGet(0),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            Call(r.write_int),
+            Return,
+            End,
+        ]);
+        body.extend(tag(BOOL));
+        body.push(LocalGet(0));
+        body.extend(self.choose("true", "false", &[LocalGet(1), Numeric(I32WrapI64)]));
+        body.extend([Call(r.write), Return, End]);
+        body.extend(tag(CHAR));
+        body.extend([
+            LocalGet(0),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            Call(r.write_char),
+            Return,
+            End,
+        ]);
+        body.extend(tag(UNIT));
+        body.push(LocalGet(0));
+        body.extend(self.choose("null", "()", &[LocalGet(1), Numeric(I32WrapI64)]));
+        body.extend([Call(r.write), Return, End]);
+        body.extend([
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            LocalTee(3),
+            Memory(I32Load, 0),
+            LocalSet(4),
+        ]);
+        body.extend(kind(STRING));
+        body.extend([
+            If(Empty),
+            LocalGet(0),
+            LocalGet(3),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(3),
+            Memory(I32Load, 4),
+            Call(r.write),
+            Return,
+            End,
+        ]);
+        body.extend(kind(RANGE));
+        body.extend([
+            If(Empty),
+            LocalGet(0),
+            LocalGet(3),
+            Memory(I32Load, 8),
+            Call(r.write_int),
+            LocalGet(0),
+        ]);
+        // `..` is a prefix of `..=`
+        let (dots, _) = self.statics.text("..=");
+        body.extend([
+            I32Const(dots as i32),
+            I32Const(2),
+            LocalGet(3),
+            Memory(I32Load, 4),
+            Numeric(I32Add),
+            Call(r.write),
+            LocalGet(0),
+            LocalGet(3),
+            Memory(I32Load, 12),
+            Call(r.write_int),
+            Return,
+            End,
+        ]);
+        body.extend(kind(OK));
+        body.extend(kind(ERR));
+        body.extend([Numeric(I32Or), If(Empty), LocalGet(0)]);
+        body.extend(self.choose("Ok(", "Err(", &kind(OK)));
+        body.extend([
+            Call(r.write),
+            LocalGet(0),
+            LocalGet(3),
+            Memory(I64Load, 8),
+            Call(r.write_value),
+        ]);
+        body.extend(self.text(LocalGet(0), ")"));
+        body.extend([Return, End]);
+        body.extend(kind(CELL));
+        body.extend([
+            If(Empty),
+            LocalGet(0),
+            LocalGet(3),
+            Memory(I64Load, 8),
+            Call(r.write_value),
+            Return,
+            End,
+        ]);
+        body.extend(kind(FILE));
+        body.push(If(Empty));
+        body.extend(self.text(LocalGet(0), "<file>"));
+        body.extend([Return, End]);
+        body.extend(self.text(LocalGet(0), "<closure>"));
+        self.define(
+            r.write_value,
+            "write_value",
+            (&[I32, I64], &[]),
+            &[I32, I32, I32],
+            body,
+        );
+
+        let mut body = vec![I32Const(STDOUT), LocalGet(0), Call(r.write_value)];
+        body.extend(self.text(I32Const(STDOUT), "\n"));
+        self.define(r.println, "println", (&[I64], &[]), &[], body);
+        let body = self.text(I32Const(STDOUT), "\n");
+        self.define(r.println_empty, "println_empty", (&[], &[]), &[], body);
+
+        let body = vec![I32Const(STDOUT), LocalGet(0), Call(r.write_value)];
+        self.define(r.print, "print", (&[I64], &[]), &[], body);
+
+        // print_padded(value, width, precision, alignment) writes the value twice: first counting
+        // its characters, then for real between the spaces that pad it
+        let formatted = [
+            LocalGet(5),
+            I32Const(0),
+            Numeric(I32GeS),
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            I32Const(top(INT)),
+            Numeric(I32LtU),
+            Numeric(I32And),
+            If(Empty),
+            I32Const(STDOUT),
+            LocalGet(0),
+            Numeric(F64ReinterpretI64),
+            LocalGet(5),
+            Call(r.write_float),
+            Else,
+            I32Const(STDOUT),
+            LocalGet(0),
+            Call(r.write_value),
+            End,
+        ];
+        let (spaces, _) = self.statics.text(&" ".repeat(MAX_WIDTH as usize));
+        let mut body = Vec::new();
+        for param in 1..4 {
+            body.extend([LocalGet(param), Numeric(I32WrapI64), LocalSet(param + 3)]);
+        }
+        body.extend([I32Const(0), GlobalSet(COUNT)]);
+        body.extend(formatted);
+        // The padding, then the part of it before the value: all of it when aligned to the right
+        // (1), half of it when centered (2), and none when aligned to the left (0)
+        body.extend([
+            LocalGet(4),
+            GlobalGet(COUNT),
+            Numeric(I32Sub),
+            LocalTee(7),
+            I32Const(0),
+            LocalGet(7),
+            I32Const(0),
+            Numeric(I32GtS),
+            Select,
+            LocalSet(7),
+            I32Const(-1),
+            GlobalSet(COUNT),
+            LocalGet(7),
+            LocalGet(7),
+            I32Const(1),
+            Numeric(I32ShrU),
+            LocalGet(6),
+            I32Const(1),
+            Numeric(I32Eq),
+            Select,
+            I32Const(0),
+            LocalGet(6),
+            Select,
+            LocalSet(8),
+            I32Const(STDOUT),
+            I32Const(spaces as i32),
+            LocalGet(8),
+            Call(r.write),
+        ]);
+        body.extend(formatted);
+        body.extend([
+            I32Const(STDOUT),
+            I32Const(spaces as i32),
+            LocalGet(7),
+            LocalGet(8),
+            Numeric(I32Sub),
+            Call(r.write),
+        ]);
+        self.define(
+            r.print_padded,
+            "print_padded",
+            (&[I64, I64, I64, I64], &[]),
+            &[I32, I32, I32, I32, I32],
+            body,
+        );
+
+        // input() -> a string, or null at the end of stdin. The host keeps a line that does not
+        // fit, so that its length is known before the string is allocated.
+        let body = vec![
+            Call(r.flush),
+            I32Const(0),
+            I32Const(0),
+            Call(r.read_line),
+            LocalTee(0),
+            I32Const(0),
+            Numeric(I32LtS),
+            If(Empty),
+            I64Const(NULL),
+            Return,
+            End,
+            LocalGet(0),
+            I32Const(8),
+            Numeric(I32Add),
+            Call(r.alloc),
+            LocalTee(1),
+            I32Const(STRING),
+            Memory(I32Store, 0),
+            LocalGet(1),
+            LocalGet(0),
+            Memory(I32Store, 4),
+            LocalGet(0),
+            If(Empty),
+            LocalGet(1),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(0),
+            Call(r.read_line),
+            Drop,
+            End,
+            LocalGet(1),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+        ];
+        self.define(r.input, "input", (&[], &[I64]), &[I32, I32], body);
+
+        // to_string(value) -> a string of the text that printing the value writes. The text is
+        // written twice to memory: first counting its bytes, then into the allocated string.
+        let body = vec![
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            I32Const(top(OBJECT)),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I32Load, 0),
+            I32Const(STRING),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(0),
+            Return,
+            End,
+            End,
+            I32Const(0),
+            GlobalSet(COUNT),
+            I32Const(MEMORY),
+            LocalGet(0),
+            Call(r.write_value),
+            GlobalGet(COUNT),
+            LocalSet(1),
+            I32Const(-1),
+            GlobalSet(COUNT),
+            LocalGet(1),
+            I32Const(8),
+            Numeric(I32Add),
+            Call(r.alloc),
+            LocalTee(2),
+            I32Const(STRING),
+            Memory(I32Store, 0),
+            LocalGet(2),
+            LocalGet(1),
+            Memory(I32Store, 4),
+            LocalGet(2),
+            I32Const(8),
+            Numeric(I32Add),
+            GlobalSet(CAPTURE),
+            I32Const(MEMORY),
+            LocalGet(0),
+            Call(r.write_value),
+            LocalGet(2),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+        ];
+        self.define(
+            r.to_string,
+            "to_string",
+            (&[I64], &[I64]),
+            &[I32, I32],
+            body,
+        );
+
+        // concat(a, b) -> the string `a` followed by the string `b`
+        let mut body = vec![
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalTee(2),
+            Memory(I32Load, 4),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            LocalTee(3),
+            Memory(I32Load, 4),
+            Numeric(I32Add),
+            LocalTee(4),
+            I32Const(8),
+            Numeric(I32Add),
+        ];
+        body.extend(self.rooted_alloc(&[0, 1]));
+        body.extend([
+            LocalTee(5),
+            I32Const(STRING),
+            Memory(I32Store, 0),
+            LocalGet(5),
+            LocalGet(4),
+            Memory(I32Store, 4),
+            LocalGet(5),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(2),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(2),
+            Memory(I32Load, 4),
+            Call(r.copy),
+            LocalGet(5),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(2),
+            Memory(I32Load, 4),
+            Numeric(I32Add),
+            LocalGet(3),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(3),
+            Memory(I32Load, 4),
+            Call(r.copy),
+            LocalGet(5),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+        ]);
+        self.define(
+            r.concat,
+            "concat",
+            (&[I64, I64], &[I64]),
+            &[I32, I32, I32, I32],
+            body,
+        );
+
+        // is_number(string, fraction) -> whether the string object is an optional sign followed by
+        // decimal digits and, unless `fraction` is 0, an optional fraction and exponent.
+        // Locals: 2 points into the text, which ends at 3, and 4 is where the last digits start.
+        let peek = [
+            LocalGet(2),
+            LocalGet(3),
+            Numeric(I32LtU),
+            If(BlockType::Value(I32)),
+            LocalGet(2),
+            Memory(I32Load8U, 0),
+            Else,
+            I32Const(0),
+            End,
+        ];
+        let advance = [LocalGet(2), I32Const(1), Numeric(I32Add), LocalSet(2)];
+        let mut skip_sign = peek.to_vec();
+        skip_sign.extend([I32Const(b'+' as i32), Numeric(I32Eq)]);
+        skip_sign.extend(peek);
+        skip_sign.extend([
+            I32Const(b'-' as i32),
+            Numeric(I32Eq),
+            Numeric(I32Or),
+            If(Empty),
+        ]);
+        skip_sign.extend(advance);
+        skip_sign.push(End);
+        let mut skip_digits = vec![
+            LocalGet(2),
+            LocalSet(4),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(2),
+            LocalGet(3),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(2),
+            Memory(I32Load8U, 0),
+            I32Const(b'0' as i32),
+            Numeric(I32Sub),
+            I32Const(9),
+            Numeric(I32GtU),
+            BrIf(1),
+        ];
+        skip_digits.extend(advance);
+        skip_digits.extend([
+            Br(0),
+            End,
+            End,
+            LocalGet(2),
+            LocalGet(4),
+            Numeric(I32Eq),
+            If(Empty),
+            I32Const(0),
+            Return,
+            End,
+        ]);
+        let mut body = vec![
+            LocalGet(0),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalTee(2),
+            LocalGet(0),
+            Memory(I32Load, 4),
+            Numeric(I32Add),
+            LocalSet(3),
+        ];
+        body.extend(skip_sign.iter().cloned());
+        body.extend(skip_digits.iter().cloned());
+        body.extend([LocalGet(1), If(Empty)]);
+        body.extend(peek);
+        body.extend([I32Const(b'.' as i32), Numeric(I32Eq), If(Empty)]);
+        body.extend(advance);
+        body.extend(skip_digits.iter().cloned());
+        body.push(End);
+        // 'E' and 'e' are the only bytes that are 'e' with bit 5 set
+        body.extend(peek);
+        body.extend([
+            I32Const(0x20),
+            Numeric(I32Or),
+            I32Const(b'e' as i32),
+            Numeric(I32Eq),
+            If(Empty),
+        ]);
+        body.extend(advance);
+        body.extend(skip_sign);
+        body.extend(skip_digits);
+        body.extend([End, End, LocalGet(2), LocalGet(3), Numeric(I32Eq)]);
+        self.define(
+            r.is_number,
+            "is_number",
+            (&[I32, I32], &[I32]),
+            &[I32, I32, I32],
+            body,
+        );
+
+        // parse_error(string, suffix, length) -> an `Err` holding `cannot parse "<string>` followed
+        // by the text at `suffix`
+        let (prefix, prefix_len) = self.statics.text("cannot parse \"");
+        let body = vec![
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalTee(3),
+            Memory(I32Load, 4),
+            LocalGet(2),
+            Numeric(I32Add),
+            I32Const(prefix_len as i32),
+            Numeric(I32Add),
+            LocalTee(4),
+            I32Const(8),
+            Numeric(I32Add),
+            Call(r.alloc),
+            LocalTee(5),
+            I32Const(STRING),
+            Memory(I32Store, 0),
+            LocalGet(5),
+            LocalGet(4),
+            Memory(I32Store, 4),
+            LocalGet(5),
+            I32Const(8),
+            Numeric(I32Add),
+            I32Const(prefix as i32),
+            I32Const(prefix_len as i32),
+            Call(r.copy),
+            LocalGet(5),
+            I32Const(8 + prefix_len as i32),
+            Numeric(I32Add),
+            LocalGet(3),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(3),
+            Memory(I32Load, 4),
+            Call(r.copy),
+            LocalGet(5),
+            I32Const(8 + prefix_len as i32),
+            Numeric(I32Add),
+            LocalGet(3),
+            Memory(I32Load, 4),
+            Numeric(I32Add),
+            LocalGet(1),
+            LocalGet(2),
+            Call(r.copy),
+            LocalGet(5),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+            I32Const(ERR),
+            Call(r.wrap),
+        ];
+        self.define(
+            r.parse_error,
+            "parse_error",
+            (&[I64, I32, I32], &[I64]),
+            &[I32, I32, I32],
+            body,
+        );
+
+        // to_int(value, site): the integer a float truncates to, the scalar value of a character,
+        // or the result of parsing a string. Locals: 2 is the tag; 3 points into the text of a
+        // string, which ends at 4; 5 is its first byte; and 6 the magnitude of its number, which
+        // is at most 2^31 for an `Int`.
+        let boxed_int = [Numeric(I64ExtendI32U), I64Const(INT), Numeric(I64Or)];
+        let mut body = vec![
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            LocalTee(2),
+            I32Const(top(INT)),
+            Numeric(I32LtU),
+            If(Empty),
+            // NaN fails both comparisons
+            LocalGet(0),
+            Numeric(F64ReinterpretI64),
+            F64Const(i32::MIN as f64 - 1.0),
+            Numeric(F64Gt),
+            LocalGet(0),
+            Numeric(F64ReinterpretI64),
+            F64Const(i32::MAX as f64 + 1.0),
+            Numeric(F64Lt),
+            Numeric(I32And),
+            If(Empty),
+            LocalGet(0),
+            Numeric(F64ReinterpretI64),
+            Numeric(I32TruncF64S),
+        ];
+        body.extend(boxed_int);
+        body.extend([Return, End]);
+        body.extend(self.conversion_failed(0, 1, "Int"));
+        body.extend([
+            End,
+            LocalGet(2),
+            I32Const(top(CHAR)),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+        ]);
+        body.extend(boxed_int);
+        body.extend([
+            Return,
+            End,
+            LocalGet(2),
+            I32Const(top(OBJECT)),
+            Numeric(I32Ne),
+            If(Empty),
+            LocalGet(0),
+            Return,
+            End,
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            I32Const(0),
+            Call(r.is_number),
+            Numeric(I32Eqz),
+            If(Empty),
+        ]);
+        let failed = self.parse_failed(0, "Int");
+        body.extend(failed.iter().cloned());
+        body.extend([
+            End,
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalTee(3),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I32Load, 4),
+            Numeric(I32Add),
+            LocalSet(4),
+            // A sign is below '0'
+            LocalGet(3),
+            Memory(I32Load8U, 0),
+            LocalTee(5),
+            I32Const(b'0' as i32),
+            Numeric(I32LtU),
+            If(Empty),
+            LocalGet(3),
+            I32Const(1),
+            Numeric(I32Add),
+            LocalSet(3),
+            End,
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(3),
+            LocalGet(4),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(6),
+            I64Const(10),
+            Numeric(I64Mul),
+            LocalGet(3),
+            Memory(I32Load8U, 0),
+            I32Const(b'0' as i32),
+            Numeric(I32Sub),
+            Numeric(I64ExtendI32U),
+            Numeric(I64Add),
+            LocalTee(6),
+            I64Const(1 << 31),
+            Numeric(I64GtU),
+            If(Empty),
+        ]);
+        body.extend(failed.iter().cloned());
+        body.extend([
+            End,
+            LocalGet(3),
+            I32Const(1),
+            Numeric(I32Add),
+            LocalSet(3),
+            Br(0),
+            End,
+            End,
+            LocalGet(5),
+            I32Const(b'-' as i32),
+            Numeric(I32Eq),
+            If(Empty),
+            I64Const(0),
+            LocalGet(6),
+            Numeric(I64Sub),
+            LocalSet(6),
+            Else,
+            LocalGet(6),
+            I64Const(1 << 31),
+            Numeric(I64Eq),
+            If(Empty),
+        ]);
+        body.extend(failed);
+        body.extend([End, End, LocalGet(6), Numeric(I32WrapI64)]);
+        body.extend(boxed_int);
+        body.extend([I32Const(OK), Call(r.wrap)]);
+        self.define(
+            r.to_int,
+            "to_int",
+            (&[I64, I32], &[I64]),
+            &[I32, I32, I32, I32, I64],
+            body,
+        );
+
+        // to_float(value): an integer exactly, or the result of parsing a string
+        let mut body = vec![
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            LocalTee(1),
+            I32Const(top(INT)),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Numeric(F64ConvertI32S),
+            Numeric(I64ReinterpretF64),
+            Return,
+            End,
+            LocalGet(1),
+            I32Const(top(OBJECT)),
+            Numeric(I32Ne),
+            If(Empty),
+            LocalGet(0),
+            Return,
+            End,
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            I32Const(1),
+            Call(r.is_number),
+            Numeric(I32Eqz),
+            If(Empty),
+        ];
+        body.extend(self.parse_failed(0, "Float"));
+        body.extend([
+            End,
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I32Load, 4),
+            Call(r.parse_float),
+            Numeric(I64ReinterpretF64),
+            I32Const(OK),
+            Call(r.wrap),
+        ]);
+        self.define(r.to_float, "to_float", (&[I64], &[I64]), &[I32], body);
+
+        // to_char(value, site): the character whose scalar value an integer is. Negative integers
+        // are above 0x10FFFF unsigned, and surrogates are 0xD800 to 0xDFFF.
+        let mut body = vec![
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            I32Const(top(INT)),
+            Numeric(I32Ne),
+            If(Empty),
+            LocalGet(0),
+            Return,
+            End,
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalTee(2),
+            I32Const(0x10FFFF),
+            Numeric(I32GtU),
+            LocalGet(2),
+            I32Const(0xFFFFF800u32 as i32),
+            Numeric(I32And),
+            I32Const(0xD800),
+            Numeric(I32Eq),
+            Numeric(I32Or),
+            If(Empty),
+        ];
+        body.extend(self.conversion_failed(0, 1, "Char"));
+        body.extend([
+            End,
+            LocalGet(2),
+            Numeric(I64ExtendI32U),
+            I64Const(CHAR),
+            Numeric(I64Or),
+        ]);
+        self.define(r.to_char, "to_char", (&[I64, I32], &[I64]), &[I32], body);
+
+        // panic_begin(site) writes "panicked at path:line:column: "
+        let mut body = self.text(I32Const(STDERR), "panicked at ");
+        body.extend([
+            I32Const(STDERR),
+            LocalGet(0),
+            Memory(I32Load, 0),
+            LocalGet(0),
+            Memory(I32Load, 4),
+            Call(r.write),
+        ]);
+        for (offset, separator) in [(8, ":"), (12, ":")] {
+            body.extend(self.text(I32Const(STDERR), separator));
+            body.extend([
+                I32Const(STDERR),
+                LocalGet(0),
+                Memory(I32Load, offset),
+                Call(r.write_int),
+            ]);
+        }
+        body.extend(self.text(I32Const(STDERR), ": "));
+        self.define(r.panic_begin, "panic_begin", (&[I32], &[]), &[], body);
+
+        // panic(message, length, site)
+        let mut body = vec![
+            LocalGet(2),
+            Call(r.panic_begin),
+            I32Const(STDERR),
+            LocalGet(0),
+            LocalGet(1),
+            Call(r.write),
+        ];
+        body.extend(self.finish_panic());
+        self.define(r.panic, "panic", (&[I32, I32, I32], &[]), &[], body);
+
+        // panic_value(value, site)
+        let mut body = vec![
+            LocalGet(1),
+            Call(r.panic_begin),
+            I32Const(STDERR),
+            LocalGet(0),
+            Call(r.write_value),
+        ];
+        body.extend(self.finish_panic());
+        self.define(r.panic_value, "panic_value", (&[I64, I32], &[]), &[], body);
+
+        // unwrap(result, expected kind, site) -> the value inside
+        let expects_ok = [LocalGet(1), I32Const(OK), Numeric(I32Eq)];
+        let mut body = vec![
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I32Load, 0),
+            LocalGet(1),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I64Load, 8),
+            Return,
+            End,
+            LocalGet(2),
+            Call(r.panic_begin),
+            I32Const(STDERR),
+        ];
+        body.extend(self.choose(
+            "called `unwrap()` on an `Err` value: ",
+            "called `unwrap_err()` on an `Ok` value: ",
+            &expects_ok,
+        ));
+        body.extend([
+            Call(r.write),
+            I32Const(STDERR),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I64Load, 8),
+            Call(r.write_value),
+        ]);
+        body.extend(self.finish_panic());
+        self.define(r.unwrap, "unwrap", (&[I64, I32, I32], &[I64]), &[], body);
+
+        // wrap(value, kind) -> an object of `kind` holding the value: an `Ok`, `Err` or cell
+        let mut body = vec![I32Const(16)];
+        body.extend(self.rooted_alloc(&[0]));
+        body.extend([
+            LocalTee(2),
+            LocalGet(1),
+            Memory(I32Store, 0),
+            LocalGet(2),
+            LocalGet(0),
+            Memory(I64Store, 8),
+            LocalGet(2),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+        ]);
+        self.define(r.wrap, "wrap", (&[I64, I32], &[I64]), &[I32], body);
+
+        let body = vec![
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I32Load, 0),
+            LocalGet(1),
+            Numeric(I32Eq),
+        ];
+        self.define(r.has_kind, "has_kind", (&[I64, I32], &[I32]), &[], body);
+
+        // range(start, end, inclusive)
+        let body = vec![
+            I32Const(16),
+            Call(r.alloc),
+            LocalTee(3),
+            I32Const(RANGE),
+            Memory(I32Store, 0),
+            LocalGet(3),
+            LocalGet(2),
+            Memory(I32Store, 4),
+            LocalGet(3),
+            LocalGet(0),
+            Memory(I32Store, 8),
+            LocalGet(3),
+            LocalGet(1),
+            Memory(I32Store, 12),
+            LocalGet(3),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+        ];
+        self.define(r.range, "range", (&[I32, I32, I32], &[I64]), &[I32], body);
+
+        // equal(a, b): floats compare as numbers, objects by contents, anything else by bits
+        let object_kind = [LocalGet(4)];
+        let mut body = vec![
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            I32Const(top(INT)),
+            Numeric(I32LtU),
+            If(Empty),
+            LocalGet(0),
+            Numeric(F64ReinterpretI64),
+            LocalGet(1),
+            Numeric(F64ReinterpretI64),
+            Numeric(F64Eq),
+            Return,
+            End,
+            LocalGet(0),
+            LocalGet(1),
+            Numeric(I64Eq),
+            If(Empty),
+            I32Const(1),
+            Return,
+            End,
+            LocalGet(0),
+            I64Const(48),
+            Numeric(I64ShrU),
+            Numeric(I32WrapI64),
+            I32Const(top(OBJECT)),
+            Numeric(I32Ne),
+            If(Empty),
+            I32Const(0),
+            Return,
+            End,
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalSet(2),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            LocalSet(3),
+            LocalGet(2),
+            Memory(I32Load, 0),
+            LocalTee(4),
+            LocalGet(3),
+            Memory(I32Load, 0),
+            Numeric(I32Ne),
+            If(Empty),
+            I32Const(0),
+            Return,
+            End,
+        ];
+        body.extend(object_kind);
+        body.extend([
+            I32Const(STRING),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(2),
+            Memory(I32Load, 4),
+            LocalTee(5),
+            LocalGet(3),
+            Memory(I32Load, 4),
+            Numeric(I32Ne),
+            If(Empty),
+            I32Const(0),
+            Return,
+            End,
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(6),
+            LocalGet(5),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(2),
+            LocalGet(6),
+            Numeric(I32Add),
+            Memory(I32Load8U, 8),
+            LocalGet(3),
+            LocalGet(6),
+            Numeric(I32Add),
+            Memory(I32Load8U, 8),
+            Numeric(I32Ne),
+            If(Empty),
+            I32Const(0),
+            Return,
+            End,
+            LocalGet(6),
+            I32Const(1),
+            Numeric(I32Add),
+            LocalSet(6),
+            Br(0),
+            End,
+            End,
+            I32Const(1),
+            Return,
+            End,
+        ]);
+        body.extend(object_kind);
+        body.extend([
+            I32Const(RANGE),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(2),
+            Memory(I64Load, 0),
+            LocalGet(3),
+            Memory(I64Load, 0),
+            Numeric(I64Eq),
+            LocalGet(2),
+            Memory(I64Load, 8),
+            LocalGet(3),
+            Memory(I64Load, 8),
+            Numeric(I64Eq),
+            Numeric(I32And),
+            Return,
+            End,
+        ]);
+        body.extend(object_kind);
+        body.extend([I32Const(OK), Numeric(I32Eq)]);
+        body.extend(object_kind);
+        body.extend([
+            I32Const(ERR),
+            Numeric(I32Eq),
+            Numeric(I32Or),
+            If(Empty),
+            LocalGet(2),
+            Memory(I64Load, 8),
+            LocalGet(3),
+            Memory(I64Load, 8),
+            Call(r.equal),
+            Return,
+            End,
+            I32Const(0),
+        ]);
+        self.define(
+            r.equal,
+            "equal",
+            (&[I64, I64], &[I32]),
+            &[I32, I32, I32, I32, I32],
+            body,
+        );
+
+        // enter(site) counts a call towards the maximum depth, and leave() uncounts it
+        let mut body = vec![
+            GlobalGet(DEPTH),
+            I32Const(MAX_CALL_DEPTH as i32),
+            Numeric(I32GeU),
+            If(Empty),
+        ];
+        body.extend(self.fail(0, "stack overflow"));
+        body.extend([
+            End,
+            GlobalGet(DEPTH),
+            I32Const(1),
+            Numeric(I32Add),
+            GlobalSet(DEPTH),
+        ]);
+        self.define(r.enter, "enter", (&[I32], &[]), &[], body);
+        let body = vec![
+            GlobalGet(DEPTH),
+            I32Const(1),
+            Numeric(I32Sub),
+            GlobalSet(DEPTH),
+        ];
+        self.define(r.leave, "leave", (&[], &[]), &[], body);
+
+        // add, subtract and multiply compute in 64 bits, and fail unless the result fits in 32
+        for (index, name, op) in [
+            (r.add, "add", I64Add),
+            (r.subtract, "subtract", I64Sub),
+            (r.multiply, "multiply", I64Mul),
+        ] {
+            let mut body = vec![
+                LocalGet(0),
+                Numeric(I64ExtendI32S),
+                LocalGet(1),
+                Numeric(I64ExtendI32S),
+                Numeric(op),
+                LocalTee(3),
+                LocalGet(3),
+                Numeric(I32WrapI64),
+                Numeric(I64ExtendI32S),
+                Numeric(I64Ne),
+                If(Empty),
+            ];
+            body.extend(self.fail(2, &format!("attempt to {} with overflow", name)));
+            body.extend([End, LocalGet(3), Numeric(I32WrapI64)]);
+            self.define(index, name, (&[I32, I32, I32], &[I32]), &[I64], body);
+        }
+
+        for (index, name, op, message) in [
+            (
+                r.divide,
+                "divide",
+                I32DivS,
+                "attempt to divide with overflow",
+            ),
+            (
+                r.remainder,
+                "remainder",
+                I32RemS,
+                "attempt to calculate the remainder with overflow",
+            ),
+        ] {
+            let mut body = vec![LocalGet(1), Numeric(I32Eqz), If(Empty)];
+            body.extend(self.fail(2, "attempt to divide by zero"));
+            body.extend([
+                End,
+                LocalGet(0),
+                I32Const(i32::MIN),
+                Numeric(I32Eq),
+                LocalGet(1),
+                I32Const(-1),
+                Numeric(I32Eq),
+                Numeric(I32And),
+                If(Empty),
+            ]);
+            body.extend(self.fail(2, message));
+            body.extend([End, LocalGet(0), LocalGet(1), Numeric(op)]);
+            self.define(index, name, (&[I32, I32, I32], &[I32]), &[], body);
+        }
+
+        // Negative amounts are large unsigned numbers, so one comparison covers both
+        for (index, name, op, message) in [
+            (
+                r.shift_left,
+                "shift_left",
+                I32Shl,
+                "attempt to shift left with overflow",
+            ),
+            (
+                r.shift_right,
+                "shift_right",
+                I32ShrS,
+                "attempt to shift right with overflow",
+            ),
+        ] {
+            let mut body = vec![LocalGet(1), I32Const(32), Numeric(I32GeU), If(Empty)];
+            body.extend(self.fail(2, message));
+            body.extend([End, LocalGet(0), LocalGet(1), Numeric(op)]);
+            self.define(index, name, (&[I32, I32, I32], &[I32]), &[], body);
+        }
+
+        let mut body = vec![LocalGet(0), I32Const(i32::MIN), Numeric(I32Eq), If(Empty)];
+        body.extend(self.fail(1, "attempt to negate with overflow"));
+        body.extend([End, I32Const(0), LocalGet(0), Numeric(I32Sub)]);
+        self.define(r.negate, "negate", (&[I32, I32], &[I32]), &[], body);
+
+        let body = vec![
+            LocalGet(0),
+            I32Const(0),
+            Numeric(I32LtS),
+            If(BlockType::Value(I32)),
+            LocalGet(0),
+            LocalGet(1),
+            Call(r.negate),
+            Else,
+            LocalGet(0),
+            End,
+        ];
+        self.define(r.abs, "abs", (&[I32, I32], &[I32]), &[], body);
+
+        // pow_int squares the base in local 4 and multiplies the result in local 3 by it for each
+        // set bit of the exponent, in 64 bits. A square that overflows would be a factor of the
+        // result, so both are checked to fit in 32.
+        let mut body = vec![LocalGet(1), I32Const(0), Numeric(I32LtS), If(Empty)];
+        body.extend(self.fail(2, "attempt to raise an integer to a negative power"));
+        body.extend([
+            End,
+            I64Const(1),
+            LocalSet(3),
+            LocalGet(0),
+            Numeric(I64ExtendI32S),
+            LocalSet(4),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(1),
+            Numeric(I32Eqz),
+            BrIf(1),
+            LocalGet(1),
+            I32Const(1),
+            Numeric(I32And),
+            If(Empty),
+            LocalGet(3),
+            LocalGet(4),
+            Numeric(I64Mul),
+            LocalSet(3),
+        ]);
+        let overflow = self.fail(2, "attempt to multiply with overflow");
+        let check = |local: u32| {
+            let mut code = vec![
+                LocalGet(local),
+                LocalGet(local),
+                Numeric(I32WrapI64),
+                Numeric(I64ExtendI32S),
+                Numeric(I64Ne),
+                If(Empty),
+            ];
+            code.extend(overflow.iter().copied());
+            code.push(End);
+            code
+        };
+        body.extend(check(3));
+        body.extend([
+            End,
+            LocalGet(1),
+            I32Const(1),
+            Numeric(I32ShrU),
+            LocalTee(1),
+            Numeric(I32Eqz),
+            BrIf(1),
+            LocalGet(4),
+            LocalGet(4),
+            Numeric(I64Mul),
+            LocalSet(4),
+        ]);
+        body.extend(check(4));
+        body.extend([Br(0), End, End, LocalGet(3), Numeric(I32WrapI64)]);
+        self.define(
+            r.pow_int,
+            "pow_int",
+            (&[I32, I32, I32], &[I32]),
+            &[I64, I64],
+            body,
+        );
+
+        self.define(
+            r.fmod,
+            "fmod",
+            (&[F64, F64], &[F64]),
+            &[I64, I64, I64, I64, I64, I64],
+            fmod(),
+        );
+
+        self.files();
+
+        // utf8_width(string, offset), for `for` loops over the characters of a string. A byte
+        // that does not start a sequence counts as one, and a sequence cut short by the end of
+        // the string as the bytes left. Locals: 2 is the address of the string; 3 the offset; 4
+        // the bytes left from it; 5 the first byte of the sequence; and 6 its width.
+        let body = vec![
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            LocalTee(3),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalTee(2),
+            Memory(I32Load, 4),
+            LocalTee(4),
+            Numeric(I32GeU),
+            If(Empty),
+            I32Const(0),
+            Return,
+            End,
+            LocalGet(4),
+            LocalGet(3),
+            Numeric(I32Sub),
+            LocalSet(4),
+            LocalGet(2),
+            LocalGet(3),
+            Numeric(I32Add),
+            Memory(I32Load8U, 8),
+            LocalTee(5),
+            I32Const(0xC0),
+            Numeric(I32GeU),
+            I32Const(1),
+            Numeric(I32Add),
+            LocalGet(5),
+            I32Const(0xE0),
+            Numeric(I32GeU),
+            Numeric(I32Add),
+            LocalGet(5),
+            I32Const(0xF0),
+            Numeric(I32GeU),
+            Numeric(I32Add),
+            LocalSet(6),
+            I32Const(1),
+            LocalGet(6),
+            LocalGet(5),
+            I32Const(0xF8),
+            Numeric(I32GeU),
+            Select,
+            LocalTee(6),
+            LocalGet(4),
+            LocalGet(6),
+            LocalGet(4),
+            Numeric(I32LeU),
+            Select,
+        ];
+        self.define(
+            r.utf8_width,
+            "utf8_width",
+            (&[I64, I64], &[I32]),
+            &[I32, I32, I32, I32, I32],
+            body,
+        );
+
+        let body = vec![
+            LocalGet(0),
+            LocalGet(1),
+            Call(r.utf8_width),
+            Numeric(I64ExtendI32U),
+            I64Const(INT),
+            Numeric(I64Or),
+        ];
+        self.define(r.char_width, "char_width", (&[I64, I64], &[I64]), &[], body);
+
+        // char_at(string, offset). Locals: 2 is the address of the character less 8; 3 its
+        // width; 4 its code point; and 5 the index of a byte continuing its sequence.
+        let body = vec![
+            LocalGet(0),
+            LocalGet(1),
+            Call(r.utf8_width),
+            LocalSet(3),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            Numeric(I32Add),
+            LocalTee(2),
+            Memory(I32Load8U, 8),
+            LocalSet(4),
+            LocalGet(3),
+            I32Const(1),
+            Numeric(I32GtU),
+            If(Empty),
+            // Keeps the bits of the first byte that are not its length
+            LocalGet(4),
+            I32Const(0x7F),
+            LocalGet(3),
+            Numeric(I32ShrU),
+            Numeric(I32And),
+            LocalSet(4),
+            End,
+            I32Const(1),
+            LocalSet(5),
+            Block(Empty),
+            Loop(Empty),
+            LocalGet(5),
+            LocalGet(3),
+            Numeric(I32GeU),
+            BrIf(1),
+            LocalGet(4),
+            I32Const(6),
+            Numeric(I32Shl),
+            LocalGet(2),
+            LocalGet(5),
+            Numeric(I32Add),
+            Memory(I32Load8U, 8),
+            I32Const(0x3F),
+            Numeric(I32And),
+            Numeric(I32Or),
+            LocalSet(4),
+            LocalGet(5),
+            I32Const(1),
+            Numeric(I32Add),
+            LocalSet(5),
+            Br(0),
+            End,
+            End,
+            LocalGet(4),
+            Numeric(I64ExtendI32U),
+            I64Const(CHAR),
+            Numeric(I64Or),
+        ];
+        self.define(
+            r.char_at,
+            "char_at",
+            (&[I64, I64], &[I64]),
+            &[I32, I32, I32, I32],
+            body,
+        );
+    }
+
+    /// The string object of constant text, as a value
+    fn string_value(&mut self, text: &str) -> Instr {
+        I64Const(self.statics.string(text) as i64 | OBJECT)
+    }
+
+    /// Returns an `Err` holding the reason for the code on top of the stack
+    fn file_error(&self) -> [Instr; 4] {
+        let r = self.runtime;
+        [Call(r.file_reason), I32Const(ERR), Call(r.wrap), Return]
+    }
+
+    /// Defines the file built-ins. A file object holds the host's handle of the file, then its
+    /// state, which the runtime checks before asking the host to read or write it.
+    fn files(&mut self) {
+        let r = self.runtime;
+
+        // file_reason(code) -> string
+        let mut body = Vec::new();
+        for (code, reason) in REASONS {
+            let reason = self.string_value(reason);
+            body.extend([
+                LocalGet(0),
+                I32Const(code),
+                Numeric(I32Eq),
+                If(Empty),
+                reason,
+                Return,
+                End,
+            ]);
+        }
+        let (_, io_error) = REASONS.iter().find(|(code, _)| *code == IO_ERROR).unwrap();
+        body.push(self.string_value(io_error));
+        self.define(r.file_reason, "file_reason", (&[I32], &[I64]), &[], body);
+
+        // open(path, mode) -> a result. Locals: 2 is the handle or the code of the failure, and 3
+        // the letter of the mode.
+        let mut body = vec![
+            I32Const(INVALID_MODE),
+            LocalSet(2),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            LocalTee(3),
+            Memory(I32Load, 4),
+            I32Const(1),
+            Numeric(I32Eq),
+            If(Empty),
+            LocalGet(3),
+            Memory(I32Load8U, 8),
+            LocalTee(3),
+            I32Const(b'r' as i32),
+            Numeric(I32Eq),
+            LocalGet(3),
+            I32Const(b'w' as i32),
+            Numeric(I32Eq),
+            Numeric(I32Or),
+            LocalGet(3),
+            I32Const(b'a' as i32),
+            Numeric(I32Eq),
+            Numeric(I32Or),
+            If(Empty),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            Memory(I32Load, 4),
+            LocalGet(3),
+            Call(r.host_open),
+            LocalSet(2),
+            End,
+            End,
+            LocalGet(2),
+            I32Const(0),
+            Numeric(I32LtS),
+            If(Empty),
+        ];
+        let prefix = self.string_value("cannot open \"");
+        let separator = self.string_value("\": ");
+        body.extend([
+            prefix,
+            LocalGet(0),
+            Call(r.concat),
+            separator,
+            Call(r.concat),
+            LocalGet(2),
+            Call(r.file_reason),
+            Call(r.concat),
+            I32Const(ERR),
+            Call(r.wrap),
+            Return,
+            End,
+            I32Const(12),
+            Call(r.alloc),
+            LocalTee(4),
+            I32Const(FILE),
+            Memory(I32Store, 0),
+            LocalGet(4),
+            LocalGet(2),
+            Memory(I32Store, 4),
+            LocalGet(4),
+            I32Const(OPEN_FOR_READING),
+            I32Const(OPEN_FOR_WRITING),
+            LocalGet(3),
+            I32Const(b'r' as i32),
+            Numeric(I32Eq),
+            Select,
+            Memory(I32Store, 8),
+            LocalGet(4),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+            I32Const(OK),
+            Call(r.wrap),
+        ]);
+        self.define(
+            r.open,
+            "open",
+            (&[I64, I64], &[I64]),
+            &[I32, I32, I32],
+            body,
+        );
+
+        // close(file) -> (), which closing a closed file again also returns
+        let body = vec![
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalTee(1),
+            Memory(I32Load, 8),
+            If(Empty),
+            LocalGet(1),
+            Memory(I32Load, 4),
+            Call(r.host_close),
+            LocalGet(1),
+            I32Const(0),
+            Memory(I32Store, 8),
+            End,
+            I64Const(UNIT),
+        ];
+        self.define(r.close, "close", (&[I64], &[I64]), &[I32], body);
+
+        // read(file, line) -> a result. Like `input()`, the host keeps text that does not fit, so
+        // that its length is known before the string is allocated. Locals: 2 is the file object,
+        // 3 the length or the code of the failure, and 4 the string.
+        let mut body = vec![
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalTee(2),
+            Memory(I32Load, 8),
+            I32Const(OPEN_FOR_READING),
+            Numeric(I32Ne),
+            If(Empty),
+            I32Const(NOT_READABLE),
+            I32Const(CLOSED),
+            LocalGet(2),
+            Memory(I32Load, 8),
+            Select,
+        ];
+        body.extend(self.file_error());
+        body.extend([
+            End,
+            LocalGet(2),
+            Memory(I32Load, 4),
+            LocalGet(1),
+            I32Const(0),
+            I32Const(0),
+            Call(r.host_read),
+            LocalTee(3),
+            I32Const(-1),
+            Numeric(I32Eq),
+            If(Empty),
+            I64Const(NULL),
+            I32Const(OK),
+            Call(r.wrap),
+            Return,
+            End,
+            LocalGet(3),
+            I32Const(0),
+            Numeric(I32LtS),
+            If(Empty),
+            LocalGet(3),
+        ]);
+        body.extend(self.file_error());
+        body.extend([
+            End,
+            LocalGet(3),
+            I32Const(8),
+            Numeric(I32Add),
+            Call(r.alloc),
+            LocalTee(4),
+            I32Const(STRING),
+            Memory(I32Store, 0),
+            LocalGet(4),
+            LocalGet(3),
+            Memory(I32Store, 4),
+            LocalGet(3),
+            If(Empty),
+            LocalGet(2),
+            Memory(I32Load, 4),
+            LocalGet(1),
+            LocalGet(4),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(3),
+            Call(r.host_read),
+            Drop,
+            End,
+            LocalGet(4),
+            Numeric(I64ExtendI32U),
+            I64Const(OBJECT),
+            Numeric(I64Or),
+            I32Const(OK),
+            Call(r.wrap),
+        ]);
+        self.define(
+            r.read,
+            "read",
+            (&[I64, I32], &[I64]),
+            &[I32, I32, I32],
+            body,
+        );
+
+        // write_file(file, text) -> a result. Locals: 2 is the file object, and 3 the string, then
+        // the status of writing it.
+        let mut body = vec![
+            LocalGet(0),
+            Numeric(I32WrapI64),
+            LocalTee(2),
+            Memory(I32Load, 8),
+            I32Const(OPEN_FOR_WRITING),
+            Numeric(I32Ne),
+            If(Empty),
+            I32Const(NOT_WRITABLE),
+            I32Const(CLOSED),
+            LocalGet(2),
+            Memory(I32Load, 8),
+            Select,
+        ];
+        body.extend(self.file_error());
+        body.extend([
+            End,
+            LocalGet(2),
+            Memory(I32Load, 4),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            LocalTee(3),
+            I32Const(8),
+            Numeric(I32Add),
+            LocalGet(3),
+            Memory(I32Load, 4),
+            Call(r.host_write_file),
+            LocalTee(3),
+            If(Empty),
+            LocalGet(3),
+        ]);
+        body.extend(self.file_error());
+        body.extend([End, I64Const(UNIT), I32Const(OK), Call(r.wrap)]);
+        self.define(
+            r.write_file,
+            "write_file",
+            (&[I64, I64], &[I64]),
+            &[I32, I32],
+            body,
+        );
+
+        // next_line(file), for `for` loops over the lines of a file
+        let body = vec![
+            LocalGet(0),
+            I32Const(1),
+            Call(r.read),
+            LocalTee(1),
+            I32Const(OK),
+            Call(r.has_kind),
+            If(BlockType::Value(I64)),
+            LocalGet(1),
+            Numeric(I32WrapI64),
+            Memory(I64Load, 8),
+            Else,
+            I64Const(NULL),
+            End,
+        ];
+        self.define(r.next_line, "next_line", (&[I64], &[I64]), &[I64], body);
+    }
+}
+
+/// The floating-point remainder of `x / y`, with the sign of `x`, computed exactly on the bits of
+/// the operands by long division of their mantissas, as `fmod` in C.
+/// Locals: 2 and 3 hold the bits, then the mantissas, of `x` and `y`; 4 and 5 their exponents;
+/// 6 the sign of `x`; and 7 a scratch value.
+fn fmod() -> Vec<Instr> {
+    const MANTISSA: i64 = (1 << 52) - 1;
+    const IMPLICIT: i64 = 1 << 52;
+    let zero = [F64Const(0.0), LocalGet(0), Numeric(F64Mul), Return];
+    let mut body = vec![
+        LocalGet(0),
+        Numeric(I64ReinterpretF64),
+        LocalSet(2),
+        LocalGet(1),
+        Numeric(I64ReinterpretF64),
+        LocalSet(3),
+    ];
+    for (bits, exponent) in [(2, 4), (3, 5)] {
+        body.extend([
+            LocalGet(bits),
+            I64Const(52),
+            Numeric(I64ShrU),
+            I64Const(0x7FF),
+            Numeric(I64And),
+            LocalSet(exponent),
+        ]);
+    }
+    body.extend([
+        LocalGet(2),
+        I64Const(63),
+        Numeric(I64ShrU),
+        LocalSet(6),
+        // A zero or NaN divisor, or an infinite or NaN dividend, gives NaN
+        LocalGet(3),
+        I64Const(1),
+        Numeric(I64Shl),
+        Numeric(I64Eqz),
+        LocalGet(1),
+        LocalGet(1),
+        Numeric(F64Ne),
+        Numeric(I32Or),
+        LocalGet(4),
+        I64Const(0x7FF),
+        Numeric(I64Eq),
+        Numeric(I32Or),
+        If(Empty),
+        LocalGet(0),
+        LocalGet(1),
+        Numeric(F64Mul),
+        LocalGet(0),
+        LocalGet(1),
+        Numeric(F64Mul),
+        Numeric(F64Div),
+        Return,
+        End,
+        // |x| <= |y|
+        LocalGet(2),
+        I64Const(1),
+        Numeric(I64Shl),
+        LocalGet(3),
+        I64Const(1),
+        Numeric(I64Shl),
+        Numeric(I64LeU),
+        If(Empty),
+        LocalGet(2),
+        I64Const(1),
+        Numeric(I64Shl),
+        LocalGet(3),
+        I64Const(1),
+        Numeric(I64Shl),
+        Numeric(I64Eq),
+        If(Empty),
+    ]);
+    body.extend(zero);
+    body.extend([End, LocalGet(0), Return, End]);
+    // Mantissas get their implicit leading bit, and subnormals are normalized
+    for (bits, exponent) in [(2, 4), (3, 5)] {
+        body.extend([
+            LocalGet(exponent),
+            Numeric(I64Eqz),
+            If(Empty),
+            I64Const(0),
+            LocalGet(bits),
+            I64Const(12),
+            Numeric(I64Shl),
+            Numeric(I64Clz),
+            Numeric(I64Sub),
+            LocalSet(exponent),
+            LocalGet(bits),
+            I64Const(1),
+            LocalGet(exponent),
+            Numeric(I64Sub),
+            Numeric(I64Shl),
+            LocalSet(bits),
+            Else,
+            LocalGet(bits),
+            I64Const(MANTISSA),
+            Numeric(I64And),
+            I64Const(IMPLICIT),
+            Numeric(I64Or),
+            LocalSet(bits),
+            End,
+        ]);
+    }
+    // Subtracts the divisor when it fits, one bit of the quotient at a time
+    let step = {
+        let mut step = vec![
+            LocalGet(2),
+            LocalGet(3),
+            Numeric(I64Sub),
+            LocalTee(7),
+            I64Const(0),
+            Numeric(I64GeS),
+            If(Empty),
+            LocalGet(7),
+            Numeric(I64Eqz),
+            If(Empty),
+        ];
+        step.extend(zero);
+        step.extend([End, LocalGet(7), LocalSet(2), End]);
+        step
+    };
+    body.extend([
+        Block(Empty),
+        Loop(Empty),
+        LocalGet(4),
+        LocalGet(5),
+        Numeric(I64GtS),
+        Numeric(I32Eqz),
+        BrIf(1),
+    ]);
+    body.extend(step.clone());
+    body.extend([
+        LocalGet(2),
+        I64Const(1),
+        Numeric(I64Shl),
+        LocalSet(2),
+        LocalGet(4),
+        I64Const(1),
+        Numeric(I64Sub),
+        LocalSet(4),
+        Br(0),
+        End,
+        End,
+    ]);
+    body.extend(step);
+    body.extend([
+        // Normalizes the remainder, so its leading bit is the implicit one
+        LocalGet(2),
+        Numeric(I64Clz),
+        I64Const(11),
+        Numeric(I64Sub),
+        LocalSet(7),
+        LocalGet(2),
+        LocalGet(7),
+        Numeric(I64Shl),
+        LocalSet(2),
+        LocalGet(4),
+        LocalGet(7),
+        Numeric(I64Sub),
+        LocalSet(4),
+        LocalGet(4),
+        I64Const(0),
+        Numeric(I64GtS),
+        If(Empty),
+        LocalGet(2),
+        I64Const(IMPLICIT),
+        Numeric(I64Sub),
+        LocalGet(4),
+        I64Const(52),
+        Numeric(I64Shl),
+        Numeric(I64Or),
+        LocalSet(2),
+        Else,
+        // A subnormal result
+        LocalGet(2),
+        I64Const(1),
+        LocalGet(4),
+        Numeric(I64Sub),
+        Numeric(I64ShrU),
+        LocalSet(2),
+        End,
+        LocalGet(2),
+        LocalGet(6),
+        I64Const(63),
+        Numeric(I64Shl),
+        Numeric(I64Or),
+        Numeric(F64ReinterpretI64),
+    ]);
+    body
+}
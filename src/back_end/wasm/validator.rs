@@ -0,0 +1,799 @@
+//! Decoding and validation of WebAssembly modules, so generated modules can be checked without an
+//! external toolchain.
+//!
+//! `decode` reads the binary format back into a `Module`, and `validate` checks it the way an
+//! engine does before instantiating it: every index refers to something that exists, and every
+//! function body is well typed, following the algorithm of the specification's appendix, with an
+//! operand stack of value types and a stack of control frames.
+use crate::back_end::wasm::module::*;
+use crate::back_end::wasm::WasmError;
+
+const PAGE_SIZE: u64 = 65536;
+
+/// Reads a module from its binary encoding
+pub fn decode(bytes: &[u8]) -> Result<Module, WasmError> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(4)? != MAGIC {
+        return Err(WasmError::NotWasm);
+    }
+    let version = u32::from_le_bytes(reader.take(4)?.try_into().unwrap());
+    if version != VERSION {
+        return Err(WasmError::UnsupportedVersion(version));
+    }
+
+    let mut module = Module::default();
+    let mut function_types = Vec::new();
+    let mut last = 0;
+    while !reader.is_empty() {
+        let id = reader.u8()?;
+        let len = reader.u32()? as usize;
+        let mut section = Reader::new(reader.take(len)?);
+        if id <= last || id == 8 || id > DATA_SECTION {
+            return Err(WasmError::InvalidSection(id));
+        }
+        last = id;
+        let count = section.u32()?;
+        for _ in 0..count {
+            match id {
+                TYPE_SECTION => {
+                    if section.u8()? != FUNC_TYPE {
+                        return Err(WasmError::InvalidSection(id));
+                    }
+                    let params = section.types()?;
+                    let results = section.types()?;
+                    module.types.push(FuncType { params, results });
+                }
+                IMPORT_SECTION => {
+                    let module_name = section.name()?;
+                    let name = section.name()?;
+                    if section.u8()? != 0x00 {
+                        return Err(invalid("import", "only functions can be imported"));
+                    }
+                    module.imports.push(Import {
+                        module: module_name,
+                        name,
+                        ty: section.u32()?,
+                    });
+                }
+                FUNCTION_SECTION => function_types.push(section.u32()?),
+                TABLE_SECTION => {
+                    if section.u8()? != FUNCREF || section.u8()? != 0x00 {
+                        return Err(invalid("table", "expected a funcref table without maximum"));
+                    }
+                    let size = section.u32()?;
+                    module.table = vec![u32::MAX; size as usize];
+                }
+                MEMORY_SECTION => {
+                    if section.u8()? != 0x00 {
+                        return Err(invalid("memory", "expected a memory without maximum"));
+                    }
+                    module.memory = section.u32()?;
+                }
+                GLOBAL_SECTION => {
+                    let ty = section.val_type()?;
+                    let mutable = match section.u8()? {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(invalid("global", "invalid mutability")),
+                    };
+                    let init = section.constant()?;
+                    module.globals.push(Global { ty, mutable, init });
+                }
+                EXPORT_SECTION => {
+                    let name = section.name()?;
+                    let kind = match section.u8()? {
+                        0x00 => ExportKind::Function,
+                        0x02 => ExportKind::Memory,
+                        _ => return Err(invalid("export", "unsupported export kind")),
+                    };
+                    let index = section.u32()?;
+                    module.exports.push(Export { name, kind, index });
+                }
+                ELEMENT_SECTION => {
+                    if section.u8()? != 0x00 || section.constant()? != Instr::I32Const(0) {
+                        return Err(invalid("elem", "expected an active segment at offset 0"));
+                    }
+                    let len = section.u32()?;
+                    if len as usize > module.table.len() {
+                        return Err(invalid("elem", "the segment does not fit in the table"));
+                    }
+                    for slot in 0..len as usize {
+                        module.table[slot] = section.u32()?;
+                    }
+                }
+                CODE_SECTION => {
+                    let size = section.u32()? as usize;
+                    let mut code = Reader::new(section.take(size)?);
+                    let mut locals = Vec::new();
+                    for _ in 0..code.u32()? {
+                        let count = code.u32()?;
+                        let ty = code.val_type()?;
+                        if locals.len() + count as usize > 50000 {
+                            return Err(invalid("code", "too many locals"));
+                        }
+                        locals.extend(std::iter::repeat_n(ty, count as usize));
+                    }
+                    let body = code.body()?;
+                    if !code.is_empty() {
+                        return Err(WasmError::TrailingBytes);
+                    }
+                    let index = module.functions.len();
+                    let ty = *function_types
+                        .get(index)
+                        .ok_or_else(|| invalid("code", "more bodies than functions"))?;
+                    module.functions.push(Function {
+                        name: format!("f{}", module.imports.len() + index),
+                        ty,
+                        locals,
+                        body,
+                    });
+                }
+                _ => {
+                    if section.u8()? != 0x00 {
+                        return Err(invalid("data", "expected an active segment"));
+                    }
+                    let Instr::I32Const(offset) = section.constant()? else {
+                        return Err(invalid("data", "the offset is not an i32 constant"));
+                    };
+                    let len = section.u32()? as usize;
+                    module.data.push(Data {
+                        offset: offset as u32,
+                        bytes: section.take(len)?.to_vec(),
+                    });
+                }
+            }
+        }
+        if !section.is_empty() {
+            return Err(WasmError::TrailingBytes);
+        }
+    }
+    if function_types.len() != module.functions.len() {
+        return Err(invalid("code", "some functions have no body"));
+    }
+    Ok(module)
+}
+
+/// Checks that a module is valid
+pub fn validate(module: &Module) -> Result<(), WasmError> {
+    let types = module.types.len() as u32;
+    let signatures: Vec<u32> = module
+        .imports
+        .iter()
+        .map(|import| import.ty)
+        .chain(module.functions.iter().map(|function| function.ty))
+        .collect();
+    if let Some(ty) = signatures.iter().find(|&&ty| ty >= types) {
+        return Err(invalid("module", format!("type {} does not exist", ty)));
+    }
+    if let Some(function) = module
+        .table
+        .iter()
+        .find(|&&function| function as usize >= signatures.len())
+    {
+        return Err(invalid(
+            "elem",
+            format!("function {} does not exist", function),
+        ));
+    }
+    for global in &module.globals {
+        let ty = match global.init {
+            Instr::I32Const(_) => ValType::I32,
+            Instr::I64Const(_) => ValType::I64,
+            Instr::F64Const(_) => ValType::F64,
+            _ => return Err(invalid("global", "the initializer is not a constant")),
+        };
+        if ty != global.ty {
+            return Err(invalid("global", "the initializer has the wrong type"));
+        }
+    }
+    for (n, export) in module.exports.iter().enumerate() {
+        let exists = match export.kind {
+            ExportKind::Function => (export.index as usize) < signatures.len(),
+            ExportKind::Memory => export.index == 0,
+        };
+        if !exists {
+            return Err(invalid("export", format!("{} does not exist", export.name)));
+        }
+        if module.exports[..n]
+            .iter()
+            .any(|other| other.name == export.name)
+        {
+            return Err(invalid(
+                "export",
+                format!("{} is exported twice", export.name),
+            ));
+        }
+    }
+    for data in &module.data {
+        if data.offset as u64 + data.bytes.len() as u64 > module.memory as u64 * PAGE_SIZE {
+            return Err(invalid("data", "the segment does not fit in memory"));
+        }
+    }
+    for (index, function) in module.functions.iter().enumerate() {
+        let index = module.imports.len() + index;
+        FunctionValidator::new(module, &signatures, function)
+            .validate()
+            .map_err(|reason| invalid(&format!("function {}", index), reason))?;
+    }
+    Ok(())
+}
+
+fn invalid(context: &str, reason: impl Into<String>) -> WasmError {
+    WasmError::Invalid {
+        context: context.to_string(),
+        reason: reason.into(),
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    position: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn new(bytes: &'b [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'b [u8], WasmError> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position.saturating_add(len))
+            .ok_or(WasmError::Truncated)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, WasmError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// A LEB128 number of at most `bits` bits, sign-extended when `signed`
+    fn leb128(&mut self, bits: u32, signed: bool) -> Result<i64, WasmError> {
+        let mut value: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= bits {
+                return Err(WasmError::InvalidInteger);
+            }
+            value |= ((byte & 0x7F) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if signed && shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32, WasmError> {
+        let value = self.leb128(32, false)?;
+        u32::try_from(value).map_err(|_| WasmError::InvalidInteger)
+    }
+
+    fn name(&mut self) -> Result<String, WasmError> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| WasmError::InvalidString)
+    }
+
+    fn val_type(&mut self) -> Result<ValType, WasmError> {
+        let code = self.u8()?;
+        ValType::from_code(code).ok_or(WasmError::InvalidType(code))
+    }
+
+    fn types(&mut self) -> Result<Vec<ValType>, WasmError> {
+        (0..self.u32()?).map(|_| self.val_type()).collect()
+    }
+
+    fn block_type(&mut self) -> Result<BlockType, WasmError> {
+        match self.u8()? {
+            EMPTY_BLOCK => Ok(BlockType::Empty),
+            code => ValType::from_code(code)
+                .map(BlockType::Value)
+                .ok_or(WasmError::InvalidType(code)),
+        }
+    }
+
+    /// A constant expression: one constant instruction and `end`
+    fn constant(&mut self) -> Result<Instr, WasmError> {
+        let instr = self.instr()?;
+        if !matches!(
+            instr,
+            Instr::I32Const(_) | Instr::I64Const(_) | Instr::F64Const(_)
+        ) || self.instr()? != Instr::End
+        {
+            return Err(invalid("module", "expected a constant expression"));
+        }
+        Ok(instr)
+    }
+
+    /// The instructions of a function body, without the `end` that closes it
+    fn body(&mut self) -> Result<Vec<Instr>, WasmError> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let instr = self.instr()?;
+            match instr {
+                Instr::Block(_) | Instr::Loop(_) | Instr::If(_) => depth += 1,
+                Instr::End if depth == 0 => return Ok(body),
+                Instr::End => depth -= 1,
+                _ => {}
+            }
+            body.push(instr);
+        }
+    }
+
+    fn instr(&mut self) -> Result<Instr, WasmError> {
+        let opcode = self.u8()?;
+        let instr = match opcode {
+            0x00 => Instr::Unreachable,
+            0x02 => Instr::Block(self.block_type()?),
+            0x03 => Instr::Loop(self.block_type()?),
+            0x04 => Instr::If(self.block_type()?),
+            0x05 => Instr::Else,
+            0x0B => Instr::End,
+            0x0C => Instr::Br(self.u32()?),
+            0x0D => Instr::BrIf(self.u32()?),
+            0x0F => Instr::Return,
+            0x10 => Instr::Call(self.u32()?),
+            0x11 => {
+                let ty = self.u32()?;
+                if self.u8()? != 0x00 {
+                    return Err(invalid("code", "call_indirect only uses table 0"));
+                }
+                Instr::CallIndirect(ty)
+            }
+            0x1A => Instr::Drop,
+            0x1B => Instr::Select,
+            0x20 => Instr::LocalGet(self.u32()?),
+            0x21 => Instr::LocalSet(self.u32()?),
+            0x22 => Instr::LocalTee(self.u32()?),
+            0x23 => Instr::GlobalGet(self.u32()?),
+            0x24 => Instr::GlobalSet(self.u32()?),
+            0x3F | 0x40 => {
+                if self.u8()? != 0x00 {
+                    return Err(invalid("code", "memory instructions only use memory 0"));
+                }
+                match opcode {
+                    0x3F => Instr::MemorySize,
+                    _ => Instr::MemoryGrow,
+                }
+            }
+            0x41 => Instr::I32Const(self.leb128(32, true)? as i32),
+            0x42 => Instr::I64Const(self.leb128(64, true)?),
+            0x44 => {
+                let bytes = self.take(8)?.try_into().unwrap();
+                Instr::F64Const(f64::from_le_bytes(bytes))
+            }
+            _ => {
+                if let Some(access) = Access::from_opcode(opcode) {
+                    let align = self.u32()?;
+                    if align > access.info().2 {
+                        return Err(invalid("code", "the alignment exceeds the access size"));
+                    }
+                    Instr::Memory(access, self.u32()?)
+                } else if let Some(op) = Op::from_opcode(opcode) {
+                    Instr::Numeric(op)
+                } else {
+                    return Err(WasmError::InvalidOpcode(opcode));
+                }
+            }
+        };
+        Ok(instr)
+    }
+}
+
+/// A block, loop, `if` or the function body being validated
+/// - `height` is the height of the operand stack when the frame was entered
+/// - `unreachable` is set after an unconditional branch, from which point the stack is polymorphic
+struct Frame {
+    result: Option<ValType>,
+    is_loop: bool,
+    is_if: bool,
+    height: usize,
+    unreachable: bool,
+}
+
+impl Frame {
+    /// The values a branch to the frame carries: none for a loop, which branches to its start
+    fn label(&self) -> Option<ValType> {
+        if self.is_loop {
+            None
+        } else {
+            self.result
+        }
+    }
+}
+
+struct FunctionValidator<'m> {
+    module: &'m Module,
+    signatures: &'m [u32],
+    function: &'m Function,
+    locals: Vec<ValType>,
+    /// Operand types, where `None` is a value of unknown type popped from a polymorphic stack
+    operands: Vec<Option<ValType>>,
+    frames: Vec<Frame>,
+}
+
+impl<'m> FunctionValidator<'m> {
+    fn new(module: &'m Module, signatures: &'m [u32], function: &'m Function) -> Self {
+        let ty = &module.types[function.ty as usize];
+        Self {
+            module,
+            signatures,
+            function,
+            locals: ty.params.iter().chain(&function.locals).copied().collect(),
+            operands: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    fn validate(mut self) -> Result<(), String> {
+        let ty = &self.module.types[self.function.ty as usize];
+        if ty.results.len() > 1 {
+            return Err("functions return at most one value".to_string());
+        }
+        self.push_frame(ty.results.first().copied(), false, false);
+        for (offset, instr) in self.function.body.iter().enumerate() {
+            self.instr(instr)
+                .map_err(|reason| format!("at instruction {} ({}): {}", offset, instr, reason))?;
+            if self.frames.is_empty() {
+                return Err("instructions after the end of the body".to_string());
+            }
+        }
+        self.instr(&Instr::End)
+            .map_err(|reason| format!("at the end of the body: {}", reason))?;
+        if !self.frames.is_empty() {
+            return Err("unterminated block".to_string());
+        }
+        Ok(())
+    }
+
+    fn push_frame(&mut self, result: Option<ValType>, is_loop: bool, is_if: bool) {
+        self.frames.push(Frame {
+            result,
+            is_loop,
+            is_if,
+            height: self.operands.len(),
+            unreachable: false,
+        });
+    }
+
+    fn push(&mut self, ty: ValType) {
+        self.operands.push(Some(ty));
+    }
+
+    fn pop(&mut self, expected: Option<ValType>) -> Result<Option<ValType>, String> {
+        let frame = self.frames.last().unwrap();
+        if self.operands.len() == frame.height {
+            return match frame.unreachable {
+                true => Ok(expected),
+                false => Err("the operand stack is empty".to_string()),
+            };
+        }
+        let actual = self.operands.pop().unwrap();
+        match (actual, expected) {
+            (Some(actual), Some(expected)) if actual != expected => Err(format!(
+                "expected {} but found {}",
+                expected.name(),
+                actual.name()
+            )),
+            (Some(actual), _) => Ok(Some(actual)),
+            (None, _) => Ok(expected),
+        }
+    }
+
+    fn pop_all(&mut self, types: &[ValType]) -> Result<(), String> {
+        for ty in types.iter().rev() {
+            self.pop(Some(*ty))?;
+        }
+        Ok(())
+    }
+
+    /// Marks the rest of the current block as unreachable
+    fn unreachable(&mut self) {
+        let frame = self.frames.last_mut().unwrap();
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    /// Checks the stack holds exactly the results of the current frame
+    fn end_frame(&mut self) -> Result<(), String> {
+        let result = self.frames.last().unwrap().result;
+        if let Some(ty) = result {
+            self.pop(Some(ty))?;
+        }
+        if self.operands.len() != self.frames.last().unwrap().height {
+            return Err("values are left on the operand stack".to_string());
+        }
+        Ok(())
+    }
+
+    fn label(&self, depth: u32) -> Result<Option<ValType>, String> {
+        let index = self
+            .frames
+            .len()
+            .checked_sub(depth as usize + 1)
+            .ok_or_else(|| format!("label {} does not exist", depth))?;
+        Ok(self.frames[index].label())
+    }
+
+    fn local(&self, index: u32) -> Result<ValType, String> {
+        self.locals
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| format!("local {} does not exist", index))
+    }
+
+    fn global(&self, index: u32) -> Result<&'m Global, String> {
+        self.module
+            .globals
+            .get(index as usize)
+            .ok_or_else(|| format!("global {} does not exist", index))
+    }
+
+    fn call(&mut self, ty: &FuncType) -> Result<(), String> {
+        self.pop_all(&ty.params)?;
+        for result in &ty.results {
+            self.push(*result);
+        }
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: &Instr) -> Result<(), String> {
+        let module = self.module;
+        match *instr {
+            Instr::Unreachable => self.unreachable(),
+            Instr::Block(ty) | Instr::Loop(ty) | Instr::If(ty) => {
+                if let Instr::If(_) = instr {
+                    self.pop(Some(ValType::I32))?;
+                }
+                let result = match ty {
+                    BlockType::Empty => None,
+                    BlockType::Value(ty) => Some(ty),
+                };
+                let is_loop = matches!(instr, Instr::Loop(_));
+                let is_if = matches!(instr, Instr::If(_));
+                self.push_frame(result, is_loop, is_if);
+            }
+            Instr::Else => {
+                if !self.frames.last().unwrap().is_if {
+                    return Err("else outside of an if".to_string());
+                }
+                self.end_frame()?;
+                let frame = self.frames.last_mut().unwrap();
+                frame.is_if = false;
+                frame.unreachable = false;
+            }
+            Instr::End => {
+                self.end_frame()?;
+                let frame = self.frames.pop().unwrap();
+                // Without an else branch, an `if` has nothing to produce its value with
+                if frame.is_if && frame.result.is_some() {
+                    return Err("an if with a result needs an else".to_string());
+                }
+                if let Some(ty) = frame.result {
+                    if !self.frames.is_empty() {
+                        self.push(ty);
+                    }
+                }
+            }
+            Instr::Br(depth) => {
+                if let Some(ty) = self.label(depth)? {
+                    self.pop(Some(ty))?;
+                }
+                self.unreachable();
+            }
+            Instr::BrIf(depth) => {
+                self.pop(Some(ValType::I32))?;
+                if let Some(ty) = self.label(depth)? {
+                    self.pop(Some(ty))?;
+                    self.push(ty);
+                }
+            }
+            Instr::Return => {
+                if let Some(ty) = self.frames[0].result {
+                    self.pop(Some(ty))?;
+                }
+                self.unreachable();
+            }
+            Instr::Call(function) => {
+                let ty = self
+                    .signatures
+                    .get(function as usize)
+                    .ok_or_else(|| format!("function {} does not exist", function))?;
+                self.call(&module.types[*ty as usize])?;
+            }
+            Instr::CallIndirect(ty) => {
+                let ty = module
+                    .types
+                    .get(ty as usize)
+                    .ok_or_else(|| format!("type {} does not exist", ty))?;
+                self.pop(Some(ValType::I32))?;
+                self.call(ty)?;
+            }
+            Instr::Drop => {
+                self.pop(None)?;
+            }
+            Instr::Select => {
+                self.pop(Some(ValType::I32))?;
+                let first = self.pop(None)?;
+                let second = self.pop(first)?;
+                match first.or(second) {
+                    Some(ty) => self.push(ty),
+                    None => self.operands.push(None),
+                }
+            }
+            Instr::LocalGet(index) => {
+                let ty = self.local(index)?;
+                self.push(ty);
+            }
+            Instr::LocalSet(index) => {
+                let ty = self.local(index)?;
+                self.pop(Some(ty))?;
+            }
+            Instr::LocalTee(index) => {
+                let ty = self.local(index)?;
+                self.pop(Some(ty))?;
+                self.push(ty);
+            }
+            Instr::GlobalGet(index) => {
+                let global = self.global(index)?;
+                self.push(global.ty);
+            }
+            Instr::GlobalSet(index) => {
+                let global = self.global(index)?;
+                if !global.mutable {
+                    return Err(format!("global {} is immutable", index));
+                }
+                self.pop(Some(global.ty))?;
+            }
+            Instr::Memory(access, _) => {
+                let (_, _, _, ty, store) = access.info();
+                if store {
+                    self.pop(Some(ty))?;
+                    self.pop(Some(ValType::I32))?;
+                } else {
+                    self.pop(Some(ValType::I32))?;
+                    self.push(ty);
+                }
+            }
+            Instr::MemorySize => self.push(ValType::I32),
+            Instr::MemoryGrow => {
+                self.pop(Some(ValType::I32))?;
+                self.push(ValType::I32);
+            }
+            Instr::I32Const(_) => self.push(ValType::I32),
+            Instr::I64Const(_) => self.push(ValType::I64),
+            Instr::F64Const(_) => self.push(ValType::F64),
+            Instr::Numeric(op) => {
+                let (_, _, params, result) = op.info();
+                self.pop_all(params)?;
+                self.push(result);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module with one function of type `() -> i32`
+    fn module(body: Vec<Instr>) -> Module {
+        let mut module = Module {
+            memory: 1,
+            ..Module::default()
+        };
+        let ty = module.type_index(&[], &[ValType::I32]);
+        module.functions.push(Function {
+            name: "test".to_string(),
+            ty,
+            locals: vec![ValType::I64],
+            body,
+        });
+        module
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut module = module(vec![
+            Instr::Block(BlockType::Value(ValType::I32)),
+            Instr::I64Const(-1),
+            Instr::LocalSet(0),
+            Instr::I32Const(7),
+            Instr::I32Const(1),
+            Instr::BrIf(0),
+            Instr::Drop,
+            Instr::F64Const(0.5),
+            Instr::Numeric(Op::I32TruncF64S),
+            Instr::End,
+            Instr::Memory(Access::I32Load, 16),
+        ]);
+        module.table = vec![0];
+        module.exports.push(Export {
+            name: "test".to_string(),
+            kind: ExportKind::Function,
+            index: 0,
+        });
+        module.data.push(Data {
+            offset: 8,
+            bytes: b"hello".to_vec(),
+        });
+        let decoded = decode(&module.encode()).unwrap();
+        assert_eq!(
+            Module {
+                functions: vec![Function {
+                    name: "f0".to_string(),
+                    ..module.functions[0].clone()
+                }],
+                ..module.clone()
+            },
+            decoded
+        );
+        assert_eq!(validate(&decoded), Ok(()));
+    }
+
+    #[test]
+    fn test_malformed_modules() {
+        assert_eq!(decode(b"\0elf\x01\0\0\0"), Err(WasmError::NotWasm));
+        assert_eq!(
+            decode(b"\0asm\x02\0\0\0"),
+            Err(WasmError::UnsupportedVersion(2))
+        );
+        let bytes = module(vec![Instr::I32Const(1)]).encode();
+        assert_eq!(decode(&bytes[..bytes.len() - 1]), Err(WasmError::Truncated));
+    }
+
+    #[test]
+    fn test_ill_typed_bodies() {
+        let error = |body: Vec<Instr>| match validate(&module(body)) {
+            Err(WasmError::Invalid { reason, .. }) => reason,
+            other => panic!("expected an error, got {:?}", other),
+        };
+        assert_eq!(
+            error(vec![Instr::I64Const(1)]),
+            "at the end of the body: expected i32 but found i64"
+        );
+        assert_eq!(
+            error(vec![Instr::I32Const(1), Instr::I32Const(2)]),
+            "at the end of the body: values are left on the operand stack"
+        );
+        assert_eq!(
+            error(vec![Instr::Numeric(Op::I32Add)]),
+            "at instruction 0 (i32.add): the operand stack is empty"
+        );
+        assert_eq!(
+            error(vec![Instr::Br(1), Instr::End]),
+            "at instruction 0 (br 1): label 1 does not exist"
+        );
+        assert_eq!(
+            error(vec![
+                Instr::I32Const(1),
+                Instr::If(BlockType::Value(ValType::I32)),
+                Instr::I32Const(2),
+                Instr::End,
+            ]),
+            "at instruction 3 (end): an if with a result needs an else"
+        );
+        assert_eq!(
+            error(vec![Instr::LocalGet(1)]),
+            "at instruction 0 (local.get 1): local 1 does not exist"
+        );
+        // Code after an unconditional branch may pop values that were never pushed
+        assert_eq!(
+            validate(&module(vec![
+                Instr::Unreachable,
+                Instr::Numeric(Op::I32Add)
+            ])),
+            Ok(())
+        );
+    }
+}
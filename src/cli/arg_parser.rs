@@ -1,19 +1,92 @@
-use std::fmt;
+use crate::back_end::ir::passes::OptLevel;
 use std::error::Error;
-use std::path::{Path, PathBuf};
+use std::fmt;
+use std::path::PathBuf;
 
 pub enum Command {
-    Build(PathBuf),
+    Build(PathBuf, BuildOptions),
     Run(PathBuf),
     Help,
     Version,
 }
 
+/// Options of `crawfish build`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildOptions {
+    pub target: Target,
+    pub backend: Backend,
+    pub emit: Option<Emit>,
+    pub opt_level: OptLevel,
+}
+
+/// What `crawfish build` produces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Target {
+    /// A native executable
+    #[default]
+    Native,
+    /// A `.crwb` bytecode file, run with `crawfish run`
+    Bytecode,
+    /// A `.wasm` WebAssembly module, run by a host providing its imports
+    Wasm32,
+}
+
+/// How `crawfish build` produces a native executable
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Through C, compiled by the system C compiler
+    #[default]
+    C,
+    /// Through LLVM IR, compiled by `llc`
+    Llvm,
+    /// Through x86-64 assembly, assembled and linked by `as` and `ld`
+    Asm,
+}
+
+/// A stage of compilation that `crawfish build` stops after, printing what it produced instead of
+/// an executable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// The tokens of the entry file, printed
+    Tokens,
+    /// The syntax tree of the entry file, printed
+    Ast,
+    /// The syntax tree of the whole program with the type of every expression, printed
+    TypedAst,
+    /// The SSA IR after optimization, printed
+    Ir,
+    /// C source of the `c` backend, printed
+    C,
+    /// Textual LLVM IR of the `llvm` backend, printed
+    LlvmIr,
+    /// x86-64 assembly of the `asm` backend, printed
+    Asm,
+    /// The WebAssembly text format of the `wasm32` target, printed
+    Wat,
+}
+
+impl Emit {
+    /// The target and, for native code, the backend whose output this stage is, if it is one
+    /// stage of a single backend
+    fn output_of(self) -> Option<(Target, Option<Backend>)> {
+        match self {
+            Emit::Tokens | Emit::Ast | Emit::TypedAst | Emit::Ir => None,
+            Emit::C => Some((Target::Native, Some(Backend::C))),
+            Emit::LlvmIr => Some((Target::Native, Some(Backend::Llvm))),
+            Emit::Asm => Some((Target::Native, Some(Backend::Asm))),
+            Emit::Wat => Some((Target::Wasm32, None)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum CLIError {
     FileNotFound(String),
-    InvalidFileExtension,
+    InvalidFileExtension(String),
     InvalidCommand(String),
+    InvalidOption(String),
+    UnexpectedArgument(String),
+    ConflictingOptions(String, String),
     MissingArgument,
 }
 
@@ -21,8 +94,15 @@ impl fmt::Display for CLIError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CLIError::FileNotFound(path) => write!(f, "File not found ({})", path),
-            CLIError::InvalidFileExtension => write!(f, "Invalid file extension (expected .crw)"),
+            CLIError::InvalidFileExtension(expected) => {
+                write!(f, "Invalid file extension (expected {})", expected)
+            }
             CLIError::InvalidCommand(cmd) => write!(f, "Invalid command ({})", cmd),
+            CLIError::InvalidOption(option) => write!(f, "Invalid option ({})", option),
+            CLIError::UnexpectedArgument(arg) => write!(f, "Unexpected argument ({})", arg),
+            CLIError::ConflictingOptions(first, second) => {
+                write!(f, "Conflicting options ({} and {})", first, second)
+            }
             CLIError::MissingArgument => {
                 write!(
                     f,
@@ -38,22 +118,68 @@ impl Error for CLIError {}
 pub fn parse_args(args: &[String]) -> Result<Command, CLIError> {
     let command = args.get(1).map(String::as_str);
     match (args.len(), command) {
-        (3, Some("build" | "run")) => {
-            let source_path = PathBuf::from(&args[2]);
-
-            if !source_path.exists() || !source_path.is_file() {
-                return Err(CLIError::FileNotFound(args[2].clone()));
+        (3.., Some("build")) => {
+            let mut options = BuildOptions::default();
+            let mut paths = Vec::new();
+            // The last of each option given, to reject the combinations that contradict each other
+            let (mut target, mut backend, mut emit) = (None, None, None);
+            for arg in &args[2..] {
+                match arg.split_once('=') {
+                    Some(("--target", _)) => target = Some(arg),
+                    Some(("--backend", _)) => backend = Some(arg),
+                    Some(("--emit", _)) => emit = Some(arg),
+                    _ => (),
+                }
+                match arg.split_once('=') {
+                    Some(("--target", "native")) => options.target = Target::Native,
+                    Some(("--target", "bytecode")) => options.target = Target::Bytecode,
+                    Some(("--target", "wasm32")) => options.target = Target::Wasm32,
+                    Some(("--backend", "c")) => options.backend = Backend::C,
+                    Some(("--backend", "llvm")) => options.backend = Backend::Llvm,
+                    Some(("--backend", "asm")) => options.backend = Backend::Asm,
+                    Some(("--emit", "tokens")) => options.emit = Some(Emit::Tokens),
+                    Some(("--emit", "ast")) => options.emit = Some(Emit::Ast),
+                    Some(("--emit", "typed-ast")) => options.emit = Some(Emit::TypedAst),
+                    Some(("--emit", "ir")) => options.emit = Some(Emit::Ir),
+                    Some(("--emit", "c")) => options.emit = Some(Emit::C),
+                    Some(("--emit", "llvm-ir")) => options.emit = Some(Emit::LlvmIr),
+                    Some(("--emit", "asm")) => options.emit = Some(Emit::Asm),
+                    Some(("--emit", "wat")) => options.emit = Some(Emit::Wat),
+                    None if arg == "-O0" => options.opt_level = OptLevel::O0,
+                    None if arg == "-O1" => options.opt_level = OptLevel::O1,
+                    None if arg == "-O2" => options.opt_level = OptLevel::O2,
+                    _ if arg.starts_with('-') => return Err(CLIError::InvalidOption(arg.clone())),
+                    _ => paths.push(arg),
+                }
             }
-
-            if !has_crw_extension(&source_path) {
-                return Err(CLIError::InvalidFileExtension);
+            let conflict = |first: &String, second: &String| {
+                CLIError::ConflictingOptions(first.clone(), second.clone())
+            };
+            if let (Some(target), Some(backend)) = (target, backend) {
+                if options.target != Target::Native {
+                    return Err(conflict(backend, target));
+                }
+            }
+            if let (Some(emit), Some((emit_target, emit_backend))) =
+                (emit, options.emit.and_then(Emit::output_of))
+            {
+                match (target, backend) {
+                    (Some(target), _) if options.target != emit_target => {
+                        return Err(conflict(emit, target))
+                    }
+                    (_, Some(backend)) if Some(options.backend) != emit_backend => {
+                        return Err(conflict(emit, backend))
+                    }
+                    _ => (),
+                }
+            }
+            match paths[..] {
+                [path] => Ok(Command::Build(source_file(path, &["crw"])?, options)),
+                [] => Err(CLIError::MissingArgument),
+                [_, extra, ..] => Err(CLIError::UnexpectedArgument(extra.clone())),
             }
-
-            Ok(match command.unwrap() {
-                "build" => Command::Build(source_path),
-                _ => Command::Run(source_path),
-            })
         }
+        (3, Some("run")) => Ok(Command::Run(source_file(&args[2], &["crw", "crwb"])?)),
         (2, Some("-h" | "--help")) => Ok(Command::Help),
         (2, Some("-v" | "--version")) => Ok(Command::Version),
         (_, Some(command)) => Err(CLIError::InvalidCommand(command.to_string())),
@@ -68,7 +194,25 @@ Usage: crawfish [OPTIONS] [ARGUMENT]
 
 Options:
     build [file].crw              compile the current file
+        --target=native           produce an executable (default)
+        --target=bytecode         produce a [file].crwb bytecode file
+        --target=wasm32           produce a [file].wasm WebAssembly module
+        --backend=c               build the executable through C (default)
+        --backend=llvm            build the executable through LLVM IR
+        --backend=asm             build the executable through x86-64 assembly
+        --emit=tokens             print the tokens of [file].crw instead
+        --emit=ast                print the syntax tree of [file].crw instead
+        --emit=typed-ast          print the type checked syntax tree instead
+        --emit=ir                 print the optimized SSA IR instead
+        --emit=c                  print the C source of the c backend instead
+        --emit=llvm-ir            print the LLVM IR of the llvm backend instead
+        --emit=asm                print the x86-64 assembly of the asm backend instead
+        --emit=wat                print the WebAssembly text of wasm32 instead
+        -O0                       do not optimize (default)
+        -O1                       optimize within functions
+        -O2                       also inline functions and hoist loop invariants
     run [file].crw                run the current file
+    run [file].crwb               run a bytecode file
     -h, --help                    print possible commands
     -v, --version                 print compiler version"#;
     println!("{}", message);
@@ -79,6 +223,79 @@ pub fn version() {
     println!("{}", message);
 }
 
-fn has_crw_extension(p: &Path) -> bool {
-    p.extension().and_then(|ext| ext.to_str()) == Some("crw")
+/// Checks that `arg` names an existing file with one of the `extensions`
+fn source_file(arg: &str, extensions: &[&str]) -> Result<PathBuf, CLIError> {
+    let source_path = PathBuf::from(arg);
+
+    if !source_path.exists() || !source_path.is_file() {
+        return Err(CLIError::FileNotFound(arg.to_string()));
+    }
+
+    let extension = source_path.extension().and_then(|ext| ext.to_str());
+    if !extensions
+        .iter()
+        .any(|expected| extension == Some(expected))
+    {
+        let expected: Vec<String> = extensions.iter().map(|ext| format!(".{}", ext)).collect();
+        return Err(CLIError::InvalidFileExtension(expected.join(" or ")));
+    }
+
+    Ok(source_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `crawfish build <a program> <options>`
+    fn build_options(options: &[&str]) -> Result<BuildOptions, CLIError> {
+        let program = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/back_end/ir/golden/loops.crw"
+        );
+        let mut args = vec![
+            "crawfish".to_string(),
+            "build".to_string(),
+            program.to_string(),
+        ];
+        args.extend(options.iter().map(|option| option.to_string()));
+        match parse_args(&args)? {
+            Command::Build(_, options) => Ok(options),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_emitted_stages_match_the_target_and_backend() {
+        let options = build_options(&["--emit=llvm-ir", "--backend=llvm", "-O2"]).unwrap();
+        assert_eq!(options.emit, Some(Emit::LlvmIr));
+        assert_eq!(options.opt_level, OptLevel::O2);
+        assert!(build_options(&["--emit=wat", "--target=wasm32"]).is_ok());
+        assert!(build_options(&["--emit=ir", "--target=wasm32", "--backend=c"]).is_err());
+        assert!(build_options(&["--emit=ir", "--target=bytecode"]).is_ok());
+
+        for (options, first, second) in [
+            (
+                &["--emit=c", "--target=wasm32"][..],
+                "--emit=c",
+                "--target=wasm32",
+            ),
+            (&["--backend=asm", "--emit=c"], "--emit=c", "--backend=asm"),
+            (
+                &["--emit=wat", "--backend=llvm"],
+                "--emit=wat",
+                "--backend=llvm",
+            ),
+            (
+                &["--target=bytecode", "--backend=c"],
+                "--backend=c",
+                "--target=bytecode",
+            ),
+        ] {
+            match build_options(options) {
+                Err(CLIError::ConflictingOptions(a, b)) => assert_eq!((&*a, &*b), (first, second)),
+                other => panic!("{:?}: {:?}", options, other),
+            }
+        }
+    }
 }
@@ -1,10 +1,128 @@
+use crate::back_end::ir::passes;
+use crate::back_end::{asm, bytecode, c, ir, llvm, wasm};
+use crate::cli::arg_parser::{Backend, BuildOptions, Emit, Target};
 use crate::front_end;
+use crate::front_end::diagnostic::{Diagnostic, Severity};
+use crate::front_end::dump;
+use crate::front_end::modules::SourceFile;
+use crate::front_end::parser::{self, ParserError};
+use crate::front_end::Analysis;
+use std::env;
 use std::error::Error;
 use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
 
-pub fn build(p: &Path) -> Result<(), Box<dyn Error>> {
-    let source = fs::read_to_string(&p)?;
-    let lexer = front_end::lexer::Lexer::new(&source);
+/// Compiles the program whose entry file is `p`, along with every module it imports
+pub fn build(p: &Path, options: &BuildOptions) -> Result<(), Box<dyn Error>> {
+    match options.emit {
+        Some(Emit::Tokens) => return dump_entry(p, dump::tokens),
+        Some(Emit::Ast) => {
+            return dump_entry(p, |source| Ok(dump::ast(&parser::parse(source)?, false)));
+        }
+        _ => {}
+    }
+    let (files, analysis) = analyze(p)?;
+    if options.emit == Some(Emit::TypedAst) {
+        io::stdout().write_all(dump::ast(&analysis.program, true).as_bytes())?;
+        return Ok(());
+    }
+    let mut program = ir::build(&analysis.program);
+    passes::optimize(&mut program, options.opt_level);
+    let dump = match options.emit {
+        Some(Emit::Ir) => program.to_string(),
+        Some(Emit::C) => c::generate(&program, &files),
+        Some(Emit::LlvmIr) => llvm::generate(&program, &files),
+        Some(Emit::Asm) => asm::generate(&program, &files),
+        Some(Emit::Wat) => wasm::generate(&program, &files).to_string(),
+        _ => return produce(p, options, &program, &files),
+    };
+    io::stdout().write_all(dump.as_bytes())?;
     Ok(())
 }
+
+/// Builds what `options.target` asks for from the optimized IR of the program at `p`
+fn produce(
+    p: &Path,
+    options: &BuildOptions,
+    program: &ir::Program,
+    files: &[SourceFile],
+) -> Result<(), Box<dyn Error>> {
+    match options.target {
+        Target::Native => {
+            // The executable is placed next to the source file, e.g. `hello.crw` builds `hello`
+            let output = p.with_extension("");
+            let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+            match options.backend {
+                Backend::C => {
+                    let source = c::generate(program, files);
+                    c::compile(&source, &output, &compiler)?;
+                }
+                Backend::Llvm => {
+                    let ir = llvm::generate(program, files);
+                    llvm::compile(&ir, &output, &compiler)?;
+                }
+                Backend::Asm => {
+                    asm::assemble(&asm::generate(program, files), &output)?;
+                }
+            }
+        }
+        Target::Bytecode => {
+            let bytecode = bytecode::compile_ir(program);
+            fs::write(
+                p.with_extension("crwb"),
+                bytecode::file::encode(&bytecode, files),
+            )?;
+        }
+        Target::Wasm32 => {
+            let module = wasm::generate(program, files);
+            fs::write(p.with_extension("wasm"), module.encode())?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads and analyzes the program whose entry file is `p`, reporting its diagnostics
+pub fn analyze(p: &Path) -> Result<(Vec<SourceFile>, Analysis), Box<dyn Error>> {
+    let source = fs::read_to_string(p)?;
+    let mut read = |path: &Path| fs::read_to_string(path);
+    let (files, analysis) = front_end::analyze_program(p, source, &mut read);
+    let analysis = match analysis {
+        Ok(analysis) => analysis,
+        Err(diagnostics) => {
+            report(&files, &diagnostics);
+            let errors = diagnostics
+                .iter()
+                .filter(|d| d.severity == Severity::Error)
+                .count();
+            return Err(format!("{} error(s) emitted", errors).into());
+        }
+    };
+    report(&files, &analysis.warnings);
+    Ok((files, analysis))
+}
+
+/// Prints what `dump` makes of the source of the entry file `p`, or the error it stops at
+fn dump_entry(
+    p: &Path,
+    dump: impl FnOnce(&str) -> Result<String, ParserError>,
+) -> Result<(), Box<dyn Error>> {
+    let source = fs::read_to_string(p)?;
+    match dump(&source) {
+        Ok(text) => Ok(io::stdout().write_all(text.as_bytes())?),
+        Err(e) => {
+            let diagnostic = Diagnostic::error(&e, e.span());
+            eprintln!("{}\n", diagnostic.render(&p.display().to_string(), &source));
+            Err("1 error(s) emitted".into())
+        }
+    }
+}
+
+/// Prints every diagnostic to stderr, against the file it points into
+pub fn report(files: &[SourceFile], diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        let file = &files[diagnostic.file];
+        let path = file.path.display().to_string();
+        eprintln!("{}\n", diagnostic.render(&path, &file.source));
+    }
+}
@@ -1,6 +1,26 @@
+use crate::back_end::bytecode;
+use crate::cli::builder;
+use crate::runtime::vm;
 use std::error::Error;
+use std::fs;
+use std::io::{self, BufWriter};
 use std::path::Path;
 
-pub fn run(_path: &Path) -> Result<(), Box<dyn Error>> {
-    Err("Runner not implemented yet".into())
+/// Runs the program whose entry file is `p`, starting at `main()`.
+/// A `.crwb` file is loaded as is, and a source file is compiled to bytecode first.
+pub fn run(p: &Path) -> Result<(), Box<dyn Error>> {
+    let (bytecode, files) = if p.extension().is_some_and(|ext| ext == "crwb") {
+        bytecode::file::decode(&fs::read(p)?)?
+    } else {
+        let (files, analysis) = builder::analyze(p)?;
+        (bytecode::compile(&analysis.program), files)
+    };
+    let mut out = BufWriter::new(io::stdout());
+    if let Err(panic) = vm::run(&bytecode, &mut io::stdin().lock(), &mut out) {
+        let file = &files[panic.file];
+        let path = file.path.display().to_string();
+        eprintln!("{}", panic.render(&path, &file.source));
+        return Err(panic.into());
+    }
+    Ok(())
 }
@@ -1,8 +1,193 @@
+use diagnostic::Diagnostic;
+use modules::{ModuleGraph, SourceFile};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
 // syntactic analysis
 pub mod ast;
 pub mod parser;
 // lexical analysis
 pub mod lexer;
 pub mod token;
+// module loading
+pub mod modules;
 // semantic analysis
+pub mod control_flow;
+pub mod format;
 pub mod type_checker;
+pub mod types;
+// error reporting
+pub mod diagnostic;
+// debugging dumps
+pub mod dump;
+
+/// The type checked program, along with any warnings raised along the way.
+/// The functions of every module are merged into `program` under their linked names.
+pub struct Analysis {
+    pub program: ast::Program,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Runs every front end phase over a single file that imports nothing
+pub fn analyze(source: &str) -> Result<Analysis, Vec<Diagnostic>> {
+    let mut read = |_: &Path| Err(io::ErrorKind::NotFound.into());
+    analyze_program(Path::new("main.crw"), source.to_string(), &mut read).1
+}
+
+/// Runs every front end phase over the program whose entry file is at `entry` and contains `source`:
+/// loading the modules it imports (read with `read`), parsing, type checking, then control-flow analysis.
+/// Returns every source file of the program, which diagnostics refer to by index.
+/// On failure, every error is returned together with the warnings found so far.
+pub fn analyze_program(
+    entry: &Path,
+    source: String,
+    read: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> (Vec<SourceFile>, Result<Analysis, Vec<Diagnostic>>) {
+    let ModuleGraph {
+        files,
+        programs,
+        imports,
+        order,
+        diagnostics,
+    } = modules::load(entry, source, read);
+    if !diagnostics.is_empty() {
+        return (files, Err(diagnostics));
+    }
+    let mut programs: Vec<ast::Program> = programs.into_iter().map(Option::unwrap).collect();
+
+    // Modules are checked after the modules they import, whose interfaces are then known
+    let mut interfaces = vec![type_checker::ModuleInterface::default(); files.len()];
+    let mut diagnostics = Vec::new();
+    for &file in &order {
+        let imported: HashMap<_, _> = imports[file]
+            .iter()
+            .map(|(alias, &target)| (alias.clone(), interfaces[target].clone()))
+            .collect();
+        let module = files[file].module.as_deref();
+        let (interface, errors) = type_checker::check_module(&mut programs[file], module, imported);
+        interfaces[file] = interface;
+        if !errors.is_empty() {
+            diagnostics.extend(
+                errors
+                    .iter()
+                    .map(|e| Diagnostic::error(e, e.span()).in_file(file)),
+            );
+            continue;
+        }
+
+        let report = control_flow::analyze(&programs[file]);
+        diagnostics.extend(
+            report
+                .errors
+                .iter()
+                .map(|e| Diagnostic::error(e, e.span()))
+                .chain(
+                    report
+                        .warnings
+                        .iter()
+                        .map(|w| Diagnostic::warning(w, w.span())),
+                )
+                .map(|d| d.in_file(file)),
+        );
+    }
+    diagnostics.sort_by_key(|d| (d.file, d.span.start));
+
+    if diagnostics
+        .iter()
+        .any(|d| d.severity == diagnostic::Severity::Error)
+    {
+        return (files, Err(diagnostics));
+    }
+    let mut items = Vec::new();
+    for &file in &order {
+        for mut item in std::mem::take(&mut programs[file].items) {
+            let ast::Item::Function(function) = &mut item;
+            function.file = file;
+            items.push(item);
+        }
+    }
+    let program = ast::Program {
+        imports: Vec::new(),
+        items,
+    };
+    let analysis = Analysis {
+        program,
+        warnings: diagnostics,
+    };
+    (files, Ok(analysis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn analyze_files(
+        files: &[(&str, &str)],
+    ) -> (Vec<SourceFile>, Result<Analysis, Vec<Diagnostic>>) {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect();
+        let entry = Path::new("main.crw");
+        analyze_program(entry, files[entry].clone(), &mut |path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        })
+    }
+
+    #[test]
+    fn test_modules_are_merged() {
+        let (files, analysis) = analyze_files(&[
+            (
+                "main.crw",
+                "import math; func main() { println(math::double(2)); }",
+            ),
+            (
+                "math.crw",
+                "pub func double(x: Int) -> Int { return x * 2; }",
+            ),
+        ]);
+        assert_eq!(files.len(), 2);
+        let names: Vec<String> = analysis
+            .unwrap()
+            .program
+            .items
+            .iter()
+            .map(|item| {
+                let ast::Item::Function(function) = item;
+                function.name.name.clone()
+            })
+            .collect();
+        assert_eq!(names, ["math::double", "main"]);
+    }
+
+    #[test]
+    fn test_diagnostics_point_into_their_file() {
+        let (files, analysis) = analyze_files(&[
+            ("main.crw", "import math; func main() { math::half(2); }"),
+            (
+                "math.crw",
+                "func half(x: Int) -> Int { return x / 2; }\nfunc f() -> Int { }",
+            ),
+        ]);
+        let diagnostics = analysis.err().unwrap();
+        let rendered: Vec<String> = diagnostics
+            .iter()
+            .map(|d| {
+                let file = &files[d.file];
+                d.render(&file.path.display().to_string(), &file.source)
+            })
+            .collect();
+        assert_eq!(
+            rendered,
+            [
+                "main.crw:1:28: error: Function `math::half` is private; declare it with `pub` to use it from other modules\nimport math; func main() { math::half(2); }\n                           ^^^^^^^^^^",
+                "math.crw:2:19: error: Function `math::f` can reach its end without returning a value\nfunc f() -> Int { }\n                  ^",
+            ]
+        );
+    }
+}
@@ -1,27 +1,309 @@
 //! Abstract syntax tree data structure
+//! Every node carries the span of source it was parsed from, and every expression carries a `ty`
+//! slot that starts out as `Type::Error` and is filled in by the type checker (i.e. the annotated AST).
 use crate::front_end::token::Span;
+use crate::front_end::types::Type;
 
-pub struct ASTNode {
+/// A whole source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub imports: Vec<Import>,
+    pub items: Vec<Item>,
+}
+
+/// `import a::b;`, which makes the public functions of module `a::b` available as `b::<name>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub path: Vec<Ident>,
+    pub span: Span,
+}
+
+impl Import {
+    /// The name that qualifies the imported module's functions, i.e. the last segment of its path
+    pub fn alias(&self) -> &Ident {
+        self.path.last().unwrap()
+    }
+}
+
+/// A top level declaration
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Function(Function),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// Whether other modules may use the function, i.e. it is declared with `pub`
+    pub public: bool,
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeExpr>,
+    pub body: Block,
+    pub span: Span,
+    /// Index of the source file the function is declared in, among the files of the program
+    pub file: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: TypeExpr,
+}
+
+/// A local variable or parameter, numbered by the type checker in the order of declaration within
+/// a module, so that variables with the same name are told apart
+pub type VariableId = usize;
+
+/// A name as written in the source
+/// - `variable` is the variable the name declares, filled in by the type checker for parameters,
+///   `var` and `const` statements and `for` loop items
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+    pub variable: Option<VariableId>,
+}
+
+/// A type as written in the source (e.g. the `Int` in `var x: Int = 5;`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeExpr {
+    pub kind: TypeExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeExprKind {
+    Named(String),
+    Unit,
+    /// `T?`, which is either a `T` or `null`
+    Optional(Box<TypeExpr>),
+    /// A type with type arguments, e.g. `Result[Int, String]`
+    Generic {
+        name: String,
+        args: Vec<TypeExpr>,
+    },
+    /// `func(A, B) -> R`, the type of functions and closures; no return type means `()`
+    Function {
+        params: Vec<TypeExpr>,
+        return_type: Option<Box<TypeExpr>>,
+    },
+}
+
+/// A `{ ... }` block.
+/// The optional `tail` is a trailing expression without a semicolon, which becomes the block's value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub tail: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
     pub span: Span,
 }
 
-impl ASTNode {
-    pub fn new(start: usize, end: usize) -> Self {
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    /// `var` (mutable) or `const` (immutable) declaration
+    Var {
+        name: Ident,
+        mutable: bool,
+        ty: Option<TypeExpr>,
+        value: Expr,
+    },
+    /// `target = value`, or `target op= value` when `op` is present
+    Assign {
+        target: Expr,
+        op: Option<BinaryOp>,
+        value: Expr,
+    },
+    Expr(Expr),
+    While {
+        cond: Expr,
+        body: Block,
+    },
+    For {
+        item: Ident,
+        iterable: Expr,
+        body: Block,
+    },
+    Return(Option<Expr>),
+    /// `defer expr;`, which evaluates `expr` when control leaves the enclosing block, after every
+    /// statement deferred later in it
+    Defer(Expr),
+    Break,
+    Continue,
+}
+
+/// An expression
+/// - `variable` is the variable an identifier refers to, filled in by the type checker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    pub ty: Type,
+    pub variable: Option<VariableId>,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
         Self {
-            span: Span { start, end },
+            kind,
+            span,
+            ty: Type::Error,
+            variable: None,
         }
     }
+
+    /// Whether the expression ends in a block, and thus needs no `;` when used as a statement
+    pub fn is_block_like(&self) -> bool {
+        matches!(self.kind, ExprKind::If { .. } | ExprKind::Block(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Identifier(String),
+    /// `module::name`, a function of an imported module
+    Qualified {
+        module: String,
+        name: String,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        left: Box<Expr>,
+        op: BinaryOp,
+        right: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    If {
+        cond: Box<Expr>,
+        then_branch: Block,
+        /// Either a `Block` expression or another `If` expression (i.e. `else if`)
+        else_branch: Option<Box<Expr>>,
+    },
+    Block(Block),
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+        inclusive: bool,
+    },
+    /// `expr?`, which unwraps an `Ok`, or returns an `Err` from the enclosing function
+    Try(Box<Expr>),
+    /// An anonymous function expression, e.g. `func(x: Int) -> Int { return x * 2; }`
+    Lambda(Box<Lambda>),
+    /// A string literal with embedded expressions, e.g. `"{name} is {age} years old"`
+    Interpolation(Vec<StringPart>),
+}
+
+/// A piece of an interpolated string: either text, or an expression whose value is converted to text
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    Expr(Expr),
+}
+
+/// An anonymous function
+/// - `captures` lists the variables of enclosing functions that the body uses, in order of first use.
+///   It starts out empty and is filled in by the type checker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda {
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeExpr>,
+    pub body: Block,
+    pub captures: Vec<Capture>,
 }
 
-trait Expression {}
-// literal struct: self.value
-// identifier struct: self.id
-// Unary op struct: self.op, self.operand
-// binary op struct: self.left, self.op, self.right
-// function call struct: self.func, self.args
+/// A variable that a closure uses from an enclosing function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub name: String,
+    pub variable: VariableId,
+    pub ty: Type,
+}
 
-trait Statement {}
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Null,
+}
 
-trait Declaration {}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    BitNot,
+}
 
-trait Program {}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    /// `??`, which unwraps an optional or falls back to a default when it is `null`
+    Coalesce,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Remainder => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Coalesce => "??",
+        }
+    }
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::BitNot => "~",
+        }
+    }
+}
@@ -0,0 +1,713 @@
+//! Control-flow analysis that runs after type checking.
+//! Each function body is lowered into a graph of basic blocks, which is then used to find
+//! functions that can fall off their end without returning, statements that can never run,
+//! `break`/`continue` statements outside of any loop, and jumps out of a `defer`.
+//! Anonymous functions get graphs of their own, since their bodies do not run where they are written.
+use crate::front_end::ast::{
+    Block, Expr, ExprKind, Item, Lambda, Literal, Program, Stmt, StmtKind, StringPart, TypeExpr,
+    TypeExprKind,
+};
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum ControlFlowError {
+    /// `name` is `None` for an anonymous function
+    MissingReturn {
+        name: Option<String>,
+        span: Span,
+    },
+    BreakOutsideLoop(Span),
+    ContinueOutsideLoop(Span),
+    /// `return`, `break`, `continue` or `?`, named by `keyword`, leaving a deferred expression
+    JumpOutOfDefer {
+        keyword: &'static str,
+        span: Span,
+    },
+}
+
+impl ControlFlowError {
+    pub fn span(&self) -> Span {
+        match self {
+            ControlFlowError::MissingReturn { span, .. } => *span,
+            ControlFlowError::BreakOutsideLoop(span) => *span,
+            ControlFlowError::ContinueOutsideLoop(span) => *span,
+            ControlFlowError::JumpOutOfDefer { span, .. } => *span,
+        }
+    }
+}
+
+impl fmt::Display for ControlFlowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlFlowError::MissingReturn {
+                name: Some(name), ..
+            } => write!(
+                f,
+                "Function `{}` can reach its end without returning a value",
+                name
+            ),
+            ControlFlowError::MissingReturn { name: None, .. } => write!(
+                f,
+                "Anonymous function can reach its end without returning a value"
+            ),
+            ControlFlowError::BreakOutsideLoop(_) => write!(f, "`break` outside of a loop"),
+            ControlFlowError::ContinueOutsideLoop(_) => write!(f, "`continue` outside of a loop"),
+            ControlFlowError::JumpOutOfDefer { keyword, .. } => {
+                write!(f, "`{}` cannot leave a deferred expression", keyword)
+            }
+        }
+    }
+}
+
+impl Error for ControlFlowError {}
+
+#[derive(Debug, PartialEq)]
+pub enum ControlFlowWarning {
+    UnreachableCode(Span),
+}
+
+impl ControlFlowWarning {
+    pub fn span(&self) -> Span {
+        match self {
+            ControlFlowWarning::UnreachableCode(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for ControlFlowWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlFlowWarning::UnreachableCode(_) => write!(f, "Unreachable code"),
+        }
+    }
+}
+
+/// Everything found by the control-flow analysis of a program
+#[derive(Debug, Default, PartialEq)]
+pub struct ControlFlowReport {
+    pub errors: Vec<ControlFlowError>,
+    pub warnings: Vec<ControlFlowWarning>,
+}
+
+pub type BlockId = usize;
+
+/// A straight-line run of statements, recorded as indices into `ControlFlowGraph::statements`
+#[derive(Debug, Default, PartialEq)]
+pub struct BasicBlock {
+    pub statements: Vec<usize>,
+    pub successors: Vec<BlockId>,
+    pub predecessors: Vec<BlockId>,
+}
+
+/// A statement (or block tail expression) and the block it starts in.
+/// `anchor` is the statement executed just before it in the source: its preceding sibling,
+/// or the enclosing statement when it is the first one in its block.
+#[derive(Debug, PartialEq)]
+pub struct Statement {
+    pub span: Span,
+    pub block: BlockId,
+    pub anchor: Option<usize>,
+}
+
+/// Control-flow graph of a single function
+/// - `entry` is where the body starts
+/// - `exit` is the block every `return` jumps to
+/// - `fall_through` is the block that runs off the end of the body
+/// - `lambdas` are the anonymous functions written in the body, whose own bodies are not part of this graph
+pub struct ControlFlowGraph<'a> {
+    pub blocks: Vec<BasicBlock>,
+    pub statements: Vec<Statement>,
+    pub entry: BlockId,
+    pub exit: BlockId,
+    pub fall_through: BlockId,
+    pub lambdas: Vec<&'a Lambda>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    /// Builds the graph of a function body, along with any misplaced `break` or `continue`
+    pub fn build(body: &'a Block) -> (Self, Vec<ControlFlowError>) {
+        let mut builder = GraphBuilder {
+            blocks: Vec::new(),
+            statements: Vec::new(),
+            previous: None,
+            current: 0,
+            loops: Vec::new(),
+            deferred_loops: None,
+            exit: 0,
+            lambdas: Vec::new(),
+            errors: Vec::new(),
+        };
+        let entry = builder.new_block();
+        let exit = builder.new_block();
+        builder.current = entry;
+        builder.exit = exit;
+        builder.block(body);
+        let fall_through = builder.current;
+        builder.edge(fall_through, exit);
+
+        let graph = Self {
+            blocks: builder.blocks,
+            statements: builder.statements,
+            entry,
+            exit,
+            fall_through,
+            lambdas: builder.lambdas,
+        };
+        (graph, builder.errors)
+    }
+
+    /// Returns whether each block can be reached from the entry
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![self.entry];
+        while let Some(id) = stack.pop() {
+            if reachable[id] {
+                continue;
+            }
+            reachable[id] = true;
+            stack.extend(&self.blocks[id].successors);
+        }
+        reachable
+    }
+}
+
+/// Runs the control-flow analysis over every function of a type checked program
+pub fn analyze(program: &Program) -> ControlFlowReport {
+    let mut report = ControlFlowReport::default();
+    for item in &program.items {
+        let Item::Function(function) = item;
+        let name = Some(function.name.name.clone());
+        analyze_function(
+            name,
+            function.return_type.as_ref(),
+            &function.body,
+            &mut report,
+        );
+    }
+    report
+}
+
+fn analyze_function(
+    name: Option<String>,
+    return_type: Option<&TypeExpr>,
+    body: &Block,
+    report: &mut ControlFlowReport,
+) {
+    let (graph, errors) = ControlFlowGraph::build(body);
+    report.errors.extend(errors);
+    let reachable = graph.reachable();
+
+    // Only the first statement of a dead region is reported, i.e. an unreachable statement
+    // whose anchor is reachable. Everything after or nested inside it is part of the same region.
+    let is_reachable = |statement: &Statement| reachable[statement.block];
+    for statement in &graph.statements {
+        let anchor_reachable = statement
+            .anchor
+            .is_none_or(|anchor| is_reachable(&graph.statements[anchor]));
+        if !is_reachable(statement) && anchor_reachable {
+            report
+                .warnings
+                .push(ControlFlowWarning::UnreachableCode(statement.span));
+        }
+    }
+
+    let returns_value = !matches!(
+        return_type.map(|ty| &ty.kind),
+        None | Some(TypeExprKind::Unit)
+    );
+    if returns_value && reachable[graph.fall_through] {
+        let end = body.span.end;
+        report.errors.push(ControlFlowError::MissingReturn {
+            name,
+            span: Span::new(end - 1, end),
+        });
+    }
+
+    for lambda in graph.lambdas {
+        analyze_function(None, lambda.return_type.as_ref(), &lambda.body, report);
+    }
+}
+
+/// Loop targets for `continue` and `break` respectively
+struct LoopTargets {
+    header: BlockId,
+    after: BlockId,
+}
+
+/// Graph builder
+/// - `deferred_loops` is the number of loops around the innermost `defer` being built, if any,
+///   whose expression only runs when its block ends and thus cannot jump anywhere
+struct GraphBuilder<'a> {
+    blocks: Vec<BasicBlock>,
+    statements: Vec<Statement>,
+    previous: Option<usize>,
+    current: BlockId,
+    loops: Vec<LoopTargets>,
+    deferred_loops: Option<usize>,
+    exit: BlockId,
+    lambdas: Vec<&'a Lambda>,
+    errors: Vec<ControlFlowError>,
+}
+
+impl<'a> GraphBuilder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock::default());
+        self.blocks.len() - 1
+    }
+
+    fn edge(&mut self, from: BlockId, to: BlockId) {
+        self.blocks[from].successors.push(to);
+        self.blocks[to].predecessors.push(from);
+    }
+
+    /// Records a statement starting in the current block, and makes it the anchor of whatever comes next
+    fn record(&mut self, span: Span) -> usize {
+        let index = self.statements.len();
+        self.statements.push(Statement {
+            span,
+            block: self.current,
+            anchor: self.previous,
+        });
+        let current = self.current;
+        self.blocks[current].statements.push(index);
+        self.previous = Some(index);
+        index
+    }
+
+    /// Jumps from the current block to `target`, then continues in a fresh block with no predecessors
+    fn jump(&mut self, target: BlockId) {
+        self.edge(self.current, target);
+        self.current = self.new_block();
+    }
+
+    fn block(&mut self, block: &'a Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        if let Some(tail) = &block.tail {
+            let index = self.record(tail.span);
+            self.expr(tail);
+            self.previous = Some(index);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        let index = self.record(stmt.span);
+        self.stmt_kind(stmt);
+        // Statements nested inside this one have updated `previous`, so restore it for the next sibling
+        self.previous = Some(index);
+    }
+
+    fn stmt_kind(&mut self, stmt: &'a Stmt) {
+        match &stmt.kind {
+            StmtKind::Var { value, .. } => self.expr(value),
+            StmtKind::Assign { target, value, .. } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::While { cond, body } => {
+                let header = self.new_block();
+                self.edge(self.current, header);
+                self.current = header;
+                self.expr(cond);
+                let body_start = self.new_block();
+                let after = self.new_block();
+                self.edge(self.current, body_start);
+                // `while true` only exits through a `break`
+                if cond.kind != ExprKind::Literal(Literal::Bool(true)) {
+                    self.edge(self.current, after);
+                }
+                self.loop_body(body, body_start, header, after);
+            }
+            StmtKind::For { iterable, body, .. } => {
+                self.expr(iterable);
+                let header = self.new_block();
+                self.edge(self.current, header);
+                let body_start = self.new_block();
+                let after = self.new_block();
+                self.edge(header, body_start);
+                self.edge(header, after);
+                self.loop_body(body, body_start, header, after);
+            }
+            StmtKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                self.check_not_deferred("return", stmt.span);
+                self.jump(self.exit);
+            }
+            // The expression runs when the block ends, so it branches off without rejoining
+            StmtKind::Defer(expr) => {
+                let resume = self.current;
+                let outer = self.deferred_loops.replace(self.loops.len());
+                self.current = self.new_block();
+                self.edge(resume, self.current);
+                self.expr(expr);
+                self.deferred_loops = outer;
+                self.current = resume;
+            }
+            StmtKind::Break => match self.loops.last() {
+                Some(_) if self.deferred_loops == Some(self.loops.len()) => {
+                    self.check_not_deferred("break", stmt.span);
+                    self.current = self.new_block();
+                }
+                Some(targets) => self.jump(targets.after),
+                None => {
+                    self.errors
+                        .push(ControlFlowError::BreakOutsideLoop(stmt.span));
+                    self.current = self.new_block();
+                }
+            },
+            StmtKind::Continue => match self.loops.last() {
+                Some(_) if self.deferred_loops == Some(self.loops.len()) => {
+                    self.check_not_deferred("continue", stmt.span);
+                    self.current = self.new_block();
+                }
+                Some(targets) => self.jump(targets.header),
+                None => {
+                    self.errors
+                        .push(ControlFlowError::ContinueOutsideLoop(stmt.span));
+                    self.current = self.new_block();
+                }
+            },
+        }
+    }
+
+    /// Reports a jump to outside the innermost deferred expression, if there is one
+    fn check_not_deferred(&mut self, keyword: &'static str, span: Span) {
+        if self.deferred_loops.is_some() {
+            self.errors
+                .push(ControlFlowError::JumpOutOfDefer { keyword, span });
+        }
+    }
+
+    fn loop_body(&mut self, body: &'a Block, body_start: BlockId, header: BlockId, after: BlockId) {
+        self.current = body_start;
+        self.loops.push(LoopTargets { header, after });
+        self.block(body);
+        self.loops.pop();
+        self.edge(self.current, header);
+        self.current = after;
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::Identifier(_) | ExprKind::Qualified { .. } => (),
+            ExprKind::Unary { operand, .. } => self.expr(operand),
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Call { callee, args } => {
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+                // A call that never returns (e.g. `panic`) ends the block without going anywhere
+                if expr.ty == Type::Never {
+                    self.current = self.new_block();
+                }
+            }
+            ExprKind::Try(operand) => {
+                self.expr(operand);
+                self.check_not_deferred("?", expr.span);
+                // An `Err` returns early, while an `Ok` carries on in the same block
+                self.edge(self.current, self.exit);
+            }
+            ExprKind::Range { start, end, .. } => {
+                self.expr(start);
+                self.expr(end);
+            }
+            ExprKind::Block(block) => self.block(block),
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+            // Creating a closure does not run its body, so the body is analyzed separately
+            ExprKind::Lambda(lambda) => self.lambdas.push(lambda),
+            ExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(cond);
+                let branch = self.current;
+                let then_start = self.new_block();
+                self.edge(branch, then_start);
+                self.current = then_start;
+                self.block(then_branch);
+                let then_end = self.current;
+
+                let else_end = match else_branch {
+                    Some(else_branch) => {
+                        let else_start = self.new_block();
+                        self.edge(branch, else_start);
+                        self.current = else_start;
+                        self.expr(else_branch);
+                        self.current
+                    }
+                    None => branch,
+                };
+
+                let join = self.new_block();
+                self.edge(then_end, join);
+                self.edge(else_end, join);
+                self.current = join;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end::{parser, type_checker};
+
+    fn analyze_source(source: &str) -> ControlFlowReport {
+        let mut program = parser::parse(source).unwrap();
+        type_checker::check(&mut program).unwrap();
+        analyze(&program)
+    }
+
+    fn span_of(source: &str, needle: &str) -> Span {
+        let start = source.find(needle).unwrap();
+        Span::new(start, start + needle.len())
+    }
+
+    #[test]
+    fn test_all_paths_return() {
+        let source = r#"
+            func sign(x: Int) -> Int {
+                if x > 0 {
+                    return 1;
+                } else if x < 0 {
+                    return -1;
+                } else {
+                    return 0;
+                }
+            }
+            func main() {}
+        "#;
+        assert_eq!(analyze_source(source), ControlFlowReport::default());
+    }
+
+    #[test]
+    fn test_missing_return() {
+        let source = "func f(x: Int) -> Int { if x > 0 { return 1; } } func main() {}";
+        let report = analyze_source(source);
+        assert_eq!(
+            report.errors,
+            vec![ControlFlowError::MissingReturn {
+                name: Some("f".to_string()),
+                span: Span::new(47, 48),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_empty_non_unit_function() {
+        let report = analyze_source("func f() -> Bool {} func main() {}");
+        assert_eq!(report.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_unit_function_may_fall_off() {
+        let report = analyze_source("func main() { var x = 1; }");
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn test_infinite_loop_needs_no_return() {
+        let source = "func f() -> Int { while true { } } func main() {}";
+        assert!(analyze_source(source).errors.is_empty());
+    }
+
+    #[test]
+    fn test_break_makes_infinite_loop_exit() {
+        let source = "func f() -> Int { while true { break; } } func main() {}";
+        assert_eq!(analyze_source(source).errors.len(), 1);
+    }
+
+    #[test]
+    fn test_conditional_loop_may_not_run() {
+        let source = "func f(c: Bool) -> Int { while c { return 1; } } func main() {}";
+        assert_eq!(analyze_source(source).errors.len(), 1);
+    }
+
+    #[test]
+    fn test_unreachable_after_return() {
+        let source = "func main() { return; var x = 1; var y = 2; }";
+        assert_eq!(
+            analyze_source(source).warnings,
+            vec![ControlFlowWarning::UnreachableCode(span_of(
+                source,
+                "var x = 1;"
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_after_break_and_continue() {
+        let source = r#"
+            func main() {
+                for i in 0..10 {
+                    if i == 2 {
+                        continue;
+                        println(i);
+                    }
+                    break;
+                    println(0);
+                }
+            }
+        "#;
+        assert_eq!(
+            analyze_source(source).warnings,
+            vec![
+                ControlFlowWarning::UnreachableCode(span_of(source, "println(i);")),
+                ControlFlowWarning::UnreachableCode(span_of(source, "println(0);")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_region_reported_once() {
+        let source = "func main() { return; while true { var x = 1; } var y = 2; }";
+        assert_eq!(
+            analyze_source(source).warnings,
+            vec![ControlFlowWarning::UnreachableCode(span_of(
+                source,
+                "while true { var x = 1; }"
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_after_exhaustive_if() {
+        let source = "func f(c: Bool) -> Int { if c { return 1; } else { return 2; } return 3; } func main() {}";
+        let report = analyze_source(source);
+        assert!(report.errors.is_empty());
+        assert_eq!(
+            report.warnings,
+            vec![ControlFlowWarning::UnreachableCode(span_of(
+                source,
+                "return 3;"
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let source = "func main() { if true { break; } continue; }";
+        assert_eq!(
+            analyze_source(source).errors,
+            vec![
+                ControlFlowError::BreakOutsideLoop(span_of(source, "break;")),
+                ControlFlowError::ContinueOutsideLoop(span_of(source, "continue;")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_jumps_out_of_defer() {
+        let source = r#"
+            func f() -> Result[Int, String] {
+                for i in 0..3 {
+                    defer {
+                        for j in 0..i { continue; }
+                        if i == 1 { break; }
+                    }
+                }
+                defer { return Ok(1); }
+                defer Ok(2)?;
+                return Ok(0);
+            }
+            func main() {
+                defer panic("deferred");
+                println(1);
+            }
+        "#;
+        let report = analyze_source(source);
+        let jump = |keyword, needle| ControlFlowError::JumpOutOfDefer {
+            keyword,
+            span: span_of(source, needle),
+        };
+        assert_eq!(
+            report.errors,
+            vec![
+                jump("break", "break;"),
+                jump("return", "return Ok(1);"),
+                jump("?", "Ok(2)?"),
+            ]
+        );
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_graph_shape() {
+        let mut program = parser::parse("func main() { while true { } }").unwrap();
+        type_checker::check(&mut program).unwrap();
+        let Item::Function(main) = &program.items[0];
+        let (graph, errors) = ControlFlowGraph::build(&main.body);
+        assert!(errors.is_empty());
+        let reachable = graph.reachable();
+        assert!(reachable[graph.entry]);
+        assert!(!reachable[graph.exit]);
+        assert!(!reachable[graph.fall_through]);
+    }
+
+    #[test]
+    fn test_panic_diverges() {
+        let source =
+            r#"func f(c: Bool) -> Int { if c { return 1; } panic("unreachable"); } func main() {}"#;
+        assert!(analyze_source(source).errors.is_empty());
+        let source = r#"func main() { panic("stop"); println(1); }"#;
+        assert_eq!(
+            analyze_source(source).warnings,
+            vec![ControlFlowWarning::UnreachableCode(span_of(
+                source,
+                "println(1);"
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_try_does_not_end_the_block() {
+        let source =
+            "func f(r: Result[Int, String]) -> Result[Int, String] { var x = r?; } func main() {}";
+        assert_eq!(analyze_source(source).errors.len(), 1);
+    }
+
+    #[test]
+    fn test_lambdas_are_analyzed_separately() {
+        let source = r#"
+            func main() {
+                while true {
+                    const f = func(x: Int) -> Int {
+                        if x > 0 { return 1; }
+                    };
+                    const g = func() { break; };
+                    break;
+                }
+            }
+        "#;
+        let report = analyze_source(source);
+        assert_eq!(
+            report.errors,
+            vec![
+                ControlFlowError::MissingReturn {
+                    name: None,
+                    span: Span::new(source.find("};").unwrap(), source.find("};").unwrap() + 1),
+                },
+                ControlFlowError::BreakOutsideLoop(span_of(source, "break;")),
+            ]
+        );
+        assert!(report.warnings.is_empty());
+    }
+}
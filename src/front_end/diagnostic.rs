@@ -0,0 +1,105 @@
+//! Diagnostics reported to the user, rendered against the source they point into
+use crate::front_end::token::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message attached to a span of source code
+/// - `file` is the index of the source file the span points into, among the files of the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub file: usize,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl fmt::Display, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.to_string(),
+            span,
+            file: 0,
+        }
+    }
+
+    pub fn warning(message: impl fmt::Display, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.to_string(),
+            span,
+            file: 0,
+        }
+    }
+
+    pub fn in_file(self, file: usize) -> Self {
+        Self { file, ..self }
+    }
+
+    /// Renders the diagnostic as `path:line:column: severity: message`,
+    /// followed by the offending line with the span underlined
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let (line, col) = self.span.line_col(source);
+        let line_start = source[..self.span.start.min(source.len())]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let line_end = source[line_start..]
+            .find('\n')
+            .map_or(source.len(), |i| line_start + i);
+        let text = &source[line_start..line_end];
+        let underline_len = source[self.span.start.min(line_end)..self.span.end.min(line_end)]
+            .chars()
+            .count()
+            .max(1);
+        format!(
+            "{}:{}:{}: {}: {}\n{}\n{}{}",
+            path,
+            line,
+            col,
+            self.severity,
+            self.message,
+            text,
+            " ".repeat(col - 1),
+            "^".repeat(underline_len)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let source = "func main() {\n    brek;\n}";
+        let diagnostic = Diagnostic::error("Undefined variable `brek`", Span::new(18, 22));
+        assert_eq!(
+            diagnostic.render("main.crw", source),
+            "main.crw:2:5: error: Undefined variable `brek`\n    brek;\n    ^^^^"
+        );
+    }
+
+    #[test]
+    fn test_render_empty_span_at_end() {
+        let source = "func f()";
+        let diagnostic = Diagnostic::warning("Unreachable code", Span::new(8, 8));
+        assert_eq!(
+            diagnostic.render("f.crw", source),
+            "f.crw:1:9: warning: Unreachable code\nfunc f()\n        ^"
+        );
+    }
+}
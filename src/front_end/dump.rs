@@ -0,0 +1,392 @@
+//! Human-readable dumps of the front end's phases, which `crawfish build --emit=...` prints.
+//! Their format is stable, so that they can serve as golden files:
+//! - tokens come one per line, with their span, kind and quoted lexeme
+//! - the AST is a tree with one node per line, indented by two spaces per level under its parent,
+//!   and each expression followed by its type when the tree has been type checked
+use crate::front_end::ast::{
+    Block, Expr, ExprKind, Function, Item, Lambda, Literal, Param, Program, Stmt, StmtKind,
+    StringPart, TypeExpr, TypeExprKind,
+};
+use crate::front_end::lexer::Lexer;
+use crate::front_end::parser::{Parser, ParserError};
+use crate::front_end::token::TokenKind;
+use crate::front_end::types::Type;
+use std::fmt::Write;
+
+/// Returns every token of `source`, up to and including the end of file
+pub fn tokens(source: &str) -> Result<String, ParserError> {
+    let mut lexer = Lexer::new(source);
+    let mut out = String::new();
+    loop {
+        let token = Parser::lex(source, &mut lexer)?;
+        let span = token.span;
+        let lexeme = token.lexeme(source);
+        writeln!(
+            out,
+            "{}..{} {:?} {:?}",
+            span.start, span.end, token.kind, lexeme
+        )
+        .unwrap();
+        if token.kind == TokenKind::EOF {
+            return Ok(out);
+        }
+    }
+}
+
+/// Returns the tree of `program`, along with the type of every expression when `typed`
+pub fn ast(program: &Program, typed: bool) -> String {
+    let mut printer = Printer {
+        out: String::new(),
+        depth: 0,
+        typed,
+    };
+    printer.program(program);
+    printer.out
+}
+
+/// Returns a type as written in the source
+fn type_expr(ty: &TypeExpr) -> String {
+    match &ty.kind {
+        TypeExprKind::Named(name) => name.clone(),
+        TypeExprKind::Unit => "()".to_string(),
+        TypeExprKind::Optional(inner) => format!("{}?", type_expr(inner)),
+        TypeExprKind::Generic { name, args } => {
+            let args: Vec<String> = args.iter().map(type_expr).collect();
+            format!("{}[{}]", name, args.join(", "))
+        }
+        TypeExprKind::Function {
+            params,
+            return_type,
+        } => {
+            let params: Vec<String> = params.iter().map(type_expr).collect();
+            match return_type {
+                Some(return_type) => {
+                    format!("func({}) -> {}", params.join(", "), type_expr(return_type))
+                }
+                None => format!("func({})", params.join(", ")),
+            }
+        }
+    }
+}
+
+/// Writes the lines of a tree, `depth` levels deep
+struct Printer {
+    out: String,
+    depth: usize,
+    typed: bool,
+}
+
+impl Printer {
+    fn line(&mut self, text: &str) {
+        writeln!(self.out, "{:indent$}{}", "", text, indent = self.depth * 2).unwrap();
+    }
+
+    /// Writes a line, then the lines that `children` writes one level deeper
+    fn node(&mut self, text: &str, children: impl FnOnce(&mut Self)) {
+        self.line(text);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn program(&mut self, program: &Program) {
+        self.node("Program", |p| {
+            for import in &program.imports {
+                let path: Vec<&str> = import.path.iter().map(|i| i.name.as_str()).collect();
+                p.line(&format!("Import {}", path.join("::")));
+            }
+            for item in &program.items {
+                let Item::Function(function) = item;
+                p.function(function);
+            }
+        });
+    }
+
+    fn function(&mut self, function: &Function) {
+        let visibility = if function.public { "pub " } else { "" };
+        let text = format!("Function {}{}", visibility, function.name.name);
+        self.node(&text, |p| {
+            p.signature(&function.params, &function.return_type);
+            p.block(&function.body);
+        });
+    }
+
+    fn signature(&mut self, params: &[Param], return_type: &Option<TypeExpr>) {
+        for param in params {
+            self.line(&format!(
+                "Param {}: {}",
+                param.name.name,
+                type_expr(&param.ty)
+            ));
+        }
+        if let Some(return_type) = return_type {
+            self.line(&format!("Returns {}", type_expr(return_type)));
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.node("Block", |p| {
+            for stmt in &block.stmts {
+                p.stmt(stmt);
+            }
+            if let Some(tail) = &block.tail {
+                p.node("Tail", |p| p.expr(tail));
+            }
+        });
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Var {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                let keyword = if *mutable { "Var" } else { "Const" };
+                let text = match ty {
+                    Some(ty) => format!("{} {}: {}", keyword, name.name, type_expr(ty)),
+                    None => format!("{} {}", keyword, name.name),
+                };
+                self.node(&text, |p| p.expr(value));
+            }
+            StmtKind::Assign { target, op, value } => {
+                let text = match op {
+                    Some(op) => format!("Assign {}=", op.symbol()),
+                    None => "Assign".to_string(),
+                };
+                self.node(&text, |p| {
+                    p.expr(target);
+                    p.expr(value);
+                });
+            }
+            StmtKind::Expr(expr) => self.node("Expr", |p| p.expr(expr)),
+            StmtKind::While { cond, body } => self.node("While", |p| {
+                p.expr(cond);
+                p.block(body);
+            }),
+            StmtKind::For {
+                item,
+                iterable,
+                body,
+            } => self.node(&format!("For {}", item.name), |p| {
+                p.expr(iterable);
+                p.block(body);
+            }),
+            StmtKind::Return(value) => self.node("Return", |p| {
+                if let Some(value) = value {
+                    p.expr(value);
+                }
+            }),
+            StmtKind::Defer(expr) => self.node("Defer", |p| p.expr(expr)),
+            StmtKind::Break => self.line("Break"),
+            StmtKind::Continue => self.line("Continue"),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let text = match &expr.kind {
+            ExprKind::Literal(literal) => match literal {
+                Literal::Int(value) => format!("Literal {}", value),
+                Literal::Float(value) => format!("Literal {:?}", value),
+                Literal::Bool(value) => format!("Literal {}", value),
+                Literal::Char(value) => format!("Literal {:?}", value),
+                Literal::String(value) => format!("Literal {:?}", value),
+                Literal::Null => "Literal null".to_string(),
+            },
+            ExprKind::Identifier(name) => format!("Identifier {}", name),
+            ExprKind::Qualified { module, name } => format!("Qualified {}::{}", module, name),
+            ExprKind::Unary { op, .. } => format!("Unary {}", op.symbol()),
+            ExprKind::Binary { op, .. } => format!("Binary {}", op.symbol()),
+            ExprKind::Call { .. } => "Call".to_string(),
+            ExprKind::If { .. } => "If".to_string(),
+            ExprKind::Block(_) => "BlockExpr".to_string(),
+            ExprKind::Range {
+                inclusive: false, ..
+            } => "Range ..".to_string(),
+            ExprKind::Range {
+                inclusive: true, ..
+            } => "Range ..=".to_string(),
+            ExprKind::Try(_) => "Try".to_string(),
+            ExprKind::Lambda(_) => "Lambda".to_string(),
+            ExprKind::Interpolation(_) => "Interpolation".to_string(),
+        };
+        // Built-in functions are not values, so the callee of their calls has no type
+        let text = if self.typed && expr.ty != Type::Error {
+            format!("{}: {}", text, expr.ty)
+        } else {
+            text
+        };
+        self.node(&text, |p| match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::Identifier(_) | ExprKind::Qualified { .. } => {}
+            ExprKind::Unary { operand, .. } => p.expr(operand),
+            ExprKind::Binary { left, right, .. } => {
+                p.expr(left);
+                p.expr(right);
+            }
+            ExprKind::Call { callee, args } => {
+                p.expr(callee);
+                for arg in args {
+                    p.expr(arg);
+                }
+            }
+            ExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                p.expr(cond);
+                p.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    p.node("Else", |p| p.expr(else_branch));
+                }
+            }
+            ExprKind::Block(block) => p.block(block),
+            ExprKind::Range { start, end, .. } => {
+                p.expr(start);
+                p.expr(end);
+            }
+            ExprKind::Try(inner) => p.expr(inner),
+            ExprKind::Lambda(lambda) => p.lambda(lambda),
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    match part {
+                        StringPart::Text(text) => p.line(&format!("Text {:?}", text)),
+                        StringPart::Expr(expr) => p.expr(expr),
+                    }
+                }
+            }
+        });
+    }
+
+    fn lambda(&mut self, lambda: &Lambda) {
+        self.signature(&lambda.params, &lambda.return_type);
+        for capture in &lambda.captures {
+            self.line(&format!("Capture {}: {}", capture.name, capture.ty));
+        }
+        self.block(&lambda.body);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end::lexer::LexerError;
+    use crate::front_end::token::Span;
+    use crate::front_end::{analyze, parser};
+
+    #[test]
+    fn test_tokens() {
+        let source = "var x = \"hi\"; // done\nx += 1;";
+        let expected = r#"0..3 Var "var"
+4..5 Identifier "x"
+6..7 Equal "="
+8..12 StringLiteral "\"hi\""
+12..13 Semicolon ";"
+22..23 Identifier "x"
+24..26 PlusEqual "+="
+27..28 IntegerLiteral "1"
+28..29 Semicolon ";"
+29..29 EOF ""
+"#;
+        assert_eq!(tokens(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tokens_stop_at_lexer_errors() {
+        assert_eq!(
+            tokens("x = 'a").unwrap_err(),
+            ParserError::Lexer {
+                error: LexerError::UnterminatedChar,
+                span: Span::new(4, 6),
+            }
+        );
+    }
+
+    #[test]
+    fn test_ast() {
+        let source = r#"
+            import util::text;
+
+            pub func apply(f: func(Int) -> Int, n: Int?) -> Int {
+                var total = 0;
+                for i in 0..=n ?? 3 {
+                    total += f(-i);
+                }
+                if total > 10 { total } else { 0 }
+            }
+        "#;
+        let expected = r#"Program
+  Import util::text
+  Function pub apply
+    Param f: func(Int) -> Int
+    Param n: Int?
+    Returns Int
+    Block
+      Var total
+        Literal 0
+      For i
+        Range ..=
+          Literal 0
+          Binary ??
+            Identifier n
+            Literal 3
+        Block
+          Assign +=
+            Identifier total
+            Call
+              Identifier f
+              Unary -
+                Identifier i
+      Expr
+        If
+          Binary >
+            Identifier total
+            Literal 10
+          Block
+            Tail
+              Identifier total
+          Else
+            BlockExpr
+              Block
+                Tail
+                  Literal 0
+"#;
+        assert_eq!(ast(&parser::parse(source).unwrap(), false), expected);
+    }
+
+    #[test]
+    fn test_typed_ast() {
+        let source = r#"
+            func main() {
+                const step = 0.5;
+                const next = func(x: Float) -> Float { return x + step; };
+                println(next(1.0));
+            }
+        "#;
+        let expected = r#"Program
+  Function main
+    Block
+      Const step
+        Literal 0.5: Float
+      Const next
+        Lambda: func(Float) -> Float
+          Param x: Float
+          Returns Float
+          Capture step: Float
+          Block
+            Return
+              Binary +: Float
+                Identifier x: Float
+                Identifier step: Float
+      Expr
+        Call: ()
+          Identifier println
+          Call: Float
+            Identifier next: func(Float) -> Float
+            Literal 1.0: Float
+"#;
+        let analysis = analyze(source).unwrap();
+        assert_eq!(ast(&analysis.program, true), expected);
+    }
+}
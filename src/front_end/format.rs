@@ -0,0 +1,254 @@
+//! Format strings of `print()` and `println()`, such as `"{} scored {:>6.1}%"`: text in which each
+//! `{}` placeholder stands for the next argument, and `{{` and `}}` for literal braces.
+//! A placeholder may specify, after a colon, an alignment (`<` left, `>` right or `^` centered), a
+//! minimum width in characters, and for floats a precision, the number of digits after the decimal
+//! point. Without an alignment, numbers are aligned to the right and everything else to the left.
+//! Unlike other string literals, the parser leaves the braces of a format string as written, so that
+//! an escaped `{{}}` is not mistaken for a placeholder.
+use crate::front_end::ast::{Expr, ExprKind, Literal};
+use std::error::Error;
+use std::fmt;
+
+/// The largest width or precision a placeholder may specify
+pub const MAX_WIDTH: u32 = 255;
+
+/// A part of a format string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Piece {
+    Text(String),
+    Placeholder(Spec),
+}
+
+/// How a placeholder formats its argument
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Spec {
+    pub align: Option<Align>,
+    pub width: u32,
+    pub precision: Option<u32>,
+}
+
+/// Which side of a value padding goes opposite to, numbered as in the IR's `print_padded`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left = 0,
+    Right = 1,
+    Center = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    UnmatchedBrace(char),
+    InvalidSpec(String),
+    TooWide(u32),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::UnmatchedBrace(brace) => write!(
+                f,
+                "unmatched `{}`; write `{}{}` for a literal brace",
+                brace, brace, brace
+            ),
+            FormatError::InvalidSpec(spec) => write!(f, "invalid placeholder `{{{}}}`", spec),
+            FormatError::TooWide(value) => write!(
+                f,
+                "width or precision {} is larger than {}",
+                value, MAX_WIDTH
+            ),
+        }
+    }
+}
+
+impl Error for FormatError {}
+
+impl Spec {
+    /// Whether the placeholder prints its argument as `print()` alone would
+    pub fn is_plain(&self) -> bool {
+        self.width == 0 && self.precision.is_none()
+    }
+
+    /// The alignment of an argument, which is numeric or not
+    pub fn alignment(&self, numeric: bool) -> Align {
+        self.align
+            .unwrap_or(if numeric { Align::Right } else { Align::Left })
+    }
+}
+
+impl Align {
+    pub fn from_code(code: i32) -> Align {
+        match code {
+            0 => Align::Left,
+            1 => Align::Right,
+            _ => Align::Center,
+        }
+    }
+}
+
+/// Whether the first argument of the built-in `name` is a format string when it is a string literal
+pub fn takes_format(name: &str) -> bool {
+    name == "print" || name == "println"
+}
+
+/// The format string of a call to the built-in `name`: the first argument of `print()` and
+/// `println()` when it is a string literal followed by more arguments. A literal alone prints as
+/// it is, like any other string.
+pub fn of_call<'a>(name: &str, args: &'a [Expr]) -> Option<&'a str> {
+    match args {
+        [first, _, ..] if takes_format(name) => match &first.kind {
+            ExprKind::Literal(Literal::String(format)) => Some(format),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Splits a format string into its text and placeholders, merging adjacent text
+pub fn parse(format: &str) -> Result<Vec<Piece>, FormatError> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut inside = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inside.push(c),
+                        None => return Err(FormatError::UnmatchedBrace('{')),
+                    }
+                }
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(Piece::Placeholder(parse_spec(&inside)?));
+            }
+            '}' => return Err(FormatError::UnmatchedBrace('}')),
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// Reads what is between the braces of a placeholder: nothing, or `:[align][width][.precision]`
+fn parse_spec(inside: &str) -> Result<Spec, FormatError> {
+    let invalid = || FormatError::InvalidSpec(inside.to_string());
+    if inside.is_empty() {
+        return Ok(Spec::default());
+    }
+    let rest = inside.strip_prefix(':').ok_or_else(invalid)?;
+    let (align, rest) = match rest.chars().next() {
+        Some('<') => (Some(Align::Left), &rest[1..]),
+        Some('>') => (Some(Align::Right), &rest[1..]),
+        Some('^') => (Some(Align::Center), &rest[1..]),
+        _ => (None, rest),
+    };
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(precision)),
+        None => (rest, None),
+    };
+    let number = |digits: &str| -> Result<u32, FormatError> {
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        match digits.parse::<u32>() {
+            Ok(value) if value <= MAX_WIDTH => Ok(value),
+            Ok(value) => Err(FormatError::TooWide(value)),
+            Err(_) => Err(FormatError::TooWide(u32::MAX)),
+        }
+    };
+    let width = match width {
+        "" => 0,
+        width => number(width)?,
+    };
+    let precision = precision.map(number).transpose()?;
+    Ok(Spec {
+        align,
+        width,
+        precision,
+    })
+}
+
+/// Pads `text` with spaces up to `width` characters, on the side opposite to `align`, or on both
+/// sides when centered, with the extra space on the right
+pub fn pad(text: &str, width: u32, align: Align) -> String {
+    let padding = (width as usize).saturating_sub(text.chars().count());
+    let before = match align {
+        Align::Left => 0,
+        Align::Right => padding,
+        Align::Center => padding / 2,
+    };
+    format!(
+        "{}{}{}",
+        " ".repeat(before),
+        text,
+        " ".repeat(padding - before)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let spec = |align, width, precision| {
+            Piece::Placeholder(Spec {
+                align,
+                width,
+                precision,
+            })
+        };
+        assert_eq!(
+            parse("{{x}} = {}, {:8} {:<3.2}{:^10}!{:.0}").unwrap(),
+            vec![
+                Piece::Text("{x} = ".to_string()),
+                spec(None, 0, None),
+                Piece::Text(", ".to_string()),
+                spec(None, 8, None),
+                Piece::Text(" ".to_string()),
+                spec(Some(Align::Left), 3, Some(2)),
+                spec(Some(Align::Center), 10, None),
+                Piece::Text("!".to_string()),
+                spec(None, 0, Some(0)),
+            ]
+        );
+        assert_eq!(parse("").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_format_strings() {
+        assert_eq!(parse("a } b"), Err(FormatError::UnmatchedBrace('}')));
+        assert_eq!(parse("{:5"), Err(FormatError::UnmatchedBrace('{')));
+        assert_eq!(parse("{x}"), Err(FormatError::InvalidSpec("x".to_string())));
+        assert_eq!(
+            parse("{:5.}"),
+            Err(FormatError::InvalidSpec(":5.".to_string()))
+        );
+        assert_eq!(
+            parse("{:-3}"),
+            Err(FormatError::InvalidSpec(":-3".to_string()))
+        );
+        assert_eq!(parse("{:256}"), Err(FormatError::TooWide(256)));
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(pad("ab", 5, Align::Left), "ab   ");
+        assert_eq!(pad("ab", 5, Align::Right), "   ab");
+        assert_eq!(pad("ab", 5, Align::Center), " ab  ");
+        assert_eq!(pad("héllo", 3, Align::Right), "héllo");
+    }
+}
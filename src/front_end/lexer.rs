@@ -11,6 +11,11 @@ pub enum LexerError {
     EmptyChar,
     UnterminatedChar,
     InvalidEscSeqChar,
+    UnterminatedString,
+    InvalidEscSeqString,
+    /// A `{` in a string literal that no embedded expression follows, which the parser reports at
+    /// the brace itself
+    UnclosedBrace,
 }
 
 impl fmt::Display for LexerError {
@@ -24,6 +29,16 @@ impl fmt::Display for LexerError {
             LexerError::InvalidEscSeqChar => {
                 write!(f, "Invalid escape sequence in character literal")
             }
+            LexerError::UnterminatedString => write!(f, "Unterminated string literal"),
+            LexerError::InvalidEscSeqString => {
+                write!(f, "Invalid escape sequence in string literal")
+            }
+            LexerError::UnclosedBrace => {
+                write!(
+                    f,
+                    "Unclosed `{{` in string literal; write `{{{{` for a brace"
+                )
+            }
         }
     }
 }
@@ -33,10 +48,14 @@ impl Error for LexerError {}
 /// Lexer
 /// - `source` is a string slice to the original source code
 /// - `chars` is an iterator that returns `(index, character)` elements
-///    and supports lookahead out of the box
+///   and supports lookahead out of the box
+/// - `interpolations` holds, for every embedded expression being lexed (innermost last),
+///   how many of the `{` it contains are still open, so that its closing `}` resumes the string
+#[derive(Clone)]
 pub struct Lexer<'a> {
     source: &'a str,
     chars: Peekable<CharIndices<'a>>,
+    interpolations: Vec<usize>,
 }
 
 impl<'a> Lexer<'a> {
@@ -52,11 +71,15 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        Self { source, chars }
+        Self {
+            source,
+            chars,
+            interpolations: Vec::new(),
+        }
     }
 
     /// Returns the next token
-    pub fn next(&mut self) -> Result<Token, LexerError> {
+    pub fn next_token(&mut self) -> Result<Token, LexerError> {
         // Use an iterative loop instead of recursion to handle comments
         loop {
             self.skip_whitespace();
@@ -82,16 +105,27 @@ impl<'a> Lexer<'a> {
                     ))
                 }
                 '{' => {
+                    if let Some(open) = self.interpolations.last_mut() {
+                        *open += 1;
+                    }
                     return Ok(Token::new(
                         TokenKind::LeftCurlyBracket,
                         Span::new(start, start + c.len_utf8()),
-                    ))
+                    ));
                 }
                 '}' => {
+                    match self.interpolations.last_mut() {
+                        Some(0) => {
+                            self.interpolations.pop();
+                            return self.read_string(start, true);
+                        }
+                        Some(open) => *open -= 1,
+                        None => (),
+                    }
                     return Ok(Token::new(
                         TokenKind::RightCurlyBracket,
                         Span::new(start, start + c.len_utf8()),
-                    ))
+                    ));
                 }
                 '[' => {
                     return Ok(Token::new(
@@ -120,6 +154,18 @@ impl<'a> Lexer<'a> {
                         Span::new(start, start + c.len_utf8()),
                     ))
                 }
+                '?' => {
+                    if let Some(end) = self.match_next('?') {
+                        return Ok(Token::new(
+                            TokenKind::QuestionQuestion,
+                            Span::new(start, end),
+                        ));
+                    }
+                    return Ok(Token::new(
+                        TokenKind::Question,
+                        Span::new(start, start + c.len_utf8()),
+                    ));
+                }
                 '|' => {
                     if let Some(end) = self.match_next('=') {
                         return Ok(Token::new(TokenKind::PipeEqual, Span::new(start, end)));
@@ -301,7 +347,7 @@ impl<'a> Lexer<'a> {
                     ));
                 }
                 c if c.is_alphabetic() || c == '_' => {
-                    let end = self.read_lexeme(start);
+                    let end = self.read_lexeme();
                     return Ok(Token::new(
                         Token::lexeme_token_kind(&self.source[start..end]),
                         Span::new(start, end),
@@ -330,19 +376,20 @@ impl<'a> Lexer<'a> {
                         None => return Err(LexerError::UnterminatedChar),
                     }
                 }
+                '"' => return self.read_string(start, false),
                 '0'..='9' => {
-                    let end = self.read_number(start);
+                    let end = self.read_number();
                     if let Some(&(_, '.')) = self.chars.peek() {
                         // Save state, without having to modify the actual underlying iterator, via `clone()`,
                         // before consuming the dot to rewind as necessary
                         let mut chars_clone = self.chars.clone();
                         chars_clone.next();
-                        if let Some(&(after_dot_idx, after_dot_char)) = chars_clone.peek() {
+                        if let Some(&(_, after_dot_char)) = chars_clone.peek() {
                             // Fraction detected, so we consume the dot on the actual underlying iterator,
                             // and read the rest to see if it is indeed a float
                             if after_dot_char.is_ascii_digit() {
                                 self.chars.next();
-                                let float_end = self.read_number(after_dot_idx);
+                                let float_end = self.read_number();
                                 return Ok(Token::new(
                                     TokenKind::FloatLiteral,
                                     Span::new(start, float_end),
@@ -359,6 +406,59 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Reads the rest of a string literal, or of the part of one that follows an embedded expression
+    /// when `resumed`, up to its closing `"` or the `{` of its next embedded expression.
+    /// `{{` and `}}` stand for single braces, and `{}` and `{:...}` are placeholders of format strings,
+    /// so neither starts an embedded expression.
+    fn read_string(&mut self, start: usize, resumed: bool) -> Result<Token, LexerError> {
+        loop {
+            match self.chars.next() {
+                Some((end, '"')) => {
+                    let kind = if resumed {
+                        TokenKind::InterpolationEnd
+                    } else {
+                        TokenKind::StringLiteral
+                    };
+                    return Ok(Token::new(kind, Span::new(start, end + '"'.len_utf8())));
+                }
+                Some((_, '\\')) => match self.chars.next() {
+                    Some((_, c)) if Self::is_single_char_escape_sequence(c) => (),
+                    Some(_) => return Err(LexerError::InvalidEscSeqString),
+                    None => return Err(LexerError::UnterminatedString),
+                },
+                Some((end, '{')) => match self.chars.peek().map(|&(_, c)| c) {
+                    Some('{') => {
+                        self.chars.next();
+                    }
+                    Some('}' | ':') => (),
+                    Some('\n') | None => return Err(LexerError::UnclosedBrace),
+                    // `"{";` would otherwise lex `"; ...` as an embedded string and report that
+                    // it is unterminated, far from the real mistake
+                    Some('"') if self.clone().next_token().is_err() => {
+                        return Err(LexerError::UnclosedBrace)
+                    }
+                    _ => {
+                        self.interpolations.push(0);
+                        let kind = if resumed {
+                            TokenKind::InterpolationMiddle
+                        } else {
+                            TokenKind::InterpolationStart
+                        };
+                        return Ok(Token::new(kind, Span::new(start, end + '{'.len_utf8())));
+                    }
+                },
+                // Single-line strings cannot span lines
+                Some((_, '\n')) | None => return Err(LexerError::UnterminatedString),
+                Some(_) => (),
+            }
+        }
+    }
+
+    /// Returns the byte offset of the next unconsumed character
+    pub fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.source.len(), |&(i, _)| i)
+    }
+
     fn skip_whitespace(&mut self) {
         self.skip_while(|c| c.is_whitespace());
     }
@@ -389,19 +489,21 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn read_lexeme(&mut self, start: usize) -> usize {
-        self.read_while(start, |c| c.is_alphanumeric() || c == '_')
+    fn read_lexeme(&mut self) -> usize {
+        self.read_while(|c| c.is_alphanumeric() || c == '_')
     }
 
-    fn read_number(&mut self, start: usize) -> usize {
-        self.read_while(start, |c| c.is_ascii_digit())
+    fn read_number(&mut self) -> usize {
+        self.read_while(|c| c.is_ascii_digit())
     }
 
-    fn read_while<F>(&mut self, start: usize, predicate: F) -> usize
+    fn read_while<F>(&mut self, predicate: F) -> usize
     where
         F: Fn(char) -> bool,
     {
-        let mut end = start;
+        // Start from the next unconsumed character, so that a lexeme whose first
+        // character was already consumed still ends after that character.
+        let mut end = self.offset();
         while let Some(&(i, c)) = self.chars.peek() {
             if predicate(c) {
                 self.chars.next();
@@ -438,12 +540,12 @@ mod tests {
         let mut lexer = Lexer::new(&source);
 
         // Should skip all comments and find the variable declaration
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Var);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Identifier);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Equal);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Semicolon);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EOF);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Var);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Identifier);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Equal);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Semicolon);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EOF);
     }
 
     #[test]
@@ -457,10 +559,10 @@ mod tests {
 
         let mut lexer = Lexer::new(&source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Struct);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Identifier);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Semicolon);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EOF);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Struct);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Identifier);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Semicolon);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EOF);
     }
 
     #[test]
@@ -468,9 +570,9 @@ mod tests {
         let source = "var x // comment at end";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Var);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Identifier);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EOF);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Var);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Identifier);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EOF);
     }
 
     #[test]
@@ -490,7 +592,7 @@ mod tests {
         // Should correctly parse all variable declarations
         let mut var_count = 0;
         loop {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             if token.kind == TokenKind::Var {
                 var_count += 1;
             } else if token.kind == TokenKind::EOF {
@@ -505,7 +607,7 @@ mod tests {
     fn test_bom_is_skipped() {
         let source = "\u{FEFF}struct";
         let mut lexer = Lexer::new(source);
-        let token = lexer.next().unwrap();
+        let token = lexer.next_token().unwrap();
         assert_eq!(token.kind, TokenKind::Struct);
     }
 
@@ -513,7 +615,7 @@ mod tests {
     fn test_valid_operators() {
         let source = r"!=";
         let mut lexer = Lexer::new(source);
-        let token = lexer.next().unwrap();
+        let token = lexer.next_token().unwrap();
         assert_eq!(token.kind, TokenKind::BangEqual);
     }
 
@@ -522,40 +624,40 @@ mod tests {
         let source = r"'a' '\n' '\r' '\t' '\0' '\\'";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().lexeme(source), r"'a'");
-        assert_eq!(lexer.next().unwrap().lexeme(source), r"'\n'");
-        assert_eq!(lexer.next().unwrap().lexeme(source), r"'\r'");
-        assert_eq!(lexer.next().unwrap().lexeme(source), r"'\t'");
-        assert_eq!(lexer.next().unwrap().lexeme(source), r"'\0'");
-        assert_eq!(lexer.next().unwrap().lexeme(source), r"'\\'");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), r"'a'");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), r"'\n'");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), r"'\r'");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), r"'\t'");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), r"'\0'");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), r"'\\'");
     }
 
     #[test]
     fn test_empty_char() {
         let source = r"''";
         let mut lexer = Lexer::new(source);
-        assert_eq!(lexer.next(), Err(LexerError::EmptyChar));
+        assert_eq!(lexer.next_token(), Err(LexerError::EmptyChar));
     }
 
     #[test]
     fn test_invalid_single_char_esc_seq() {
         let source = r"'\a'";
         let mut lexer = Lexer::new(source);
-        assert_eq!(lexer.next(), Err(LexerError::InvalidEscSeqChar));
+        assert_eq!(lexer.next_token(), Err(LexerError::InvalidEscSeqChar));
     }
 
     #[test]
     fn test_more_than_one_char_in_char() {
         let source = r"'ab'";
         let mut lexer = Lexer::new(source);
-        assert_eq!(lexer.next(), Err(LexerError::UnterminatedChar));
+        assert_eq!(lexer.next_token(), Err(LexerError::UnterminatedChar));
     }
 
     #[test]
     fn test_unterminated_char() {
         let source = r"'a";
         let mut lexer = Lexer::new(source);
-        assert_eq!(lexer.next(), Err(LexerError::UnterminatedChar));
+        assert_eq!(lexer.next_token(), Err(LexerError::UnterminatedChar));
     }
 
     #[test]
@@ -576,10 +678,111 @@ mod tests {
         ];
 
         for kind in kinds {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, kind);
         }
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EOF);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EOF);
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let source = r#""hello" "" "say \"hi\"\n" "ünïcode""#;
+        let mut lexer = Lexer::new(source);
+        for expected in [r#""hello""#, r#""""#, r#""say \"hi\"\n""#, r#""ünïcode""#] {
+            let token = lexer.next_token().unwrap();
+            assert_eq!(token.kind, TokenKind::StringLiteral);
+            assert_eq!(token.lexeme(source), expected);
+        }
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EOF);
+    }
+
+    #[test]
+    fn test_invalid_strings() {
+        assert_eq!(
+            Lexer::new(r#""abc"#).next_token(),
+            Err(LexerError::UnterminatedString)
+        );
+        assert_eq!(
+            Lexer::new("\"abc\n\"").next_token(),
+            Err(LexerError::UnterminatedString)
+        );
+        assert_eq!(
+            Lexer::new(r#""\q""#).next_token(),
+            Err(LexerError::InvalidEscSeqString)
+        );
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let source = r#""a {x} b {f({1}, "{y}")}!" "{{x}} {} {:>4}""#;
+        let mut lexer = Lexer::new(source);
+        let expected = [
+            (TokenKind::InterpolationStart, r#""a {"#),
+            (TokenKind::Identifier, "x"),
+            (TokenKind::InterpolationMiddle, "} b {"),
+            (TokenKind::Identifier, "f"),
+            (TokenKind::LeftCircleBracket, "("),
+            (TokenKind::LeftCurlyBracket, "{"),
+            (TokenKind::IntegerLiteral, "1"),
+            (TokenKind::RightCurlyBracket, "}"),
+            (TokenKind::Comma, ","),
+            (TokenKind::InterpolationStart, r#""{"#),
+            (TokenKind::Identifier, "y"),
+            (TokenKind::InterpolationEnd, r#"}""#),
+            (TokenKind::RightCircleBracket, ")"),
+            (TokenKind::InterpolationEnd, r#"}!""#),
+            (TokenKind::StringLiteral, r#""{{x}} {} {:>4}""#),
+            (TokenKind::EOF, ""),
+        ];
+        for (kind, lexeme) in expected {
+            let token = lexer.next_token().unwrap();
+            assert_eq!((token.kind, token.lexeme(source)), (kind, lexeme));
+        }
+        let mut lexer = Lexer::new("\"{x}\n");
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::InterpolationStart
+        );
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Identifier);
+        assert_eq!(lexer.next_token(), Err(LexerError::UnterminatedString));
+    }
+
+    #[test]
+    fn test_unclosed_brace_in_string() {
+        for source in [r#""{""#, r#""a {x} {""#, "\"{\n\"", "\"{"] {
+            let mut lexer = Lexer::new(source);
+            let error = loop {
+                match lexer.next_token() {
+                    Ok(_) => (),
+                    Err(error) => break error,
+                }
+            };
+            assert_eq!(error, LexerError::UnclosedBrace, "{}", source);
+        }
+        assert_eq!(
+            Lexer::new(r#""{{""#).next_token().unwrap().kind,
+            TokenKind::StringLiteral
+        );
+    }
+
+    #[test]
+    fn test_question_marks() {
+        let source = "Int? x ?? 0 ???";
+        let mut lexer = Lexer::new(source);
+        let kinds = [
+            TokenKind::Identifier,
+            TokenKind::Question,
+            TokenKind::Identifier,
+            TokenKind::QuestionQuestion,
+            TokenKind::IntegerLiteral,
+            TokenKind::QuestionQuestion,
+            TokenKind::Question,
+            TokenKind::EOF,
+        ];
+
+        for kind in kinds {
+            assert_eq!(lexer.next_token().unwrap().kind, kind);
+        }
     }
 
     #[test]
@@ -606,7 +809,7 @@ mod tests {
 
         let mut lexer = Lexer::new(source);
         for kind in expected_kinds {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, kind);
         }
     }
@@ -629,7 +832,7 @@ mod tests {
 
         let mut lexer = Lexer::new(source);
         for kind in expected_kinds {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, kind);
         }
     }
@@ -650,7 +853,7 @@ mod tests {
 
         let mut lexer = Lexer::new(source);
         for kind in expected_kinds {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, kind);
         }
     }
@@ -671,7 +874,7 @@ mod tests {
         ];
 
         for kind in expected_kinds {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, kind);
         }
     }
@@ -681,13 +884,13 @@ mod tests {
         let source = "42 3.14 0 10.0 1.";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::FloatLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::FloatLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::FloatLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::FloatLiteral);
         // `1.` is not a float since there's no digit after the `.`
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Dot);
     }
 
     #[test]
@@ -695,7 +898,7 @@ mod tests {
         let source = r"// this is a comment
     var";
         let mut lexer = Lexer::new(source);
-        let token = lexer.next().unwrap();
+        let token = lexer.next_token().unwrap();
         assert_eq!(token.kind, TokenKind::Var);
     }
 
@@ -703,7 +906,10 @@ mod tests {
     fn test_unrecognized_character() {
         let source = "@";
         let mut lexer = Lexer::new(source);
-        assert_eq!(lexer.next(), Err(LexerError::UnrecognizedCharacter('@')));
+        assert_eq!(
+            lexer.next_token(),
+            Err(LexerError::UnrecognizedCharacter('@'))
+        );
     }
 
     #[test]
@@ -712,7 +918,7 @@ mod tests {
         let mut lexer = Lexer::new(source);
 
         for _ in 0..4 {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, TokenKind::CharLiteral);
         }
     }
@@ -721,7 +927,7 @@ mod tests {
     fn test_empty_source() {
         let source = "";
         let mut lexer = Lexer::new(source);
-        let token = lexer.next().unwrap();
+        let token = lexer.next_token().unwrap();
         assert_eq!(token.kind, TokenKind::EOF);
     }
 
@@ -729,7 +935,7 @@ mod tests {
     fn test_only_whitespace() {
         let source = "   \t\n\r  ";
         let mut lexer = Lexer::new(source);
-        let token = lexer.next().unwrap();
+        let token = lexer.next_token().unwrap();
         assert_eq!(token.kind, TokenKind::EOF);
     }
 
@@ -737,7 +943,7 @@ mod tests {
     fn test_only_comments() {
         let source = "// first comment\n// second comment";
         let mut lexer = Lexer::new(source);
-        let token = lexer.next().unwrap();
+        let token = lexer.next_token().unwrap();
         assert_eq!(token.kind, TokenKind::EOF);
     }
 
@@ -746,10 +952,10 @@ mod tests {
         let source = "var // comment at end of file";
         let mut lexer = Lexer::new(source);
 
-        let token1 = lexer.next().unwrap();
+        let token1 = lexer.next_token().unwrap();
         assert_eq!(token1.kind, TokenKind::Var);
 
-        let token2 = lexer.next().unwrap();
+        let token2 = lexer.next_token().unwrap();
         assert_eq!(token2.kind, TokenKind::EOF);
     }
 
@@ -758,13 +964,13 @@ mod tests {
         let source = "  \t var  \n  42  \r\n  ";
         let mut lexer = Lexer::new(source);
 
-        let token1 = lexer.next().unwrap();
+        let token1 = lexer.next_token().unwrap();
         assert_eq!(token1.kind, TokenKind::Var);
 
-        let token2 = lexer.next().unwrap();
+        let token2 = lexer.next_token().unwrap();
         assert_eq!(token2.kind, TokenKind::IntegerLiteral);
 
-        let token3 = lexer.next().unwrap();
+        let token3 = lexer.next_token().unwrap();
         assert_eq!(token3.kind, TokenKind::EOF);
     }
 
@@ -773,27 +979,27 @@ mod tests {
         let source = "café αβγ δεζ _underscore русский";
         let mut lexer = Lexer::new(source);
 
-        let token1 = lexer.next().unwrap();
+        let token1 = lexer.next_token().unwrap();
         assert_eq!(token1.kind, TokenKind::Identifier);
         assert_eq!(token1.lexeme(source), "café");
 
-        let token2 = lexer.next().unwrap();
+        let token2 = lexer.next_token().unwrap();
         assert_eq!(token2.kind, TokenKind::Identifier);
         assert_eq!(token2.lexeme(source), "αβγ");
 
-        let token3 = lexer.next().unwrap();
+        let token3 = lexer.next_token().unwrap();
         assert_eq!(token3.kind, TokenKind::Identifier);
         assert_eq!(token3.lexeme(source), "δεζ");
 
-        let token4 = lexer.next().unwrap();
+        let token4 = lexer.next_token().unwrap();
         assert_eq!(token4.kind, TokenKind::Identifier);
         assert_eq!(token4.lexeme(source), "_underscore");
 
-        let token5 = lexer.next().unwrap();
+        let token5 = lexer.next_token().unwrap();
         assert_eq!(token5.kind, TokenKind::Identifier);
         assert_eq!(token5.lexeme(source), "русский");
 
-        let token6 = lexer.next().unwrap();
+        let token6 = lexer.next_token().unwrap();
         assert_eq!(token6.kind, TokenKind::EOF);
     }
 
@@ -802,15 +1008,15 @@ mod tests {
         let source = "hello世界 test123 _test_测试";
         let mut lexer = Lexer::new(source);
 
-        let token1 = lexer.next().unwrap();
+        let token1 = lexer.next_token().unwrap();
         assert_eq!(token1.kind, TokenKind::Identifier);
         assert_eq!(token1.lexeme(source), "hello世界");
 
-        let token2 = lexer.next().unwrap();
+        let token2 = lexer.next_token().unwrap();
         assert_eq!(token2.kind, TokenKind::Identifier);
         assert_eq!(token2.lexeme(source), "test123");
 
-        let token3 = lexer.next().unwrap();
+        let token3 = lexer.next_token().unwrap();
         assert_eq!(token3.kind, TokenKind::Identifier);
         assert_eq!(token3.lexeme(source), "_test_测试");
     }
@@ -821,11 +1027,11 @@ mod tests {
         let mut lexer = Lexer::new(source);
 
         for _ in 0..5 {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, TokenKind::Identifier);
         }
 
-        let eof = lexer.next().unwrap();
+        let eof = lexer.next_token().unwrap();
         assert_eq!(eof.kind, TokenKind::EOF);
     }
 
@@ -835,7 +1041,7 @@ mod tests {
         let mut lexer = Lexer::new(source);
 
         for _ in 0..4 {
-            let token = lexer.next().unwrap();
+            let token = lexer.next_token().unwrap();
             assert_eq!(token.kind, TokenKind::IntegerLiteral);
         }
     }
@@ -846,15 +1052,15 @@ mod tests {
         let mut lexer = Lexer::new(source);
 
         // 0.0
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::FloatLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::FloatLiteral);
         // 00.00
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::FloatLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::FloatLiteral);
         // .5 - should be dot followed by integer (not a float in this implementation)
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Dot);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
         // 1. - should be integer followed by dot
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Dot);
     }
 
     #[test]
@@ -872,7 +1078,7 @@ mod tests {
         ];
 
         for kind in expected {
-            assert_eq!(lexer.next().unwrap().kind, kind);
+            assert_eq!(lexer.next_token().unwrap().kind, kind);
         }
     }
 
@@ -881,14 +1087,20 @@ mod tests {
         let source = "< <= << <<=";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::LeftAngleBracket);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::LeftAngleBracketEqual);
         assert_eq!(
-            lexer.next().unwrap().kind,
+            lexer.next_token().unwrap().kind,
+            TokenKind::LeftAngleBracket
+        );
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
+            TokenKind::LeftAngleBracketEqual
+        );
+        assert_eq!(
+            lexer.next_token().unwrap().kind,
             TokenKind::LeftAngleBracketLeftAngleBracket
         );
         assert_eq!(
-            lexer.next().unwrap().kind,
+            lexer.next_token().unwrap().kind,
             TokenKind::LeftAngleBracketLeftAngleBracketEqual
         );
     }
@@ -898,14 +1110,14 @@ mod tests {
         let source = ". .. ... ..= ...=";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Ellipsis);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Ellipsis);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EllipsisEqual);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Ellipsis);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Dot);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Equal);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Dot);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Ellipsis);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Ellipsis);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Dot);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EllipsisEqual);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Ellipsis);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Dot);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Equal);
     }
 
     #[test]
@@ -913,11 +1125,11 @@ mod tests {
         let source = "'\\'' '\\\"'";
         let mut lexer = Lexer::new(source);
 
-        let token1 = lexer.next().unwrap();
+        let token1 = lexer.next_token().unwrap();
         assert_eq!(token1.kind, TokenKind::CharLiteral);
         assert_eq!(token1.lexeme(source), "'\\''");
 
-        let token2 = lexer.next().unwrap();
+        let token2 = lexer.next_token().unwrap();
         assert_eq!(token2.kind, TokenKind::CharLiteral);
         assert_eq!(token2.lexeme(source), "'\\\"'");
     }
@@ -926,14 +1138,14 @@ mod tests {
     fn test_unterminated_char_at_eof() {
         let source = "'";
         let mut lexer = Lexer::new(source);
-        assert_eq!(lexer.next(), Err(LexerError::UnterminatedChar));
+        assert_eq!(lexer.next_token(), Err(LexerError::UnterminatedChar));
     }
 
     #[test]
     fn test_unterminated_escape_at_eof() {
         let source = r"'\";
         let mut lexer = Lexer::new(source);
-        assert_eq!(lexer.next(), Err(LexerError::UnterminatedChar));
+        assert_eq!(lexer.next_token(), Err(LexerError::UnterminatedChar));
     }
 
     #[test]
@@ -941,38 +1153,51 @@ mod tests {
         let source = "hello world";
         let mut lexer = Lexer::new(source);
 
-        let token1 = lexer.next().unwrap();
+        let token1 = lexer.next_token().unwrap();
         assert_eq!(token1.lexeme(source), "hello");
 
-        let token2 = lexer.next().unwrap();
+        let token2 = lexer.next_token().unwrap();
         assert_eq!(token2.lexeme(source), "world");
     }
 
+    #[test]
+    fn test_single_character_lexeme_spans() {
+        let source = "x 7 1.5";
+        let mut lexer = Lexer::new(source);
+
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), "x");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), "7");
+        assert_eq!(lexer.next_token().unwrap().lexeme(source), "1.5");
+    }
+
     #[test]
     fn test_multiple_consecutive_operators() {
         let source = "==>>>===";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EqualEqual);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EqualEqual);
         assert_eq!(
-            lexer.next().unwrap().kind,
+            lexer.next_token().unwrap().kind,
             TokenKind::RightAngleBracketRightAngleBracket
         );
         assert_eq!(
-            lexer.next().unwrap().kind,
+            lexer.next_token().unwrap().kind,
             TokenKind::RightAngleBracketEqual
         );
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EqualEqual);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EqualEqual);
     }
 
     #[test]
     fn test_various_unrecognized_characters() {
-        let unrecognized_chars = ['@', '#', '$', '?', '`'];
+        let unrecognized_chars = ['@', '#', '$', '`'];
 
         for &c in &unrecognized_chars {
             let source = c.to_string();
             let mut lexer = Lexer::new(&source);
-            assert_eq!(lexer.next(), Err(LexerError::UnrecognizedCharacter(c)));
+            assert_eq!(
+                lexer.next_token(),
+                Err(LexerError::UnrecognizedCharacter(c))
+            );
         }
     }
 
@@ -982,11 +1207,11 @@ mod tests {
         let mut lexer = Lexer::new(source);
 
         // BOM should be skipped, first token should be 'var'
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Var);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Identifier);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Equal);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Semicolon);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Var);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Identifier);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Equal);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Semicolon);
     }
 
     #[test]
@@ -994,8 +1219,8 @@ mod tests {
         let source = "123456789 987654321.123456789";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::FloatLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::FloatLiteral);
     }
 
     #[test]
@@ -1003,8 +1228,8 @@ mod tests {
         let source = "var // comment\n42 // another comment\n";
         let mut lexer = Lexer::new(source);
 
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::Var);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::IntegerLiteral);
-        assert_eq!(lexer.next().unwrap().kind, TokenKind::EOF);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::Var);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::IntegerLiteral);
+        assert_eq!(lexer.next_token().unwrap().kind, TokenKind::EOF);
     }
 }
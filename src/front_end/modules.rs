@@ -0,0 +1,365 @@
+//! Module loading, which finds every file reachable from the entry file through `import` declarations.
+//! `import a::b;` refers to `a/b.crw`, looked up next to the importing file first, then in the
+//! project root (the directory of the entry file).
+use crate::front_end::ast::{Import, Program};
+use crate::front_end::diagnostic::Diagnostic;
+use crate::front_end::parser;
+use crate::front_end::token::Span;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub enum ModuleError {
+    NotFound {
+        module: String,
+        span: Span,
+    },
+    Unreadable {
+        path: String,
+        message: String,
+        span: Span,
+    },
+    /// `chain` lists the files of the cycle, starting and ending with the same file
+    Cycle {
+        chain: Vec<String>,
+        span: Span,
+    },
+    DuplicateImport(String, Span),
+}
+
+impl ModuleError {
+    pub fn span(&self) -> Span {
+        match self {
+            ModuleError::NotFound { span, .. }
+            | ModuleError::Unreadable { span, .. }
+            | ModuleError::Cycle { span, .. }
+            | ModuleError::DuplicateImport(_, span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModuleError::NotFound { module, .. } => write!(
+                f,
+                "Cannot find module `{}` next to this file or in the project root",
+                module
+            ),
+            ModuleError::Unreadable { path, message, .. } => {
+                write!(f, "Cannot read module file `{}` ({})", path, message)
+            }
+            ModuleError::Cycle { chain, .. } => {
+                write!(f, "Import cycle: {}", chain.join(" -> "))
+            }
+            ModuleError::DuplicateImport(alias, _) => {
+                write!(f, "A module named `{}` is already imported", alias)
+            }
+        }
+    }
+}
+
+impl Error for ModuleError {}
+
+/// A source file of the program
+/// - `module` is the file's path from the project root (e.g. `geometry::shapes` for
+///   `geometry/shapes.crw`), or `None` for the entry file
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub module: Option<String>,
+    pub source: String,
+}
+
+/// Every file of a program, in the order they were found, so the entry file comes first
+/// - `programs[i]` is the syntax tree of `files[i]`, or `None` if it failed to parse
+/// - `imports[i]` maps each alias imported by `files[i]` to the index of the imported file
+/// - `order` lists every file after the files it imports, so the entry file comes last
+/// - `diagnostics` holds the parse and import errors of every file
+#[derive(Debug, Default)]
+pub struct ModuleGraph {
+    pub files: Vec<SourceFile>,
+    pub programs: Vec<Option<Program>>,
+    pub imports: Vec<HashMap<String, usize>>,
+    pub order: Vec<usize>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Loads the program whose entry file is at `entry` and contains `source`.
+/// Imported files are read with `read`, and a file that `read` reports as `NotFound` is skipped.
+pub fn load(
+    entry: &Path,
+    source: String,
+    read: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> ModuleGraph {
+    let root = entry.parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut loader = Loader {
+        root,
+        read,
+        graph: ModuleGraph::default(),
+        index: HashMap::new(),
+        stack: Vec::new(),
+    };
+    let file = loader.add_file(entry.to_path_buf(), None, source);
+    loader.visit(file);
+    loader.graph
+}
+
+/// Module loader
+/// - `index` maps the path of every file found so far to its index
+/// - `stack` is the chain of files whose imports are being loaded, starting with the entry file
+struct Loader<'r> {
+    root: PathBuf,
+    read: &'r mut dyn FnMut(&Path) -> io::Result<String>,
+    graph: ModuleGraph,
+    index: HashMap<PathBuf, usize>,
+    stack: Vec<usize>,
+}
+
+impl Loader<'_> {
+    fn add_file(&mut self, path: PathBuf, module: Option<String>, source: String) -> usize {
+        let file = self.graph.files.len();
+        self.index.insert(path.clone(), file);
+        self.graph.files.push(SourceFile {
+            path,
+            module,
+            source,
+        });
+        self.graph.programs.push(None);
+        self.graph.imports.push(HashMap::new());
+        file
+    }
+
+    /// Parses `file` and loads everything it imports, depth first
+    fn visit(&mut self, file: usize) {
+        self.stack.push(file);
+        match parser::parse(&self.graph.files[file].source) {
+            Ok(program) => {
+                for import in &program.imports {
+                    self.import(file, import);
+                }
+                self.graph.programs[file] = Some(program);
+            }
+            Err(e) => self.error(file, &e, e.span()),
+        }
+        self.stack.pop();
+        self.graph.order.push(file);
+    }
+
+    fn import(&mut self, file: usize, import: &Import) {
+        let alias = &import.alias().name;
+        if self.graph.imports[file].contains_key(alias) {
+            let error = ModuleError::DuplicateImport(alias.clone(), import.span);
+            self.error(file, &error, error.span());
+            return;
+        }
+
+        let mut relative: PathBuf = import.path.iter().map(|ident| &ident.name).collect();
+        relative.set_extension("crw");
+        let directory = self.graph.files[file]
+            .path
+            .parent()
+            .unwrap_or(Path::new(""));
+        let mut candidates = vec![directory.join(&relative)];
+        if directory != self.root {
+            candidates.push(self.root.join(&relative));
+        }
+
+        for candidate in candidates {
+            if let Some(&target) = self.index.get(&candidate) {
+                if let Some(position) = self.stack.iter().position(|&f| f == target) {
+                    let mut chain: Vec<String> = self.stack[position..]
+                        .iter()
+                        .map(|&f| self.display(f))
+                        .collect();
+                    chain.push(self.display(target));
+                    let error = ModuleError::Cycle {
+                        chain,
+                        span: import.span,
+                    };
+                    self.error(file, &error, error.span());
+                } else {
+                    self.graph.imports[file].insert(alias.clone(), target);
+                }
+                return;
+            }
+
+            match (self.read)(&candidate) {
+                Ok(source) => {
+                    let module = self.module_name(&candidate);
+                    let target = self.add_file(candidate, Some(module), source);
+                    self.graph.imports[file].insert(alias.clone(), target);
+                    self.visit(target);
+                    return;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => {
+                    let error = ModuleError::Unreadable {
+                        path: candidate.display().to_string(),
+                        message: e.to_string(),
+                        span: import.span,
+                    };
+                    self.error(file, &error, error.span());
+                    return;
+                }
+            }
+        }
+
+        let module = import
+            .path
+            .iter()
+            .map(|ident| ident.name.as_str())
+            .collect::<Vec<_>>()
+            .join("::");
+        let error = ModuleError::NotFound {
+            module,
+            span: import.span,
+        };
+        self.error(file, &error, error.span());
+    }
+
+    /// The module path of a file below the project root, e.g. `geometry::shapes`
+    fn module_name(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        relative
+            .with_extension("")
+            .iter()
+            .map(|segment| segment.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("::")
+    }
+
+    fn display(&self, file: usize) -> String {
+        let path = &self.graph.files[file].path;
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    fn error(&mut self, file: usize, message: &dyn fmt::Display, span: Span) {
+        let diagnostic = Diagnostic::error(message, span).in_file(file);
+        self.graph.diagnostics.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `main.crw` from an in-memory project
+    fn load_files(files: &[(&str, &str)]) -> ModuleGraph {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, source)| (Path::new("project").join(path), source.to_string()))
+            .collect();
+        let entry = Path::new("project/main.crw");
+        load(entry, files[entry].clone(), &mut |path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        })
+    }
+
+    fn messages(graph: &ModuleGraph) -> Vec<(usize, String)> {
+        graph
+            .diagnostics
+            .iter()
+            .map(|d| (d.file, d.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let graph = load_files(&[
+            (
+                "main.crw",
+                "import geometry::shapes; import util; func main() {}",
+            ),
+            ("geometry/shapes.crw", "import geometry::point;"),
+            ("geometry/point.crw", "import util;"),
+            ("util.crw", ""),
+        ]);
+        assert!(graph.diagnostics.is_empty());
+        let modules: Vec<Option<&str>> = graph.files.iter().map(|f| f.module.as_deref()).collect();
+        assert_eq!(
+            modules,
+            [
+                None,
+                Some("geometry::shapes"),
+                Some("geometry::point"),
+                Some("util")
+            ]
+        );
+        assert_eq!(graph.order, [3, 2, 1, 0]);
+        assert_eq!(
+            graph.imports[0],
+            HashMap::from([("shapes".to_string(), 1), ("util".to_string(), 3)])
+        );
+    }
+
+    #[test]
+    fn test_imports_next_to_the_importing_file_come_first() {
+        let graph = load_files(&[
+            ("main.crw", "import lib::a;"),
+            ("lib/a.crw", "import b;"),
+            ("lib/b.crw", ""),
+            ("b.crw", ""),
+        ]);
+        assert!(graph.diagnostics.is_empty());
+        assert_eq!(graph.files[2].module.as_deref(), Some("lib::b"));
+    }
+
+    #[test]
+    fn test_missing_module() {
+        let graph = load_files(&[("main.crw", "import nowhere::x;")]);
+        assert_eq!(
+            graph.diagnostics,
+            vec![Diagnostic::error(
+                ModuleError::NotFound {
+                    module: "nowhere::x".to_string(),
+                    span: Span::new(0, 18)
+                },
+                Span::new(0, 18)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_cycle_reports_the_full_chain() {
+        let graph = load_files(&[
+            ("main.crw", "import a;"),
+            ("a.crw", "import b;"),
+            ("b.crw", "import a;"),
+        ]);
+        assert_eq!(
+            messages(&graph),
+            [(2, "Import cycle: a.crw -> b.crw -> a.crw".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_alias() {
+        let graph = load_files(&[
+            ("main.crw", "import a::x; import b::x;"),
+            ("a/x.crw", ""),
+            ("b/x.crw", ""),
+        ]);
+        assert_eq!(
+            messages(&graph),
+            [(0, "A module named `x` is already imported".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_are_reported_per_file() {
+        let graph = load_files(&[("main.crw", "import a;"), ("a.crw", "func")]);
+        assert_eq!(graph.diagnostics.len(), 1);
+        assert_eq!(graph.diagnostics[0].file, 1);
+        assert!(graph.programs[1].is_none());
+    }
+}
@@ -1,11 +1,1135 @@
 //! Recursive descent parser that turns tokens into an abstract syntax tree
 //! It does not construct a parse tree/concrete syntax tree, but directly produces an abstract syntax tree.
-use crate::front_end::token::Token;
+//! Binary expressions are parsed with one function per precedence level, from loosest to tightest:
+//! range, `or`, `and`, comparison, `??`, `|`, `^`, `&`, shift, additive, multiplicative, unary,
+//! then postfix calls and `?`.
+use crate::front_end::ast::{
+    BinaryOp, Block, Expr, ExprKind, Function, Ident, Import, Item, Lambda, Literal, Param,
+    Program, Stmt, StmtKind, StringPart, TypeExpr, TypeExprKind, UnaryOp,
+};
+use crate::front_end::format;
+use crate::front_end::lexer::{Lexer, LexerError};
+use crate::front_end::token::{Span, Token, TokenKind};
+use std::error::Error;
+use std::fmt;
 
-struct Parser {}
+#[derive(Debug, PartialEq)]
+pub enum ParserError {
+    Lexer {
+        error: LexerError,
+        span: Span,
+    },
+    UnexpectedToken {
+        expected: &'static str,
+        found: TokenKind,
+        span: Span,
+    },
+    IntegerLiteralTooLarge(Span),
+}
+
+impl ParserError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParserError::Lexer { span, .. } => *span,
+            ParserError::UnexpectedToken { span, .. } => *span,
+            ParserError::IntegerLiteralTooLarge(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserError::Lexer { error, .. } => write!(f, "{}", error),
+            ParserError::UnexpectedToken {
+                expected, found, ..
+            } => write!(f, "Expected {}, found {}", expected, found),
+            ParserError::IntegerLiteralTooLarge(_) => write!(f, "Integer literal is too large"),
+        }
+    }
+}
+
+impl Error for ParserError {}
+
+/// Parser
+/// - `source` is a string slice to the original source code
+/// - `lexer` produces tokens on demand, one at a time
+/// - `current` is the token being looked at, and `previous_end` is where the last consumed token ended
+pub struct Parser<'a> {
+    source: &'a str,
+    lexer: Lexer<'a>,
+    current: Token,
+    previous_end: usize,
+}
+
+/// Parses a whole source file
+pub fn parse(source: &str) -> Result<Program, ParserError> {
+    Parser::new(source)?.parse_program()
+}
+
+impl<'a> Parser<'a> {
+    /// Returns a parser positioned at the first token of `source`
+    pub fn new(source: &'a str) -> Result<Self, ParserError> {
+        let mut lexer = Lexer::new(source);
+        let current = Self::lex(source, &mut lexer)?;
+        Ok(Self {
+            source,
+            lexer,
+            current,
+            previous_end: 0,
+        })
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, ParserError> {
+        let mut imports = Vec::new();
+        let mut items = Vec::new();
+        while !self.check(TokenKind::EOF) {
+            if self.check(TokenKind::Import) {
+                imports.push(self.parse_import()?);
+            } else {
+                items.push(self.parse_item()?);
+            }
+        }
+        Ok(Program { imports, items })
+    }
+
+    fn parse_import(&mut self) -> Result<Import, ParserError> {
+        let start = self.expect(TokenKind::Import, "`import`")?.span;
+        let mut path = vec![self.parse_ident()?];
+        while self.eat(TokenKind::DoubleColon)? {
+            path.push(self.parse_ident()?);
+        }
+        let end = self.expect(TokenKind::Semicolon, "`;`")?.span;
+        Ok(Import {
+            path,
+            span: start.to(end),
+        })
+    }
+
+    fn parse_item(&mut self) -> Result<Item, ParserError> {
+        match self.current.kind {
+            TokenKind::Func | TokenKind::Pub => Ok(Item::Function(self.parse_function()?)),
+            _ => Err(self.unexpected("a declaration")),
+        }
+    }
+
+    fn parse_function(&mut self) -> Result<Function, ParserError> {
+        let public = self.check(TokenKind::Pub);
+        let start = if public {
+            self.advance()?.span
+        } else {
+            self.current.span
+        };
+        self.expect(TokenKind::Func, "`func`")?;
+        let name = self.parse_ident()?;
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+        let body = self.parse_statement_block()?;
+        Ok(Function {
+            public,
+            name,
+            params,
+            return_type,
+            span: start.to(body.span),
+            body,
+            file: 0,
+        })
+    }
+
+    /// Parses an anonymous function expression, e.g. `func(x: Int) -> Int { return x + 1; }`
+    fn parse_lambda(&mut self) -> Result<Expr, ParserError> {
+        let start = self.expect(TokenKind::Func, "`func`")?.span;
+        let params = self.parse_params()?;
+        let return_type = self.parse_return_type()?;
+        let body = self.parse_statement_block()?;
+        let span = start.to(body.span);
+        let lambda = Lambda {
+            params,
+            return_type,
+            body,
+            captures: Vec::new(),
+        };
+        Ok(Expr::new(ExprKind::Lambda(Box::new(lambda)), span))
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<Param>, ParserError> {
+        self.expect(TokenKind::LeftCircleBracket, "`(`")?;
+        let mut params = Vec::new();
+        while !self.check(TokenKind::RightCircleBracket) {
+            let name = self.parse_ident()?;
+            self.expect(TokenKind::Colon, "`:`")?;
+            let ty = self.parse_type()?;
+            params.push(Param { name, ty });
+            if !self.eat(TokenKind::Comma)? {
+                break;
+            }
+        }
+        self.expect(TokenKind::RightCircleBracket, "`)`")?;
+        Ok(params)
+    }
+
+    fn parse_return_type(&mut self) -> Result<Option<TypeExpr>, ParserError> {
+        if self.eat(TokenKind::SkinnyArrow)? {
+            Ok(Some(self.parse_type()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<TypeExpr, ParserError> {
+        let ty = match self.current.kind {
+            TokenKind::Identifier => {
+                let ident = self.parse_ident()?;
+                if self.eat(TokenKind::LeftSquareBracket)? {
+                    let mut args = vec![self.parse_type()?];
+                    while self.eat(TokenKind::Comma)? {
+                        args.push(self.parse_type()?);
+                    }
+                    let end = self.expect(TokenKind::RightSquareBracket, "`]`")?.span;
+                    TypeExpr {
+                        kind: TypeExprKind::Generic {
+                            name: ident.name,
+                            args,
+                        },
+                        span: ident.span.to(end),
+                    }
+                } else {
+                    TypeExpr {
+                        kind: TypeExprKind::Named(ident.name),
+                        span: ident.span,
+                    }
+                }
+            }
+            TokenKind::LeftCircleBracket => {
+                let start = self.advance()?.span;
+                let end = self.expect(TokenKind::RightCircleBracket, "`)`")?.span;
+                TypeExpr {
+                    kind: TypeExprKind::Unit,
+                    span: start.to(end),
+                }
+            }
+            // A function type, e.g. `func(Int, Int) -> Bool`, whose return type defaults to `()`
+            TokenKind::Func => {
+                let start = self.advance()?.span;
+                self.expect(TokenKind::LeftCircleBracket, "`(`")?;
+                let mut params = Vec::new();
+                while !self.check(TokenKind::RightCircleBracket) {
+                    params.push(self.parse_type()?);
+                    if !self.eat(TokenKind::Comma)? {
+                        break;
+                    }
+                }
+                let mut end = self.expect(TokenKind::RightCircleBracket, "`)`")?.span;
+                let return_type = self.parse_return_type()?.map(Box::new);
+                if let Some(return_type) = &return_type {
+                    end = return_type.span;
+                }
+                // The return type already took any `?`, so `func() -> Int?` returns an `Int?`
+                return Ok(TypeExpr {
+                    kind: TypeExprKind::Function {
+                        params,
+                        return_type,
+                    },
+                    span: start.to(end),
+                });
+            }
+            _ => return Err(self.unexpected("a type")),
+        };
+        if self.check(TokenKind::Question) {
+            let end = self.advance()?.span;
+            return Ok(TypeExpr {
+                span: ty.span.to(end),
+                kind: TypeExprKind::Optional(Box::new(ty)),
+            });
+        }
+        Ok(ty)
+    }
+
+    fn parse_ident(&mut self) -> Result<Ident, ParserError> {
+        let token = self.expect(TokenKind::Identifier, "an identifier")?;
+        Ok(Ident {
+            name: token.lexeme(self.source).to_string(),
+            span: token.span,
+            variable: None,
+        })
+    }
+
+    /// Parses a block whose value is discarded (function and loop bodies).
+    /// A trailing `if` or block is demoted to a statement, any other trailing expression needs a `;`.
+    fn parse_statement_block(&mut self) -> Result<Block, ParserError> {
+        let mut block = self.parse_block()?;
+        if let Some(tail) = block.tail.take() {
+            if !tail.is_block_like() {
+                return Err(ParserError::UnexpectedToken {
+                    expected: "`;`",
+                    found: TokenKind::RightCurlyBracket,
+                    span: Span::new(block.span.end - 1, block.span.end),
+                });
+            }
+            block.stmts.push(Stmt {
+                span: tail.span,
+                kind: StmtKind::Expr(*tail),
+            });
+        }
+        Ok(block)
+    }
+
+    fn parse_block(&mut self) -> Result<Block, ParserError> {
+        let start = self.expect(TokenKind::LeftCurlyBracket, "`{`")?.span;
+        let mut stmts = Vec::new();
+        let mut tail = None;
+        while !self.check(TokenKind::RightCurlyBracket) && !self.check(TokenKind::EOF) {
+            match self.parse_statement()? {
+                ParsedStatement::Stmt(stmt) => stmts.push(stmt),
+                ParsedStatement::Tail(expr) => {
+                    tail = Some(Box::new(expr));
+                    break;
+                }
+            }
+        }
+        let end = self.expect(TokenKind::RightCurlyBracket, "`}`")?.span;
+        Ok(Block {
+            stmts,
+            tail,
+            span: start.to(end),
+        })
+    }
+
+    fn parse_statement(&mut self) -> Result<ParsedStatement, ParserError> {
+        let start = self.current.span;
+        let kind = match self.current.kind {
+            TokenKind::Var | TokenKind::Const => {
+                let mutable = self.advance()?.kind == TokenKind::Var;
+                let name = self.parse_ident()?;
+                let ty = if self.eat(TokenKind::Colon)? {
+                    Some(self.parse_type()?)
+                } else {
+                    None
+                };
+                self.expect(TokenKind::Equal, "`=`")?;
+                let value = self.parse_expression()?;
+                self.expect(TokenKind::Semicolon, "`;`")?;
+                StmtKind::Var {
+                    name,
+                    mutable,
+                    ty,
+                    value,
+                }
+            }
+            TokenKind::While => {
+                self.advance()?;
+                let cond = self.parse_expression()?;
+                let body = self.parse_statement_block()?;
+                StmtKind::While { cond, body }
+            }
+            TokenKind::For => {
+                self.advance()?;
+                let item = self.parse_ident()?;
+                self.expect(TokenKind::In, "`in`")?;
+                let iterable = self.parse_expression()?;
+                let body = self.parse_statement_block()?;
+                StmtKind::For {
+                    item,
+                    iterable,
+                    body,
+                }
+            }
+            TokenKind::Return => {
+                self.advance()?;
+                let value = if self.check(TokenKind::Semicolon) {
+                    None
+                } else {
+                    Some(self.parse_expression()?)
+                };
+                self.expect(TokenKind::Semicolon, "`;`")?;
+                StmtKind::Return(value)
+            }
+            TokenKind::Defer => {
+                self.advance()?;
+                let expr = self.parse_expression()?;
+                if !self.eat(TokenKind::Semicolon)? && !expr.is_block_like() {
+                    return Err(self.unexpected("`;`"));
+                }
+                StmtKind::Defer(expr)
+            }
+            TokenKind::Break => {
+                self.advance()?;
+                self.expect(TokenKind::Semicolon, "`;`")?;
+                StmtKind::Break
+            }
+            TokenKind::Continue => {
+                self.advance()?;
+                self.expect(TokenKind::Semicolon, "`;`")?;
+                StmtKind::Continue
+            }
+            _ => {
+                let expr = self.parse_expression()?;
+                if let Some(op) = Self::assignment_op(self.current.kind) {
+                    self.advance()?;
+                    let value = self.parse_expression()?;
+                    self.expect(TokenKind::Semicolon, "`;`")?;
+                    StmtKind::Assign {
+                        target: expr,
+                        op,
+                        value,
+                    }
+                } else if self.eat(TokenKind::Semicolon)? {
+                    StmtKind::Expr(expr)
+                } else if self.check(TokenKind::RightCurlyBracket) {
+                    return Ok(ParsedStatement::Tail(expr));
+                } else if expr.is_block_like() {
+                    StmtKind::Expr(expr)
+                } else {
+                    return Err(self.unexpected("`;`"));
+                }
+            }
+        };
+        Ok(ParsedStatement::Stmt(Stmt {
+            kind,
+            span: Span::new(start.start, self.previous_end),
+        }))
+    }
+
+    /// Maps `=` to `Some(None)`, and a compound assignment such as `+=` to `Some(Some(BinaryOp::Add))`
+    fn assignment_op(kind: TokenKind) -> Option<Option<BinaryOp>> {
+        let op = match kind {
+            TokenKind::Equal => return Some(None),
+            TokenKind::PlusEqual => BinaryOp::Add,
+            TokenKind::MinusEqual => BinaryOp::Subtract,
+            TokenKind::AsteriskEqual => BinaryOp::Multiply,
+            TokenKind::SlashEqual => BinaryOp::Divide,
+            TokenKind::PercentEqual => BinaryOp::Remainder,
+            TokenKind::AmpersandEqual => BinaryOp::BitAnd,
+            TokenKind::PipeEqual => BinaryOp::BitOr,
+            TokenKind::CaretEqual => BinaryOp::BitXor,
+            TokenKind::LeftAngleBracketLeftAngleBracketEqual => BinaryOp::ShiftLeft,
+            TokenKind::RightAngleBracketRightAngleBracketEqual => BinaryOp::ShiftRight,
+            _ => return None,
+        };
+        Some(Some(op))
+    }
+
+    pub fn parse_expression(&mut self) -> Result<Expr, ParserError> {
+        let start = self.parse_binary(0)?;
+        let inclusive = match self.current.kind {
+            TokenKind::Ellipsis => false,
+            TokenKind::EllipsisEqual => true,
+            _ => return Ok(start),
+        };
+        self.advance()?;
+        let end = self.parse_binary(0)?;
+        let span = start.span.to(end.span);
+        Ok(Expr::new(
+            ExprKind::Range {
+                start: Box::new(start),
+                end: Box::new(end),
+                inclusive,
+            },
+            span,
+        ))
+    }
+
+    /// Binary operator precedence levels, from loosest to tightest
+    const PRECEDENCE: [&'static [(TokenKind, BinaryOp)]; 10] = [
+        &[(TokenKind::Or, BinaryOp::Or)],
+        &[(TokenKind::And, BinaryOp::And)],
+        &[
+            (TokenKind::EqualEqual, BinaryOp::Equal),
+            (TokenKind::BangEqual, BinaryOp::NotEqual),
+            (TokenKind::LeftAngleBracket, BinaryOp::Less),
+            (TokenKind::LeftAngleBracketEqual, BinaryOp::LessEqual),
+            (TokenKind::RightAngleBracket, BinaryOp::Greater),
+            (TokenKind::RightAngleBracketEqual, BinaryOp::GreaterEqual),
+        ],
+        &[(TokenKind::QuestionQuestion, BinaryOp::Coalesce)],
+        &[(TokenKind::Pipe, BinaryOp::BitOr)],
+        &[(TokenKind::Caret, BinaryOp::BitXor)],
+        &[(TokenKind::Ampersand, BinaryOp::BitAnd)],
+        &[
+            (
+                TokenKind::LeftAngleBracketLeftAngleBracket,
+                BinaryOp::ShiftLeft,
+            ),
+            (
+                TokenKind::RightAngleBracketRightAngleBracket,
+                BinaryOp::ShiftRight,
+            ),
+        ],
+        &[
+            (TokenKind::Plus, BinaryOp::Add),
+            (TokenKind::Minus, BinaryOp::Subtract),
+        ],
+        &[
+            (TokenKind::Asterisk, BinaryOp::Multiply),
+            (TokenKind::Slash, BinaryOp::Divide),
+            (TokenKind::Percent, BinaryOp::Remainder),
+        ],
+    ];
+
+    /// Parses a left-associative chain of the operators at precedence `level`
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, ParserError> {
+        if level == Self::PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+        let mut left = self.parse_binary(level + 1)?;
+        while let Some(op) = Self::binary_op(Self::PRECEDENCE[level], self.current.kind) {
+            self.advance()?;
+            let right = self.parse_binary(level + 1)?;
+            left = Self::binary(left, op, right);
+        }
+        Ok(left)
+    }
+
+    fn binary_op(operators: &[(TokenKind, BinaryOp)], kind: TokenKind) -> Option<BinaryOp> {
+        operators
+            .iter()
+            .find(|(token_kind, _)| *token_kind == kind)
+            .map(|&(_, op)| op)
+    }
+
+    fn binary(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+        let span = left.span.to(right.span);
+        Expr::new(
+            ExprKind::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            },
+            span,
+        )
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ParserError> {
+        let op = match self.current.kind {
+            TokenKind::Minus => UnaryOp::Negate,
+            TokenKind::Bang => UnaryOp::Not,
+            TokenKind::Tilde => UnaryOp::BitNot,
+            _ => return self.parse_call(),
+        };
+        let start = self.advance()?.span;
+        let operand = self.parse_unary()?;
+        let span = start.to(operand.span);
+        Ok(Expr::new(
+            ExprKind::Unary {
+                op,
+                operand: Box::new(operand),
+            },
+            span,
+        ))
+    }
+
+    fn parse_call(&mut self) -> Result<Expr, ParserError> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            if self.check(TokenKind::Question) {
+                let end = self.advance()?.span;
+                let span = expr.span.to(end);
+                expr = Expr::new(ExprKind::Try(Box::new(expr)), span);
+                continue;
+            }
+            if !self.eat(TokenKind::LeftCircleBracket)? {
+                break;
+            }
+            let mut args = Vec::new();
+            while !self.check(TokenKind::RightCircleBracket) {
+                args.push(self.parse_expression()?);
+                if !self.eat(TokenKind::Comma)? {
+                    break;
+                }
+            }
+            let end = self.expect(TokenKind::RightCircleBracket, "`)`")?.span;
+            let span = expr.span.to(end);
+            if matches!(&expr.kind, ExprKind::Identifier(name) if format::takes_format(name)) {
+                self.keep_format_braces(&mut args);
+            }
+            expr = Expr::new(
+                ExprKind::Call {
+                    callee: Box::new(expr),
+                    args,
+                },
+                span,
+            );
+        }
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParserError> {
+        let token = self.current;
+        let lexeme = token.lexeme(self.source);
+        let kind = match token.kind {
+            TokenKind::IntegerLiteral => match lexeme.parse::<i64>() {
+                Ok(value) => ExprKind::Literal(Literal::Int(value)),
+                Err(_) => return Err(ParserError::IntegerLiteralTooLarge(token.span)),
+            },
+            // The lexer only produces digits with a single dot, which always parses
+            TokenKind::FloatLiteral => ExprKind::Literal(Literal::Float(lexeme.parse().unwrap())),
+            TokenKind::CharLiteral => {
+                let inner = &lexeme[1..lexeme.len() - 1];
+                ExprKind::Literal(Literal::Char(Self::unescape(inner).next().unwrap_or('\0')))
+            }
+            TokenKind::StringLiteral => {
+                let inner = &lexeme[1..lexeme.len() - 1];
+                ExprKind::Literal(Literal::String(Self::string_text(inner)))
+            }
+            TokenKind::InterpolationStart => return self.parse_interpolation(),
+            TokenKind::True => ExprKind::Literal(Literal::Bool(true)),
+            TokenKind::False => ExprKind::Literal(Literal::Bool(false)),
+            TokenKind::Null => ExprKind::Literal(Literal::Null),
+            TokenKind::Identifier => {
+                self.advance()?;
+                if !self.eat(TokenKind::DoubleColon)? {
+                    return Ok(Expr::new(
+                        ExprKind::Identifier(lexeme.to_string()),
+                        token.span,
+                    ));
+                }
+                let name = self.parse_ident()?;
+                let kind = ExprKind::Qualified {
+                    module: lexeme.to_string(),
+                    name: name.name,
+                };
+                return Ok(Expr::new(kind, token.span.to(name.span)));
+            }
+            TokenKind::LeftCircleBracket => {
+                self.advance()?;
+                let mut expr = self.parse_expression()?;
+                let end = self.expect(TokenKind::RightCircleBracket, "`)`")?.span;
+                expr.span = token.span.to(end);
+                return Ok(expr);
+            }
+            TokenKind::LeftCurlyBracket => {
+                let block = self.parse_block()?;
+                let span = block.span;
+                return Ok(Expr::new(ExprKind::Block(block), span));
+            }
+            TokenKind::If => return self.parse_if(),
+            TokenKind::Func => return self.parse_lambda(),
+            _ => return Err(self.unexpected("an expression")),
+        };
+        self.advance()?;
+        Ok(Expr::new(kind, token.span))
+    }
+
+    /// Undoes the decoding of `{{` and `}}` in a format string, which `format::parse` does instead.
+    /// A literal with no arguments after it is not a format string.
+    fn keep_format_braces(&self, args: &mut [Expr]) {
+        let [format, _, ..] = args else {
+            return;
+        };
+        let lexeme = &self.source[format.span.start..format.span.end];
+        if let ExprKind::Literal(Literal::String(text)) = &mut format.kind {
+            if lexeme.starts_with('"') {
+                *text = Self::unescape(&lexeme[1..lexeme.len() - 1]).collect();
+            }
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<Expr, ParserError> {
+        let start = self.expect(TokenKind::If, "`if`")?.span;
+        let cond = self.parse_expression()?;
+        let then_branch = self.parse_block()?;
+        let mut end = then_branch.span;
+        let else_branch = if self.eat(TokenKind::Else)? {
+            let branch = if self.check(TokenKind::If) {
+                self.parse_if()?
+            } else {
+                let block = self.parse_block()?;
+                let span = block.span;
+                Expr::new(ExprKind::Block(block), span)
+            };
+            end = branch.span;
+            Some(Box::new(branch))
+        } else {
+            None
+        };
+        Ok(Expr::new(
+            ExprKind::If {
+                cond: Box::new(cond),
+                then_branch,
+                else_branch,
+            },
+            start.to(end),
+        ))
+    }
+
+    /// Parses a string literal with embedded expressions, whose tokens the lexer has split around them
+    fn parse_interpolation(&mut self) -> Result<Expr, ParserError> {
+        let start = self.current.span;
+        let mut parts = Vec::new();
+        let mut token = self.advance()?;
+        loop {
+            // Every piece of text is delimited by a `"`, `{` or `}` on both sides
+            let lexeme = token.lexeme(self.source);
+            let text = Self::string_text(&lexeme[1..lexeme.len() - 1]);
+            if !text.is_empty() {
+                parts.push(StringPart::Text(text));
+            }
+            if token.kind == TokenKind::InterpolationEnd {
+                break;
+            }
+            parts.push(StringPart::Expr(self.parse_expression()?));
+            if !self.check(TokenKind::InterpolationMiddle)
+                && !self.check(TokenKind::InterpolationEnd)
+            {
+                return Err(self.unexpected("`}`"));
+            }
+            token = self.advance()?;
+        }
+        Ok(Expr::new(
+            ExprKind::Interpolation(parts),
+            start.to(token.span),
+        ))
+    }
+
+    /// Decodes the contents of a string literal: its escape sequences, then `{{` and `}}`
+    fn string_text(inner: &str) -> String {
+        let mut text = String::new();
+        let mut chars = Self::unescape(inner).peekable();
+        while let Some(c) = chars.next() {
+            if matches!(c, '{' | '}') && chars.peek() == Some(&c) {
+                chars.next();
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    /// Decodes the escape sequences of a literal's contents, which the lexer has already validated
+    fn unescape(inner: &str) -> impl Iterator<Item = char> + '_ {
+        let mut chars = inner.chars();
+        std::iter::from_fn(move || match chars.next()? {
+            '\\' => Some(match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                '0' => '\0',
+                c => c,
+            }),
+            c => Some(c),
+        })
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        self.current.kind == kind
+    }
+
+    /// Consumes the current token if it is of the given kind
+    fn eat(&mut self, kind: TokenKind) -> Result<bool, ParserError> {
+        if self.check(kind) {
+            self.advance()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<Token, ParserError> {
+        if self.check(kind) {
+            self.advance()
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    /// Consumes the current token, returning it
+    fn advance(&mut self) -> Result<Token, ParserError> {
+        let token = self.current;
+        self.previous_end = token.span.end;
+        self.current = Self::lex(self.source, &mut self.lexer)?;
+        Ok(token)
+    }
+
+    /// Returns the next token of `lexer`, or its error spanning the text it gave up on
+    pub(crate) fn lex(source: &str, lexer: &mut Lexer) -> Result<Token, ParserError> {
+        let start = lexer.offset();
+        lexer.next_token().map_err(|error| {
+            let end = lexer.offset();
+            let skipped = source[start..end].len() - source[start..end].trim_start().len();
+            let start = match error {
+                // The lexer stops right after the brace, which is clearer to point at than the
+                // whole string
+                LexerError::UnclosedBrace => end - '{'.len_utf8(),
+                _ => start + skipped,
+            };
+            ParserError::Lexer {
+                error,
+                span: Span::new(start, end),
+            }
+        })
+    }
+
+    fn unexpected(&self, expected: &'static str) -> ParserError {
+        ParserError::UnexpectedToken {
+            expected,
+            found: self.current.kind,
+            span: self.current.span,
+        }
+    }
+}
+
+enum ParsedStatement {
+    Stmt(Stmt),
+    /// A trailing expression without a semicolon, which becomes the value of its block
+    Tail(Expr),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_body(source: &str) -> Block {
+        let program = parse(source).unwrap();
+        let Item::Function(function) = program.items.into_iter().next().unwrap();
+        function.body
+    }
+
+    fn parse_expr(source: &str) -> Expr {
+        Parser::new(source).unwrap().parse_expression().unwrap()
+    }
+
+    #[test]
+    fn test_empty_program() {
+        assert_eq!(parse("").unwrap().items.len(), 0);
+    }
+
+    #[test]
+    fn test_function_signature() {
+        let program = parse("func add(a: Int, b: Int) -> Int { return a + b; }").unwrap();
+        let Item::Function(function) = &program.items[0];
+        assert_eq!(function.name.name, "add");
+        assert_eq!(function.params.len(), 2);
+        assert_eq!(function.params[1].name.name, "b");
+        assert_eq!(
+            function.return_type.as_ref().unwrap().kind,
+            TypeExprKind::Named("Int".to_string())
+        );
+        assert_eq!(function.span, Span::new(0, 49));
+    }
+
+    #[test]
+    fn test_unit_return_type() {
+        let program = parse("func f() -> () {}").unwrap();
+        let Item::Function(function) = &program.items[0];
+        assert_eq!(
+            function.return_type.as_ref().unwrap().kind,
+            TypeExprKind::Unit
+        );
+    }
+
+    #[test]
+    fn test_precedence() {
+        let expr = parse_expr("1 + 2 * 3 == 7 and !false");
+        let ExprKind::Binary { left, op, .. } = expr.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op, BinaryOp::And);
+        let ExprKind::Binary { left, op, .. } = left.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op, BinaryOp::Equal);
+        let ExprKind::Binary { right, op, .. } = left.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op, BinaryOp::Add);
+        assert!(matches!(
+            right.kind,
+            ExprKind::Binary {
+                op: BinaryOp::Multiply,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_left_associativity() {
+        let expr = parse_expr("10 - 4 - 3");
+        let ExprKind::Binary { left, .. } = expr.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(left.span, Span::new(0, 6));
+    }
+
+    #[test]
+    fn test_parenthesized_span() {
+        let expr = parse_expr("(1 + 2) * 3");
+        let ExprKind::Binary { left, .. } = expr.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(left.span, Span::new(0, 7));
+    }
+
+    #[test]
+    fn test_optional_type() {
+        let program = parse("func f(x: Int?) {}").unwrap();
+        let Item::Function(function) = &program.items[0];
+        let ty = &function.params[0].ty;
+        assert_eq!(ty.span, Span::new(10, 14));
+        assert!(matches!(ty.kind, TypeExprKind::Optional(_)));
+    }
+
+    #[test]
+    fn test_coalesce_precedence() {
+        // `??` binds tighter than comparison, but looser than arithmetic
+        let expr = parse_expr("x ?? 0 + 1 > 2");
+        let ExprKind::Binary { left, op, .. } = expr.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op, BinaryOp::Greater);
+        let ExprKind::Binary { op, right, .. } = left.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(op, BinaryOp::Coalesce);
+        assert!(matches!(
+            right.kind,
+            ExprKind::Binary {
+                op: BinaryOp::Add,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_function_type() {
+        let body =
+            parse_body("func main() { var f: func(Int, Bool) -> Int? = g; var h: func() = k; }");
+        let StmtKind::Var { ty: Some(ty), .. } = &body.stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        let TypeExprKind::Function {
+            params,
+            return_type: Some(return_type),
+        } = &ty.kind
+        else {
+            panic!("expected a function type");
+        };
+        assert_eq!(params.len(), 2);
+        assert!(matches!(return_type.kind, TypeExprKind::Optional(_)));
+        assert_eq!(ty.span, Span::new(21, 44));
+
+        let StmtKind::Var { ty: Some(ty), .. } = &body.stmts[1].kind else {
+            panic!("expected a declaration");
+        };
+        assert_eq!(
+            ty.kind,
+            TypeExprKind::Function {
+                params: Vec::new(),
+                return_type: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_lambda_expression() {
+        let expr = parse_expr("func(x: Int) -> Int { return x; }(1)");
+        let ExprKind::Call { callee, args } = expr.kind else {
+            panic!("expected a call");
+        };
+        assert_eq!(args.len(), 1);
+        let ExprKind::Lambda(lambda) = callee.kind else {
+            panic!("expected an anonymous function");
+        };
+        assert_eq!(callee.span, Span::new(0, 33));
+        assert_eq!(lambda.params[0].name.name, "x");
+        assert_eq!(lambda.body.stmts.len(), 1);
+        assert!(lambda.captures.is_empty());
+    }
+
+    #[test]
+    fn test_generic_type() {
+        let program = parse("func f() -> Result[Int?, String] {}").unwrap();
+        let Item::Function(function) = &program.items[0];
+        let ty = function.return_type.as_ref().unwrap();
+        let TypeExprKind::Generic { name, args } = &ty.kind else {
+            panic!("expected generic type");
+        };
+        assert_eq!(name, "Result");
+        assert_eq!(args.len(), 2);
+        assert_eq!(ty.span, Span::new(12, 32));
+    }
+
+    #[test]
+    fn test_try_operator() {
+        let expr = parse_expr("parse(s)? + 1");
+        let ExprKind::Binary { left, .. } = expr.kind else {
+            panic!("expected binary expression");
+        };
+        assert_eq!(left.span, Span::new(0, 9));
+        assert!(matches!(left.kind, ExprKind::Try(_)));
+    }
+
+    #[test]
+    fn test_string_literal() {
+        assert_eq!(
+            parse_expr(r#""a\tb\"""#).kind,
+            ExprKind::Literal(Literal::String("a\tb\"".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let expr = parse_expr(r#""{{x}} = {x + 1}\n{f("{y}")}""#);
+        assert_eq!(expr.span, Span::new(0, 29));
+        let ExprKind::Interpolation(parts) = expr.kind else {
+            panic!("expected interpolation");
+        };
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], StringPart::Text("{x} = ".to_string()));
+        assert!(
+            matches!(&parts[1], StringPart::Expr(e) if matches!(e.kind, ExprKind::Binary { .. }))
+        );
+        assert_eq!(parts[2], StringPart::Text("\n".to_string()));
+        let StringPart::Expr(call) = &parts[3] else {
+            panic!("expected embedded call");
+        };
+        let ExprKind::Call { args, .. } = &call.kind else {
+            panic!("expected call");
+        };
+        assert!(matches!(args[0].kind, ExprKind::Interpolation(_)));
+
+        assert_eq!(
+            Parser::new(r#""{x y}""#).unwrap().parse_expression(),
+            Err(ParserError::UnexpectedToken {
+                expected: "`}`",
+                found: TokenKind::Identifier,
+                span: Span::new(4, 5),
+            })
+        );
+    }
+
+    #[test]
+    fn test_format_strings_keep_their_braces() {
+        let literal = |text: &str| ExprKind::Literal(Literal::String(text.to_string()));
+        let ExprKind::Call { args, .. } = parse_expr(r#"println("{{}} {}", "{{}}")"#).kind else {
+            panic!("expected call");
+        };
+        assert_eq!(args[0].kind, literal("{{}} {}"));
+        assert_eq!(args[1].kind, literal("{}"));
+    }
+
+    #[test]
+    fn test_range() {
+        let expr = parse_expr("0..=n - 1");
+        assert!(matches!(
+            expr.kind,
+            ExprKind::Range {
+                inclusive: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_char_escapes() {
+        assert_eq!(
+            parse_expr(r"'\n'").kind,
+            ExprKind::Literal(Literal::Char('\n'))
+        );
+        assert_eq!(
+            parse_expr(r"'\''").kind,
+            ExprKind::Literal(Literal::Char('\''))
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        let body = parse_body(
+            "func main() { var x: Int = 1; const y = 2; x += y; while x < 10 { x = x * 2; } for i in 0..3 { break; } return; }",
+        );
+        assert_eq!(body.stmts.len(), 6);
+        assert!(matches!(
+            body.stmts[2].kind,
+            StmtKind::Assign {
+                op: Some(BinaryOp::Add),
+                ..
+            }
+        ));
+        assert!(matches!(body.stmts[5].kind, StmtKind::Return(None)));
+    }
+
+    #[test]
+    fn test_statement_span_includes_semicolon() {
+        let body = parse_body("func main() { var x = 1; }");
+        assert_eq!(body.stmts[0].span, Span::new(14, 24));
+    }
+
+    #[test]
+    fn test_if_expression_with_tails() {
+        let body = parse_body("func main() { var c = if x > 0 { 'y' } else { 'n' }; }");
+        let StmtKind::Var { value, .. } = &body.stmts[0].kind else {
+            panic!("expected var declaration");
+        };
+        let ExprKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } = &value.kind
+        else {
+            panic!("expected if expression");
+        };
+        assert!(then_branch.tail.is_some());
+        assert!(else_branch.is_some());
+    }
+
+    #[test]
+    fn test_trailing_if_is_a_statement() {
+        let body = parse_body("func main() { if a { f(); } else if b { g(); } }");
+        assert_eq!(body.stmts.len(), 1);
+        assert!(body.tail.is_none());
+    }
+
+    #[test]
+    fn test_defer() {
+        let body = parse_body("func main() { defer close(f); defer { g(); } h(); }");
+        assert_eq!(body.stmts.len(), 3);
+        let StmtKind::Defer(expr) = &body.stmts[0].kind else {
+            panic!("expected defer statement");
+        };
+        assert!(matches!(expr.kind, ExprKind::Call { .. }));
+        assert!(matches!(body.stmts[1].kind, StmtKind::Defer(_)));
+        assert!(parse("func main() { defer f() }").is_err());
+    }
+
+    #[test]
+    fn test_missing_semicolon() {
+        let err = parse("func main() { var x = 1 }").unwrap_err();
+        assert_eq!(
+            err,
+            ParserError::UnexpectedToken {
+                expected: "`;`",
+                found: TokenKind::RightCurlyBracket,
+                span: Span::new(24, 25),
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_semicolon_before_closing_brace() {
+        let err = parse("func main() { f() }").unwrap_err();
+        assert_eq!(err.span(), Span::new(18, 19));
+    }
 
-impl Parser {
-    pub fn new() {}
+    #[test]
+    fn test_integer_literal_too_large() {
+        let err = parse("func main() { var x = 99999999999999999999; }").unwrap_err();
+        assert_eq!(err, ParserError::IntegerLiteralTooLarge(Span::new(22, 42)));
+    }
 
-    pub fn consume() {}
+    #[test]
+    fn test_lexer_error_span() {
+        let err = parse("func main() {  @ }").unwrap_err();
+        assert_eq!(
+            err,
+            ParserError::Lexer {
+                error: LexerError::UnrecognizedCharacter('@'),
+                span: Span::new(15, 16),
+            }
+        );
+        assert_eq!(
+            parse(r#"func main() { var s = "{"; }"#).unwrap_err(),
+            ParserError::Lexer {
+                error: LexerError::UnclosedBrace,
+                span: Span::new(23, 24),
+            }
+        );
+    }
 }
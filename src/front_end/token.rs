@@ -1,7 +1,8 @@
 //! Token and token related data structures
+use std::fmt;
 
 /// Token type
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
@@ -15,7 +16,7 @@ pub struct Token {
 /// - Operators
 /// - Keywords
 /// - Special token (i.e. EOF)
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokenKind {
     // NOTE:
     // We could have easily attached the lexemes to a variant (e.g. Identifier(String)),
@@ -32,6 +33,11 @@ pub enum TokenKind {
     CharLiteral,
     StringLiteral,
     MultilineStringLiteral,
+    // A string literal with embedded expressions is split around them, e.g. `"a {x} b {y}!"` is
+    // `"a {`, then the tokens of `x`, `} b {`, the tokens of `y`, and finally `}!"`
+    InterpolationStart,
+    InterpolationMiddle,
+    InterpolationEnd,
 
     // Delimiters
     LeftCircleBracket,
@@ -80,6 +86,8 @@ pub enum TokenKind {
     FatArrow,
     Ellipsis,
     EllipsisEqual,
+    Question,
+    QuestionQuestion,
 
     // Keywords
     And,
@@ -113,7 +121,7 @@ pub enum TokenKind {
 }
 
 /// The location of the token within the source, bounded by an inclusive `start`, and a exclusive `end`.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Span {
     // inclusive
     pub start: usize,
@@ -126,6 +134,23 @@ impl Span {
     pub fn new(start: usize, end: usize) -> Self {
         Span { start, end }
     }
+
+    /// Returns the smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Self {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// Returns the 1-based line and column of the span's start within `source`
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let col = before[line_start..].chars().count() + 1;
+        (line, col)
+    }
 }
 
 impl Token {
@@ -170,3 +195,91 @@ impl Token {
         }
     }
 }
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TokenKind::Identifier => "identifier",
+            TokenKind::IntegerLiteral => "integer literal",
+            TokenKind::FloatLiteral => "float literal",
+            TokenKind::CharLiteral => "character literal",
+            TokenKind::StringLiteral => "string literal",
+            TokenKind::MultilineStringLiteral => "multiline string literal",
+            TokenKind::InterpolationStart => "interpolated string literal",
+            TokenKind::InterpolationMiddle | TokenKind::InterpolationEnd => "`}`",
+            TokenKind::LeftCircleBracket => "`(`",
+            TokenKind::RightCircleBracket => "`)`",
+            TokenKind::LeftCurlyBracket => "`{`",
+            TokenKind::RightCurlyBracket => "`}`",
+            TokenKind::LeftSquareBracket => "`[`",
+            TokenKind::RightSquareBracket => "`]`",
+            TokenKind::Colon => "`:`",
+            TokenKind::DoubleColon => "`::`",
+            TokenKind::Semicolon => "`;`",
+            TokenKind::Dot => "`.`",
+            TokenKind::Comma => "`,`",
+            TokenKind::Equal => "`=`",
+            TokenKind::Bang => "`!`",
+            TokenKind::Plus => "`+`",
+            TokenKind::Minus => "`-`",
+            TokenKind::Asterisk => "`*`",
+            TokenKind::Ampersand => "`&`",
+            TokenKind::Slash => "`/`",
+            TokenKind::Percent => "`%`",
+            TokenKind::AmpersandEqual => "`&=`",
+            TokenKind::Tilde => "`~`",
+            TokenKind::Pipe => "`|`",
+            TokenKind::PipeEqual => "`|=`",
+            TokenKind::PlusEqual => "`+=`",
+            TokenKind::MinusEqual => "`-=`",
+            TokenKind::CaretEqual => "`^=`",
+            TokenKind::Caret => "`^`",
+            TokenKind::AsteriskEqual => "`*=`",
+            TokenKind::SlashEqual => "`/=`",
+            TokenKind::PercentEqual => "`%=`",
+            TokenKind::EqualEqual => "`==`",
+            TokenKind::BangEqual => "`!=`",
+            TokenKind::RightAngleBracket => "`>`",
+            TokenKind::RightAngleBracketRightAngleBracketEqual => "`>>=`",
+            TokenKind::RightAngleBracketRightAngleBracket => "`>>`",
+            TokenKind::RightAngleBracketEqual => "`>=`",
+            TokenKind::LeftAngleBracket => "`<`",
+            TokenKind::LeftAngleBracketLeftAngleBracket => "`<<`",
+            TokenKind::LeftAngleBracketLeftAngleBracketEqual => "`<<=`",
+            TokenKind::LeftAngleBracketEqual => "`<=`",
+            TokenKind::SkinnyArrow => "`->`",
+            TokenKind::FatArrow => "`=>`",
+            TokenKind::Ellipsis => "`..`",
+            TokenKind::EllipsisEqual => "`..=`",
+            TokenKind::Question => "`?`",
+            TokenKind::QuestionQuestion => "`??`",
+            TokenKind::And => "`and`",
+            TokenKind::Break => "`break`",
+            TokenKind::Continue => "`continue`",
+            TokenKind::Const => "`const`",
+            TokenKind::Else => "`else`",
+            TokenKind::Enum => "`enum`",
+            TokenKind::Defer => "`defer`",
+            TokenKind::False => "`false`",
+            TokenKind::For => "`for`",
+            TokenKind::Func => "`func`",
+            TokenKind::If => "`if`",
+            TokenKind::Implements => "`implements`",
+            TokenKind::Import => "`import`",
+            TokenKind::In => "`in`",
+            TokenKind::Interface => "`interface`",
+            TokenKind::Match => "`match`",
+            TokenKind::Null => "`null`",
+            TokenKind::Or => "`or`",
+            TokenKind::Pub => "`pub`",
+            TokenKind::Return => "`return`",
+            TokenKind::Struct => "`struct`",
+            TokenKind::This => "`this`",
+            TokenKind::True => "`true`",
+            TokenKind::Var => "`var`",
+            TokenKind::While => "`while`",
+            TokenKind::EOF => "end of file",
+        };
+        write!(f, "{}", text)
+    }
+}
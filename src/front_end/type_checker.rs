@@ -0,0 +1,2336 @@
+//! Type checker that walks the abstract syntax tree, resolves names, and annotates every expression with its type
+use crate::front_end::ast::{
+    BinaryOp, Block, Capture, Expr, ExprKind, Function, Ident, Item, Lambda, Literal, Program,
+    Stmt, StmtKind, StringPart, TypeExpr, TypeExprKind, UnaryOp, VariableId,
+};
+use crate::front_end::format::{self, FormatError, Piece};
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum TypeError {
+    UnknownType(String, Span),
+    UndefinedVariable(String, Span),
+    UndefinedFunction(String, Span),
+    DuplicateFunction(String, Span),
+    DuplicateVariable(String, Span),
+    MissingMain,
+    InvalidMainSignature(Span),
+    Mismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    InvalidUnaryOperand {
+        op: UnaryOp,
+        operand: Type,
+        span: Span,
+    },
+    InvalidBinaryOperands {
+        op: BinaryOp,
+        left: Type,
+        right: Type,
+        span: Span,
+    },
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    NotCallable(Span),
+    FunctionAsValue(String, Span),
+    AssignToConstant(String, Span),
+    InvalidAssignmentTarget(Span),
+    NotIterable(Type, Span),
+    NotPrintable(Type, Span),
+    InvalidFormatString(FormatError, Span),
+    FormatArgumentCount {
+        placeholders: usize,
+        found: usize,
+        span: Span,
+    },
+    PrecisionNotFloat(Type, Span),
+    NoConversion {
+        from: Type,
+        to: Type,
+        span: Span,
+    },
+    IntegerLiteralOutOfRange(Span),
+    PossiblyNull(Type, Span),
+    UninferableNull(Span),
+    WrongTypeArguments {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    NotAResult(Type, Span),
+    NotANumber(Type, Span),
+    PropagationOutsideResult(Type, Span),
+    IncompatibleErrorType {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    UnknownModule(String, Span),
+    PrivateFunction {
+        module: String,
+        name: String,
+        span: Span,
+    },
+}
+
+impl TypeError {
+    pub fn span(&self) -> Span {
+        match self {
+            TypeError::UnknownType(_, span)
+            | TypeError::UndefinedVariable(_, span)
+            | TypeError::UndefinedFunction(_, span)
+            | TypeError::DuplicateFunction(_, span)
+            | TypeError::DuplicateVariable(_, span)
+            | TypeError::InvalidMainSignature(span)
+            | TypeError::Mismatch { span, .. }
+            | TypeError::InvalidUnaryOperand { span, .. }
+            | TypeError::InvalidBinaryOperands { span, .. }
+            | TypeError::ArgumentCount { span, .. }
+            | TypeError::NotCallable(span)
+            | TypeError::FunctionAsValue(_, span)
+            | TypeError::AssignToConstant(_, span)
+            | TypeError::InvalidAssignmentTarget(span)
+            | TypeError::NotIterable(_, span)
+            | TypeError::NotPrintable(_, span)
+            | TypeError::InvalidFormatString(_, span)
+            | TypeError::FormatArgumentCount { span, .. }
+            | TypeError::PrecisionNotFloat(_, span)
+            | TypeError::NoConversion { span, .. }
+            | TypeError::IntegerLiteralOutOfRange(span)
+            | TypeError::PossiblyNull(_, span)
+            | TypeError::UninferableNull(span)
+            | TypeError::WrongTypeArguments { span, .. }
+            | TypeError::NotAResult(_, span)
+            | TypeError::NotANumber(_, span)
+            | TypeError::PropagationOutsideResult(_, span)
+            | TypeError::IncompatibleErrorType { span, .. }
+            | TypeError::UnknownModule(_, span)
+            | TypeError::PrivateFunction { span, .. } => *span,
+            TypeError::MissingMain => Span::new(0, 0),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::UnknownType(name, _) => write!(f, "Unknown type `{}`", name),
+            TypeError::UndefinedVariable(name, _) => write!(f, "Undefined variable `{}`", name),
+            TypeError::UndefinedFunction(name, _) => write!(f, "Undefined function `{}`", name),
+            TypeError::DuplicateFunction(name, _) => {
+                write!(f, "Function `{}` is defined more than once", name)
+            }
+            TypeError::DuplicateVariable(name, _) => {
+                write!(f, "Variable `{}` is already declared in this scope", name)
+            }
+            TypeError::MissingMain => write!(f, "Missing entry point `main()`"),
+            TypeError::InvalidMainSignature(_) => {
+                write!(f, "`main()` must take no parameters and return `()`")
+            }
+            TypeError::Mismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "Mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            TypeError::InvalidUnaryOperand { op, operand, .. } => write!(
+                f,
+                "Cannot apply unary operator `{}` to `{}`",
+                op.symbol(),
+                operand
+            ),
+            TypeError::InvalidBinaryOperands {
+                op, left, right, ..
+            } => write!(
+                f,
+                "Cannot apply binary operator `{}` to `{}` and `{}`",
+                op.symbol(),
+                left,
+                right
+            ),
+            TypeError::ArgumentCount {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "Function `{}` takes {} argument(s), but {} were supplied",
+                name, expected, found
+            ),
+            TypeError::NotCallable(_) => write!(f, "Only functions can be called"),
+            TypeError::FunctionAsValue(name, _) => {
+                write!(f, "Function `{}` cannot be used as a value", name)
+            }
+            TypeError::AssignToConstant(name, _) => {
+                write!(f, "Cannot assign twice to constant `{}`", name)
+            }
+            TypeError::InvalidAssignmentTarget(_) => {
+                write!(f, "Invalid left-hand side of assignment")
+            }
+            TypeError::NotIterable(ty, _) => write!(f, "`{}` is not iterable", ty),
+            TypeError::NotPrintable(ty, _) => write!(f, "`{}` cannot be printed", ty),
+            TypeError::InvalidFormatString(error, _) => {
+                write!(f, "Invalid format string: {}", error)
+            }
+            TypeError::FormatArgumentCount {
+                placeholders,
+                found,
+                ..
+            } => write!(
+                f,
+                "Format string has {} placeholder(s), but {} argument(s) were supplied",
+                placeholders, found
+            ),
+            TypeError::PrecisionNotFloat(ty, _) => write!(
+                f,
+                "Only a `Float` can be printed with a precision, not `{}`",
+                ty
+            ),
+            TypeError::NoConversion { from, to, .. } => {
+                write!(f, "`{}` cannot be converted to `{}`", from, to)
+            }
+            TypeError::IntegerLiteralOutOfRange(_) => {
+                write!(f, "Integer literal does not fit in a 32-bit `Int`")
+            }
+            TypeError::PossiblyNull(ty, _) => write!(
+                f,
+                "Value of type `{}` may be null; compare it against `null` or provide a default with `??`",
+                ty
+            ),
+            TypeError::UninferableNull(_) => write!(
+                f,
+                "Cannot infer a type from `null`; annotate the variable with an optional type"
+            ),
+            TypeError::WrongTypeArguments {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "Type `{}` takes {} type argument(s), but {} were supplied",
+                name, expected, found
+            ),
+            TypeError::NotAResult(ty, _) => write!(f, "Expected a `Result`, found `{}`", ty),
+            TypeError::NotANumber(ty, _) => {
+                write!(f, "Expected an `Int` or a `Float`, found `{}`", ty)
+            }
+            TypeError::PropagationOutsideResult(ty, _) => write!(
+                f,
+                "`?` can only be used in a function that returns a `Result`, but this function returns `{}`",
+                ty
+            ),
+            TypeError::IncompatibleErrorType {
+                expected, found, ..
+            } => write!(
+                f,
+                "`?` cannot propagate an error of type `{}` from a function whose error type is `{}`",
+                found, expected
+            ),
+            TypeError::UnknownModule(name, _) => {
+                write!(f, "Unknown module `{}`; it must be imported first", name)
+            }
+            TypeError::PrivateFunction { module, name, .. } => write!(
+                f,
+                "Function `{}::{}` is private; declare it with `pub` to use it from other modules",
+                module, name
+            ),
+        }
+    }
+}
+
+impl Error for TypeError {}
+
+/// A function's parameter and return types
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+/// The functions of a module, as seen by the modules importing it
+/// - `name` is the module's path from the project root (e.g. `geometry::shapes`), which prefixes the
+///   linked name of each of its functions (e.g. `geometry::shapes::area`)
+/// - `functions` maps the declared name of each function to its signature
+/// - `public` holds the functions declared with `pub`, which are the only ones other modules may use
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModuleInterface {
+    pub name: String,
+    pub functions: HashMap<String, Signature>,
+    pub public: HashSet<String>,
+}
+
+/// Functions that are always in scope without a declaration.
+/// `Ok` and `Err` construct the variants of the built-in `Result` enum.
+pub const BUILTINS: [&str; 31] = [
+    "print",
+    "println",
+    "input",
+    "panic",
+    "Ok",
+    "Err",
+    "unwrap",
+    "unwrap_err",
+    "is_ok",
+    "is_err",
+    "int",
+    "float",
+    "char",
+    "string",
+    "min",
+    "max",
+    "abs",
+    "pow",
+    "sqrt",
+    "ceil",
+    "floor",
+    "sin",
+    "cos",
+    "log",
+    "ln",
+    "pow_e",
+    "open",
+    "close",
+    "read",
+    "read_line",
+    "write",
+];
+
+/// A declared variable, identified by `id` so that narrowing survives shadowing.
+/// `level` is the number of anonymous functions enclosing the declaration.
+struct Variable {
+    id: VariableId,
+    ty: Type,
+    mutable: bool,
+    level: usize,
+}
+
+/// Variables known to be non-null when a condition is true and when it is false, respectively
+type Narrowing = (HashSet<usize>, HashSet<usize>);
+
+/// Type checker
+/// - `module` is the name of the module being checked, or `None` for the program's entry file
+/// - `imports` maps the alias of every imported module to its interface
+/// - `functions` maps the linked name of every top level function in scope to its signature
+/// - `scopes` is a stack of lexical scopes, innermost last
+/// - `non_null` holds the optional variables that the control flow so far proves are not `null`
+/// - `return_type` is the declared return type of the function being checked
+/// - `captures` holds the captures found so far for each anonymous function being checked, innermost last
+/// - `unstable` holds the optional variables that some closure assigns to, which are never narrowed
+///   because calling that closure may set them to `null` at any point. So do the variables a
+///   deferred expression assigns to, which it may set when its block ends.
+/// - `deferring` is whether the expression being checked is deferred
+pub struct TypeChecker {
+    module: Option<String>,
+    imports: HashMap<String, ModuleInterface>,
+    functions: HashMap<String, Signature>,
+    scopes: Vec<HashMap<String, Variable>>,
+    variable_count: usize,
+    non_null: HashSet<usize>,
+    return_type: Type,
+    captures: Vec<Vec<Capture>>,
+    unstable: HashSet<usize>,
+    deferring: bool,
+    errors: Vec<TypeError>,
+}
+
+/// Type checks `program`, filling in the `ty` of every expression
+pub fn check(program: &mut Program) -> Result<(), Vec<TypeError>> {
+    let (_, errors) = check_module(program, None, HashMap::new());
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Type checks one module of a multi-file program, given the interfaces of the modules it imports.
+/// Every function is renamed to its linked name, and so is every reference to one, so that the
+/// modules of a program can be merged without clashes.
+/// Only the entry file (`module` is `None`) has to define `main()`.
+pub fn check_module(
+    program: &mut Program,
+    module: Option<&str>,
+    imports: HashMap<String, ModuleInterface>,
+) -> (ModuleInterface, Vec<TypeError>) {
+    let mut checker = TypeChecker::new();
+    checker.module = module.map(str::to_string);
+    checker.imports = imports;
+    checker.check_program(program);
+
+    let mut interface = ModuleInterface {
+        name: module.unwrap_or_default().to_string(),
+        ..ModuleInterface::default()
+    };
+    for item in &program.items {
+        let Item::Function(function) = item;
+        let name = function.name.name.rsplit("::").next().unwrap();
+        if let Some(signature) = checker.functions.get(&function.name.name) {
+            interface
+                .functions
+                .insert(name.to_string(), signature.clone());
+        }
+        if function.public {
+            interface.public.insert(name.to_string());
+        }
+    }
+    (interface, checker.errors)
+}
+
+/// Resolves a type annotation from a program that already passed type checking
+pub fn resolve_annotation(ty: &TypeExpr) -> Type {
+    TypeChecker::new().resolve_type(ty)
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            module: None,
+            imports: HashMap::new(),
+            functions: HashMap::new(),
+            scopes: Vec::new(),
+            variable_count: 0,
+            non_null: HashSet::new(),
+            return_type: Type::Unit,
+            captures: Vec::new(),
+            unstable: HashSet::new(),
+            deferring: false,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn check_program(&mut self, program: &mut Program) {
+        for interface in self.imports.values() {
+            for (name, signature) in &interface.functions {
+                let linked = format!("{}::{}", interface.name, name);
+                self.functions.insert(linked, signature.clone());
+            }
+        }
+
+        // Collect every signature first, so that functions can be called before their declaration
+        let mut declared = HashSet::new();
+        for item in &program.items {
+            let Item::Function(function) = item;
+            let signature = self.signature(function);
+            let name = &function.name;
+            if !declared.insert(&name.name) || BUILTINS.contains(&name.name.as_str()) {
+                self.error(TypeError::DuplicateFunction(name.name.clone(), name.span));
+            } else {
+                self.functions
+                    .insert(self.linked_name(&name.name), signature);
+            }
+        }
+
+        if self.module.is_none() {
+            let main = program.items.iter().find_map(|item| match item {
+                Item::Function(function) if function.name.name == "main" => Some(function),
+                _ => None,
+            });
+            match main {
+                None => self.error(TypeError::MissingMain),
+                Some(main)
+                    if !main.params.is_empty()
+                        || self.functions["main"].return_type != Type::Unit =>
+                {
+                    self.error(TypeError::InvalidMainSignature(main.name.span));
+                }
+                Some(_) => (),
+            }
+        }
+
+        for item in &mut program.items {
+            let Item::Function(function) = item;
+            self.check_function(function);
+        }
+        for item in &mut program.items {
+            let Item::Function(function) = item;
+            function.name.name = self.linked_name(&function.name.name);
+        }
+    }
+
+    fn signature(&mut self, function: &Function) -> Signature {
+        let params = function
+            .params
+            .iter()
+            .map(|param| self.resolve_type(&param.ty))
+            .collect();
+        let return_type = match &function.return_type {
+            Some(ty) => self.resolve_type(ty),
+            None => Type::Unit,
+        };
+        Signature {
+            params,
+            return_type,
+        }
+    }
+
+    fn resolve_type(&mut self, ty: &TypeExpr) -> Type {
+        match &ty.kind {
+            TypeExprKind::Unit => Type::Unit,
+            TypeExprKind::Optional(inner) => Type::Optional(Box::new(self.resolve_type(inner))),
+            TypeExprKind::Named(name) if name == "Result" => {
+                self.error(TypeError::WrongTypeArguments {
+                    name: name.clone(),
+                    expected: 2,
+                    found: 0,
+                    span: ty.span,
+                });
+                Type::Error
+            }
+            TypeExprKind::Named(name) => Type::from_name(name).unwrap_or_else(|| {
+                self.error(TypeError::UnknownType(name.clone(), ty.span));
+                Type::Error
+            }),
+            TypeExprKind::Generic { name, args } => {
+                let expected = match name.as_str() {
+                    "Result" => 2,
+                    _ if Type::from_name(name).is_some() => 0,
+                    _ => {
+                        self.error(TypeError::UnknownType(name.clone(), ty.span));
+                        return Type::Error;
+                    }
+                };
+                if args.len() != expected {
+                    self.error(TypeError::WrongTypeArguments {
+                        name: name.clone(),
+                        expected,
+                        found: args.len(),
+                        span: ty.span,
+                    });
+                    return Type::Error;
+                }
+                let ok = self.resolve_type(&args[0]);
+                let err = self.resolve_type(&args[1]);
+                Type::Result(Box::new(ok), Box::new(err))
+            }
+            TypeExprKind::Function {
+                params,
+                return_type,
+            } => {
+                let params = params
+                    .iter()
+                    .map(|param| self.resolve_type(param))
+                    .collect();
+                let return_type = match return_type {
+                    Some(ty) => self.resolve_type(ty),
+                    None => Type::Unit,
+                };
+                Type::Function(params, Box::new(return_type))
+            }
+        }
+    }
+
+    fn check_function(&mut self, function: &mut Function) {
+        // A function named like a built-in one was reported, and has no signature
+        let Some(signature) = self.functions.get(&self.linked_name(&function.name.name)) else {
+            return;
+        };
+        let signature = signature.clone();
+        self.return_type = signature.return_type;
+        self.non_null.clear();
+        self.scopes.push(HashMap::new());
+        for (param, ty) in function.params.iter_mut().zip(signature.params) {
+            self.declare(&mut param.name, ty, true);
+        }
+        self.check_block(&mut function.body);
+        self.scopes.pop();
+    }
+
+    fn check_block(&mut self, block: &mut Block) -> Type {
+        self.scopes.push(HashMap::new());
+        let mut diverges = false;
+        for stmt in &mut block.stmts {
+            self.check_stmt(stmt);
+            diverges |= match &stmt.kind {
+                StmtKind::Return(_) | StmtKind::Break | StmtKind::Continue => true,
+                StmtKind::Expr(expr) => expr.ty == Type::Never,
+                _ => false,
+            };
+        }
+        let ty = match &mut block.tail {
+            Some(tail) => self.check_expr(tail),
+            None if diverges => Type::Never,
+            None => Type::Unit,
+        };
+        self.scopes.pop();
+        ty
+    }
+
+    fn check_stmt(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Var {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                let value_ty = self.check_expr(value);
+                let ty = match ty {
+                    Some(annotation) => {
+                        let declared = self.resolve_type(annotation);
+                        self.expect_type(&declared, &value_ty, value.span);
+                        declared
+                    }
+                    None if value_ty == Type::Null => {
+                        self.error(TypeError::UninferableNull(value.span));
+                        Type::Error
+                    }
+                    None => value_ty.clone(),
+                };
+                if self.scopes.last().unwrap().contains_key(&name.name) {
+                    self.error(TypeError::DuplicateVariable(name.name.clone(), name.span));
+                }
+                let id = self.declare(name, ty, *mutable);
+                self.narrow_on_assignment(id, &value_ty);
+            }
+            StmtKind::Assign { target, op, value } => {
+                let target_ty = self.check_assignment_target(target);
+                let value_ty = self.check_expr(value);
+                match op {
+                    Some(op) => {
+                        let left = self.reject_optional(target_ty.clone(), target.span);
+                        let right = self.reject_optional(value_ty, value.span);
+                        let result = self.binary_result(*op, &left, &right, stmt.span);
+                        self.expect_type(&target_ty, &result, stmt.span);
+                    }
+                    None => {
+                        self.expect_type(&target_ty, &value_ty, value.span);
+                        if let Some(id) = self.variable_id(target) {
+                            self.narrow_on_assignment(id, &value_ty);
+                        }
+                    }
+                }
+            }
+            StmtKind::Expr(expr) => {
+                self.check_expr(expr);
+            }
+            StmtKind::While { cond, body } => {
+                self.forget_assigned_in(body);
+                let entry = self.non_null.clone();
+                let cond_ty = self.check_expr(cond);
+                self.expect_type(&Type::Bool, &cond_ty, cond.span);
+                let (when_true, _) = self.narrowing(cond);
+                self.non_null.extend(when_true);
+                self.check_block(body);
+                self.non_null = entry;
+            }
+            StmtKind::For {
+                item,
+                iterable,
+                body,
+            } => {
+                let iterable_ty = self.check_expr(iterable);
+                let item_ty = match iterable_ty {
+                    Type::Range => Type::Int,
+                    Type::String => Type::Char,
+                    Type::File => Type::String,
+                    Type::Error => Type::Error,
+                    other => {
+                        self.error(TypeError::NotIterable(other, iterable.span));
+                        Type::Error
+                    }
+                };
+                self.forget_assigned_in(body);
+                let entry = self.non_null.clone();
+                self.scopes.push(HashMap::new());
+                self.declare(item, item_ty, false);
+                self.check_block(body);
+                self.scopes.pop();
+                self.non_null = entry;
+            }
+            StmtKind::Return(value) => {
+                let (ty, span) = match value {
+                    Some(value) => (self.check_expr(value), value.span),
+                    None => (Type::Unit, stmt.span),
+                };
+                let expected = self.return_type.clone();
+                self.expect_type(&expected, &ty, span);
+            }
+            // The expression runs when the block ends, so nothing narrowed here holds inside it
+            StmtKind::Defer(expr) => {
+                let outer_non_null = std::mem::take(&mut self.non_null);
+                let outer_deferring = std::mem::replace(&mut self.deferring, true);
+                self.check_expr(expr);
+                self.deferring = outer_deferring;
+                self.non_null = outer_non_null;
+                let unstable = &self.unstable;
+                self.non_null.retain(|id| !unstable.contains(id));
+            }
+            StmtKind::Break | StmtKind::Continue => (),
+        }
+    }
+
+    /// Checks an operand of `==`, `!=` or `??`, which work on optionals: a variable keeps its
+    /// optional type there even where it is narrowed, so that comparing it with `null` or falling
+    /// back to a default stays valid
+    fn check_null_aware_operand(&mut self, operand: &mut Expr) -> Type {
+        if !matches!(operand.kind, ExprKind::Identifier(_)) {
+            return self.check_expr(operand);
+        }
+        let non_null = std::mem::take(&mut self.non_null);
+        let ty = self.check_expr(operand);
+        self.non_null = non_null;
+        ty
+    }
+
+    fn check_assignment_target(&mut self, target: &mut Expr) -> Type {
+        let ExprKind::Identifier(name) = &target.kind else {
+            self.error(TypeError::InvalidAssignmentTarget(target.span));
+            return Type::Error;
+        };
+        match self.use_variable(name) {
+            Some(variable) => {
+                // Assignments check against the declared type, so a narrowed `T?` still accepts `null`
+                if !variable.mutable {
+                    self.error(TypeError::AssignToConstant(name.clone(), target.span));
+                }
+                if variable.level < self.captures.len() || self.deferring {
+                    self.unstable.insert(variable.id);
+                }
+                target.variable = Some(variable.id);
+                target.ty = variable.ty.clone();
+                variable.ty
+            }
+            None => {
+                self.error(TypeError::UndefinedVariable(name.clone(), target.span));
+                Type::Error
+            }
+        }
+    }
+
+    fn check_expr(&mut self, expr: &mut Expr) -> Type {
+        let span = expr.span;
+        if let ExprKind::Qualified { module, name } = &expr.kind {
+            // Once resolved, a qualified name is just a reference to a function by its linked name
+            match self.resolve_qualified(module, name, span) {
+                Some(linked) => expr.kind = ExprKind::Identifier(linked),
+                None => {
+                    expr.ty = Type::Error;
+                    return Type::Error;
+                }
+            }
+        }
+        if let ExprKind::Unary {
+            op: UnaryOp::Negate,
+            operand,
+        } = &expr.kind
+        {
+            // A negated literal is one literal, so the range check allows `-2147483648`
+            if let ExprKind::Literal(Literal::Int(value)) = operand.kind {
+                expr.kind = ExprKind::Literal(Literal::Int(-value));
+            }
+        }
+        let ty = match &mut expr.kind {
+            ExprKind::Literal(literal) => match literal {
+                Literal::Int(value) => {
+                    if i32::try_from(*value).is_err() {
+                        self.error(TypeError::IntegerLiteralOutOfRange(span));
+                    }
+                    Type::Int
+                }
+                Literal::Float(_) => Type::Float,
+                Literal::Bool(_) => Type::Bool,
+                Literal::Char(_) => Type::Char,
+                Literal::String(_) => Type::String,
+                Literal::Null => Type::Null,
+            },
+            ExprKind::Identifier(name) => match self.use_variable(name) {
+                Some(variable) => {
+                    expr.variable = Some(variable.id);
+                    match variable.ty {
+                        Type::Optional(inner) if self.non_null.contains(&variable.id) => *inner,
+                        ty => ty,
+                    }
+                }
+                None if self.functions.contains_key(&self.linked_name(name)) => {
+                    *name = self.linked_name(name);
+                    let signature = &self.functions[name.as_str()];
+                    Type::Function(
+                        signature.params.clone(),
+                        Box::new(signature.return_type.clone()),
+                    )
+                }
+                None if BUILTINS.contains(&name.as_str()) => {
+                    self.error(TypeError::FunctionAsValue(name.clone(), span));
+                    Type::Error
+                }
+                None => {
+                    self.error(TypeError::UndefinedVariable(name.clone(), span));
+                    Type::Error
+                }
+            },
+            ExprKind::Unary { op, operand } => {
+                let operand_ty = self.check_expr(operand);
+                let operand_ty = self.reject_optional(operand_ty, operand.span);
+                let valid = match op {
+                    UnaryOp::Negate => operand_ty.is_numeric(),
+                    UnaryOp::Not => operand_ty == Type::Bool,
+                    UnaryOp::BitNot => operand_ty == Type::Int,
+                };
+                if valid || operand_ty == Type::Error {
+                    operand_ty
+                } else {
+                    self.error(TypeError::InvalidUnaryOperand {
+                        op: *op,
+                        operand: operand_ty,
+                        span,
+                    });
+                    Type::Error
+                }
+            }
+            ExprKind::Binary { left, op, right } => {
+                let null_aware = matches!(
+                    op,
+                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Coalesce
+                );
+                let left_ty = if null_aware {
+                    self.check_null_aware_operand(left)
+                } else {
+                    self.check_expr(left)
+                };
+                // The right operand of a short-circuiting operator only runs when the left one
+                // has a particular value, which may prove some optionals are not `null`
+                let entry = self.non_null.clone();
+                match op {
+                    BinaryOp::And => self.non_null.extend(self.narrowing(left).0),
+                    BinaryOp::Or => self.non_null.extend(self.narrowing(left).1),
+                    _ => (),
+                }
+                let right_ty = if null_aware {
+                    self.check_null_aware_operand(right)
+                } else {
+                    self.check_expr(right)
+                };
+                self.non_null = entry;
+
+                match op {
+                    BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Coalesce => {
+                        self.binary_result(*op, &left_ty, &right_ty, span)
+                    }
+                    _ => {
+                        let left_ty = self.reject_optional(left_ty, left.span);
+                        let right_ty = self.reject_optional(right_ty, right.span);
+                        self.binary_result(*op, &left_ty, &right_ty, span)
+                    }
+                }
+            }
+            ExprKind::Call { callee, args } => self.check_call(callee, args, span),
+            ExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let cond_ty = self.check_expr(cond);
+                self.expect_type(&Type::Bool, &cond_ty, cond.span);
+                let (when_true, when_false) = self.narrowing(cond);
+                let entry = self.non_null.clone();
+
+                self.non_null.extend(when_true);
+                let then_ty = self.check_block(then_branch);
+                let then_non_null = std::mem::replace(&mut self.non_null, entry);
+
+                self.non_null.extend(when_false);
+                let else_ty = match else_branch {
+                    Some(else_branch) => self.check_expr(else_branch),
+                    None => Type::Unit,
+                };
+
+                // Whatever holds at the end of a branch holds after the `if`, unless that branch
+                // never finishes, in which case only the other branch matters
+                match (&then_ty, &else_ty) {
+                    (Type::Never, _) => (),
+                    (_, Type::Never) => self.non_null = then_non_null,
+                    _ => self.non_null.retain(|id| then_non_null.contains(id)),
+                }
+
+                match else_branch {
+                    Some(else_branch) => match then_ty.join(&else_ty) {
+                        Some(ty) => ty,
+                        None => {
+                            self.expect_type(&then_ty, &else_ty, else_branch.span);
+                            Type::Error
+                        }
+                    },
+                    None => {
+                        let tail_span = then_branch
+                            .tail
+                            .as_ref()
+                            .map_or(then_branch.span, |t| t.span);
+                        if then_ty != Type::Never {
+                            self.expect_type(&Type::Unit, &then_ty, tail_span);
+                        }
+                        Type::Unit
+                    }
+                }
+            }
+            ExprKind::Block(block) => self.check_block(block),
+            ExprKind::Try(operand) => {
+                let operand_ty = self.check_expr(operand);
+                match operand_ty {
+                    Type::Result(ok, err) => {
+                        match self.return_type.clone() {
+                            Type::Result(_, expected_err) => {
+                                if !err.is_assignable_to(&expected_err) {
+                                    self.error(TypeError::IncompatibleErrorType {
+                                        expected: *expected_err,
+                                        found: *err,
+                                        span,
+                                    });
+                                }
+                            }
+                            Type::Error => (),
+                            other => self.error(TypeError::PropagationOutsideResult(other, span)),
+                        }
+                        *ok
+                    }
+                    Type::Error => Type::Error,
+                    other => {
+                        self.error(TypeError::NotAResult(other, operand.span));
+                        Type::Error
+                    }
+                }
+            }
+            ExprKind::Range { start, end, .. } => {
+                for bound in [start, end] {
+                    let bound_ty = self.check_expr(bound);
+                    self.expect_type(&Type::Int, &bound_ty, bound.span);
+                }
+                Type::Range
+            }
+            ExprKind::Lambda(lambda) => self.check_lambda(lambda),
+            ExprKind::Interpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(expr) = part {
+                        let ty = self.check_expr(expr);
+                        self.check_conversion(Type::String, ty, expr.span);
+                    }
+                }
+                Type::String
+            }
+            ExprKind::Qualified { .. } => unreachable!("qualified names are resolved above"),
+        };
+        expr.ty = ty.clone();
+        ty
+    }
+
+    fn check_call(&mut self, callee: &mut Expr, args: &mut [Expr], span: Span) -> Type {
+        let arg_types: Vec<Type> = args.iter_mut().map(|arg| self.check_expr(arg)).collect();
+        if let ExprKind::Identifier(name) = &callee.kind {
+            if self.lookup(name).is_none() {
+                if BUILTINS.contains(&name.as_str()) {
+                    let name = name.clone();
+                    return self.check_builtin_call(&name, args, &arg_types, span);
+                }
+                if !self.functions.contains_key(&self.linked_name(name)) {
+                    self.error(TypeError::UndefinedFunction(name.clone(), callee.span));
+                    return Type::Error;
+                }
+            }
+        }
+
+        // Top level functions and closures are both values of a function type
+        let (params, return_type) = match self.check_expr(callee) {
+            Type::Function(params, return_type) => (params, *return_type),
+            Type::Error => return Type::Error,
+            _ => {
+                self.error(TypeError::NotCallable(callee.span));
+                return Type::Error;
+            }
+        };
+        if params.len() != args.len() {
+            let name = match &callee.kind {
+                ExprKind::Identifier(name) => name.clone(),
+                _ => callee.ty.to_string(),
+            };
+            self.error(TypeError::ArgumentCount {
+                name,
+                expected: params.len(),
+                found: args.len(),
+                span,
+            });
+        } else {
+            for ((param, arg_ty), arg) in params.iter().zip(&arg_types).zip(args.iter()) {
+                self.expect_type(param, arg_ty, arg.span);
+            }
+        }
+        return_type
+    }
+
+    /// Checks an anonymous function's body as if it were a function of its own, collecting the
+    /// variables it captures from the functions around it
+    fn check_lambda(&mut self, lambda: &mut Lambda) -> Type {
+        let params: Vec<Type> = lambda
+            .params
+            .iter()
+            .map(|param| self.resolve_type(&param.ty))
+            .collect();
+        let return_type = match &lambda.return_type {
+            Some(ty) => self.resolve_type(ty),
+            None => Type::Unit,
+        };
+
+        // The body may run at any later point, so nothing narrowed out here holds inside it
+        let outer_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+        let outer_non_null = std::mem::take(&mut self.non_null);
+        self.captures.push(Vec::new());
+        self.scopes.push(HashMap::new());
+        for (param, ty) in lambda.params.iter_mut().zip(&params) {
+            self.declare(&mut param.name, ty.clone(), true);
+        }
+        self.check_block(&mut lambda.body);
+        self.scopes.pop();
+        lambda.captures = self.captures.pop().unwrap();
+        self.return_type = outer_return_type;
+        self.non_null = outer_non_null;
+        let unstable = &self.unstable;
+        self.non_null.retain(|id| !unstable.contains(id));
+
+        Type::Function(params, Box::new(return_type))
+    }
+
+    fn check_builtin_call(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        arg_types: &[Type],
+        span: Span,
+    ) -> Type {
+        match name {
+            "print" | "println" => return self.check_print_call(name, args, arg_types, span),
+            // The line read from standard input, or null at its end
+            "input" if args.is_empty() => return Type::Optional(Box::new(Type::String)),
+            _ => {}
+        }
+        // `Ok` also accepts no argument at all, wrapping `()`
+        let (min, max) = match name {
+            "input" => (0, 0),
+            "Ok" => (0, 1),
+            "min" | "max" | "pow" | "open" | "write" => (2, 2),
+            _ => (1, 1),
+        };
+        if args.len() < min || args.len() > max {
+            self.error(TypeError::ArgumentCount {
+                name: name.to_string(),
+                expected: max,
+                found: args.len(),
+                span,
+            });
+            return Type::Error;
+        }
+        let arg_ty = arg_types.first().cloned().unwrap_or(Type::Unit);
+        let arg_span = args.first().map_or(span, |arg| arg.span);
+
+        match name {
+            "panic" => {
+                self.expect_type(&Type::String, &arg_ty, arg_span);
+                Type::Never
+            }
+            "Ok" => Type::Result(Box::new(arg_ty), Box::new(Type::Never)),
+            "Err" => Type::Result(Box::new(Type::Never), Box::new(arg_ty)),
+            "int" => self.check_conversion(Type::Int, arg_ty, arg_span),
+            "float" => self.check_conversion(Type::Float, arg_ty, arg_span),
+            "char" => self.check_conversion(Type::Char, arg_ty, arg_span),
+            "string" => self.check_conversion(Type::String, arg_ty, arg_span),
+            "min" | "max" | "abs" | "pow" => self.check_numbers(args, arg_types),
+            "sqrt" | "ceil" | "floor" | "sin" | "cos" | "log" | "ln" | "pow_e" => {
+                self.expect_type(&Type::Float, &arg_ty, arg_span);
+                Type::Float
+            }
+            "open" => {
+                for (arg, arg_ty) in args.iter().zip(arg_types) {
+                    self.expect_type(&Type::String, arg_ty, arg.span);
+                }
+                Type::Result(Box::new(Type::File), Box::new(Type::String))
+            }
+            "close" | "read" | "read_line" | "write" => {
+                self.expect_type(&Type::File, &arg_ty, arg_span);
+                let ok = match name {
+                    "close" => return Type::Unit,
+                    "read" => Type::String,
+                    "read_line" => Type::Optional(Box::new(Type::String)),
+                    _ => {
+                        self.expect_type(&Type::String, &arg_types[1], args[1].span);
+                        Type::Unit
+                    }
+                };
+                Type::Result(Box::new(ok), Box::new(Type::String))
+            }
+            _ => {
+                let Type::Result(ok, err) = arg_ty else {
+                    if arg_ty != Type::Error {
+                        self.error(TypeError::NotAResult(arg_ty, arg_span));
+                    }
+                    return Type::Error;
+                };
+                match name {
+                    "unwrap" => *ok,
+                    "unwrap_err" => *err,
+                    _ => Type::Bool,
+                }
+            }
+        }
+    }
+
+    /// Checks a call to `print()` or `println()`: either a single value, or a format string literal
+    /// followed by one value per placeholder. `println()` alone prints an empty line.
+    fn check_print_call(
+        &mut self,
+        name: &str,
+        args: &[Expr],
+        arg_types: &[Type],
+        span: Span,
+    ) -> Type {
+        let format = match format::of_call(name, args) {
+            Some(format) => format,
+            None if name == "println" && args.is_empty() => return Type::Unit,
+            None => {
+                if args.len() != 1 {
+                    self.error(TypeError::ArgumentCount {
+                        name: name.to_string(),
+                        expected: 1,
+                        found: args.len(),
+                        span,
+                    });
+                } else {
+                    self.expect_printable(&arg_types[0], args[0].span);
+                }
+                return Type::Unit;
+            }
+        };
+        let pieces = match format::parse(format) {
+            Ok(pieces) => pieces,
+            Err(error) => {
+                self.error(TypeError::InvalidFormatString(error, args[0].span));
+                return Type::Unit;
+            }
+        };
+        let specs: Vec<_> = pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Placeholder(spec) => Some(spec),
+                Piece::Text(_) => None,
+            })
+            .collect();
+        if specs.len() != args.len() - 1 {
+            self.error(TypeError::FormatArgumentCount {
+                placeholders: specs.len(),
+                found: args.len() - 1,
+                span,
+            });
+            return Type::Unit;
+        }
+        for ((spec, arg), ty) in specs.iter().zip(&args[1..]).zip(&arg_types[1..]) {
+            self.expect_printable(ty, arg.span);
+            if spec.precision.is_some() && !matches!(ty, Type::Float | Type::Error) {
+                self.error(TypeError::PrecisionNotFloat(ty.clone(), arg.span));
+            }
+        }
+        Type::Unit
+    }
+
+    /// Checks converting a value of type `from` to the type `to`, which gives a `Result` when it
+    /// parses a number out of a string. Anything printable converts to a string.
+    fn check_conversion(&mut self, to: Type, from: Type, span: Span) -> Type {
+        let converts = match (&to, &from) {
+            (_, Type::Error | Type::Never) => true,
+            (Type::Int | Type::Float, Type::String) => {
+                return Type::Result(Box::new(to), Box::new(Type::String));
+            }
+            (Type::Int, Type::Int | Type::Float | Type::Char) => true,
+            (Type::Float, Type::Int | Type::Float) => true,
+            (Type::Char, Type::Int | Type::Char) => true,
+            (Type::String, from) => from.is_printable(),
+            _ => false,
+        };
+        if !converts {
+            self.error(TypeError::NoConversion {
+                from,
+                to: to.clone(),
+                span,
+            });
+        }
+        to
+    }
+
+    /// Checks the arguments of `min()`, `max()`, `abs()` or `pow()`, which are all integers or all
+    /// floats, and returns their type
+    fn check_numbers(&mut self, args: &[Expr], arg_types: &[Type]) -> Type {
+        let ty = match &arg_types[0] {
+            Type::Int | Type::Float => arg_types[0].clone(),
+            Type::Error => return Type::Error,
+            other => {
+                self.error(TypeError::NotANumber(other.clone(), args[0].span));
+                return Type::Error;
+            }
+        };
+        for (arg, arg_ty) in args.iter().zip(arg_types).skip(1) {
+            self.expect_type(&ty, arg_ty, arg.span);
+        }
+        ty
+    }
+
+    fn expect_printable(&mut self, ty: &Type, span: Span) {
+        if !ty.is_printable() {
+            self.error(TypeError::NotPrintable(ty.clone(), span));
+        }
+    }
+
+    /// Returns the type produced by `left op right`, reporting an error if the operands are invalid
+    fn binary_result(&mut self, op: BinaryOp, left: &Type, right: &Type, span: Span) -> Type {
+        if *left == Type::Error || *right == Type::Error {
+            return Type::Error;
+        }
+        let comparable_with_null = |a: &Type, b: &Type| match a {
+            Type::Optional(inner) => *b == Type::Null || **inner == *b,
+            _ => false,
+        };
+        let result = match op {
+            BinaryOp::Add
+            | BinaryOp::Subtract
+            | BinaryOp::Multiply
+            | BinaryOp::Divide
+            | BinaryOp::Remainder
+                if left == right && left.is_numeric() =>
+            {
+                Some(left.clone())
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual
+                if left == right
+                    && !matches!(
+                        left,
+                        Type::Unit | Type::Range | Type::Null | Type::Function(..) | Type::File
+                    ) =>
+            {
+                Some(Type::Bool)
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual
+                if comparable_with_null(left, right) || comparable_with_null(right, left) =>
+            {
+                Some(Type::Bool)
+            }
+            BinaryOp::Coalesce => match left {
+                Type::Optional(inner) if right.is_assignable_to(inner) => Some(*inner.clone()),
+                Type::Optional(_) if right.is_assignable_to(left) => Some(left.clone()),
+                // `result ?? default` is the `Ok` value, or `default` for an `Err`
+                Type::Result(ok, _) if right.is_assignable_to(ok) => Some(*ok.clone()),
+                _ => None,
+            },
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual
+                if left == right && matches!(left, Type::Int | Type::Float | Type::Char) =>
+            {
+                Some(Type::Bool)
+            }
+            BinaryOp::And | BinaryOp::Or if *left == Type::Bool && *right == Type::Bool => {
+                Some(Type::Bool)
+            }
+            BinaryOp::BitAnd
+            | BinaryOp::BitOr
+            | BinaryOp::BitXor
+            | BinaryOp::ShiftLeft
+            | BinaryOp::ShiftRight
+                if *left == Type::Int && *right == Type::Int =>
+            {
+                Some(Type::Int)
+            }
+            _ => None,
+        };
+        result.unwrap_or_else(|| {
+            self.error(TypeError::InvalidBinaryOperands {
+                op,
+                left: left.clone(),
+                right: right.clone(),
+                span,
+            });
+            Type::Error
+        })
+    }
+
+    fn expect_type(&mut self, expected: &Type, found: &Type, span: Span) {
+        if let Type::Optional(inner) = found {
+            if !found.is_assignable_to(expected) && inner.is_assignable_to(expected) {
+                self.error(TypeError::PossiblyNull(found.clone(), span));
+                return;
+            }
+        }
+        if !found.is_assignable_to(expected) {
+            self.error(TypeError::Mismatch {
+                expected: expected.clone(),
+                found: found.clone(),
+                span,
+            });
+        }
+    }
+
+    /// Reports an optional operand, which must be unwrapped before an operator can use it
+    fn reject_optional(&mut self, ty: Type, span: Span) -> Type {
+        if ty.is_optional() || ty == Type::Null {
+            self.error(TypeError::PossiblyNull(ty, span));
+            Type::Error
+        } else {
+            ty
+        }
+    }
+
+    /// Returns the optional variables proven non-null when `cond` is true, and when it is false
+    fn narrowing(&self, cond: &Expr) -> Narrowing {
+        match &cond.kind {
+            ExprKind::Binary { left, op, right } => match op {
+                BinaryOp::Equal | BinaryOp::NotEqual => {
+                    let checked = match (&left.kind, &right.kind) {
+                        (_, ExprKind::Literal(Literal::Null)) => self.optional_variable(left),
+                        (ExprKind::Literal(Literal::Null), _) => self.optional_variable(right),
+                        _ => None,
+                    };
+                    let proven = checked.into_iter().collect::<HashSet<_>>();
+                    if *op == BinaryOp::NotEqual {
+                        (proven, HashSet::new())
+                    } else {
+                        (HashSet::new(), proven)
+                    }
+                }
+                BinaryOp::And => {
+                    let (left_true, left_false) = self.narrowing(left);
+                    let (right_true, right_false) = self.narrowing(right);
+                    let when_false = left_false.intersection(&right_false).copied().collect();
+                    (&left_true | &right_true, when_false)
+                }
+                BinaryOp::Or => {
+                    let (left_true, left_false) = self.narrowing(left);
+                    let (right_true, right_false) = self.narrowing(right);
+                    let when_true = left_true.intersection(&right_true).copied().collect();
+                    (when_true, &left_false | &right_false)
+                }
+                _ => (HashSet::new(), HashSet::new()),
+            },
+            ExprKind::Unary {
+                op: UnaryOp::Not,
+                operand,
+            } => {
+                let (when_true, when_false) = self.narrowing(operand);
+                (when_false, when_true)
+            }
+            _ => (HashSet::new(), HashSet::new()),
+        }
+    }
+
+    /// Returns the id of the variable that `expr` names, if it was declared with an optional type
+    fn optional_variable(&self, expr: &Expr) -> Option<usize> {
+        let ExprKind::Identifier(name) = &expr.kind else {
+            return None;
+        };
+        self.lookup(name)
+            .filter(|variable| variable.ty.is_optional() && !self.unstable.contains(&variable.id))
+            .map(|variable| variable.id)
+    }
+
+    fn variable_id(&self, expr: &Expr) -> Option<usize> {
+        let ExprKind::Identifier(name) = &expr.kind else {
+            return None;
+        };
+        self.lookup(name).map(|variable| variable.id)
+    }
+
+    /// Assigning a definitely non-null value narrows the variable, and anything else widens it again
+    fn narrow_on_assignment(&mut self, id: usize, value_ty: &Type) {
+        if value_ty.is_optional()
+            || matches!(value_ty, Type::Null | Type::Error)
+            || self.unstable.contains(&id)
+        {
+            self.non_null.remove(&id);
+        } else {
+            self.non_null.insert(id);
+        }
+    }
+
+    /// A loop body may run after itself, so whatever it assigns cannot stay narrowed on entry
+    fn forget_assigned_in(&mut self, body: &Block) {
+        let mut assigned = Vec::new();
+        collect_assigned_block(body, &mut assigned);
+        for name in assigned {
+            if let Some(id) = self.lookup(name).map(|variable| variable.id) {
+                self.non_null.remove(&id);
+            }
+        }
+    }
+
+    /// Returns the linked name of `module::name`, if the module is imported and the function is public
+    fn resolve_qualified(&mut self, module: &str, name: &str, span: Span) -> Option<String> {
+        let Some(interface) = self.imports.get(module) else {
+            self.error(TypeError::UnknownModule(module.to_string(), span));
+            return None;
+        };
+        let linked = format!("{}::{}", interface.name, name);
+        if !interface.functions.contains_key(name) {
+            let qualified = format!("{}::{}", module, name);
+            self.error(TypeError::UndefinedFunction(qualified, span));
+            return None;
+        }
+        if !interface.public.contains(name) {
+            self.error(TypeError::PrivateFunction {
+                module: module.to_string(),
+                name: name.to_string(),
+                span,
+            });
+            return None;
+        }
+        Some(linked)
+    }
+
+    /// The name a function declared in the module being checked has once all modules are merged.
+    /// Names that are already linked (i.e. resolved qualified names) are returned unchanged.
+    fn linked_name(&self, name: &str) -> String {
+        match &self.module {
+            Some(module) if !name.contains("::") => format!("{}::{}", module, name),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Declares the variable `name`, recording its id in the identifier
+    fn declare(&mut self, name: &mut Ident, ty: Type, mutable: bool) -> VariableId {
+        let id = self.variable_count;
+        self.variable_count += 1;
+        name.variable = Some(id);
+        let variable = Variable {
+            id,
+            ty,
+            mutable,
+            level: self.captures.len(),
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.name.clone(), variable);
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Looks up a variable that is read or assigned here, recording it as a capture of every
+    /// anonymous function between its declaration and this use
+    fn use_variable(&mut self, name: &str) -> Option<Variable> {
+        let variable = self.lookup(name)?;
+        let variable = Variable {
+            ty: variable.ty.clone(),
+            ..*variable
+        };
+        for captures in &mut self.captures[variable.level..] {
+            if !captures
+                .iter()
+                .any(|capture| capture.variable == variable.id)
+            {
+                captures.push(Capture {
+                    name: name.to_string(),
+                    variable: variable.id,
+                    ty: variable.ty.clone(),
+                });
+            }
+        }
+        Some(variable)
+    }
+
+    fn error(&mut self, error: TypeError) {
+        self.errors.push(error);
+    }
+}
+
+/// Collects the names of every variable assigned to within `block`, including nested blocks
+fn collect_assigned_block<'a>(block: &'a Block, assigned: &mut Vec<&'a str>) {
+    for stmt in &block.stmts {
+        match &stmt.kind {
+            StmtKind::Var { value, .. } => collect_assigned_expr(value, assigned),
+            StmtKind::Assign { target, value, .. } => {
+                if let ExprKind::Identifier(name) = &target.kind {
+                    assigned.push(name);
+                }
+                collect_assigned_expr(value, assigned);
+            }
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) | StmtKind::Defer(expr) => {
+                collect_assigned_expr(expr, assigned)
+            }
+            StmtKind::While { cond, body } => {
+                collect_assigned_expr(cond, assigned);
+                collect_assigned_block(body, assigned);
+            }
+            StmtKind::For { iterable, body, .. } => {
+                collect_assigned_expr(iterable, assigned);
+                collect_assigned_block(body, assigned);
+            }
+            StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue => (),
+        }
+    }
+    if let Some(tail) = &block.tail {
+        collect_assigned_expr(tail, assigned);
+    }
+}
+
+fn collect_assigned_expr<'a>(expr: &'a Expr, assigned: &mut Vec<&'a str>) {
+    match &expr.kind {
+        ExprKind::Literal(_) | ExprKind::Identifier(_) | ExprKind::Qualified { .. } => (),
+        ExprKind::Unary { operand, .. } | ExprKind::Try(operand) => {
+            collect_assigned_expr(operand, assigned)
+        }
+        ExprKind::Binary { left, right, .. }
+        | ExprKind::Range {
+            start: left,
+            end: right,
+            ..
+        } => {
+            collect_assigned_expr(left, assigned);
+            collect_assigned_expr(right, assigned);
+        }
+        ExprKind::Call { callee, args } => {
+            collect_assigned_expr(callee, assigned);
+            for arg in args {
+                collect_assigned_expr(arg, assigned);
+            }
+        }
+        ExprKind::Interpolation(parts) => {
+            for part in parts {
+                if let StringPart::Expr(expr) = part {
+                    collect_assigned_expr(expr, assigned);
+                }
+            }
+        }
+        ExprKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            collect_assigned_expr(cond, assigned);
+            collect_assigned_block(then_branch, assigned);
+            if let Some(else_branch) = else_branch {
+                collect_assigned_expr(else_branch, assigned);
+            }
+        }
+        ExprKind::Block(block) => collect_assigned_block(block, assigned),
+        ExprKind::Lambda(lambda) => collect_assigned_block(&lambda.body, assigned),
+    }
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end::parser;
+
+    fn check_source(source: &str) -> Result<Program, Vec<TypeError>> {
+        let mut program = parser::parse(source).unwrap();
+        check(&mut program).map(|_| program)
+    }
+
+    fn errors(source: &str) -> Vec<TypeError> {
+        check_source(source).unwrap_err()
+    }
+
+    #[test]
+    fn test_valid_program() {
+        let source = r#"
+            func even(x: Int) -> Bool {
+                return if x % 2 == 0 { true } else { false };
+            }
+
+            func main() {
+                var x: Int = 10;
+                var result: Char = if x > 0 { 'y' } else { 'n' };
+                for i in 0..x {
+                    x += i;
+                }
+                while even(x) and x < 100 {
+                    x = x * 3;
+                }
+                println(result);
+                println();
+            }
+        "#;
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_expressions_are_annotated() {
+        let program = check_source("func main() { var x = 1.5 * 2.0; }").unwrap();
+        let Item::Function(main) = &program.items[0];
+        let StmtKind::Var { value, .. } = &main.body.stmts[0].kind else {
+            panic!("expected var declaration");
+        };
+        assert_eq!(value.ty, Type::Float);
+    }
+
+    #[test]
+    fn test_missing_main() {
+        assert_eq!(errors("func f() {}"), vec![TypeError::MissingMain]);
+    }
+
+    #[test]
+    fn test_invalid_main_signature() {
+        assert_eq!(
+            errors("func main() -> Int { return 0; }"),
+            vec![TypeError::InvalidMainSignature(Span::new(5, 9))]
+        );
+    }
+
+    #[test]
+    fn test_mismatched_declaration() {
+        assert_eq!(
+            errors("func main() { var x: Int = 1.0; }"),
+            vec![TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Float,
+                span: Span::new(27, 30),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_no_implicit_numeric_conversion() {
+        assert!(matches!(
+            errors("func main() { var x = 1 + 2.0; }")[..],
+            [TypeError::InvalidBinaryOperands {
+                op: BinaryOp::Add,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_errors_do_not_cascade() {
+        assert_eq!(
+            errors("func main() { var x = y + 1; var z: Int = x; }"),
+            vec![TypeError::UndefinedVariable(
+                "y".to_string(),
+                Span::new(22, 23)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_assign_to_constant() {
+        assert_eq!(
+            errors("func main() { const x = 1; x = 2; }"),
+            vec![TypeError::AssignToConstant(
+                "x".to_string(),
+                Span::new(27, 28)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_wrong_return_type() {
+        assert_eq!(
+            errors("func f() -> Bool { return 1; } func main() {}"),
+            vec![TypeError::Mismatch {
+                expected: Type::Bool,
+                found: Type::Int,
+                span: Span::new(26, 27),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_argument_count_and_types() {
+        let errs = errors("func f(a: Int) {} func main() { f(); f('c'); }");
+        assert!(matches!(
+            errs[0],
+            TypeError::ArgumentCount {
+                expected: 1,
+                found: 0,
+                ..
+            }
+        ));
+        assert!(matches!(
+            errs[1],
+            TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Char,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_if_branches_must_agree() {
+        assert!(matches!(
+            errors("func main() { var x = if true { 1 } else { 'a' }; }")[..],
+            [TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Char,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_diverging_branch() {
+        let source = "func f(c: Bool) -> Int { var x = if c { 1 } else { return 0; }; return x; } func main() {}";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_scoping() {
+        assert!(matches!(
+            errors("func main() { if true { var x = 1; } var y = x; }")[..],
+            [TypeError::UndefinedVariable(..)]
+        ));
+        assert!(matches!(
+            errors("func main() { var x = 1; var x = 2; }")[..],
+            [TypeError::DuplicateVariable(..)]
+        ));
+        assert!(check_source("func main() { var x = 1; { var x = 'c'; } }").is_ok());
+    }
+
+    #[test]
+    fn test_integer_literal_out_of_range() {
+        assert_eq!(
+            errors("func main() { var x = 2147483648; }"),
+            vec![TypeError::IntegerLiteralOutOfRange(Span::new(22, 32))]
+        );
+        assert!(check_source("func main() { var x = -2147483648; }").is_ok());
+        assert_eq!(
+            errors("func main() { var x = -2147483649; }"),
+            vec![TypeError::IntegerLiteralOutOfRange(Span::new(22, 33))]
+        );
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        assert_eq!(
+            errors("func f(x: Foo) {} func main() {}"),
+            vec![TypeError::UnknownType("Foo".to_string(), Span::new(10, 13))]
+        );
+    }
+
+    #[test]
+    fn test_for_requires_range() {
+        assert!(matches!(
+            errors("func main() { for i in 5 {} }")[..],
+            [TypeError::NotIterable(Type::Int, _)]
+        ));
+    }
+
+    #[test]
+    fn test_for_goes_through_the_characters_of_a_string() {
+        assert!(check_source("func main() { for c in \"abc\" { var x: Char = c; } }").is_ok());
+        assert!(matches!(
+            errors("func main() { for c in \"abc\" { var x: String = c; } }")[..],
+            [TypeError::Mismatch { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_null_only_fits_optionals() {
+        assert!(check_source("func main() { var x: Int? = null; var y: Int? = 5; }").is_ok());
+        assert!(matches!(
+            errors("func main() { var x: Int = null; }")[..],
+            [TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Null,
+                ..
+            }]
+        ));
+        assert_eq!(
+            errors("func main() { var x = null; }"),
+            vec![TypeError::UninferableNull(Span::new(22, 26))]
+        );
+    }
+
+    #[test]
+    fn test_possibly_null_use() {
+        let source = "func f(x: Int?) -> Int { return x + 1; } func main() {}";
+        assert_eq!(
+            errors(source),
+            vec![TypeError::PossiblyNull(
+                Type::Optional(Box::new(Type::Int)),
+                Span::new(32, 33)
+            )]
+        );
+        let source = "func f(x: Int?) -> Int { return x; } func main() {}";
+        assert!(matches!(errors(source)[..], [TypeError::PossiblyNull(..)]));
+    }
+
+    #[test]
+    fn test_narrowing_in_branches() {
+        let source = r#"
+            func f(x: Int?) -> Int {
+                if x != null {
+                    return x + 1;
+                } else {
+                    return 0;
+                }
+            }
+            func g(x: Int?) -> Int {
+                if x == null {
+                    return 0;
+                } else {
+                    return x;
+                }
+            }
+            func h(x: Int?, y: Int?) -> Bool {
+                return x != null and y != null and x < y;
+            }
+            func main() {}
+        "#;
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_narrowing_after_early_return() {
+        let source =
+            "func f(x: Int?) -> Int { if x == null { return 0; } return x * 2; } func main() {}";
+        assert!(check_source(source).is_ok());
+        let source =
+            "func f(x: Int?) -> Int { if x == null { println(0); } return x * 2; } func main() {}";
+        assert!(matches!(errors(source)[..], [TypeError::PossiblyNull(..)]));
+    }
+
+    #[test]
+    fn test_narrowed_variables_still_compare_with_null() {
+        let source = "func main() { var o: Int? = 3; if o != null { println(o + 1); } }";
+        assert!(check_source(source).is_ok());
+        let source = "func f(x: Int?) -> Int { if x == null { return 0; } return x ?? 5; } \
+                      func main() {}";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_or_narrows_on_false() {
+        let source = "func f(x: Int?) -> Bool { return x == null or x > 0; } func main() {}";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_assignment_widens_narrowing() {
+        let source = r#"
+            func f(x: Int?) -> Int {
+                var y: Int? = x;
+                if y != null {
+                    y = null;
+                    return y + 1;
+                }
+                return 0;
+            }
+            func main() {}
+        "#;
+        assert!(matches!(errors(source)[..], [TypeError::PossiblyNull(..)]));
+        let source =
+            "func f(x: Int?) -> Int { var y: Int? = null; y = 3; return y; } func main() {}";
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_loop_assignments_clear_narrowing() {
+        let source = r#"
+            func f(x: Int?) -> Int {
+                var y: Int? = 1;
+                while true {
+                    println(y + 1);
+                    y = x;
+                }
+            }
+            func main() {}
+        "#;
+        assert!(matches!(errors(source)[..], [TypeError::PossiblyNull(..)]));
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let program = check_source("func f(x: Int?) -> Int { return x ?? 0; } func main() {}");
+        assert!(program.is_ok());
+        let source = "func f(x: Int?, y: Int?) -> Int? { return x ?? y; } func main() {}";
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors("func main() { var x = 1 ?? 2; }")[..],
+            [TypeError::InvalidBinaryOperands {
+                op: BinaryOp::Coalesce,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_if_expression_joins_null() {
+        let program = check_source("func main() { var x = if true { 1 } else { null }; }").unwrap();
+        let Item::Function(main) = &program.items[0];
+        let StmtKind::Var { value, .. } = &main.body.stmts[0].kind else {
+            panic!("expected var declaration");
+        };
+        assert_eq!(value.ty, Type::Optional(Box::new(Type::Int)));
+    }
+
+    #[test]
+    fn test_compare_with_null() {
+        assert!(matches!(
+            errors("func main() { var b = 1 == null; }")[..],
+            [TypeError::InvalidBinaryOperands { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_result_construction() {
+        let source = r#"
+            func parse(s: String) -> Result[Int, String] {
+                if s == "" {
+                    return Err("empty");
+                }
+                return Ok(1);
+            }
+            func main() {
+                var r: Result[Int, String] = parse("1");
+                var both = if true { Ok(1) } else { Err('e') };
+                var nothing: Result[(), String] = Ok();
+                var n: Int = unwrap(r) + unwrap(both) + (r ?? 0);
+                var failed: Bool = is_err(r);
+            }
+        "#;
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_result_type_arguments() {
+        assert!(matches!(
+            errors("func f(r: Result[Int]) {} func main() {}")[..],
+            [TypeError::WrongTypeArguments {
+                expected: 2,
+                found: 1,
+                ..
+            }]
+        ));
+        assert!(matches!(
+            errors("func f(r: Result) {} func main() {}")[..],
+            [TypeError::WrongTypeArguments { found: 0, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_propagation() {
+        let source = r#"
+            func parse(s: String) -> Result[Int, String] { return Ok(1); }
+            func double(s: String) -> Result[Int, String] { return Ok(parse(s)? * 2); }
+            func main() {}
+        "#;
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_propagation_requires_result_function() {
+        let source = r#"
+            func parse(s: String) -> Result[Int, String] { return Ok(1); }
+            func main() { var x = parse("1")?; }
+        "#;
+        assert!(matches!(
+            errors(source)[..],
+            [TypeError::PropagationOutsideResult(Type::Unit, _)]
+        ));
+    }
+
+    #[test]
+    fn test_propagation_error_types_must_match() {
+        let source = r#"
+            func parse(s: String) -> Result[Int, String] { return Ok(1); }
+            func f() -> Result[Int, Int] { return Ok(parse("1")?); }
+            func main() {}
+        "#;
+        assert!(matches!(
+            errors(source)[..],
+            [TypeError::IncompatibleErrorType {
+                expected: Type::Int,
+                found: Type::String,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_propagation_requires_result_operand() {
+        let source = "func f(x: Int) -> Result[Int, String] { return Ok(x?); } func main() {}";
+        assert_eq!(
+            errors(source),
+            vec![TypeError::NotAResult(Type::Int, Span::new(50, 51))]
+        );
+    }
+
+    #[test]
+    fn test_panic_is_never() {
+        let source = r#"func f(c: Bool) -> Int { var x = if c { 1 } else { panic("no") }; return x; } func main() {}"#;
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors("func main() { panic(1); }")[..],
+            [TypeError::Mismatch {
+                expected: Type::String,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_functions_are_values() {
+        let source = r#"
+            func add(a: Int, b: Int) -> Int { return a + b; }
+            func apply(f: func(Int, Int) -> Int, x: Int) -> Int { return f(x, x); }
+            func adder() -> func(Int, Int) -> Int { return add; }
+            func main() { var sum = apply(adder(), 2); }
+        "#;
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors("func f(a: Int) {} func main() { var g: func(Char) = f; }")[..],
+            [TypeError::Mismatch { .. }]
+        ));
+        assert!(matches!(
+            errors("func main() { var p = println; }")[..],
+            [TypeError::FunctionAsValue(..)]
+        ));
+    }
+
+    #[test]
+    fn test_calling_function_values() {
+        let source =
+            "func main() { const f = func(x: Int) -> Bool { return x > 0; }; f(); f('c'); 5(1); }";
+        let errs = errors(source);
+        assert!(matches!(
+            &errs[0],
+            TypeError::ArgumentCount { name, expected: 1, found: 0, .. } if name == "f"
+        ));
+        assert!(matches!(
+            errs[1],
+            TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Char,
+                ..
+            }
+        ));
+        assert!(matches!(errs[2], TypeError::NotCallable(_)));
+    }
+
+    #[test]
+    fn test_lambda_body_is_checked_against_its_return_type() {
+        let source = r#"func main() { const f = func(x: Int) -> Int { return 'c'; }; return; }"#;
+        assert!(matches!(
+            errors(source)[..],
+            [TypeError::Mismatch {
+                expected: Type::Int,
+                found: Type::Char,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_lambda_captures() {
+        let source = r#"
+            func main() {
+                var a = 1;
+                const b = 'c';
+                const f = func(x: Int) -> Int {
+                    const g = func() -> Int { return a + x; };
+                    var local = b;
+                    return g();
+                };
+            }
+        "#;
+        let program = check_source(source).unwrap();
+        let Item::Function(main) = &program.items[0];
+        let StmtKind::Var { value, .. } = &main.body.stmts[2].kind else {
+            panic!("expected a declaration");
+        };
+        assert_eq!(
+            value.ty,
+            Type::Function(vec![Type::Int], Box::new(Type::Int))
+        );
+        let ExprKind::Lambda(f) = &value.kind else {
+            panic!("expected an anonymous function");
+        };
+        let names: Vec<&str> = f.captures.iter().map(|c| c.name.as_str()).collect();
+        // `a` is captured for the nested closure, and `x` is `f`'s own parameter
+        assert_eq!(names, ["a", "b"]);
+        let StmtKind::Var { value, .. } = &f.body.stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        let ExprKind::Lambda(g) = &value.kind else {
+            panic!("expected an anonymous function");
+        };
+        let names: Vec<&str> = g.captures.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a", "x"]);
+    }
+
+    #[test]
+    fn test_closures_do_not_see_outer_narrowing() {
+        let source = "func f(x: Int?) { if x != null { const g = func() -> Int { return x + 1; }; } } func main() {}";
+        assert!(matches!(errors(source)[..], [TypeError::PossiblyNull(..)]));
+    }
+
+    #[test]
+    fn test_variables_assigned_by_closures_are_not_narrowed() {
+        let source = r#"
+            func main() {
+                var x: Int? = 1;
+                const clear = func() { x = null; };
+                if x != null {
+                    clear();
+                    var y = x + 1;
+                }
+            }
+        "#;
+        assert!(matches!(errors(source)[..], [TypeError::PossiblyNull(..)]));
+    }
+
+    fn check_with_import(source: &str, imported: &str) -> (Program, Vec<TypeError>) {
+        let mut library = parser::parse(imported).unwrap();
+        let (interface, errors) = check_module(&mut library, Some("lib::shapes"), HashMap::new());
+        assert!(errors.is_empty());
+        let mut program = parser::parse(source).unwrap();
+        let imports = HashMap::from([("shapes".to_string(), interface)]);
+        let (_, errors) = check_module(&mut program, None, imports);
+        (program, errors)
+    }
+
+    #[test]
+    fn test_qualified_names_are_linked() {
+        let library = "pub func area(w: Int) -> Int { return square(w); } func square(x: Int) -> Int { return x * x; }";
+        let mut program = parser::parse(library).unwrap();
+        let (interface, errors) = check_module(&mut program, Some("lib::shapes"), HashMap::new());
+        assert!(errors.is_empty());
+        assert_eq!(interface.public, HashSet::from(["area".to_string()]));
+        let Item::Function(area) = &program.items[0];
+        assert_eq!(area.name.name, "lib::shapes::area");
+        let StmtKind::Return(Some(value)) = &area.body.stmts[0].kind else {
+            panic!("expected a return");
+        };
+        let ExprKind::Call { callee, .. } = &value.kind else {
+            panic!("expected a call");
+        };
+        assert_eq!(
+            callee.kind,
+            ExprKind::Identifier("lib::shapes::square".to_string())
+        );
+
+        let (program, errors) =
+            check_with_import("func main() { var a = shapes::area(2); }", library);
+        assert!(errors.is_empty());
+        let Item::Function(main) = &program.items[0];
+        assert_eq!(main.name.name, "main");
+        let StmtKind::Var { value, .. } = &main.body.stmts[0].kind else {
+            panic!("expected a declaration");
+        };
+        assert_eq!(value.ty, Type::Int);
+    }
+
+    #[test]
+    fn test_private_and_unknown_functions() {
+        let library = "pub func area(w: Int) -> Int { return w; } func helper() {}";
+        let source = "func main() { shapes::helper(); shapes::volume(); shape::area(1); }";
+        let (_, errors) = check_with_import(source, library);
+        assert_eq!(
+            errors,
+            vec![
+                TypeError::PrivateFunction {
+                    module: "shapes".to_string(),
+                    name: "helper".to_string(),
+                    span: Span::new(14, 28)
+                },
+                TypeError::UndefinedFunction("shapes::volume".to_string(), Span::new(32, 46)),
+                TypeError::UnknownModule("shape".to_string(), Span::new(50, 61)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_the_entry_file_needs_main() {
+        let mut program = parser::parse("func helper() {}").unwrap();
+        let (_, errors) = check_module(&mut program, Some("util"), HashMap::new());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_print_format_strings() {
+        let source =
+            r#"func main() { print("{:>5}|{:.2}|{}", 1, 2.5, "x"); println("{{}}"); println(); }"#;
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors(r#"func main() { println("{:x}", 1); }"#)[..],
+            [TypeError::InvalidFormatString(
+                FormatError::InvalidSpec(_),
+                _
+            )]
+        ));
+        assert!(matches!(
+            errors(r#"func main() { println("{} {}", 1); }"#)[..],
+            [TypeError::FormatArgumentCount {
+                placeholders: 2,
+                found: 1,
+                ..
+            }]
+        ));
+        assert!(matches!(
+            errors(r#"func main() { println("{:.1}", 1); }"#)[..],
+            [TypeError::PrecisionNotFloat(Type::Int, _)]
+        ));
+        assert!(matches!(
+            errors(r#"func main() { println("{}", main); }"#)[..],
+            [TypeError::NotPrintable(Type::Function(..), _)]
+        ));
+    }
+
+    #[test]
+    fn test_a_lone_string_literal_is_not_a_format_string() {
+        let source = r#"func main() { println("a}b"); print("{}\n"); println("{:x"); }"#;
+        assert!(check_source(source).is_ok());
+    }
+
+    #[test]
+    fn test_interpolation_needs_a_string_conversion() {
+        let source = r#"func main() { var s: String = "{1} {2.5} {null} {Ok(1)} {"{'c'}"}"; }"#;
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors(r#"func f() {} func main() { var s = "{f()} {main}"; }"#)[..],
+            [
+                TypeError::NoConversion {
+                    from: Type::Unit,
+                    ..
+                },
+                TypeError::NoConversion {
+                    from: Type::Function(..),
+                    ..
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let source = r#"
+            func main() {
+                var i: Int = int(1.5) + int('a') + int(1);
+                var f: Float = float(1) + float(2.5);
+                var c: Char = char(97);
+                var s: String = string(Ok(null));
+                var parsed: Result[Int, String] = int("12");
+                var real: Result[Float, String] = float("1.5");
+            }
+        "#;
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors(r#"func main() { int(true); float('a'); char(1.0); string(main); int(); }"#)[..],
+            [
+                TypeError::NoConversion {
+                    from: Type::Bool,
+                    to: Type::Int,
+                    ..
+                },
+                TypeError::NoConversion {
+                    from: Type::Char,
+                    to: Type::Float,
+                    ..
+                },
+                TypeError::NoConversion {
+                    from: Type::Float,
+                    to: Type::Char,
+                    ..
+                },
+                TypeError::NoConversion {
+                    to: Type::String,
+                    ..
+                },
+                TypeError::ArgumentCount { .. }
+            ]
+        ));
+        assert!(matches!(
+            errors("func int(x: Float) -> Int { return 0; } func main() {}")[..],
+            [TypeError::DuplicateFunction(..)]
+        ));
+    }
+
+    #[test]
+    fn test_math_builtins() {
+        let source = r#"
+            func main() {
+                var i: Int = min(1, 2) + max(3, 4) + abs(-5) + pow(2, 10);
+                var f: Float = min(1.5, 2.0) + abs(-0.5) + pow(2.0, 0.5) + sqrt(2.0);
+                var g: Float = ceil(f) + floor(f) + sin(f) + cos(f) + log(f) + ln(f) + pow_e(f);
+            }
+        "#;
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors(r#"func main() { min(1, 2.0); abs("1"); sqrt(4); pow(2); }"#)[..],
+            [
+                TypeError::Mismatch {
+                    expected: Type::Int,
+                    found: Type::Float,
+                    ..
+                },
+                TypeError::NotANumber(Type::String, _),
+                TypeError::Mismatch {
+                    expected: Type::Float,
+                    found: Type::Int,
+                    ..
+                },
+                TypeError::ArgumentCount { expected: 2, .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_input_returns_an_optional_string() {
+        assert!(check_source(r#"func main() { var s: String? = input(); }"#).is_ok());
+        assert!(matches!(
+            errors(r#"func main() { input("> "); }"#)[..],
+            [TypeError::ArgumentCount { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_file_builtins() {
+        let source = r#"
+            func main() {
+                var file: Result[File, String] = open("in.txt", "r");
+                var f = unwrap(file);
+                var all: Result[String, String] = read(f);
+                var line: Result[String?, String] = read_line(f);
+                var written: Result[(), String] = write(f, "text");
+                for line in f { var s: String = line; }
+                close(f);
+            }
+        "#;
+        assert!(check_source(source).is_ok());
+        assert!(matches!(
+            errors(
+                r#"func main() { var f = unwrap(open("a", "r")); println(f == f); println(f); }"#
+            )[..],
+            [
+                TypeError::InvalidBinaryOperands { .. },
+                TypeError::NotPrintable(Type::File, _)
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_variables_assigned_by_deferred_expressions_are_not_narrowed() {
+        let source = r#"
+            func main() {
+                var x = input();
+                if x != null {
+                    defer { x = null; }
+                    var y = x + "!";
+                }
+            }
+        "#;
+        assert!(matches!(errors(source)[..], [TypeError::PossiblyNull(..)]));
+    }
+}
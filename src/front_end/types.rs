@@ -0,0 +1,133 @@
+//! Semantic types assigned to expressions by the type checker
+use std::fmt;
+
+/// A crawfish type.
+/// `Optional` values may also be `null`, which is the only value of the `Null` type.
+/// `Result` is the built-in enum of `Ok(T)` and `Err(E)`.
+/// `Function` holds the parameter and return types of a function or closure value.
+/// `File` is a file opened with `open()`.
+/// `Never` is the type of expressions that never produce a value (e.g. a block ending in `return`),
+/// and `Error` is assigned to ill-typed expressions so that one mistake does not cascade into many.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    Char,
+    String,
+    Unit,
+    Range,
+    File,
+    Optional(Box<Type>),
+    Null,
+    Result(Box<Type>, Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    Never,
+    #[default]
+    Error,
+}
+
+impl Type {
+    /// Returns the type named by a type annotation, if it is a built-in type
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "Int" => Some(Type::Int),
+            "Float" => Some(Type::Float),
+            "Bool" => Some(Type::Bool),
+            "Char" => Some(Type::Char),
+            "String" => Some(Type::String),
+            "File" => Some(Type::File),
+            _ => None,
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+
+    /// Whether values of the type have a text form, which printing and string interpolation use
+    pub fn is_printable(&self) -> bool {
+        !matches!(self, Type::Unit | Type::File | Type::Function(..))
+    }
+
+    pub fn is_optional(&self) -> bool {
+        matches!(self, Type::Optional(_))
+    }
+
+    /// Whether a value of type `self` can be used where `expected` is required.
+    /// A `T` is implicitly wrapped when a `T?` is expected, and `null` fits any optional.
+    pub fn is_assignable_to(&self, expected: &Type) -> bool {
+        if self == expected || matches!(self, Type::Never | Type::Error) || *expected == Type::Error
+        {
+            return true;
+        }
+        match (self, expected) {
+            (_, Type::Optional(inner)) => *self == Type::Null || self.is_assignable_to(inner),
+            // `Ok(1)` is a `Result[Int, Never]`, which fits any `Result[Int, E]`
+            (Type::Result(ok, err), Type::Result(expected_ok, expected_err)) => {
+                ok.is_assignable_to(expected_ok) && err.is_assignable_to(expected_err)
+            }
+            // Parameters must match exactly, but a function that never returns fits any return type
+            (Type::Function(params, ret), Type::Function(expected_params, expected_ret)) => {
+                params == expected_params && ret.is_assignable_to(expected_ret)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the type that can hold values of both `self` and `other`, if there is one
+    /// (e.g. the two branches of an `if` expression)
+    pub fn join(&self, other: &Type) -> Option<Type> {
+        if let (Type::Result(ok, err), Type::Result(other_ok, other_err)) = (self, other) {
+            return Some(Type::Result(
+                Box::new(ok.join(other_ok)?),
+                Box::new(err.join(other_err)?),
+            ));
+        }
+        if other.is_assignable_to(self) {
+            Some(self.clone())
+        } else if self.is_assignable_to(other) {
+            Some(other.clone())
+        } else if *self == Type::Null {
+            Some(Type::Optional(Box::new(other.clone())))
+        } else if *other == Type::Null {
+            Some(Type::Optional(Box::new(self.clone())))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Char => write!(f, "Char"),
+            Type::String => write!(f, "String"),
+            Type::Unit => write!(f, "()"),
+            Type::Range => write!(f, "Range"),
+            Type::File => write!(f, "File"),
+            Type::Optional(inner) => write!(f, "{}?", inner),
+            Type::Null => write!(f, "null"),
+            Type::Result(ok, err) => write!(f, "Result[{}, {}]", ok, err),
+            Type::Function(params, ret) => {
+                write!(f, "func(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ")")?;
+                if **ret != Type::Unit {
+                    write!(f, " -> {}", ret)?;
+                }
+                Ok(())
+            }
+            Type::Never => write!(f, "Never"),
+            Type::Error => write!(f, "{{error}}"),
+        }
+    }
+}
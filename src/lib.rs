@@ -1,2 +1,6 @@
+pub mod back_end;
 pub mod cli;
 pub mod front_end;
+pub mod runtime;
+#[cfg(test)]
+mod testing;
@@ -1,4 +1,5 @@
 use crawfish::cli::{arg_parser, builder, runner};
+use crawfish::runtime::panic::{self, Panic};
 use std::env;
 use std::process;
 
@@ -6,15 +7,19 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     match arg_parser::parse_args(&args) {
         Ok(command) => match command {
-            arg_parser::Command::Build(path) => {
-                if let Err(e) = builder::build(&path) {
-                    eprintln!("Error: Compilation failure. {:?}", e);
+            arg_parser::Command::Build(path, options) => {
+                if let Err(e) = builder::build(&path, &options) {
+                    eprintln!("Error: Compilation failure. {}", e);
                     process::exit(1);
                 }
             }
             arg_parser::Command::Run(path) => {
                 if let Err(e) = runner::run(&path) {
-                    eprintln!("Error: Run failure. {:?}", e);
+                    // A panic has already been reported with its location
+                    if e.is::<Panic>() {
+                        process::exit(panic::EXIT_CODE);
+                    }
+                    eprintln!("Error: Run failure. {}", e);
                     process::exit(1);
                 }
             }
@@ -0,0 +1,16 @@
+// unrecoverable errors
+pub mod panic;
+// standard streams
+pub mod io;
+// type conversion built-ins
+pub mod convert;
+// math built-ins
+pub mod math;
+// files
+pub mod file;
+// tree-walking interpreter, kept as the oracle that tests compare every backend with
+#[cfg(test)]
+pub mod interpreter;
+pub mod value;
+// bytecode virtual machine
+pub mod vm;
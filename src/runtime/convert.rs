@@ -0,0 +1,111 @@
+//! Conversions of `int()`, `float()` and `char()`, shared by the interpreter, the virtual machine
+//! and constant folding
+//!
+//! A float converts to the integer it truncates to, and a panic when that integer is not an `Int`
+//! or the float is NaN. An integer converts to the character with that Unicode scalar value, and a
+//! panic when there is none. Parsing a string gives an `Err` with a message unless the whole
+//! string is a number: an optional sign and decimal digits, and for a float an optional fraction
+//! and exponent, like `-12`, `+0.5` or `6.02e23`.
+
+/// The `Int` that `value` truncates to, or `None` when there is none
+pub fn float_to_int(value: f64) -> Option<i32> {
+    // Both bounds are exact, and NaN is within neither
+    if value > i32::MIN as f64 - 1.0 && value < i32::MAX as f64 + 1.0 {
+        Some(value as i32)
+    } else {
+        None
+    }
+}
+
+/// The character whose Unicode scalar value is `value`, or `None` when there is none
+pub fn int_to_char(value: i32) -> Option<char> {
+    u32::try_from(value).ok().and_then(char::from_u32)
+}
+
+/// The message of the panic raised by converting `value`, shown as `println()` shows it, to the
+/// type `to`
+pub fn panic_message(value: &dyn std::fmt::Display, to: &str) -> String {
+    format!("cannot convert {} to {}", value, to)
+}
+
+/// Parses an integer, which must be within the range of `Int`
+pub fn parse_int(text: &str) -> Result<i32, String> {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    match digits.bytes().all(|c| c.is_ascii_digit()) {
+        true => text.parse().map_err(|_| parse_error(text, "Int")),
+        false => Err(parse_error(text, "Int")),
+    }
+}
+
+/// Parses a float, which is infinite when it is too large to be finite
+pub fn parse_float(text: &str) -> Result<f64, String> {
+    let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let exponent = exponent.map(|exponent| exponent.strip_prefix(['+', '-']).unwrap_or(exponent));
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|c| c.is_ascii_digit());
+    if is_digits(whole) && fraction.is_none_or(is_digits) && exponent.is_none_or(is_digits) {
+        Ok(text.parse().expect("the text is a decimal number"))
+    } else {
+        Err(parse_error(text, "Float"))
+    }
+}
+
+/// The message of the `Err` returned for a string that is not a number of type `to`
+fn parse_error(text: &str, to: &str) -> String {
+    format!("cannot parse \"{}\" as {}", text, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_float_to_int() {
+        assert_eq!(float_to_int(-2.9), Some(-2));
+        assert_eq!(float_to_int(2147483647.9), Some(i32::MAX));
+        assert_eq!(float_to_int(-2147483648.9), Some(i32::MIN));
+        assert_eq!(float_to_int(2147483648.0), None);
+        assert_eq!(float_to_int(-2147483649.0), None);
+        assert_eq!(float_to_int(f64::NAN), None);
+        assert_eq!(float_to_int(f64::NEG_INFINITY), None);
+    }
+
+    #[test]
+    fn test_int_to_char() {
+        assert_eq!(int_to_char(65), Some('A'));
+        assert_eq!(int_to_char(0x10FFFF), Some('\u{10FFFF}'));
+        assert_eq!(int_to_char(0xD800), None);
+        assert_eq!(int_to_char(0x110000), None);
+        assert_eq!(int_to_char(-1), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_int("-0042"), Ok(-42));
+        assert_eq!(parse_int("+7"), Ok(7));
+        assert_eq!(parse_int("-2147483648"), Ok(i32::MIN));
+        for text in ["", "-", "2147483648", " 1", "1_000", "0x10", "+-1"] {
+            assert_eq!(
+                parse_int(text),
+                Err(format!("cannot parse \"{}\" as Int", text))
+            );
+        }
+        assert_eq!(parse_float("6.02e23"), Ok(6.02e23));
+        assert_eq!(parse_float("-1E-3"), Ok(-0.001));
+        assert_eq!(parse_float("+10"), Ok(10.0));
+        assert_eq!(parse_float("1e400"), Ok(f64::INFINITY));
+        for text in ["", ".5", "5.", "1e", "inf", "NaN", "1.5.2", "e5", "1 "] {
+            assert_eq!(
+                parse_float(text),
+                Err(format!("cannot parse \"{}\" as Float", text))
+            );
+        }
+    }
+}
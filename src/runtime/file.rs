@@ -0,0 +1,130 @@
+//! Files of `open()`, shared by the interpreter and the virtual machine
+//!
+//! A file is opened for reading (mode `"r"`), writing over it (`"w"`) or appending to it (`"a"`),
+//! where both writing modes create a missing file. Failures are returned as the message of an
+//! `Err`: opening gives `cannot open "<path>": <reason>`, and reading or writing gives the reason
+//! alone. Writes are not buffered, so that the file holds them even if the program panics.
+use crate::runtime::io;
+use std::fs::{self, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Write};
+
+/// The reason given when reading or writing a closed file
+const CLOSED: &str = "the file is closed";
+
+/// A file, which is `Closed` once `close()` is called on it
+#[derive(Debug)]
+pub enum File {
+    Reading(BufReader<fs::File>),
+    Writing(fs::File),
+    Closed,
+}
+
+/// Opens the file at `path` in `mode`
+pub fn open(path: &str, mode: &str) -> Result<File, String> {
+    let file = match mode {
+        "r" => fs::File::open(path).map(|file| File::Reading(BufReader::new(file))),
+        "w" => fs::File::create(path).map(File::Writing),
+        "a" => OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map(File::Writing),
+        _ => return Err(open_error(path, "invalid mode")),
+    };
+    file.map_err(|e| open_error(path, reason(&e)))
+}
+
+impl File {
+    pub fn close(&mut self) {
+        *self = File::Closed;
+    }
+
+    /// Reads the rest of the file
+    pub fn read(&mut self) -> Result<String, String> {
+        let mut text = String::new();
+        self.reader()?
+            .read_to_string(&mut text)
+            .map_err(|e| reason(&e).to_string())?;
+        Ok(text)
+    }
+
+    /// Reads the next line without its line ending, or returns `None` at the end of the file
+    pub fn read_line(&mut self) -> Result<Option<String>, String> {
+        io::read_line(self.reader()?).map_err(|e| reason(&e).to_string())
+    }
+
+    pub fn write(&mut self, text: &str) -> Result<(), String> {
+        match self {
+            File::Writing(file) => file
+                .write_all(text.as_bytes())
+                .map_err(|e| reason(&e).to_string()),
+            File::Reading(_) => Err("the file is not open for writing".to_string()),
+            File::Closed => Err(CLOSED.to_string()),
+        }
+    }
+
+    fn reader(&mut self) -> Result<&mut BufReader<fs::File>, String> {
+        match self {
+            File::Reading(reader) => Ok(reader),
+            File::Writing(_) => Err("the file is not open for reading".to_string()),
+            File::Closed => Err(CLOSED.to_string()),
+        }
+    }
+}
+
+fn open_error(path: &str, reason: &str) -> String {
+    format!("cannot open \"{}\": {}", path, reason)
+}
+
+/// The reason an operation failed, which every backend words the same
+fn reason(error: &std::io::Error) -> &'static str {
+    match error.kind() {
+        ErrorKind::NotFound => "no such file or directory",
+        ErrorKind::PermissionDenied => "permission denied",
+        ErrorKind::IsADirectory => "is a directory",
+        ErrorKind::InvalidData => "invalid UTF-8",
+        _ => "input/output error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_files() {
+        let path = std::env::temp_dir().join(format!("crawfish-file-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut file = open(path, "w").unwrap();
+        file.write("one\r\ntwo\n").unwrap();
+        assert_eq!(
+            file.read(),
+            Err("the file is not open for reading".to_string())
+        );
+        file.close();
+        assert_eq!(file.write("x"), Err(CLOSED.to_string()));
+        let mut file = open(path, "a").unwrap();
+        file.write("three").unwrap();
+
+        let mut file = open(path, "r").unwrap();
+        assert_eq!(file.read_line(), Ok(Some("one".to_string())));
+        assert_eq!(file.read(), Ok("two\nthree".to_string()));
+        assert_eq!(file.read_line(), Ok(None));
+        assert_eq!(
+            file.write("x"),
+            Err("the file is not open for writing".to_string())
+        );
+        fs::remove_file(path).unwrap();
+
+        let missing = format!("cannot open \"{}\": no such file or directory", path);
+        assert_eq!(open(path, "r").unwrap_err(), missing);
+        let invalid = format!("cannot open \"{}\": invalid mode", path);
+        assert_eq!(open(path, "rw").unwrap_err(), invalid);
+        let directory = std::env::temp_dir();
+        let directory = directory.to_str().unwrap();
+        assert_eq!(
+            open(directory, "r").unwrap().read(),
+            Err("is a directory".to_string())
+        );
+    }
+}
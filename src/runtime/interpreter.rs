@@ -0,0 +1,1109 @@
+//! Tree-walking interpreter, which runs a type checked program directly from its syntax tree.
+//! `crawfish run` uses the virtual machine, and every backend consumes the IR; the interpreter is
+//! only built for tests, as an oracle that shares none of that lowering, so each backend's output
+//! is compared with it.
+use crate::front_end::ast::{
+    BinaryOp, Block, Expr, ExprKind, Function, Item, Lambda, Literal, Program, Stmt, StmtKind,
+    StringPart, UnaryOp,
+};
+use crate::front_end::format::{self, Piece};
+use crate::front_end::token::Span;
+use crate::front_end::types::Type;
+use crate::runtime::convert;
+use crate::runtime::file;
+use crate::runtime::io;
+use crate::runtime::math::{self, Number};
+use crate::runtime::panic::Panic;
+use crate::runtime::value::{Cell, Closure, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+use std::thread;
+
+/// Deepest chain of nested calls before the program is stopped with a stack overflow
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Stack size of the interpreter's thread, which needs room for `MAX_CALL_DEPTH` nested calls
+const STACK_SIZE: usize = 1 << 30;
+
+/// Runs `main()` of a type checked program, reading the lines of `input()` from `input` and
+/// writing everything it prints to `out`
+pub fn run(
+    program: &Program,
+    input: &mut (dyn BufRead + Send),
+    out: &mut (dyn Write + Send),
+) -> Result<(), Panic> {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let mut interpreter = Interpreter::new(program, input, out);
+                let main = interpreter.functions["main"];
+                // Output printed before a panic is flushed before the panic is reported
+                let result = interpreter.call_function(main, Vec::new(), main.span);
+                let flushed = interpreter.flush();
+                result.and(flushed)
+            })
+            .expect("failed to spawn the interpreter thread")
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    })
+}
+
+/// Why evaluation stopped before producing a value
+enum Unwind<'a> {
+    Return(Value<'a>),
+    Break,
+    Continue,
+    Panic(Panic),
+}
+
+type Flow<'a, T> = Result<T, Unwind<'a>>;
+
+/// A deferred expression, and the scopes its variables are looked up in
+type Deferred<'a> = (&'a Expr, Vec<HashMap<&'a str, Cell<'a>>>);
+
+/// Interpreter
+/// - `functions` maps the linked name of every top level function to its declaration
+/// - `scopes` is the stack of lexical scopes of the function being run, innermost last
+/// - `deferred` holds, for each block being run, innermost last, the expressions deferred in it so
+///   far along with the scopes they were written in
+/// - `file` is the index of the source file of the function being run, which panics point into
+/// - `depth` is the number of calls in progress
+struct Interpreter<'a, 'o> {
+    functions: HashMap<&'a str, &'a Function>,
+    scopes: Vec<HashMap<&'a str, Cell<'a>>>,
+    deferred: Vec<Vec<Deferred<'a>>>,
+    file: usize,
+    depth: usize,
+    input: &'o mut (dyn BufRead + Send),
+    out: &'o mut (dyn Write + Send),
+}
+
+impl<'a, 'o> Interpreter<'a, 'o> {
+    fn new(
+        program: &'a Program,
+        input: &'o mut (dyn BufRead + Send),
+        out: &'o mut (dyn Write + Send),
+    ) -> Self {
+        let functions = program
+            .items
+            .iter()
+            .map(|item| {
+                let Item::Function(function) = item;
+                (function.name.name.as_str(), function)
+            })
+            .collect();
+        Self {
+            functions,
+            scopes: Vec::new(),
+            deferred: Vec::new(),
+            file: 0,
+            depth: 0,
+            input,
+            out,
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        function: &'a Function,
+        args: Vec<Value<'a>>,
+        span: Span,
+    ) -> Result<Value<'a>, Panic> {
+        let params = function.params.iter().map(|param| param.name.name.as_str());
+        let scope = params
+            .zip(args)
+            .map(|(name, arg)| (name, cell(arg)))
+            .collect();
+        self.call(&function.body, scope, function.file, span)
+    }
+
+    fn call_closure(
+        &mut self,
+        closure: &Closure<'a>,
+        args: Vec<Value<'a>>,
+        span: Span,
+    ) -> Result<Value<'a>, Panic> {
+        // The captured variables form an outer scope, so that parameters can shadow them
+        let params = closure.lambda.params.iter().map(|p| p.name.name.as_str());
+        let scope = params
+            .zip(args)
+            .map(|(name, arg)| (name, cell(arg)))
+            .collect();
+        let mut scopes = vec![closure.environment.clone(), scope];
+        std::mem::swap(&mut self.scopes, &mut scopes);
+        let result = self.call_body(&closure.lambda.body, closure.file, span);
+        self.scopes = scopes;
+        result
+    }
+
+    fn call(
+        &mut self,
+        body: &'a Block,
+        scope: HashMap<&'a str, Cell<'a>>,
+        file: usize,
+        span: Span,
+    ) -> Result<Value<'a>, Panic> {
+        let outer_scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        let result = self.call_body(body, file, span);
+        self.scopes = outer_scopes;
+        result
+    }
+
+    fn call_body(&mut self, body: &'a Block, file: usize, span: Span) -> Result<Value<'a>, Panic> {
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(Panic::new("stack overflow", span).in_file(self.file));
+        }
+        self.depth += 1;
+        let outer_file = std::mem::replace(&mut self.file, file);
+        let result = match self.eval_block(body) {
+            Ok(value) => Ok(value),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Panic(panic)) => Err(panic),
+            Err(Unwind::Break | Unwind::Continue) => {
+                unreachable!("control-flow analysis rejects `break` and `continue` outside loops")
+            }
+        };
+        self.file = outer_file;
+        self.depth -= 1;
+        result
+    }
+
+    fn eval_block(&mut self, block: &'a Block) -> Flow<'a, Value<'a>> {
+        self.scopes.push(HashMap::new());
+        self.deferred.push(Vec::new());
+        let result = self.eval_block_contents(block);
+        let result = self.run_deferred(result);
+        self.scopes.pop();
+        result
+    }
+
+    /// Evaluates the expressions deferred in the block that just ended, the latest first, unless
+    /// it ended with a panic
+    fn run_deferred(&mut self, result: Flow<'a, Value<'a>>) -> Flow<'a, Value<'a>> {
+        let deferred = self.deferred.pop().unwrap();
+        if let Err(Unwind::Panic(_)) = result {
+            return result;
+        }
+        for (expr, scopes) in deferred.into_iter().rev() {
+            let scopes = std::mem::replace(&mut self.scopes, scopes);
+            // Control-flow analysis rejects jumps out of a deferred expression, so only panics
+            // stop it
+            let value = self.eval(expr);
+            self.scopes = scopes;
+            value?;
+        }
+        result
+    }
+
+    fn eval_block_contents(&mut self, block: &'a Block) -> Flow<'a, Value<'a>> {
+        for stmt in &block.stmts {
+            self.exec(stmt)?;
+        }
+        match &block.tail {
+            Some(tail) => self.eval(tail),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn exec(&mut self, stmt: &'a Stmt) -> Flow<'a, ()> {
+        match &stmt.kind {
+            StmtKind::Var { name, value, .. } => {
+                let value = self.eval(value)?;
+                self.declare(&name.name, value);
+            }
+            StmtKind::Assign { target, op, value } => {
+                let ExprKind::Identifier(name) = &target.kind else {
+                    unreachable!("the type checker only accepts variables as assignment targets");
+                };
+                let value = self.eval(value)?;
+                let cell = self.lookup(name).expect("assigned variables are declared");
+                let value = match op {
+                    Some(op) => {
+                        let current = cell.borrow().clone();
+                        self.binary(*op, current, value, stmt.span)?
+                    }
+                    None => value,
+                };
+                *cell.borrow_mut() = value;
+            }
+            StmtKind::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            StmtKind::While { cond, body } => loop {
+                if !self.eval(cond)?.as_bool() {
+                    break;
+                }
+                match self.eval_block(body) {
+                    Ok(_) | Err(Unwind::Continue) => (),
+                    Err(Unwind::Break) => break,
+                    Err(unwind) => return Err(unwind),
+                }
+            },
+            StmtKind::For {
+                item,
+                iterable,
+                body,
+            } => match self.eval(iterable)? {
+                Value::Range {
+                    start,
+                    end,
+                    inclusive,
+                } => {
+                    let end = if inclusive {
+                        end as i64 + 1
+                    } else {
+                        end as i64
+                    };
+                    for i in start as i64..end {
+                        if !self.iterate(&item.name, Value::Int(i as i32), body)? {
+                            break;
+                        }
+                    }
+                }
+                Value::String(text) => {
+                    for c in text.chars() {
+                        if !self.iterate(&item.name, Value::Char(c), body)? {
+                            break;
+                        }
+                    }
+                }
+                // The lines of a file, until its end or the first line that cannot be read
+                Value::File(file) => loop {
+                    let Ok(Some(line)) = file.borrow_mut().read_line() else {
+                        break;
+                    };
+                    if !self.iterate(&item.name, Value::String(line.into()), body)? {
+                        break;
+                    }
+                },
+                _ => unreachable!(
+                    "the type checker only accepts ranges, strings and files in `for` loops"
+                ),
+            },
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Unit,
+                };
+                return Err(Unwind::Return(value));
+            }
+            StmtKind::Defer(expr) => {
+                let scopes = self.scopes.clone();
+                self.deferred.last_mut().unwrap().push((expr, scopes));
+            }
+            StmtKind::Break => return Err(Unwind::Break),
+            StmtKind::Continue => return Err(Unwind::Continue),
+        }
+        Ok(())
+    }
+
+    /// Runs the body of a `for` loop with its item bound to `value`, returning whether the loop
+    /// goes on
+    fn iterate(&mut self, item: &'a str, value: Value<'a>, body: &'a Block) -> Flow<'a, bool> {
+        self.scopes.push(HashMap::new());
+        self.declare(item, value);
+        let result = self.eval_block(body);
+        self.scopes.pop();
+        match result {
+            Ok(_) | Err(Unwind::Continue) => Ok(true),
+            Err(Unwind::Break) => Ok(false),
+            Err(unwind) => Err(unwind),
+        }
+    }
+
+    fn eval(&mut self, expr: &'a Expr) -> Flow<'a, Value<'a>> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => Ok(match literal {
+                // The type checker only accepts integer literals that fit in 32 bits
+                Literal::Int(value) => Value::Int(*value as i32),
+                Literal::Float(value) => Value::Float(*value),
+                Literal::Bool(value) => Value::Bool(*value),
+                Literal::Char(value) => Value::Char(*value),
+                Literal::String(value) => Value::String(Rc::from(value.as_str())),
+                Literal::Null => Value::Null,
+            }),
+            ExprKind::Identifier(name) => match self.lookup(name) {
+                Some(cell) => Ok(cell.borrow().clone()),
+                None => Ok(Value::Function(self.functions[name.as_str()])),
+            },
+            ExprKind::Qualified { .. } => {
+                unreachable!("the type checker resolves qualified names")
+            }
+            ExprKind::Unary { op, operand } => {
+                let operand = self.eval(operand)?;
+                match (op, operand) {
+                    (UnaryOp::Negate, Value::Int(value)) => match value.checked_neg() {
+                        Some(value) => Ok(Value::Int(value)),
+                        None => Err(self.panic("attempt to negate with overflow", expr.span)),
+                    },
+                    (UnaryOp::Negate, Value::Float(value)) => Ok(Value::Float(-value)),
+                    (UnaryOp::Not, Value::Bool(value)) => Ok(Value::Bool(!value)),
+                    (UnaryOp::BitNot, Value::Int(value)) => Ok(Value::Int(!value)),
+                    _ => unreachable!("the type checker rejects invalid unary operands"),
+                }
+            }
+            ExprKind::Binary { left, op, right } => {
+                // An optional `Result` is only replaced by the default when it is `null`
+                let result = matches!(left.ty, Type::Result(..));
+                let left = self.eval(left)?;
+                // `and`, `or` and `??` only evaluate their right operand when it decides the result
+                match (op, &left) {
+                    (BinaryOp::And, Value::Bool(false)) | (BinaryOp::Or, Value::Bool(true)) => {
+                        return Ok(left)
+                    }
+                    (BinaryOp::And | BinaryOp::Or, _) => return self.eval(right),
+                    (BinaryOp::Coalesce, Value::Null) => return self.eval(right),
+                    (BinaryOp::Coalesce, Value::Err(_)) if result => return self.eval(right),
+                    (BinaryOp::Coalesce, Value::Ok(value)) if result => return Ok(*value.clone()),
+                    (BinaryOp::Coalesce, _) => return Ok(left),
+                    _ => (),
+                }
+                let right = self.eval(right)?;
+                self.binary(*op, left, right, expr.span)
+            }
+            ExprKind::Call { callee, args } => self.eval_call(callee, args, expr.span),
+            ExprKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                if self.eval(cond)?.as_bool() {
+                    self.eval_block(then_branch)
+                } else {
+                    match else_branch {
+                        Some(else_branch) => self.eval(else_branch),
+                        None => Ok(Value::Unit),
+                    }
+                }
+            }
+            ExprKind::Block(block) => self.eval_block(block),
+            ExprKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let start = self.eval(start)?.as_int();
+                let end = self.eval(end)?.as_int();
+                Ok(Value::Range {
+                    start,
+                    end,
+                    inclusive: *inclusive,
+                })
+            }
+            ExprKind::Try(operand) => match self.eval(operand)? {
+                Value::Ok(value) => Ok(*value),
+                err @ Value::Err(_) => Err(Unwind::Return(err)),
+                _ => unreachable!("the type checker only accepts `Result` operands for `?`"),
+            },
+            ExprKind::Lambda(lambda) => Ok(self.make_closure(lambda)),
+            ExprKind::Interpolation(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Text(part) => text.push_str(part),
+                        StringPart::Expr(expr) => text.push_str(&self.eval(expr)?.to_string()),
+                    }
+                }
+                Ok(Value::String(text.into()))
+            }
+        }
+    }
+
+    fn eval_call(&mut self, callee: &'a Expr, args: &'a [Expr], span: Span) -> Flow<'a, Value<'a>> {
+        let builtin = match &callee.kind {
+            ExprKind::Identifier(name) if self.lookup(name).is_none() => {
+                !self.functions.contains_key(name.as_str())
+            }
+            _ => false,
+        };
+        let callee_value = if builtin {
+            None
+        } else {
+            Some(self.eval(callee)?)
+        };
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.eval(arg)?);
+        }
+
+        let result = match callee_value {
+            None => {
+                let ExprKind::Identifier(name) = &callee.kind else {
+                    unreachable!("built-in functions are called by name");
+                };
+                if let Some(format) = format::of_call(name, args) {
+                    values.remove(0);
+                    return self.print_format(name == "println", format, &args[1..], values, span);
+                }
+                return self.call_builtin(name, values, span);
+            }
+            Some(Value::Function(function)) => self.call_function(function, values, span),
+            Some(Value::Closure(closure)) => self.call_closure(&closure, values, span),
+            Some(_) => unreachable!("the type checker only accepts calls to functions"),
+        };
+        result.map_err(Unwind::Panic)
+    }
+
+    fn call_builtin(
+        &mut self,
+        name: &str,
+        args: Vec<Value<'a>>,
+        span: Span,
+    ) -> Flow<'a, Value<'a>> {
+        let mut args = args.into_iter();
+        let arg = args.next();
+        match (name, arg) {
+            ("print", Some(value)) => self.print(&value.to_string(), span),
+            ("println", arg) => {
+                let line = arg.map_or(String::new(), |value| value.to_string());
+                self.print(&(line + "\n"), span)
+            }
+            ("input", None) => {
+                // Whatever was printed before, such as a prompt, shows before the program waits
+                self.flush().map_err(Unwind::Panic)?;
+                match io::read_line(self.input) {
+                    Ok(Some(line)) => Ok(Value::String(line.into())),
+                    Ok(None) => Ok(Value::Null),
+                    Err(e) => Err(self.panic(format!("failed reading from stdin: {}", e), span)),
+                }
+            }
+            ("panic", Some(message)) => Err(self.panic(message.to_string(), span)),
+            ("Ok", arg) => Ok(Value::Ok(Box::new(arg.unwrap_or(Value::Unit)))),
+            ("Err", Some(error)) => Ok(Value::Err(Box::new(error))),
+            ("unwrap", Some(Value::Ok(value))) | ("unwrap_err", Some(Value::Err(value))) => {
+                Ok(*value)
+            }
+            ("unwrap", Some(Value::Err(error))) => Err(self.panic(
+                format!("called `unwrap()` on an `Err` value: {}", error),
+                span,
+            )),
+            ("unwrap_err", Some(Value::Ok(value))) => Err(self.panic(
+                format!("called `unwrap_err()` on an `Ok` value: {}", value),
+                span,
+            )),
+            ("is_ok", Some(result)) => Ok(Value::Bool(matches!(result, Value::Ok(_)))),
+            ("is_err", Some(result)) => Ok(Value::Bool(matches!(result, Value::Err(_)))),
+            ("int" | "float" | "char" | "string", Some(value)) => self.convert(name, value, span),
+            (name, Some(first)) if math::FUNCTIONS.contains(&name) => {
+                let args: Vec<Value<'a>> = std::iter::once(first).chain(args).collect();
+                self.math(name, &args, span)
+            }
+            ("open" | "close" | "read" | "read_line" | "write", Some(first)) => {
+                let args: Vec<Value<'a>> = std::iter::once(first).chain(args).collect();
+                Ok(file_builtin(name, &args))
+            }
+            _ => unreachable!("the type checker validates calls to built-in functions"),
+        }
+    }
+
+    /// Converts a value with `int()`, `float()`, `char()` or `string()`
+    fn convert(&self, name: &str, value: Value<'a>, span: Span) -> Flow<'a, Value<'a>> {
+        let parsed = |result: Result<Value<'a>, String>| match result {
+            Ok(value) => Value::Ok(Box::new(value)),
+            Err(message) => Value::Err(Box::new(Value::String(message.into()))),
+        };
+        let converted = match (name, &value) {
+            ("string", value) => Some(Value::String(value.to_string().into())),
+            ("int", Value::Float(float)) => convert::float_to_int(*float).map(Value::Int),
+            ("int", Value::Char(c)) => Some(Value::Int(*c as i32)),
+            ("int", Value::String(text)) => Some(parsed(convert::parse_int(text).map(Value::Int))),
+            ("float", Value::Int(int)) => Some(Value::Float(*int as f64)),
+            ("float", Value::String(text)) => {
+                Some(parsed(convert::parse_float(text).map(Value::Float)))
+            }
+            ("char", Value::Int(int)) => convert::int_to_char(*int).map(Value::Char),
+            _ => Some(value.clone()),
+        };
+        converted.ok_or_else(|| {
+            let to = if name == "int" { "Int" } else { "Char" };
+            self.panic(convert::panic_message(&value, to), span)
+        })
+    }
+
+    /// Calls a math built-in on integers or floats
+    fn math(&self, name: &str, args: &[Value<'a>], span: Span) -> Flow<'a, Value<'a>> {
+        let numbers: Vec<Number> = args
+            .iter()
+            .map(|arg| match arg {
+                Value::Int(int) => Number::Int(*int),
+                Value::Float(float) => Number::Float(*float),
+                _ => unreachable!("the type checker only passes numbers to math built-ins"),
+            })
+            .collect();
+        match math::call(name, &numbers) {
+            Ok(Number::Int(int)) => Ok(Value::Int(int)),
+            Ok(Number::Float(float)) => Ok(Value::Float(float)),
+            Err(message) => Err(self.panic(message, span)),
+        }
+    }
+
+    /// Prints the pieces of a format string, with the values of the arguments in its placeholders
+    fn print_format(
+        &mut self,
+        newline: bool,
+        format: &str,
+        args: &[Expr],
+        values: Vec<Value<'a>>,
+        span: Span,
+    ) -> Flow<'a, Value<'a>> {
+        let mut text = String::new();
+        let mut values = values.into_iter().zip(args);
+        for piece in format::parse(format).expect("the type checker validates format strings") {
+            match piece {
+                Piece::Text(piece) => text.push_str(&piece),
+                Piece::Placeholder(spec) => {
+                    let (value, arg) = values.next().expect("the type checker counts arguments");
+                    let float = match value {
+                        Value::Float(float) => Some(float),
+                        _ => None,
+                    };
+                    let align = spec.alignment(arg.ty.is_numeric());
+                    let padded = io::padded(&value, float, spec.width, spec.precision, align);
+                    text.push_str(&padded);
+                }
+            }
+        }
+        if newline {
+            text.push('\n');
+        }
+        self.print(&text, span)
+    }
+
+    fn print(&mut self, text: &str, span: Span) -> Flow<'a, Value<'a>> {
+        match self.out.write_all(text.as_bytes()) {
+            Ok(()) => Ok(Value::Unit),
+            Err(e) => Err(self.panic(format!("failed printing to stdout: {}", e), span)),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        left: Value<'a>,
+        right: Value<'a>,
+        span: Span,
+    ) -> Flow<'a, Value<'a>> {
+        use Value::{Bool, Char, Float, Int};
+        let value = match (op, left, right) {
+            (BinaryOp::Equal, left, right) => Bool(left == right),
+            (BinaryOp::NotEqual, left, right) => Bool(left != right),
+
+            (BinaryOp::Add, Int(a), Int(b)) => self.checked(a.checked_add(b), "add", span)?,
+            (BinaryOp::Subtract, Int(a), Int(b)) => {
+                self.checked(a.checked_sub(b), "subtract", span)?
+            }
+            (BinaryOp::Multiply, Int(a), Int(b)) => {
+                self.checked(a.checked_mul(b), "multiply", span)?
+            }
+            (BinaryOp::Divide | BinaryOp::Remainder, Int(_), Int(0)) => {
+                return Err(self.panic("attempt to divide by zero", span));
+            }
+            (BinaryOp::Divide, Int(a), Int(b)) => self.checked(a.checked_div(b), "divide", span)?,
+            (BinaryOp::Remainder, Int(a), Int(b)) => {
+                self.checked(a.checked_rem(b), "calculate the remainder", span)?
+            }
+            (BinaryOp::BitAnd, Int(a), Int(b)) => Int(a & b),
+            (BinaryOp::BitOr, Int(a), Int(b)) => Int(a | b),
+            (BinaryOp::BitXor, Int(a), Int(b)) => Int(a ^ b),
+            (BinaryOp::ShiftLeft, Int(a), Int(b)) => {
+                let shifted = u32::try_from(b).ok().and_then(|b| a.checked_shl(b));
+                self.checked(shifted, "shift left", span)?
+            }
+            (BinaryOp::ShiftRight, Int(a), Int(b)) => {
+                let shifted = u32::try_from(b).ok().and_then(|b| a.checked_shr(b));
+                self.checked(shifted, "shift right", span)?
+            }
+
+            (BinaryOp::Add, Float(a), Float(b)) => Float(a + b),
+            (BinaryOp::Subtract, Float(a), Float(b)) => Float(a - b),
+            (BinaryOp::Multiply, Float(a), Float(b)) => Float(a * b),
+            (BinaryOp::Divide, Float(a), Float(b)) => Float(a / b),
+            (BinaryOp::Remainder, Float(a), Float(b)) => Float(a % b),
+
+            (BinaryOp::Less, Int(a), Int(b)) => Bool(a < b),
+            (BinaryOp::LessEqual, Int(a), Int(b)) => Bool(a <= b),
+            (BinaryOp::Greater, Int(a), Int(b)) => Bool(a > b),
+            (BinaryOp::GreaterEqual, Int(a), Int(b)) => Bool(a >= b),
+            (BinaryOp::Less, Float(a), Float(b)) => Bool(a < b),
+            (BinaryOp::LessEqual, Float(a), Float(b)) => Bool(a <= b),
+            (BinaryOp::Greater, Float(a), Float(b)) => Bool(a > b),
+            (BinaryOp::GreaterEqual, Float(a), Float(b)) => Bool(a >= b),
+            (BinaryOp::Less, Char(a), Char(b)) => Bool(a < b),
+            (BinaryOp::LessEqual, Char(a), Char(b)) => Bool(a <= b),
+            (BinaryOp::Greater, Char(a), Char(b)) => Bool(a > b),
+            (BinaryOp::GreaterEqual, Char(a), Char(b)) => Bool(a >= b),
+            _ => unreachable!("the type checker rejects invalid binary operands"),
+        };
+        Ok(value)
+    }
+
+    /// Wraps the result of a checked integer operation, panicking if it overflowed
+    fn checked(&self, result: Option<i32>, operation: &str, span: Span) -> Flow<'a, Value<'a>> {
+        match result {
+            Some(value) => Ok(Value::Int(value)),
+            None => Err(self.panic(format!("attempt to {} with overflow", operation), span)),
+        }
+    }
+
+    fn make_closure(&self, lambda: &'a Lambda) -> Value<'a> {
+        let environment = lambda
+            .captures
+            .iter()
+            .map(|capture| {
+                let name = capture.name.as_str();
+                let cell = self.lookup(name).expect("captured variables are in scope");
+                (name, cell)
+            })
+            .collect();
+        Value::Closure(Rc::new(Closure {
+            lambda,
+            environment,
+            file: self.file,
+        }))
+    }
+
+    fn declare(&mut self, name: &'a str, value: Value<'a>) {
+        self.scopes.last_mut().unwrap().insert(name, cell(value));
+    }
+
+    fn lookup(&self, name: &str) -> Option<Cell<'a>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+    }
+
+    fn panic(&self, message: impl Into<String>, span: Span) -> Unwind<'a> {
+        Unwind::Panic(Panic::new(message, span).in_file(self.file))
+    }
+
+    fn flush(&mut self) -> Result<(), Panic> {
+        self.out
+            .flush()
+            .map_err(|e| Panic::new(format!("failed printing to stdout: {}", e), Span::default()))
+    }
+}
+
+/// Calls a file built-in, which returns a `Result` holding how it failed, except for `close()`
+fn file_builtin<'a>(name: &str, args: &[Value<'a>]) -> Value<'a> {
+    let result = match (name, args) {
+        ("open", [Value::String(path), Value::String(mode)]) => {
+            file::open(path, mode).map(|file| Value::File(Rc::new(RefCell::new(file))))
+        }
+        ("close", [Value::File(file)]) => {
+            file.borrow_mut().close();
+            return Value::Unit;
+        }
+        ("read", [Value::File(file)]) => file
+            .borrow_mut()
+            .read()
+            .map(|text| Value::String(text.into())),
+        ("read_line", [Value::File(file)]) => file
+            .borrow_mut()
+            .read_line()
+            .map(|line| line.map_or(Value::Null, |line| Value::String(line.into()))),
+        ("write", [Value::File(file), Value::String(text)]) => {
+            file.borrow_mut().write(text).map(|()| Value::Unit)
+        }
+        _ => unreachable!("the type checker validates calls to file built-ins"),
+    };
+    match result {
+        Ok(value) => Value::Ok(Box::new(value)),
+        Err(message) => Value::Err(Box::new(Value::String(message.into()))),
+    }
+}
+
+impl<'a> Value<'a> {
+    fn as_bool(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            _ => unreachable!("the type checker only accepts `Bool` conditions"),
+        }
+    }
+
+    fn as_int(&self) -> i32 {
+        match self {
+            Value::Int(value) => *value,
+            _ => unreachable!("the type checker only accepts `Int` range bounds"),
+        }
+    }
+}
+
+fn cell(value: Value<'_>) -> Cell<'_> {
+    Rc::new(RefCell::new(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::front_end;
+
+    /// Runs `source`, returning what it printed and how it panicked, if it did
+    fn run_source(source: &str) -> (String, Option<Panic>) {
+        run_with_input(source, "")
+    }
+
+    /// Runs `source` with `input` as its stdin
+    fn run_with_input(source: &str, input: &str) -> (String, Option<Panic>) {
+        let analysis = front_end::analyze(source).unwrap();
+        let mut out = Vec::new();
+        let result = run(&analysis.program, &mut input.as_bytes(), &mut out);
+        (String::from_utf8(out).unwrap(), result.err())
+    }
+
+    fn output(source: &str) -> String {
+        let (out, panic) = run_source(source);
+        assert_eq!(panic, None);
+        out
+    }
+
+    #[test]
+    fn test_arithmetic_and_printing() {
+        let source = r#"
+            func main() {
+                println(1 + 2 * 3);
+                println(7 / 2);
+                println(-7 % 3);
+                println(1.5 * 2.0);
+                println(0.1 + 0.2);
+                println(1 << 4 | 1);
+                println('c');
+                println("hi");
+                println(3 > 2 and !false);
+                println();
+            }
+        "#;
+        assert_eq!(
+            output(source),
+            "7\n3\n-1\n3.0\n0.30000000000000004\n17\nc\nhi\ntrue\n\n"
+        );
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = r#"
+            func fib(n: Int) -> Int {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            func main() {
+                var total = 0;
+                for i in 0..=10 {
+                    if i % 2 == 0 { continue; }
+                    total += i;
+                }
+                println(total);
+                var n = 0;
+                while true {
+                    n += 1;
+                    if n == 5 { break; }
+                }
+                println(n);
+                println(fib(15));
+                const sign = if total > 0 { 1 } else { -1 };
+                println(sign);
+            }
+        "#;
+        assert_eq!(output(source), "25\n5\n610\n1\n");
+    }
+
+    #[test]
+    fn test_shadowing_and_scopes() {
+        let source = r#"
+            func main() {
+                const x = 1;
+                {
+                    const x = 2;
+                    println(x);
+                }
+                println(x);
+            }
+        "#;
+        assert_eq!(output(source), "2\n1\n");
+    }
+
+    #[test]
+    fn test_optionals_and_results() {
+        let source = r#"
+            func half(x: Int) -> Result[Int, String] {
+                if x % 2 != 0 { return Err("odd"); }
+                return Ok(x / 2);
+            }
+            func quarter(x: Int) -> Result[Int, String] {
+                return Ok(half(half(x)?)?);
+            }
+            func greet(name: String?) {
+                println(name ?? "anonymous");
+            }
+            func main() {
+                greet(null);
+                greet("crawfish");
+                println(quarter(8));
+                println(quarter(6));
+                println(half(3) ?? 0);
+                println(is_ok(half(2)));
+                println(unwrap_err(half(1)));
+            }
+        "#;
+        assert_eq!(
+            output(source),
+            "anonymous\ncrawfish\nOk(2)\nErr(odd)\n0\ntrue\nodd\n"
+        );
+    }
+
+    #[test]
+    fn test_closures_share_captured_variables() {
+        let source = r#"
+            func counter() -> func() -> Int {
+                var count = 0;
+                return func() -> Int {
+                    count += 1;
+                    return count;
+                };
+            }
+            func apply(f: func(Int) -> Int, x: Int) -> Int { return f(x); }
+            func double(x: Int) -> Int { return x * 2; }
+            func main() {
+                const next = counter();
+                next();
+                println(next());
+                const other = counter();
+                println(other());
+                var offset = 1;
+                const add = func(x: Int) -> Int { return x + offset; };
+                offset = 10;
+                println(apply(add, 5));
+                println(apply(double, 5));
+            }
+        "#;
+        assert_eq!(output(source), "2\n1\n15\n10\n");
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let source = "func main() {\n    var zero = 0;\n    println(1 / zero);\n}";
+        let (_, panic) = run_source(source);
+        let panic = panic.unwrap();
+        assert_eq!(panic.message, "attempt to divide by zero");
+        assert_eq!(
+            panic.render("main.crw", source),
+            "panicked at main.crw:3:13: attempt to divide by zero"
+        );
+
+        let (_, panic) = run_source("func main() { var x = 2147483647; x += 1; }");
+        assert_eq!(panic.unwrap().message, "attempt to add with overflow");
+
+        let (_, panic) = run_source("func main() { var s = 40; println(1 << s); }");
+        assert_eq!(
+            panic.unwrap().message,
+            "attempt to shift left with overflow"
+        );
+    }
+
+    #[test]
+    fn test_panics() {
+        let source = r#"func main() { println("before"); panic("oh no"); println("after"); }"#;
+        let (out, panic) = run_source(source);
+        assert_eq!(out, "before\n");
+        assert_eq!(panic.unwrap().message, "oh no");
+
+        let source = r#"func main() { const r: Result[Int, String] = Err("bad"); unwrap(r); }"#;
+        let (_, panic) = run_source(source);
+        assert_eq!(
+            panic.unwrap().message,
+            "called `unwrap()` on an `Err` value: bad"
+        );
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        let (_, panic) =
+            run_source("func f(n: Int) -> Int { return f(n + 1); } func main() { f(0); }");
+        assert_eq!(panic.unwrap().message, "stack overflow");
+    }
+
+    #[test]
+    fn test_formatted_printing() {
+        let source = r#"
+            func main() {
+                print("{}-", 1);
+                println("[{:>4}|{:<4}|{:^5}]", 7, "ab", true);
+                println("{:.2} {:8.3} {{literal}}", 3.14159, -2.0);
+                println("{} and {}", null, Ok(1));
+            }
+        "#;
+        assert_eq!(
+            output(source),
+            "1-[   7|ab  |true ]\n3.14   -2.000 {literal}\nnull and Ok(1)\n"
+        );
+        let source = r#"func main() { println("a}b"); print("{}\n"); println("{{x}} {:>3}"); }"#;
+        assert_eq!(output(source), "a}b\n{}\n{x} {:>3}\n");
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let source = r#"
+            func greet(name: String?) -> String {
+                return "Hello, {name ?? "stranger"}!";
+            }
+            func main() {
+                const x = 41;
+                println("{{x}} = {x + 1}, {greet("Ada")} {greet(null)}");
+                println("{1.5} {'é'} {null} {Err("no")}");
+            }
+        "#;
+        assert_eq!(
+            output(source),
+            "{x} = 42, Hello, Ada! Hello, stranger!\n1.5 é null Err(no)\n"
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        let source = r#"
+            func main() {
+                println(int(-2.9) + int('A'));
+                println(float(3));
+                println(char(129408));
+                println(string(1.0 / 3.0));
+                println(int("-17"));
+                println(int("17.0"));
+                println(float("2.5e-3"));
+                println(float("two"));
+            }
+        "#;
+        assert_eq!(
+            output(source),
+            "63\n3.0\n🦀\n0.3333333333333333\nOk(-17)\nErr(cannot parse \"17.0\" as Int)\nOk(0.0025)\nErr(cannot parse \"two\" as Float)\n"
+        );
+        let (_, panic) = run_source("func main() { println(int(1.0 / 0.0)); }");
+        assert_eq!(panic.unwrap().message, "cannot convert inf to Int");
+        let (_, panic) = run_source("func main() { println(char(-5)); }");
+        assert_eq!(panic.unwrap().message, "cannot convert -5 to Char");
+    }
+
+    #[test]
+    fn test_math_builtins() {
+        let source = r#"
+            func main() {
+                println("{min(3, -4)} {max(0.5, 2.0)} {abs(-7)} {abs(-1.5)}");
+                println("{pow(-2, 31)} {pow(2.0, -1.0)} {sqrt(2.0)}");
+                println("{ceil(-1.5)} {floor(-1.5)} {log(1000.0)} {ln(1.0)} {pow_e(0.0)}");
+                println("{min(0.0 / 0.0, 1.0)} {sin(0.0)} {cos(0.0)}");
+            }
+        "#;
+        assert_eq!(
+            output(source),
+            "-4 2.0 7 1.5\n-2147483648 0.5 1.4142135623730951\n-1.0 -2.0 3.0 0.0 1.0\n1.0 0.0 1.0\n"
+        );
+        let (_, panic) = run_source("func main() { var n = 46341; println(pow(n, 2)); }");
+        assert_eq!(panic.unwrap().message, "attempt to multiply with overflow");
+        let (_, panic) = run_source("func main() { println(abs(-2147483648)); }");
+        assert_eq!(panic.unwrap().message, "attempt to negate with overflow");
+    }
+
+    #[test]
+    fn test_input_reads_lines_until_eof() {
+        let source = r#"
+            func main() {
+                var first = input();
+                var second = input();
+                var third = input();
+                println(first ?? "eof");
+                println(second ?? "eof");
+                println(third ?? "eof");
+            }
+        "#;
+        assert_eq!(run_with_input(source, "Ada\r\nlast").0, "Ada\nlast\neof\n");
+    }
+
+    #[test]
+    fn test_for_goes_through_the_characters_of_a_string() {
+        let source = r#"
+            func main() {
+                for c in "añ€😀" {
+                    print("{int(c)} ");
+                    if c == '€' { break; }
+                }
+                for c in "" { println(c); }
+                println();
+            }
+        "#;
+        assert_eq!(output(source), "97 241 8364 \n");
+    }
+
+    #[test]
+    fn test_defer_runs_when_leaving_the_block() {
+        let source = r#"
+            func half(x: Int) -> Result[Int, String] {
+                defer println("checked {x}");
+                if x % 2 != 0 { return Err("odd"); }
+                return Ok(x / 2);
+            }
+            func quarter(x: Int) -> Result[Int, String] {
+                defer println("quartered");
+                return half(half(x)?);
+            }
+            func main() {
+                for i in 0..3 {
+                    defer println("end {i}");
+                    if i == 1 { continue; }
+                    defer println("second {i}");
+                    if i == 2 { break; }
+                }
+                var x = 1;
+                {
+                    defer println("x = {x}");
+                    var x = 5;
+                    defer println("inner x = {x}");
+                    x = 6;
+                }
+                x = 2;
+                println(quarter(6));
+            }
+        "#;
+        assert_eq!(
+            output(source),
+            "second 0\nend 0\nend 1\nsecond 2\nend 2\ninner x = 6\nx = 1\n\
+             checked 6\nchecked 3\nquartered\nErr(odd)\n"
+        );
+        let (out, panic) = run_source("func main() { defer println(1); panic(\"stop\"); }");
+        assert_eq!(
+            (out.as_str(), panic.unwrap().message.as_str()),
+            ("", "stop")
+        );
+    }
+
+    #[test]
+    fn test_files() {
+        let path =
+            std::env::temp_dir().join(format!("crawfish-interpreter-{}", std::process::id()));
+        let source = r#"
+            func main() {
+                const path = "PATH";
+                var out = unwrap(open(path, "w"));
+                println(write(out, "one\ntwo\r\n"));
+                close(out);
+                println(write(out, "three"));
+                var file = unwrap(open(path, "r"));
+                defer close(file);
+                println(read_line(file));
+                for line in file { println("[{line}]"); }
+                println(read(file));
+                println(open(path, "x"));
+            }
+        "#
+        .replace("PATH", path.to_str().unwrap());
+        let expected = format!(
+            "Ok(())\nErr(the file is closed)\nOk(one)\n[two]\nOk()\n\
+             Err(cannot open \"{}\": invalid mode)\n",
+            path.display()
+        );
+        assert_eq!(output(&source), expected);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+//! Standard input and output of `input()` and `print()`, shared by the interpreter and the virtual
+//! machine
+use crate::front_end::format::{self, Align};
+use std::fmt::Display;
+use std::io::{self, BufRead};
+
+/// Reads the next line of `input` without its line ending, or returns `None` at the end of input
+pub fn read_line(input: &mut dyn BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
+
+/// Formats a value for a placeholder that pads it to `width` characters. Floats, which are given
+/// as `float`, may have a `precision`.
+pub fn padded(
+    value: &dyn Display,
+    float: Option<f64>,
+    width: u32,
+    precision: Option<u32>,
+    align: Align,
+) -> String {
+    let text = match (float, precision) {
+        (Some(float), Some(precision)) => format!("{:.*}", precision as usize, float),
+        _ => value.to_string(),
+    };
+    format::pad(&text, width, align)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_line() {
+        let mut input = "one\r\ntwo\n\nthree".as_bytes();
+        let lines: Vec<_> = (0..5).map(|_| read_line(&mut input).unwrap()).collect();
+        let expected = [Some("one"), Some("two"), Some(""), Some("three"), None];
+        assert_eq!(lines, expected.map(|line| line.map(str::to_string)));
+    }
+
+    #[test]
+    fn test_padded() {
+        assert_eq!(padded(&2.5, Some(2.5), 6, Some(2), Align::Right), "  2.50");
+        assert_eq!(padded(&"ab", None, 4, None, Align::Center), " ab ");
+        let nan = f64::NAN;
+        assert_eq!(padded(&"NaN", Some(nan), 0, Some(3), Align::Left), "NaN");
+    }
+}
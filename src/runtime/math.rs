@@ -0,0 +1,99 @@
+//! The math built-ins, shared by the interpreter, the virtual machine and constant folding
+//!
+//! `min()`, `max()`, `abs()` and `pow()` take integers or floats, all of the same type, and the
+//! others take a float. Integer `abs()` and `pow()` panic when their result is not an `Int`, and
+//! `pow()` when its exponent is negative. `min()` and `max()` of floats ignore a NaN, and return
+//! their first argument when both are equal. Float functions are those of the C math library:
+//! `log()` is the base 10 logarithm, `ln()` the natural one, and `pow_e(x)` is e to the power `x`.
+
+/// An argument or the result of a math built-in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i32),
+    Float(f64),
+}
+
+/// The names of the math built-ins
+pub const FUNCTIONS: [&str; 12] = [
+    "min", "max", "abs", "pow", "sqrt", "ceil", "floor", "sin", "cos", "log", "ln", "pow_e",
+];
+
+/// The message of the panic raised by `pow()` of integers with a negative exponent
+const NEGATIVE_EXPONENT: &str = "attempt to raise an integer to a negative power";
+
+/// Calls the math built-in `name`, or returns the message of the panic it raises
+pub fn call(name: &str, args: &[Number]) -> Result<Number, &'static str> {
+    use Number::{Float, Int};
+    let float = |function: fn(f64) -> f64| match args {
+        [Float(x)] => Ok(Float(function(*x))),
+        _ => unreachable!("the type checker only passes a float to `{}()`", name),
+    };
+    match (name, args) {
+        ("min", &[Int(a), Int(b)]) => Ok(Int(a.min(b))),
+        ("min", &[Float(a), Float(b)]) => Ok(Float(if b < a || a.is_nan() { b } else { a })),
+        ("max", &[Int(a), Int(b)]) => Ok(Int(a.max(b))),
+        ("max", &[Float(a), Float(b)]) => Ok(Float(if b > a || a.is_nan() { b } else { a })),
+        ("abs", &[Int(a)]) => a
+            .checked_abs()
+            .map(Int)
+            .ok_or("attempt to negate with overflow"),
+        ("abs", &[Float(a)]) => Ok(Float(a.abs())),
+        ("pow", &[Int(base), Int(exponent)]) => match u32::try_from(exponent) {
+            Ok(exponent) => base
+                .checked_pow(exponent)
+                .map(Int)
+                .ok_or("attempt to multiply with overflow"),
+            Err(_) => Err(NEGATIVE_EXPONENT),
+        },
+        ("pow", &[Float(base), Float(exponent)]) => Ok(Float(base.powf(exponent))),
+        ("sqrt", _) => float(f64::sqrt),
+        ("ceil", _) => float(f64::ceil),
+        ("floor", _) => float(f64::floor),
+        ("sin", _) => float(f64::sin),
+        ("cos", _) => float(f64::cos),
+        ("log", _) => float(f64::log10),
+        ("ln", _) => float(f64::ln),
+        ("pow_e", _) => float(f64::exp),
+        _ => unreachable!("the type checker validates calls to math built-ins"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Number::{Float, Int};
+
+    #[test]
+    fn test_overloads() {
+        assert_eq!(call("min", &[Int(3), Int(-4)]), Ok(Int(-4)));
+        assert_eq!(call("max", &[Float(0.5), Float(2.0)]), Ok(Float(2.0)));
+        assert_eq!(call("min", &[Float(f64::NAN), Float(1.0)]), Ok(Float(1.0)));
+        assert_eq!(call("max", &[Float(1.0), Float(f64::NAN)]), Ok(Float(1.0)));
+        assert_eq!(call("abs", &[Int(-7)]), Ok(Int(7)));
+        assert_eq!(call("abs", &[Float(-0.0)]), Ok(Float(0.0)));
+        assert_eq!(call("pow", &[Int(-2), Int(31)]), Ok(Int(i32::MIN)));
+        assert_eq!(call("pow", &[Int(1), Int(i32::MAX)]), Ok(Int(1)));
+        assert_eq!(call("pow", &[Int(0), Int(0)]), Ok(Int(1)));
+        assert_eq!(call("pow", &[Float(2.0), Float(-1.0)]), Ok(Float(0.5)));
+        assert_eq!(call("log", &[Float(1000.0)]), Ok(Float(3.0)));
+        assert_eq!(call("ln", &[Float(1.0)]), Ok(Float(0.0)));
+        assert_eq!(call("floor", &[Float(-1.5)]), Ok(Float(-2.0)));
+    }
+
+    #[test]
+    fn test_integer_overflow_panics() {
+        assert_eq!(
+            call("abs", &[Int(i32::MIN)]),
+            Err("attempt to negate with overflow")
+        );
+        assert_eq!(
+            call("pow", &[Int(2), Int(31)]),
+            Err("attempt to multiply with overflow")
+        );
+        assert_eq!(
+            call("pow", &[Int(65536), Int(3)]),
+            Err("attempt to multiply with overflow")
+        );
+        assert_eq!(call("pow", &[Int(2), Int(-1)]), Err(NEGATIVE_EXPONENT));
+    }
+}
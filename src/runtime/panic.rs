@@ -0,0 +1,59 @@
+//! Unrecoverable runtime errors, reported with a message and the source location that raised them
+use crate::front_end::token::Span;
+use std::error::Error;
+use std::fmt;
+
+/// Process exit code of a program that panicked
+pub const EXIT_CODE: i32 = 101;
+
+/// A panic raised by `panic()`, `unwrap()` on the wrong variant, or a failed runtime check
+/// - `file` is the index of the source file `span` points into, among the files of the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panic {
+    pub message: String,
+    pub span: Span,
+    pub file: usize,
+}
+
+impl Panic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            file: 0,
+        }
+    }
+
+    pub fn in_file(self, file: usize) -> Self {
+        Self { file, ..self }
+    }
+
+    /// Renders the panic as `panicked at path:line:column: message`
+    pub fn render(&self, path: &str, source: &str) -> String {
+        let (line, col) = self.span.line_col(source);
+        format!("panicked at {}:{}:{}: {}", path, line, col, self.message)
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked: {}", self.message)
+    }
+}
+
+impl Error for Panic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let source = "func main() {\n    panic(\"oh no\");\n}";
+        let panic = Panic::new("oh no", Span::new(18, 32));
+        assert_eq!(
+            panic.render("main.crw", source),
+            "panicked at main.crw:2:5: oh no"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Values manipulated by the tree-walking interpreter
+use crate::front_end::ast::{Function, Lambda};
+use crate::runtime::file::File;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A runtime value, borrowing functions from the program being interpreted.
+/// An optional holds either its inner value or `Null`, and a `Result` is `Ok` or `Err`.
+/// A file is shared by every copy of it, so that closing one closes them all.
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    String(Rc<str>),
+    Unit,
+    Range {
+        start: i32,
+        end: i32,
+        inclusive: bool,
+    },
+    Null,
+    Ok(Box<Value<'a>>),
+    Err(Box<Value<'a>>),
+    Function(&'a Function),
+    Closure(Rc<Closure<'a>>),
+    File(Rc<RefCell<File>>),
+}
+
+/// A variable's storage, shared between its scope and every closure capturing it
+pub type Cell<'a> = Rc<RefCell<Value<'a>>>;
+
+/// An anonymous function together with the variables it captured when it was created
+/// - `file` is the index of the source file the anonymous function is written in
+#[derive(Debug)]
+pub struct Closure<'a> {
+    pub lambda: &'a Lambda,
+    pub environment: HashMap<&'a str, Cell<'a>>,
+    pub file: usize,
+}
+
+impl PartialEq for Value<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Unit, Value::Unit) | (Value::Null, Value::Null) => true,
+            (
+                Value::Range {
+                    start,
+                    end,
+                    inclusive,
+                },
+                Value::Range {
+                    start: other_start,
+                    end: other_end,
+                    inclusive: other_inclusive,
+                },
+            ) => start == other_start && end == other_end && inclusive == other_inclusive,
+            (Value::Ok(a), Value::Ok(b)) | (Value::Err(a), Value::Err(b)) => a == b,
+            // The type checker rejects comparisons between functions and between files
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write_float(f, *value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Char(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Unit => write!(f, "()"),
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => write!(
+                f,
+                "{}{}{}",
+                start,
+                if *inclusive { "..=" } else { ".." },
+                end
+            ),
+            Value::Null => write!(f, "null"),
+            Value::Ok(value) => write!(f, "Ok({})", value),
+            Value::Err(value) => write!(f, "Err({})", value),
+            Value::Function(function) => write!(f, "<func {}>", function.name.name),
+            Value::Closure(_) => write!(f, "<closure>"),
+            Value::File(_) => write!(f, "<file>"),
+        }
+    }
+}
+
+/// Writes a float the way `println()` shows it.
+/// Whole numbers keep a trailing `.0`, so that floats never print like integers.
+pub fn write_float(f: &mut fmt::Formatter<'_>, value: f64) -> fmt::Result {
+    if value.is_finite() && value.fract() == 0.0 {
+        write!(f, "{:.1}", value)
+    } else {
+        write!(f, "{}", value)
+    }
+}
@@ -0,0 +1,820 @@
+//! Stack-based virtual machine, which runs the bytecode of a program
+use crate::back_end::bytecode::{Builtin, Bytecode, Code, Constant, Instruction};
+use crate::front_end::format::Align;
+use crate::runtime::convert;
+use crate::runtime::file::{self, File};
+use crate::runtime::io;
+use crate::runtime::math::{self, Number};
+use crate::runtime::panic::Panic;
+use crate::runtime::value::write_float;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// Deepest chain of nested calls before the program is stopped with a stack overflow
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Most values on the stack, counting the local slots of every call, before the program is stopped
+/// with a stack overflow
+pub const MAX_STACK_SIZE: usize = 1 << 22;
+
+/// A value on the stack of the virtual machine.
+/// `Int`, `Float`, `Bool` and `Char` are stored inline, and everything else behind a single
+/// pointer, so that a value fits in two machine words.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Unit,
+    Null,
+    Range {
+        start: i32,
+        end: i32,
+        inclusive: bool,
+    },
+    String(Rc<String>),
+    Ok(Rc<Value>),
+    Err(Rc<Value>),
+    Function(u32),
+    Closure(Rc<Closure>),
+    /// The heap cell of a variable captured by reference
+    Cell(Rc<RefCell<Value>>),
+    File(Rc<RefCell<File>>),
+}
+
+/// A function together with the environment it captured when it was created
+#[derive(Debug)]
+pub struct Closure {
+    pub function: u32,
+    pub captures: Vec<Value>,
+}
+
+/// A call in progress
+/// - `ip` is the offset of the next instruction to run
+/// - `base` is the position on the stack of the function's first local slot
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    function: usize,
+    ip: usize,
+    base: usize,
+}
+
+/// Why an instruction stopped the program
+/// - `Panic` is a panic of the program itself
+/// - `Malformed` is an operand of the wrong kind, which only a tampered bytecode file can produce,
+///   as the verifier cannot know the kinds of parameters, call results and payloads
+enum Fault {
+    Panic(Panic),
+    Malformed(&'static str),
+}
+
+impl From<Panic> for Fault {
+    fn from(panic: Panic) -> Self {
+        Fault::Panic(panic)
+    }
+}
+
+/// Runs `main()` of a compiled program, reading the lines of `input()` from `input` and writing
+/// everything it prints to `out`
+pub fn run(bytecode: &Bytecode, input: &mut dyn BufRead, out: &mut dyn Write) -> Result<(), Panic> {
+    let mut vm = Vm::new(bytecode, input, out);
+    // Output printed before a panic is flushed before the panic is reported
+    let result = vm.execute();
+    let flushed = vm.out.flush().map_err(|e| {
+        Panic::new(
+            format!("failed printing to stdout: {}", e),
+            Default::default(),
+        )
+    });
+    result.and(flushed)
+}
+
+/// Virtual machine
+/// - `constants` holds the constant pool, converted to values once
+/// - `frames` holds the callers of the function being run
+struct Vm<'b, 'o> {
+    bytecode: &'b Bytecode,
+    constants: Vec<Value>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    input: &'o mut dyn BufRead,
+    out: &'o mut dyn Write,
+}
+
+impl<'b, 'o> Vm<'b, 'o> {
+    fn new(bytecode: &'b Bytecode, input: &'o mut dyn BufRead, out: &'o mut dyn Write) -> Self {
+        let constants = bytecode
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                Constant::Float(value) => Value::Float(*value),
+                Constant::String(value) => Value::String(Rc::new(value.clone())),
+            })
+            .collect();
+        Self {
+            bytecode,
+            constants,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            input,
+            out,
+        }
+    }
+
+    /// The dispatch loop
+    fn execute(&mut self) -> Result<(), Panic> {
+        let entry = self.bytecode.entry as usize;
+        let mut frame = Frame {
+            function: entry,
+            ip: 0,
+            base: 0,
+        };
+        let locals = self.bytecode.functions[entry].locals as usize;
+        self.stack.resize(locals, Value::Unit);
+        loop {
+            match self.step(&mut frame) {
+                Ok(true) => (),
+                Ok(false) => return Ok(()),
+                Err(Fault::Panic(panic)) => return Err(panic),
+                Err(Fault::Malformed(reason)) => {
+                    let code = &self.bytecode.functions[frame.function];
+                    let message = format!("malformed bytecode: {}", reason);
+                    return Err(panic_at(code, frame, message));
+                }
+            }
+        }
+    }
+
+    /// Runs the next instruction of the function in `frame`, returning false once `main()` returned.
+    /// Inlined into the dispatch loop, which would otherwise pay for a call on every instruction.
+    #[inline(always)]
+    fn step(&mut self, frame: &mut Frame) -> Result<bool, Fault> {
+        let code = &self.bytecode.functions[frame.function];
+        let instruction = *code
+            .instructions
+            .get(frame.ip)
+            .ok_or(Fault::Malformed("execution ran past the end of a function"))?;
+        frame.ip += 1;
+        match instruction {
+            Instruction::Int(value) => self.stack.push(Value::Int(value)),
+            Instruction::Char(value) => self.stack.push(Value::Char(value)),
+            Instruction::Bool(value) => self.stack.push(Value::Bool(value)),
+            Instruction::Unit => self.stack.push(Value::Unit),
+            Instruction::Null => self.stack.push(Value::Null),
+            Instruction::Constant(index) => self.stack.push(self.constants[index as usize].clone()),
+            Instruction::Function(function) => self.stack.push(Value::Function(function)),
+            Instruction::Closure { function, captures } => {
+                let captures = self.stack.split_off(self.stack.len() - captures as usize);
+                let closure = Closure { function, captures };
+                self.stack.push(Value::Closure(Rc::new(closure)));
+            }
+
+            Instruction::Load(slot) => {
+                let value = self.stack[frame.base + slot as usize].clone();
+                self.stack.push(value);
+            }
+            Instruction::Store(slot) => {
+                let value = self.pop();
+                self.stack[frame.base + slot as usize] = value;
+            }
+            Instruction::LoadCell(slot) => {
+                let value = self.cell(*frame, slot)?.borrow().clone();
+                self.stack.push(value);
+            }
+            Instruction::StoreCell(slot) => {
+                let value = self.pop();
+                *self.cell(*frame, slot)?.borrow_mut() = value;
+            }
+            Instruction::MakeCell => {
+                let value = self.pop();
+                self.stack.push(Value::Cell(Rc::new(RefCell::new(value))));
+            }
+            Instruction::Pop => {
+                self.pop();
+            }
+
+            Instruction::AddInt => self.checked(code, *frame, i32::checked_add, "add")?,
+            Instruction::SubtractInt => self.checked(code, *frame, i32::checked_sub, "subtract")?,
+            Instruction::MultiplyInt => self.checked(code, *frame, i32::checked_mul, "multiply")?,
+            Instruction::DivideInt | Instruction::RemainderInt
+                if matches!(self.stack.last(), Some(Value::Int(0))) =>
+            {
+                return Err(panic_at(code, *frame, "attempt to divide by zero").into());
+            }
+            Instruction::DivideInt => self.checked(code, *frame, i32::checked_div, "divide")?,
+            Instruction::RemainderInt => {
+                self.checked(code, *frame, i32::checked_rem, "calculate the remainder")?
+            }
+            Instruction::NegateInt => {
+                let value = self.pop().as_int()?;
+                match value.checked_neg() {
+                    Some(value) => self.stack.push(Value::Int(value)),
+                    None => {
+                        return Err(panic_at(code, *frame, "attempt to negate with overflow").into())
+                    }
+                }
+            }
+            Instruction::AddFloat => self.float(|a, b| a + b)?,
+            Instruction::SubtractFloat => self.float(|a, b| a - b)?,
+            Instruction::MultiplyFloat => self.float(|a, b| a * b)?,
+            Instruction::DivideFloat => self.float(|a, b| a / b)?,
+            Instruction::RemainderFloat => self.float(|a, b| a % b)?,
+            Instruction::NegateFloat => {
+                let value = self.pop().as_float()?;
+                self.stack.push(Value::Float(-value));
+            }
+            Instruction::BitAnd => self.int(|a, b| a & b)?,
+            Instruction::BitOr => self.int(|a, b| a | b)?,
+            Instruction::BitXor => self.int(|a, b| a ^ b)?,
+            Instruction::BitNot => {
+                let value = self.pop().as_int()?;
+                self.stack.push(Value::Int(!value));
+            }
+            Instruction::ShiftLeft => self.checked(
+                code,
+                *frame,
+                |a, b| u32::try_from(b).ok().and_then(|b| a.checked_shl(b)),
+                "shift left",
+            )?,
+            Instruction::ShiftRight => self.checked(
+                code,
+                *frame,
+                |a, b| u32::try_from(b).ok().and_then(|b| a.checked_shr(b)),
+                "shift right",
+            )?,
+            Instruction::Not => {
+                let value = self.pop().as_bool()?;
+                self.stack.push(Value::Bool(!value));
+            }
+            Instruction::Equal => {
+                let (a, b) = self.pop_pair();
+                self.stack.push(Value::Bool(a == b));
+            }
+            Instruction::NotEqual => {
+                let (a, b) = self.pop_pair();
+                self.stack.push(Value::Bool(a != b));
+            }
+            Instruction::Less => self.compare(|ordering| ordering == Ordering::Less)?,
+            Instruction::LessEqual => self.compare(|ordering| ordering != Ordering::Greater)?,
+            Instruction::Greater => self.compare(|ordering| ordering == Ordering::Greater)?,
+            Instruction::GreaterEqual => self.compare(|ordering| ordering != Ordering::Less)?,
+            Instruction::Range { inclusive } => {
+                let end = self.pop().as_int()?;
+                let start = self.pop().as_int()?;
+                self.stack.push(Value::Range {
+                    start,
+                    end,
+                    inclusive,
+                });
+            }
+            Instruction::RangeStart => {
+                let Value::Range { start, .. } = self.pop() else {
+                    return Err(Fault::Malformed("expected a range"));
+                };
+                self.stack.push(Value::Int(start));
+            }
+            Instruction::RangeContains => {
+                let item = self.pop().as_int()?;
+                let Value::Range { end, inclusive, .. } = self.pop() else {
+                    return Err(Fault::Malformed("expected a range"));
+                };
+                self.stack
+                    .push(Value::Bool(item < end || (inclusive && item == end)));
+            }
+            Instruction::Payload => match self.pop() {
+                Value::Ok(value) | Value::Err(value) => self.stack.push(Rc::unwrap_or_clone(value)),
+                _ => return Err(Fault::Malformed("expected a result")),
+            },
+
+            Instruction::Jump(target) => frame.ip = target as usize,
+            Instruction::JumpIfFalse(target) => {
+                if !self.pop().as_bool()? {
+                    frame.ip = target as usize;
+                }
+            }
+            Instruction::Call { function, argc } => {
+                let base = self.stack.len() - argc as usize;
+                self.enter(frame, function as usize, argc, base)?;
+            }
+            Instruction::CallValue { argc } => {
+                let position = self.stack.len() - argc as usize - 1;
+                let function = match std::mem::replace(&mut self.stack[position], Value::Unit) {
+                    Value::Function(function) => {
+                        self.stack.remove(position);
+                        function
+                    }
+                    Value::Closure(closure) => {
+                        // The captured variables become the first locals of the call
+                        let captures = closure.captures.iter().cloned();
+                        self.stack.splice(position..position + 1, captures);
+                        closure.function
+                    }
+                    _ => return Err(Fault::Malformed("expected a function")),
+                };
+                self.enter(frame, function as usize, argc, position)?;
+            }
+            Instruction::CallBuiltin { builtin, argc } => {
+                let args = self.stack.split_off(self.stack.len() - argc as usize);
+                let result = self.builtin(builtin, args, code, *frame)?;
+                self.stack.push(result);
+            }
+            Instruction::Return => {
+                let value = self.pop();
+                return Ok(self.leave(frame, value));
+            }
+        }
+        Ok(true)
+    }
+
+    /// The heap cell of a boxed variable in a local slot
+    fn cell(&self, frame: Frame, slot: u32) -> Result<&Rc<RefCell<Value>>, Fault> {
+        match &self.stack[frame.base + slot as usize] {
+            Value::Cell(cell) => Ok(cell),
+            _ => Err(Fault::Malformed("expected a heap cell")),
+        }
+    }
+
+    /// Starts running `function`, whose `argc` arguments start at `base` on the stack
+    fn enter(
+        &mut self,
+        frame: &mut Frame,
+        function: usize,
+        argc: u32,
+        base: usize,
+    ) -> Result<(), Fault> {
+        let code = &self.bytecode.functions[frame.function];
+        let callee = &self.bytecode.functions[function];
+        if callee.params != argc {
+            return Err(Fault::Malformed("wrong number of arguments"));
+        }
+        let locals = callee.locals as usize;
+        if self.frames.len() + 1 == MAX_CALL_DEPTH || base + locals > MAX_STACK_SIZE {
+            return Err(panic_at(code, *frame, "stack overflow").into());
+        }
+        self.stack.resize(base + locals, Value::Unit);
+        let caller = std::mem::replace(
+            frame,
+            Frame {
+                function,
+                ip: 0,
+                base,
+            },
+        );
+        self.frames.push(caller);
+        Ok(())
+    }
+
+    /// Returns `value` from the function being run, or returns false if it was `main()`
+    fn leave(&mut self, frame: &mut Frame, value: Value) -> bool {
+        self.stack.truncate(frame.base);
+        match self.frames.pop() {
+            Some(caller) => {
+                *frame = caller;
+                self.stack.push(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn builtin(
+        &mut self,
+        builtin: Builtin,
+        args: Vec<Value>,
+        code: &Code,
+        frame: Frame,
+    ) -> Result<Value, Fault> {
+        let mut args = args.into_iter();
+        let arg = args.next();
+        let failed = |e| panic_at(code, frame, format!("failed printing to stdout: {}", e));
+        let result = match (builtin, arg) {
+            (Builtin::Println, arg) => {
+                let written = match arg {
+                    Some(value) => writeln!(self.out, "{}", value),
+                    None => writeln!(self.out),
+                };
+                written.map(|()| Value::Unit).map_err(failed)
+            }
+            (Builtin::Print, Some(value)) => write!(self.out, "{}", value)
+                .map(|()| Value::Unit)
+                .map_err(failed),
+            (Builtin::PrintPadded, Some(value)) => {
+                let mut int = || args.next().map_or(Ok(0), |arg| arg.as_int());
+                let (width, precision, align) = (int()?, int()?, int()?);
+                let float = match value {
+                    Value::Float(float) => Some(float),
+                    _ => None,
+                };
+                let precision = u32::try_from(precision).ok();
+                let align = Align::from_code(align);
+                let padded = io::padded(&value, float, width as u32, precision, align);
+                self.out
+                    .write_all(padded.as_bytes())
+                    .map(|()| Value::Unit)
+                    .map_err(failed)
+            }
+            (Builtin::Input, None) => {
+                // Whatever was printed before, such as a prompt, shows before the program waits
+                self.out.flush().map_err(failed)?;
+                match io::read_line(self.input) {
+                    Ok(Some(line)) => Ok(Value::String(Rc::new(line))),
+                    Ok(None) => Ok(Value::Null),
+                    Err(e) => Err(panic_at(
+                        code,
+                        frame,
+                        format!("failed reading from stdin: {}", e),
+                    )),
+                }
+            }
+            (Builtin::ToString, Some(value)) => Ok(Value::String(Rc::new(value.to_string()))),
+            (Builtin::Concat, Some(Value::String(a))) => {
+                let Some(Value::String(b)) = args.next() else {
+                    return Err(Fault::Malformed("expected a string"));
+                };
+                Ok(Value::String(Rc::new(format!("{}{}", a, b))))
+            }
+            (Builtin::Panic, Some(message)) => Err(panic_at(code, frame, message.to_string())),
+            (Builtin::Ok, arg) => Ok(Value::Ok(Rc::new(arg.unwrap_or(Value::Unit)))),
+            (Builtin::Err, Some(error)) => Ok(Value::Err(Rc::new(error))),
+            (Builtin::Unwrap, Some(Value::Ok(value)))
+            | (Builtin::UnwrapErr, Some(Value::Err(value))) => Ok(Rc::unwrap_or_clone(value)),
+            (Builtin::Unwrap, Some(Value::Err(error))) => Err(panic_at(
+                code,
+                frame,
+                format!("called `unwrap()` on an `Err` value: {}", error),
+            )),
+            (Builtin::UnwrapErr, Some(Value::Ok(value))) => Err(panic_at(
+                code,
+                frame,
+                format!("called `unwrap_err()` on an `Ok` value: {}", value),
+            )),
+            (Builtin::IsOk, Some(result)) => Ok(Value::Bool(matches!(result, Value::Ok(_)))),
+            (Builtin::IsErr, Some(result)) => Ok(Value::Bool(matches!(result, Value::Err(_)))),
+            (Builtin::Int | Builtin::Float | Builtin::Char, Some(value)) => {
+                convert_value(builtin, &value).ok_or_else(|| {
+                    let to = if builtin == Builtin::Int {
+                        "Int"
+                    } else {
+                        "Char"
+                    };
+                    panic_at(code, frame, convert::panic_message(&value, to))
+                })
+            }
+            (builtin, Some(first)) if builtin.is_math() => {
+                let numbers = std::iter::once(first)
+                    .chain(args)
+                    .map(|arg| match arg {
+                        Value::Int(int) => Ok(Number::Int(int)),
+                        Value::Float(float) => Ok(Number::Float(float)),
+                        _ => Err(Fault::Malformed("expected a number")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                match math::call(builtin.name(), &numbers) {
+                    Ok(Number::Int(int)) => Ok(Value::Int(int)),
+                    Ok(Number::Float(float)) => Ok(Value::Float(float)),
+                    Err(message) => Err(panic_at(code, frame, message)),
+                }
+            }
+            (Builtin::NextLine, Some(Value::File(file))) => {
+                // A failed read ends a `for` loop like the end of the file does
+                match file.borrow_mut().read_line() {
+                    Ok(Some(line)) => Ok(Value::String(Rc::new(line))),
+                    Ok(None) | Err(_) => Ok(Value::Null),
+                }
+            }
+            (builtin @ (Builtin::CharAt | Builtin::CharWidth), Some(Value::String(text))) => {
+                let offset = args.next().map_or(Ok(0), |arg| arg.as_int())?;
+                let c = usize::try_from(offset)
+                    .ok()
+                    .and_then(|offset| text.get(offset..))
+                    .and_then(|rest| rest.chars().next());
+                match (builtin, c) {
+                    (Builtin::CharAt, Some(c)) => Ok(Value::Char(c)),
+                    (Builtin::CharAt, None) => Err(panic_at(
+                        code,
+                        frame,
+                        format!("byte {} does not start a character of the string", offset),
+                    )),
+                    // The end of the string, or an offset past it, is an empty sequence
+                    _ => Ok(Value::Int(c.map_or(0, |c| c.len_utf8() as i32))),
+                }
+            }
+            (builtin, Some(first)) if builtin.is_file() => {
+                let args: Vec<Value> = std::iter::once(first).chain(args).collect();
+                return file_builtin(builtin, &args);
+            }
+            _ => return Err(Fault::Malformed("wrong arguments to a built-in function")),
+        };
+        Ok(result?)
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the operand stack is not empty")
+    }
+
+    fn pop_pair(&mut self) -> (Value, Value) {
+        let b = self.pop();
+        let a = self.pop();
+        (a, b)
+    }
+
+    fn int(&mut self, op: impl Fn(i32, i32) -> i32) -> Result<(), Fault> {
+        let (a, b) = self.pop_pair();
+        self.stack.push(Value::Int(op(a.as_int()?, b.as_int()?)));
+        Ok(())
+    }
+
+    /// Applies a checked integer operation, panicking if it overflowed
+    fn checked(
+        &mut self,
+        code: &Code,
+        frame: Frame,
+        op: impl Fn(i32, i32) -> Option<i32>,
+        operation: &str,
+    ) -> Result<(), Fault> {
+        let (a, b) = self.pop_pair();
+        match op(a.as_int()?, b.as_int()?) {
+            Some(value) => {
+                self.stack.push(Value::Int(value));
+                Ok(())
+            }
+            None => Err(panic_at(
+                code,
+                frame,
+                format!("attempt to {} with overflow", operation),
+            )
+            .into()),
+        }
+    }
+
+    fn float(&mut self, op: impl Fn(f64, f64) -> f64) -> Result<(), Fault> {
+        let (a, b) = self.pop_pair();
+        self.stack
+            .push(Value::Float(op(a.as_float()?, b.as_float()?)));
+        Ok(())
+    }
+
+    fn compare(&mut self, test: impl Fn(Ordering) -> bool) -> Result<(), Fault> {
+        let (a, b) = self.pop_pair();
+        let ordering = match (a, b) {
+            (Value::Int(a), Value::Int(b)) => a.partial_cmp(&b),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(&b),
+            (Value::Char(a), Value::Char(b)) => a.partial_cmp(&b),
+            _ => return Err(Fault::Malformed("expected two numbers or two characters")),
+        };
+        // Every comparison with NaN is false
+        self.stack.push(Value::Bool(ordering.is_some_and(test)));
+        Ok(())
+    }
+}
+
+/// Converts a value with `int()`, `float()` or `char()`, or returns `None` when that panics
+fn convert_value(builtin: Builtin, value: &Value) -> Option<Value> {
+    let parsed = |result: Result<Value, String>| match result {
+        Ok(value) => Value::Ok(Rc::new(value)),
+        Err(message) => Value::Err(Rc::new(Value::String(Rc::new(message)))),
+    };
+    match (builtin, value) {
+        (Builtin::Int, Value::Float(float)) => convert::float_to_int(*float).map(Value::Int),
+        (Builtin::Int, Value::Char(c)) => Some(Value::Int(*c as i32)),
+        (Builtin::Int, Value::String(text)) => {
+            Some(parsed(convert::parse_int(text).map(Value::Int)))
+        }
+        (Builtin::Float, Value::Int(int)) => Some(Value::Float(*int as f64)),
+        (Builtin::Float, Value::String(text)) => {
+            Some(parsed(convert::parse_float(text).map(Value::Float)))
+        }
+        (Builtin::Char, Value::Int(int)) => convert::int_to_char(*int).map(Value::Char),
+        _ => Some(value.clone()),
+    }
+}
+
+/// Calls `open()`, `close()`, `read()`, `read_line()` or `write()`, which return a failure as an
+/// `Err`, except for `close()`
+fn file_builtin(builtin: Builtin, args: &[Value]) -> Result<Value, Fault> {
+    let result = match (builtin, args) {
+        (Builtin::Open, [Value::String(path), Value::String(mode)]) => {
+            file::open(path, mode).map(|file| Value::File(Rc::new(RefCell::new(file))))
+        }
+        (Builtin::Close, [Value::File(file)]) => {
+            file.borrow_mut().close();
+            return Ok(Value::Unit);
+        }
+        (Builtin::Read, [Value::File(file)]) => file
+            .borrow_mut()
+            .read()
+            .map(|text| Value::String(Rc::new(text))),
+        (Builtin::ReadLine, [Value::File(file)]) => file
+            .borrow_mut()
+            .read_line()
+            .map(|line| line.map_or(Value::Null, |line| Value::String(Rc::new(line)))),
+        (Builtin::Write, [Value::File(file), Value::String(text)]) => {
+            file.borrow_mut().write(text).map(|()| Value::Unit)
+        }
+        _ => {
+            return Err(Fault::Malformed(
+                "wrong arguments to a file built-in function",
+            ))
+        }
+    };
+    Ok(match result {
+        Ok(value) => Value::Ok(Rc::new(value)),
+        Err(message) => Value::Err(Rc::new(Value::String(Rc::new(message)))),
+    })
+}
+
+/// A panic raised by the instruction that was just run
+fn panic_at(code: &Code, frame: Frame, message: impl Into<String>) -> Panic {
+    Panic::new(message, code.span_at(frame.ip - 1)).in_file(code.file)
+}
+
+impl Value {
+    fn as_int(&self) -> Result<i32, Fault> {
+        match self {
+            Value::Int(value) => Ok(*value),
+            _ => Err(Fault::Malformed("expected an `Int`")),
+        }
+    }
+
+    fn as_float(&self) -> Result<f64, Fault> {
+        match self {
+            Value::Float(value) => Ok(*value),
+            _ => Err(Fault::Malformed("expected a `Float`")),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, Fault> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(Fault::Malformed("expected a `Bool`")),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Unit, Value::Unit) | (Value::Null, Value::Null) => true,
+            (
+                Value::Range {
+                    start,
+                    end,
+                    inclusive,
+                },
+                Value::Range {
+                    start: other_start,
+                    end: other_end,
+                    inclusive: other_inclusive,
+                },
+            ) => start == other_start && end == other_end && inclusive == other_inclusive,
+            (Value::Ok(a), Value::Ok(b)) | (Value::Err(a), Value::Err(b)) => a == b,
+            // The type checker rejects comparisons between functions and between files
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Float(value) => write_float(f, *value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Char(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Unit => write!(f, "()"),
+            Value::Null => write!(f, "null"),
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => write!(
+                f,
+                "{}{}{}",
+                start,
+                if *inclusive { "..=" } else { ".." },
+                end
+            ),
+            Value::Ok(value) => write!(f, "Ok({})", value),
+            Value::Err(value) => write!(f, "Err({})", value),
+            Value::Function(_) => write!(f, "<func>"),
+            Value::Closure(_) => write!(f, "<closure>"),
+            Value::Cell(cell) => write!(f, "{}", cell.borrow()),
+            Value::File(_) => write!(f, "<file>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::back_end::bytecode::{self, verifier};
+    use crate::front_end;
+    use crate::testing::{interpret, source_files, FILES, INPUT, PROGRAMS};
+
+    /// Runs `source` on the virtual machine, returning what it printed and how it panicked, if it did
+    fn run_source(source: &str) -> (String, Option<Panic>) {
+        let analysis = front_end::analyze(source).unwrap();
+        let mut out = Vec::new();
+        let bytecode = bytecode::compile(&analysis.program);
+        verifier::verify(&bytecode, &source_files(source)).unwrap();
+        let result = run(&bytecode, &mut INPUT.as_bytes(), &mut out);
+        (String::from_utf8(out).unwrap(), result.err())
+    }
+
+    /// Checks that the virtual machine and the tree-walking interpreter agree on `source`
+    fn assert_matches_interpreter(source: &str) {
+        let (expected, expected_panic) = interpret(source);
+        let (out, panic) = run_source(source);
+        assert_eq!(out, String::from_utf8(expected).unwrap(), "{}", source);
+        assert_eq!(panic, expected_panic, "{}", source);
+    }
+
+    #[test]
+    fn test_value_fits_in_two_words() {
+        assert_eq!(std::mem::size_of::<Value>(), 16);
+    }
+
+    #[test]
+    fn test_programs_match_interpreter() {
+        let path = std::env::temp_dir().join(format!("crawfish-vm-{}", std::process::id()));
+        let files = FILES.replace("PATH", path.to_str().unwrap());
+        for program in PROGRAMS.iter().copied().chain([files.as_str()]) {
+            assert_matches_interpreter(program);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_runtime_error_spans() {
+        let source = "func main() {\n    var zero = 0;\n    println(1 / zero);\n}";
+        let (_, panic) = run_source(source);
+        assert_eq!(
+            panic.unwrap().render("main.crw", source),
+            "panicked at main.crw:3:13: attempt to divide by zero"
+        );
+    }
+
+    #[test]
+    fn test_break_out_of_an_expression() {
+        let source = r#"
+            func main() {
+                var i = 0;
+                while true {
+                    i += 1;
+                    const x = 1 + { if i == 3 { break; } i };
+                    println(x);
+                }
+                println(i);
+            }
+        "#;
+        assert_eq!(run_source(source).0, "2\n3\n3\n");
+    }
+
+    #[test]
+    fn test_tampered_bytecode_stops_with_an_error() {
+        let source = r#"
+            func next(n: Int) -> Int { return next(n + 1); }
+            func main() { println("n"); println(next(1)); }
+        "#;
+        let analysis = front_end::analyze(source).unwrap();
+        let bytecode = bytecode::compile(&analysis.program);
+        let files = source_files(source);
+        let run_tampered = |bytecode: &Bytecode| {
+            verifier::verify(bytecode, &files).unwrap();
+            let mut out = Vec::new();
+            run(bytecode, &mut INPUT.as_bytes(), &mut out).unwrap_err()
+        };
+
+        // The verifier cannot know the kind of a parameter, so passing a string instead of an
+        // `Int` is only found when the parameter is used
+        let mut tampered = bytecode.clone();
+        let main = &mut tampered.functions[bytecode.entry as usize];
+        let one = main
+            .instructions
+            .iter()
+            .position(|instruction| *instruction == Instruction::Int(1))
+            .unwrap();
+        main.instructions[one] = Instruction::Constant(0);
+        assert_eq!(
+            run_tampered(&tampered).message,
+            "malformed bytecode: expected an `Int`"
+        );
+
+        // Calls stop once the stack is full, however many local slots each of them declares
+        let mut tampered = bytecode.clone();
+        for code in &mut tampered.functions {
+            code.locals = verifier::MAX_SLOTS;
+        }
+        assert_eq!(run_tampered(&tampered).message, "stack overflow");
+    }
+}
@@ -0,0 +1,338 @@
+//! Helpers shared by the tests that check each backend against the tree-walking interpreter
+use crate::front_end;
+use crate::front_end::modules::SourceFile;
+use crate::runtime::interpreter;
+use crate::runtime::panic::Panic;
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// The lines that programs under test read with `input()`
+pub const INPUT: &str = "Ada\n  42 \r\n\nno newline";
+
+/// A program reading and writing the file at `PATH`
+pub const FILES: &str = r#"
+    func main() {
+        var out = unwrap(open("PATH", "w"));
+        defer close(out);
+        println(write(out, "one\ntwo\r\n\nlast"));
+        println(read(out));
+        var file = unwrap(open("PATH", "r"));
+        println(read_line(file));
+        for line in file { println("[{line}]"); }
+        println(read_line(file));
+        close(file);
+        println(read(file));
+        println(write(unwrap(open("PATH", "a")), "!"));
+        println(open("PATH", "rw"));
+        println(open("PATH.missing", "r"));
+    }
+"#;
+
+/// Programs that every backend must run exactly like the interpreter, printing and panicking
+/// alike. `FILES` is left out, as it needs a path of its own for each backend.
+pub const PROGRAMS: &[&str] = &[
+    r#"
+    func main() {
+        println(1 + 2 * 3);
+        println(-7 % 3);
+        println(1.5 * 2.0);
+        println(0.1 + 0.2);
+        println(-7.5 % 2.0);
+        println(-(2.5));
+        println(1000000000000000000000.0 / 3.0);
+        println(1.0 / 3.0);
+        println(123456.789);
+        println(0.000123);
+        println(1 << 4 | 1 ^ 3 & 7);
+        println(~5 >> 1);
+        println(-8 >> 1);
+        println('c' < 'd');
+        println('🦀');
+        println("héllo" == "héllo");
+        println("a" != "b");
+        println(1.0 == 1.0 and 0.5 != 0.5);
+        println(3 > 2 and !false or false);
+        println(1.0 / 0.0 > 1.0);
+        println(0.0 / 0.0 >= 0.0);
+        println(-3..=3);
+        println(1..3);
+        println();
+    }
+    "#,
+    r#"
+    func fib(n: Int) -> Int {
+        if n < 2 { return n; }
+        return fib(n - 1) + fib(n - 2);
+    }
+    func many(a: Int, b: Int, c: Int, d: Int, e: Int, f: Int, g: Int) -> Int {
+        return a - b + c - d + e - f + g * 100;
+    }
+    func main() {
+        var total = 0;
+        for i in 0..=10 {
+            if i % 2 == 0 { continue; }
+            total += i;
+        }
+        println(total);
+        var n = 0;
+        while true {
+            n += 1;
+            const x = 1 + { if n == 5 { break; } n };
+            println(x);
+        }
+        println(fib(20));
+        println(many(1, 2, 3, 4, 5, 6, 7));
+        println(if total > 0 { 1 } else if total < 0 { -1 } else { 0 });
+        const r = 0..2;
+        for i in r { for j in r { println(i * 10 + j); } }
+        for i in 2147483646..=2147483647 { println(i); }
+        for i in -2..1 { println(i); }
+        for i in 3..1 { println(i); }
+    }
+    "#,
+    r#"
+    func half(x: Int) -> Result[Int, String] {
+        if x % 2 != 0 { return Err("odd"); }
+        return Ok(x / 2);
+    }
+    func quarter(x: Int) -> Result[Int, String] {
+        return Ok(half(half(x)?)?);
+    }
+    func greet(name: String?) { println(name ?? "anonymous"); }
+    func main() {
+        greet(null);
+        greet("crawfish");
+        println(quarter(8));
+        println(quarter(6));
+        println(half(3) ?? 0);
+        println(half(4) ?? 0 == 2);
+        println(is_ok(half(2)));
+        println(is_err(half(2)));
+        println(Ok());
+        println(unwrap_err(half(1)));
+        println(unwrap(quarter(3)));
+    }
+    "#,
+    r#"
+    func counter() -> func() -> Int {
+        var count = 0;
+        return func() -> Int {
+            count += 1;
+            return count;
+        };
+    }
+    func apply(f: func(Int) -> Int, x: Int) -> Int { return f(x); }
+    func double(x: Int) -> Int { return x * 2; }
+    func main() {
+        const next = counter();
+        next();
+        println(next());
+        println(counter()());
+        var offset = 1;
+        const add = func(x: Int) -> Int { return x + offset; };
+        offset = 10;
+        println(apply(add, 5));
+        println(apply(double, 5));
+        const scale = 3;
+        const nested = func() -> func(Int) -> Int {
+            return func(x: Int) -> Int { return x * scale + offset; };
+        };
+        offset = 100;
+        println(nested()(2));
+    }
+    "#,
+    r#"
+    func chain(n: Int) -> func() -> Int {
+        if n == 0 { return func() -> Int { return 0; }; }
+        const inner = chain(n - 1);
+        return func() -> Int { return inner() + 1; };
+    }
+    func wrap(x: Int) -> Result[Int, String] { return Ok(x); }
+    func main() {
+        const kept = chain(100);
+        var total = 0;
+        for i in 0..2000 {
+            const result = wrap(i);
+            const add = func() -> Int { return unwrap(result) + total; };
+            total = add() % 1000;
+        }
+        println(total);
+        println(kept());
+    }
+    "#,
+    r#"
+    func main() {
+        print("Name? ");
+        const name = input();
+        if name != null { println("Hello, {}!", name); }
+        var count = 0;
+        var line = input();
+        while line != null {
+            count += 1;
+            println("[{:>6}] [{:<3}] [{:^5}]", line, count, 'é');
+            line = input();
+        }
+        println("{} lines {{read}}, then {}", count, input());
+        println("{:.2} {:8.3} {:<7.1}| {:.0} {:^9}|", 3.14159, -2.5, 0.125, 2.5, 1.5);
+        println("{:.1} {:6.2} {:>5}", 0.0 / 0.0, 1.0 / 0.0, -1.0 / 0.0);
+        print(42);
+        print(' ');
+        println(true);
+        println("{:>7}{:>5}|", Ok(1), 0..3);
+    }
+    "#,
+    r#"
+    func describe(n: Int) -> String {
+        return "{n} is {if n % 2 == 0 { "even" } else { "odd" }}";
+    }
+    func main() {
+        const name = "crawfish";
+        const missing: Int? = null;
+        const result: Result[Float, String] = Ok(0.5);
+        println("{name}: {{{1 + 2}}} {'c'} {true} {missing} {result} {-0.0}");
+        println("{describe(7)}, {describe(10)}, {"inner {name}"}");
+        var digits = "";
+        for i in 0..12 { digits = "{digits}{i % 10}"; }
+        println("[{digits}]");
+        println("{{}} {} {:>5}", "{{x}}", 1);
+    }
+    "#,
+    r#"
+    func parsed(r: Result[Int, String]) -> String {
+        return if is_ok(r) { "{unwrap(r)}" } else { unwrap_err(r) };
+    }
+    func main() {
+        println("{int(-3.99)} {int(2147483647.5)} {int('é')} {float(7)} {char(128512)}");
+        println("{parsed(int("-0042"))}, {parsed(int("2147483648"))}, {parsed(int(""))}");
+        println("{float("6.02e23")} {float("-1E-3")} {float("1e400")} {float(".5")}");
+        println("{string(0.1 + 0.2)} {1.0 / 3.0} {0.0000001} {string(char(int('a') + 1))}");
+    }
+    "#,
+    "func main() { println(int(0.0 / 0.0)); }",
+    "func main() { var n = 55296; println(char(n)); }",
+    r#"
+    func main() {
+        var x = 2.5;
+        println("{min(3, -4)} {max(-0.0, 0.0)} {min(0.0 / 0.0, x)} {abs(-7)} {abs(-x)}");
+        println("{pow(-2, 31)} {pow(x, 3.0)} {sqrt(x)} {ceil(x)} {floor(-x)} {log(1000.0)}");
+        println("{ln(0.0)} {pow_e(1.0)} {sin(0.0)} {cos(0.0)}");
+    }
+    "#,
+    "func main() { var n = 46341; println(pow(n, 2)); }",
+    "func main() { var n = -2147483648; println(abs(n)); }",
+    "func main() { var n = -1; println(pow(2, n)); }",
+    r#"
+    func half(x: Int) -> Result[Int, String] {
+        defer println("checked {x}");
+        if x % 2 != 0 { return Err("odd"); }
+        return Ok(x / 2);
+    }
+    func main() {
+        for i in 0..3 {
+            defer println("end {i}");
+            if i == 1 { continue; }
+            var s = "second {i}";
+            defer { println(s); }
+            if i == 2 { break; }
+            s = "changed {i}";
+        }
+        var x = 1;
+        {
+            defer println("x = {x}");
+            x = 2;
+        }
+        println(half(6));
+        println(half(3));
+    }
+    "#,
+    r#"
+    func main() {
+        const y = 3;
+        const k = func() -> func() -> Int {
+            var y = y + 1;
+            return func() -> Int { y += 1; return y; };
+        };
+        println(k()());
+    }
+    "#,
+    r#"
+    func main() {
+        for c in "añ€😀!" {
+            print("{c}{int(c)} ");
+            if c == '😀' { continue; }
+            defer print("| ");
+        }
+        println();
+        var word = "";
+        for c in "héllo" { word = "{c}{word}"; }
+        println(word);
+    }
+    "#,
+    "func main() { var x = 2147483647; x += 1; }",
+    "func main() { var x = -2147483648; println(x / -1); }",
+    "func main() { var x = -2147483648; println(-x); }",
+    "func main() { var s = 40; println(1 << s); }",
+    "func main() { var zero = 0; println(5 % zero); }",
+    "func f(n: Int) -> Int { return f(n + 1); } func main() { f(0); }",
+    r#"func main() { println("before"); panic("oh no"); }"#,
+    r#"func main() { unwrap(Err(1.5)); }"#,
+];
+
+/// The files of a program whose only source is `source`, as `main.crw`
+pub fn source_files(source: &str) -> [SourceFile; 1] {
+    [SourceFile {
+        path: PathBuf::from("main.crw"),
+        module: None,
+        source: source.to_string(),
+    }]
+}
+
+/// Runs `source` on the interpreter with `INPUT`, returning what it printed and how it panicked,
+/// if it did
+pub fn interpret(source: &str) -> (Vec<u8>, Option<Panic>) {
+    let analysis = front_end::analyze(source).unwrap();
+    let mut out = Vec::new();
+    let panic = interpreter::run(&analysis.program, &mut INPUT.as_bytes(), &mut out).err();
+    (out, panic)
+}
+
+/// Where a test builds the executable named `name`
+pub fn executable_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("crawfish-test-{}-{}", std::process::id(), name))
+}
+
+/// Runs a command with `INPUT` on its stdin, which it may not read
+pub fn run_with_input(command: &mut Command) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let _ = child.stdin.take().unwrap().write_all(INPUT.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+/// Checks that each run of the executable built from `source` printed and panicked like the
+/// interpreter
+pub fn assert_outputs_match_interpreter(source: &str, outputs: &[Output]) {
+    let (expected, panic) = interpret(source);
+    let stderr = panic.map_or(String::new(), |p| p.render("main.crw", source) + "\n");
+    let code = if stderr.is_empty() { 0 } else { 101 };
+    for output in outputs {
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&expected),
+            "{}",
+            source
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            stderr,
+            "{}",
+            source
+        );
+        assert_eq!(output.status.code(), Some(code), "{}", source);
+    }
+}